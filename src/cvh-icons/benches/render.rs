@@ -0,0 +1,115 @@
+//! Render throughput benchmarks
+//!
+//! Measures icons per second through the headless render pipeline: building
+//! draw commands, rasterizing them into a pixmap, and converting the pixels to
+//! the BGRA layout Wayland buffers use. No Wayland display is needed.
+//!
+//! Run with `cargo bench --bench render`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use std::path::Path;
+use tempfile::TempDir;
+use tiny_skia::Pixmap;
+
+use cvh_icons::config::Config;
+use cvh_icons::icons::DesktopIcon;
+use cvh_icons::renderer::{rgba_to_bgra, IconRenderer};
+
+/// Height of the label area below the glyph (matches the daemon)
+const LABEL_HEIGHT: u32 = 24;
+
+const ICON_COUNTS: [usize; 3] = [16, 128, 512];
+const ICON_SIZES: [u32; 3] = [48, 64, 128];
+
+/// Desktop entries of mixed types so each type's fallback is exercised
+const NAMES: [&str; 6] = ["notes.txt", "photo.png", "song.mp3", "backup.zip", "clip.mp4", "Projects"];
+
+/// Create `count` desktop entries and icons for them, without Lua processes
+fn make_icons(dir: &Path, count: usize, config: &Config) -> Vec<DesktopIcon> {
+    (0..count)
+        .map(|i| {
+            let name = NAMES[i % NAMES.len()];
+            let path = dir.join(format!("{}-{}", i, name));
+            if name == "Projects" {
+                std::fs::create_dir_all(&path).unwrap();
+            } else {
+                std::fs::write(&path, "x").unwrap();
+            }
+            DesktopIcon::new(&path, config).unwrap()
+        })
+        .collect()
+}
+
+/// Draw commands (no Lua process, so the built-in fallback commands) executed on a pixmap
+fn bench_draw_commands(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render/draw_commands");
+
+    for &size in &ICON_SIZES {
+        let config = Config { icon_size: size, ..Config::default() };
+        let renderer = IconRenderer::new(size, config.font_size);
+        let height = size + LABEL_HEIGHT;
+
+        for &count in &ICON_COUNTS {
+            let dir = TempDir::new().unwrap();
+            let mut icons = make_icons(dir.path(), count, &config);
+            let mut bgra = vec![0u8; (size * height * 4) as usize];
+
+            group.throughput(Throughput::Elements(count as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}px", size), count),
+                &count,
+                |b, _| {
+                    b.iter(|| {
+                        for icon in icons.iter_mut() {
+                            let commands = icon.request_render(size, height, 1.0);
+                            let mut pixmap = Pixmap::new(size, height).unwrap();
+                            renderer.execute_commands(&mut pixmap, &commands).unwrap();
+                            rgba_to_bgra(pixmap.data(), &mut bgra);
+                        }
+                        black_box(&bgra);
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+/// The native renderer (glyph + label) as used in symbolic mode
+fn bench_native(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render/native");
+
+    for &size in &ICON_SIZES {
+        let config = Config { icon_size: size, ..Config::default() };
+        let renderer = IconRenderer::new(size, config.font_size);
+        let height = size + LABEL_HEIGHT;
+
+        for &count in &ICON_COUNTS {
+            let dir = TempDir::new().unwrap();
+            let icons = make_icons(dir.path(), count, &config);
+            let mut bgra = vec![0u8; (size * height * 4) as usize];
+
+            group.throughput(Throughput::Elements(count as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}px", size), count),
+                &count,
+                |b, _| {
+                    b.iter(|| {
+                        for icon in &icons {
+                            let pixmap = renderer.render(icon).unwrap();
+                            rgba_to_bgra(pixmap.data(), &mut bgra);
+                        }
+                        black_box(&bgra);
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_draw_commands, bench_native);
+criterion_main!(benches);
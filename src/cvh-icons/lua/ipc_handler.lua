@@ -377,6 +377,16 @@ function Canvas:clear(color)
     }
 end
 
+-- Horizontal gauge filled to `fraction` (0..1), e.g. for disk usage
+function Canvas:bar(x, y, w, h, fraction, color, background)
+    self.commands[#self.commands + 1] = {
+        type = "Bar",
+        x = x, y = y, w = w, h = h,
+        fraction = fraction,
+        color = color, background = background
+    }
+end
+
 function Canvas:get_width()
     return self.width
 end
@@ -389,6 +399,8 @@ end
 local IconManager = {}
 IconManager.loaded_script = nil
 IconManager.icon = nil
+-- Script a pooled worker's slot was bound to
+IconManager.bound_script = nil
 
 function IconManager.load_script(script_path)
     -- Reset any previously loaded script
@@ -477,8 +489,10 @@ function IconManager.call_event(event)
     local action = nil
 
     if event.type == "Click" then
+        -- on_click(button, x, y, name): button is 1/2/3/8/9 for left, middle,
+        -- right, back, forward; name is the daemon's stable button name
         if type(icon.on_click) == "function" then
-            local ok, result = pcall(icon.on_click, icon, event.button, event.x, event.y)
+            local ok, result = pcall(icon.on_click, icon, event.button, event.x, event.y, event.name)
             if ok and result then
                 handled = true
                 if type(result) == "string" then
@@ -486,6 +500,24 @@ function IconManager.call_event(event)
                 end
             end
         end
+    elseif event.type == "DoubleClick" then
+        -- on_double_click(x, y): the second press of a double click; a
+        -- truthy result means the widget opened (or otherwise acted on) itself
+        if type(icon.on_double_click) == "function" then
+            local ok, result = pcall(icon.on_double_click, icon, event.x, event.y)
+            if ok and result then
+                handled = true
+                if type(result) == "string" then
+                    action = { action = result, payload = icon.path }
+                end
+            end
+        end
+    elseif event.type == "Scroll" then
+        -- on_scroll(dx, dy): pixels scrolled, positive is right and down
+        if type(icon.on_scroll) == "function" then
+            local ok, result = pcall(icon.on_scroll, icon, event.dx, event.dy)
+            handled = ok and result and true or false
+        end
     elseif event.type == "HoverEnter" then
         if type(icon.on_hover) == "function" then
             local ok, _ = pcall(icon.on_hover, icon, true)
@@ -519,6 +551,101 @@ function IconManager.call_event(event)
     return { handled = handled, action = action }
 end
 
+function IconManager.call_overlay()
+    if not IconManager.icon then
+        return nil
+    end
+
+    local icon = IconManager.icon
+    if type(icon.overlay) ~= "function" then
+        return nil
+    end
+
+    local ok, result = pcall(icon.overlay, icon)
+    if not ok then
+        return nil, "overlay() failed: " .. tostring(result)
+    end
+    if type(result) ~= "table" then
+        return nil
+    end
+    return result
+end
+
+-- Context-menu entries the widget adds, as a list of { id, label }
+-- Widgets define them with an `actions` table or an actions() method.
+function IconManager.call_actions()
+    if not IconManager.icon then
+        return nil
+    end
+
+    local icon = IconManager.icon
+    local actions = icon.actions
+    if type(actions) == "function" then
+        local ok, result = pcall(actions, icon)
+        if not ok then
+            io.stderr:write("actions() failed: " .. tostring(result) .. "\n")
+            return nil
+        end
+        actions = result
+    end
+    if type(actions) ~= "table" then
+        return nil
+    end
+
+    local list = {}
+    for _, action in ipairs(actions) do
+        if type(action) == "table" and type(action.id) == "string" and type(action.label) == "string" then
+            table.insert(list, { id = action.id, label = action.label })
+        end
+    end
+    -- An empty table would encode as an object; leave the field out instead
+    if #list == 0 then
+        return nil
+    end
+    return list
+end
+
+-- How often the widget wants to be rendered again, in milliseconds
+-- Widgets that show something changing on its own (a clock, the weather)
+-- set a `refresh_ms` number; the daemon raises very short intervals.
+-- Whether the widget draws badges; only those are polled for them
+function IconManager.has_overlay()
+    return IconManager.icon ~= nil and type(IconManager.icon.overlay) == "function"
+end
+
+function IconManager.refresh_ms()
+    if not IconManager.icon then
+        return nil
+    end
+
+    local refresh_ms = IconManager.icon.refresh_ms
+    if type(refresh_ms) ~= "number" or refresh_ms <= 0 then
+        return nil
+    end
+    return math.floor(refresh_ms)
+end
+
+function IconManager.call_action(id)
+    if not IconManager.icon then
+        return nil, "No icon loaded"
+    end
+
+    local icon = IconManager.icon
+    if type(icon.on_action) ~= "function" then
+        return { handled = false }
+    end
+
+    local ok, result = pcall(icon.on_action, icon, id)
+    if not ok then
+        return nil, "on_action() failed: " .. tostring(result)
+    end
+    local action = nil
+    if type(result) == "string" then
+        action = { action = result, payload = icon.path }
+    end
+    return { handled = true, action = action }
+end
+
 function IconManager.call_get_position(input)
     if not IconManager.icon then
         return nil, "No icon loaded"
@@ -554,26 +681,43 @@ end
 -- Request handlers
 local Handlers = {}
 
+-- Widget script to load, from the request or the environment
+local function widget_script_path(request)
+    return request.script_path
+        or IconManager.bound_script
+        or os.getenv("CVH_ICON_SCRIPT")
+        or "/usr/share/cvh-icons/scripts/file.lua"
+end
+
 function Handlers.Handshake(request)
     local remote_version = request.version or 0
     local success = (remote_version == PROTOCOL_VERSION)
+
+    -- Load the widget now so its context-menu entries go out with the ack;
+    -- a script that fails to load is reported on the first render instead
+    local actions = nil
+    local refresh_ms = nil
+    local overlay = false
+    if success and IconManager.load_script(widget_script_path(request)) then
+        actions = IconManager.call_actions()
+        refresh_ms = IconManager.refresh_ms()
+        overlay = IconManager.has_overlay()
+    end
+
     return {
         type = "HandshakeAck",
         version = PROTOCOL_VERSION,
-        success = success
+        success = success,
+        actions = actions,
+        refresh_ms = refresh_ms,
+        overlay = overlay
     }
 end
 
 function Handlers.Render(request)
     local metadata = request.metadata
     local context = request.context
-
-    -- Determine script path from icon_type or use default
-    local script_path = request.script_path
-    if not script_path then
-        -- Use environment variable or default
-        script_path = os.getenv("CVH_ICON_SCRIPT") or "/usr/share/cvh-icons/scripts/file.lua"
-    end
+    local script_path = widget_script_path(request)
 
     -- Load script if not already loaded or if different
     if IconManager.loaded_script ~= script_path then
@@ -610,8 +754,28 @@ function Handlers.Render(request)
     }
 end
 
+-- The daemon sends events as {"Click": {...}} or "HoverEnter"; flatten
+-- them to {type = "Click", ...} for call_event
+local function normalize_event(event)
+    if type(event) == "string" then
+        return { type = event }
+    end
+    if type(event) == "table" and event.type == nil then
+        for name, fields in pairs(event) do
+            local normalized = { type = name }
+            if type(fields) == "table" then
+                for key, value in pairs(fields) do
+                    normalized[key] = value
+                end
+            end
+            return normalized
+        end
+    end
+    return event
+end
+
 function Handlers.Event(request)
-    local event = request.event
+    local event = normalize_event(request.event)
 
     local result, err = IconManager.call_event(event)
     if not result then
@@ -625,6 +789,19 @@ function Handlers.Event(request)
     }
 end
 
+function Handlers.Action(request)
+    local result, err = IconManager.call_action(request.id)
+    if not result then
+        return { type = "Error", message = err }
+    end
+
+    return {
+        type = "Event",
+        handled = result.handled,
+        action = result.action
+    }
+end
+
 function Handlers.Position(request)
     local input = request.input
 
@@ -639,10 +816,72 @@ function Handlers.Position(request)
     }
 end
 
+function Handlers.Overlay(request)
+    local overlay, err = IconManager.call_overlay()
+    if err then
+        return { type = "Error", message = err }
+    end
+
+    return {
+        type = "Overlay",
+        overlay = overlay
+    }
+end
+
+-- Load a widget into a pooled worker's slot; answered like a handshake
+function Handlers.Bind(request)
+    local ok, err = IconManager.load_script(request.script_path)
+    if not ok then
+        return { type = "Error", message = err }
+    end
+    IconManager.bound_script = request.script_path
+
+    return {
+        type = "HandshakeAck",
+        version = PROTOCOL_VERSION,
+        success = true,
+        actions = IconManager.call_actions(),
+        refresh_ms = IconManager.refresh_ms(),
+        overlay = IconManager.has_overlay()
+    }
+end
+
+function Handlers.Release(request)
+    IconManager.icon = nil
+    IconManager.loaded_script = nil
+    IconManager.bound_script = nil
+    return { type = "Released" }
+end
+
 function Handlers.Shutdown(request)
     return { type = "ShutdownAck" }
 end
 
+-- Widgets of a pooled worker, by the slot id routed requests carry
+local Slots = {}
+
+-- Make a slot's widget the current one while its request is handled
+local function enter_slot(id)
+    local slot = Slots[id] or {}
+    IconManager.icon = slot.icon
+    IconManager.loaded_script = slot.loaded_script
+    IconManager.bound_script = slot.bound_script
+    Icon = slot.icon
+end
+
+-- Keep the current widget in its slot; a released slot is forgotten
+local function leave_slot(id)
+    if IconManager.icon == nil and IconManager.bound_script == nil then
+        Slots[id] = nil
+        return
+    end
+    Slots[id] = {
+        icon = IconManager.icon,
+        loaded_script = IconManager.loaded_script,
+        bound_script = IconManager.bound_script
+    }
+end
+
 -- Main IPC loop
 local function main()
     -- Set stdin/stdout to binary mode if possible
@@ -670,6 +909,11 @@ local function main()
         local request_type = request.type
         local handler = Handlers[request_type]
 
+        local slot = request.icon
+        if slot then
+            enter_slot(slot)
+        end
+
         local response
         if handler then
             local ok, result = pcall(handler, request)
@@ -688,6 +932,13 @@ local function main()
             }
         end
 
+        if slot then
+            leave_slot(slot)
+            -- Echo the slot so the daemon can tell a late reply from the
+            -- one it is waiting for
+            response.icon = slot
+        end
+
         -- Send response
         local ok, err = pcall(IPC.send, response)
         if not ok then
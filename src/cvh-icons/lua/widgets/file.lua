@@ -148,4 +148,26 @@ function Icon:get_position(input)
     }
 end
 
+-- Extra context-menu entries, shown after the built-in ones
+-- Read once when the widget starts (and again if it is restarted).
+-- returns: list of { id = "...", label = "..." }
+function Icon:actions()
+    return {}
+end
+
+-- Called with the id of the chosen entry from actions()
+function Icon:on_action(id)
+end
+
+-- Badge drawn over the icon, polled between renders
+-- Only widgets that define it are polled, so this one leaves it out:
+--
+-- function Icon:overlay()
+--     -- nil for no badge, or a table with one of
+--     --   count (number), emblem (icon theme name) or dot (color),
+--     --   plus optional color (badge background), corner
+--     --   ("top_right", "top_left", "bottom_right", "bottom_left") and rotation (degrees)
+--     return nil
+-- end
+
 return Icon
@@ -0,0 +1,1126 @@
+//! Surface backends
+//!
+//! The daemon draws each icon into its own surface and reads pointer input
+//! back from those surfaces. `SurfaceBackend` is everything it needs from the
+//! display side; the Wayland layer-shell manager is one implementation, and
+//! `HeadlessBackend` keeps buffers in memory for embedders (or tests) that
+//! composite icons themselves and feed in their own input.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::os::fd::BorrowedFd;
+use std::rc::Rc;
+
+use tracing::warn;
+
+use crate::config::{Corner, SurfaceOutputBinding};
+use crate::error::{IconsError, IconsResult};
+use crate::launcher;
+use crate::renderer::{scale_opacity, DamageRect};
+
+/// Unique identifier for icon surfaces
+pub type SurfaceId = u64;
+
+/// Surfaces whose frames are paced together, such as those on one output
+pub type FrameGroup = u32;
+
+/// Buffer scale for a `buffer` drawn for a `surface`-sized surface at `scale`
+///
+/// Buffers drawn at the surface's scale get it; anything else, such as a
+/// frame rendered before the scale was known, is shown at 1x.
+pub fn buffer_scale(surface: (u32, u32), buffer: (u32, u32), scale: u32) -> u32 {
+    if scale > 1 && buffer == (surface.0 * scale, surface.1 * scale) {
+        scale
+    } else {
+        1
+    }
+}
+
+/// Keyboard modifiers held at the time of an input event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyModifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// A pointer button, named from its Linux evdev code
+///
+/// | Code  | evdev name    | Name            | Number |
+/// |-------|---------------|-----------------|--------|
+/// | 272   | `BTN_LEFT`    | `left`          | 1      |
+/// | 273   | `BTN_RIGHT`   | `right`         | 3      |
+/// | 274   | `BTN_MIDDLE`  | `middle`        | 2      |
+/// | 275   | `BTN_SIDE`    | `back`          | 8      |
+/// | 276   | `BTN_EXTRA`   | `forward`       | 9      |
+/// | 277   | `BTN_FORWARD` | `forward`       | 9      |
+/// | 278   | `BTN_BACK`    | `back`          | 8      |
+/// | other |               | `button<code>`  | code   |
+///
+/// The names are what `button_actions` keys on and what widgets receive;
+/// the numbers are the X11-style ones passed to a widget's `on_click`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    Other(u32),
+}
+
+impl MouseButton {
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            272 => MouseButton::Left,
+            273 => MouseButton::Right,
+            274 => MouseButton::Middle,
+            275 | 278 => MouseButton::Back,
+            276 | 277 => MouseButton::Forward,
+            code => MouseButton::Other(code),
+        }
+    }
+
+    /// X11-style button number, as widgets have always received it
+    pub fn number(self) -> u32 {
+        match self {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+            MouseButton::Back => 8,
+            MouseButton::Forward => 9,
+            MouseButton::Other(code) => code,
+        }
+    }
+
+    /// Stable name used in `button_actions` and sent to widgets
+    pub fn name(self) -> String {
+        match self {
+            MouseButton::Left => "left".to_string(),
+            MouseButton::Right => "right".to_string(),
+            MouseButton::Middle => "middle".to_string(),
+            MouseButton::Back => "back".to_string(),
+            MouseButton::Forward => "forward".to_string(),
+            MouseButton::Other(code) => format!("button{}", code),
+        }
+    }
+}
+
+/// Input event on an icon surface
+#[derive(Debug, Clone)]
+pub enum InputEvent {
+    /// Pointer entered a surface
+    PointerEnter {
+        surface_id: SurfaceId,
+        x: f64,
+        y: f64,
+    },
+    /// Pointer left a surface
+    PointerLeave { surface_id: SurfaceId },
+    /// Pointer moved on a surface
+    PointerMotion {
+        surface_id: SurfaceId,
+        x: f64,
+        y: f64,
+    },
+    /// Pointer button pressed/released
+    ///
+    /// A touch on a touchscreen comes as a press and release of `BTN_TOUCH`,
+    /// with the enter, motion and leave events of a pointer around it.
+    PointerButton {
+        surface_id: SurfaceId,
+        button: u32,
+        pressed: bool,
+        x: f64,
+        y: f64,
+        modifiers: KeyModifiers,
+    },
+    /// Pointer scrolled over a surface
+    PointerScroll {
+        surface_id: SurfaceId,
+        /// Horizontal scroll distance in surface pixels, positive to the right
+        horizontal: f64,
+        /// Vertical scroll distance in surface pixels, positive downwards
+        vertical: f64,
+        /// Wheel clicks `(horizontal, vertical)`, zero for touchpads
+        discrete: (i32, i32),
+    },
+    /// The button held on a surface was let go of without a release, as when
+    /// the compositor takes a touch over for a gesture of its own
+    PointerCancel { surface_id: SurfaceId },
+    /// Key pressed while an icon surface had keyboard focus
+    Key {
+        /// X keysym of the key, after the layout and Shift are applied
+        keysym: u32,
+        modifiers: KeyModifiers,
+    },
+}
+
+/// Button code of a touch, evdev's `BTN_TOUCH`
+pub const BTN_TOUCH: u32 = 330;
+
+/// Pointer cursor shown over the daemon's surfaces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CursorShape {
+    /// The usual arrow
+    #[default]
+    Default,
+    /// A hand, over something that can be clicked or dragged
+    Pointer,
+    /// A closed hand, while icons are dragged
+    Grabbing,
+}
+
+/// Default for `InputQueue`'s limit
+pub const DEFAULT_INPUT_QUEUE_LIMIT: usize = 1024;
+
+/// Input events waiting for the daemon to take them
+///
+/// If the daemon's loop stalls, a burst of pointer input could grow the
+/// queue without bound. Past `limit` events the oldest motion is dropped to
+/// make room; the latest motion still carries the pointer position. Enter,
+/// leave, button, scroll and key events are never dropped, so the queue can
+/// only exceed the limit with those.
+#[derive(Debug)]
+pub struct InputQueue {
+    events: Vec<InputEvent>,
+    limit: usize,
+    /// Whether the limit was hit since the queue was last taken
+    overflowed: bool,
+}
+
+impl Default for InputQueue {
+    fn default() -> Self {
+        Self { events: Vec::new(), limit: DEFAULT_INPUT_QUEUE_LIMIT, overflowed: false }
+    }
+}
+
+impl InputQueue {
+    /// Change the number of events kept (at least one)
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+    }
+
+    /// Queue `event`, dropping motion if the queue is full
+    pub fn push(&mut self, event: InputEvent) {
+        if self.events.len() >= self.limit {
+            if !self.overflowed {
+                warn!("Input queue reached {} events, dropping pointer motion", self.limit);
+                self.overflowed = true;
+            }
+            let is_motion = |event: &InputEvent| matches!(event, InputEvent::PointerMotion { .. });
+            match self.events.iter().position(is_motion) {
+                Some(oldest) => {
+                    self.events.remove(oldest);
+                }
+                // Nothing to make room with: newer motion supersedes none
+                // of the queued events, so it is the one to lose
+                None if is_motion(&event) => return,
+                None => {}
+            }
+        }
+        self.events.push(event);
+    }
+
+    /// Take every queued event, oldest first
+    pub fn take(&mut self) -> Vec<InputEvent> {
+        self.overflowed = false;
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Display side of the daemon: surfaces, buffers, outputs and input
+///
+/// Pixel data passed to `attach_buffer*` is tiny-skia's premultiplied RGBA.
+pub trait SurfaceBackend {
+    /// Create a surface for an icon at output coordinates `(x, y)`
+    fn create_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId>;
+
+    /// Create an icon surface at `(x, y)` on the output named `output`,
+    /// counting from that output's top-left corner
+    ///
+    /// Backends with a single output create it on that one.
+    fn create_surface_on(
+        &mut self,
+        _output: &str,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        self.create_surface(x, y, width, height)
+    }
+
+    /// Create a transient surface above everything else that ignores input
+    fn create_overlay_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId>;
+
+    /// Create a transparent surface beneath the icons, covering the output
+    ///
+    /// Presses on the desktop between icons land on it. Surfaces on a layer
+    /// stack in the order they are created, so it is made before the icons'.
+    /// Backends without one return an error.
+    fn create_backdrop_surface(&mut self) -> IconsResult<SurfaceId> {
+        Err(IconsError::Wayland(anyhow::anyhow!("This backend has no backdrop surface")))
+    }
+
+    /// Destroy a surface (unknown IDs are ignored)
+    fn destroy_surface(&mut self, surface_id: SurfaceId);
+
+    /// Move a surface to output coordinates `(x, y)`
+    fn set_surface_position(&mut self, surface_id: SurfaceId, x: i32, y: i32);
+
+    /// Unmap a surface, or map it again
+    ///
+    /// A remapped surface reports itself in `take_newly_configured` once it
+    /// is ready for a new buffer; until then attaches return `false`.
+    fn set_surface_mapped(&mut self, _surface_id: SurfaceId, _mapped: bool) {}
+
+    /// Attach a buffer, damaging only the given regions
+    ///
+    /// Returns `false` if the surface is not ready for buffers yet; the daemon
+    /// retries once it shows up in `take_newly_configured`.
+    fn attach_buffer_damaged(
+        &mut self,
+        surface_id: SurfaceId,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        damage: &[DamageRect],
+    ) -> IconsResult<bool>;
+
+    /// Attach a buffer, damaging the whole surface
+    fn attach_buffer(
+        &mut self,
+        surface_id: SurfaceId,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> IconsResult<bool> {
+        self.attach_buffer_damaged(surface_id, pixels, width, height, &[DamageRect::full(width, height)])
+    }
+
+    /// Current size of a surface, which the backend may have changed
+    fn surface_size(&self, surface_id: SurfaceId) -> Option<(u32, u32)>;
+
+    /// Integer scale of the output a surface is on
+    ///
+    /// Buffers `scale` times the surface size are shown at that scale (see
+    /// `buffer_scale`). A change is reported through `take_resized`.
+    fn surface_scale(&self, _surface_id: SurfaceId) -> u32 {
+        1
+    }
+
+    /// Output coordinates of a surface's top-left corner
+    fn surface_position(&self, surface_id: SurfaceId) -> Option<(i32, i32)>;
+
+    /// Process pending backend events without blocking
+    fn dispatch_events(&mut self) -> IconsResult<()> {
+        Ok(())
+    }
+
+    /// File descriptor that becomes readable when there are events to dispatch
+    ///
+    /// With one the daemon can sleep until the display has something for it
+    /// rather than waking every frame to poll. Backends without one are polled.
+    fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+
+    /// Send requests made since the last dispatch to the display now
+    ///
+    /// Called before the daemon goes to sleep, so buffers it just attached
+    /// are shown (and their frame callbacks come back) without waiting for
+    /// the next dispatch.
+    fn flush(&mut self) {}
+
+    /// Surfaces that became ready for buffers since the last call
+    fn take_newly_configured(&mut self) -> Vec<SurfaceId>;
+
+    /// Surfaces whose size changed since the last call
+    fn take_resized(&mut self) -> Vec<SurfaceId> {
+        Vec::new()
+    }
+
+    /// Whether an output icons are on changed its logical size or scale
+    /// since the last call
+    fn take_outputs_changed(&mut self) -> bool {
+        false
+    }
+
+    /// Input events received since the last call
+    fn take_input_events(&mut self) -> Vec<InputEvent>;
+
+    /// Whether a presented frame has not been consumed yet
+    fn frame_callback_pending(&self) -> bool {
+        false
+    }
+
+    /// Frame group a surface belongs to
+    ///
+    /// Groups are paced independently, so a slow or hidden output does not
+    /// hold back drawing on the others. By default every surface shares one.
+    fn frame_group(&self, _surface_id: SurfaceId) -> FrameGroup {
+        0
+    }
+
+    /// Whether a surface in `group` has a presented frame not consumed yet
+    fn frame_group_pending(&self, _group: FrameGroup) -> bool {
+        self.frame_callback_pending()
+    }
+
+    /// Whether the backend wants the daemon to stop
+    fn should_exit(&self) -> bool {
+        false
+    }
+
+    /// Whether a window is known to cover the whole output icons are on
+    ///
+    /// Backends that cannot tell report `false`, so icons stay visible.
+    fn desktop_covered(&self) -> bool {
+        false
+    }
+
+    /// Raise a running window whose app ID is one of `app_ids`
+    ///
+    /// Returns whether one was found. Backends that cannot see or raise other
+    /// windows return `false`, so the application is launched instead.
+    fn activate_app(&mut self, _app_ids: &[String]) -> bool {
+        false
+    }
+
+    /// Dimensions of the output icons are placed on
+    fn get_output_dimensions(&self) -> Option<(u32, u32)>;
+
+    /// Name of the output icons are placed on, for per-output settings
+    fn get_output_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Name and logical size of every connected output, in the order the
+    /// compositor announced them
+    ///
+    /// Outputs whose name or size is not known yet are left out.
+    fn output_dimensions_all(&self) -> Vec<(String, u32, u32)> {
+        Vec::new()
+    }
+
+    /// Place icons on the output with this name while it is connected
+    ///
+    /// With None, or while the named output is missing, the first output is
+    /// used. Backends with a single output ignore this.
+    fn set_primary_output(&mut self, _name: Option<String>) {}
+
+    /// Choose which output surfaces created from now on are bound to
+    ///
+    /// Existing surfaces keep their output. Backends with a single output
+    /// ignore this.
+    fn set_surface_output_binding(&mut self, _binding: SurfaceOutputBinding) {}
+
+    /// Anchor surfaces created or moved from now on to `corner` of the output
+    ///
+    /// Positions are still given from the top-left. Backends that anchor
+    /// surfaces turn them into offsets from `corner`, so surfaces stay pinned
+    /// to it while the output changes size; others ignore this.
+    fn set_anchor_corner(&mut self, _corner: Corner) {}
+
+    /// Limit the input events queued between calls to `take_input_events`
+    ///
+    /// See `InputQueue` for which events are dropped past the limit.
+    fn set_input_queue_limit(&mut self, _limit: usize) {}
+
+    /// Show every surface at `opacity` from 0 to 1
+    ///
+    /// Applies to buffers attached from now on.
+    fn set_surface_opacity(&mut self, _opacity: f32) {}
+
+    /// Start or stop placing surfaces relative to the output's work area
+    ///
+    /// The work area is the output minus the exclusive zones of panels and
+    /// docks. Backends that cannot tell it ignore this.
+    fn set_work_area_anchoring(&mut self, _enabled: bool) {}
+
+    /// Size of the work area, once anchoring to it is enabled and it is known
+    ///
+    /// While this returns a size, surface coordinates count from the work
+    /// area's top-left corner rather than the output's.
+    fn get_work_area(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Offer `text` on the clipboard, replacing what was there
+    ///
+    /// Backends without a clipboard return an error.
+    fn set_clipboard_text(&mut self, _text: &str) -> IconsResult<()> {
+        Err(IconsError::Wayland(anyhow::anyhow!("This backend has no clipboard")))
+    }
+
+    /// Show `shape` while the pointer is over any of the surfaces
+    ///
+    /// Backends without a pointer of their own ignore this.
+    fn set_cursor_shape(&mut self, _shape: CursorShape) {}
+}
+
+/// A surface held by `HeadlessBackend`
+#[derive(Debug, Clone)]
+pub struct HeadlessSurface {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Created with `create_overlay_surface`
+    pub overlay: bool,
+    /// Last attached buffer (premultiplied RGBA), if any
+    pub pixels: Option<Vec<u8>>,
+    /// Whether the surface is mapped; unmapping drops its buffer
+    pub mapped: bool,
+    /// Buffers attached so far
+    pub frames: u32,
+    /// Times the surface was moved with `set_surface_position`
+    pub moves: u32,
+    /// Frame group, as set by `set_frame_group`
+    pub frame_group: FrameGroup,
+    /// Output named in `create_surface_on`, None for the primary output
+    pub output: Option<String>,
+    /// Scale of the surface's output, as set by `set_surface_scale`
+    pub scale: u32,
+    /// Scale the last attached buffer is shown at
+    pub buffer_scale: u32,
+}
+
+/// In-memory backend for compositing icons yourself
+///
+/// Surfaces are ready as soon as they are created. Attached buffers are kept
+/// for the embedder to read back, and input is whatever it pushes. Clones
+/// share state, so keep one after handing the backend to the daemon.
+#[derive(Debug, Clone)]
+pub struct HeadlessBackend {
+    state: Rc<RefCell<HeadlessState>>,
+}
+
+#[derive(Debug)]
+struct HeadlessState {
+    /// None until the output reports its size
+    output: Option<(u32, u32)>,
+    output_name: Option<String>,
+    /// Outputs connected besides the one icons are placed on
+    other_outputs: Vec<(String, u32, u32)>,
+    /// Output name last passed to `set_primary_output`
+    primary_output: Option<String>,
+    /// Binding last passed to `set_surface_output_binding`
+    surface_output_binding: SurfaceOutputBinding,
+    /// Corner last passed to `set_anchor_corner`
+    anchor_corner: Corner,
+    surfaces: HashMap<SurfaceId, HeadlessSurface>,
+    next_surface_id: SurfaceId,
+    newly_configured: Vec<SurfaceId>,
+    resized: Vec<SurfaceId>,
+    /// Whether the output changed size or scale since the last drain
+    outputs_changed: bool,
+    input_events: InputQueue,
+    /// Opacity last passed to `set_surface_opacity`, applied to attached pixels
+    surface_opacity: f32,
+    exit: bool,
+    /// Bytes of buffers all surfaces may hold at once, if limited
+    pool_size: Option<usize>,
+    /// Whether attaches leave a frame pending until `present_frames`
+    frame_callbacks: bool,
+    /// Surfaces with an attached frame not yet presented
+    pending_frames: HashSet<SurfaceId>,
+    /// Work area reported while anchoring to it, if any
+    work_area: Option<(u32, u32)>,
+    work_area_anchoring: bool,
+    /// Whether a window covers the output, as set by `set_desktop_covered`
+    covered: bool,
+    /// App IDs of the windows `activate_app` can raise
+    running_apps: Vec<String>,
+    /// App IDs of the windows raised so far
+    activated_apps: Vec<String>,
+    /// Text last passed to `set_clipboard_text`
+    clipboard: Option<String>,
+    /// Shape last passed to `set_cursor_shape`
+    cursor_shape: CursorShape,
+}
+
+impl HeadlessBackend {
+    /// Create a backend for an output of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        let state = HeadlessState {
+            output: Some((width, height)),
+            output_name: None,
+            other_outputs: Vec::new(),
+            primary_output: None,
+            surface_output_binding: SurfaceOutputBinding::default(),
+            anchor_corner: Corner::default(),
+            surfaces: HashMap::new(),
+            next_surface_id: 1,
+            newly_configured: Vec::new(),
+            resized: Vec::new(),
+            outputs_changed: false,
+            input_events: InputQueue::default(),
+            surface_opacity: 1.0,
+            exit: false,
+            pool_size: None,
+            frame_callbacks: false,
+            pending_frames: HashSet::new(),
+            work_area: None,
+            work_area_anchoring: false,
+            covered: false,
+            running_apps: Vec::new(),
+            activated_apps: Vec::new(),
+            clipboard: None,
+            cursor_shape: CursorShape::Default,
+        };
+        Self { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Set the output name used to look up per-output settings
+    pub fn with_output_name(self, name: impl Into<String>) -> Self {
+        self.state.borrow_mut().output_name = Some(name.into());
+        self
+    }
+
+    /// Change the output name, as when icons move to another output
+    pub fn set_output_name(&self, name: impl Into<String>) {
+        self.state.borrow_mut().output_name = Some(name.into());
+    }
+
+    /// Connect further outputs, by name and size, or unplug them
+    pub fn set_other_outputs(&self, outputs: Vec<(String, u32, u32)>) {
+        self.state.borrow_mut().other_outputs = outputs;
+    }
+
+    /// Output name the daemon asked icons to be placed on
+    pub fn primary_output(&self) -> Option<String> {
+        self.state.borrow().primary_output.clone()
+    }
+
+    /// Output binding the daemon asked new surfaces to use
+    pub fn surface_output_binding(&self) -> SurfaceOutputBinding {
+        self.state.borrow().surface_output_binding
+    }
+
+    /// Corner the daemon asked surfaces to be anchored to
+    pub fn anchor_corner(&self) -> Corner {
+        self.state.borrow().anchor_corner
+    }
+
+    /// Change the output size (the daemon re-lays out icons on its next step)
+    ///
+    /// Reported in `take_outputs_changed`, as a compositor's output update is.
+    pub fn set_output_dimensions(&self, width: u32, height: u32) {
+        let mut state = self.state.borrow_mut();
+        state.output = Some((width, height));
+        state.outputs_changed = true;
+    }
+
+    /// Report that the output's scale changed, leaving its logical size alone
+    pub fn change_output_scale(&self) {
+        self.state.borrow_mut().outputs_changed = true;
+    }
+
+    /// Report no output size, as before the compositor has announced outputs
+    pub fn clear_output_dimensions(&self) {
+        self.state.borrow_mut().output = None;
+    }
+
+    /// Set the work area reported once the daemon anchors to it
+    pub fn set_work_area(&self, area: Option<(u32, u32)>) {
+        self.state.borrow_mut().work_area = area;
+    }
+
+    /// Whether the daemon asked for surfaces to be placed in the work area
+    pub fn anchors_to_work_area(&self) -> bool {
+        self.state.borrow().work_area_anchoring
+    }
+
+    /// Resize a surface, as a compositor might
+    pub fn resize_surface(&self, surface_id: SurfaceId, width: u32, height: u32) {
+        let mut state = self.state.borrow_mut();
+        if let Some(surface) = state.surfaces.get_mut(&surface_id) {
+            surface.width = width;
+            surface.height = height;
+            state.resized.push(surface_id);
+        }
+    }
+
+    /// Move a surface to an output with the given scale
+    pub fn set_surface_scale(&self, surface_id: SurfaceId, scale: u32) {
+        let mut state = self.state.borrow_mut();
+        if let Some(surface) = state.surfaces.get_mut(&surface_id) {
+            surface.scale = scale.max(1);
+            state.resized.push(surface_id);
+        }
+    }
+
+    /// Limit the bytes of buffers all surfaces may hold at once
+    ///
+    /// Attaches that would exceed the limit fail, like a shm pool that cannot
+    /// grow. `None` removes the limit.
+    pub fn set_pool_size(&self, bytes: Option<usize>) {
+        self.state.borrow_mut().pool_size = bytes;
+    }
+
+    /// Leave each attached frame pending until `present_frames`, as a
+    /// compositor's frame callbacks would
+    pub fn with_frame_callbacks(self) -> Self {
+        self.state.borrow_mut().frame_callbacks = true;
+        self
+    }
+
+    /// Put a surface in a frame group, as if it were on another output
+    pub fn set_frame_group(&self, surface_id: SurfaceId, group: FrameGroup) {
+        if let Some(surface) = self.state.borrow_mut().surfaces.get_mut(&surface_id) {
+            surface.frame_group = group;
+        }
+    }
+
+    /// Signal the frame callbacks of every surface in `group`
+    pub fn present_frames(&self, group: FrameGroup) {
+        let mut state = self.state.borrow_mut();
+        let HeadlessState { surfaces, pending_frames, .. } = &mut *state;
+        pending_frames.retain(|id| surfaces.get(id).is_some_and(|s| s.frame_group != group));
+    }
+
+    /// Report a window covering the output, or none
+    pub fn set_desktop_covered(&self, covered: bool) {
+        self.state.borrow_mut().covered = covered;
+    }
+
+    /// Report running windows with these app IDs
+    pub fn set_running_apps(&self, app_ids: &[&str]) {
+        self.state.borrow_mut().running_apps = app_ids.iter().map(|id| id.to_string()).collect();
+    }
+
+    /// App IDs of the windows `activate_app` raised, in order
+    pub fn activated_apps(&self) -> Vec<String> {
+        self.state.borrow().activated_apps.clone()
+    }
+
+    /// Queue an input event for the daemon
+    pub fn push_input(&self, event: InputEvent) {
+        self.state.borrow_mut().input_events.push(event);
+    }
+
+    /// Text the daemon last put on the clipboard
+    pub fn clipboard(&self) -> Option<String> {
+        self.state.borrow().clipboard.clone()
+    }
+
+    /// Cursor the daemon last asked for
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.state.borrow().cursor_shape
+    }
+
+    /// Ask the daemon to stop
+    pub fn request_exit(&self) {
+        self.state.borrow_mut().exit = true;
+    }
+
+    /// A snapshot of a surface, including its last buffer
+    pub fn surface(&self, surface_id: SurfaceId) -> Option<HeadlessSurface> {
+        self.state.borrow().surfaces.get(&surface_id).cloned()
+    }
+
+    /// IDs of all live surfaces
+    pub fn surface_ids(&self) -> Vec<SurfaceId> {
+        self.state.borrow().surfaces.keys().copied().collect()
+    }
+
+    fn insert_surface(&mut self, x: i32, y: i32, width: u32, height: u32, overlay: bool) -> SurfaceId {
+        let mut state = self.state.borrow_mut();
+        let surface_id = state.next_surface_id;
+        state.next_surface_id += 1;
+        state.surfaces.insert(
+            surface_id,
+            HeadlessSurface {
+                x,
+                y,
+                width,
+                height,
+                overlay,
+                pixels: None,
+                mapped: true,
+                frames: 0,
+                moves: 0,
+                frame_group: 0,
+                output: None,
+                scale: 1,
+                buffer_scale: 1,
+            },
+        );
+        state.newly_configured.push(surface_id);
+        surface_id
+    }
+}
+
+impl SurfaceBackend for HeadlessBackend {
+    fn create_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        Ok(self.insert_surface(x, y, width, height, false))
+    }
+
+    fn create_surface_on(
+        &mut self,
+        output: &str,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        let surface_id = self.insert_surface(x, y, width, height, false);
+        if let Some(surface) = self.state.borrow_mut().surfaces.get_mut(&surface_id) {
+            surface.output = Some(output.to_string());
+        }
+        Ok(surface_id)
+    }
+
+    fn create_overlay_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        Ok(self.insert_surface(x, y, width, height, true))
+    }
+
+    fn create_backdrop_surface(&mut self) -> IconsResult<SurfaceId> {
+        // Sized to the output as it is now; an embedder resizes it with `resize_surface`
+        let (width, height) = self.state.borrow().output.unwrap_or((0, 0));
+        Ok(self.insert_surface(0, 0, width, height, false))
+    }
+
+    fn destroy_surface(&mut self, surface_id: SurfaceId) {
+        let mut state = self.state.borrow_mut();
+        state.surfaces.remove(&surface_id);
+        state.pending_frames.remove(&surface_id);
+    }
+
+    fn set_surface_mapped(&mut self, surface_id: SurfaceId, mapped: bool) {
+        let mut state = self.state.borrow_mut();
+        let Some(surface) = state.surfaces.get_mut(&surface_id) else {
+            return;
+        };
+        if surface.mapped == mapped {
+            return;
+        }
+        surface.mapped = mapped;
+        if mapped {
+            state.newly_configured.push(surface_id);
+        } else {
+            surface.pixels = None;
+        }
+    }
+
+    fn set_surface_position(&mut self, surface_id: SurfaceId, x: i32, y: i32) {
+        if let Some(surface) = self.state.borrow_mut().surfaces.get_mut(&surface_id) {
+            surface.x = x;
+            surface.y = y;
+            surface.moves += 1;
+        }
+    }
+
+    fn attach_buffer_damaged(
+        &mut self,
+        surface_id: SurfaceId,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        _damage: &[DamageRect],
+    ) -> IconsResult<bool> {
+        let mut state = self.state.borrow_mut();
+        if let Some(pool_size) = state.pool_size {
+            let in_use: usize = state
+                .surfaces
+                .iter()
+                .filter(|(&id, _)| id != surface_id)
+                .filter_map(|(_, s)| s.pixels.as_ref().map(Vec::len))
+                .sum();
+            if in_use + pixels.len() > pool_size {
+                return Err(IconsError::Wayland(anyhow::anyhow!(
+                    "Failed to create buffer: pool of {} bytes exhausted",
+                    pool_size
+                )));
+            }
+        }
+
+        let opacity = state.surface_opacity;
+        let surface = state
+            .surfaces
+            .get_mut(&surface_id)
+            .ok_or_else(|| {
+                IconsError::Wayland(anyhow::anyhow!("Surface {} not found", surface_id))
+            })?;
+
+        if !surface.mapped {
+            return Ok(false);
+        }
+
+        let expected_size = (width * height * 4) as usize;
+        if pixels.len() != expected_size {
+            return Err(IconsError::Wayland(anyhow::anyhow!(
+                "Buffer size mismatch: got {} bytes, expected {} bytes ({}x{}x4)",
+                pixels.len(), expected_size, width, height
+            )));
+        }
+
+        // Undamaged regions are unchanged, so keeping the whole frame is enough
+        let mut pixels = pixels.to_vec();
+        scale_opacity(&mut pixels, opacity);
+        surface.pixels = Some(pixels);
+        surface.buffer_scale =
+            buffer_scale((surface.width, surface.height), (width, height), surface.scale);
+        surface.frames += 1;
+        if state.frame_callbacks {
+            state.pending_frames.insert(surface_id);
+        }
+        Ok(true)
+    }
+
+    fn surface_size(&self, surface_id: SurfaceId) -> Option<(u32, u32)> {
+        self.state.borrow().surfaces.get(&surface_id).map(|s| (s.width, s.height))
+    }
+
+    fn surface_scale(&self, surface_id: SurfaceId) -> u32 {
+        self.state.borrow().surfaces.get(&surface_id).map_or(1, |s| s.scale)
+    }
+
+    fn surface_position(&self, surface_id: SurfaceId) -> Option<(i32, i32)> {
+        self.state.borrow().surfaces.get(&surface_id).map(|s| (s.x, s.y))
+    }
+
+    fn take_newly_configured(&mut self) -> Vec<SurfaceId> {
+        std::mem::take(&mut self.state.borrow_mut().newly_configured)
+    }
+
+    fn take_resized(&mut self) -> Vec<SurfaceId> {
+        std::mem::take(&mut self.state.borrow_mut().resized)
+    }
+
+    fn take_outputs_changed(&mut self) -> bool {
+        std::mem::take(&mut self.state.borrow_mut().outputs_changed)
+    }
+
+    fn take_input_events(&mut self) -> Vec<InputEvent> {
+        self.state.borrow_mut().input_events.take()
+    }
+
+    fn should_exit(&self) -> bool {
+        self.state.borrow().exit
+    }
+
+    fn desktop_covered(&self) -> bool {
+        self.state.borrow().covered
+    }
+
+    fn activate_app(&mut self, app_ids: &[String]) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(app_id) = state.running_apps.iter().find(|id| launcher::matches(app_ids, id)).cloned() else {
+            return false;
+        };
+        state.activated_apps.push(app_id);
+        true
+    }
+
+    fn get_output_dimensions(&self) -> Option<(u32, u32)> {
+        self.state.borrow().output
+    }
+
+    fn get_output_name(&self) -> Option<String> {
+        self.state.borrow().output_name.clone()
+    }
+
+    fn output_dimensions_all(&self) -> Vec<(String, u32, u32)> {
+        let state = self.state.borrow();
+        let primary = match (&state.output_name, state.output) {
+            (Some(name), Some((width, height))) => Some((name.clone(), width, height)),
+            _ => None,
+        };
+        primary.into_iter().chain(state.other_outputs.iter().cloned()).collect()
+    }
+
+    fn set_primary_output(&mut self, name: Option<String>) {
+        self.state.borrow_mut().primary_output = name;
+    }
+
+    fn set_surface_output_binding(&mut self, binding: SurfaceOutputBinding) {
+        self.state.borrow_mut().surface_output_binding = binding;
+    }
+
+    fn set_anchor_corner(&mut self, corner: Corner) {
+        self.state.borrow_mut().anchor_corner = corner;
+    }
+
+    fn set_input_queue_limit(&mut self, limit: usize) {
+        self.state.borrow_mut().input_events.set_limit(limit);
+    }
+
+    fn set_surface_opacity(&mut self, opacity: f32) {
+        self.state.borrow_mut().surface_opacity = opacity;
+    }
+
+    fn set_work_area_anchoring(&mut self, enabled: bool) {
+        self.state.borrow_mut().work_area_anchoring = enabled;
+    }
+
+    fn get_work_area(&self) -> Option<(u32, u32)> {
+        let state = self.state.borrow();
+        state.work_area.filter(|_| state.work_area_anchoring)
+    }
+
+    fn set_clipboard_text(&mut self, text: &str) -> IconsResult<()> {
+        self.state.borrow_mut().clipboard = Some(text.to_string());
+        Ok(())
+    }
+
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.state.borrow_mut().cursor_shape = shape;
+    }
+
+    fn frame_callback_pending(&self) -> bool {
+        !self.state.borrow().pending_frames.is_empty()
+    }
+
+    fn frame_group(&self, surface_id: SurfaceId) -> FrameGroup {
+        self.state.borrow().surfaces.get(&surface_id).map_or(0, |surface| surface.frame_group)
+    }
+
+    fn frame_group_pending(&self, group: FrameGroup) -> bool {
+        let state = self.state.borrow();
+        state.pending_frames.iter().any(|id| {
+            state.surfaces.get(id).is_some_and(|surface| surface.frame_group == group)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_button_names() {
+        let names: Vec<String> = (272..=279).map(|code| MouseButton::from_code(code).name()).collect();
+        assert_eq!(
+            names,
+            ["left", "right", "middle", "back", "forward", "forward", "back", "button279"]
+        );
+    }
+
+    #[test]
+    fn test_headless_surfaces_are_ready_immediately() {
+        let mut backend = HeadlessBackend::new(800, 600);
+        let id = backend.create_surface(10, 20, 4, 2).unwrap();
+
+        assert_eq!(backend.take_newly_configured(), vec![id]);
+        assert!(backend.attach_buffer(id, &[255; 32], 4, 2).unwrap());
+        assert_eq!(backend.surface(id).unwrap().pixels.as_deref(), Some(&[255; 32][..]));
+    }
+
+    #[test]
+    fn test_headless_rejects_mismatched_buffers() {
+        let mut backend = HeadlessBackend::new(800, 600);
+        let id = backend.create_surface(0, 0, 4, 2).unwrap();
+
+        assert!(backend.attach_buffer(id, &[0; 8], 4, 2).is_err());
+        assert!(backend.attach_buffer(id + 1, &[0; 32], 4, 2).is_err());
+    }
+
+    #[test]
+    fn test_buffers_at_the_surface_scale_are_scaled() {
+        let mut backend = HeadlessBackend::new(800, 600);
+        let id = backend.create_surface(0, 0, 4, 2).unwrap();
+        backend.set_surface_scale(id, 2);
+        assert_eq!(backend.take_resized(), vec![id]);
+        assert_eq!(backend.surface_scale(id), 2);
+
+        assert!(backend.attach_buffer(id, &[0; 128], 8, 4).unwrap());
+        assert_eq!(backend.surface(id).unwrap().buffer_scale, 2);
+        assert!(backend.attach_buffer(id, &[0; 32], 4, 2).unwrap());
+        assert_eq!(backend.surface(id).unwrap().buffer_scale, 1, "1x buffers stay unscaled");
+        assert_eq!(buffer_scale((4, 2), (8, 5), 2), 1);
+    }
+
+    #[test]
+    fn test_headless_pool_limit() {
+        let mut backend = HeadlessBackend::new(800, 600);
+        let a = backend.create_surface(0, 0, 4, 2).unwrap();
+        let b = backend.create_surface(0, 0, 4, 2).unwrap();
+        backend.set_pool_size(Some(48));
+
+        assert!(backend.attach_buffer(a, &[0; 32], 4, 2).unwrap());
+        assert!(backend.attach_buffer(b, &[0; 32], 4, 2).is_err());
+        // Replacing a surface's own buffer does not count it twice
+        assert!(backend.attach_buffer(a, &[1; 32], 4, 2).unwrap());
+
+        backend.set_pool_size(None);
+        assert!(backend.attach_buffer(b, &[0; 32], 4, 2).unwrap());
+    }
+
+    #[test]
+    fn test_headless_input_and_resize_drain() {
+        let mut backend = HeadlessBackend::new(800, 600);
+        let id = backend.create_surface(0, 0, 4, 2).unwrap();
+        let handle = backend.clone();
+        handle.push_input(InputEvent::PointerLeave { surface_id: id });
+        handle.resize_surface(id, 8, 4);
+
+        assert_eq!(backend.take_input_events().len(), 1);
+        assert!(backend.take_input_events().is_empty());
+        assert_eq!(backend.take_resized(), vec![id]);
+        assert_eq!(backend.surface_size(id), Some((8, 4)));
+    }
+
+    #[test]
+    fn test_input_queue_drops_oldest_motion_first() {
+        let motion = |x: f64| InputEvent::PointerMotion { surface_id: 1, x, y: 0.0 };
+        let button = |pressed: bool| InputEvent::PointerButton {
+            surface_id: 1,
+            button: 272,
+            pressed,
+            x: 0.0,
+            y: 0.0,
+            modifiers: KeyModifiers::default(),
+        };
+        let mut queue = InputQueue::default();
+        queue.set_limit(3);
+
+        queue.push(InputEvent::PointerEnter { surface_id: 1, x: 0.0, y: 0.0 });
+        queue.push(motion(1.0));
+        queue.push(motion(2.0));
+        queue.push(button(true));
+        queue.push(motion(3.0));
+        let events = queue.take();
+        assert!(matches!(events[0], InputEvent::PointerEnter { .. }));
+        assert!(matches!(events[1], InputEvent::PointerButton { pressed: true, .. }));
+        assert!(matches!(events[2], InputEvent::PointerMotion { x, .. } if x == 3.0));
+        assert_eq!(events.len(), 3);
+
+        // With no motion left to drop, other events still get in and new
+        // motion is the one lost
+        queue.push(button(true));
+        queue.push(button(false));
+        queue.push(InputEvent::PointerLeave { surface_id: 1 });
+        queue.push(motion(4.0));
+        queue.push(button(true));
+        let events = queue.take();
+        assert_eq!(events.len(), 4);
+        assert!(!events.iter().any(|event| matches!(event, InputEvent::PointerMotion { .. })));
+    }
+}
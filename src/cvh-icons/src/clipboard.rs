@@ -0,0 +1,115 @@
+//! Clipboard text for the Copy Path and Copy as Text actions
+//!
+//! The daemon builds the text here and hands it to the backend, which offers
+//! it on the Wayland clipboard (`wl_data_device`) under the usual plain-text
+//! MIME types.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// MIME types clipboard text is offered as
+pub const TEXT_MIME_TYPES: [&str; 3] = ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING"];
+
+/// Largest file Copy as Text copies
+pub const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+
+/// Bytes read to decide whether a file is text
+const SNIFF_BYTES: usize = 8192;
+
+/// Text for Copy Path: the absolute paths, one per line
+pub fn paths_text(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| std::path::absolute(path).unwrap_or_else(|_| path.clone()))
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether `bytes` from the start of a file look like text: valid UTF-8
+/// without NUL bytes
+///
+/// A multi-byte character cut off at the end of the sample is allowed.
+fn looks_like_text(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && bytes.len() - e.valid_up_to() < 4,
+    }
+}
+
+/// Whether Copy as Text can copy the file: a regular text file of at most
+/// `MAX_TEXT_BYTES`
+pub fn is_copyable_text(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    if !metadata.is_file() || metadata.len() > MAX_TEXT_BYTES {
+        return false;
+    }
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut sample = Vec::with_capacity(SNIFF_BYTES);
+    if file.take(SNIFF_BYTES as u64).read_to_end(&mut sample).is_err() {
+        return false;
+    }
+    looks_like_text(&sample)
+}
+
+/// Text for Copy as Text: the file's contents
+pub fn file_text(path: &Path) -> Result<String> {
+    if !is_copyable_text(path) {
+        bail!("{} is not a text file of at most {} bytes", path.display(), MAX_TEXT_BYTES);
+    }
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|file| file.take(MAX_TEXT_BYTES).read_to_string(&mut contents))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_like_text() {
+        assert!(looks_like_text(b"hello\nworld"));
+        assert!(looks_like_text("naïve".as_bytes()));
+        // Cut in the middle of "é"
+        assert!(looks_like_text(&"café".as_bytes()[..4]));
+        assert!(!looks_like_text(b"\x89PNG\r\n\x1a\n\0\0"));
+        assert!(!looks_like_text(b"\xff\xfe text"));
+    }
+
+    #[test]
+    fn test_file_text_rejects_binary_and_large_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let notes = temp_dir.path().join("notes.txt");
+        std::fs::write(&notes, "line one\nline two\n").unwrap();
+        assert_eq!(file_text(&notes).unwrap(), "line one\nline two\n");
+
+        let binary = temp_dir.path().join("blob.bin");
+        std::fs::write(&binary, [0u8, 1, 2, 3]).unwrap();
+        assert!(!is_copyable_text(&binary));
+        assert!(file_text(&binary).is_err());
+
+        let large = temp_dir.path().join("large.txt");
+        std::fs::write(&large, "a".repeat(MAX_TEXT_BYTES as usize + 1)).unwrap();
+        assert!(!is_copyable_text(&large));
+
+        assert!(!is_copyable_text(temp_dir.path()), "Folders are not text");
+    }
+
+    #[test]
+    fn test_paths_text_one_per_line() {
+        let paths = vec![PathBuf::from("/home/u/Desktop/a.txt"), PathBuf::from("/home/u/Desktop/b c")];
+        assert_eq!(paths_text(&paths), "/home/u/Desktop/a.txt\n/home/u/Desktop/b c");
+    }
+}
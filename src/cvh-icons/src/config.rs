@@ -1,8 +1,29 @@
 //! Configuration for cvh-icons
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::warn;
+
+use crate::error::{DaemonError, IconsError, IconsResult};
+
+/// Smallest icon size the daemon renders
+pub const MIN_ICON_SIZE: u32 = 8;
+
+/// Largest icon size the daemon renders
+pub const MAX_ICON_SIZE: u32 = 1024;
+
+/// Largest magnification of the icon under the pointer
+pub const MAX_HOVER_ZOOM: f32 = 4.0;
+
+/// `size` limited to the icon sizes the daemon can render
+pub fn clamp_icon_size(size: u32) -> u32 {
+    size.clamp(MIN_ICON_SIZE, MAX_ICON_SIZE)
+}
 
 /// Main configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,6 +32,12 @@ pub struct Config {
     #[serde(default = "default_icon_size")]
     pub icon_size: u32,
 
+    /// Take the icon size from GNOME/Plasma settings instead of `icon_size`
+    ///
+    /// `icon_size` is still used when no supported desktop is detected.
+    #[serde(default)]
+    pub follow_desktop_icon_size: bool,
+
     /// Grid spacing
     #[serde(default = "default_grid_spacing")]
     pub grid_spacing: u32,
@@ -23,6 +50,41 @@ pub struct Config {
     #[serde(default = "default_label_width")]
     pub label_width: usize,
 
+    /// Most lines a label wraps over before the rest of the name is cut
+    /// off with "…"; icons are laid out tall enough for this many
+    #[serde(default = "default_label_lines")]
+    pub label_lines: u32,
+
+    /// Horizontal alignment of the label text within the label band
+    #[serde(default)]
+    pub label_align: LabelAlign,
+
+    /// Show the full name of an icon whose label is truncated while the
+    /// pointer is over it, wrapped over as many lines as it needs
+    #[serde(default)]
+    pub expand_labels_on_hover: bool,
+
+    /// Show a tooltip with the full file name beside an icon the pointer
+    /// rests on for `tooltip_delay_ms`
+    #[serde(default = "default_true")]
+    pub tooltips: bool,
+
+    /// How long (ms) the pointer rests on an icon before its tooltip shows
+    #[serde(default = "default_tooltip_delay_ms")]
+    pub tooltip_delay_ms: u64,
+
+    /// Add the file's size and when it was last modified to the tooltip
+    #[serde(default)]
+    pub tooltip_details: bool,
+
+    /// How icons with the same name (a file and a volume, say) are told apart
+    #[serde(default)]
+    pub duplicate_labels: DuplicateLabels,
+
+    /// Pill behind just the label text, instead of a background across the label
+    #[serde(default)]
+    pub label_pill: LabelPillConfig,
+
     /// Directories to search for Lua scripts
     #[serde(default = "default_script_dirs")]
     pub script_dirs: Vec<PathBuf>,
@@ -31,6 +93,405 @@ pub struct Config {
     #[serde(default = "default_icon_theme")]
     pub icon_theme: String,
 
+    /// Directory of images named for icon types (`folder.png`, `archive.svg`,
+    /// ...) drawn for icons no widget draws; other types keep their glyph
+    #[serde(default)]
+    pub icon_theme_dir: Option<PathBuf>,
+
+    /// Filename glob patterns to hide from the desktop (e.g. `*.tmp`, `~*`, `Thumbs.db`)
+    ///
+    /// Patterns containing a `/` match the entry's full path instead.
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+
+    /// If non-empty, only filenames matching one of these glob patterns are shown
+    #[serde(default)]
+    pub allow_patterns: Vec<String>,
+
+    /// Whether dotfiles on the desktop get icons
+    ///
+    /// Ignore and allow patterns still apply to them.
+    #[serde(default)]
+    pub show_hidden: bool,
+
+    /// What drives rendering: compositor frame callbacks or a fixed timer
+    #[serde(default)]
+    pub frame_pacing: FramePacing,
+
+    /// Render timer interval in ms (timer pacing) and main loop wake-up interval
+    ///
+    /// With frame-callback pacing an idle daemon doesn't wake on this
+    /// interval; it sleeps until the display or another event source wakes it.
+    #[serde(default = "default_frame_interval_ms")]
+    pub frame_interval_ms: u64,
+
+    /// How long, in ms, an idle daemon waits on the display connection at most
+    ///
+    /// While no icon needs drawing or is animating, the main loop sleeps on
+    /// the display until an event source wakes it. A nonzero value bounds
+    /// that sleep, for compositors that stop sending events a frame-paced
+    /// loop relies on. 0 waits without a bound; busy loops never wait on it.
+    #[serde(default)]
+    pub dispatch_timeout_ms: u64,
+
+    /// Interval in ms for icon state updates (existence checks etc.)
+    #[serde(default = "default_update_interval_ms")]
+    pub update_interval_ms: u64,
+
+    /// How long a path must go without filesystem events before its icon is updated, in ms
+    ///
+    /// Larger windows fold more of a burst of changes (a download, a build,
+    /// a slow network filesystem) into one icon update, but delay every new
+    /// icon by as much. 0 applies each event as soon as it arrives.
+    #[serde(default = "default_fs_coalesce_ms", alias = "fs_debounce_ms")]
+    pub fs_coalesce_ms: u64,
+
+    /// Whether files created and deleted within one debounce window get an icon
+    #[serde(default)]
+    pub transient_files: TransientFiles,
+
+    /// Log every raw watcher event at info level, before debouncing and filtering
+    #[serde(default)]
+    pub trace_fs_events: bool,
+
+    /// Draw the icon grid's cells and layout indices over the output, for
+    /// tuning spacing and origins
+    #[serde(default)]
+    pub debug_grid: bool,
+
+    /// What happens to icons whose files become unreachable, e.g. on unplugged media
+    #[serde(default)]
+    pub unavailable_files: UnavailableFiles,
+
+    /// Consecutive failed checks of a file before its icon is removed (or
+    /// marked unavailable); the icon is drawn faded until then
+    #[serde(default = "default_update_error_threshold")]
+    pub update_error_threshold: u32,
+
+    /// What a modify event that does not say what changed does to an icon
+    #[serde(default)]
+    pub ambiguous_modify: AmbiguousModify,
+
+    /// What happens to filesystem events while the daemon is paused
+    #[serde(default)]
+    pub paused_fs_events: PausedFsEvents,
+
+    /// Render type glyphs as single-color symbolic icons tinted with `colors.label_fg`
+    ///
+    /// Widgets are not drawn while this is on: they paint their glyphs,
+    /// labels and highlights in their own colors in one buffer, which can't
+    /// be recolored without flattening the highlights too. They still get
+    /// events, and their menu entries and badges still show.
+    #[serde(default)]
+    pub symbolic_icons: bool,
+
+    /// With `symbolic_icons`, also recolor image thumbnails; otherwise they keep their colors
+    #[serde(default)]
+    pub symbolic_strict: bool,
+
+    /// Output to show icons on, by name (e.g. "DP-1")
+    ///
+    /// Unset, or while that output is not connected, icons go on the first
+    /// output the compositor announced.
+    #[serde(default)]
+    pub primary_output: Option<String>,
+
+    /// Which output each icon surface is created on (see
+    /// `SurfaceOutputBinding` for which compositors want which)
+    #[serde(default)]
+    pub surface_output_binding: SurfaceOutputBinding,
+
+    /// Carry icons that don't fit the primary output's grid on to grids on
+    /// the other outputs, in the order the compositor announced them
+    ///
+    /// Icons on an output that is unplugged go back to the remaining ones.
+    #[serde(default)]
+    pub span_outputs: bool,
+
+    /// Space kept free of icons along each screen edge (e.g. for a dock)
+    #[serde(default)]
+    pub reserved_edges: ReservedEdges,
+
+    /// Per-output overrides of `reserved_edges`, keyed by output name (e.g. "DP-1")
+    #[serde(default)]
+    pub output_reserved_edges: HashMap<String, ReservedEdges>,
+
+    /// Areas inside the grid icons never occupy (e.g. a wallpaper's logo),
+    /// keyed by output name, or `"*"` for outputs without an entry
+    ///
+    /// Grid cells overlapping a region are skipped.
+    #[serde(default)]
+    pub keep_clear_regions: HashMap<String, Vec<KeepClearRegion>>,
+
+    /// What the icon grid is laid out in: the whole output, or its work area
+    #[serde(default)]
+    pub grid_anchor: GridAnchor,
+
+    /// Order icons fill the grid in: along rows, or down columns
+    #[serde(default)]
+    pub grid_flow: GridFlow,
+
+    /// Corner of the grid the first icon takes
+    ///
+    /// Rows and columns fill away from it, so with `"top_right"` rows fill
+    /// leftward. Surfaces are anchored to this corner of the output, and
+    /// stay pinned to it when the output changes size.
+    #[serde(default)]
+    pub grid_corner: Corner,
+
+    /// Put dragged icons in the grid cell nearest where they are dropped
+    ///
+    /// An icon dropped on a cell another icon was placed in moves on to the
+    /// next free cell. Off, icons stay exactly where they are dropped; they
+    /// still take the nearest free cell, which the keyboard and the layout
+    /// export go by.
+    #[serde(default = "default_true")]
+    pub snap_to_grid: bool,
+
+    /// Order icons without a placement are arranged in
+    ///
+    /// `"manual"` keeps them where they arrived or were moved to; the others
+    /// rearrange the grid whenever an icon is added, removed or renamed.
+    #[serde(default)]
+    pub sort_order: SortOrder,
+
+    /// With a `sort_order`, put folders ahead of files, each sorted on their own
+    #[serde(default)]
+    pub sort_folders_first: bool,
+
+    /// Most rows the grid has, even if more would fit
+    ///
+    /// With `grid_flow = "columns"` icons wrap into the next column after
+    /// this many rows.
+    #[serde(default)]
+    pub max_rows: Option<u32>,
+
+    /// Stacking of icons that don't fit in the grid into a pile
+    #[serde(default)]
+    pub overflow_pile: OverflowPileConfig,
+
+    /// Commands used to open files by extension, instead of xdg-open
+    ///
+    /// Keys are matched case-insensitively, with or without the leading dot.
+    /// `{file}` in the command is replaced by the file's path (which is
+    /// appended if there is no placeholder). The command is split on
+    /// whitespace and run directly, not through a shell.
+    #[serde(default)]
+    pub open_commands: HashMap<String, String>,
+
+    /// Swap the primary (select/drag) and secondary (context menu) buttons
+    ///
+    /// This is applied on top of whatever the compositor delivers. If
+    /// left-handed mode is already enabled in libinput or the desktop
+    /// settings, the buttons arrive swapped and the daemon follows them, so
+    /// leave this off; turning it on as well swaps them back.
+    #[serde(default)]
+    pub swap_buttons: bool,
+
+    /// What pressing a button other than the primary one on an icon does
+    ///
+    /// Keys are button names: `right`, `middle`, `back` (evdev codes 275 and
+    /// 278), `forward` (276 and 277), or `button<code>` for anything else.
+    /// Unmapped back/forward and higher buttons go to the icon's widget.
+    #[serde(default)]
+    pub button_actions: HashMap<String, ButtonAction>,
+
+    /// Holding the primary button this long (ms) on an icon without moving
+    /// it runs `long_press_action`; unset turns long presses off
+    ///
+    /// Moving the pointer far enough to start a drag, or releasing the
+    /// button first, cancels the long press.
+    #[serde(default)]
+    pub long_press_ms: Option<u64>,
+
+    /// What a long press of the primary button on an icon does
+    #[serde(default = "default_long_press_action")]
+    pub long_press_action: ButtonAction,
+
+    /// Two primary-button presses on the same icon this close together (ms)
+    /// are a double click, which opens the icon
+    #[serde(default = "default_double_click_ms")]
+    pub double_click_ms: u64,
+
+    /// Dragging on empty desktop draws a rectangle selecting the icons it
+    /// touches; a click there clears the selection
+    ///
+    /// Takes presses between icons, so turn it off if another program
+    /// should get them.
+    #[serde(default = "default_true")]
+    pub rubber_band_select: bool,
+
+    /// What opening a `.desktop` launcher does when its application is running
+    ///
+    /// Other files are always opened with their handler.
+    #[serde(default)]
+    pub launcher_click: LauncherClick,
+
+    /// Most input events queued while the daemon is busy rendering
+    ///
+    /// Past this, the oldest pointer motion is dropped; clicks, key presses
+    /// and the pointer entering or leaving an icon are always kept.
+    #[serde(default = "default_input_queue_limit")]
+    pub input_queue_limit: usize,
+
+    /// A Lua process exiting sooner than this after spawning counts as a crash loop
+    #[serde(default = "default_lua_min_uptime_ms")]
+    pub lua_min_uptime_ms: u64,
+
+    /// Number of shared Lua worker processes running the widgets of all icons
+    ///
+    /// Each worker runs many widgets, so a crash only restarts the icons on
+    /// that worker. 0, the default, gives every icon a process of its own.
+    ///
+    /// Only turn this on if every widget in `script_dirs` is pool-safe: it
+    /// keeps its state in its `Icon` table rather than in other globals,
+    /// doesn't read `CVH_ICON_SCRIPT`, and never blocks in a callback, as a
+    /// slow widget stalls every icon on its worker. The bundled `file.lua`
+    /// and `folder.lua` are pool-safe.
+    #[serde(default)]
+    pub lua_pool_size: usize,
+
+    /// Badge icons whose widget is not running: no IPC handler or widget
+    /// script was found in `script_dirs`, or the widget crashed
+    ///
+    /// Turn it off when running without widgets on purpose.
+    #[serde(default = "default_true")]
+    pub widget_status_badges: bool,
+
+    /// Show a built-in Trash icon for the XDG home trash
+    #[serde(default)]
+    pub show_trash: bool,
+
+    /// Pin the Trash icon to a screen corner instead of placing it in the grid
+    #[serde(default)]
+    pub trash_corner: Option<Corner>,
+
+    /// Show icons for mounted volumes with their free space and file count
+    #[serde(default)]
+    pub show_volumes: bool,
+
+    /// How often volumes are re-scanned and their free space re-read
+    #[serde(default = "default_volume_refresh_ms")]
+    pub volume_refresh_ms: u64,
+
+    /// Mark files with their git status (modified, untracked, ...) by an emblem
+    ///
+    /// Runs `git status` for a desktop inside a repository and for
+    /// repositories checked out on the desktop.
+    #[serde(default)]
+    pub vcs_emblems: bool,
+
+    /// How often emblems are re-read, besides after changes on the desktop
+    #[serde(default = "default_emblem_refresh_ms")]
+    pub emblem_refresh_ms: u64,
+
+    /// Directory to cache rendered icons in, for a fast first frame on startup
+    ///
+    /// Unset (the default) disables the cache.
+    #[serde(default)]
+    pub render_cache_dir: Option<PathBuf>,
+
+    /// Size limit of the render cache; least recently used icons go first
+    #[serde(default = "default_render_cache_max_mb")]
+    pub render_cache_max_mb: u64,
+
+    /// Show thumbnails of image files instead of the generic image glyph
+    #[serde(default = "default_true")]
+    pub thumbnails: bool,
+
+    /// Directory decoded thumbnails are kept in between runs
+    ///
+    /// Defaults to `cvh-icons/thumbnails` in the XDG cache directory; unset,
+    /// thumbnails are decoded again on every start.
+    #[serde(default = "default_thumbnail_cache_dir")]
+    pub thumbnail_cache_dir: Option<PathBuf>,
+
+    /// Show a faint hint in the middle of the screen while the desktop is empty
+    #[serde(default = "default_true")]
+    pub show_empty_hint: bool,
+
+    /// Text of the empty-desktop hint
+    #[serde(default = "default_empty_hint_text")]
+    pub empty_hint_text: String,
+
+    /// Show icons for FIFOs, sockets and device nodes on the desktop
+    ///
+    /// They get their own glyphs and can't be opened; set to false to hide
+    /// them instead.
+    #[serde(default = "default_true")]
+    pub show_special_files: bool,
+
+    /// Create the desktop directory at startup if it is missing
+    ///
+    /// Otherwise a missing directory shows no icons until it is created,
+    /// which the daemon notices by watching its parent.
+    #[serde(default)]
+    pub create_desktop_dir: bool,
+
+    /// Ask for confirmation before deleting files
+    ///
+    /// Deleting permanently and emptying the trash always ask while this is
+    /// on; moving to the trash, which can be undone, only asks once it
+    /// affects `confirm_delete_threshold` items or more.
+    #[serde(default = "default_true")]
+    pub confirm_delete: bool,
+
+    /// Number of items from which moving to the trash asks for confirmation
+    #[serde(default = "default_confirm_delete_threshold")]
+    pub confirm_delete_threshold: usize,
+
+    /// Icon appear/disappear animations
+    #[serde(default)]
+    pub animations: AnimationConfig,
+
+    /// Turn off all motion for accessibility, whatever `animations` says
+    ///
+    /// Icons appear and go at once, hover zoom and idle dimming snap to their
+    /// final state, and a `pulse` flash glows instead of shrinking.
+    #[serde(default)]
+    pub reduce_motion: bool,
+
+    /// Look of the attention flash started by the `flash` control command
+    #[serde(default)]
+    pub flash: FlashConfig,
+
+    /// Drop shadow beneath each icon, lifting it off the wallpaper
+    #[serde(default)]
+    pub icon_elevation: ElevationConfig,
+
+    /// Dim icons while the pointer has been idle for a while
+    #[serde(default)]
+    pub idle_dim: IdleDimConfig,
+
+    /// Opacity from 0 to 1 every surface is shown at, for icons that stay in
+    /// the background (1.0 = opaque)
+    ///
+    /// Applies on top of idle dimming.
+    #[serde(default = "default_surface_opacity")]
+    pub surface_opacity: f32,
+
+    /// Magnify the icon under the pointer by this factor, drawn above its
+    /// neighbours (1.0 = off)
+    ///
+    /// Meant for low-vision users. The zoom animates in and out when
+    /// animations are enabled.
+    #[serde(default = "default_hover_zoom")]
+    pub hover_zoom: f32,
+
+    /// Unmap icons while a window covers the desktop, to stop rendering them
+    ///
+    /// Needs `zwlr-foreign-toplevel-management-v1`. Only a focused maximized
+    /// or fullscreen window on the icons' output counts as covering it.
+    #[serde(default)]
+    pub hide_when_covered: bool,
+
+    /// Stop rendering and updating icons while the session is locked
+    ///
+    /// The lock state is read from logind; without it this does nothing.
+    /// Changes made meanwhile are caught up with on unlock.
+    #[serde(default = "default_true")]
+    pub suspend_when_locked: bool,
+
     /// Sandbox configuration
     #[serde(default)]
     pub sandbox: SandboxConfig,
@@ -40,7 +501,412 @@ pub struct Config {
     pub colors: Colors,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Pixels reserved along each output edge, shrinking the icon grid's usable area
+///
+/// Unlike layer-shell exclusive zones this is purely our own bookkeeping, so it
+/// also works for docks that don't reserve space themselves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservedEdges {
+    #[serde(default)]
+    pub top: u32,
+    #[serde(default)]
+    pub bottom: u32,
+    #[serde(default)]
+    pub left: u32,
+    #[serde(default)]
+    pub right: u32,
+}
+
+/// Rectangle of an output kept free of icons, in output-local coordinates
+///
+/// While the grid is anchored to the work area, coordinates are relative to
+/// the work area instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeepClearRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl KeepClearRegion {
+    /// Whether the region and the `width` x `height` rectangle at (`x`, `y`) share any area
+    pub fn overlaps(&self, x: i32, y: i32, width: u32, height: u32) -> bool {
+        x < self.x + self.width as i32
+            && self.x < x + width as i32
+            && y < self.y + self.height as i32
+            && self.y < y + height as i32
+    }
+}
+
+/// Rectangle of an output available for icons, in output-local coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsableArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ReservedEdges {
+    /// Usable area of an output of the given size after removing reserved edges
+    pub fn usable_area(&self, width: u32, height: u32) -> UsableArea {
+        let left = self.left.min(width);
+        let top = self.top.min(height);
+        UsableArea {
+            x: left as i32,
+            y: top as i32,
+            width: width.saturating_sub(left).saturating_sub(self.right),
+            height: height.saturating_sub(top).saturating_sub(self.bottom),
+        }
+    }
+}
+
+/// Where a label's text sits within the band under the glyph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// Order icons without a placement fill the grid in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridFlow {
+    /// Left to right, then the next row down
+    #[default]
+    Rows,
+    /// Top to bottom, then the next column to the right (the classic desktop)
+    Columns,
+}
+
+/// What the icon grid is anchored to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridAnchor {
+    /// The full output, minus `reserved_edges`
+    #[default]
+    Output,
+    /// The output minus the exclusive zones of panels and docks
+    ///
+    /// layer-shell has no request for the work area, so the daemon maps an
+    /// invisible surface stretched to all four edges that does not reserve
+    /// space itself; the compositor sizes it to the work area. Icons then
+    /// follow panels without any `reserved_edges`. Until the compositor has
+    /// sized that surface (or if it never does), `reserved_edges` apply.
+    WorkArea,
+}
+
+/// Opening a launcher whose application already has a window
+///
+/// Windows are found by the launcher's app ID (or `StartupWMClass`) and
+/// raised through `zwlr-foreign-toplevel-management-v1`. Compositors
+/// without it (e.g. GNOME) always launch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LauncherClick {
+    /// Start a new instance every time
+    #[default]
+    AlwaysLaunch,
+    /// Activate a running window of the application, launching if there is none
+    FocusOrLaunch,
+}
+
+/// Action bound to a mouse button in `button_actions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    /// Open the icon, as a double click does
+    Open,
+    /// Show the icon's context menu
+    ContextMenu,
+    /// Send the click to the icon's widget as a named `Click` event
+    Widget,
+    /// Do nothing
+    Ignore,
+}
+
+/// Handling of files that appear and disappear within one debounce window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransientFiles {
+    /// Drop the create/remove pair so no icon is ever created
+    #[default]
+    Hide,
+    /// Deliver both events, briefly showing the icon
+    Show,
+}
+
+/// Handling of `Modify(Any)` events, which some platforms send instead of
+/// saying whether data, metadata or the name changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbiguousModify {
+    /// Re-stat the file and only recreate the icon if its type or metadata
+    /// changed; otherwise just redraw it
+    #[default]
+    Restat,
+    /// Always recreate the icon, respawning its widget
+    Recreate,
+}
+
+/// Handling of filesystem events while the daemon is paused
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PausedFsEvents {
+    /// Hold events and apply them on resume, before the rescan
+    #[default]
+    Buffer,
+    /// Discard events and rely on the rescan alone, for operations touching
+    /// so many files that holding their events is not worth it
+    Drop,
+}
+
+/// Label of icons whose name another icon has too
+///
+/// Icons with a name of their own are always labelled with just the name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateLabels {
+    /// Just the name, as for any other icon
+    Plain,
+    /// The name and its directory's name: `notes.txt (Desktop)`
+    #[default]
+    ParentName,
+    /// The name and its directory's path, home as `~`: `notes.txt (~/Desktop)`
+    ParentPath,
+}
+
+/// Rounded background drawn behind just the label text
+///
+/// The pill is sized to the measured text, so short names get short pills.
+/// It replaces the background `colors.label_bg` otherwise draws across the
+/// whole label band. Only labels the daemon draws itself get one (symbolic
+/// icons, and labels expanded on hover); widget scripts draw their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LabelPillConfig {
+    /// Draw the pill; off by default
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Pill color (hex)
+    #[serde(default = "default_label_pill_color")]
+    pub color: String,
+
+    /// Space between the text and the pill's left and right ends, in pixels
+    #[serde(default = "default_label_pill_padding")]
+    pub padding: f32,
+
+    /// Corner radius, in pixels; capped at half the pill's height
+    #[serde(default = "default_label_pill_radius")]
+    pub radius: f32,
+
+    /// How labels wrapped over several lines get their pills
+    #[serde(default)]
+    pub lines: PillLines,
+}
+
+/// Pills of a label wrapped over several lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PillLines {
+    /// A pill for each line, as wide as that line
+    #[default]
+    PerLine,
+    /// One pill around all lines, as wide as the longest
+    Enclosing,
+}
+
+/// Highlight drawn on an icon while it flashes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashStyle {
+    /// A colored glow behind the glyph
+    #[default]
+    Glow,
+    /// The whole icon briefly shrinks and grows back
+    Pulse,
+}
+
+/// Handling of icons whose files vanish along with their filesystem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnavailableFiles {
+    /// Remove the icons, as for deleted files
+    #[default]
+    Remove,
+    /// Keep the icons dimmed until the filesystem comes back
+    Stale,
+}
+
+/// Output passed to the compositor when an icon surface is created
+///
+/// Getting this wrong is the usual cause of icons showing up on the wrong
+/// monitor, and compositors differ in what they expect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SurfaceOutputBinding {
+    /// Bind every surface to the primary output. Right for compositors that
+    /// place layer surfaces on the output they are given, which is most of
+    /// them (Sway, Hyprland, river, niri, KWin, Wayfire).
+    #[default]
+    Explicit,
+    /// Pass no output and let the compositor choose, usually the focused
+    /// one. For compositors that misplace surfaces bound to an explicit
+    /// output, as some do after an output is unplugged and plugged back, and for
+    /// single-output kiosk setups. On several outputs icons may follow focus.
+    None,
+    /// Bind each surface to the output its position falls on, counting
+    /// positions from the primary output's top-left corner in the
+    /// compositor's global layout. Lets placements past the primary output's
+    /// edge land on the neighbouring output; needs compositors that report
+    /// logical output positions (xdg-output), and falls back to the primary
+    /// output elsewhere.
+    PerPosition,
+}
+
+/// Corner of the usable area that a special icon, or the grid, can be pinned to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Whether the corner is on the right edge
+    pub fn is_right(self) -> bool {
+        matches!(self, Corner::TopRight | Corner::BottomRight)
+    }
+
+    /// Whether the corner is on the bottom edge
+    pub fn is_bottom(self) -> bool {
+        matches!(self, Corner::BottomLeft | Corner::BottomRight)
+    }
+}
+
+/// Order icons are arranged in on the grid
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Where they arrived or were moved to
+    #[default]
+    Manual,
+    /// By name, A to Z
+    Name,
+    NameReverse,
+    /// By kind of file, then by extension
+    Type,
+    TypeReverse,
+    /// By size, smallest first
+    Size,
+    SizeReverse,
+    /// By modification time, oldest first
+    Modified,
+    ModifiedReverse,
+}
+
+impl SortOrder {
+    /// Whether this is a reverse variant
+    pub fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            SortOrder::NameReverse
+                | SortOrder::TypeReverse
+                | SortOrder::SizeReverse
+                | SortOrder::ModifiedReverse
+        )
+    }
+}
+
+/// How the render cadence is driven
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FramePacing {
+    /// Render when the compositor signals it is ready for a new frame,
+    /// falling back to a timeout if callbacks stop arriving
+    #[default]
+    FrameCallback,
+    /// Render on a fixed `frame_interval_ms` timer
+    Timer,
+}
+
+/// Drop shadow drawn beneath the whole icon, label included
+///
+/// Sizes are in logical pixels and scale with the device pixel ratio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElevationConfig {
+    /// Draw the shadow; off by default for flat themes
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Blur radius of the shadow's edge
+    #[serde(default = "default_elevation_blur")]
+    pub blur: f32,
+
+    /// Horizontal shadow offset
+    #[serde(default)]
+    pub offset_x: f32,
+
+    /// Vertical shadow offset
+    #[serde(default = "default_elevation_offset_y")]
+    pub offset_y: f32,
+
+    /// Shadow opacity from 0 to 1
+    #[serde(default = "default_elevation_opacity")]
+    pub opacity: f32,
+}
+
+/// Pile of the icons that don't fit in the grid
+///
+/// Without it, icons past the last cell carry on below the usable area and
+/// off the screen. With it, they are stacked with a small offset in the
+/// grid's last cell, together with the icon already there. Clicking the pile
+/// fans it out over the cells before it; clicking any other icon collapses
+/// it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverflowPileConfig {
+    /// Pile overflowing icons; off by default
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Offset between stacked icons, in pixels
+    #[serde(default = "default_pile_offset")]
+    pub offset: u32,
+
+    /// Fewest icons, counting the one in the last cell, worth piling
+    ///
+    /// With fewer, the overflowing icons are laid out past the grid as usual.
+    #[serde(default = "default_pile_threshold")]
+    pub threshold: u32,
+}
+
+/// Dimming of all icons after a period without input
+///
+/// Purely cosmetic: any pointer activity brightens them again. The change
+/// fades in and out when animations are enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdleDimConfig {
+    /// Dim idle icons; off by default
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Opacity from 0 to 1 that idle icons are dimmed to
+    #[serde(default = "default_idle_dim_opacity")]
+    pub opacity: f32,
+
+    /// Time without input before icons dim, in ms
+    #[serde(default = "default_idle_dim_delay_ms")]
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SandboxConfig {
     /// Enable sandboxing
     #[serde(default = "default_true")]
@@ -60,6 +926,40 @@ pub struct SandboxConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationConfig {
+    /// Fade/scale icons in when they appear and fade them out when removed
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Duration of the appear animation in ms
+    #[serde(default = "default_appear_ms")]
+    pub appear_ms: u64,
+
+    /// Duration of the disappear animation in ms
+    #[serde(default = "default_disappear_ms")]
+    pub disappear_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashConfig {
+    /// How the flashing icon is highlighted
+    #[serde(default)]
+    pub style: FlashStyle,
+
+    /// Color of the glow (`#rrggbb` or `#rrggbbaa`)
+    #[serde(default = "default_flash_color")]
+    pub color: String,
+
+    /// How long the flash lasts, in ms
+    #[serde(default = "default_flash_duration_ms")]
+    pub duration_ms: u64,
+
+    /// Number of times the highlight swells and fades within the flash
+    #[serde(default = "default_flash_pulses")]
+    pub pulses: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Colors {
     #[serde(default = "default_label_fg")]
     pub label_fg: String,
@@ -70,8 +970,17 @@ pub struct Colors {
     #[serde(default = "default_label_shadow")]
     pub label_shadow: String,
 
+    /// Fill behind selected icons
     #[serde(default = "default_selection")]
     pub selection: String,
+
+    /// Highlight over the icon under the pointer, on top of the selection fill
+    #[serde(default = "default_hover")]
+    pub hover: String,
+
+    /// Outline around the icon with the keyboard focus
+    #[serde(default = "default_focus_ring")]
+    pub focus_ring: String,
 }
 
 // Default functions
@@ -79,6 +988,34 @@ fn default_icon_size() -> u32 { 64 }
 fn default_grid_spacing() -> u32 { 20 }
 fn default_font_size() -> f32 { 12.0 }
 fn default_label_width() -> usize { 12 }
+fn default_label_lines() -> u32 { 2 }
+fn default_frame_interval_ms() -> u64 { 16 }
+fn default_update_interval_ms() -> u64 { 500 }
+fn default_long_press_action() -> ButtonAction { ButtonAction::ContextMenu }
+fn default_lua_min_uptime_ms() -> u64 { 2000 }
+fn default_input_queue_limit() -> usize { 1024 }
+fn default_volume_refresh_ms() -> u64 { 30_000 }
+fn default_emblem_refresh_ms() -> u64 { 10_000 }
+fn default_update_error_threshold() -> u32 { 3 }
+fn default_render_cache_max_mb() -> u64 { 64 }
+fn default_fs_coalesce_ms() -> u64 { 100 }
+fn default_double_click_ms() -> u64 { 400 }
+fn default_tooltip_delay_ms() -> u64 { 700 }
+fn default_appear_ms() -> u64 { 180 }
+fn default_disappear_ms() -> u64 { 180 }
+fn default_flash_color() -> String { "#ffd54fc0".to_string() }
+fn default_flash_duration_ms() -> u64 { 3000 }
+fn default_flash_pulses() -> u32 { 3 }
+fn default_elevation_blur() -> f32 { 3.0 }
+fn default_elevation_offset_y() -> f32 { 2.0 }
+fn default_elevation_opacity() -> f32 { 0.35 }
+fn default_confirm_delete_threshold() -> usize { 5 }
+fn default_pile_offset() -> u32 { 6 }
+fn default_pile_threshold() -> u32 { 2 }
+fn default_idle_dim_opacity() -> f32 { 0.5 }
+fn default_idle_dim_delay_ms() -> u64 { 30_000 }
+fn default_surface_opacity() -> f32 { 1.0 }
+fn default_hover_zoom() -> f32 { 1.0 }
 fn default_true() -> bool { true }
 
 fn default_script_dirs() -> Vec<PathBuf> {
@@ -98,20 +1035,109 @@ fn default_icon_theme() -> String {
     "Adwaita".to_string()
 }
 
+fn default_thumbnail_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("cvh-icons/thumbnails"))
+}
+
+fn default_empty_hint_text() -> String { "Drop files here".to_string() }
+
 fn default_label_fg() -> String { "#ffffff".to_string() }
 fn default_label_bg() -> String { "#00000080".to_string() }
+fn default_label_pill_color() -> String { "#000000a0".to_string() }
+fn default_label_pill_padding() -> f32 { 6.0 }
+fn default_label_pill_radius() -> f32 { 8.0 }
 fn default_label_shadow() -> String { "#000000".to_string() }
 fn default_selection() -> String { "#88c0d040".to_string() }
+fn default_hover() -> String { "#ffffff26".to_string() }
+fn default_focus_ring() -> String { "#88c0d0".to_string() }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             icon_size: default_icon_size(),
+            follow_desktop_icon_size: false,
             grid_spacing: default_grid_spacing(),
             font_size: default_font_size(),
             label_width: default_label_width(),
+            label_lines: default_label_lines(),
+            label_align: LabelAlign::default(),
+            expand_labels_on_hover: false,
+            tooltips: true,
+            tooltip_delay_ms: default_tooltip_delay_ms(),
+            tooltip_details: false,
+            duplicate_labels: DuplicateLabels::default(),
+            label_pill: LabelPillConfig::default(),
             script_dirs: default_script_dirs(),
             icon_theme: default_icon_theme(),
+            icon_theme_dir: None,
+            ignore_patterns: Vec::new(),
+            allow_patterns: Vec::new(),
+            show_hidden: false,
+            frame_pacing: FramePacing::default(),
+            frame_interval_ms: default_frame_interval_ms(),
+            update_interval_ms: default_update_interval_ms(),
+            dispatch_timeout_ms: 0,
+            fs_coalesce_ms: default_fs_coalesce_ms(),
+            transient_files: TransientFiles::default(),
+            trace_fs_events: false,
+            debug_grid: false,
+            unavailable_files: UnavailableFiles::default(),
+            update_error_threshold: default_update_error_threshold(),
+            ambiguous_modify: AmbiguousModify::default(),
+            paused_fs_events: PausedFsEvents::default(),
+            symbolic_icons: false,
+            symbolic_strict: false,
+            primary_output: None,
+            surface_output_binding: SurfaceOutputBinding::default(),
+            span_outputs: false,
+            reserved_edges: ReservedEdges::default(),
+            output_reserved_edges: HashMap::new(),
+            keep_clear_regions: HashMap::new(),
+            grid_anchor: GridAnchor::default(),
+            grid_flow: GridFlow::default(),
+            grid_corner: Corner::default(),
+            snap_to_grid: true,
+            sort_order: SortOrder::default(),
+            sort_folders_first: false,
+            overflow_pile: OverflowPileConfig::default(),
+            max_rows: None,
+            open_commands: HashMap::new(),
+            swap_buttons: false,
+            button_actions: HashMap::new(),
+            long_press_ms: None,
+            double_click_ms: default_double_click_ms(),
+            rubber_band_select: true,
+            long_press_action: default_long_press_action(),
+            launcher_click: LauncherClick::default(),
+            input_queue_limit: default_input_queue_limit(),
+            lua_min_uptime_ms: default_lua_min_uptime_ms(),
+            lua_pool_size: 0,
+            widget_status_badges: true,
+            show_trash: false,
+            trash_corner: None,
+            show_volumes: false,
+            volume_refresh_ms: default_volume_refresh_ms(),
+            vcs_emblems: false,
+            emblem_refresh_ms: default_emblem_refresh_ms(),
+            render_cache_dir: None,
+            render_cache_max_mb: default_render_cache_max_mb(),
+            thumbnails: true,
+            thumbnail_cache_dir: default_thumbnail_cache_dir(),
+            show_empty_hint: true,
+            empty_hint_text: default_empty_hint_text(),
+            show_special_files: true,
+            create_desktop_dir: false,
+            confirm_delete: true,
+            confirm_delete_threshold: default_confirm_delete_threshold(),
+            animations: AnimationConfig::default(),
+            reduce_motion: false,
+            flash: FlashConfig::default(),
+            icon_elevation: ElevationConfig::default(),
+            idle_dim: IdleDimConfig::default(),
+            surface_opacity: default_surface_opacity(),
+            hover_zoom: default_hover_zoom(),
+            hide_when_covered: false,
+            suspend_when_locked: true,
             sandbox: SandboxConfig::default(),
             colors: Colors::default(),
         }
@@ -129,38 +1155,1184 @@ impl Default for SandboxConfig {
     }
 }
 
-impl Default for Colors {
+impl Default for ElevationConfig {
     fn default() -> Self {
         Self {
-            label_fg: default_label_fg(),
-            label_bg: default_label_bg(),
-            label_shadow: default_label_shadow(),
-            selection: default_selection(),
+            enabled: false,
+            blur: default_elevation_blur(),
+            offset_x: 0.0,
+            offset_y: default_elevation_offset_y(),
+            opacity: default_elevation_opacity(),
         }
     }
 }
 
-impl Config {
-    /// Load configuration from file or defaults
-    pub fn load(path: Option<&Path>) -> Result<Self> {
+impl Default for LabelPillConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: default_label_pill_color(),
+            padding: default_label_pill_padding(),
+            radius: default_label_pill_radius(),
+            lines: PillLines::default(),
+        }
+    }
+}
+
+impl Default for OverflowPileConfig {
+    fn default() -> Self {
+        Self { enabled: false, offset: default_pile_offset(), threshold: default_pile_threshold() }
+    }
+}
+
+impl Default for IdleDimConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            opacity: default_idle_dim_opacity(),
+            delay_ms: default_idle_dim_delay_ms(),
+        }
+    }
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            appear_ms: default_appear_ms(),
+            disappear_ms: default_disappear_ms(),
+        }
+    }
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        Self {
+            style: FlashStyle::default(),
+            color: default_flash_color(),
+            duration_ms: default_flash_duration_ms(),
+            pulses: default_flash_pulses(),
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            label_fg: default_label_fg(),
+            label_bg: default_label_bg(),
+            label_shadow: default_label_shadow(),
+            selection: default_selection(),
+            hover: default_hover(),
+            focus_ring: default_focus_ring(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from file or defaults
+    pub fn load(path: Option<&Path>) -> IconsResult<Self> {
         // Try explicit path first
         if let Some(p) = path {
             if p.exists() {
-                let content = std::fs::read_to_string(p)?;
-                return Ok(toml::from_str(&content)?);
+                return Self::load_file(p).map_err(IconsError::Config);
             }
         }
 
         // Try XDG config
-        if let Some(config_dir) = dirs::config_dir() {
-            let config_file = config_dir.join("cvh-icons/config.toml");
-            if config_file.exists() {
-                let content = std::fs::read_to_string(&config_file)?;
-                return Ok(toml::from_str(&content)?);
-            }
+        if let Some(config_file) = Self::default_file().filter(|p| p.exists()) {
+            return Self::load_file(&config_file).map_err(IconsError::Config);
         }
 
         // Use defaults
         Ok(Self::default())
     }
+
+    /// Configuration file in the XDG config directory
+    pub fn default_file() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("cvh-icons/config.toml"))
+    }
+
+    /// Reserved edges for an output, falling back to the global setting
+    pub fn reserved_edges_for(&self, output: Option<&str>) -> ReservedEdges {
+        output
+            .and_then(|name| self.output_reserved_edges.get(name))
+            .copied()
+            .unwrap_or(self.reserved_edges)
+    }
+
+    /// Keep-clear regions of an output, falling back to the `"*"` entry
+    pub fn keep_clear_regions_for(&self, output: Option<&str>) -> &[KeepClearRegion] {
+        output
+            .and_then(|name| self.keep_clear_regions.get(name))
+            .or_else(|| self.keep_clear_regions.get("*"))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// `open_commands` template for a file's extension, if any
+    pub fn open_command_template(&self, file: &Path) -> Option<&str> {
+        let ext = file.extension()?.to_str()?;
+        self.open_commands
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            .map(|(_, template)| template.as_str())
+    }
+
+    /// Command that opens `file`: its extension's open command, else xdg-open
+    pub fn open_command(&self, file: &Path) -> Command {
+        let args = self
+            .open_command_template(file)
+            .map(|template| expand_open_command(template, file))
+            .filter(|args| !args.is_empty());
+
+        match args {
+            Some(args) => {
+                let mut command = Command::new(&args[0]);
+                command.args(&args[1..]);
+                command
+            }
+            None => {
+                let mut command = Command::new("xdg-open");
+                command.arg(file);
+                command
+            }
+        }
+    }
+
+    /// Drop open commands whose program cannot be found, so those files
+    /// fall back to xdg-open
+    fn drop_missing_open_commands(&mut self) {
+        self.open_commands.retain(|ext, template| {
+            match template.split_whitespace().next() {
+                Some(program) if program_exists(program) => true,
+                Some(program) => {
+                    warn!("Open command for .{} not found: {} (using xdg-open)", ext, program);
+                    false
+                }
+                None => {
+                    warn!("Empty open command for .{} (using xdg-open)", ext);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Whether anything should animate: `animations` is enabled and `reduce_motion` is off
+    pub fn animates(&self) -> bool {
+        self.animations.enabled && !self.reduce_motion
+    }
+
+    /// Build the filename filter for desktop entries
+    pub fn name_filter(&self) -> NameFilter {
+        NameFilter::new(&self.ignore_patterns, &self.allow_patterns).with_hidden(self.show_hidden)
+    }
+
+    /// Read and parse a single config file
+    fn load_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .map_err(DaemonError::Config)?;
+        let mut config: Config = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .map_err(DaemonError::Config)?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid configuration in {}", path.display()))
+            .map_err(DaemonError::Config)?;
+        config.drop_missing_open_commands();
+        Ok(config)
+    }
+
+    /// Reject settings the daemon cannot work with
+    fn validate(&self) -> Result<()> {
+        if !(MIN_ICON_SIZE..=MAX_ICON_SIZE).contains(&self.icon_size) {
+            anyhow::bail!(
+                "icon_size {} is out of range ({} to {})",
+                self.icon_size,
+                MIN_ICON_SIZE,
+                MAX_ICON_SIZE
+            );
+        }
+        if !(1.0..=MAX_HOVER_ZOOM).contains(&self.hover_zoom) {
+            anyhow::bail!(
+                "hover_zoom {} is out of range (1 to {})",
+                self.hover_zoom,
+                MAX_HOVER_ZOOM
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Arguments of an open command template with `{file}` substituted
+///
+/// The path is appended as the last argument if there is no placeholder.
+pub fn expand_open_command(template: &str, file: &Path) -> Vec<OsString> {
+    let mut substituted = false;
+    let mut args: Vec<OsString> = template
+        .split_whitespace()
+        .map(|word| {
+            let mut arg = OsString::new();
+            for (i, part) in word.split("{file}").enumerate() {
+                if i > 0 {
+                    arg.push(file.as_os_str());
+                    substituted = true;
+                }
+                arg.push(part);
+            }
+            arg
+        })
+        .collect();
+
+    if !substituted && !args.is_empty() {
+        args.push(file.as_os_str().to_owned());
+    }
+    args
+}
+
+/// Whether a program name resolves to a file, either as a path or on `PATH`
+fn program_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Filename filter for desktop entries
+///
+/// Hidden files are not shown unless enabled with `with_hidden`. Otherwise a
+/// name matching any ignore pattern is hidden, even if it also matches the
+/// allowlist; when the allowlist is non-empty, only names matching it are shown.
+/// Patterns with a `/` in them are matched against the full path, and only
+/// by `is_path_visible`.
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    ignore: Vec<Pattern>,
+    allow: Vec<Pattern>,
+    show_hidden: bool,
+}
+
+impl NameFilter {
+    /// Compile ignore and allow patterns, skipping invalid ones
+    pub fn new(ignore: &[String], allow: &[String]) -> Self {
+        Self {
+            ignore: compile_patterns(ignore),
+            allow: compile_patterns(allow),
+            show_hidden: false,
+        }
+    }
+
+    /// Set whether names starting with a dot may be shown
+    pub fn with_hidden(mut self, show_hidden: bool) -> Self {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    /// Check whether a file name should get a desktop icon
+    pub fn is_visible(&self, name: &str) -> bool {
+        self.visible(name, None)
+    }
+
+    /// Check whether a path should get a desktop icon
+    ///
+    /// Names that aren't valid UTF-8 are matched lossily, so they are
+    /// filtered like any other rather than always hidden.
+    pub fn is_path_visible(&self, path: &Path) -> bool {
+        path.file_name()
+            .map(|n| self.visible(&n.to_string_lossy(), Some(path)))
+            .unwrap_or(false)
+    }
+
+    fn visible(&self, name: &str, path: Option<&Path>) -> bool {
+        if name.starts_with('.') && !self.show_hidden {
+            return false;
+        }
+
+        let matches = |p: &Pattern| match path {
+            _ if !p.as_str().contains('/') => p.matches(name),
+            Some(path) => p.matches_path(path),
+            None => false,
+        };
+        if self.ignore.iter().any(matches) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(matches)
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Vec<Pattern> {
+    patterns
+        .iter()
+        .filter_map(|p| match Pattern::new(p) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                warn!("Ignoring invalid filename pattern '{}': {}", p, e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(ignore: &[&str], allow: &[&str]) -> NameFilter {
+        let ignore: Vec<String> = ignore.iter().map(|s| s.to_string()).collect();
+        let allow: Vec<String> = allow.iter().map(|s| s.to_string()).collect();
+        NameFilter::new(&ignore, &allow)
+    }
+
+    #[test]
+    fn test_default_filter_hides_only_dotfiles() {
+        let f = NameFilter::default();
+        assert!(f.is_visible("notes.txt"));
+        assert!(f.is_visible("~lock.docx"));
+        assert!(!f.is_visible(".hidden"));
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        let f = filter(&["*.tmp", "~*", "Thumbs.db"], &[]);
+        assert!(!f.is_visible("build.tmp"));
+        assert!(!f.is_visible("~$report.docx"));
+        assert!(!f.is_visible("Thumbs.db"));
+        assert!(f.is_visible("report.docx"));
+        assert!(f.is_visible("thumbs.db.bak"));
+    }
+
+    #[test]
+    fn test_allowlist_restricts_names() {
+        let f = filter(&[], &["*.pdf", "Projects"]);
+        assert!(f.is_visible("paper.pdf"));
+        assert!(f.is_visible("Projects"));
+        assert!(!f.is_visible("notes.txt"));
+    }
+
+    #[test]
+    fn test_ignore_takes_precedence_over_allow() {
+        let f = filter(&["draft-*"], &["*.pdf"]);
+        assert!(f.is_visible("final.pdf"));
+        assert!(!f.is_visible("draft-1.pdf"));
+    }
+
+    #[test]
+    fn test_hidden_files_not_revealed_by_allowlist() {
+        let f = filter(&[], &["*"]);
+        assert!(!f.is_visible(".bashrc"));
+    }
+
+    #[test]
+    fn test_hidden_files_shown_when_enabled() {
+        let f = filter(&["*.swp"], &[]).with_hidden(true);
+        assert!(f.is_visible(".bashrc"));
+        assert!(!f.is_visible(".notes.txt.swp"), "Patterns still apply to dotfiles");
+    }
+
+    #[test]
+    fn test_non_utf8_names_are_filtered_lossily() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        let path = Path::new("/home/user/Desktop").join(name);
+        assert!(NameFilter::default().is_path_visible(&path));
+        assert!(!filter(&["*.txt"], &[]).is_path_visible(&path));
+
+        let hidden = Path::new("/home/user/Desktop").join(std::ffi::OsStr::from_bytes(b".caf\xe9"));
+        assert!(!NameFilter::default().is_path_visible(&hidden));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_skipped() {
+        let f = filter(&["[", "*.tmp"], &[]);
+        assert!(!f.is_visible("a.tmp"));
+        assert!(f.is_visible("["));
+    }
+
+    #[test]
+    fn test_patterns_with_slash_match_full_path() {
+        let f = filter(&["/home/*/Desktop/build-*"], &[]);
+        assert!(!f.is_path_visible(Path::new("/home/ana/Desktop/build-1")));
+        assert!(f.is_path_visible(Path::new("/home/ana/Desktop/notes.txt")));
+        assert!(f.is_path_visible(Path::new("/srv/Desktop/build-1")));
+        // Names alone never match a path pattern
+        assert!(f.is_visible("build-1"));
+    }
+
+    #[test]
+    fn test_patterns_parse_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            ignore_patterns = ["*.tmp"]
+            allow_patterns = ["*.txt", "*.tmp"]
+            "#,
+        )
+        .unwrap();
+        let f = config.name_filter();
+        assert!(f.is_visible("a.txt"));
+        assert!(!f.is_visible("a.tmp"));
+        assert!(!f.is_visible("a.png"));
+    }
+
+    #[test]
+    fn test_frame_pacing_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            frame_pacing = "timer"
+            frame_interval_ms = 33
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.frame_pacing, FramePacing::Timer);
+        assert_eq!(config.frame_interval_ms, 33);
+        assert_eq!(config.update_interval_ms, 500);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.frame_pacing, FramePacing::FrameCallback);
+    }
+
+    #[test]
+    fn test_open_command_lookup_ignores_case_and_dot() {
+        let mut config = Config::default();
+        config.open_commands.insert("md".to_string(), "nvim-qt".to_string());
+        config.open_commands.insert(".Blend".to_string(), "blender {file}".to_string());
+
+        assert_eq!(config.open_command_template(Path::new("/d/README.MD")), Some("nvim-qt"));
+        assert_eq!(config.open_command_template(Path::new("/d/scene.blend")), Some("blender {file}"));
+        assert_eq!(config.open_command_template(Path::new("/d/notes.txt")), None);
+        assert_eq!(config.open_command_template(Path::new("/d/Makefile")), None);
+
+        let command = config.open_command(Path::new("/d/notes.txt"));
+        assert_eq!(command.get_program(), "xdg-open");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["/d/notes.txt"]);
+    }
+
+    #[test]
+    fn test_expand_open_command_placeholder() {
+        let file = Path::new("/d/My Notes.md");
+        assert_eq!(
+            expand_open_command("code --goto {file}:1", file),
+            ["code", "--goto", "/d/My Notes.md:1"]
+        );
+        assert_eq!(expand_open_command("gedit --new-window", file), ["gedit", "--new-window", "/d/My Notes.md"]);
+        assert!(expand_open_command("   ", file).is_empty());
+
+        let mut config = Config::default();
+        config.open_commands.insert("md".to_string(), "typora {file}".to_string());
+        let command = config.open_command(file);
+        assert_eq!(command.get_program(), "typora");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["/d/My Notes.md"]);
+    }
+
+    #[test]
+    fn test_missing_open_commands_are_dropped_at_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [open_commands]
+            txt = "sh -c true {file}"
+            md = "cvh-no-such-editor {file}"
+            log = ""
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::load(Some(&path)).unwrap();
+        assert!(config.open_commands.contains_key("txt"));
+        assert!(!config.open_commands.contains_key("md"));
+        assert!(!config.open_commands.contains_key("log"));
+    }
+
+    #[test]
+    fn test_out_of_range_icon_size_is_rejected_at_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+
+        for size in [0, 100_000] {
+            std::fs::write(&path, format!("icon_size = {}", size)).unwrap();
+            let error = Config::load(Some(&path)).unwrap_err();
+            assert!(format!("{:#}", error).contains("out of range"), "{:#}", error);
+        }
+
+        std::fs::write(&path, "icon_size = 1024").unwrap();
+        assert_eq!(Config::load(Some(&path)).unwrap().icon_size, 1024);
+    }
+
+    #[test]
+    fn test_hover_zoom_config() {
+        assert_eq!(Config::default().hover_zoom, 1.0);
+
+        let config: Config = toml::from_str("hover_zoom = 2.5").unwrap();
+        assert_eq!(config.hover_zoom, 2.5);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        for zoom in ["0.5", "10.0", "nan"] {
+            std::fs::write(&path, format!("hover_zoom = {}", zoom)).unwrap();
+            let error = Config::load(Some(&path)).unwrap_err();
+            assert!(format!("{:#}", error).contains("out of range"), "{:#}", error);
+        }
+    }
+
+    #[test]
+    fn test_clamp_icon_size() {
+        assert_eq!(clamp_icon_size(0), MIN_ICON_SIZE);
+        assert_eq!(clamp_icon_size(64), 64);
+        assert_eq!(clamp_icon_size(u32::MAX), MAX_ICON_SIZE);
+    }
+
+    #[test]
+    fn test_reserved_edges_usable_area() {
+        let edges = ReservedEdges { top: 30, bottom: 64, left: 10, right: 0 };
+        let area = edges.usable_area(1920, 1080);
+        assert_eq!(area, UsableArea { x: 10, y: 30, width: 1910, height: 986 });
+    }
+
+    #[test]
+    fn test_reserved_edges_larger_than_output() {
+        let edges = ReservedEdges { top: 0, bottom: 0, left: 2000, right: 50 };
+        let area = edges.usable_area(1920, 1080);
+        assert_eq!(area.x, 1920);
+        assert_eq!(area.width, 0);
+    }
+
+    #[test]
+    fn test_keep_clear_regions_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[keep_clear_regions."*"]]
+            x = 760
+            y = 340
+            width = 400
+            height = 400
+
+            [[keep_clear_regions.DP-2]]
+            x = 0
+            y = 0
+            width = 100
+            height = 100
+            "#,
+        )
+        .unwrap();
+
+        let logo = config.keep_clear_regions_for(Some("HDMI-A-1"));
+        assert_eq!(logo, config.keep_clear_regions_for(None));
+        assert_eq!(logo.len(), 1);
+        assert!(logo[0].overlaps(700, 300, 80, 80));
+        assert!(!logo[0].overlaps(680, 300, 80, 80), "Touching edges don't overlap");
+        assert_eq!(config.keep_clear_regions_for(Some("DP-2"))[0].width, 100);
+        assert!(Config::default().keep_clear_regions_for(None).is_empty());
+    }
+
+    #[test]
+    fn test_reserved_edges_per_output() {
+        let config: Config = toml::from_str(
+            r#"
+            [reserved_edges]
+            bottom = 48
+
+            [output_reserved_edges.DP-2]
+            left = 72
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.reserved_edges_for(None).bottom, 48);
+        assert_eq!(config.reserved_edges_for(Some("HDMI-A-1")).bottom, 48);
+
+        let dp2 = config.reserved_edges_for(Some("DP-2"));
+        assert_eq!(dp2.left, 72);
+        assert_eq!(dp2.bottom, 0, "Per-output entries replace the global setting");
+    }
+
+    #[test]
+    fn test_primary_output_config() {
+        assert_eq!(Config::default().primary_output, None);
+
+        let config: Config = toml::from_str(r#"primary_output = "DP-1""#).unwrap();
+        assert_eq!(config.primary_output.as_deref(), Some("DP-1"));
+    }
+
+    #[test]
+    fn test_surface_output_binding_config() {
+        assert_eq!(Config::default().surface_output_binding, SurfaceOutputBinding::Explicit);
+
+        let config: Config = toml::from_str(r#"surface_output_binding = "none""#).unwrap();
+        assert_eq!(config.surface_output_binding, SurfaceOutputBinding::None);
+        let config: Config = toml::from_str(r#"surface_output_binding = "per_position""#).unwrap();
+        assert_eq!(config.surface_output_binding, SurfaceOutputBinding::PerPosition);
+    }
+
+    #[test]
+    fn test_span_outputs_config() {
+        assert!(!Config::default().span_outputs);
+
+        let config: Config = toml::from_str("span_outputs = true").unwrap();
+        assert!(config.span_outputs);
+    }
+
+    #[test]
+    fn test_trash_config() {
+        let config = Config::default();
+        assert!(!config.show_trash);
+        assert_eq!(config.trash_corner, None);
+
+        let config: Config = toml::from_str(
+            r#"
+            show_trash = true
+            trash_corner = "bottom_right"
+            "#,
+        )
+        .unwrap();
+        assert!(config.show_trash);
+        assert_eq!(config.trash_corner, Some(Corner::BottomRight));
+    }
+
+    #[test]
+    fn test_volume_config() {
+        let config = Config::default();
+        assert!(!config.show_volumes);
+        assert_eq!(config.volume_refresh_ms, 30_000);
+
+        let config: Config = toml::from_str(
+            r#"
+            show_volumes = true
+            volume_refresh_ms = 5000
+            "#,
+        )
+        .unwrap();
+        assert!(config.show_volumes);
+        assert_eq!(config.volume_refresh_ms, 5000);
+    }
+
+    #[test]
+    fn test_vcs_emblems_config() {
+        let config = Config::default();
+        assert!(!config.vcs_emblems);
+        assert_eq!(config.emblem_refresh_ms, 10_000);
+
+        let config: Config = toml::from_str(
+            r#"
+            vcs_emblems = true
+            emblem_refresh_ms = 60000
+            "#,
+        )
+        .unwrap();
+        assert!(config.vcs_emblems);
+        assert_eq!(config.emblem_refresh_ms, 60_000);
+    }
+
+    #[test]
+    fn test_lua_min_uptime() {
+        assert_eq!(Config::default().lua_min_uptime_ms, 2000);
+
+        let config: Config = toml::from_str("lua_min_uptime_ms = 500").unwrap();
+        assert_eq!(config.lua_min_uptime_ms, 500);
+    }
+
+    #[test]
+    fn test_lua_pool_config() {
+        assert_eq!(Config::default().lua_pool_size, 0);
+
+        let config: Config = toml::from_str("lua_pool_size = 4").unwrap();
+        assert_eq!(config.lua_pool_size, 4);
+    }
+
+    #[test]
+    fn test_widget_status_badges_config() {
+        assert!(Config::default().widget_status_badges);
+        let config: Config = toml::from_str("widget_status_badges = false").unwrap();
+        assert!(!config.widget_status_badges);
+    }
+
+    #[test]
+    fn test_dispatch_timeout_config() {
+        assert_eq!(Config::default().dispatch_timeout_ms, 0);
+        let config: Config = toml::from_str("dispatch_timeout_ms = 5").unwrap();
+        assert_eq!(config.dispatch_timeout_ms, 5);
+    }
+
+    #[test]
+    fn test_fs_coalesce_config() {
+        let config = Config::default();
+        assert_eq!(config.fs_coalesce_ms, 100);
+        assert_eq!(config.transient_files, TransientFiles::Hide);
+        assert!(!config.trace_fs_events);
+
+        let config: Config = toml::from_str(
+            r#"
+            fs_coalesce_ms = 250
+            transient_files = "show"
+            trace_fs_events = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.fs_coalesce_ms, 250);
+        assert_eq!(config.transient_files, TransientFiles::Show);
+        assert!(config.trace_fs_events);
+
+        // The option's old name still works
+        let config: Config = toml::from_str("fs_debounce_ms = 50").unwrap();
+        assert_eq!(config.fs_coalesce_ms, 50);
+    }
+
+    #[test]
+    fn test_unavailable_files_config() {
+        assert_eq!(Config::default().unavailable_files, UnavailableFiles::Remove);
+
+        let config: Config = toml::from_str(r#"unavailable_files = "stale""#).unwrap();
+        assert_eq!(config.unavailable_files, UnavailableFiles::Stale);
+    }
+
+    #[test]
+    fn test_update_error_threshold_config() {
+        assert_eq!(Config::default().update_error_threshold, 3);
+
+        let config: Config = toml::from_str("update_error_threshold = 10").unwrap();
+        assert_eq!(config.update_error_threshold, 10);
+    }
+
+    #[test]
+    fn test_ambiguous_modify_config() {
+        assert_eq!(Config::default().ambiguous_modify, AmbiguousModify::Restat);
+
+        let config: Config = toml::from_str(r#"ambiguous_modify = "recreate""#).unwrap();
+        assert_eq!(config.ambiguous_modify, AmbiguousModify::Recreate);
+    }
+
+    #[test]
+    fn test_paused_fs_events_config() {
+        assert_eq!(Config::default().paused_fs_events, PausedFsEvents::Buffer);
+
+        let config: Config = toml::from_str(r#"paused_fs_events = "drop""#).unwrap();
+        assert_eq!(config.paused_fs_events, PausedFsEvents::Drop);
+    }
+
+    #[test]
+    fn test_thumbnails_config() {
+        let config = Config::default();
+        assert!(config.thumbnails);
+        assert_eq!(config.thumbnail_cache_dir, default_thumbnail_cache_dir());
+
+        let config: Config = toml::from_str(
+            r#"
+            thumbnails = false
+            thumbnail_cache_dir = "/tmp/cvh-thumbs"
+            "#,
+        )
+        .unwrap();
+        assert!(!config.thumbnails);
+        assert_eq!(config.thumbnail_cache_dir, Some(PathBuf::from("/tmp/cvh-thumbs")));
+    }
+
+    #[test]
+    fn test_render_cache_config() {
+        let config = Config::default();
+        assert_eq!(config.render_cache_dir, None);
+        assert_eq!(config.render_cache_max_mb, 64);
+
+        let config: Config = toml::from_str(
+            r#"
+            render_cache_dir = "/tmp/cvh-cache"
+            render_cache_max_mb = 8
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.render_cache_dir, Some(PathBuf::from("/tmp/cvh-cache")));
+        assert_eq!(config.render_cache_max_mb, 8);
+    }
+
+    #[test]
+    fn test_grid_anchor_config() {
+        assert_eq!(Config::default().grid_anchor, GridAnchor::Output);
+        let config: Config = toml::from_str(r#"grid_anchor = "work_area""#).unwrap();
+        assert_eq!(config.grid_anchor, GridAnchor::WorkArea);
+        assert!(toml::from_str::<Config>(r#"grid_anchor = "panel""#).is_err());
+    }
+
+    #[test]
+    fn test_confirm_delete_config() {
+        let config = Config::default();
+        assert!(config.confirm_delete);
+        assert_eq!(config.confirm_delete_threshold, 5);
+
+        let config: Config = toml::from_str("confirm_delete = false\nconfirm_delete_threshold = 1").unwrap();
+        assert!(!config.confirm_delete);
+        assert_eq!(config.confirm_delete_threshold, 1);
+    }
+
+    #[test]
+    fn test_show_hidden_config() {
+        assert!(!Config::default().show_hidden);
+        let config: Config = toml::from_str("show_hidden = true").unwrap();
+        assert!(config.show_hidden);
+        assert!(config.name_filter().is_visible(".env"));
+    }
+
+    #[test]
+    fn test_show_special_files_config() {
+        assert!(Config::default().show_special_files);
+        let config: Config = toml::from_str("show_special_files = false").unwrap();
+        assert!(!config.show_special_files);
+    }
+
+    #[test]
+    fn test_create_desktop_dir_config() {
+        assert!(!Config::default().create_desktop_dir);
+        let config: Config = toml::from_str("create_desktop_dir = true").unwrap();
+        assert!(config.create_desktop_dir);
+    }
+
+    #[test]
+    fn test_grid_flow_config() {
+        let config = Config::default();
+        assert_eq!(config.grid_flow, GridFlow::Rows);
+        assert_eq!(config.max_rows, None);
+
+        let config: Config = toml::from_str(
+            r#"
+            grid_flow = "columns"
+            max_rows = 4
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.grid_flow, GridFlow::Columns);
+        assert_eq!(config.max_rows, Some(4));
+    }
+
+    #[test]
+    fn test_grid_corner_config() {
+        assert_eq!(Config::default().grid_corner, Corner::TopLeft);
+        let config: Config = toml::from_str(r#"grid_corner = "bottom_right""#).unwrap();
+        assert_eq!(config.grid_corner, Corner::BottomRight);
+        assert!(toml::from_str::<Config>(r#"grid_corner = "center""#).is_err());
+    }
+
+    #[test]
+    fn test_snap_to_grid_config() {
+        assert!(Config::default().snap_to_grid);
+        let config: Config = toml::from_str("snap_to_grid = false").unwrap();
+        assert!(!config.snap_to_grid);
+    }
+
+    #[test]
+    fn test_sort_order_config() {
+        let config = Config::default();
+        assert_eq!(config.sort_order, SortOrder::Manual);
+        assert!(!config.sort_folders_first);
+
+        let config: Config = toml::from_str(
+            r#"
+            sort_order = "modified_reverse"
+            sort_folders_first = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sort_order, SortOrder::ModifiedReverse);
+        assert!(config.sort_order.is_reverse());
+        assert!(config.sort_folders_first);
+        assert!(!SortOrder::Name.is_reverse());
+    }
+
+    #[test]
+    fn test_icon_elevation_config() {
+        let config = Config::default();
+        assert!(!config.icon_elevation.enabled);
+        assert_eq!(config.icon_elevation.offset_y, 2.0);
+
+        let config: Config = toml::from_str(
+            r#"
+            [icon_elevation]
+            enabled = true
+            blur = 6.0
+            "#,
+        )
+        .unwrap();
+        assert!(config.icon_elevation.enabled);
+        assert_eq!(config.icon_elevation.blur, 6.0);
+        assert_eq!(config.icon_elevation.opacity, 0.35);
+    }
+
+    #[test]
+    fn test_surface_opacity_config() {
+        assert_eq!(Config::default().surface_opacity, 1.0);
+        let config: Config = toml::from_str("surface_opacity = 0.4").unwrap();
+        assert_eq!(config.surface_opacity, 0.4);
+    }
+
+    #[test]
+    fn test_idle_dim_config() {
+        let config = Config::default();
+        assert!(!config.idle_dim.enabled);
+        assert_eq!(config.idle_dim.delay_ms, 30_000);
+
+        let config: Config = toml::from_str(
+            r#"
+            [idle_dim]
+            enabled = true
+            opacity = 0.3
+            "#,
+        )
+        .unwrap();
+        assert!(config.idle_dim.enabled);
+        assert_eq!(config.idle_dim.opacity, 0.3);
+        assert_eq!(config.idle_dim.delay_ms, 30_000);
+    }
+
+    #[test]
+    fn test_overflow_pile_config() {
+        let config = Config::default();
+        assert!(!config.overflow_pile.enabled);
+        assert_eq!(config.overflow_pile.threshold, 2);
+
+        let config: Config = toml::from_str(
+            r#"
+            [overflow_pile]
+            enabled = true
+            offset = 10
+            "#,
+        )
+        .unwrap();
+        assert!(config.overflow_pile.enabled);
+        assert_eq!(config.overflow_pile.offset, 10);
+        assert_eq!(config.overflow_pile.threshold, 2);
+    }
+
+    #[test]
+    fn test_hide_when_covered_config() {
+        assert!(!Config::default().hide_when_covered);
+        let config: Config = toml::from_str("hide_when_covered = true").unwrap();
+        assert!(config.hide_when_covered);
+
+        assert!(Config::default().suspend_when_locked);
+        let config: Config = toml::from_str("suspend_when_locked = false").unwrap();
+        assert!(!config.suspend_when_locked);
+    }
+
+    #[test]
+    fn test_empty_hint_config() {
+        let config = Config::default();
+        assert!(config.show_empty_hint);
+        assert_eq!(config.empty_hint_text, "Drop files here");
+
+        let config: Config = toml::from_str(
+            r#"
+            show_empty_hint = false
+            empty_hint_text = "Nothing here yet"
+            "#,
+        )
+        .unwrap();
+        assert!(!config.show_empty_hint);
+        assert_eq!(config.empty_hint_text, "Nothing here yet");
+    }
+
+    #[test]
+    fn test_launcher_click_config() {
+        assert_eq!(Config::default().launcher_click, LauncherClick::AlwaysLaunch);
+        let config: Config = toml::from_str(r#"launcher_click = "focus_or_launch""#).unwrap();
+        assert_eq!(config.launcher_click, LauncherClick::FocusOrLaunch);
+    }
+
+    #[test]
+    fn test_icon_theme_dir_config() {
+        assert_eq!(Config::default().icon_theme_dir, None);
+        let config: Config = toml::from_str("icon_theme_dir = \"/usr/share/cvh/icons\"").unwrap();
+        assert_eq!(config.icon_theme_dir, Some(PathBuf::from("/usr/share/cvh/icons")));
+    }
+
+    #[test]
+    fn test_label_lines_config() {
+        assert_eq!(Config::default().label_lines, 2);
+        let config: Config = toml::from_str("label_lines = 1").unwrap();
+        assert_eq!(config.label_lines, 1);
+    }
+
+    #[test]
+    fn test_label_align_config() {
+        assert_eq!(Config::default().label_align, LabelAlign::Center);
+        let config: Config = toml::from_str(r#"label_align = "right""#).unwrap();
+        assert_eq!(config.label_align, LabelAlign::Right);
+        assert!(toml::from_str::<Config>(r#"label_align = "justify""#).is_err());
+    }
+
+    #[test]
+    fn test_expand_labels_on_hover_config() {
+        assert!(!Config::default().expand_labels_on_hover);
+        let config: Config = toml::from_str("expand_labels_on_hover = true").unwrap();
+        assert!(config.expand_labels_on_hover);
+    }
+
+    #[test]
+    fn test_tooltips_config() {
+        let config = Config::default();
+        assert!(config.tooltips);
+        assert_eq!(config.tooltip_delay_ms, 700);
+        assert!(!config.tooltip_details);
+
+        let config: Config = toml::from_str("tooltips = false\ntooltip_delay_ms = 250").unwrap();
+        assert!(!config.tooltips);
+        assert_eq!(config.tooltip_delay_ms, 250);
+    }
+
+    #[test]
+    fn test_label_pill_config() {
+        let config = Config::default();
+        assert!(!config.label_pill.enabled);
+        assert_eq!(config.label_pill.lines, PillLines::PerLine);
+
+        let config: Config = toml::from_str(
+            r##"
+            [label_pill]
+            enabled = true
+            color = "#202020c0"
+            lines = "enclosing"
+            "##,
+        )
+        .unwrap();
+        assert!(config.label_pill.enabled);
+        assert_eq!(config.label_pill.color, "#202020c0");
+        assert_eq!(config.label_pill.lines, PillLines::Enclosing);
+        assert_eq!(config.label_pill.radius, 8.0);
+    }
+
+    #[test]
+    fn test_duplicate_labels_config() {
+        assert_eq!(Config::default().duplicate_labels, DuplicateLabels::ParentName);
+        let config: Config = toml::from_str(r#"duplicate_labels = "parent_path""#).unwrap();
+        assert_eq!(config.duplicate_labels, DuplicateLabels::ParentPath);
+    }
+
+    #[test]
+    fn test_state_colors_config() {
+        let config: Config = toml::from_str("[colors]\nfocus_ring = \"#ff8800\"").unwrap();
+        assert_eq!(config.colors.focus_ring, "#ff8800");
+        assert_eq!(config.colors.hover, Colors::default().hover);
+        assert_eq!(config.colors.selection, Colors::default().selection);
+    }
+
+    #[test]
+    fn test_button_actions_config() {
+        assert!(Config::default().button_actions.is_empty());
+        let config: Config = toml::from_str(
+            r#"
+            [button_actions]
+            back = "widget"
+            forward = "open"
+            button279 = "ignore"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.button_actions["back"], ButtonAction::Widget);
+        assert_eq!(config.button_actions["forward"], ButtonAction::Open);
+        assert_eq!(config.button_actions["button279"], ButtonAction::Ignore);
+    }
+
+    #[test]
+    fn test_input_queue_limit_config() {
+        assert_eq!(Config::default().input_queue_limit, 1024);
+        let config: Config = toml::from_str("input_queue_limit = 256").unwrap();
+        assert_eq!(config.input_queue_limit, 256);
+    }
+
+    #[test]
+    fn test_long_press_config() {
+        let config = Config::default();
+        assert_eq!(config.long_press_ms, None);
+        assert_eq!(config.long_press_action, ButtonAction::ContextMenu);
+
+        let config: Config = toml::from_str(
+            r#"
+            long_press_ms = 600
+            long_press_action = "open"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.long_press_ms, Some(600));
+        assert_eq!(config.long_press_action, ButtonAction::Open);
+    }
+
+    #[test]
+    fn test_double_click_config() {
+        assert_eq!(Config::default().double_click_ms, 400);
+        let config: Config = toml::from_str("double_click_ms = 250").unwrap();
+        assert_eq!(config.double_click_ms, 250);
+    }
+
+    #[test]
+    fn test_rubber_band_select_config() {
+        assert!(Config::default().rubber_band_select);
+        let config: Config = toml::from_str("rubber_band_select = false").unwrap();
+        assert!(!config.rubber_band_select);
+    }
+
+    #[test]
+    fn test_swap_buttons_config() {
+        assert!(!Config::default().swap_buttons);
+        let config: Config = toml::from_str("swap_buttons = true").unwrap();
+        assert!(config.swap_buttons);
+    }
+
+    #[test]
+    fn test_follow_desktop_icon_size_config() {
+        assert!(!Config::default().follow_desktop_icon_size);
+
+        let config: Config = toml::from_str("follow_desktop_icon_size = true").unwrap();
+        assert!(config.follow_desktop_icon_size);
+        assert_eq!(config.icon_size, 64);
+    }
+
+    #[test]
+    fn test_animation_config() {
+        let config = Config::default();
+        assert!(config.animations.enabled);
+        assert_eq!(config.animations.appear_ms, 180);
+
+        let config: Config = toml::from_str(
+            r#"
+            [animations]
+            enabled = false
+            disappear_ms = 400
+            "#,
+        )
+        .unwrap();
+        assert!(!config.animations.enabled);
+        assert_eq!(config.animations.appear_ms, 180);
+        assert_eq!(config.animations.disappear_ms, 400);
+    }
+
+    #[test]
+    fn test_reduce_motion_config() {
+        let config = Config::default();
+        assert!(!config.reduce_motion);
+        assert!(config.animates());
+
+        let config: Config = toml::from_str("reduce_motion = true").unwrap();
+        assert!(config.animations.enabled);
+        assert!(!config.animates(), "reduce_motion overrides animations.enabled");
+    }
+
+    #[test]
+    fn test_flash_config() {
+        let config = Config::default();
+        assert_eq!(config.flash.style, FlashStyle::Glow);
+        assert_eq!(config.flash.duration_ms, 3000);
+
+        let config: Config = toml::from_str(
+            r##"
+            [flash]
+            style = "pulse"
+            color = "#ff0000"
+            pulses = 5
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.flash.style, FlashStyle::Pulse);
+        assert_eq!(config.flash.color, "#ff0000");
+        assert_eq!(config.flash.pulses, 5);
+        assert_eq!(config.flash.duration_ms, 3000);
+    }
 }
@@ -0,0 +1,449 @@
+//! Control socket for scripting the daemon
+//!
+//! The daemon listens on `$XDG_RUNTIME_DIR/cvh-icons.sock`. A client writes
+//! one JSON command per line and gets one JSON reply line back:
+//!
+//! ```text
+//! {"cmd":"place","path":"/home/me/Desktop/notes.txt","col":2,"row":1}
+//! {"ok":true}
+//! ```
+//!
+//! Commands that take no arguments may also be sent as just their name,
+//! e.g. `refresh` or `reload-config`. A client may keep the connection open
+//! and send any number of commands; a malformed one gets an error reply and
+//! the connection stays usable.
+//!
+//! Commands that return something put it in the reply's `data` field.
+//! `ping` is answered straight from the socket handler, so a supervisor gets
+//! a reply even while icons are being rendered (and possibly ahead of
+//! replies to commands sent before it).
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{IconsError, IconsResult};
+
+/// Socket file name inside the runtime directory
+const SOCKET_NAME: &str = "cvh-icons.sock";
+
+/// How long a client may take to send its command
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long `send` waits for the daemon to reply before treating it as hung
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest command line a client may send
+const MAX_LINE: usize = 1024 * 1024;
+
+/// A command sent over the control socket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Move the icon for `path` to grid cell (`col`, `row`) and remember it there
+    Place { path: PathBuf, col: u32, row: u32 },
+    /// Keep the icon for `path` on the output named `output` (`DP-1`), or unpin it
+    PinOutput {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output: Option<String>,
+    },
+    /// Return the cell of every desktop icon as a `Layout`
+    ExportLayout,
+    /// Place the icons named in an exported `Layout`, skipping missing ones
+    ImportLayout { data: serde_json::Value },
+    /// Report liveness as `Health`
+    Ping,
+    /// Show or hide the layout debugging grid, toggling it if `enabled` is not given
+    DebugGrid {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enabled: Option<bool>,
+    },
+    /// Report the IPC handler and the widget script resolved for each icon type
+    LuaStatus,
+    /// Stop applying filesystem events, rendering and handling input
+    Pause,
+    /// Undo `Pause`, catching up with the desktop directory
+    Resume,
+    /// Flash the icon for `path` to draw attention to it, once it exists
+    Flash { path: PathBuf },
+    /// Rescan the desktop directory
+    Refresh,
+    /// Re-read the configuration file, as on SIGHUP
+    ReloadConfig,
+    /// Return the path and grid cell of every icon
+    List,
+    /// Stop the daemon
+    Quit,
+}
+
+/// Reply to a control command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlReply {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl ControlReply {
+    pub fn ok() -> Self {
+        Self { ok: true, error: None, data: None }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self { ok: false, error: Some(message.into()), data: None }
+    }
+
+    /// Successful reply carrying a result
+    pub fn data(data: serde_json::Value) -> Self {
+        Self { ok: true, error: None, data: Some(data) }
+    }
+}
+
+impl From<Result<()>> for ControlReply {
+    fn from(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::ok(),
+            Err(e) => Self::error(format!("{:#}", e)),
+        }
+    }
+}
+
+/// Liveness snapshot returned for `ping`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// When the daemon started, for the uptime
+    pub started: Instant,
+    /// Number of desktop icons
+    pub icons: usize,
+    /// Whether icons are drawn to a display
+    pub wayland: bool,
+    /// When a render pass last completed, if one has
+    pub last_render: Option<SystemTime>,
+    /// Filesystem event coalescing window in effect, in ms
+    pub fs_coalesce_ms: u64,
+    /// Whether the daemon is paused by the `pause` command
+    pub paused: bool,
+}
+
+impl Health {
+    /// Reply carrying the snapshot
+    ///
+    /// `last_render_ms` is milliseconds since the Unix epoch, so a caller can
+    /// tell a stalled render loop from one that has nothing to draw.
+    pub fn reply(&self) -> ControlReply {
+        let last_render_ms = self
+            .last_render
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_millis() as u64);
+        ControlReply::data(serde_json::json!({
+            "uptime_ms": self.started.elapsed().as_millis() as u64,
+            "icons": self.icons,
+            "wayland": self.wayland,
+            "last_render_ms": last_render_ms,
+            "fs_coalesce_ms": self.fs_coalesce_ms,
+            "paused": self.paused,
+        }))
+    }
+}
+
+/// Default socket location, next to the instance lock
+pub fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join(SOCKET_NAME),
+        None => std::env::temp_dir().join(format!("cvh-icons-{}.sock", nix::unistd::getuid())),
+    }
+}
+
+/// Listen on `path` without blocking
+///
+/// The caller holds the instance lock, so an existing socket file is left
+/// over from a crashed instance and is replaced.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Read one command line from a client
+pub fn read_command(stream: &UnixStream) -> Result<ControlCommand> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("Failed to read control command")?;
+    parse_command(&line)
+}
+
+/// Parse a command line: a JSON object, or the name of a command without arguments
+pub fn parse_command(line: &str) -> Result<ControlCommand> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return serde_json::from_str(line).context("Invalid control command");
+    }
+    serde_json::from_value(serde_json::json!({ "cmd": line.replace('-', "_") }))
+        .with_context(|| format!("Invalid control command {:?}", line))
+}
+
+/// Splits what a client sends into commands, one per line
+#[derive(Debug, Default)]
+pub struct CommandReader {
+    /// Data received after the last complete line
+    partial: Vec<u8>,
+}
+
+impl CommandReader {
+    /// Read what a non-blocking `stream` has available and parse the complete lines
+    ///
+    /// A malformed line gives an error in its place, leaving the others be.
+    /// Also returns whether the client hung up or the connection broke.
+    pub fn read(&mut self, mut stream: &UnixStream) -> (Vec<Result<ControlCommand>>, bool) {
+        let mut closed = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(n) => self.partial.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut commands = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                commands.push(parse_command(&line));
+            }
+        }
+        if self.partial.len() > MAX_LINE {
+            self.partial.clear();
+            commands.push(Err(anyhow::anyhow!("Control command longer than {} bytes", MAX_LINE)));
+        }
+        (commands, closed)
+    }
+}
+
+/// Send the reply line for a command
+pub fn write_reply(mut stream: &UnixStream, reply: &ControlReply) -> Result<()> {
+    let mut line = serde_json::to_vec(reply)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+/// Send a command to the daemon at `path` and wait for its reply
+///
+/// A reply with `ok: false` is turned into an error, as is no reply within
+/// `REPLY_TIMEOUT`.
+pub fn send(path: &Path, command: &ControlCommand) -> IconsResult<ControlReply> {
+    request(path, command).map_err(IconsError::Ipc)
+}
+
+fn request(path: &Path, command: &ControlCommand) -> Result<ControlReply> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to {} (is the daemon running?)", path.display()))?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+
+    let mut line = serde_json::to_vec(command)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .context("Failed to read reply from the daemon (is it hung?)")?;
+    let reply: ControlReply = serde_json::from_str(reply.trim()).context("Invalid reply from the daemon")?;
+    if !reply.ok {
+        anyhow::bail!("{}", reply.error.as_deref().unwrap_or("Command failed"));
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_place_command() {
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"cmd":"place","path":"/d/a.txt","col":2,"row":1}"#).unwrap();
+        assert_eq!(
+            command,
+            ControlCommand::Place { path: PathBuf::from("/d/a.txt"), col: 2, row: 1 }
+        );
+
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"cmd":"explode"}"#).is_err());
+
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"cmd":"pin_output","path":"/d/a.txt","output":"DP-1"}"#).unwrap();
+        let path = PathBuf::from("/d/a.txt");
+        assert_eq!(command, ControlCommand::PinOutput { path, output: Some("DP-1".to_string()) });
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"cmd":"pin_output","path":"/d/a.txt"}"#).unwrap();
+        assert!(matches!(command, ControlCommand::PinOutput { output: None, .. }));
+    }
+
+    #[test]
+    fn test_parse_layout_commands() {
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"export_layout"}"#).unwrap();
+        assert_eq!(command, ControlCommand::ExportLayout);
+
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"cmd":"import_layout","data":{"version":1,"icons":{}}}"#).unwrap();
+        assert!(matches!(command, ControlCommand::ImportLayout { ref data } if data["version"] == 1));
+    }
+
+    #[test]
+    fn test_ping_reply() {
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"ping"}"#).unwrap();
+        assert_eq!(command, ControlCommand::Ping);
+
+        let mut health = Health {
+            started: Instant::now(),
+            icons: 3,
+            wayland: false,
+            last_render: None,
+            fs_coalesce_ms: 100,
+            paused: false,
+        };
+        let data = health.reply().data.unwrap();
+        assert_eq!(data["icons"], 3);
+        assert_eq!(data["fs_coalesce_ms"], 100);
+        assert_eq!(data["wayland"], false);
+        assert_eq!(data["paused"], false);
+        assert!(data["uptime_ms"].is_u64());
+        assert!(data["last_render_ms"].is_null());
+
+        health.last_render = Some(UNIX_EPOCH + Duration::from_millis(1_500));
+        assert_eq!(health.reply().data.unwrap()["last_render_ms"], 1_500);
+    }
+
+    #[test]
+    fn test_debug_grid_command() {
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"debug_grid"}"#).unwrap();
+        assert_eq!(command, ControlCommand::DebugGrid { enabled: None });
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"cmd":"debug_grid","enabled":false}"#).unwrap();
+        assert_eq!(command, ControlCommand::DebugGrid { enabled: Some(false) });
+        assert_eq!(
+            serde_json::to_string(&ControlCommand::DebugGrid { enabled: None }).unwrap(),
+            r#"{"cmd":"debug_grid"}"#
+        );
+    }
+
+    #[test]
+    fn test_pause_commands() {
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"pause"}"#).unwrap();
+        assert_eq!(command, ControlCommand::Pause);
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"resume"}"#).unwrap();
+        assert_eq!(command, ControlCommand::Resume);
+    }
+
+    #[test]
+    fn test_lua_status_command() {
+        let command: ControlCommand = serde_json::from_str(r#"{"cmd":"lua_status"}"#).unwrap();
+        assert_eq!(command, ControlCommand::LuaStatus);
+    }
+
+    #[test]
+    fn test_bare_command_names() {
+        assert_eq!(parse_command("refresh\n").unwrap(), ControlCommand::Refresh);
+        assert_eq!(parse_command("reload-config").unwrap(), ControlCommand::ReloadConfig);
+        assert_eq!(parse_command(r#"{"cmd":"list"}"#).unwrap(), ControlCommand::List);
+        assert_eq!(parse_command("quit").unwrap(), ControlCommand::Quit);
+        assert!(parse_command("explode").is_err());
+        // Commands with arguments need the JSON form
+        assert!(parse_command("flash").is_err());
+    }
+
+    #[test]
+    fn test_reader_keeps_going_past_malformed_lines() {
+        let (client, server) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let mut reader = CommandReader::default();
+
+        (&client).write_all(b"refresh\n{not json}\n\nli").unwrap();
+        let (commands, closed) = reader.read(&server);
+        assert!(!closed);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].as_ref().unwrap(), &ControlCommand::Refresh);
+        assert!(commands[1].is_err());
+
+        // The partial line is finished by the next read
+        (&client).write_all(b"st\n").unwrap();
+        drop(client);
+        let (commands, closed) = reader.read(&server);
+        assert!(closed);
+        assert_eq!(commands[0].as_ref().unwrap(), &ControlCommand::List);
+    }
+
+    #[test]
+    fn test_reply_json() {
+        assert_eq!(serde_json::to_string(&ControlReply::ok()).unwrap(), r#"{"ok":true}"#);
+        let reply: ControlReply = Err(anyhow::anyhow!("no such icon")).into();
+        assert_eq!(
+            serde_json::to_string(&reply).unwrap(),
+            r#"{"ok":false,"error":"no such icon"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ControlReply::data(serde_json::json!([1]))).unwrap(),
+            r#"{"ok":true,"data":[1]}"#
+        );
+    }
+
+    #[test]
+    fn test_command_round_trip_over_socket() {
+        let (client, server) = UnixStream::pair().unwrap();
+        (&client)
+            .write_all(b"{\"cmd\":\"place\",\"path\":\"/d/a.txt\",\"col\":0,\"row\":3}\n")
+            .unwrap();
+
+        let command = read_command(&server).unwrap();
+        assert!(matches!(command, ControlCommand::Place { row: 3, .. }));
+
+        write_reply(&server, &ControlReply::ok()).unwrap();
+        let mut reply = String::new();
+        BufReader::new(&client).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "{\"ok\":true}\n");
+    }
+
+    #[test]
+    fn test_send_reports_daemon_errors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("test.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = std::thread::spawn(move || {
+            for reply in [ControlReply::data(serde_json::json!({"version":1})), ControlReply::error("nope")] {
+                let (stream, _) = listener.accept().unwrap();
+                read_command(&stream).unwrap();
+                write_reply(&stream, &reply).unwrap();
+            }
+        });
+
+        let reply = send(&path, &ControlCommand::ExportLayout).unwrap();
+        assert_eq!(reply.data.unwrap()["version"], 1);
+        let error = send(&path, &ControlCommand::ExportLayout).unwrap_err();
+        assert!(matches!(error, IconsError::Ipc(_)));
+        assert_eq!(error.to_string(), "nope");
+        server.join().unwrap();
+    }
+}
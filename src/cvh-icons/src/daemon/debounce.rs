@@ -0,0 +1,295 @@
+//! Filesystem event debouncing
+//!
+//! Watcher events are held per path until the path has been quiet for a
+//! short window and coalesced before the daemon sees them, so bursts of
+//! writes refresh an icon once and files that only exist for a moment never
+//! get an icon at all.
+
+use notify::{Event, EventKind};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::TransientFiles;
+
+/// A path that keeps changing is still released after this many windows
+const MAX_HOLD_WINDOWS: u32 = 10;
+
+/// Events for one path waiting out the debounce window
+#[derive(Debug)]
+struct PendingPath {
+    /// When the first event for this path arrived
+    first_seen: Instant,
+    /// When the latest event for this path arrived
+    last_seen: Instant,
+    /// Coalesced operations, oldest first
+    kinds: Vec<EventKind>,
+}
+
+/// Buffers watcher events and releases them, coalesced, once their window passes
+#[derive(Debug)]
+pub struct EventDebouncer {
+    window: Duration,
+    transient_files: TransientFiles,
+    pending: HashMap<PathBuf, PendingPath>,
+    /// Paths in the order their first event arrived
+    order: Vec<PathBuf>,
+}
+
+impl PendingPath {
+    /// When the path's events are released if nothing else arrives
+    fn due(&self, window: Duration) -> Instant {
+        (self.last_seen + window).min(self.first_seen + window * MAX_HOLD_WINDOWS)
+    }
+}
+
+impl EventDebouncer {
+    /// Create a debouncer holding events for `window`
+    pub fn new(window: Duration, transient_files: TransientFiles) -> Self {
+        Self {
+            window,
+            transient_files,
+            pending: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// How long events are held
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Change how long events are held, including those already waiting
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Add a watcher event received at `now`, restarting its path's window
+    pub fn push(&mut self, event: Event, now: Instant) {
+        for path in event.paths {
+            let entry = self.pending.entry(path.clone()).or_insert_with(|| {
+                self.order.push(path.clone());
+                PendingPath { first_seen: now, last_seen: now, kinds: Vec::new() }
+            });
+            entry.last_seen = now;
+            coalesce(&mut entry.kinds, event.kind, self.transient_files);
+        }
+    }
+
+    /// Earliest time a held path is released, if anything is held
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.due(self.window)).min()
+    }
+
+    /// Take the events of paths that have been quiet for the window by `now`
+    ///
+    /// A path that keeps changing is released anyway once it has been held
+    /// for `MAX_HOLD_WINDOWS` windows, so its icon doesn't go stale.
+    pub fn drain_ready(&mut self, now: Instant) -> Vec<Event> {
+        let mut ready = Vec::new();
+        let pending = &mut self.pending;
+        let window = self.window;
+
+        self.order.retain(|path| {
+            let due = pending.get(path).is_none_or(|p| now >= p.due(window));
+            if !due {
+                return true;
+            }
+            if let Some(p) = pending.remove(path) {
+                ready.extend(
+                    p.kinds
+                        .into_iter()
+                        .map(|kind| Event::new(kind).add_path(path.clone())),
+                );
+            }
+            false
+        });
+
+        ready
+    }
+
+    /// Take every held event, whether or not its window has passed
+    pub fn drain_all(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for path in self.order.drain(..) {
+            if let Some(p) = self.pending.remove(&path) {
+                events.extend(p.kinds.into_iter().map(|kind| Event::new(kind).add_path(path.clone())));
+            }
+        }
+        events
+    }
+
+    /// Whether any events are still being held
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Fold a new event kind into a path's pending operations
+fn coalesce(kinds: &mut Vec<EventKind>, next: EventKind, transient_files: TransientFiles) {
+    match (kinds.last().copied(), next) {
+        // Created and gone again within the window: nothing to show
+        (Some(EventKind::Create(_)), EventKind::Remove(_))
+            if transient_files == TransientFiles::Hide =>
+        {
+            kinds.pop();
+        }
+        // Further changes to a new or already-modified path add nothing
+        (Some(EventKind::Create(_) | EventKind::Modify(_)), EventKind::Modify(_)) => {}
+        // Modified then removed: only the removal matters
+        (Some(EventKind::Modify(_)), EventKind::Remove(_)) => {
+            kinds.pop();
+            coalesce(kinds, next, transient_files);
+        }
+        (Some(EventKind::Remove(_)), EventKind::Remove(_)) => {}
+        _ => kinds.push(next),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    fn create(path: &str) -> Event {
+        event(EventKind::Create(CreateKind::File), path)
+    }
+
+    fn remove(path: &str) -> Event {
+        event(EventKind::Remove(RemoveKind::File), path)
+    }
+
+    fn modify(path: &str) -> Event {
+        event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), path)
+    }
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_events_held_until_window_passes() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(create("/d/a.txt"), start);
+
+        assert!(debouncer.drain_ready(start + WINDOW / 2).is_empty());
+        let ready = debouncer.drain_ready(start + WINDOW);
+        assert_eq!(ready.len(), 1);
+        assert!(matches!(ready[0].kind, EventKind::Create(_)));
+        assert!(debouncer.is_empty());
+    }
+
+    #[test]
+    fn test_drain_all_ignores_window() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(remove("/d/b.txt"), start);
+        debouncer.push(create("/d/a.txt"), start);
+        debouncer.push(create("/d/b.txt"), start);
+
+        let events = debouncer.drain_all();
+        let paths: Vec<&PathBuf> = events.iter().map(|e| &e.paths[0]).collect();
+        let (a, b) = (PathBuf::from("/d/a.txt"), PathBuf::from("/d/b.txt"));
+        assert_eq!(paths, [&b, &b, &a], "Held in order of each path's first event");
+        assert!(matches!(events[0].kind, EventKind::Remove(_)));
+        assert!(debouncer.is_empty());
+    }
+
+    #[test]
+    fn test_create_then_remove_is_dropped() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(create("/d/tmp.part"), start);
+        debouncer.push(modify("/d/tmp.part"), start);
+        debouncer.push(remove("/d/tmp.part"), start);
+
+        assert!(debouncer.drain_ready(start + WINDOW).is_empty());
+        assert!(debouncer.is_empty());
+    }
+
+    #[test]
+    fn test_create_then_remove_delivered_when_shown() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Show);
+        let start = Instant::now();
+        debouncer.push(create("/d/tmp.part"), start);
+        debouncer.push(remove("/d/tmp.part"), start);
+
+        let ready = debouncer.drain_ready(start + WINDOW);
+        assert_eq!(ready.len(), 2);
+        assert!(matches!(ready[0].kind, EventKind::Create(_)));
+        assert!(matches!(ready[1].kind, EventKind::Remove(_)));
+    }
+
+    #[test]
+    fn test_new_events_restart_the_window() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(create("/d/a.txt"), start);
+        debouncer.push(modify("/d/a.txt"), start + WINDOW * 3 / 4);
+        assert_eq!(debouncer.next_due(), Some(start + WINDOW * 7 / 4));
+
+        assert!(debouncer.drain_ready(start + WINDOW).is_empty(), "Not quiet yet");
+        let ready = debouncer.drain_ready(start + WINDOW * 7 / 4);
+        assert_eq!(ready.len(), 1);
+        assert!(matches!(ready[0].kind, EventKind::Create(_)));
+        assert_eq!(debouncer.next_due(), None);
+    }
+
+    #[test]
+    fn test_busy_path_released_after_max_hold() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        let step = WINDOW / 2;
+        let mut now = start;
+        while now < start + WINDOW * MAX_HOLD_WINDOWS {
+            debouncer.push(modify("/d/log.txt"), now);
+            assert!(debouncer.drain_ready(now).is_empty());
+            now += step;
+        }
+
+        assert_eq!(debouncer.drain_ready(now).len(), 1);
+    }
+
+    #[test]
+    fn test_repeated_modifies_coalesce() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        for _ in 0..5 {
+            debouncer.push(modify("/d/log.txt"), start);
+        }
+
+        assert_eq!(debouncer.drain_ready(start + WINDOW).len(), 1);
+    }
+
+    #[test]
+    fn test_remove_then_create_keeps_both_in_order() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(remove("/d/a.txt"), start);
+        debouncer.push(create("/d/a.txt"), start);
+
+        let ready = debouncer.drain_ready(start + WINDOW);
+        assert_eq!(ready.len(), 2);
+        assert!(matches!(ready[0].kind, EventKind::Remove(_)));
+        assert!(matches!(ready[1].kind, EventKind::Create(_)));
+    }
+
+    #[test]
+    fn test_paths_released_in_arrival_order() {
+        let mut debouncer = EventDebouncer::new(WINDOW, TransientFiles::Hide);
+        let start = Instant::now();
+        debouncer.push(create("/d/b"), start);
+        debouncer.push(create("/d/a"), start + Duration::from_millis(10));
+
+        let ready = debouncer.drain_ready(start + WINDOW);
+        assert_eq!(ready.len(), 1, "The later path is still inside its window");
+        assert_eq!(ready[0].paths, vec![PathBuf::from("/d/b")]);
+
+        let ready = debouncer.drain_ready(start + WINDOW * 2);
+        assert_eq!(ready[0].paths, vec![PathBuf::from("/d/a")]);
+    }
+}
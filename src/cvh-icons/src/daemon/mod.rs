@@ -5,31 +5,382 @@
 
 use anyhow::{Context, Result};
 use calloop::channel::{Channel, Sender};
+use calloop::generic::Generic;
+use calloop::{Interest, LoopHandle, Mode, PostAction, RegistrationToken};
 use calloop::timer::{TimeoutAction, Timer};
 use calloop::EventLoop;
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use nix::sys::signal::Signal;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, error, info, warn};
 
-use crate::config::Config;
-use crate::icons::{DesktopIcon, IconType};
-use crate::renderer::IconRenderer;
-use crate::wayland::{InputEvent, SurfaceId, WaylandManager};
+use crate::clipboard;
+use crate::control::{self, CommandReader, ControlCommand, ControlReply, Health};
+use crate::config::{
+    clamp_icon_size, AmbiguousModify, ButtonAction, Config, Corner, DuplicateLabels, FlashStyle,
+    FramePacing, GridAnchor, IdleDimConfig, KeepClearRegion, LauncherClick, NameFilter,
+    PausedFsEvents, SortOrder, UnavailableFiles, UsableArea, MAX_HOVER_ZOOM,
+};
+use crate::desktop_settings::{self, SettingsSource};
+use crate::emblems::{Emblem, EmblemProvider, GitStatusProvider};
+use crate::folder_icon::{self, FolderIcon};
+use crate::error::{DaemonError, IconsError, IconsResult};
+use crate::launcher;
+use crate::log_level::{self, LogControl};
+use crate::reload;
+use crate::session_lock::{self, LockState};
+use crate::shutdown;
+use crate::signals;
+use crate::icons::{
+    cell_at, cell_origin, flow_rank, flow_slot, free_flow_slot, grid_size, mirror_to_corner, sandbox_options,
+    ClickAction, ContextAction, DesktopIcon, IconType, LinkState, MenuEntry, WidgetStatus,
+};
+use crate::lua::LuaPool;
+use crate::renderer::{
+    animate_frame, confirm_popup_hit, context_menu_height, context_menu_hit, ease_out, fit_pixmap,
+    flash_level, glow_frame, label_height, wrap_label, zoom_frame, DamageRect, IconRenderer,
+    SymbolicStyle, TextAlign, TypeTheme, CONFIRM_POPUP_HEIGHT, CONFIRM_POPUP_WIDTH, CONTEXT_MENU_WIDTH,
+    SELECTION_BADGE_SIZE,
+};
+use crate::mounts::{self, FileState};
+use crate::placements::{Cell, Layout, Placements};
+use crate::render_cache::RenderCache;
+use crate::thumbnails::Thumbnailer;
+use crate::templates::{self, NewItem};
+use crate::trash;
+use crate::backend::{
+    CursorShape, FrameGroup, InputEvent, KeyModifiers, MouseButton, SurfaceBackend, SurfaceId, BTN_TOUCH,
+};
+use crate::wayland::WaylandManager;
+
+mod debounce;
+
+use debounce::EventDebouncer;
+
+/// Render anyway if a requested frame callback has not arrived within this time
+///
+/// Compositors may throttle callbacks for occluded background surfaces.
+const FRAME_CALLBACK_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Distance in pixels the pointer must travel while pressed to start a drag
+const DRAG_THRESHOLD: f64 = 4.0;
+
+/// Offset of the selection badge from the cursor hotspot
+const BADGE_OFFSET: i32 = 12;
+
+/// Distance of corner-pinned icons from the edges of the usable area
+const PINNED_MARGIN: i32 = 20;
+
+/// Icons of the overflow pile past this many are stacked without further offset
+const PILE_MAX_STEPS: u32 = 4;
+
+/// A pointer press on an icon that may turn into a drag
+#[derive(Debug, Clone)]
+struct PointerPress {
+    surface_id: SurfaceId,
+    path: PathBuf,
+    x: f64,
+    y: f64,
+    /// Reduce the selection to this icon on release unless a drag started
+    collapse_on_release: bool,
+    /// When the button went down, for telling a long press
+    started: Instant,
+}
+
+/// An in-progress internal drag of one or more icons
+#[derive(Debug)]
+struct DragState {
+    /// Number of icons being dragged
+    count: usize,
+    /// Follow-cursor badge surface (only for multi-icon drags)
+    badge_surface: Option<SurfaceId>,
+    /// Icons following the pointer, the pressed one first
+    icons: Vec<DraggedIcon>,
+    /// Output position of the press that started the drag
+    grab: (i32, i32),
+    /// Latest output position of the pointer
+    pointer: (i32, i32),
+}
+
+/// An icon whose surface follows the pointer during a drag
+#[derive(Debug)]
+struct DraggedIcon {
+    path: PathBuf,
+    surface_id: SurfaceId,
+    /// Where the surface was when the drag started
+    origin: (i32, i32),
+}
+
+/// A file moved to the trash, kept to undo that
+#[derive(Debug)]
+struct TrashedFile {
+    original: PathBuf,
+    trashed: PathBuf,
+    trash_dir: PathBuf,
+    /// Cell its icon was in, to go back to
+    cell: Option<Cell>,
+}
+
+/// Moves to the trash that can be undone, counting back from the latest
+const TRASH_UNDO_DEPTH: usize = 10;
+
+/// How files are deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeleteKind {
+    /// Move to the trash
+    Trash,
+    /// Delete permanently
+    Permanent,
+    /// Permanently delete everything in the trash
+    EmptyTrash,
+}
+
+/// A delete waiting for the user to answer the confirmation popup
+#[derive(Debug)]
+struct PendingDelete {
+    /// Files to delete, or the trash directory to empty
+    paths: Vec<PathBuf>,
+    kind: DeleteKind,
+    /// Surface of the confirmation popup, once created
+    surface: Option<SurfaceId>,
+}
+
+impl PendingDelete {
+    /// Question the confirmation popup asks
+    fn prompt(&self) -> String {
+        let items = match self.paths.len() {
+            1 => "1 item".to_string(),
+            n => format!("{} items", n),
+        };
+        match self.kind {
+            DeleteKind::Trash => format!("Move {} to the trash?", items),
+            DeleteKind::Permanent => format!("Delete {}?", items),
+            DeleteKind::EmptyTrash => "Permanently empty the trash?".to_string(),
+        }
+    }
+}
+
+/// Magnified copy of the hovered icon, drawn above its neighbours
+#[derive(Debug)]
+struct Spotlight {
+    path: PathBuf,
+    /// Overlay surface showing the magnified icon
+    surface: SurfaceId,
+    /// Size of the overlay: the icon's surface at full zoom
+    width: u32,
+    height: u32,
+    /// Zoom the current animation starts from and heads to
+    from: f32,
+    to: f32,
+    started: Instant,
+    /// Zoom of the last frame drawn on the overlay
+    rendered_zoom: f32,
+}
+
+impl Spotlight {
+    /// Zoom at `now`, for an animation lasting `duration`
+    fn zoom(&self, now: Instant, duration: Duration) -> f32 {
+        let t = fade_progress(now.saturating_duration_since(self.started), duration);
+        self.from + (self.to - self.from) * t
+    }
+}
+
+/// An icon's context menu, open at the pointer
+#[derive(Debug)]
+struct ContextMenu {
+    /// Icon the menu acts on
+    path: PathBuf,
+    entries: Vec<MenuEntry>,
+    surface: SurfaceId,
+    /// Entry under the pointer
+    highlighted: Option<usize>,
+    /// Since when the pointer has been off the menu; None while it is on it
+    left_since: Option<Instant>,
+}
+
+/// How long the pointer may stay off an open context menu before it closes
+const CONTEXT_MENU_LEAVE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Full name of the hovered icon, shown over its truncated label
+#[derive(Debug)]
+struct ExpandedLabel {
+    path: PathBuf,
+    surface: SurfaceId,
+    /// Size of the label surface, fitting the name it was created for
+    width: u32,
+    height: u32,
+}
+
+/// Tooltip of the icon the pointer rests on
+struct Tooltip {
+    path: PathBuf,
+    /// Surface of the icon the pointer is on
+    icon_surface: SurfaceId,
+    /// When the tooltip shows, until it has
+    due: Option<Instant>,
+    /// The tooltip's own surface once shown, and the size it was made for
+    surface: Option<(SurfaceId, u32, u32)>,
+}
+
+/// Characters per line of a tooltip, before a long name wraps
+const TOOLTIP_LINE_CHARS: usize = 48;
+
+/// Gap between an icon and its tooltip
+const TOOLTIP_GAP: i32 = 4;
+
+/// A rectangle being dragged out on empty desktop to select the icons it touches
+struct RubberBand {
+    /// Output position of the press that started it
+    origin: (i32, i32),
+    /// Latest output position of the pointer
+    pointer: (i32, i32),
+    /// Overlay drawing the band, once the pointer moved past `DRAG_THRESHOLD`
+    surface: Option<SurfaceId>,
+}
+
+impl RubberBand {
+    /// The band's output rectangle as `(x, y, width, height)`
+    fn rect(&self) -> (i32, i32, u32, u32) {
+        (
+            self.origin.0.min(self.pointer.0),
+            self.origin.1.min(self.pointer.1),
+            self.origin.0.abs_diff(self.pointer.0),
+            self.origin.1.abs_diff(self.pointer.1),
+        )
+    }
+}
 
-/// Height reserved for the label area below the icon
-const LABEL_HEIGHT: u32 = 24;
+/// Frames an icon's buffer attach is retried for before giving up on it
+///
+/// Attaches fail when the shm pool cannot provide a buffer, which is usually
+/// transient (the pool grows on the next attempt).
+const MAX_ATTACH_RETRIES: u32 = 5;
+
+/// Opacity of icons whose files are on a filesystem that went away
+const UNAVAILABLE_OPACITY: f32 = 0.4;
+
+/// Opacity of icons whose files failed their last check but have not yet
+/// reached `update_error_threshold`
+const UPDATE_ERROR_OPACITY: f32 = 0.7;
+
+/// Scale icons grow from when appearing and shrink to when disappearing
+const ANIMATION_MIN_SCALE: f32 = 0.8;
+
+/// How much smaller a flashing icon gets at the height of a pulse
+const FLASH_PULSE_DEPTH: f32 = 0.15;
+
+/// How long a flash asked for before its file appears stays queued
+const PENDING_FLASH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long icons take to dim when idle and to brighten again, if animated
+const IDLE_DIM_FADE: Duration = Duration::from_millis(400);
+
+/// How long the hover zoom takes to grow and shrink, if animated
+const HOVER_ZOOM_DURATION: Duration = Duration::from_millis(150);
+
+/// Space left around an image's thumbnail inside the glyph area
+const THUMBNAIL_MARGIN: u32 = 4;
+
+/// Smallest output dimension icons are laid out on
+///
+/// Outputs can briefly report 0x0 or 1x1 (e.g. during a mode switch); the
+/// current layout is kept until a usable size is reported.
+const MIN_OUTPUT_SIZE: u32 = 64;
+
+/// How often the desktop environment's icon size setting is re-read
+const SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the session's lock state is re-read
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Largest surface side rendered at the compositor's size; bigger (or empty)
+/// surfaces are rendered at the icon's own size instead
+const MAX_SURFACE_SIDE: u32 = 4096;
+
+/// How long the first render waits for the output to report its size
+const OUTPUT_WAIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Size of the empty-desktop hint surface
+const EMPTY_HINT_WIDTH: u32 = 360;
+const EMPTY_HINT_HEIGHT: u32 = 48;
+
+/// Font size of the empty-desktop hint
+const EMPTY_HINT_FONT_SIZE: f32 = 20.0;
+
+/// Opacity of the empty-desktop hint text, faint enough to stay out of the way
+const EMPTY_HINT_OPACITY: f32 = 0.45;
+
+/// Script every widget process runs, loading the icon type's widget script
+const IPC_HANDLER_SCRIPT: &str = "ipc_handler.lua";
+
+/// Icon types whose widget scripts `lua_status` reports
+const WIDGET_ICON_TYPES: [IconType; 12] = [
+    IconType::File,
+    IconType::Folder,
+    IconType::Symlink,
+    IconType::Executable,
+    IconType::Image,
+    IconType::Document,
+    IconType::Archive,
+    IconType::Video,
+    IconType::Audio,
+    IconType::Trash,
+    IconType::Volume,
+    IconType::Unknown,
+];
+
+/// Linux evdev button codes
+const BTN_LEFT: u32 = 272;
+const BTN_RIGHT: u32 = 273;
+
+/// X keysyms of the C key, without and with Shift
+const KEY_C: u32 = 0x0063;
+const KEY_SHIFT_C: u32 = 0x0043;
+
+/// X keysyms of the Delete key and the Z key
+const KEY_DELETE: u32 = 0xffff;
+const KEY_Z: u32 = 0x007a;
+
+/// X keysyms of the keys that move the keyboard focus and open its icon
+const KEY_LEFT: u32 = 0xff51;
+const KEY_UP: u32 = 0xff52;
+const KEY_RIGHT: u32 = 0xff53;
+const KEY_DOWN: u32 = 0xff54;
+const KEY_RETURN: u32 = 0xff0d;
+const KEY_KP_ENTER: u32 = 0xff8d;
+
+/// X keysyms of the keys that start, leave and edit an inline rename
+const KEY_F2: u32 = 0xffbf;
+const KEY_ESCAPE: u32 = 0xff1b;
+const KEY_BACKSPACE: u32 = 0xff08;
+const KEY_HOME: u32 = 0xff50;
+const KEY_END: u32 = 0xff57;
+
+/// A removed icon's surface, kept on screen while its last frame fades out
+#[derive(Debug)]
+struct FadeOut {
+    surface_id: SurfaceId,
+    /// Last frame presented on the surface before the icon was removed
+    frame: tiny_skia::Pixmap,
+    started: Instant,
+}
 
 /// Icon daemon that manages desktop icons
 pub struct IconDaemon {
     config: Config,
     desktop_dir: PathBuf,
+    /// Whether the desktop directory is missing and its creation awaited
+    awaiting_desktop_dir: bool,
     icons: HashMap<PathBuf, DesktopIcon>,
     watcher: Option<RecommendedWatcher>,
     event_sender: Option<Sender<notify::Result<Event>>>,
-    /// Wayland manager for surfaces and input
-    wayland: Option<WaylandManager>,
+    /// Display backend for surfaces and input (Wayland unless embedded)
+    backend: Option<Box<dyn SurfaceBackend>>,
     /// Icon renderer
     renderer: IconRenderer,
     /// Map surface IDs to icon paths for event routing
@@ -39,54 +390,316 @@ pub struct IconDaemon {
     /// Default screen dimensions (will be updated from outputs)
     screen_width: u32,
     screen_height: u32,
-    /// Flag indicating icons need to be re-rendered
+    /// Name of the output icons are placed on, for per-output settings
+    output_name: Option<String>,
+    /// Every icon needs re-rendering (see `mark_all_dirty`)
     needs_render: bool,
+    /// Icons whose own frame changed and need re-rendering (see `mark_dirty`)
+    dirty_icons: HashSet<PathBuf>,
+    /// Current pointer press on an icon, if any
+    pointer_press: Option<PointerPress>,
+    /// Surface and time of the last primary-button press that could start a double click
+    last_click: Option<(SurfaceId, Instant)>,
+    /// Current internal drag, if any
+    drag: Option<DragState>,
+    /// Icon that Shift+click range selection extends from
+    selection_anchor: Option<PathBuf>,
+    /// Icon with the keyboard focus ring, which arrow keys move and Enter opens
+    keyboard_focus: Option<PathBuf>,
+    /// Icons stacked in the overflow pile, bottom first
+    pile: Vec<PathBuf>,
+    /// Whether the overflow pile is fanned out over the grid
+    pile_fanned: bool,
+    /// Filename filter compiled from the config's ignore/allow patterns
+    name_filter: NameFilter,
+    /// When icons were last rendered to their surfaces
+    last_render: Instant,
+    /// When icons in each frame group (output) were last drawn
+    frame_group_renders: HashMap<FrameGroup, Instant>,
+    /// Wall-clock time the last render pass completed, reported by `ping`
+    last_render_completed: Option<SystemTime>,
+    /// Surfaces whose buffer attach was skipped because they were not configured yet
+    awaiting_configure: HashSet<SurfaceId>,
+    /// Trash directory backing the built-in Trash icon, if shown
+    trash_dir: Option<PathBuf>,
+    /// Holds and coalesces filesystem events before they are applied
+    fs_debouncer: EventDebouncer,
+    /// Icons playing their appear animation, with its start time
+    appearing: HashMap<PathBuf, Instant>,
+    /// Surfaces of removed icons playing their disappear animation
+    disappearing: Vec<FadeOut>,
+    /// Flashes asked for paths without an icon yet, with when they were asked for
+    pending_flashes: HashMap<PathBuf, Instant>,
+    /// Icon whose label is being edited inline
+    renaming: Option<PathBuf>,
+    /// Desktop environment settings the icon size follows, if enabled and detected
+    settings_source: Option<Box<dyn SettingsSource>>,
+    /// When `settings_source` was last read
+    last_settings_poll: Instant,
+    /// Icons placed at specific grid cells
+    placements: Placements,
+    /// Consecutive failed buffer attaches per icon
+    attach_failures: HashMap<PathBuf, u32>,
+    /// Mount points of the volumes shown as icons
+    volumes: HashSet<PathBuf>,
+    /// When volumes were last re-scanned
+    last_volume_refresh: Instant,
+    /// Rendered icons kept on disk for a fast first frame
+    render_cache: Option<RenderCache>,
+    /// Worker making thumbnails of image files, if `thumbnails` is on
+    thumbnailer: Option<Thumbnailer>,
+    /// Shared processes running the widgets, unless `lua_pool_size` is 0
+    lua_pool: Option<LuaPool>,
+    /// Icons whose surface size could not be rendered at (warned about once)
+    unrenderable_sizes: HashSet<PathBuf>,
+    /// Whether the screen size came from the output rather than the placeholder
+    dimensions_known: bool,
+    /// When the daemon was created, to bound the wait for the output size
+    created: Instant,
+    /// Surface of the hint shown while the desktop has no icons
+    empty_hint: Option<SurfaceId>,
+    /// Work area reported by the backend, when the grid is anchored to it
+    work_area: Option<(u32, u32)>,
+    /// Other connected outputs, by name and size, which icons pinned to them
+    /// and icons overflowing the grid with `span_outputs` go to
+    secondary_outputs: Vec<(String, u32, u32)>,
+    /// Output of each icon whose surface is on a secondary output
+    icon_outputs: HashMap<PathBuf, String>,
+    /// Log level switched by SIGUSR1, if the embedder enabled it
+    log_control: Option<LogControl>,
+    /// Whether SIGHUP and edits to the configuration file reload it, if the
+    /// embedder enabled it
+    reload_on_sighup: bool,
+    /// Whether SIGINT and SIGTERM stop the loop cleanly, if the embedder enabled it
+    stop_on_signals: bool,
+    /// Whether `scan_desktop` is adding icons, which it sorts once at the end
+    scanning: bool,
+    /// Configuration file re-read on reload; `None` for the default location
+    config_path: Option<PathBuf>,
+    /// Delete waiting for confirmation, if any
+    pending_delete: Option<PendingDelete>,
+    /// Moves to the trash Ctrl+Z can undo, the latest last
+    trash_undo: Vec<Vec<TrashedFile>>,
+    /// Context menu open on an icon, if any
+    context_menu: Option<ContextMenu>,
+    /// When the last input event arrived, for idle dimming
+    last_input: Instant,
+    /// When icons started brightening after idle dimming, and from what opacity
+    idle_wake: Option<(Instant, f32)>,
+    /// Idle dim opacity of the last rendered frame
+    rendered_idle_opacity: f32,
+    /// Whether icon surfaces are unmapped because a window covers the desktop
+    covered: bool,
+    /// Magnified hovered icon, while `hover_zoom` is on
+    spotlight: Option<Spotlight>,
+    /// Full label of the hovered icon, while `expand_labels_on_hover` is on
+    expanded_label: Option<ExpandedLabel>,
+    /// Tooltip of the icon the pointer rests on, with `tooltips`
+    tooltip: Option<Tooltip>,
+    /// Overlay showing the layout grid, while `debug_grid` is on
+    debug_grid: Option<SurfaceId>,
+    /// Transparent surface beneath the icons catching presses on empty
+    /// desktop, while `rubber_band_select` is on
+    backdrop: Option<SurfaceId>,
+    /// Size of the buffer last attached to the backdrop
+    backdrop_drawn: Option<(u32, u32)>,
+    /// Rubber band selection in progress
+    rubber_band: Option<RubberBand>,
+    /// Cursor last asked of the backend
+    cursor_shape: CursorShape,
+    /// Sources of status emblems, in order of precedence
+    emblem_providers: Vec<Box<dyn EmblemProvider>>,
+    /// Emblem drawn on each icon, as of the last refresh
+    emblems: HashMap<PathBuf, Emblem>,
+    /// When emblems were last refreshed
+    last_emblem_refresh: Instant,
+    /// Whether the desktop changed since emblems were last refreshed
+    emblems_stale: bool,
+    /// Whether the missing IPC handler was already warned about
+    warned_missing_handler: bool,
+    /// Whether the `pause` control command froze the desktop
+    paused: bool,
+    /// Whether the `quit` control command asked the daemon to stop
+    quit_requested: bool,
+    /// Where the session's lock state is read, with `suspend_when_locked`
+    session_lock: Option<Box<dyn LockState>>,
+    /// When `session_lock` was last read
+    last_lock_poll: Instant,
+    /// Whether icons are suspended because the session is locked
+    locked: bool,
 }
 
 impl IconDaemon {
-    /// Create a new icon daemon
-    pub fn new(config: Config, desktop_dir: PathBuf) -> Result<Self> {
-        info!("Initializing icon daemon for {}", desktop_dir.display());
-
-        // Try to create Wayland manager (may fail if not on Wayland)
-        let wayland = match WaylandManager::new() {
+    /// Create a new icon daemon on the Wayland display, if there is one
+    ///
+    /// Fails with `IconsError::Wayland` if the display is there but unusable.
+    pub fn new(config: Config, desktop_dir: PathBuf) -> IconsResult<Self> {
+        // Try to create Wayland manager. Without WAYLAND_DISPLAY we run headless;
+        // any other failure is fatal so the exit code tells the supervisor why.
+        let backend: Option<Box<dyn SurfaceBackend>> = match WaylandManager::new() {
             Ok(wm) => {
                 info!("Wayland manager initialized successfully");
-                Some(wm)
+                Some(Box::new(wm))
             }
-            Err(e) => {
-                warn!("Failed to initialize Wayland manager: {} (running without display)", e);
+            Err(e) if matches!(e.downcast_ref::<DaemonError>(), Some(DaemonError::NoWaylandDisplay)) => {
+                warn!("{} (running without display)", e);
                 None
             }
+            Err(e) => return Err(IconsError::Wayland(e)),
         };
 
+        Self::with_backend(config, desktop_dir, backend)
+    }
+
+    /// Create a new icon daemon drawing to the given backend
+    ///
+    /// This is the entry point for embedding: pass your own `SurfaceBackend`
+    /// (or `None` to manage icons without drawing them) and drive the daemon
+    /// with `queue_fs_event` and `step` instead of `run`. Fails with
+    /// `IconsError::Desktop` if the desktop directory cannot be read.
+    pub fn with_backend(
+        mut config: Config,
+        desktop_dir: PathBuf,
+        mut backend: Option<Box<dyn SurfaceBackend>>,
+    ) -> IconsResult<Self> {
+        info!("Initializing icon daemon for {}", desktop_dir.display());
+
+        let settings_source = desktop_icon_size_source(&mut config);
+        let session_lock = session_lock_state(&config);
+        config.icon_size = safe_icon_size(config.icon_size);
+
         // Create renderer
-        let renderer = IconRenderer::new(config.icon_size, config.font_size);
+        let renderer = create_renderer(&config);
+        let name_filter = config.name_filter();
+        let fs_debouncer = create_debouncer(&config);
+        let render_cache = create_render_cache(&config);
+        let thumbnailer = create_thumbnailer(&config);
+        let lua_pool = create_lua_pool(&config);
+        let emblem_providers = emblem_providers(&config);
+
+        if let Some(ref mut backend) = backend {
+            backend.set_primary_output(config.primary_output.clone());
+            backend.set_surface_output_binding(config.surface_output_binding);
+            backend.set_anchor_corner(config.grid_corner);
+            backend.set_input_queue_limit(config.input_queue_limit);
+            backend.set_surface_opacity(config.surface_opacity);
+        }
 
-        // Get initial screen dimensions from Wayland if available
-        let (screen_width, screen_height) = if let Some(ref wm) = wayland {
-            wm.get_output_dimensions().unwrap_or((1920, 1080))
-        } else {
-            (1920, 1080)
-        };
+        // Get initial screen dimensions from the backend if available. Until
+        // the output reports a size, rendering is held back rather than
+        // showing a layout for the placeholder that then jumps.
+        let output_dimensions = backend
+            .as_ref()
+            .and_then(|backend| backend.get_output_dimensions())
+            .filter(|&(width, height)| is_usable_output(width, height));
+        let dimensions_known = backend.is_none() || output_dimensions.is_some();
+        let (screen_width, screen_height) = output_dimensions.unwrap_or((1920, 1080));
+        let output_name = backend.as_ref().and_then(|backend| backend.get_output_name());
+
+        if let Some(ref mut backend) = backend {
+            backend.set_work_area_anchoring(config.grid_anchor == GridAnchor::WorkArea);
+        }
+        let work_area = backend.as_ref().and_then(|backend| backend.get_work_area());
+
+        if config.create_desktop_dir && !desktop_dir.exists() {
+            match std::fs::create_dir_all(&desktop_dir) {
+                Ok(()) => info!("Created desktop directory {}", desktop_dir.display()),
+                Err(e) => {
+                    warn!("Failed to create desktop directory {}: {}", desktop_dir.display(), e)
+                }
+            }
+        }
 
         let mut daemon = Self {
             config,
+            awaiting_desktop_dir: !desktop_dir.is_dir(),
             desktop_dir,
             icons: HashMap::new(),
             watcher: None,
             event_sender: None,
-            wayland,
+            backend,
             renderer,
             surface_to_path: HashMap::new(),
             path_to_surface: HashMap::new(),
             screen_width,
             screen_height,
+            output_name,
             needs_render: true, // Initial render needed
+            dirty_icons: HashSet::new(),
+            pointer_press: None,
+            last_click: None,
+            drag: None,
+            selection_anchor: None,
+            keyboard_focus: None,
+            pile: Vec::new(),
+            pile_fanned: false,
+            name_filter,
+            last_render: Instant::now(),
+            frame_group_renders: HashMap::new(),
+            last_render_completed: None,
+            awaiting_configure: HashSet::new(),
+            trash_dir: None,
+            fs_debouncer,
+            appearing: HashMap::new(),
+            disappearing: Vec::new(),
+            pending_flashes: HashMap::new(),
+            renaming: None,
+            settings_source,
+            last_settings_poll: Instant::now(),
+            placements: Placements::load_default(),
+            attach_failures: HashMap::new(),
+            volumes: HashSet::new(),
+            last_volume_refresh: Instant::now(),
+            render_cache,
+            thumbnailer,
+            lua_pool,
+            unrenderable_sizes: HashSet::new(),
+            dimensions_known,
+            created: Instant::now(),
+            empty_hint: None,
+            work_area,
+            secondary_outputs: Vec::new(),
+            icon_outputs: HashMap::new(),
+            log_control: None,
+            reload_on_sighup: false,
+            stop_on_signals: false,
+            scanning: false,
+            config_path: None,
+            pending_delete: None,
+            trash_undo: Vec::new(),
+            context_menu: None,
+            last_input: Instant::now(),
+            idle_wake: None,
+            rendered_idle_opacity: 1.0,
+            covered: false,
+            spotlight: None,
+            expanded_label: None,
+            tooltip: None,
+            debug_grid: None,
+            backdrop: None,
+            backdrop_drawn: None,
+            rubber_band: None,
+            cursor_shape: CursorShape::Default,
+            emblem_providers,
+            emblems: HashMap::new(),
+            last_emblem_refresh: Instant::now(),
+            emblems_stale: true,
+            warned_missing_handler: false,
+            paused: false,
+            quit_requested: false,
+            session_lock,
+            last_lock_poll: Instant::now(),
+            locked: false,
         };
 
+        // The backdrop goes beneath the icons, so it is created before them
+        daemon.sync_backdrop();
+
         // Initial scan of desktop directory
-        daemon.scan_desktop()?;
+        daemon.scan_desktop().map_err(IconsError::Desktop)?;
+        daemon.sync_trash_icon();
+        daemon.sync_volume_icons();
+        daemon.sync_empty_hint();
 
         Ok(daemon)
     }
@@ -104,43 +717,114 @@ impl IconDaemon {
 
         self.watcher = Some(watcher);
         self.event_sender = Some(sender);
+        info!("File watcher backend: {:?}", RecommendedWatcher::kind());
+
+        // Start watching, or wait for the desktop directory to be created
+        if let Some(ref mut watcher) = self.watcher {
+            if !self.awaiting_desktop_dir {
+                watcher.watch(&self.desktop_dir, RecursiveMode::NonRecursive)?;
+                info!("Watching desktop directory: {}", self.desktop_dir.display());
+            } else if let Some(parent) = self.desktop_dir.parent().filter(|p| p.is_dir()) {
+                watcher.watch(parent, RecursiveMode::NonRecursive)?;
+                info!("Waiting for desktop directory {} to be created", self.desktop_dir.display());
+            } else {
+                warn!("Neither the desktop directory nor its parent exist, not watching");
+            }
+        }
+
+        // Edits to the configuration file reload it. Its directory is watched,
+        // since editors save by replacing the file
+        let config_dir = self.config_file().and_then(|file| Some(file.parent()?.to_path_buf()));
+        if let (Some(dir), Some(watcher)) = (config_dir, self.watcher.as_mut()) {
+            match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => info!("Watching configuration directory: {}", dir.display()),
+                Err(e) => debug!("Cannot watch {}: {}", dir.display(), e),
+            }
+        }
+
+        // Icons loaded before the watcher existed still need their own watches
+        let watched: Vec<PathBuf> = self.icons.values().filter_map(icon_watch_path).collect();
+        if let Some(ref mut watcher) = self.watcher {
+            for path in watched {
+                if let Err(e) = watcher.watch(&path, RecursiveMode::NonRecursive) {
+                    debug!("Cannot watch {}: {}", path.display(), e);
+                }
+            }
+        }
 
-        // Start watching
+        Ok(())
+    }
+
+    /// Start showing the desktop directory once it has been created
+    ///
+    /// Moves the watch from its parent to the directory itself and loads
+    /// whatever was put in it meanwhile.
+    fn desktop_dir_created(&mut self) -> Result<()> {
+        info!("Desktop directory {} created", self.desktop_dir.display());
+        self.awaiting_desktop_dir = false;
         if let Some(ref mut watcher) = self.watcher {
+            if let Some(parent) = self.desktop_dir.parent() {
+                let _ = watcher.unwatch(parent);
+            }
             watcher.watch(&self.desktop_dir, RecursiveMode::NonRecursive)?;
             info!("Watching desktop directory: {}", self.desktop_dir.display());
         }
-
+        self.scan_desktop()?;
+        self.sync_empty_hint();
+        self.needs_render = true;
         Ok(())
     }
 
     /// Scan the desktop directory for files/folders
     fn scan_desktop(&mut self) -> Result<()> {
+        if let Some(paths) = self.list_desktop()? {
+            self.add_listed(paths);
+        }
+        Ok(())
+    }
+
+    /// The desktop directory's entries, or None if it doesn't exist
+    fn list_desktop(&self) -> Result<Option<Vec<PathBuf>>> {
         if !self.desktop_dir.exists() {
             warn!("Desktop directory does not exist: {}", self.desktop_dir.display());
-            return Ok(());
+            return Ok(None);
         }
 
         let entries = std::fs::read_dir(&self.desktop_dir)
             .context("Failed to read desktop directory")?;
+        Ok(Some(listed_paths(&self.desktop_dir, entries)))
+    }
 
-        for entry in entries.filter_map(|e| e.ok()) {
-            let path = entry.path();
-
-            // Skip hidden files
-            if path.file_name()
-                .and_then(|n| n.to_str())
-                .map(|n| n.starts_with('.'))
-                .unwrap_or(false)
-            {
-                continue;
+    /// Add icons for the listed desktop entries that don't have one yet
+    ///
+    /// An entry that fails to load is logged and skipped, like an
+    /// unreadable one in `listed_paths`.
+    fn add_listed(&mut self, paths: Vec<PathBuf>) {
+        // Free the cells of files deleted while we weren't watching first,
+        // so new files can flow into them
+        let pruned = self.placements.prune_missing(&self.desktop_dir);
+        if !pruned.is_empty() {
+            debug!("Dropped the placements of {} deleted file(s)", pruned.len());
+            if let Err(e) = self.placements.save() {
+                warn!("Failed to save icon placements: {:#}", e);
             }
+        }
 
-            self.add_icon(&path)?;
+        // Under a sort order, or with an overflow pile, the grid is arranged
+        // once after the whole scan
+        self.scanning = true;
+        // Hidden and ignored files are filtered in add_icon
+        for path in paths {
+            if let Err(e) = self.add_icon(&path) {
+                warn!("Failed to add icon for {}: {:#}", path.display(), e);
+            }
+        }
+        self.scanning = false;
+        if self.config.sort_order != SortOrder::Manual || self.config.overflow_pile.enabled {
+            self.reposition_all_icons();
         }
 
         info!("Loaded {} desktop icons", self.icons.len());
-        Ok(())
     }
 
     /// Add an icon for a file/folder
@@ -149,10 +833,118 @@ impl IconDaemon {
             return Ok(());
         }
 
-        let mut icon = DesktopIcon::new(path, &self.config)?;
+        // Skip hidden files and names excluded by ignore/allow patterns
+        if !self.name_filter.is_path_visible(path) {
+            debug!("Skipping filtered entry: {}", path.display());
+            return Ok(());
+        }
+
+        let icon = DesktopIcon::new(path, &self.config)?;
+        if icon.icon_type().is_special() && !self.config.show_special_files {
+            debug!("Skipping special file: {}", path.display());
+            return Ok(());
+        }
+        self.insert_icon(icon);
+
+        Ok(())
+    }
+
+    /// Show or hide the built-in Trash icon to match the config
+    fn sync_trash_icon(&mut self) {
+        match (self.config.show_trash, self.trash_dir.clone()) {
+            (true, None) => match trash::home_trash_dir() {
+                Some(trash_dir) => self.add_trash_icon(&trash_dir),
+                None => warn!("Cannot locate the trash directory; not showing Trash icon"),
+            },
+            (false, Some(trash_dir)) => {
+                self.remove_icon(&trash_dir);
+                self.trash_dir = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Add the built-in Trash icon for the trash at `trash_dir`
+    fn add_trash_icon(&mut self, trash_dir: &Path) {
+        // Create the trash (as the spec allows) so there is something to watch
+        if let Err(e) = std::fs::create_dir_all(trash::files_dir(trash_dir)) {
+            debug!("Cannot create trash directory {}: {}", trash_dir.display(), e);
+        }
+
+        match DesktopIcon::new_trash(trash_dir, &self.config) {
+            Ok(icon) => {
+                self.trash_dir = Some(trash_dir.to_path_buf());
+                self.insert_icon(icon);
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create Trash icon: {}", e),
+        }
+    }
+
+    /// Re-check the trash contents and re-render the Trash icon if it changed
+    fn refresh_trash_icon(&mut self) {
+        let trash_dir = match self.trash_dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+        if let Some(icon) = self.icons.get_mut(trash_dir) {
+            if icon.refresh_trash_state() {
+                debug!("Trash is now {}", if icon.is_trash_full() { "full" } else { "empty" });
+                self.dirty_icons.insert(trash_dir.clone());
+            }
+        }
+    }
+
+    /// Add icons for new volumes, drop unmounted ones and refresh the rest
+    fn sync_volume_icons(&mut self) {
+        let mounted: HashSet<PathBuf> = if self.config.show_volumes {
+            mounts::volumes(&mounts::mount_points()).into_iter().collect()
+        } else {
+            HashSet::new()
+        };
+
+        let unmounted: Vec<PathBuf> = self.volumes.difference(&mounted).cloned().collect();
+        for path in unmounted {
+            debug!("Volume {} is gone", path.display());
+            self.remove_icon_animated(&path);
+            self.volumes.remove(&path);
+            self.needs_render = true;
+        }
+
+        for path in mounted {
+            if self.volumes.contains(&path) {
+                if self.icons.get_mut(&path).is_some_and(|icon| icon.refresh_volume()) {
+                    self.mark_dirty(&path);
+                }
+                continue;
+            }
+
+            match DesktopIcon::new_volume(&path, &self.config) {
+                Ok(icon) => {
+                    debug!("Showing volume {}", path.display());
+                    self.insert_icon(icon);
+                    self.volumes.insert(path);
+                    self.needs_render = true;
+                }
+                Err(e) => warn!("Failed to create icon for volume {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Spawn the icon's script, create its surface and start tracking it
+    fn insert_icon(&mut self, mut icon: DesktopIcon) {
+        let path = icon.path().to_path_buf();
+
+        // Remember which filesystem the file is on, to recognise it being unmounted
+        if self.config.unavailable_files == UnavailableFiles::Stale {
+            let target = path.canonicalize().unwrap_or_else(|_| path.clone());
+            icon.set_mount_point(mounts::mount_point_of(&target, &mounts::mount_points()));
+        }
 
         // Try to spawn a Lua process for this icon
-        if let Some((handler_path, widget_script_path)) = self.find_script_for_icon(&icon) {
+        icon.set_lua_pool(self.lua_pool.clone());
+        let scripts = self.find_script_for_icon(icon.icon_type());
+        if let Some((handler_path, widget_script_path)) = scripts {
             match icon.spawn_lua_process(&handler_path, &widget_script_path) {
                 Ok(()) => {
                     debug!(
@@ -171,28 +963,46 @@ impl IconDaemon {
                     // Icon will use fallback rendering
                 }
             }
+        } else {
+            icon.set_script_status(self.missing_script_status(icon.icon_type()));
         }
 
         // Calculate position for this icon
         // Use full height including label area for grid calculations
-        let surface_height = self.config.icon_size + LABEL_HEIGHT;
+        let surface_height = self.config.icon_size + self.label_height();
         let cell_width = self.config.icon_size + self.config.grid_spacing;
         let cell_height = surface_height + self.config.grid_spacing;
 
-        let icon_count = self.icons.len() as u32;
-        let icon_index = icon_count;
-        let position = icon.request_position(
-            self.screen_width,
-            self.screen_height,
+        // Pinned icons don't take a grid cell
+        let icon_count = self.icons.values().filter(|i| i.pinned_corner().is_none()).count() as u32;
+        let taken: HashSet<u32> = self
+            .icons
+            .values()
+            .filter(|i| i.pinned_corner().is_none())
+            .map(|i| i.layout_index())
+            .collect();
+        let keep_clear = self.keep_clear_slots();
+        let placed_slot = self.placed_slot(&icon, &keep_clear);
+        // A placed icon may land on an unplaced one, which then has to move on
+        let displaces = placed_slot.is_some_and(|slot| taken.contains(&slot));
+        let icon_index = placed_slot.unwrap_or_else(|| {
+            (icon_count..)
+                .map(|n| self.nth_slot(n, &keep_clear))
+                .find(|slot| !taken.contains(slot))
+                .unwrap_or(icon_count)
+        });
+        let position = place_icon(
+            &mut icon,
+            self.grid_area(),
             icon_count + 1,
             icon_index,
-            Some(cell_width),
-            Some(cell_height),
+            (cell_width, cell_height),
+            self.config.grid_corner,
         );
 
-        // Create Wayland surface for this icon with full height including label
-        if let Some(ref mut wayland) = self.wayland {
-            match wayland.create_surface(
+        // Create a surface for this icon with full height including label
+        if let Some(ref mut backend) = self.backend {
+            match backend.create_surface(
                 position.x,
                 position.y,
                 self.config.icon_size,
@@ -215,869 +1025,10017 @@ impl IconDaemon {
             }
         }
 
-        debug!("Added icon for: {}", path.display());
-        self.icons.insert(path.to_path_buf(), icon);
+        // Watch folders (and the trash) non-recursively so their contents stay current
+        if let (Some(watch_path), Some(watcher)) = (icon_watch_path(&icon), self.watcher.as_mut()) {
+            if let Err(e) = watcher.watch(&watch_path, RecursiveMode::NonRecursive) {
+                debug!("Cannot watch {}: {}", watch_path.display(), e);
+            }
+        }
 
-        Ok(())
+        // A file flashed while it was still being written flashes now
+        if self.pending_flashes.remove(&path).is_some() {
+            icon.start_flash(Instant::now(), self.flash_duration());
+        }
+
+        debug!("Added icon for: {}", path.display());
+        self.icons.insert(path.clone(), icon);
+        self.request_thumbnail(&path);
+
+        // A sorted icon usually belongs between others, which move along for
+        // it, and one past the grid joins the overflow pile in the last cell
+        let sorted = self.config.sort_order != SortOrder::Manual && placed_slot.is_none();
+        let (columns, rows) = self.grid_dimensions();
+        let piled = self.config.overflow_pile.enabled && icon_index >= columns * rows;
+        if displaces || ((sorted || piled) && !self.scanning) {
+            self.reposition_all_icons();
+        }
+        self.sync_empty_hint();
+        self.sync_duplicate_labels();
     }
 
     /// Find the IPC handler and appropriate widget script for an icon based on its type
     ///
-    /// Returns a tuple of (handler_path, widget_script_path) if both are found
-    fn find_script_for_icon(&self, icon: &DesktopIcon) -> Option<(PathBuf, PathBuf)> {
-        let script_name = match icon.icon_type() {
-            IconType::Folder => "folder.lua",
-            IconType::File => "file.lua",
-            IconType::Symlink => "symlink.lua",
-            IconType::Executable => "executable.lua",
-            IconType::Image => "image.lua",
-            IconType::Document => "document.lua",
-            IconType::Archive => "archive.lua",
-            IconType::Video => "video.lua",
-            IconType::Audio => "audio.lua",
-            IconType::Unknown => "file.lua",
-        };
-
-        // First, find the IPC handler script
-        let mut handler_path = None;
-        for dir in &self.config.script_dirs {
-            let path = dir.join("ipc_handler.lua");
-            if path.exists() {
-                handler_path = Some(path);
-                break;
-            }
-        }
+    /// Returns a tuple of (handler_path, widget_script_path) if both are found.
+    /// A missing handler disables every widget, so it is warned about once.
+    fn find_script_for_icon(&mut self, icon_type: IconType) -> Option<(PathBuf, PathBuf)> {
+        let script_name = widget_script_name(icon_type)?;
 
         // If no handler found, we can't spawn a Lua process
-        let handler_path = handler_path?;
-
-        // Search through script directories for the widget script
-        for dir in &self.config.script_dirs {
-            let script_path = dir.join(script_name);
-            if script_path.exists() {
-                return Some((handler_path.clone(), script_path));
+        let Some(handler_path) = self.find_ipc_handler() else {
+            if !self.warned_missing_handler {
+                self.warned_missing_handler = true;
+                warn!(
+                    "No {} found in script_dirs ({}); icons are drawn without Lua widgets",
+                    IPC_HANDLER_SCRIPT,
+                    display_dirs(&self.config.script_dirs)
+                );
             }
+            return None;
+        };
 
-            // Also check in widgets subdirectory
-            let widgets_path = dir.join("widgets").join(script_name);
-            if widgets_path.exists() {
-                return Some((handler_path.clone(), widgets_path));
-            }
+        let script_path = self.find_widget_script(script_name)?;
+        Some((handler_path, script_path))
+    }
+
+    /// Why an icon of `icon_type` has no widget when `find_script_for_icon` found none
+    ///
+    /// Types that never get a widget are Ok.
+    fn missing_script_status(&self, icon_type: IconType) -> WidgetStatus {
+        let Some(script_name) = widget_script_name(icon_type) else {
+            return WidgetStatus::Ok;
+        };
+        if self.find_ipc_handler().is_none() {
+            WidgetStatus::NoHandler
+        } else if self.find_widget_script(script_name).is_none() {
+            WidgetStatus::NoWidget
+        } else {
+            WidgetStatus::Ok
         }
+    }
 
-        // No matching widget script found
-        None
+    /// Widget status of every icon, sorted by path
+    pub fn icon_statuses(&self) -> Vec<(PathBuf, WidgetStatus)> {
+        let mut statuses: Vec<(PathBuf, WidgetStatus)> =
+            self.icons.iter().map(|(path, icon)| (path.clone(), icon.widget_status())).collect();
+        statuses.sort_by(|(a, _), (b, _)| a.cmp(b));
+        statuses
     }
 
-    /// Remove an icon
-    fn remove_icon(&mut self, path: &Path) {
-        if let Some(mut icon) = self.icons.remove(path) {
-            // Kill the Lua process before removing the icon
-            icon.kill_lua_process();
+    /// The first IPC handler script in `script_dirs`
+    fn find_ipc_handler(&self) -> Option<PathBuf> {
+        self.config
+            .script_dirs
+            .iter()
+            .map(|dir| dir.join(IPC_HANDLER_SCRIPT))
+            .find(|path| path.exists())
+    }
 
-            // Destroy the Wayland surface
-            if let Some(surface_id) = self.path_to_surface.remove(path) {
-                if let Some(ref mut wayland) = self.wayland {
-                    wayland.destroy_surface(surface_id);
-                    debug!("Destroyed surface {} for icon: {}", surface_id, path.display());
-                }
-                self.surface_to_path.remove(&surface_id);
-            }
+    /// The first widget script named `script_name` in `script_dirs`, or in a
+    /// `widgets` subdirectory of one
+    fn find_widget_script(&self, script_name: &str) -> Option<PathBuf> {
+        self.config.script_dirs.iter().find_map(|dir| {
+            [dir.join(script_name), dir.join("widgets").join(script_name)]
+                .into_iter()
+                .find(|path| path.exists())
+        })
+    }
 
-            debug!("Removed icon for: {}", path.display());
-        }
+    /// Which handler and widget scripts resolve, answered to `lua_status`
+    ///
+    /// Lists the searched directories and, for each icon type that can have a
+    /// widget, the script it looks for and where it was found (null if not).
+    /// Icons whose widget isn't running are listed under `problems`.
+    pub fn lua_status(&self) -> serde_json::Value {
+        let handler = self.find_ipc_handler();
+        let types: serde_json::Map<String, serde_json::Value> = WIDGET_ICON_TYPES
+            .iter()
+            .filter_map(|&icon_type| {
+                let script = widget_script_name(icon_type)?;
+                let status = serde_json::json!({
+                    "script": script,
+                    "path": self.find_widget_script(script),
+                });
+                Some((format!("{:?}", icon_type).to_lowercase(), status))
+            })
+            .collect();
+        let widgets = self.icons.values().filter(|icon| icon.has_lua_process()).count();
+        let problems: Vec<serde_json::Value> = self
+            .icon_statuses()
+            .into_iter()
+            .filter(|(_, status)| *status != WidgetStatus::Ok)
+            .map(|(path, status)| serde_json::json!({ "path": path, "status": status }))
+            .collect();
+
+        serde_json::json!({
+            "script_dirs": self.config.script_dirs,
+            "handler": handler,
+            "types": types,
+            "icons_with_widgets": widgets,
+            "problems": problems,
+        })
     }
 
-    /// Handle a file system event
-    fn handle_fs_event(&mut self, event: Event) -> Result<()> {
-        use notify::EventKind;
+    /// Replace the configuration, re-filtering icons if the patterns changed
+    ///
+    /// Icons that are now ignored are removed and newly allowed entries are added.
+    /// The Trash icon is shown, hidden or re-pinned to follow the new settings.
+    /// On error nothing has been applied yet.
+    #[allow(dead_code)]
+    pub fn apply_config(&mut self, mut config: Config) -> Result<()> {
+        let patterns_changed = config.ignore_patterns != self.config.ignore_patterns
+            || config.allow_patterns != self.config.allow_patterns
+            || config.show_hidden != self.config.show_hidden
+            || config.show_special_files != self.config.show_special_files;
+        // Everything that can fail happens before the new config is committed,
+        // so an error leaves the daemon as it was
+        let listing = if patterns_changed { self.list_desktop()? } else { None };
+        let trash_moved = config.trash_corner != self.config.trash_corner;
+        let flow_changed = config.grid_flow != self.config.grid_flow
+            || config.grid_corner != self.config.grid_corner
+            || config.sort_order != self.config.sort_order
+            || config.sort_folders_first != self.config.sort_folders_first
+            || config.max_rows != self.config.max_rows
+            || config.overflow_pile != self.config.overflow_pile
+            || config.keep_clear_regions != self.config.keep_clear_regions
+            || config.snap_to_grid != self.config.snap_to_grid
+            || config.grid_spacing != self.config.grid_spacing
+            || config.reserved_edges != self.config.reserved_edges
+            || config.output_reserved_edges != self.config.output_reserved_edges
+            || config.span_outputs != self.config.span_outputs;
+        let hint_changed = config.empty_hint_text != self.config.empty_hint_text;
+        let anchor_changed = config.grid_anchor != self.config.grid_anchor;
+        let vcs_changed = config.vcs_emblems != self.config.vcs_emblems;
+        let primary_changed = config.primary_output != self.config.primary_output;
+        let binding_changed = config.surface_output_binding != self.config.surface_output_binding;
+        let opacity_changed = config.surface_opacity != self.config.surface_opacity;
+        let backdrop_changed = config.rubber_band_select != self.config.rubber_band_select;
+        let thumbnails_changed = config.thumbnails != self.config.thumbnails
+            || config.thumbnail_cache_dir != self.config.thumbnail_cache_dir;
+        let pool_changed = config.lua_pool_size != self.config.lua_pool_size
+            || config.script_dirs != self.config.script_dirs
+            || config.sandbox != self.config.sandbox;
+        // Warn again if the handler is still missing from the new directories
+        if config.script_dirs != self.config.script_dirs {
+            self.warned_missing_handler = false;
+        }
+        let style_changed = config.label_align != self.config.label_align
+            || config.label_width != self.config.label_width
+            || config.icon_elevation != self.config.icon_elevation
+            || config.colors != self.config.colors
+            || config.label_pill != self.config.label_pill
+            || config.icon_theme_dir != self.config.icon_theme_dir
+            || config.font_size != self.config.font_size
+            || config.label_lines != self.config.label_lines
+            || config.widget_status_badges != self.config.widget_status_badges;
+        let label_resized =
+            label_height(config.font_size, config.label_lines) != self.label_height();
+        self.settings_source = desktop_icon_size_source(&mut config);
+        // Icons left suspended are caught up with on the next poll
+        if config.suspend_when_locked != self.config.suspend_when_locked {
+            self.session_lock = session_lock_state(&config);
+        }
+        let icon_size = config.icon_size;
+        // Keep the old size until set_icon_size has replaced the surfaces
+        self.config = Config { icon_size: self.config.icon_size, ..config };
+        self.set_icon_size(icon_size);
+        self.render_cache = create_render_cache(&self.config);
+        if thumbnails_changed {
+            self.thumbnailer = create_thumbnailer(&self.config);
+            for icon in self.icons.values_mut().filter(|icon| icon.thumbnail().is_some()) {
+                icon.set_thumbnail(None);
+            }
+            self.request_thumbnails();
+            self.needs_render = true;
+        }
+        self.fs_debouncer.set_window(Duration::from_millis(self.config.fs_coalesce_ms));
+        // Running widgets stay on the old workers until they are restarted
+        if pool_changed {
+            self.lua_pool = create_lua_pool(&self.config);
+            for icon in self.icons.values_mut() {
+                icon.set_lua_pool(self.lua_pool.clone());
+            }
+        }
+        if vcs_changed {
+            self.emblem_providers = emblem_providers(&self.config);
+            self.emblems_stale = true;
+        }
+        if style_changed {
+            self.renderer = create_renderer(&self.config);
+            // Cached glyph layers carry the old state colors
+            for icon in self.icons.values_mut() {
+                icon.invalidate_render();
+            }
+            self.needs_render = true;
+        }
+        // Surfaces are created at a fixed height, so a taller or shorter
+        // label band needs new ones, as a new icon size does
+        if label_resized {
+            self.drop_icon_surfaces();
+            self.reposition_all_icons();
+            self.mark_all_dirty();
+        }
 
-        match event.kind {
-            EventKind::Create(_) => {
-                for path in event.paths {
-                    self.add_icon(&path)?;
-                }
-                self.needs_render = true;
+        // The new work area (or its absence) is picked up on the next step
+        if let (true, Some(backend)) = (anchor_changed, self.backend.as_mut()) {
+            backend.set_work_area_anchoring(self.config.grid_anchor == GridAnchor::WorkArea);
+        }
+        // As is the new output, if icons move to one
+        if let (true, Some(backend)) = (primary_changed, self.backend.as_mut()) {
+            backend.set_primary_output(self.config.primary_output.clone());
+        }
+        // Surfaces keep the output they were created on, so rebind by recreating them
+        if let (true, Some(backend)) = (binding_changed, self.backend.as_mut()) {
+            backend.set_surface_output_binding(self.config.surface_output_binding);
+            self.recreate_surfaces();
+        }
+        if backdrop_changed {
+            if self.config.rubber_band_select {
+                // Only a backdrop made before the icons' surfaces is beneath them
+                self.recreate_surfaces();
+            } else {
+                self.cancel_rubber_band();
+                self.sync_backdrop();
             }
-            EventKind::Remove(_) => {
-                for path in event.paths {
-                    self.remove_icon(&path);
-                }
-                self.needs_render = true;
+        }
+        if let Some(ref mut backend) = self.backend {
+            backend.set_input_queue_limit(self.config.input_queue_limit);
+            // Surfaces are re-anchored as the icons are repositioned
+            backend.set_anchor_corner(self.config.grid_corner);
+        }
+        // The backend applies the opacity as buffers are attached, so upload every icon again
+        if let (true, Some(backend)) = (opacity_changed, self.backend.as_mut()) {
+            backend.set_surface_opacity(self.config.surface_opacity);
+            for icon in self.icons.values_mut() {
+                icon.clear_presented_frame();
             }
-            EventKind::Modify(_) => {
-                // Refresh icons if metadata changed
-                for path in event.paths {
-                    if self.icons.contains_key(&path) {
-                        self.remove_icon(&path);
-                        self.add_icon(&path)?;
-                    }
-                }
-                self.needs_render = true;
+            self.needs_render = true;
+        }
+
+        if patterns_changed {
+            self.name_filter = self.config.name_filter();
+            self.refilter_icons(listing);
+        }
+
+        if trash_moved {
+            if let Some(icon) = self.trash_dir.as_ref().and_then(|dir| self.icons.get_mut(dir)) {
+                icon.set_pinned_corner(self.config.trash_corner);
             }
-            _ => {}
+        }
+        if trash_moved || flow_changed {
+            self.reposition_all_icons();
+        }
+        self.sync_trash_icon();
+        self.sync_volume_icons();
+        self.sync_empty_hint();
+        self.sync_duplicate_labels();
+        if hint_changed {
+            self.needs_render = true;
         }
 
         Ok(())
     }
 
-    /// Update all icons
-    fn update_icons(&mut self) {
-        // Collect paths of icons to remove (file no longer exists)
-        let mut to_remove = Vec::new();
+    /// Height of the label band below each icon, in surface pixels
+    fn label_height(&self) -> u32 {
+        label_height(self.config.font_size, self.config.label_lines)
+    }
 
-        for (path, icon) in self.icons.iter_mut() {
-            if let Err(e) = icon.update() {
-                warn!("Error updating icon: {}", e);
-                to_remove.push(path.clone());
-            }
+    /// Switch to a new icon size, re-rendering and re-laying out every icon
+    ///
+    /// Surfaces are created at a fixed size, so each icon gets a new one.
+    pub fn set_icon_size(&mut self, size: u32) {
+        let size = safe_icon_size(size);
+        if size == self.config.icon_size {
+            return;
         }
+        info!("Icon size changed from {} to {}", self.config.icon_size, size);
 
-        // Remove icons for deleted files
-        for path in to_remove {
-            self.remove_icon(&path);
+        self.config.icon_size = size;
+        self.renderer = create_renderer(&self.config);
+        self.drop_icon_surfaces();
+
+        for icon in self.icons.values_mut() {
+            icon.set_size(size);
         }
+        self.request_thumbnails();
+        self.reposition_all_icons();
+        self.mark_all_dirty();
     }
 
-    /// Render all icons to their Wayland surfaces
-    fn render_icons_to_surfaces(&mut self) {
-        // Only render if we have a Wayland connection and something needs rendering
-        if self.wayland.is_none() || !self.needs_render {
+    /// Ask for a thumbnail of the icon at `path`, if it is an image and thumbnails are on
+    fn request_thumbnail(&mut self, path: &Path) {
+        let Some(ref mut thumbnailer) = self.thumbnailer else {
             return;
+        };
+        if self.icons.get(path).is_some_and(|icon| icon.icon_type() == IconType::Image) {
+            let size = self.config.icon_size.saturating_sub(THUMBNAIL_MARGIN * 2).max(1);
+            thumbnailer.request(path, size);
         }
+    }
 
-        let icon_size = self.config.icon_size;
-        let surface_height = icon_size + LABEL_HEIGHT;
-
-        // Collect paths to render (to avoid borrowing conflicts)
+    /// Ask for thumbnails of every image icon
+    fn request_thumbnails(&mut self) {
         let paths: Vec<PathBuf> = self.icons.keys().cloned().collect();
-
         for path in paths {
-            // Get the surface ID for this icon
-            let surface_id = match self.path_to_surface.get(&path) {
-                Some(&id) => id,
-                None => continue,
-            };
+            self.request_thumbnail(&path);
+        }
+    }
 
-            // Get render commands from the icon (use full height including label)
-            let commands = if let Some(icon) = self.icons.get_mut(&path) {
-                icon.request_render(icon_size, surface_height, 1.0)
-            } else {
-                continue;
-            };
+    /// Show the thumbnails the worker has finished
+    ///
+    /// A file that could not be decoded keeps its generic glyph.
+    fn collect_thumbnails(&mut self) {
+        let Some(ref mut thumbnailer) = self.thumbnailer else {
+            return;
+        };
+        for finished in thumbnailer.finished() {
+            if let Some(icon) = self.icons.get_mut(&finished.path) {
+                icon.set_thumbnail(finished.thumbnail);
+                self.dirty_icons.insert(finished.path);
+            }
+        }
+    }
 
-            // Create pixmap and render commands (use full height including label)
-            if let Some(mut pixmap) = tiny_skia::Pixmap::new(icon_size, surface_height) {
-                // Execute draw commands
-                if let Err(e) = self.renderer.execute_commands(&mut pixmap, &commands) {
-                    warn!("Failed to execute draw commands for {}: {}", path.display(), e);
-                    continue;
-                }
+    /// Destroy every icon's surface; `reposition_all_icons` creates new ones
+    fn drop_icon_surfaces(&mut self) {
+        for (surface_id, _) in self.surface_to_path.drain() {
+            if let Some(ref mut backend) = self.backend {
+                backend.destroy_surface(surface_id);
+            }
+        }
+        self.path_to_surface.clear();
+        self.icon_outputs.clear();
+        self.awaiting_configure.clear();
+        self.pointer_press = None;
+    }
 
-                // Get pixel data
-                let pixels = pixmap.data();
+    /// Give every icon and the empty-desktop hint a new surface
+    ///
+    /// Layer surfaces stay on the output they were created on, so moving to
+    /// another output means recreating them; the backend puts the new ones on
+    /// its current primary output. Hover overlays are closed and the layout
+    /// grid is redrawn on the next frame.
+    fn recreate_surfaces(&mut self) {
+        self.close_context_menu();
+        self.close_spotlight();
+        self.collapse_label();
+        self.close_tooltip();
+        self.cancel_rubber_band();
+        self.drop_icon_surfaces();
+        if let Some(ref mut backend) = self.backend {
+            let overlays = self.empty_hint.take().into_iter().chain(self.debug_grid.take());
+            for surface_id in overlays.chain(self.backdrop.take()) {
+                backend.destroy_surface(surface_id);
+            }
+        }
+        // New surfaces start without a buffer, so every icon is drawn in full
+        for icon in self.icons.values_mut() {
+            icon.clear_presented_frame();
+        }
+        self.sync_backdrop();
+        self.sync_empty_hint();
+        self.reposition_all_icons();
+        self.needs_render = true;
+    }
 
-                // Attach buffer to surface
-                if let Some(ref mut wayland) = self.wayland {
-                    if let Err(e) = wayland.attach_buffer(
-                        surface_id,
-                        pixels,
-                        icon_size,
-                        surface_height,
-                    ) {
-                        warn!("Failed to attach buffer to surface {}: {}", surface_id, e);
-                    }
+    /// Pick up icon size changes made in the desktop environment's settings
+    fn poll_desktop_settings(&mut self, now: Instant) {
+        if now.duration_since(self.last_settings_poll) < SETTINGS_POLL_INTERVAL {
+            return;
+        }
+        self.last_settings_poll = now;
+
+        if let Some(size) = self.settings_source.as_ref().and_then(|source| source.icon_size()) {
+            self.set_icon_size(size);
+        }
+    }
+
+    /// Re-evaluate existing icons against the name filter and `show_special_files`
+    ///
+    /// `listing` is the desktop's entries, read beforehand so that nothing
+    /// here can fail halfway through applying a configuration.
+    fn refilter_icons(&mut self, listing: Option<Vec<PathBuf>>) {
+        let ignored: Vec<PathBuf> = self
+            .icons
+            .iter()
+            .filter(|(path, icon)| {
+                !matches!(icon.icon_type(), IconType::Trash | IconType::Volume)
+                    && (!self.name_filter.is_path_visible(path)
+                        || (icon.icon_type().is_special() && !self.config.show_special_files))
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in ignored {
+            debug!("Removing now-ignored icon: {}", path.display());
+            self.remove_icon(&path);
+        }
+
+        // Pick up entries that were previously filtered out
+        if let Some(paths) = listing {
+            self.add_listed(paths);
+        }
+        self.needs_render = true;
+    }
+
+    /// Remove an icon
+    fn remove_icon(&mut self, path: &Path) {
+        if self.context_menu.as_ref().is_some_and(|menu| menu.path == path) {
+            self.close_context_menu();
+        }
+        if self.tooltip.as_ref().is_some_and(|tooltip| tooltip.path == path) {
+            self.close_tooltip();
+        }
+        if let Some(mut icon) = self.icons.remove(path) {
+            // Kill the Lua process before removing the icon
+            icon.kill_lua_process();
+
+            if let (Some(watch_path), Some(watcher)) = (icon_watch_path(&icon), self.watcher.as_mut()) {
+                // The folder may already be gone, which also drops the watch
+                let _ = watcher.unwatch(&watch_path);
+            }
+
+            // Destroy the icon's surface
+            if let Some(surface_id) = self.path_to_surface.remove(path) {
+                self.awaiting_configure.remove(&surface_id);
+                if let Some(ref mut backend) = self.backend {
+                    backend.destroy_surface(surface_id);
+                    debug!("Destroyed surface {} for icon: {}", surface_id, path.display());
                 }
+                self.surface_to_path.remove(&surface_id);
+            }
+            self.icon_outputs.remove(path);
+            self.dirty_icons.remove(path);
+            if let Some(ref mut thumbnailer) = self.thumbnailer {
+                thumbnailer.forget(path);
+            }
+
+            if self.renaming.as_deref() == Some(path) {
+                self.renaming = None;
+            }
+            if self.keyboard_focus.as_deref() == Some(path) {
+                self.keyboard_focus = None;
+            }
+            if self.pointer_press.as_ref().is_some_and(|press| press.path == path) {
+                self.pointer_press = None;
+            }
+            // A file deleted mid-drag leaves nothing to drop
+            let in_drag = |drag: &DragState| drag.icons.iter().any(|icon| icon.path == path);
+            if self.drag.as_ref().is_some_and(in_drag) {
+                self.cancel_drag();
+            }
+            self.attach_failures.remove(path);
+            self.unrenderable_sizes.remove(path);
+            self.volumes.remove(path);
+
+            debug!("Removed icon for: {}", path.display());
+            self.sync_empty_hint();
+            self.sync_duplicate_labels();
+
+            // Sorted icons close up the gap, and a pile loses a member
+            if self.config.sort_order != SortOrder::Manual || self.pile.iter().any(|member| member == path) {
+                self.reposition_all_icons();
             }
         }
+    }
 
-        // Clear the dirty flag after rendering
-        self.needs_render = false;
+    /// Label icons whose name another icon has too with where they are from
+    ///
+    /// Names are compared across all icons, so a file and a volume of the
+    /// same name are both told apart. Icons with a name of their own get
+    /// their plain name back.
+    fn sync_duplicate_labels(&mut self) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for icon in self.icons.values() {
+            *counts.entry(icon.name()).or_default() += 1;
+        }
+        let labels: Vec<(PathBuf, Option<String>)> = self
+            .icons
+            .iter()
+            .map(|(path, icon)| {
+                let label = match counts[icon.name()] {
+                    1 => None,
+                    _ => duplicate_label(icon.name(), path, self.config.duplicate_labels),
+                };
+                (path.clone(), label)
+            })
+            .collect();
+        for (path, label) in labels {
+            if self.icons.get_mut(&path).is_some_and(|icon| icon.set_label(label)) {
+                self.needs_render = true;
+            }
+        }
     }
 
-    /// Handle Wayland input events
-    fn handle_wayland_input(&mut self) {
-        // Only process if we have a Wayland connection
-        let events = if let Some(ref mut wayland) = self.wayland {
-            wayland.take_input_events()
-        } else {
+    /// Show the empty-desktop hint while there are no icons, and hide it otherwise
+    fn sync_empty_hint(&mut self) {
+        let wanted = self.config.show_empty_hint && self.icons.is_empty();
+        let (x, y) = self.empty_hint_position();
+        let Some(ref mut backend) = self.backend else {
             return;
         };
 
-        for event in events {
-            match event {
-                InputEvent::PointerEnter { surface_id, .. } => {
-                    // Set hovered state on the icon
-                    if let Some(path) = self.surface_to_path.get(&surface_id) {
-                        if let Some(icon) = self.icons.get_mut(path) {
-                            icon.set_hovered(true);
-                            self.needs_render = true;
-                            debug!("Pointer entered icon: {}", path.display());
-                        }
-                    }
-                }
-                InputEvent::PointerLeave { surface_id } => {
-                    // Clear hovered state
-                    if let Some(path) = self.surface_to_path.get(&surface_id) {
-                        if let Some(icon) = self.icons.get_mut(path) {
-                            icon.set_hovered(false);
-                            self.needs_render = true;
-                            debug!("Pointer left icon: {}", path.display());
-                        }
-                    }
-                }
-                InputEvent::PointerMotion { surface_id, x, y } => {
-                    // Could track position for hover effects
-                    debug!("Pointer motion on surface {} at ({}, {})", surface_id, x, y);
-                }
-                InputEvent::PointerButton { surface_id, button, pressed, .. } => {
-                    if pressed {
-                        // Button pressed - handle click
-                        if let Some(path) = self.surface_to_path.get(&surface_id).cloned() {
-                            if let Some(icon) = self.icons.get_mut(&path) {
-                                // Linux mouse button codes: 272 = left, 273 = right, 274 = middle
-                                let button_num = match button {
-                                    272 => 1, // Left button
-                                    273 => 3, // Right button
-                                    274 => 2, // Middle button
-                                    _ => button,
-                                };
-                                match icon.on_click(button_num) {
-                                    Ok(action) => {
-                                        self.needs_render = true;
-                                        debug!(
-                                            "Click on icon {} button {}: {:?}",
-                                            path.display(),
-                                            button_num,
-                                            action
-                                        );
-                                    }
-                                    Err(e) => {
-                                        warn!("Error handling click on {}: {}", path.display(), e);
-                                    }
-                                }
-                            }
-                        }
-                    }
+        match self.empty_hint {
+            None if wanted => match backend.create_surface(x, y, EMPTY_HINT_WIDTH, EMPTY_HINT_HEIGHT) {
+                Ok(surface_id) => {
+                    debug!("Desktop is empty, showing hint on surface {}", surface_id);
+                    self.empty_hint = Some(surface_id);
+                    self.needs_render = true;
                 }
+                Err(e) => warn!("Failed to create empty desktop hint surface: {}", e),
+            },
+            Some(surface_id) if !wanted => {
+                backend.destroy_surface(surface_id);
+                self.awaiting_configure.remove(&surface_id);
+                self.empty_hint = None;
             }
+            _ => {}
         }
     }
 
-    /// Dispatch Wayland events
-    fn dispatch_wayland(&mut self) {
-        if let Some(ref mut wayland) = self.wayland {
-            if let Err(e) = wayland.dispatch_events() {
-                error!("Wayland dispatch error: {}", e);
+    /// Output position that centers the empty-desktop hint in the grid area
+    fn empty_hint_position(&self) -> (i32, i32) {
+        let area = self.grid_area();
+        (
+            area.x + (area.width as i32 - EMPTY_HINT_WIDTH as i32) / 2,
+            area.y + (area.height as i32 - EMPTY_HINT_HEIGHT as i32) / 2,
+        )
+    }
+
+    /// Draw the empty-desktop hint, if it is shown
+    fn render_empty_hint(&mut self) {
+        let (Some(surface_id), Some(backend)) = (self.empty_hint, self.backend.as_mut()) else {
+            return;
+        };
+        let Some(mut pixmap) = tiny_skia::Pixmap::new(EMPTY_HINT_WIDTH, EMPTY_HINT_HEIGHT) else {
+            return;
+        };
+
+        let color = tiny_skia::Color::from_rgba(1.0, 1.0, 1.0, EMPTY_HINT_OPACITY)
+            .unwrap_or(tiny_skia::Color::WHITE);
+        // The baseline sits below the middle so the text itself is centered
+        self.renderer.render_text(
+            &mut pixmap,
+            &self.config.empty_hint_text,
+            EMPTY_HINT_WIDTH as f32 / 2.0,
+            (EMPTY_HINT_HEIGHT as f32 + EMPTY_HINT_FONT_SIZE * 0.7) / 2.0,
+            EMPTY_HINT_FONT_SIZE,
+            color,
+            TextAlign::Center,
+        );
+
+        match backend.attach_buffer(surface_id, pixmap.data(), EMPTY_HINT_WIDTH, EMPTY_HINT_HEIGHT) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(surface_id);
             }
+            Err(e) => warn!("Failed to attach empty desktop hint buffer: {}", e),
         }
     }
 
-    /// Check if Wayland manager wants to exit
-    fn wayland_should_exit(&self) -> bool {
-        if let Some(ref wayland) = self.wayland {
-            wayland.should_exit()
-        } else {
-            false
+    /// Move the icon for `from` to `to` after a rename, keeping its surface
+    ///
+    /// Only the label changes, so the glyph is not rendered again and damage
+    /// tracking uploads just the label region. Returns false if the icon has
+    /// to be recreated instead: when there is none, when the new name changes
+    /// the file's type, or when the new name is filtered out.
+    fn rename_icon(&mut self, from: &Path, to: &Path) -> bool {
+        if self.icons.contains_key(to) || !self.name_filter.is_path_visible(to) {
+            return false;
+        }
+        let Some(icon) = self.icons.get_mut(from) else {
+            return false;
+        };
+        let old_watch = icon_watch_path(icon);
+        if !icon.rename(to) {
+            return false;
+        }
+        let new_watch = icon_watch_path(icon);
+        if let Some(icon) = self.icons.remove(from) {
+            self.icons.insert(to.to_path_buf(), icon);
+        }
+
+        if let Some(surface_id) = self.path_to_surface.remove(from) {
+            self.path_to_surface.insert(to.to_path_buf(), surface_id);
+            self.surface_to_path.insert(surface_id, to.to_path_buf());
+        }
+        if let Some(output) = self.icon_outputs.remove(from) {
+            self.icon_outputs.insert(to.to_path_buf(), output);
         }
+        if let Some(started) = self.appearing.remove(from) {
+            self.appearing.insert(to.to_path_buf(), started);
+        }
+        if let Some(menu) = self.context_menu.as_mut().filter(|menu| menu.path == from) {
+            menu.path = to.to_path_buf();
+        }
+        // Downloads are often written under a temporary name and renamed when done
+        if self.pending_flashes.remove(to).is_some() {
+            let duration = self.flash_duration();
+            if let Some(icon) = self.icons.get_mut(to) {
+                icon.start_flash(Instant::now(), duration);
+            }
+        }
+        if let Some(failures) = self.attach_failures.remove(from) {
+            self.attach_failures.insert(to.to_path_buf(), failures);
+        }
+        if self.unrenderable_sizes.remove(from) {
+            self.unrenderable_sizes.insert(to.to_path_buf());
+        }
+        if self.dirty_icons.remove(from) {
+            self.dirty_icons.insert(to.to_path_buf());
+        }
+        // A thumbnail still being made was asked for under the old name
+        if self.icons.get(to).is_some_and(|icon| icon.thumbnail().is_none()) {
+            self.request_thumbnail(to);
+        }
+        // The new name may sort elsewhere
+        if self.config.sort_order != SortOrder::Manual {
+            self.reposition_all_icons();
+        }
+        let moved = [&mut self.renaming, &mut self.selection_anchor, &mut self.keyboard_focus];
+        for path in moved.into_iter().flatten() {
+            if *path == *from {
+                *path = to.to_path_buf();
+            }
+        }
+        if let Some(press) = self.pointer_press.as_mut().filter(|press| press.path == from) {
+            press.path = to.to_path_buf();
+        }
+        let dragged = self.drag.iter_mut().flat_map(|drag| drag.icons.iter_mut());
+        for icon in dragged.filter(|icon| icon.path == from) {
+            icon.path = to.to_path_buf();
+        }
+
+        // Keep a placed icon in its cell, and a pinned one on its output
+        let cell = self.placements.get(from);
+        let output = self.placements.output(from).map(str::to_string);
+        if cell.is_some() || output.is_some() {
+            self.placements.set(from, None);
+            self.placements.set(to, cell);
+            self.placements.set_output(from, None);
+            self.placements.set_output(to, output.as_deref());
+            if let Err(e) = self.placements.save() {
+                warn!("Failed to save icon placements: {:#}", e);
+            }
+        }
+
+        if let (Some(old_watch), Some(new_watch), Some(watcher)) = (old_watch, new_watch, self.watcher.as_mut()) {
+            let _ = watcher.unwatch(&old_watch);
+            if let Err(e) = watcher.watch(&new_watch, RecursiveMode::NonRecursive) {
+                debug!("Cannot watch {}: {}", new_watch.display(), e);
+            }
+        }
+
+        debug!("Renamed icon {} to {}", from.display(), to.display());
+        self.sync_duplicate_labels();
+        self.needs_render = true;
+        true
     }
 
-    /// Update screen dimensions from Wayland outputs and reposition icons if changed
-    fn update_screen_dimensions(&mut self) {
-        let (new_width, new_height) = if let Some(ref wayland) = self.wayland {
-            wayland.get_output_dimensions().unwrap_or((self.screen_width, self.screen_height))
-        } else {
+    /// Remove an icon, fading its surface out if animations are enabled and
+    /// motion isn't reduced
+    ///
+    /// The icon itself is gone immediately; only its last frame lingers on the
+    /// old surface until `tick_animations` destroys it.
+    fn remove_icon_animated(&mut self, path: &Path) {
+        self.appearing.remove(path);
+
+        let fading = self.config.animates() && self.config.animations.disappear_ms > 0;
+        let frame = match self.path_to_surface.get(path) {
+            Some(&surface_id) if fading && self.backend.is_some() => {
+                let icon_size = self.config.icon_size;
+                let (width, height) = self
+                    .backend
+                    .as_ref()
+                    .and_then(|backend| backend.surface_size(surface_id))
+                    .unwrap_or((icon_size, icon_size + self.label_height()));
+                self.icons
+                    .get_mut(path)
+                    .and_then(|icon| icon.take_presented_frame())
+                    .and_then(|pixels| {
+                        tiny_skia::Pixmap::from_vec(pixels, tiny_skia::IntSize::from_wh(width, height)?)
+                    })
+                    .map(|frame| (surface_id, frame))
+            }
+            _ => None,
+        };
+
+        let Some((surface_id, frame)) = frame else {
+            self.remove_icon(path);
             return;
         };
 
-        // Check if dimensions changed
-        if new_width != self.screen_width || new_height != self.screen_height {
-            info!(
-                "Screen dimensions changed from {}x{} to {}x{}",
-                self.screen_width, self.screen_height, new_width, new_height
-            );
-            self.screen_width = new_width;
-            self.screen_height = new_height;
+        // Detach the surface from the icon so remove_icon leaves it alive
+        self.path_to_surface.remove(path);
+        self.surface_to_path.remove(&surface_id);
+        self.awaiting_configure.remove(&surface_id);
+        self.remove_icon(path);
 
-            // Reposition all icons
-            self.reposition_all_icons();
+        self.disappearing.push(FadeOut { surface_id, frame, started: Instant::now() });
+        self.needs_render = true;
+    }
+
+    /// Finish animations that have run their course by `now`
+    ///
+    /// Fade-out surfaces are destroyed here on a timer rather than after their
+    /// last frame is drawn, so a stalled render can never leak them.
+    fn tick_animations(&mut self, now: Instant) {
+        let appear = Duration::from_millis(self.config.animations.appear_ms);
+        let disappear = Duration::from_millis(self.config.animations.disappear_ms);
+
+        let mut finished = Vec::new();
+        self.disappearing.retain(|fade| {
+            let done = now.saturating_duration_since(fade.started) >= disappear;
+            if done {
+                finished.push(fade.surface_id);
+            }
+            !done
+        });
+        for surface_id in finished {
+            if let Some(ref mut backend) = self.backend {
+                backend.destroy_surface(surface_id);
+            }
+            debug!("Destroyed faded-out surface {}", surface_id);
+        }
+
+        // Idle dimming changes every icon's frame while it fades
+        let woken = |(started, _): (Instant, f32)| now.saturating_duration_since(started) >= IDLE_DIM_FADE;
+        if self.idle_wake.is_some_and(woken) {
+            self.idle_wake = None;
+        }
+        if (self.idle_opacity(now) - self.rendered_idle_opacity).abs() > f32::EPSILON {
+            self.needs_render = true;
+        }
+
+        // The spotlight goes away once it has shrunk back to the icon's size,
+        // not when the pointer leaves: the zoom-out starts at the rendered zoom
+        if let Some(ref spotlight) = self.spotlight {
+            let duration = self.hover_zoom_duration();
+            let shrunk = spotlight.to <= 1.0 && now.saturating_duration_since(spotlight.started) >= duration;
+            if shrunk {
+                self.close_spotlight();
+            } else if (spotlight.zoom(now, duration) - spotlight.rendered_zoom).abs() > f32::EPSILON {
+                self.needs_render = true;
+            }
+        }
+
+        // Flashing icons change every frame and need one more once they stop
+        let mut flashing = false;
+        for icon in self.icons.values_mut() {
+            flashing |= icon.finish_flash(now) || icon.is_flashing();
+        }
+        if flashing {
+            self.needs_render = true;
+        }
+        self.pending_flashes
+            .retain(|_, asked| now.saturating_duration_since(*asked) < PENDING_FLASH_TIMEOUT);
+
+        // Finished icons still need their final, fully opaque frame drawn
+        let before = self.appearing.len();
+        self.appearing
+            .retain(|_, started| now.saturating_duration_since(*started) < appear);
+        if self.appearing.len() != before
+            || !self.appearing.is_empty()
+            || !self.disappearing.is_empty()
+        {
             self.needs_render = true;
         }
     }
 
-    /// Reposition all icon surfaces based on current screen dimensions
-    fn reposition_all_icons(&mut self) {
-        let surface_height = self.config.icon_size + LABEL_HEIGHT;
-        let cell_width = self.config.icon_size + self.config.grid_spacing;
-        let cell_height = surface_height + self.config.grid_spacing;
-        let icon_count = self.icons.len() as u32;
+    /// Handle a file system event
+    fn handle_fs_event(&mut self, mut event: Event) -> Result<()> {
+        self.emblems_stale = true;
+
+        // Nothing beside the configuration file matters in its directory. A
+        // removed file keeps the current configuration rather than the defaults
+        if let Some(config_file) = self.config_file() {
+            let edited = event.paths.contains(&config_file) && config_file.is_file();
+            let config_dir = config_file.parent();
+            if config_dir == Some(self.desktop_dir.as_path()) {
+                event.paths.retain(|path| *path != config_file);
+            } else {
+                event.paths.retain(|path| path.parent() != config_dir);
+            }
+            if edited {
+                if let Err(e) = self.reload() {
+                    warn!("{:#}", e);
+                }
+            }
+        }
 
-        // Collect (path, surface_id) pairs to reposition
-        let to_reposition: Vec<(PathBuf, SurfaceId)> = self.path_to_surface
-            .iter()
-            .map(|(p, &s)| (p.clone(), s))
-            .collect();
+        // Until the desktop directory exists only its parent is watched, and
+        // nothing there but the directory itself matters
+        if self.awaiting_desktop_dir {
+            let created = event.paths.contains(&self.desktop_dir) && self.desktop_dir.is_dir();
+            event.paths.retain(|path| path.parent() != self.desktop_dir.parent());
+            if created {
+                self.desktop_dir_created()?;
+            }
+        }
 
-        for (index, (path, surface_id)) in to_reposition.into_iter().enumerate() {
-            if let Some(icon) = self.icons.get_mut(&path) {
-                let position = icon.request_position(
-                    self.screen_width,
-                    self.screen_height,
-                    icon_count,
-                    index as u32,
-                    Some(cell_width),
-                    Some(cell_height),
-                );
+        // Changes in the trash only refresh the Trash icon
+        let trash_files = self.trash_dir.as_deref().map(trash::files_dir);
+        let before = event.paths.len();
+        event.paths.retain(|path| trash_files.is_none() || path.parent() != trash_files.as_deref());
+        if event.paths.len() != before {
+            self.refresh_trash_icon();
+        }
 
-                // Update surface position
-                if let Some(ref mut wayland) = self.wayland {
-                    wayland.set_surface_position(surface_id, position.x, position.y);
-                    debug!(
-                        "Repositioned icon {} to ({}, {})",
-                        path.display(),
-                        position.x,
-                        position.y
-                    );
+        // Changes inside watched folders only refresh that folder's peek (and
+        // its custom icon, if its .directory file changed)
+        event.paths.retain(|path| {
+            let folder = match path.parent().and_then(|p| self.icons.get_mut(p)) {
+                Some(icon) if icon.icon_type() == IconType::Folder => icon,
+                _ => return true,
+            };
+            if path.file_name().is_some_and(|name| name == folder_icon::DIRECTORY_FILE) {
+                folder.refresh_custom_icon();
+            }
+            folder.invalidate_folder_peek();
+            self.dirty_icons.insert(folder.path().to_path_buf());
+            false
+        });
+        if event.paths.is_empty() {
+            return Ok(());
+        }
+
+        match event.kind {
+            EventKind::Create(_) => {
+                let animate = self.config.animates() && self.config.animations.appear_ms > 0;
+                for path in event.paths {
+                    let existed = self.icons.contains_key(&path);
+                    self.add_icon(&path)?;
+                    if animate && !existed && self.icons.contains_key(&path) {
+                        self.appearing.insert(path.clone(), Instant::now());
+                    }
+                    self.mark_dirty(&path);
+                }
+            }
+            EventKind::Remove(_) => {
+                let keep_stale = self.config.unavailable_files == UnavailableFiles::Stale;
+                let mount_table = if keep_stale { mounts::mount_points() } else { Vec::new() };
+                for path in event.paths {
+                    // Files vanishing with their filesystem are handled by update_icons
+                    match self.icons.get_mut(&path) {
+                        Some(icon) if keep_stale && mount_gone(icon, &mount_table) => {
+                            icon.set_unavailable(true);
+                        }
+                        _ => self.remove_icon_animated(&path),
+                    }
+                }
+                self.needs_render = true;
+            }
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                // Renames reported as a pair were already applied by rename_icon;
+                // otherwise the old name is gone and the new one is a new icon
+                for path in event.paths {
+                    if path.exists() {
+                        self.add_icon(&path)?;
+                    } else if self.icons.contains_key(&path) {
+                        self.remove_icon_animated(&path);
+                    }
+                }
+                self.needs_render = true;
+            }
+            EventKind::Modify(ModifyKind::Any)
+                if self.config.ambiguous_modify == AmbiguousModify::Restat =>
+            {
+                // The event does not say what changed, so only recreate (and
+                // respawn the widget of) icons whose file really did
+                for path in event.paths {
+                    let changed = match self.icons.get(&path) {
+                        Some(icon) => icon.changed_on_disk(),
+                        None => continue,
+                    };
+                    if changed {
+                        self.remove_icon(&path);
+                        self.add_icon(&path)?;
+                    } else if let Some(icon) = self.icons.get_mut(&path) {
+                        // Tags and comments change without touching the mtime
+                        icon.refresh_notes();
+                        icon.invalidate_render();
+                    }
+                    self.mark_dirty(&path);
+                }
+            }
+            EventKind::Modify(_) => {
+                // Refresh icons if metadata changed
+                for path in event.paths {
+                    if self.icons.contains_key(&path) {
+                        self.remove_icon(&path);
+                        self.add_icon(&path)?;
+                        self.mark_dirty(&path);
+                    }
                 }
             }
+            _ => {}
         }
+
+        Ok(())
     }
 
-    /// Request render for all icons (called when display needs update)
-    ///
-    /// Returns a vector of (path, draw_commands) pairs
-    #[allow(dead_code)]
-    pub fn render_all_icons(
-        &mut self,
-        canvas_width: u32,
-        canvas_height: u32,
-        device_pixel_ratio: f32,
-    ) -> Vec<(PathBuf, Vec<crate::lua::DrawCommand>)> {
-        self.icons
-            .iter_mut()
-            .map(|(path, icon)| {
-                let commands = icon.request_render(canvas_width, canvas_height, device_pixel_ratio);
-                (path.clone(), commands)
-            })
-            .collect()
+    /// Redraw widgets whose refresh interval has passed at `now`
+    fn refresh_widgets(&mut self, now: Instant) {
+        for (path, icon) in self.icons.iter_mut() {
+            if icon.take_refresh_due(now) {
+                self.dirty_icons.insert(path.clone());
+            }
+        }
     }
 
-    /// Calculate positions for all icons
+    /// Redraw the icon for `path` on the next frame
+    fn mark_dirty(&mut self, path: &Path) {
+        if self.icons.contains_key(path) {
+            self.dirty_icons.insert(path.to_path_buf());
+        }
+    }
+
+    /// Redraw every icon on the next frame, for changes that affect them all
+    /// (their size, layout, colors or opacity)
+    fn mark_all_dirty(&mut self) {
+        self.needs_render = true;
+    }
+
+    /// Apply the debounced filesystem events that are due at `now`
+    fn flush_fs_events(&mut self, now: Instant) {
+        for event in self.fs_debouncer.drain_ready(now) {
+            if let Err(e) = self.handle_fs_event(event) {
+                error!("Error handling fs event: {}", e);
+            }
+        }
+    }
+
+    /// Update all icons, dropping those whose files are gone
     ///
-    /// Returns a vector of (path, position) pairs
-    #[allow(dead_code)]
-    pub fn position_all_icons(
-        &mut self,
-        screen_width: u32,
-        screen_height: u32,
-        cell_width: Option<u32>,
-        cell_height: Option<u32>,
-    ) -> Vec<(PathBuf, crate::ipc::Position)> {
-        let icon_count = self.icons.len() as u32;
+    /// With `unavailable_files = "stale"`, icons whose filesystem went away are
+    /// kept dimmed instead and restored once their files are reachable again.
+    /// Either only happens after `update_error_threshold` consecutive failed
+    /// checks, since a file can be briefly locked or its network filesystem
+    /// hiccup, and unmounting can make files look deleted before the mount
+    /// disappears from the mount table. Nothing is updated while paused or
+    /// while the session is locked.
+    pub fn update_icons(&mut self) {
+        if self.paused || self.locked {
+            return;
+        }
+        let now = Instant::now();
+        self.poll_desktop_settings(now);
 
-        self.icons
-            .iter_mut()
-            .enumerate()
-            .map(|(index, (path, icon))| {
-                let position = icon.request_position(
-                    screen_width,
-                    screen_height,
-                    icon_count,
-                    index as u32,
-                    cell_width,
-                    cell_height,
+        // Volumes change slowly and statvfs is not free, so they have their own cadence
+        if now.duration_since(self.last_volume_refresh) >= Duration::from_millis(self.config.volume_refresh_ms) {
+            self.last_volume_refresh = now;
+            self.sync_volume_icons();
+        }
+
+        // git status walks the whole checkout, so it runs on its own slow cadence
+        let emblems_due = now.duration_since(self.last_emblem_refresh)
+            >= Duration::from_millis(self.config.emblem_refresh_ms);
+        if self.emblems_stale || emblems_due {
+            self.refresh_emblems();
+        }
+
+        let keep_stale = self.config.unavailable_files == UnavailableFiles::Stale;
+        let threshold = self.config.update_error_threshold.max(1);
+        let mut mount_table = None;
+
+        // Collect paths of icons to remove (file no longer exists)
+        let mut to_remove = Vec::new();
+
+        for (path, icon) in self.icons.iter_mut() {
+            let failed_before = icon.failed_checks();
+            let state = icon.probe();
+            if state == FileState::Present {
+                if icon.is_unavailable() {
+                    info!("{} is available again", path.display());
+                    icon.set_unavailable(false);
+                    self.dirty_icons.insert(path.clone());
+                } else if failed_before > 0 {
+                    debug!("{} recovered after {} failed checks", path.display(), failed_before);
+                    self.dirty_icons.insert(path.clone());
+                }
+                // A crashed widget only costs the icon its widget, never the icon
+                if icon.check_widget() {
+                    self.dirty_icons.insert(path.clone());
+                }
+                if icon.poll_overlay() {
+                    self.dirty_icons.insert(path.clone());
+                }
+                continue;
+            }
+
+            if icon.is_unavailable() {
+                continue;
+            }
+            if icon.failed_checks() < threshold {
+                debug!(
+                    "Error updating icon {} ({} of {} failures): {:?}",
+                    path.display(),
+                    icon.failed_checks(),
+                    threshold,
+                    state
                 );
-                (path.clone(), position)
+                // Draw it in the error state from the first failure
+                if failed_before == 0 {
+                    self.dirty_icons.insert(path.clone());
+                }
+                continue;
+            }
+
+            if !keep_stale {
+                warn!("Error updating icon {}: {:?}", path.display(), state);
+                to_remove.push(path.clone());
+                continue;
+            }
+            let mount_table = mount_table.get_or_insert_with(mounts::mount_points);
+            if state == FileState::Unreachable || mount_gone(icon, mount_table) {
+                info!("{} is unavailable, keeping its icon", path.display());
+                icon.set_unavailable(true);
+                self.dirty_icons.insert(path.clone());
+            } else {
+                to_remove.push(path.clone());
+            }
+        }
+
+        // Remove icons for deleted files
+        for path in to_remove {
+            self.remove_icon_animated(&path);
+        }
+    }
+
+    /// Add a source of status emblems, after those enabled in the config
+    pub fn add_emblem_provider(&mut self, provider: Box<dyn EmblemProvider>) {
+        self.emblem_providers.push(provider);
+        self.emblems_stale = true;
+    }
+
+    /// Emblem currently drawn on the icon for `path`
+    pub fn emblem(&self, path: &Path) -> Option<Emblem> {
+        self.emblems.get(path).copied()
+    }
+
+    /// Re-read every provider and redraw icons whose emblem changed
+    ///
+    /// The first provider with an emblem for an icon wins.
+    fn refresh_emblems(&mut self) {
+        self.last_emblem_refresh = Instant::now();
+        self.emblems_stale = false;
+        if self.emblem_providers.is_empty() && self.emblems.is_empty() {
+            return;
+        }
+
+        let paths: Vec<PathBuf> = self.icons.keys().cloned().collect();
+        for provider in &mut self.emblem_providers {
+            provider.refresh(&self.desktop_dir, &paths);
+        }
+        let emblems: HashMap<PathBuf, Emblem> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let emblem = self.emblem_providers.iter().find_map(|provider| provider.emblem(&path))?;
+                Some((path, emblem))
             })
-            .collect()
+            .collect();
+
+        if emblems != self.emblems {
+            self.emblems = emblems;
+            self.needs_render = true;
+        }
+    }
+
+    /// Render the dirty icons to their surfaces
+    ///
+    /// Icons are taken off the dirty set as their frames are uploaded; those
+    /// that could not be drawn yet stay on it for the next pass.
+    fn render_icons_to_surfaces(&mut self) {
+        // Only render if we have a display backend and something needs rendering
+        if self.backend.is_none() || (!self.needs_render && self.dirty_icons.is_empty()) {
+            return;
+        }
+        // Keep the dirty state so the first frame goes out once the size is known
+        if !self.dimensions_known || self.covered {
+            return;
+        }
+        self.last_render = Instant::now();
+
+        let icon_size = self.config.icon_size;
+        let label_height = self.label_height();
+        let surface_height = icon_size + label_height;
+
+        let idle_opacity = self.idle_opacity(Instant::now());
+        self.rendered_idle_opacity = idle_opacity;
+
+        // Collect paths to render (to avoid borrowing conflicts)
+        let mut paths: Vec<PathBuf> = match std::mem::take(&mut self.needs_render) {
+            true => {
+                self.dirty_icons.clear();
+                self.icons.keys().cloned().collect()
+            }
+            false => self.dirty_icons.drain().collect(),
+        };
+        // The spotlight magnifies the hovered icon's frame, so it is always drawn
+        if let Some(spotlight) = &self.spotlight {
+            if !paths.contains(&spotlight.path) && self.icons.contains_key(&spotlight.path) {
+                paths.push(spotlight.path.clone());
+            }
+        }
+        // Icons to draw again on the next pass
+        let mut unfinished = Vec::new();
+        // Whether some icon failed to attach and should be retried next frame
+        let mut retry = false;
+        // Whether some icon's output was not ready for a frame yet
+        let mut deferred = false;
+        let mut drawn_groups = HashSet::new();
+        // Frame of the hovered icon, for the spotlight to magnify
+        let mut spotlight_frame = None;
+
+        for path in paths {
+            // Get the surface ID for this icon
+            let surface_id = match self.path_to_surface.get(&path) {
+                Some(&id) => id,
+                None => continue,
+            };
+
+            // Each output is paced by its own frame callbacks; icons on one
+            // still showing the last frame are drawn on a later pass. Once an
+            // output is drawn to, the rest of its icons join the same frame.
+            let group = self.backend.as_ref().map_or(0, |backend| backend.frame_group(surface_id));
+            if !drawn_groups.contains(&group) && !self.frame_group_ready(group) {
+                deferred = true;
+                unfinished.push(path);
+                continue;
+            }
+
+            // Render at the size the compositor configured, which may differ from ours
+            let (mut width, mut height) = self
+                .backend
+                .as_ref()
+                .and_then(|backend| backend.surface_size(surface_id))
+                .filter(|&(w, h)| w > 0 && h > 0)
+                .unwrap_or((icon_size, surface_height));
+            if width > MAX_SURFACE_SIDE || height > MAX_SURFACE_SIDE {
+                if self.unrenderable_sizes.insert(path.clone()) {
+                    warn!(
+                        "Cannot render {} at {}x{}, using {}x{} instead",
+                        path.display(),
+                        width,
+                        height,
+                        icon_size,
+                        surface_height
+                    );
+                }
+                (width, height) = (icon_size, surface_height);
+            }
+            let scale = self.surface_render_scale(surface_id, width, height);
+
+            let Some(pixmap) = self.icon_frame(&path, width, height, scale, idle_opacity) else {
+                continue;
+            };
+            if self.spotlight.as_ref().is_some_and(|spotlight| spotlight.path == path) {
+                spotlight_frame = Some((pixmap.clone(), scale));
+            }
+            let Some(icon) = self.icons.get_mut(&path) else {
+                continue;
+            };
+
+            // Get pixel data, in buffer pixels from here on
+            let pixels = pixmap.data();
+            let (width, height) = (pixmap.width(), pixmap.height());
+
+            // Only upload the regions that changed since the last presented frame
+            let label_top = height.saturating_sub(label_height * scale);
+            let damage = icon.frame_damage(pixels, width, height, label_top);
+            if damage.is_empty() {
+                continue;
+            }
+
+            // Attach buffer to surface
+            if let Some(ref mut backend) = self.backend {
+                match backend.attach_buffer_damaged(surface_id, pixels, width, height, &damage) {
+                    Ok(true) => {
+                        icon.set_presented_frame(pixels);
+                        self.attach_failures.remove(&path);
+                        drawn_groups.insert(group);
+                    }
+                    Ok(false) => {
+                        // Retry once the compositor configures the surface
+                        self.awaiting_configure.insert(surface_id);
+                    }
+                    Err(e) => {
+                        let failures = self.attach_failures.entry(path.clone()).or_insert(0);
+                        *failures += 1;
+                        if *failures <= MAX_ATTACH_RETRIES {
+                            debug!(
+                                "Failed to attach buffer to surface {} (attempt {}), retrying: {}",
+                                surface_id, failures, e
+                            );
+                            retry = true;
+                            unfinished.push(path.clone());
+                        } else if *failures == MAX_ATTACH_RETRIES + 1 {
+                            warn!(
+                                "Giving up on {} after {} failed buffer attaches: {}",
+                                path.display(),
+                                failures,
+                                e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        self.render_fade_outs();
+        self.render_empty_hint();
+        self.render_delete_prompt();
+        self.render_context_menu();
+        // A hovered icon that could not be drawn (or is gone) loses its spotlight
+        match spotlight_frame {
+            Some((frame, scale)) => self.render_spotlight(&frame, scale),
+            None => self.close_spotlight(),
+        }
+        self.render_expanded_label();
+        self.render_tooltip();
+        self.render_debug_grid();
+        self.render_backdrop();
+
+        let now = Instant::now();
+        for group in drawn_groups {
+            self.frame_group_renders.insert(group, now);
+        }
+
+        // Clear the dirty state after rendering, except for icons whose
+        // attaches are being retried or whose output has not been drawn yet
+        self.dirty_icons.extend(unfinished);
+        if !retry && !deferred {
+            self.last_render_completed = Some(SystemTime::now());
+        }
+    }
+
+    /// Draw the next frame of each removed icon's disappear animation
+    fn render_fade_outs(&mut self) {
+        let disappear_ms = self.config.animations.disappear_ms.max(1) as f32;
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+
+        for fade in &self.disappearing {
+            let t = ease_out(fade.started.elapsed().as_millis() as f32 / disappear_ms);
+            let scale = 1.0 - (1.0 - ANIMATION_MIN_SCALE) * t;
+            let Some(frame) = animate_frame(&fade.frame, 1.0 - t, scale) else {
+                continue;
+            };
+
+            let (width, height) = (frame.width(), frame.height());
+            let damage = [DamageRect::full(width, height)];
+            if let Err(e) = backend.attach_buffer_damaged(fade.surface_id, frame.data(), width, height, &damage) {
+                warn!("Failed to attach fade-out frame to surface {}: {}", fade.surface_id, e);
+            }
+        }
+    }
+
+    /// Key of an icon's first frame in the render cache
+    ///
+    /// Covers everything the frame depends on besides hover and selection,
+    /// which a first frame never has. None if the file's mtime is unknown.
+    fn render_cache_key(&self, icon: &DesktopIcon, width: u32, height: u32) -> Option<String> {
+        let mtime = |path: &Path| {
+            path.metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+        };
+        let script = icon.script_path().map(|script| (script, mtime(script)));
+        let custom_icon = icon.custom_icon().map(|custom| match custom {
+            FolderIcon::File(path) => (custom, mtime(path)),
+            FolderIcon::Named(_) => (custom, None),
+        });
+
+        Some(format!(
+            "{}|{}|{}|{}x{}|{}|{}|{:?}|{}|{:?}|{:?}|{}|{:?}|{}|{:?}|{:?}",
+            icon.path().display(),
+            icon.label(),
+            mtime(icon.path())?,
+            width,
+            height,
+            self.config.icon_size,
+            self.config.font_size,
+            self.config.label_align,
+            self.config.label_width,
+            self.config.icon_elevation,
+            self.config.label_pill,
+            self.config.icon_theme,
+            self.config.icon_theme_dir,
+            self.renderer.is_symbolic(),
+            script,
+            custom_icon,
+        ))
+    }
+
+    /// An icon's frame as drawn to its surface
+    ///
+    /// The rendered (or cached) glyph and label, with the folder peek, widget
+    /// overlay, status emblem, unavailable dimming, appear animation and idle
+    /// dimming on top, drawn at `scale` times the `width` x `height` surface.
+    fn icon_frame(
+        &mut self,
+        path: &Path,
+        width: u32,
+        height: u32,
+        scale: u32,
+        idle_opacity: f32,
+    ) -> Option<tiny_skia::Pixmap> {
+        let mut pixmap = self.first_frame_or_render(path, width, height, scale)?;
+        let icon = self.icons.get_mut(path)?;
+        let width = pixmap.width();
+
+        // Composite the content peek over folder glyphs
+        let glyph_size = (self.config.icon_size * scale).min(width);
+        let peek_size = glyph_size * 5 / 8;
+        if let Some(peek) = icon.folder_peek(&self.renderer, peek_size) {
+            let peek_x = (width as i32 - peek_size as i32) / 2;
+            let peek_y = (glyph_size - peek_size - glyph_size / 10) as i32;
+            pixmap.draw_pixmap(
+                peek_x,
+                peek_y,
+                peek.as_ref(),
+                &tiny_skia::PixmapPaint::default(),
+                tiny_skia::Transform::identity(),
+                None,
+            );
+        }
+
+        // Widget badges go on top of the glyph and peek
+        if let Some(overlay) = icon.overlay() {
+            self.renderer.composite_overlay(&mut pixmap, overlay, glyph_size);
+        }
+        if let Some(&emblem) = self.emblems.get(path) {
+            self.renderer.composite_emblem(&mut pixmap, emblem, glyph_size);
+        }
+        if self.config.widget_status_badges {
+            self.renderer.composite_widget_status(&mut pixmap, icon.widget_status(), glyph_size);
+        }
+        if let Some(link) = icon.link() {
+            self.renderer.composite_link(&mut pixmap, link == LinkState::Broken, glyph_size);
+        }
+
+        // Draw attention to an icon asked to flash
+        if let Some(progress) = icon.flash_progress(Instant::now()) {
+            let flash = &self.config.flash;
+            let level = flash_level(progress, flash.pulses);
+            // A pulse moves the icon, so it glows instead under reduce_motion
+            let frame = match flash.style {
+                FlashStyle::Pulse if !self.config.reduce_motion => {
+                    animate_frame(&pixmap, 1.0, 1.0 - FLASH_PULSE_DEPTH * level)
+                }
+                _ => glow_frame(&pixmap, glyph_size, &flash.color, level),
+            };
+            if let Some(frame) = frame {
+                pixmap = frame;
+            }
+        }
+
+        // Dim icons whose filesystem went away, and fade those whose file is
+        // failing checks a little while they may still recover
+        let opacity = if icon.is_unavailable() {
+            UNAVAILABLE_OPACITY
+        } else if icon.failed_checks() > 0 {
+            UPDATE_ERROR_OPACITY
+        } else {
+            1.0
+        };
+        if opacity < 1.0 {
+            if let Some(frame) = animate_frame(&pixmap, opacity, 1.0) {
+                pixmap = frame;
+            }
+        }
+
+        // Grow and fade in while the appear animation runs
+        if let Some(started) = self.appearing.get(path) {
+            let appear_ms = self.config.animations.appear_ms.max(1) as f32;
+            let t = ease_out(started.elapsed().as_millis() as f32 / appear_ms);
+            let scale = ANIMATION_MIN_SCALE + (1.0 - ANIMATION_MIN_SCALE) * t;
+            if let Some(frame) = animate_frame(&pixmap, t, scale) {
+                pixmap = frame;
+            }
+        }
+
+        // Dim everything while the pointer is idle
+        if idle_opacity < 1.0 {
+            if let Some(frame) = animate_frame(&pixmap, idle_opacity, 1.0) {
+                pixmap = frame;
+            }
+        }
+
+        Some(pixmap)
+    }
+
+    /// Hover tooltip text for the icon at `path`, one entry per line
+    ///
+    /// Includes the user's tags and comment (from `user.xdg.*` xattrs) when
+    /// the file has any. Returns `None` for paths without an icon.
+    pub fn tooltip(&self, path: &Path) -> Option<Vec<String>> {
+        self.icons.get(path).map(DesktopIcon::tooltip_lines)
+    }
+
+    /// Current frame of the icon for `path`, as premultiplied RGBA and its size
+    ///
+    /// Runs the same pipeline as rendering to the icon's surface, at the
+    /// surface's size if it has one, so tests and previews can look at the
+    /// pixels without a display. Returns `None` for paths without an icon.
+    pub fn render_icon_bitmap(&mut self, path: &Path) -> Option<(Vec<u8>, u32, u32)> {
+        if !self.icons.contains_key(path) {
+            return None;
+        }
+        let (width, height) = self
+            .path_to_surface
+            .get(path)
+            .and_then(|&surface_id| self.backend.as_ref()?.surface_size(surface_id))
+            .filter(|&(w, h)| w > 0 && h > 0 && w <= MAX_SURFACE_SIDE && h <= MAX_SURFACE_SIDE)
+            .unwrap_or((self.config.icon_size, self.config.icon_size + self.label_height()));
+        let scale = match self.path_to_surface.get(path) {
+            Some(&surface_id) => self.surface_render_scale(surface_id, width, height),
+            None => 1,
+        };
+
+        let idle_opacity = self.idle_opacity(Instant::now());
+        let pixmap = self.icon_frame(path, width, height, scale, idle_opacity)?;
+        Some((pixmap.data().to_vec(), pixmap.width(), pixmap.height()))
+    }
+
+    /// Scale to draw a `width` x `height` surface at: its output's, unless
+    /// the buffer would be too large
+    fn surface_render_scale(&self, surface_id: SurfaceId, width: u32, height: u32) -> u32 {
+        let scale = self.backend.as_ref().map_or(1, |backend| backend.surface_scale(surface_id));
+        if scale > 1 && width.max(height).saturating_mul(scale) <= MAX_SURFACE_SIDE {
+            scale
+        } else {
+            1
+        }
+    }
+
+    /// An icon's first frame from the render cache, or a fresh render
+    ///
+    /// Only an icon's first frame is cached: after that its script is
+    /// running and renders every change. Trash and volume icons change
+    /// without their mtime changing, so they are never cached.
+    fn first_frame_or_render(
+        &mut self,
+        path: &Path,
+        width: u32,
+        height: u32,
+        scale: u32,
+    ) -> Option<tiny_skia::Pixmap> {
+        let icon = self.icons.get(path)?;
+        let cacheable = !icon.has_presented_frame()
+            && !icon.is_hovered()
+            && !icon.is_selected()
+            && !icon.is_focused()
+            && icon.rename_field().is_none()
+            && icon.thumbnail().is_none()
+            && !matches!(icon.icon_type(), IconType::Trash | IconType::Volume);
+        // Frames are cached by their size in buffer pixels
+        let (buffer_width, buffer_height) = (width * scale, height * scale);
+        let key = match self.render_cache {
+            Some(_) if cacheable => self.render_cache_key(icon, buffer_width, buffer_height),
+            _ => None,
+        };
+        let (Some(cache), Some(key)) = (self.render_cache.as_ref(), key) else {
+            return self.render_icon_pixmap(path, width, height, scale);
+        };
+
+        if let Some(pixmap) = cache
+            .get(&key)
+            .filter(|p| p.width() == buffer_width && p.height() == buffer_height)
+        {
+            debug!("First frame of {} from the render cache", path.display());
+            return Some(pixmap);
+        }
+
+        let pixmap = self.render_icon_pixmap(path, width, height, scale)?;
+        // Don't cache the placeholder drawn while a script is unavailable
+        if self.icons.get(path).is_some_and(|icon| icon.rendered_by_script()) {
+            if let Some(ref cache) = self.render_cache {
+                if let Err(e) = cache.put(&key, &pixmap) {
+                    warn!("Failed to cache the render of {}: {:#}", path.display(), e);
+                }
+            }
+        }
+        Some(pixmap)
+    }
+
+    /// Render an icon's glyph and label for a `width` x `height` surface
+    ///
+    /// Symbolic mode, and images with a thumbnail, render natively; otherwise
+    /// the icon's Lua draw commands are used. The pixmap is `scale` times the surface size: widgets draw in
+    /// surface coordinates, told the scale as the device pixel ratio, and
+    /// their commands are scaled up to buffer pixels.
+    fn render_icon_pixmap(
+        &mut self,
+        path: &Path,
+        width: u32,
+        height: u32,
+        scale: u32,
+    ) -> Option<tiny_skia::Pixmap> {
+        let ratio = scale as f32;
+        // Symbolic mode draws every glyph itself, as widget output can't be recolored
+        let native = self.renderer.is_symbolic() || self.icons.get(path)?.thumbnail().is_some();
+        let mut pixmap = if native {
+            // A rename only changes the label, so the glyph layer is reused
+            let icon = self.icons.get_mut(path)?;
+            let glyph = match icon.glyph_layer() {
+                Some(glyph) => glyph.clone(),
+                None => match self.renderer.render_glyph_layer(icon) {
+                    Ok(glyph) => {
+                        icon.set_glyph_layer(glyph.clone());
+                        glyph
+                    }
+                    Err(e) => {
+                        warn!("Failed to render the glyph of {}: {}", path.display(), e);
+                        return None;
+                    }
+                },
+            };
+            match self.renderer.render_label_layer(&glyph, icon.label()) {
+                // The symbolic renderer draws at one size, so it is enlarged to the buffer
+                Ok(pixmap) if scale > 1 => {
+                    let pixmap = fit_pixmap(pixmap, width, height)?;
+                    zoom_frame(&pixmap, width * scale, height * scale, ratio)?
+                }
+                Ok(pixmap) => fit_pixmap(pixmap, width, height)?,
+                Err(e) => {
+                    warn!("Failed to render label for {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        } else {
+            // Get render commands from the icon (use full height including label)
+            let icon = self.icons.get_mut(path)?;
+            let mut commands = icon.request_render(width, height, ratio);
+            if scale > 1 {
+                commands = commands.into_iter().map(|command| command.scaled(ratio)).collect();
+            }
+            // The theme directory's image for the type stands in for the fallback
+            let themed = Some(icon.icon_type())
+                .filter(|&icon_type| !icon.drawn_by_widget() && self.renderer.has_type_image(icon_type));
+
+            // Create pixmap and render commands (use full height including label)
+            let mut pixmap = tiny_skia::Pixmap::new(width * scale, height * scale)?;
+            if let Some(icon_type) = themed {
+                self.renderer.draw_type_image(&mut pixmap, icon_type, scale);
+            } else if let Err(e) = self.renderer.execute_commands(&mut pixmap, &commands) {
+                warn!("Failed to execute draw commands for {}: {}", path.display(), e);
+                return None;
+            }
+            // Widgets draw hover and selection themselves, the fallback glyph
+            // doesn't; the ring is always ours
+            let icon = self.icons.get(path)?;
+            if !icon.drawn_by_widget() {
+                self.renderer.draw_state_fills(&mut pixmap, icon);
+            }
+            if icon.is_focused() {
+                self.renderer.draw_focus_ring(&mut pixmap);
+            }
+            pixmap
+        };
+
+        // The drop shadow goes beneath everything the icon drew
+        self.renderer.apply_elevation(&mut pixmap, ratio);
+
+        // Volume icons get their usage bar and file count on top of the glyph
+        let glyph_size = self.config.icon_size.min(width);
+        let volume_commands: Vec<_> = self
+            .icons
+            .get(path)?
+            .volume_commands(width, glyph_size)
+            .into_iter()
+            .map(|command| command.scaled(ratio))
+            .collect();
+        if let Err(e) = self.renderer.execute_commands(&mut pixmap, &volume_commands) {
+            warn!("Failed to draw volume usage for {}: {}", path.display(), e);
+        }
+
+        // The rename field covers the label, whoever drew it
+        if let Some(field) = self.icons.get(path)?.rename_field() {
+            self.renderer.draw_rename_field(&mut pixmap, field, ratio);
+        }
+
+        Some(pixmap)
+    }
+
+    /// Handle input events from the backend
+    fn handle_input(&mut self, now: Instant) {
+        // Only process if we have a display backend
+        let events = if let Some(ref mut backend) = self.backend {
+            backend.take_input_events()
+        } else {
+            return;
+        };
+
+        if !events.is_empty() {
+            self.note_input(now);
+        }
+
+        for event in events {
+            if self.context_menu_input(&event, now) {
+                continue;
+            }
+            match event {
+                InputEvent::PointerEnter { surface_id, .. } => {
+                    // Set hovered state on the icon
+                    if let Some(path) = self.surface_to_path.get(&surface_id) {
+                        if let Some(icon) = self.icons.get_mut(path) {
+                            icon.set_hovered(true);
+                            self.dirty_icons.insert(path.clone());
+                            debug!("Pointer entered icon: {}", path.display());
+                            self.open_spotlight(surface_id);
+                            self.expand_label(surface_id);
+                            self.schedule_tooltip(surface_id, now);
+                        }
+                    }
+                }
+                InputEvent::PointerLeave { surface_id } => {
+                    // Clear hovered state
+                    if let Some(path) = self.surface_to_path.get(&surface_id) {
+                        if let Some(icon) = self.icons.get_mut(path) {
+                            icon.set_hovered(false);
+                            self.dirty_icons.insert(path.clone());
+                            debug!("Pointer left icon: {}", path.display());
+                            self.shrink_spotlight(surface_id);
+                            self.collapse_label();
+                            let leaving = self.tooltip.as_ref().map(|tooltip| tooltip.icon_surface);
+                            if leaving == Some(surface_id) {
+                                self.close_tooltip();
+                            }
+                        }
+                    }
+                }
+                InputEvent::PointerMotion { surface_id, x, y } => {
+                    debug!("Pointer motion on surface {} at ({}, {})", surface_id, x, y);
+                    self.update_rubber_band(surface_id, x, y);
+                    self.handle_drag_motion(surface_id, x, y);
+                }
+                InputEvent::PointerCancel { surface_id } => {
+                    // Nothing the press started happens: no click, drop or band
+                    debug!("Press on surface {} cancelled", surface_id);
+                    self.pointer_press = None;
+                    self.cancel_drag();
+                    self.cancel_rubber_band();
+                }
+                InputEvent::Key { keysym, modifiers } => self.handle_key(keysym, modifiers),
+                InputEvent::PointerScroll { surface_id, horizontal, vertical, discrete } => {
+                    let Some(path) = self.surface_to_path.get(&surface_id).cloned() else {
+                        continue;
+                    };
+                    let Some(icon) = self.icons.get_mut(&path) else {
+                        continue;
+                    };
+                    match icon.on_scroll(horizontal, vertical) {
+                        Ok(handled) => {
+                            debug!(
+                                "Scroll ({}, {}) by {:?} clicks on {} (handled: {})",
+                                horizontal,
+                                vertical,
+                                discrete,
+                                path.display(),
+                                handled
+                            );
+                            // The widget may have changed what it draws
+                            if handled {
+                                self.dirty_icons.insert(path);
+                            }
+                        }
+                        Err(e) => warn!("Error sending scroll to {}: {}", path.display(), e),
+                    }
+                }
+                InputEvent::PointerButton { surface_id, button, pressed, x, y, modifiers } => {
+                    let button = logical_button(button, self.config.swap_buttons);
+
+                    // The delete confirmation popup takes primary-button presses on Yes/No
+                    let on_prompt = self
+                        .pending_delete
+                        .as_ref()
+                        .is_some_and(|pending| pending.surface == Some(surface_id));
+                    if on_prompt {
+                        let answer = confirm_popup_hit(x, y).filter(|_| pressed && button == BTN_LEFT);
+                        if let Some(confirmed) = answer {
+                            if let Err(e) = self.answer_delete_prompt(confirmed) {
+                                warn!("Failed to delete: {}", e);
+                            }
+                        }
+                        continue;
+                    }
+
+                    if pressed && self.pile_press(surface_id) {
+                        continue;
+                    }
+
+                    // Primary button: selection, and presses that motion can turn into a drag
+                    if button == BTN_LEFT && self.backdrop == Some(surface_id) {
+                        self.backdrop_press(pressed, x, y, modifiers);
+                        continue;
+                    }
+                    if button == BTN_LEFT {
+                        if pressed {
+                            if let Some(path) = self.surface_to_path.get(&surface_id).cloned() {
+                                if self.is_double_click(surface_id, now) {
+                                    self.double_click(&path, x, y);
+                                    continue;
+                                }
+                                let collapse_on_release = self.click_select(&path, modifiers);
+                                self.pointer_press = Some(PointerPress {
+                                    surface_id,
+                                    path,
+                                    x,
+                                    y,
+                                    collapse_on_release,
+                                    started: now,
+                                });
+                            }
+                        } else {
+                            let dragged = self.drag.is_some();
+                            self.end_drag();
+                            if dragged {
+                                // A press that became a drag isn't the first half of a double click
+                                self.last_click = None;
+                            }
+                            if let Some(press) = self.pointer_press.take() {
+                                if press.collapse_on_release && !dragged {
+                                    self.select_only(&press.path);
+                                }
+                            }
+                        }
+                        continue;
+                    }
+
+                    if pressed {
+                        if let Some(path) = self.surface_to_path.get(&surface_id).cloned() {
+                            self.button_press(&path, MouseButton::from_code(button), x, y);
+                        }
+                    }
+                }
+            }
+        }
+        self.sync_cursor();
+    }
+
+    /// Show a hand over icons and a closed hand while dragging them
+    fn sync_cursor(&mut self) {
+        let shape = if self.drag.is_some() {
+            CursorShape::Grabbing
+        } else if self.icons.values().any(|icon| icon.is_hovered()) {
+            CursorShape::Pointer
+        } else {
+            CursorShape::Default
+        };
+        if shape == self.cursor_shape {
+            return;
+        }
+        if let Some(ref mut backend) = self.backend {
+            debug!("Cursor shape {:?}", shape);
+            backend.set_cursor_shape(shape);
+        }
+        self.cursor_shape = shape;
+    }
+
+    /// Whether a primary-button press on `surface_id` at `now` completes a double click
+    ///
+    /// The first press of a pair is remembered; a press on another surface,
+    /// or after `double_click_ms`, starts a new pair instead.
+    fn is_double_click(&mut self, surface_id: SurfaceId, now: Instant) -> bool {
+        let window = Duration::from_millis(self.config.double_click_ms);
+        let double = self.last_click.is_some_and(|(last_surface, at)| {
+            last_surface == surface_id && now.saturating_duration_since(at) <= window
+        });
+        // A third press starts over rather than making another double click
+        self.last_click = if double { None } else { Some((surface_id, now)) };
+        double
+    }
+
+    /// Open the icon at `path` on a double click, unless its widget handles it
+    fn double_click(&mut self, path: &Path, x: f64, y: f64) {
+        let Some(icon) = self.icons.get_mut(path) else {
+            return;
+        };
+        let handled = match icon.send_double_click(x, y) {
+            Ok(handled) => handled,
+            Err(e) => {
+                warn!("Error sending double click to {}: {}", path.display(), e);
+                false
+            }
+        };
+        debug!("Double click on {} (handled by widget: {})", path.display(), handled);
+        if handled {
+            self.needs_render = true;
+            return;
+        }
+        match icon.on_double_click() {
+            Ok(ClickAction::Open) => {
+                if let Err(e) = self.perform_action(path, ContextAction::Open) {
+                    warn!("Failed to open {}: {}", path.display(), e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Error handling double click on {}: {}", path.display(), e),
+        }
+    }
+
+    /// Run `long_press_action` once the primary button has been held long enough
+    ///
+    /// The press is used up: releasing the button afterwards neither changes
+    /// the selection nor opens anything, and moving the pointer doesn't drag.
+    /// A drag that started first cancels the long press.
+    fn check_long_press(&mut self, now: Instant) {
+        let Some(hold) = self.config.long_press_ms.map(Duration::from_millis) else {
+            return;
+        };
+        let due = self
+            .pointer_press
+            .as_ref()
+            .is_some_and(|press| now.saturating_duration_since(press.started) >= hold);
+        if !due || self.drag.is_some() {
+            return;
+        }
+        let Some(press) = self.pointer_press.take() else {
+            return;
+        };
+        debug!("Long press on {}", press.path.display());
+        let action = self.config.long_press_action;
+        self.button_action(&press.path, Some(action), MouseButton::Left, press.x, press.y);
+    }
+
+    /// Restart the idle timer, brightening dimmed icons
+    fn note_input(&mut self, now: Instant) {
+        let opacity = self.idle_opacity(now);
+        if opacity < 1.0 {
+            debug!("Input after idle, brightening icons");
+            self.idle_wake = Some((now, opacity));
+        }
+        self.last_input = now;
+    }
+
+    /// Opacity all icons are drawn at because of idle dimming
+    fn idle_opacity(&self, now: Instant) -> f32 {
+        let fade = if self.config.animates() { IDLE_DIM_FADE } else { Duration::ZERO };
+        let idle = now.saturating_duration_since(self.last_input);
+        let dimmed = idle_dim_opacity(&self.config.idle_dim, idle, fade);
+
+        match self.idle_wake {
+            Some((started, from)) => {
+                let t = fade_progress(now.saturating_duration_since(started), fade);
+                dimmed.min(from + (1.0 - from) * t)
+            }
+            None => dimmed,
+        }
+    }
+
+    /// Act on a press of any button but the primary one on an icon
+    ///
+    /// `button_actions` takes precedence; otherwise right and middle keep
+    /// their built-in meaning and the rest go to the icon's widget.
+    fn button_press(&mut self, path: &Path, button: MouseButton, x: f64, y: f64) {
+        let mapped = self.config.button_actions.get(&button.name()).copied();
+        self.button_action(path, mapped, button, x, y);
+    }
+
+    /// Run `action` for a press of `button` on an icon, or the button's
+    /// built-in meaning without one
+    fn button_action(
+        &mut self,
+        path: &Path,
+        action: Option<ButtonAction>,
+        button: MouseButton,
+        x: f64,
+        y: f64,
+    ) {
+        match (action, button) {
+            (Some(ButtonAction::Ignore), _) => {}
+            (Some(ButtonAction::Open), _) => {
+                if let Err(e) = self.perform_action(path, ContextAction::Open) {
+                    warn!("Failed to open {}: {}", path.display(), e);
+                }
+            }
+            (Some(ButtonAction::ContextMenu), _) => self.icon_click(path, MouseButton::Right.number(), x, y),
+            (Some(ButtonAction::Widget), _)
+            | (None, MouseButton::Back | MouseButton::Forward | MouseButton::Other(_)) => {
+                let Some(icon) = self.icons.get_mut(path) else {
+                    return;
+                };
+                match icon.send_click(button, x, y) {
+                    Ok(handled) => {
+                        debug!(
+                            "Button {} on {} sent to widget (handled: {})",
+                            button.name(),
+                            path.display(),
+                            handled
+                        );
+                        // The widget may have changed what it draws
+                        if handled {
+                            self.dirty_icons.insert(path.to_path_buf());
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Error sending {} click to {}: {}", button.name(), path.display(), e)
+                    }
+                }
+            }
+            (None, button) => self.icon_click(path, button.number(), x, y),
+        }
+    }
+
+    /// Run the icon's built-in handling of a click with X11 button `button_num`
+    /// at surface coordinates `(x, y)`
+    fn icon_click(&mut self, path: &Path, button_num: u32, x: f64, y: f64) {
+        let Some(icon) = self.icons.get_mut(path) else {
+            return;
+        };
+        match icon.on_click(button_num) {
+            Ok(ClickAction::ContextMenu) => self.open_context_menu(path, x, y),
+            Ok(action) => {
+                self.needs_render = true;
+                debug!("Click on icon {} button {}: {:?}", path.display(), button_num, action);
+            }
+            Err(e) => {
+                warn!("Error handling click on {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Open `path`'s context menu at surface coordinates `(x, y)` of its icon
+    ///
+    /// A menu already open on any icon is closed first. The menu is kept on
+    /// the output, and closes once the pointer has been off it for
+    /// `CONTEXT_MENU_LEAVE_TIMEOUT`, or on a press anywhere else.
+    fn open_context_menu(&mut self, path: &Path, x: f64, y: f64) {
+        self.close_context_menu();
+        let Some(icon) = self.icons.get(path) else {
+            return;
+        };
+        let entries = icon.context_menu();
+        if entries.is_empty() {
+            return;
+        }
+        let (Some(&icon_surface), Some(backend)) = (self.path_to_surface.get(path), self.backend.as_mut())
+        else {
+            return;
+        };
+        let Some((icon_x, icon_y)) = backend.surface_position(icon_surface) else {
+            return;
+        };
+
+        let height = context_menu_height(&entries);
+        let max_x = (self.screen_width as i32 - CONTEXT_MENU_WIDTH as i32).max(0);
+        let max_y = (self.screen_height as i32 - height as i32).max(0);
+        let menu_x = (icon_x + x as i32).clamp(0, max_x);
+        let menu_y = (icon_y + y as i32).clamp(0, max_y);
+        match backend.create_surface(menu_x, menu_y, CONTEXT_MENU_WIDTH, height) {
+            Ok(surface) => {
+                debug!("Context menu for {}: {:?}", path.display(), entries);
+                self.context_menu = Some(ContextMenu {
+                    path: path.to_path_buf(),
+                    entries,
+                    surface,
+                    highlighted: None,
+                    // Until the pointer moves onto the menu
+                    left_since: Some(Instant::now()),
+                });
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create context menu surface: {}", e),
+        }
+    }
+
+    /// Close the open context menu, if any
+    fn close_context_menu(&mut self) {
+        let Some(menu) = self.context_menu.take() else {
+            return;
+        };
+        if let Some(ref mut backend) = self.backend {
+            backend.destroy_surface(menu.surface);
+        }
+        self.awaiting_configure.remove(&menu.surface);
+    }
+
+    /// Handle `event` if it belongs to the open context menu
+    ///
+    /// Pointer events on the menu highlight and choose its entries. A press
+    /// anywhere else closes the menu and is then handled as usual. Returns
+    /// whether the event was used up.
+    fn context_menu_input(&mut self, event: &InputEvent, now: Instant) -> bool {
+        let Some(menu) = self.context_menu.as_mut() else {
+            return false;
+        };
+        let highlighted = menu.highlighted;
+        match *event {
+            InputEvent::PointerEnter { surface_id, x, y } | InputEvent::PointerMotion { surface_id, x, y }
+                if surface_id == menu.surface =>
+            {
+                menu.left_since = None;
+                menu.highlighted = context_menu_hit(&menu.entries, x, y);
+            }
+            InputEvent::PointerLeave { surface_id } if surface_id == menu.surface => {
+                menu.left_since = Some(now);
+                menu.highlighted = None;
+            }
+            InputEvent::PointerButton { surface_id, button, pressed, x, y, .. }
+                if surface_id == menu.surface =>
+            {
+                let button = logical_button(button, self.config.swap_buttons);
+                if !pressed || button != BTN_LEFT {
+                    return true;
+                }
+                let chosen = context_menu_hit(&menu.entries, x, y).and_then(|i| match &menu.entries[i] {
+                    MenuEntry::Action { action, .. } => Some(action.clone()),
+                    MenuEntry::Separator => None,
+                });
+                let Some(action) = chosen else {
+                    return true;
+                };
+                let path = menu.path.clone();
+                self.close_context_menu();
+                debug!("Context menu action {:?} on {}", action, path.display());
+                if let Err(e) = self.perform_action(&path, action) {
+                    warn!("Failed to run menu action on {}: {}", path.display(), e);
+                }
+                return true;
+            }
+            InputEvent::PointerButton { pressed: true, .. } => {
+                self.close_context_menu();
+                return false;
+            }
+            _ => return false,
+        }
+        if menu.highlighted != highlighted {
+            self.needs_render = true;
+        }
+        true
+    }
+
+    /// Close the context menu once the pointer has been off it long enough
+    fn expire_context_menu(&mut self, now: Instant) {
+        let expired = self.context_menu.as_ref().and_then(|menu| menu.left_since).is_some_and(|left| {
+            now.saturating_duration_since(left) >= CONTEXT_MENU_LEAVE_TIMEOUT
+        });
+        if expired {
+            debug!("Pointer left the context menu, closing it");
+            self.close_context_menu();
+        }
+    }
+
+    /// Draw the context menu, if one is open
+    fn render_context_menu(&mut self) {
+        let (Some(menu), Some(backend)) = (self.context_menu.as_ref(), self.backend.as_mut()) else {
+            return;
+        };
+        let pixmap = match self.renderer.render_context_menu(&menu.entries, menu.highlighted) {
+            Ok(pixmap) => pixmap,
+            Err(e) => {
+                warn!("Failed to render context menu: {}", e);
+                return;
+            }
+        };
+        match backend.attach_buffer(menu.surface, pixmap.data(), pixmap.width(), pixmap.height()) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(menu.surface);
+            }
+            Err(e) => warn!("Failed to attach context menu buffer: {}", e),
+        }
+    }
+
+    /// Keyboard shortcuts: arrow keys move the focus ring and select its icon,
+    /// Enter opens it, Ctrl+C copies the selected icons' paths and Ctrl+Shift+C
+    /// a single selected text file's contents
+    fn handle_key(&mut self, keysym: u32, modifiers: KeyModifiers) {
+        // While an icon is renamed, keys edit its name
+        if self.renaming.is_some() {
+            return self.rename_key(keysym, modifiers);
+        }
+        let plain = !modifiers.ctrl && !modifiers.alt;
+        match keysym {
+            KEY_LEFT | KEY_UP | KEY_RIGHT | KEY_DOWN if plain => return self.move_focus(keysym),
+            KEY_RETURN | KEY_KP_ENTER if plain => return self.open_focused(),
+            KEY_F2 if plain => return self.rename_focused(),
+            KEY_DELETE if plain => {
+                if let Err(e) = self.delete_selection(modifiers.shift) {
+                    warn!("Failed to delete: {:#}", e);
+                }
+                return;
+            }
+            KEY_Z if modifiers.ctrl && !modifiers.alt && !modifiers.shift => {
+                if let Err(e) = self.undo_trash() {
+                    warn!("Failed to undo: {:#}", e);
+                }
+                return;
+            }
+            _ => {}
+        }
+        if !matches!(keysym, KEY_C | KEY_SHIFT_C) || !modifiers.ctrl || modifiers.alt {
+            return;
+        }
+        let paths = self.selected_paths();
+        let result = match (paths.as_slice(), modifiers.shift) {
+            ([], _) => return,
+            (paths, false) => self.set_clipboard_text(&clipboard::paths_text(paths)),
+            ([path], true) => {
+                clipboard::file_text(path).and_then(|text| self.set_clipboard_text(&text))
+            }
+            (_, true) => {
+                debug!("Copy as Text needs exactly one selected file");
+                return;
+            }
+        };
+        if let Err(e) = result {
+            warn!("Failed to copy: {}", e);
+        }
+    }
+
+    /// Move the keyboard focus to the next icon in the direction of arrow key `keysym`
+    ///
+    /// Left and right go along the rows and up and down along the columns,
+    /// carrying on at the other end of the next row or column past the
+    /// grid's edge and wrapping around after the last icon. Without a
+    /// focused icon the focus starts at the top-left one.
+    fn move_focus(&mut self, keysym: u32) {
+        // Arrow keys follow the screen, whichever corner the grid fills from
+        let corner = self.config.grid_corner;
+        let keysym = match keysym {
+            KEY_LEFT if corner.is_right() => KEY_RIGHT,
+            KEY_RIGHT if corner.is_right() => KEY_LEFT,
+            KEY_UP if corner.is_bottom() => KEY_DOWN,
+            KEY_DOWN if corner.is_bottom() => KEY_UP,
+            other => other,
+        };
+        let (columns, _) = self.grid_dimensions();
+        let columns = columns.max(1);
+        let mut order: Vec<((u32, u32), &PathBuf)> = self
+            .icons
+            .iter()
+            .map(|(path, icon)| {
+                let (row, col) = (icon.layout_index() / columns, icon.layout_index() % columns);
+                match keysym {
+                    KEY_UP | KEY_DOWN => ((col, row), path),
+                    _ => ((row, col), path),
+                }
+            })
+            .collect();
+        if order.is_empty() {
+            return;
+        }
+        order.sort();
+
+        let current = self
+            .keyboard_focus
+            .as_ref()
+            .and_then(|focus| order.iter().position(|(_, path)| *path == focus));
+        let next = match current {
+            Some(i) if matches!(keysym, KEY_RIGHT | KEY_DOWN) => (i + 1) % order.len(),
+            Some(i) => (i + order.len() - 1) % order.len(),
+            None => 0,
+        };
+        let path = order[next].1.clone();
+        self.set_keyboard_focus(&path);
+        self.select_only(&path);
+        self.selection_anchor = Some(path);
+    }
+
+    /// Move the focus ring to `path`'s icon
+    fn set_keyboard_focus(&mut self, path: &Path) {
+        if let Some(icon) = self.keyboard_focus.take().and_then(|old| self.icons.get_mut(&old)) {
+            icon.set_focused(false);
+        }
+        if let Some(icon) = self.icons.get_mut(path) {
+            icon.set_focused(true);
+            self.keyboard_focus = Some(path.to_path_buf());
+        }
+        self.needs_render = true;
+    }
+
+    /// Open the icon with the keyboard focus, as its Open action does
+    fn open_focused(&mut self) {
+        let Some(path) = self.keyboard_focus.clone() else {
+            return;
+        };
+        if let Err(e) = self.perform_action(&path, ContextAction::Open) {
+            warn!("Failed to open {}: {}", path.display(), e);
+        }
+    }
+
+    /// Paths of the selected icons, sorted
+    fn selected_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .icons
+            .iter()
+            .filter(|(_, icon)| icon.is_selected())
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Offer `text` on the display's clipboard
+    fn set_clipboard_text(&mut self, text: &str) -> Result<()> {
+        let Some(ref mut backend) = self.backend else {
+            anyhow::bail!("No display to copy to");
+        };
+        backend.set_clipboard_text(text)?;
+        debug!("Copied {} bytes to the clipboard", text.len());
+        Ok(())
+    }
+
+    /// Run a context action on an icon
+    #[allow(dead_code)]
+    pub fn perform_action(&mut self, path: &Path, action: ContextAction) -> Result<()> {
+        let icon = self
+            .icons
+            .get(path)
+            .with_context(|| format!("No icon for {}", path.display()))?;
+
+        match action {
+            ContextAction::Open if icon.icon_type().is_special() => {
+                debug!("Not opening special file {}", path.display());
+            }
+            ContextAction::Open => {
+                let target = icon.open_target();
+                // A launcher whose application is running raises its window instead
+                let app_ids = match self.config.launcher_click {
+                    LauncherClick::FocusOrLaunch => launcher::app_ids(&target),
+                    LauncherClick::AlwaysLaunch => Vec::new(),
+                };
+                let raised = !app_ids.is_empty()
+                    && self.backend.as_mut().is_some_and(|backend| backend.activate_app(&app_ids));
+                if !raised {
+                    self.config
+                        .open_command(&target)
+                        .spawn()
+                        .with_context(|| format!("Failed to open {}", target.display()))?;
+                }
+            }
+            ContextAction::CopyPath => {
+                // From a selected icon, every selected icon's path
+                let paths = if icon.is_selected() {
+                    self.selected_paths()
+                } else {
+                    vec![path.to_path_buf()]
+                };
+                self.set_clipboard_text(&clipboard::paths_text(&paths))?;
+            }
+            ContextAction::CopyAsText => {
+                let text = clipboard::file_text(path)?;
+                self.set_clipboard_text(&text)?;
+            }
+            ContextAction::MoveToTrash => self.delete_from_menu(path, DeleteKind::Trash)?,
+            ContextAction::Delete => self.delete_from_menu(path, DeleteKind::Permanent)?,
+            ContextAction::EmptyTrash => {
+                if icon.icon_type() != IconType::Trash {
+                    anyhow::bail!("{} is not the trash", path.display());
+                }
+                self.request_delete(vec![path.to_path_buf()], DeleteKind::EmptyTrash)?;
+            }
+            ContextAction::Widget(id) => {
+                if let Some(icon) = self.icons.get_mut(path) {
+                    icon.run_widget_action(&id)?;
+                }
+                // The widget may have changed what it draws
+                self.needs_render = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete the selected icons' files, as the Delete key does
+    ///
+    /// With `permanent` (Shift+Delete) they bypass the trash. Either way this
+    /// may first ask for confirmation; see `confirm_delete`.
+    pub fn delete_selection(&mut self, permanent: bool) -> Result<()> {
+        let kind = if permanent { DeleteKind::Permanent } else { DeleteKind::Trash };
+        self.request_delete(self.deletable_selection(), kind)
+    }
+
+    /// Delete from an icon's context menu, on the whole selection if the icon is selected
+    fn delete_from_menu(&mut self, path: &Path, kind: DeleteKind) -> Result<()> {
+        let selected = self.icons.get(path).is_some_and(|icon| icon.is_selected());
+        let paths = if selected { self.deletable_selection() } else { vec![path.to_path_buf()] };
+        self.request_delete(paths, kind)
+    }
+
+    /// Selected icons backed by files that can be deleted, in a stable order
+    fn deletable_selection(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .icons
+            .iter()
+            .filter(|(_, icon)| icon.is_selected())
+            .filter(|(_, icon)| !matches!(icon.icon_type(), IconType::Trash | IconType::Volume))
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Delete `paths` now, or ask first if the config wants confirmation
+    fn request_delete(&mut self, paths: Vec<PathBuf>, kind: DeleteKind) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let confirm = self.config.confirm_delete
+            && (kind != DeleteKind::Trash || paths.len() >= self.config.confirm_delete_threshold);
+        if !confirm {
+            return self.delete_paths(&paths, kind);
+        }
+
+        // A new request replaces an unanswered one
+        self.close_delete_prompt();
+        let pending = PendingDelete { paths, kind, surface: None };
+        debug!("Asking for confirmation: {}", pending.prompt());
+        self.pending_delete = Some(pending);
+        self.show_delete_prompt();
+        Ok(())
+    }
+
+    /// Question of the delete waiting for confirmation, if any
+    pub fn delete_prompt(&self) -> Option<String> {
+        self.pending_delete.as_ref().map(PendingDelete::prompt)
+    }
+
+    /// Answer the pending delete confirmation, deleting if `confirmed`
+    pub fn answer_delete_prompt(&mut self, confirmed: bool) -> Result<()> {
+        let Some(pending) = self.close_delete_prompt() else {
+            return Ok(());
+        };
+        if confirmed {
+            self.delete_paths(&pending.paths, pending.kind)
+        } else {
+            debug!("Cancelled: {}", pending.prompt());
+            Ok(())
+        }
+    }
+
+    /// Forget the pending delete and destroy its popup
+    fn close_delete_prompt(&mut self) -> Option<PendingDelete> {
+        let pending = self.pending_delete.take()?;
+        if let (Some(surface_id), Some(backend)) = (pending.surface, self.backend.as_mut()) {
+            backend.destroy_surface(surface_id);
+            self.awaiting_configure.remove(&surface_id);
+        }
+        Some(pending)
+    }
+
+    /// Delete `paths` without asking; their icons go when the watcher reports it
+    fn delete_paths(&mut self, paths: &[PathBuf], kind: DeleteKind) -> Result<()> {
+        match kind {
+            DeleteKind::EmptyTrash => {
+                for trash_dir in paths {
+                    trash::empty(trash_dir)?;
+                }
+            }
+            DeleteKind::Permanent => {
+                for path in paths {
+                    trash::delete(path)?;
+                    info!("Deleted {}", path.display());
+                }
+            }
+            DeleteKind::Trash => {
+                let trash_dir = self
+                    .trash_dir
+                    .clone()
+                    .or_else(trash::home_trash_dir)
+                    .context("Cannot locate the trash directory")?;
+                let (columns, _) = self.grid_dimensions();
+                let mut trashed_files = Vec::new();
+                let mut result = Ok(());
+                for path in paths {
+                    // The cell it was placed in, or else the one it flowed into
+                    let cell = self.placements.get(path).or_else(|| {
+                        let icon = self.icons.get(path).filter(|icon| icon.pinned_corner().is_none())?;
+                        let index = icon.layout_index();
+                        Some(Cell { col: index % columns, row: index / columns })
+                    });
+                    match trash::trash(&trash_dir, path) {
+                        Ok(trashed) => {
+                            info!("Moved {} to the trash", path.display());
+                            let original = path.clone();
+                            let trash_dir = trash_dir.clone();
+                            trashed_files.push(TrashedFile { original, trashed, trash_dir, cell });
+                        }
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+                // What did get trashed can be undone, even if the rest failed
+                if !trashed_files.is_empty() {
+                    if self.trash_undo.len() >= TRASH_UNDO_DEPTH {
+                        self.trash_undo.remove(0);
+                    }
+                    self.trash_undo.push(trashed_files);
+                }
+                result?;
+            }
+        }
+        self.refresh_trash_icon();
+        Ok(())
+    }
+
+    /// Put back the files of the latest move to the trash, as Ctrl+Z does
+    ///
+    /// Their icons appear when the watcher reports the files, in the cells
+    /// they were in unless another icon was placed there since. A file whose
+    /// name was taken meanwhile stays in the trash.
+    pub fn undo_trash(&mut self) -> Result<()> {
+        let Some(trashed_files) = self.trash_undo.pop() else {
+            debug!("Nothing in the trash to undo");
+            return Ok(());
+        };
+        let mut result = Ok(());
+        for file in trashed_files {
+            if let Err(e) = trash::restore(&file.trash_dir, &file.trashed, &file.original) {
+                result = Err(e);
+                continue;
+            }
+            info!("Restored {} from the trash", file.original.display());
+            let cell = file.cell.filter(|&cell| {
+                self.placements.at(cell).iter().all(|placed| *placed == file.original)
+            });
+            if let Some(cell) = cell {
+                self.placements.set(&file.original, Some(cell));
+            }
+        }
+        if let Err(e) = self.placements.save() {
+            warn!("Failed to save icon placements: {:#}", e);
+        }
+        self.refresh_trash_icon();
+        result
+    }
+
+    /// Create the confirmation popup for the pending delete, centered in the grid area
+    fn show_delete_prompt(&mut self) {
+        let area = self.grid_area();
+        let x = area.x + (area.width as i32 - CONFIRM_POPUP_WIDTH as i32) / 2;
+        let y = area.y + (area.height as i32 - CONFIRM_POPUP_HEIGHT as i32) / 2;
+        let (Some(pending), Some(backend)) = (self.pending_delete.as_mut(), self.backend.as_mut())
+        else {
+            return;
+        };
+
+        match backend.create_surface(x, y, CONFIRM_POPUP_WIDTH, CONFIRM_POPUP_HEIGHT) {
+            Ok(surface_id) => {
+                pending.surface = Some(surface_id);
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create delete confirmation surface: {}", e),
+        }
+    }
+
+    /// Draw the delete confirmation popup, if it is shown
+    fn render_delete_prompt(&mut self) {
+        let (Some(pending), Some(backend)) = (self.pending_delete.as_ref(), self.backend.as_mut())
+        else {
+            return;
+        };
+        let Some(surface_id) = pending.surface else {
+            return;
+        };
+
+        let pixmap = match self.renderer.render_confirm_popup(&pending.prompt()) {
+            Ok(pixmap) => pixmap,
+            Err(e) => {
+                warn!("Failed to render delete confirmation: {}", e);
+                return;
+            }
+        };
+        match backend.attach_buffer(surface_id, pixmap.data(), CONFIRM_POPUP_WIDTH, CONFIRM_POPUP_HEIGHT) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(surface_id);
+            }
+            Err(e) => warn!("Failed to attach delete confirmation buffer: {}", e),
+        }
+    }
+
+    /// Magnification of the hovered icon, or None if `hover_zoom` is off
+    fn hover_zoom(&self) -> Option<f32> {
+        let zoom = self.config.hover_zoom;
+        (zoom > 1.0).then_some(zoom.min(MAX_HOVER_ZOOM))
+    }
+
+    fn hover_zoom_duration(&self) -> Duration {
+        if self.config.animates() {
+            HOVER_ZOOM_DURATION
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Start magnifying the icon on `surface_id`, which the pointer entered
+    ///
+    /// The magnified icon goes on an overlay surface centered over the icon,
+    /// so it is raised above the neighbours it overlaps and leaves the grid
+    /// alone. Re-entering an icon that is still shrinking grows it again.
+    fn open_spotlight(&mut self, surface_id: SurfaceId) {
+        let Some(zoom) = self.hover_zoom() else {
+            return;
+        };
+        let Some(path) = self.surface_to_path.get(&surface_id).cloned() else {
+            return;
+        };
+        let now = Instant::now();
+        let duration = self.hover_zoom_duration();
+
+        if let Some(ref mut spotlight) = self.spotlight {
+            if spotlight.path == path {
+                spotlight.from = spotlight.zoom(now, duration);
+                spotlight.to = zoom;
+                spotlight.started = now;
+                self.needs_render = true;
+                return;
+            }
+        }
+        self.close_spotlight();
+
+        let Some(backend) = self.backend.as_mut() else {
+            return;
+        };
+        let (Some((x, y)), Some((width, height))) =
+            (backend.surface_position(surface_id), backend.surface_size(surface_id))
+        else {
+            return;
+        };
+        let spot_width = ((width as f32 * zoom).ceil() as u32).min(MAX_SURFACE_SIDE);
+        let spot_height = ((height as f32 * zoom).ceil() as u32).min(MAX_SURFACE_SIDE);
+        let spot_x = x - (spot_width as i32 - width as i32) / 2;
+        let spot_y = y - (spot_height as i32 - height as i32) / 2;
+
+        match backend.create_overlay_surface(spot_x, spot_y, spot_width, spot_height) {
+            Ok(surface) => {
+                self.spotlight = Some(Spotlight {
+                    path,
+                    surface,
+                    width: spot_width,
+                    height: spot_height,
+                    from: 1.0,
+                    to: zoom,
+                    started: now,
+                    rendered_zoom: 1.0,
+                });
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create hover zoom surface: {}", e),
+        }
+    }
+
+    /// Shrink the spotlight back down after the pointer left `surface_id`
+    fn shrink_spotlight(&mut self, surface_id: SurfaceId) {
+        let path = self.surface_to_path.get(&surface_id);
+        let now = Instant::now();
+        let duration = self.hover_zoom_duration();
+        match self.spotlight {
+            Some(ref mut spotlight) if Some(&spotlight.path) == path => {
+                spotlight.from = spotlight.zoom(now, duration);
+                spotlight.to = 1.0;
+                spotlight.started = now;
+                self.needs_render = true;
+            }
+            _ => return,
+        }
+        if duration.is_zero() {
+            self.close_spotlight();
+        }
+    }
+
+    /// Remove the spotlight, if shown
+    fn close_spotlight(&mut self) {
+        if let Some(spotlight) = self.spotlight.take() {
+            if let Some(ref mut backend) = self.backend {
+                backend.destroy_surface(spotlight.surface);
+            }
+        }
+    }
+
+    /// Draw the hovered icon's `frame`, rendered at `scale`, magnified on the spotlight surface
+    fn render_spotlight(&mut self, frame: &tiny_skia::Pixmap, scale: u32) {
+        let duration = self.hover_zoom_duration();
+        let (Some(spotlight), Some(backend)) = (self.spotlight.as_mut(), self.backend.as_mut())
+        else {
+            return;
+        };
+        let zoom = spotlight.zoom(Instant::now(), duration);
+        spotlight.rendered_zoom = zoom;
+        let (width, height) = (spotlight.width, spotlight.height);
+        let Some(pixmap) = zoom_frame(frame, width, height, zoom / scale as f32) else {
+            return;
+        };
+        match backend.attach_buffer(spotlight.surface, pixmap.data(), width, height) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(spotlight.surface);
+            }
+            Err(e) => warn!("Failed to attach hover zoom buffer: {}", e),
+        }
+    }
+
+    /// Show the full name of the icon on `surface_id`, which the pointer entered
+    ///
+    /// Only for truncated labels. The name goes on an overlay surface over
+    /// the icon's label band, moved up or sideways where it would run off the
+    /// output, so neither the icon nor its neighbours have to move.
+    fn expand_label(&mut self, surface_id: SurfaceId) {
+        self.collapse_label();
+        if !self.config.expand_labels_on_hover {
+            return;
+        }
+        let Some(path) = self.surface_to_path.get(&surface_id).cloned() else {
+            return;
+        };
+        let Some(name) = self.icons.get(&path).map(|icon| icon.label().to_string()) else {
+            return;
+        };
+        if !self.renderer.label_truncated(&name) {
+            return;
+        }
+
+        let width = self.config.icon_size;
+        let height = self.renderer.expanded_label_height(&name);
+        let (screen_width, screen_height) = (self.screen_width as i32, self.screen_height as i32);
+        let Some(backend) = self.backend.as_mut() else {
+            return;
+        };
+        let Some((icon_x, icon_y)) = backend.surface_position(surface_id) else {
+            return;
+        };
+        let x = icon_x.min(screen_width - width as i32).max(0);
+        let label_top = icon_y + self.config.icon_size as i32 + 2;
+        let y = label_top.min(screen_height - height as i32).max(0);
+
+        match backend.create_overlay_surface(x, y, width, height) {
+            Ok(surface) => {
+                self.expanded_label = Some(ExpandedLabel { path, surface, width, height });
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create expanded label surface: {}", e),
+        }
+    }
+
+    /// Go back to the truncated label, if expanded
+    fn collapse_label(&mut self) {
+        if let Some(label) = self.expanded_label.take() {
+            if let Some(ref mut backend) = self.backend {
+                backend.destroy_surface(label.surface);
+            }
+        }
+    }
+
+    /// Draw the expanded label, if shown
+    ///
+    /// Dropped if its icon went away or was renamed to a name of another size.
+    fn render_expanded_label(&mut self) {
+        let Some(ref label) = self.expanded_label else {
+            return;
+        };
+        let pixmap = match self.icons.get(&label.path) {
+            Some(icon) => self.renderer.render_expanded_label(icon.label()),
+            None => Err(anyhow::anyhow!("{} has no icon", label.path.display())),
+        };
+        let pixmap = match pixmap {
+            Ok(pixmap) if (pixmap.width(), pixmap.height()) == (label.width, label.height) => pixmap,
+            Ok(_) => {
+                self.collapse_label();
+                return;
+            }
+            Err(e) => {
+                debug!("Dropping expanded label: {}", e);
+                self.collapse_label();
+                return;
+            }
+        };
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        match backend.attach_buffer(label.surface, pixmap.data(), label.width, label.height) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(label.surface);
+            }
+            Err(e) => warn!("Failed to attach expanded label buffer: {}", e),
+        }
+    }
+
+    /// Start timing the rest before the tooltip of the icon on `surface_id` shows
+    fn schedule_tooltip(&mut self, surface_id: SurfaceId, now: Instant) {
+        self.close_tooltip();
+        if !self.config.tooltips {
+            return;
+        }
+        let Some(path) = self.surface_to_path.get(&surface_id).cloned() else {
+            return;
+        };
+        let due = now + Duration::from_millis(self.config.tooltip_delay_ms);
+        self.tooltip = Some(Tooltip { path, icon_surface: surface_id, due: Some(due), surface: None });
+    }
+
+    /// Show the scheduled tooltip once the pointer has rested long enough
+    ///
+    /// It goes beside the icon, to the right unless it would run off the
+    /// output. Being an overlay it takes no input, so the pointer stays on
+    /// the icon beneath.
+    fn show_due_tooltip(&mut self, now: Instant) {
+        let Some(ref mut tooltip) = self.tooltip else {
+            return;
+        };
+        if tooltip.due.is_none_or(|due| due > now) {
+            return;
+        }
+        tooltip.due = None;
+        let Some(icon) = self.icons.get(&tooltip.path) else {
+            return;
+        };
+        let (width, height) = self.renderer.tooltip_size(&tooltip_lines(icon, self.config.tooltip_details));
+        let (screen_width, screen_height) = (self.screen_width as i32, self.screen_height as i32);
+        let Some(backend) = self.backend.as_mut() else {
+            return;
+        };
+        let Some((icon_x, icon_y)) = backend.surface_position(tooltip.icon_surface) else {
+            return;
+        };
+        let right = icon_x + self.config.icon_size as i32 + TOOLTIP_GAP;
+        let x = if right + width as i32 <= screen_width {
+            right
+        } else {
+            (icon_x - TOOLTIP_GAP - width as i32).max(0)
+        };
+        let y = icon_y.min(screen_height - height as i32).max(0);
+
+        match backend.create_overlay_surface(x, y, width, height) {
+            Ok(surface) => {
+                tooltip.surface = Some((surface, width, height));
+                self.needs_render = true;
+            }
+            Err(e) => warn!("Failed to create tooltip surface: {}", e),
+        }
+    }
+
+    /// Close the tooltip, or stop waiting to show it
+    fn close_tooltip(&mut self) {
+        let Some(tooltip) = self.tooltip.take() else {
+            return;
+        };
+        if let (Some((surface, _, _)), Some(backend)) = (tooltip.surface, self.backend.as_mut()) {
+            backend.destroy_surface(surface);
+        }
+    }
+
+    /// Draw the tooltip, if shown
+    ///
+    /// Dropped if its icon went away or now needs a tooltip of another size.
+    fn render_tooltip(&mut self) {
+        let Some((surface, width, height)) = self.tooltip.as_ref().and_then(|tooltip| tooltip.surface) else {
+            return;
+        };
+        let pixmap = self
+            .tooltip
+            .as_ref()
+            .and_then(|tooltip| self.icons.get(&tooltip.path))
+            .map(|icon| self.renderer.render_tooltip(&tooltip_lines(icon, self.config.tooltip_details)));
+        let pixmap = match pixmap {
+            Some(Ok(pixmap)) if (pixmap.width(), pixmap.height()) == (width, height) => pixmap,
+            Some(Err(e)) => {
+                debug!("Dropping tooltip: {}", e);
+                self.close_tooltip();
+                return;
+            }
+            _ => {
+                self.close_tooltip();
+                return;
+            }
+        };
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        match backend.attach_buffer(surface, pixmap.data(), width, height) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(surface);
+            }
+            Err(e) => warn!("Failed to attach tooltip buffer: {}", e),
+        }
+    }
+
+    /// Show or hide the layout debugging grid
+    pub fn set_debug_grid(&mut self, enabled: bool) {
+        if enabled != self.config.debug_grid {
+            info!("Layout debug grid {}", if enabled { "shown" } else { "hidden" });
+        }
+        self.config.debug_grid = enabled;
+        self.needs_render = true;
+    }
+
+    /// Rectangles of every grid cell in output coordinates, in layout index order
+    ///
+    /// Uses the same cell math as the default layout.
+    fn grid_cells(&self) -> Vec<tiny_skia::Rect> {
+        let area = self.grid_area();
+        let (columns, rows) = self.grid_dimensions();
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = self.config.icon_size + self.label_height() + self.config.grid_spacing;
+        (0..columns * rows)
+            .filter_map(|index| {
+                let cell_size = (cell_width, cell_height);
+                let (x, y) = grid_cell_origin(index, columns, cell_size, area, self.config.grid_corner);
+                tiny_skia::Rect::from_xywh(
+                    x as f32,
+                    y as f32,
+                    cell_width as f32,
+                    cell_height as f32,
+                )
+            })
+            .collect()
+    }
+
+    /// Draw the layout debugging grid across the output, or remove it when off
+    ///
+    /// The overlay ignores pointer input, and is recreated when the output
+    /// size changes.
+    fn render_debug_grid(&mut self) {
+        let (width, height) = (self.screen_width, self.screen_height);
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        let stale = self
+            .debug_grid
+            .is_some_and(|id| backend.surface_size(id) != Some((width, height)));
+        if !self.config.debug_grid || stale {
+            if let Some(surface_id) = self.debug_grid.take() {
+                backend.destroy_surface(surface_id);
+            }
+        }
+        if !self.config.debug_grid {
+            return;
+        }
+
+        let surface_id = match self.debug_grid {
+            Some(id) => id,
+            None => match backend.create_overlay_surface(0, 0, width, height) {
+                Ok(id) => {
+                    self.debug_grid = Some(id);
+                    id
+                }
+                Err(e) => {
+                    warn!("Failed to create debug grid surface: {}", e);
+                    return;
+                }
+            },
+        };
+
+        let cells = self.grid_cells();
+        let pixmap = match self.renderer.render_debug_grid(width, height, &cells) {
+            Ok(pixmap) => pixmap,
+            Err(e) => {
+                warn!("Failed to render debug grid: {}", e);
+                return;
+            }
+        };
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        match backend.attach_buffer(surface_id, pixmap.data(), width, height) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(surface_id);
+            }
+            Err(e) => warn!("Failed to attach debug grid buffer: {}", e),
+        }
+    }
+
+    /// Create or destroy the backdrop to follow `rubber_band_select`
+    ///
+    /// Surfaces stack in the order they are created, so a backdrop wanted
+    /// once icons have surfaces is made by `recreate_surfaces`.
+    fn sync_backdrop(&mut self) {
+        let wanted = self.config.rubber_band_select;
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        match self.backdrop {
+            None if wanted => match backend.create_backdrop_surface() {
+                Ok(surface_id) => {
+                    debug!("Catching presses on empty desktop with surface {}", surface_id);
+                    self.backdrop = Some(surface_id);
+                    self.backdrop_drawn = None;
+                    self.needs_render = true;
+                }
+                Err(e) => debug!("No rubber band selection: {}", e),
+            },
+            Some(surface_id) if !wanted => {
+                backend.destroy_surface(surface_id);
+                self.awaiting_configure.remove(&surface_id);
+                self.backdrop = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Give the backdrop a clear buffer of its current size
+    ///
+    /// Surfaces without a buffer are not shown and get no input. The
+    /// compositor sizes the backdrop, so it is drawn again when that changes.
+    fn render_backdrop(&mut self) {
+        let (Some(surface_id), Some(backend)) = (self.backdrop, self.backend.as_mut()) else {
+            return;
+        };
+        let Some((width, height)) = backend.surface_size(surface_id).filter(|&(w, h)| w > 0 && h > 0) else {
+            return;
+        };
+        if self.backdrop_drawn == Some((width, height)) {
+            return;
+        }
+        let Some(pixmap) = tiny_skia::Pixmap::new(width, height) else {
+            return;
+        };
+        match backend.attach_buffer(surface_id, pixmap.data(), width, height) {
+            Ok(true) => self.backdrop_drawn = Some((width, height)),
+            Ok(false) => {
+                self.awaiting_configure.insert(surface_id);
+            }
+            Err(e) => warn!("Failed to attach backdrop buffer: {}", e),
+        }
+    }
+
+    /// Handle the primary button on empty desktop
+    ///
+    /// A press clears the selection, unless Ctrl or Shift is held to add to
+    /// it, and starts a rubber band; the release selects what the band touches.
+    fn backdrop_press(&mut self, pressed: bool, x: f64, y: f64, modifiers: KeyModifiers) {
+        if !pressed {
+            if let Some(band) = self.rubber_band.take() {
+                self.finish_rubber_band(band);
+            }
+            return;
+        }
+        let Some(position) = self.backdrop_position(x, y) else {
+            return;
+        };
+        if !modifiers.ctrl && !modifiers.shift {
+            self.clear_selection();
+        }
+        self.cancel_rubber_band();
+        self.rubber_band = Some(RubberBand { origin: position, pointer: position, surface: None });
+    }
+
+    /// Output position of a point on the backdrop
+    fn backdrop_position(&self, x: f64, y: f64) -> Option<(i32, i32)> {
+        let (origin_x, origin_y) = self.backend.as_ref()?.surface_position(self.backdrop?)?;
+        Some((origin_x + x.round() as i32, origin_y + y.round() as i32))
+    }
+
+    /// Stretch the rubber band to the pointer, drawing it once it is past the drag threshold
+    ///
+    /// The backdrop keeps the pointer while the button is held, so motion over
+    /// icons still arrives on it.
+    fn update_rubber_band(&mut self, surface_id: SurfaceId, x: f64, y: f64) {
+        if self.backdrop != Some(surface_id) {
+            return;
+        }
+        let Some(pointer) = self.backdrop_position(x, y) else {
+            return;
+        };
+        let (width, height) = (self.screen_width, self.screen_height);
+        let (Some(band), Some(backend)) = (self.rubber_band.as_mut(), self.backend.as_mut()) else {
+            return;
+        };
+        band.pointer = pointer;
+        if band.surface.is_none() {
+            let (dx, dy) = (pointer.0 - band.origin.0, pointer.1 - band.origin.1);
+            if f64::from(dx).hypot(f64::from(dy)) < DRAG_THRESHOLD {
+                return;
+            }
+            match backend.create_overlay_surface(0, 0, width, height) {
+                Ok(id) => band.surface = Some(id),
+                Err(e) => {
+                    warn!("Failed to create rubber band surface: {}", e);
+                    return;
+                }
+            }
+        }
+
+        let (band_x, band_y, band_width, band_height) = band.rect();
+        let rect = tiny_skia::Rect::from_xywh(
+            band_x as f32,
+            band_y as f32,
+            band_width.max(1) as f32,
+            band_height.max(1) as f32,
+        );
+        let pixmap = match rect.map(|rect| self.renderer.render_rubber_band(width, height, rect)) {
+            Some(Ok(pixmap)) => pixmap,
+            Some(Err(e)) => {
+                warn!("Failed to render rubber band: {}", e);
+                return;
+            }
+            None => return,
+        };
+        let Some(surface_id) = band.surface else {
+            return;
+        };
+        match backend.attach_buffer(surface_id, pixmap.data(), width, height) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.awaiting_configure.insert(surface_id);
+            }
+            Err(e) => warn!("Failed to attach rubber band buffer: {}", e),
+        }
+    }
+
+    /// Select every icon whose surface the released band touches
+    ///
+    /// Each icon is its own surface, so the band is tested against the
+    /// positions and sizes the surfaces were given rather than hit-tested by
+    /// the compositor. A band never drawn was a click, which only cleared
+    /// the selection.
+    fn finish_rubber_band(&mut self, band: RubberBand) {
+        let Some(overlay) = band.surface else {
+            return;
+        };
+        if let Some(ref mut backend) = self.backend {
+            backend.destroy_surface(overlay);
+        }
+        self.awaiting_configure.remove(&overlay);
+
+        let rect = band.rect();
+        let touched: Vec<PathBuf> = match self.backend {
+            Some(ref backend) => self
+                .surface_to_path
+                .iter()
+                .filter(|&(&surface_id, _)| {
+                    let position = backend.surface_position(surface_id);
+                    let size = backend.surface_size(surface_id);
+                    position.zip(size).is_some_and(|((x, y), (width, height))| {
+                        rects_intersect(rect, (x, y, width, height))
+                    })
+                })
+                .map(|(_, path)| path.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        debug!("Rubber band selected {} icon(s)", touched.len());
+        for path in touched {
+            if let Some(icon) = self.icons.get_mut(&path) {
+                icon.set_selected(true);
+            }
+        }
+        self.needs_render = true;
+    }
+
+    /// Drop a rubber band in progress without selecting anything
+    fn cancel_rubber_band(&mut self) {
+        let Some(surface_id) = self.rubber_band.take().and_then(|band| band.surface) else {
+            return;
+        };
+        if let Some(ref mut backend) = self.backend {
+            backend.destroy_surface(surface_id);
+        }
+        self.awaiting_configure.remove(&surface_id);
+    }
+
+    /// Items for the desktop's "New" submenu
+    pub fn new_menu_items(&self) -> Vec<NewItem> {
+        templates::menu_items(templates::templates_dir().as_deref())
+    }
+
+    /// Create a new folder, empty file or template copy on the desktop
+    ///
+    /// The icon is added right away rather than when the watcher reports the
+    /// file, so it can be selected and put into inline-rename mode.
+    pub fn create_new(&mut self, item: &NewItem) -> Result<PathBuf> {
+        let path = templates::create(&self.desktop_dir, item)?;
+        info!("Created {}", path.display());
+
+        self.add_icon(&path)?;
+        if self.icons.contains_key(&path) {
+            self.select_only(&path);
+            self.start_rename(&path);
+        }
+        self.needs_render = true;
+
+        Ok(path)
+    }
+
+    /// Icon currently in inline-rename mode, if any
+    pub fn renaming(&self) -> Option<&Path> {
+        self.renaming.as_deref()
+    }
+
+    /// Start renaming the icon with the keyboard focus, or else the only selected one
+    fn rename_focused(&mut self) {
+        let path = match self.keyboard_focus.clone() {
+            Some(path) => path,
+            None => match self.selected_paths().as_slice() {
+                [path] => path.clone(),
+                _ => return,
+            },
+        };
+        self.start_rename(&path);
+    }
+
+    /// Show a text field holding `path`'s name in place of its label
+    ///
+    /// Built-in icons aren't named after their path, so they can't be renamed.
+    fn start_rename(&mut self, path: &Path) {
+        self.cancel_rename();
+        let Some(icon) = self.icons.get_mut(path) else {
+            return;
+        };
+        if matches!(icon.icon_type(), IconType::Trash | IconType::Volume) {
+            return;
+        }
+        icon.start_rename();
+        self.renaming = Some(path.to_path_buf());
+        self.dirty_icons.insert(path.to_path_buf());
+    }
+
+    /// Close the rename field without renaming
+    fn cancel_rename(&mut self) {
+        let Some(path) = self.renaming.take() else {
+            return;
+        };
+        if let Some(icon) = self.icons.get_mut(&path) {
+            icon.end_rename();
+            self.dirty_icons.insert(path);
+        }
+    }
+
+    /// Edit the name in the rename field with `keysym`
+    ///
+    /// Enter renames the file and Escape closes the field; Backspace,
+    /// Delete, the arrows, Home and End edit as in any text field.
+    fn rename_key(&mut self, keysym: u32, modifiers: KeyModifiers) {
+        let Some(path) = self.renaming.clone() else {
+            return;
+        };
+        match keysym {
+            KEY_RETURN | KEY_KP_ENTER => {
+                if let Err(e) = self.commit_rename() {
+                    warn!("Failed to rename {}: {:#}", path.display(), e);
+                }
+                return;
+            }
+            KEY_ESCAPE => return self.cancel_rename(),
+            _ => {}
+        }
+        let Some(field) = self.icons.get_mut(&path).and_then(DesktopIcon::rename_field_mut) else {
+            self.renaming = None;
+            return;
+        };
+        match keysym {
+            KEY_BACKSPACE => field.erase_back(),
+            KEY_DELETE => field.erase_forward(),
+            KEY_LEFT => field.move_cursor(-1),
+            KEY_RIGHT => field.move_cursor(1),
+            KEY_HOME => field.move_cursor_to_end(false),
+            KEY_END => field.move_cursor_to_end(true),
+            _ => match keysym_char(keysym) {
+                Some(c) if !modifiers.ctrl && !modifiers.alt => field.insert(c),
+                _ => return,
+            },
+        }
+        self.dirty_icons.insert(path);
+    }
+
+    /// Rename the file to the name typed in the rename field
+    ///
+    /// Empty names, names with a `/` and names already taken are refused,
+    /// leaving the field open and outlined in red. Otherwise the file is
+    /// renamed on disk and the watcher's rename event moves the icon along.
+    /// Returns the new path, or None if nothing was renamed.
+    fn commit_rename(&mut self) -> Result<Option<PathBuf>> {
+        let Some(path) = self.renaming.clone() else {
+            return Ok(None);
+        };
+        let Some(field) = self.icons.get_mut(&path).and_then(DesktopIcon::rename_field_mut) else {
+            self.renaming = None;
+            return Ok(None);
+        };
+        let name = field.text().to_string();
+        if path.file_name().is_some_and(|current| current == name.as_str()) {
+            self.cancel_rename();
+            return Ok(None);
+        }
+
+        let valid = !matches!(name.as_str(), "" | "." | "..") && !name.contains(['/', '\0']);
+        let target = path.with_file_name(&name);
+        if !valid || target.symlink_metadata().is_ok() {
+            debug!("Refusing to rename {} to {:?}", path.display(), name);
+            field.set_invalid();
+            self.dirty_icons.insert(path);
+            return Ok(None);
+        }
+        if let Err(e) = std::fs::rename(&path, &target) {
+            field.set_invalid();
+            self.dirty_icons.insert(path.clone());
+            return Err(e).with_context(|| format!("Failed to rename to {}", target.display()));
+        }
+
+        info!("Renamed {} to {}", path.display(), target.display());
+        self.cancel_rename();
+        Ok(Some(target))
+    }
+
+    /// Icon paths in grid layout order
+    fn grid_order(&self) -> Vec<PathBuf> {
+        let (columns, rows) = self.grid_dimensions();
+        let mut order: Vec<(u32, &PathBuf)> = self
+            .icons
+            .iter()
+            .map(|(path, icon)| (flow_rank(icon.layout_index(), columns, rows, self.config.grid_flow), path))
+            .collect();
+        order.sort();
+        order.into_iter().map(|(_, path)| path.clone()).collect()
+    }
+
+    /// Icon paths in the order they are arranged in: by `sort_order`, or
+    /// the current grid order when it is manual
+    fn arrangement_order(&self) -> Vec<PathBuf> {
+        if self.config.sort_order == SortOrder::Manual {
+            return self.grid_order();
+        }
+        sort_icons(self.icons.values(), self.config.sort_order, self.config.sort_folders_first)
+            .into_iter()
+            .map(|icon| icon.path().to_path_buf())
+            .collect()
+    }
+
+    /// Update the selection for a left click on `path`
+    ///
+    /// Ctrl toggles the icon, Shift selects the range (in grid order) from the
+    /// anchor, and Ctrl+Shift adds that range to the selection. A plain click
+    /// selects only this icon, unless it is already selected: then the selection
+    /// is kept so it can be dragged, and `true` is returned so the caller
+    /// reduces it on release.
+    fn click_select(&mut self, path: &Path, modifiers: KeyModifiers) -> bool {
+        if !self.icons.contains_key(path) {
+            return false;
+        }
+        self.needs_render = true;
+
+        if modifiers.shift {
+            let anchor = self
+                .selection_anchor
+                .clone()
+                .filter(|a| self.icons.contains_key(a))
+                .unwrap_or_else(|| path.to_path_buf());
+
+            let order = self.grid_order();
+            let from = order.iter().position(|p| *p == anchor);
+            let to = order.iter().position(|p| p == path);
+            let (start, end) = match (from, to) {
+                (Some(a), Some(b)) => (a.min(b), a.max(b)),
+                _ => return false,
+            };
+
+            if !modifiers.ctrl {
+                self.clear_selection();
+            }
+            for p in &order[start..=end] {
+                if let Some(icon) = self.icons.get_mut(p) {
+                    icon.set_selected(true);
+                }
+            }
+            return false;
+        }
+
+        self.selection_anchor = Some(path.to_path_buf());
+
+        if modifiers.ctrl {
+            if let Some(icon) = self.icons.get_mut(path) {
+                icon.set_selected(!icon.is_selected());
+            }
+            return false;
+        }
+
+        if self.icons.get(path).is_some_and(|icon| icon.is_selected()) {
+            return true;
+        }
+
+        self.select_only(path);
+        false
+    }
+
+    /// Select a single icon, deselecting all others
+    fn select_only(&mut self, path: &Path) {
+        for (p, icon) in self.icons.iter_mut() {
+            icon.set_selected(p == path);
+        }
+        self.needs_render = true;
+    }
+
+    /// Deselect all icons
+    fn clear_selection(&mut self) {
+        for icon in self.icons.values_mut() {
+            icon.set_selected(false);
+        }
+        self.needs_render = true;
+    }
+
+    /// Number of currently selected icons
+    fn selected_count(&self) -> usize {
+        self.icons.values().filter(|icon| icon.is_selected()).count()
+    }
+
+    /// Number of icons a drag starting on `path` carries
+    ///
+    /// The pressed icon always takes part, together with everything selected.
+    fn drag_count(&self, path: &Path) -> usize {
+        let pressed_selected = self.icons.get(path).is_some_and(|icon| icon.is_selected());
+        self.selected_count() + usize::from(!pressed_selected)
+    }
+
+    /// Track pointer motion while a button is held, starting a drag past the threshold
+    ///
+    /// The compositor keeps pointer focus on the pressed surface (implicit grab),
+    /// so coordinates stay relative to that surface even outside its bounds.
+    /// The dragged icons' surfaces follow the pointer; as the pressed surface
+    /// moves with it, its current position turns the coordinates back into
+    /// output ones.
+    fn handle_drag_motion(&mut self, surface_id: SurfaceId, x: f64, y: f64) {
+        let press = match self.pointer_press {
+            Some(ref press) if press.surface_id == surface_id => press.clone(),
+            _ => return,
+        };
+        let Some((surface_x, surface_y)) =
+            self.backend.as_ref().and_then(|backend| backend.surface_position(surface_id))
+        else {
+            return;
+        };
+        let pointer = (surface_x + x.round() as i32, surface_y + y.round() as i32);
+
+        if self.drag.is_none() {
+            if (x - press.x).hypot(y - press.y) < DRAG_THRESHOLD {
+                return;
+            }
+            let count = self.drag_count(&press.path);
+            debug!("Started dragging {} icon(s) from {}", count, press.path.display());
+            let grab = (surface_x + press.x.round() as i32, surface_y + press.y.round() as i32);
+            self.drag = Some(DragState {
+                count,
+                badge_surface: None,
+                icons: self.dragged_icons(&press.path),
+                grab,
+                pointer,
+            });
+        }
+
+        if let (Some(drag), Some(backend)) = (self.drag.as_mut(), self.backend.as_mut()) {
+            drag.pointer = pointer;
+            let (dx, dy) = (pointer.0 - drag.grab.0, pointer.1 - drag.grab.1);
+            for icon in &drag.icons {
+                let (x, y) = (icon.origin.0 + dx, icon.origin.1 + dy);
+                backend.set_surface_position(icon.surface_id, x, y);
+            }
+        }
+        self.update_selection_badge(pointer.0 + BADGE_OFFSET, pointer.1 + BADGE_OFFSET);
+    }
+
+    /// Icons a drag starting on `path` moves, `path` first, with their surfaces' positions
+    fn dragged_icons(&self, path: &Path) -> Vec<DraggedIcon> {
+        let Some(backend) = self.backend.as_ref() else {
+            return Vec::new();
+        };
+        let others = self.selected_paths().into_iter().filter(|p| p != path);
+        std::iter::once(path.to_path_buf())
+            .chain(others)
+            .filter_map(|path| {
+                let surface_id = *self.path_to_surface.get(&path)?;
+                let origin = backend.surface_position(surface_id)?;
+                Some(DraggedIcon { path, surface_id, origin })
+            })
+            .collect()
+    }
+
+    /// Show or move the selection count badge for a multi-icon drag
+    fn update_selection_badge(&mut self, x: i32, y: i32) {
+        let (Some(drag), Some(backend)) = (self.drag.as_mut(), self.backend.as_mut()) else {
+            return;
+        };
+        if drag.count < 2 {
+            return;
+        }
+
+        let surface_id = match drag.badge_surface {
+            Some(id) => {
+                backend.set_surface_position(id, x, y);
+                id
+            }
+            None => match backend.create_overlay_surface(x, y, SELECTION_BADGE_SIZE, SELECTION_BADGE_SIZE) {
+                Ok(id) => {
+                    drag.badge_surface = Some(id);
+                    id
+                }
+                Err(e) => {
+                    warn!("Failed to create selection badge surface: {}", e);
+                    return;
+                }
+            },
+        };
+
+        // Re-attach on every move; the first attempts land before the surface is configured
+        match self.renderer.render_selection_badge(drag.count) {
+            Ok(pixmap) => {
+                if let Err(e) = backend.attach_buffer(
+                    surface_id,
+                    pixmap.data(),
+                    SELECTION_BADGE_SIZE,
+                    SELECTION_BADGE_SIZE,
+                ) {
+                    warn!("Failed to attach selection badge buffer: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to render selection badge: {}", e),
+        }
+    }
+
+    /// Finish the current drag (on drop), moving the icons to where they
+    /// were dropped, and hide the selection badge
+    fn end_drag(&mut self) {
+        if let Some(drag) = self.take_drag() {
+            debug!("Dropped {} icon(s)", drag.count);
+            self.drop_icons(&drag);
+        }
+    }
+
+    /// Abandon the current drag, putting its icons back in their cells
+    fn cancel_drag(&mut self) {
+        if self.take_drag().is_some() {
+            debug!("Cancelled the drag");
+            self.reposition_all_icons();
+        }
+    }
+
+    /// Stop the current drag and hide its selection badge
+    fn take_drag(&mut self) -> Option<DragState> {
+        let drag = self.drag.take()?;
+        if let (Some(id), Some(backend)) = (drag.badge_surface, self.backend.as_mut()) {
+            backend.destroy_surface(id);
+        }
+        Some(drag)
+    }
+
+    /// Place the dragged icons in the cells they were dropped on
+    ///
+    /// The pressed icon goes to the cell nearest where it was dropped, and
+    /// the others move by as many cells as it did, keeping their arrangement.
+    /// An icon landing on a cell another icon was placed in moves on to the
+    /// next free cell. With `snap_to_grid` off, each icon also remembers how
+    /// far from its cell it was dropped. Icons that would land outside the
+    /// grid, and icons pinned to a corner, go back to where they were.
+    fn drop_icons(&mut self, drag: &DragState) {
+        let (columns, rows) = self.grid_dimensions();
+        let area = self.grid_area();
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = self.config.icon_size + self.label_height() + self.config.grid_spacing;
+        let (cell, area_size) = ((cell_width, cell_height), (area.width, area.height));
+        let corner = self.config.grid_corner;
+        let shift = (drag.pointer.0 - drag.grab.0, drag.pointer.1 - drag.grab.1);
+        let dropped_at = |icon: &DraggedIcon| (icon.origin.0 + shift.0, icon.origin.1 + shift.1);
+
+        // Surfaces sit in their cells as `place_icon` puts them, mirrored
+        // from the grid corner
+        let target = drag.icons.first().and_then(|pressed| {
+            let (x, y) = dropped_at(pressed);
+            let (x, y) = mirror_to_corner(corner, (x - area.x, y - area.y), cell, area_size);
+            cell_at(x + cell_width as i32 / 2, y + cell_height as i32 / 2, cell_width, cell_height)
+        });
+        let target = target.filter(|&(col, row)| col < columns && row < rows);
+        let pressed = drag.icons.first().and_then(|icon| self.icons.get(&icon.path));
+        let (Some((col, row)), Some(pressed)) = (target, pressed) else {
+            self.reposition_all_icons();
+            return;
+        };
+        let from = pressed.layout_index();
+        let delta = (
+            i64::from(col) - i64::from(from % columns),
+            i64::from(row) - i64::from(from / columns),
+        );
+        // Dropped back in its own cell: nothing moves, and nothing gets placed
+        if delta == (0, 0) && self.config.snap_to_grid {
+            self.reposition_all_icons();
+            return;
+        }
+
+        // Every target is worked out before placing any icon reflows the rest
+        let moves: Vec<(PathBuf, i64, i64, (i32, i32))> = drag
+            .icons
+            .iter()
+            .filter_map(|dragged| {
+                let icon = self.icons.get(&dragged.path)?;
+                if icon.pinned_corner().is_some() {
+                    return None;
+                }
+                let index = icon.layout_index();
+                let col = i64::from(index % columns) + delta.0;
+                let row = i64::from(index / columns) + delta.1;
+                Some((dragged.path.clone(), col, row, dropped_at(dragged)))
+            })
+            .collect();
+        let dragged: HashSet<&Path> = drag.icons.iter().map(|icon| icon.path.as_path()).collect();
+        let keep_clear = self.keep_clear_slots();
+        let mut occupied: HashSet<u32> = self
+            .icons
+            .iter()
+            .filter(|(path, _)| !dragged.contains(path.as_path()))
+            .filter_map(|(_, icon)| self.placed_slot(icon, &keep_clear))
+            .collect();
+        occupied.extend(keep_clear);
+
+        for (path, col, row, (x, y)) in moves {
+            let cell_index = u32::try_from(col)
+                .ok()
+                .zip(u32::try_from(row).ok())
+                .filter(|&(col, row)| col < columns && row < rows)
+                .map(|(col, row)| Cell { col, row }.index(columns));
+            let Some(slot) = cell_index.and_then(|index| self.free_slot_from(index, &occupied)) else {
+                debug!("Not moving {}: no free cell from ({}, {})", path.display(), col, row);
+                continue;
+            };
+            occupied.insert(slot);
+            self.placements.set(&path, Some(Cell { col: slot % columns, row: slot / columns }));
+            if !self.config.snap_to_grid {
+                let (cell_x, cell_y) = grid_cell_origin(slot, columns, cell, area, corner);
+                self.placements.set_offset(&path, (x - cell_x, y - cell_y));
+            }
+        }
+        if let Err(e) = self.placements.save() {
+            warn!("Failed to save icon placements: {:#}", e);
+        }
+        self.reposition_all_icons();
+        self.needs_render = true;
+    }
+
+    /// First cell from layout index `slot` on, in fill order, that isn't `occupied`
+    fn free_slot_from(&self, slot: u32, occupied: &HashSet<u32>) -> Option<u32> {
+        let (columns, rows) = self.grid_dimensions();
+        let flow = self.config.grid_flow;
+        (flow_rank(slot, columns, rows, flow)..columns * rows)
+            .map(|rank| flow_slot(rank, columns, rows, flow))
+            .find(|slot| !occupied.contains(slot))
+    }
+
+    /// Whether a new frame may be rendered now
+    ///
+    /// With frame-callback pacing we render once the compositor has consumed the
+    /// previous frame on some output (or the callback timed out); with timer
+    /// pacing only when the render timer ticked.
+    fn frame_due(&self, render_tick: bool) -> bool {
+        match self.config.frame_pacing {
+            FramePacing::Timer => render_tick,
+            FramePacing::FrameCallback => {
+                let Some(backend) = self.backend.as_ref() else {
+                    return true;
+                };
+                !backend.frame_callback_pending()
+                    || self.last_render.elapsed() >= FRAME_CALLBACK_TIMEOUT
+                    || self
+                        .path_to_surface
+                        .values()
+                        .any(|&surface_id| self.frame_group_ready(backend.frame_group(surface_id)))
+            }
+        }
+    }
+
+    /// Whether icons in a frame group may be drawn to now
+    ///
+    /// With frame-callback pacing, once the group's output consumed its last
+    /// frame or kept us waiting past `FRAME_CALLBACK_TIMEOUT`, so a slow or
+    /// hidden output does not hold back the others. Always with timer pacing.
+    fn frame_group_ready(&self, group: FrameGroup) -> bool {
+        if self.config.frame_pacing == FramePacing::Timer {
+            return true;
+        }
+        let Some(backend) = self.backend.as_ref() else {
+            return true;
+        };
+        !backend.frame_group_pending(group)
+            || self
+                .frame_group_renders
+                .get(&group)
+                .is_none_or(|drawn| drawn.elapsed() >= FRAME_CALLBACK_TIMEOUT)
+    }
+
+    /// Dispatch backend events
+    ///
+    /// This only reads what has arrived; while idle the loop waits for the
+    /// display in `EventLoop::dispatch`, on the display fd (see `loop_timeout`).
+    fn dispatch_backend(&mut self) {
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        if let Err(e) = backend.dispatch_events() {
+            error!("Backend dispatch error: {}", e);
+        }
+        let configured = backend.take_newly_configured();
+        let resized = !backend.take_resized().is_empty();
+
+        self.note_configured(&configured);
+        if resized {
+            // Buffers must follow the compositor's new size
+            self.needs_render = true;
+        }
+    }
+
+    /// Whether nothing needs drawing and nothing is animating
+    fn is_idle(&self) -> bool {
+        !self.needs_render
+            && self.dirty_icons.is_empty()
+            && self.appearing.is_empty()
+            && self.disappearing.is_empty()
+            && self.spotlight.is_none()
+            && self.tooltip.as_ref().is_none_or(|tooltip| tooltip.due.is_none())
+            && self.idle_wake.is_none()
+            && self.context_menu.as_ref().is_none_or(|menu| menu.left_since.is_none())
+            && self.thumbnailer.as_ref().is_none_or(|thumbnailer| !thumbnailer.is_busy())
+            && !self.icons.values().any(DesktopIcon::is_flashing)
+    }
+
+    /// How long the main loop may sleep waiting for an event source
+    ///
+    /// With frame-callback pacing, an idle daemon the display can wake
+    /// sleeps until something happens: display events, watcher events,
+    /// control commands, or the update timer every `update_interval_ms`,
+    /// but no longer than a nonzero `dispatch_timeout_ms`. Otherwise, and
+    /// while a long press is being timed, it wakes every frame.
+    fn loop_timeout(&self, frame_interval: Duration, display_wakes: bool) -> Option<Duration> {
+        let timing_long_press =
+            self.config.long_press_ms.is_some() && self.pointer_press.is_some();
+        let sleep = display_wakes
+            && self.config.frame_pacing == FramePacing::FrameCallback
+            && self.is_idle()
+            && !timing_long_press;
+        if sleep {
+            let bound = self.config.dispatch_timeout_ms;
+            (bound > 0).then(|| Duration::from_millis(bound))
+        } else {
+            Some(frame_interval)
+        }
+    }
+
+    /// Unmap icon surfaces while a window covers the desktop, and remap them after
+    fn update_covered(&mut self) {
+        let covered = self.config.hide_when_covered
+            && self.backend.as_ref().is_some_and(|backend| backend.desktop_covered());
+        if covered == self.covered {
+            return;
+        }
+        self.covered = covered;
+        info!("Desktop {}", if covered { "covered, hiding icons" } else { "exposed, showing icons" });
+        if covered {
+            self.close_spotlight();
+            self.collapse_label();
+            self.close_tooltip();
+        }
+
+        let Some(ref mut backend) = self.backend else {
+            return;
+        };
+        for &surface_id in self.surface_to_path.keys().chain(self.empty_hint.iter()) {
+            backend.set_surface_mapped(surface_id, !covered);
+        }
+        if !covered {
+            // Remapped surfaces start without a buffer, so every icon is drawn in full
+            for icon in self.icons.values_mut() {
+                icon.clear_presented_frame();
+            }
+            self.needs_render = true;
+        }
+    }
+
+    /// Re-render if any surface we failed to draw to has now been configured
+    fn note_configured(&mut self, surfaces: &[SurfaceId]) {
+        for surface_id in surfaces {
+            if self.awaiting_configure.remove(surface_id) {
+                debug!("Surface {} configured, retrying render", surface_id);
+                match self.surface_to_path.get(surface_id).cloned() {
+                    Some(path) => self.mark_dirty(&path),
+                    None => self.mark_all_dirty(),
+                }
+            }
+        }
+    }
+
+    /// Check if the backend wants the daemon to stop
+    pub fn should_exit(&self) -> bool {
+        self.quit_requested || self.backend.as_ref().is_some_and(|backend| backend.should_exit())
+    }
+
+    /// Update screen dimensions from the backend's output and reposition icons if changed
+    ///
+    /// `outputs_changed` repositions them even if the size is the same, as
+    /// after the output's scale changed.
+    fn update_screen_dimensions(&mut self, outputs_changed: bool) {
+        let (reported, new_name, work_area, outputs) = if let Some(ref backend) = self.backend {
+            let outputs = backend.output_dimensions_all();
+            let (dimensions, name) = (backend.get_output_dimensions(), backend.get_output_name());
+            (dimensions, name, backend.get_work_area(), outputs)
+        } else {
+            return;
+        };
+        let (new_width, new_height) = reported.unwrap_or((self.screen_width, self.screen_height));
+
+        if !is_usable_output(new_width, new_height) {
+            if new_width != self.screen_width || new_height != self.screen_height {
+                debug!("Ignoring degenerate output size {}x{}", new_width, new_height);
+            }
+            return;
+        }
+
+        if reported.is_some() && !self.dimensions_known {
+            debug!("Output reported its size: {}x{}", new_width, new_height);
+            self.dimensions_known = true;
+            self.mark_all_dirty();
+        }
+
+        // Panels appeared, went away or changed size
+        if work_area != self.work_area {
+            match work_area {
+                Some((width, height)) => info!("Anchoring icons to the {}x{} work area", width, height),
+                None => info!("Work area unknown, anchoring icons to the output"),
+            }
+            self.work_area = work_area;
+            self.reposition_all_icons();
+            self.mark_all_dirty();
+        }
+
+        // A different output may have different reserved edges
+        if new_name != self.output_name {
+            info!("Placing icons on output {:?}", new_name);
+            // Until the output's name is known, icons are already on it
+            let moved = self.output_name.is_some();
+            self.output_name = new_name;
+            if moved {
+                self.recreate_surfaces();
+            } else {
+                self.reposition_all_icons();
+            }
+            self.mark_all_dirty();
+        }
+
+        // Outputs plugged in or unplugged change where pinned and overflowing icons go
+        let secondary: Vec<(String, u32, u32)> = outputs
+            .into_iter()
+            .filter(|(name, width, height)| {
+                Some(name) != self.output_name.as_ref() && is_usable_output(*width, *height)
+            })
+            .collect();
+        if secondary != self.secondary_outputs {
+            let names: Vec<&str> = secondary.iter().map(|(name, _, _)| name.as_str()).collect();
+            match self.config.span_outputs {
+                true => info!("Icons overflowing the grid continue on outputs {:?}", names),
+                false => debug!("Other outputs connected: {:?}", names),
+            }
+            self.secondary_outputs = secondary;
+            self.reposition_all_icons();
+            self.mark_all_dirty();
+        }
+
+        // Check if dimensions changed
+        let resized = new_width != self.screen_width || new_height != self.screen_height;
+        if resized {
+            info!(
+                "Screen dimensions changed from {}x{} to {}x{}",
+                self.screen_width, self.screen_height, new_width, new_height
+            );
+            self.screen_width = new_width;
+            self.screen_height = new_height;
+        }
+        if resized || outputs_changed {
+            // Reposition all icons
+            self.reposition_all_icons();
+            self.mark_all_dirty();
+        }
+    }
+
+    /// Stop waiting for the output size once `OUTPUT_WAIT_TIMEOUT` has passed
+    ///
+    /// Some compositors never report a size; icons are then shown laid out
+    /// for the placeholder size rather than not at all.
+    fn wait_for_output(&mut self, now: Instant) {
+        if self.dimensions_known || now.duration_since(self.created) < OUTPUT_WAIT_TIMEOUT {
+            return;
+        }
+        warn!(
+            "Output did not report its size within {:?}, assuming {}x{}",
+            OUTPUT_WAIT_TIMEOUT, self.screen_width, self.screen_height
+        );
+        self.dimensions_known = true;
+        self.mark_all_dirty();
+    }
+
+    /// Area of the current output available to the icon grid
+    ///
+    /// Surfaces are placed relative to the work area while it is known, so
+    /// the grid then starts at the origin and reserved edges are not applied.
+    fn grid_area(&self) -> UsableArea {
+        match self.work_area {
+            Some((width, height)) => UsableArea { x: 0, y: 0, width, height },
+            None => self
+                .config
+                .reserved_edges_for(self.output_name.as_deref())
+                .usable_area(self.screen_width, self.screen_height),
+        }
+    }
+
+    /// Columns and rows of the icon grid in the usable area
+    fn grid_dimensions(&self) -> (u32, u32) {
+        let area = self.grid_area();
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = self.config.icon_size + self.label_height() + self.config.grid_spacing;
+        let (columns, rows) = grid_size(area.width, area.height, cell_width, cell_height);
+        (columns, self.config.max_rows.map_or(rows, |max| rows.min(max.max(1))))
+    }
+
+    /// Layout index of the `n`th cell unplaced icons fill, following `grid_flow`
+    ///
+    /// Cells in `keep_clear` are skipped.
+    fn nth_slot(&self, n: u32, keep_clear: &HashSet<u32>) -> u32 {
+        let (columns, rows) = self.grid_dimensions();
+        free_flow_slot(n, columns, rows, self.config.grid_flow, keep_clear)
+    }
+
+    /// Layout indices of the grid cells overlapping a keep-clear region
+    ///
+    /// Every region is tested against every cell, so a layout pass computes
+    /// this once and hands it to `nth_slot`, `placed_slot` and `free_cells`.
+    fn keep_clear_slots(&self) -> HashSet<u32> {
+        let regions = self.config.keep_clear_regions_for(self.output_name.as_deref());
+        let (columns, rows) = self.grid_dimensions();
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = self.config.icon_size + self.label_height() + self.config.grid_spacing;
+        let cell_size = (cell_width, cell_height);
+        keep_clear_cells(regions, self.grid_area(), (columns, rows), cell_size, self.config.grid_corner)
+    }
+
+    /// Secondary output the icon for `path` is pinned to, while it is connected
+    ///
+    /// Pinning to a corner wins over pinning to an output.
+    fn pinned_output(&self, path: &Path) -> Option<&str> {
+        let output = self.placements.output(path)?;
+        let connected = self.secondary_outputs.iter().any(|(name, _, _)| name == output);
+        let flows = self.icons.get(path).is_some_and(|icon| icon.pinned_corner().is_none());
+        (connected && flows).then_some(output)
+    }
+
+    /// Where icons go on the secondary outputs
+    ///
+    /// Each output gets a grid of its own, minus its reserved edges and
+    /// keep-clear regions, filled following `grid_flow`: first with the icons
+    /// pinned to it, then with `span_outputs` with those past the primary
+    /// output's grid, each in the order of `slots`. Icons that fit on none of
+    /// them are left out.
+    fn span_positions(
+        &self,
+        slots: &[(&Path, u32)],
+    ) -> HashMap<PathBuf, (String, crate::ipc::Position)> {
+        let mut spans = HashMap::new();
+        if self.secondary_outputs.is_empty() {
+            return spans;
+        }
+        let (columns, rows) = self.grid_dimensions();
+        let mut overflowing: Vec<(u32, &Path)> = slots
+            .iter()
+            .filter(|(path, index)| {
+                self.config.span_outputs
+                    && *index >= columns * rows
+                    && self.icons.get(*path).is_some_and(|icon| icon.pinned_corner().is_none())
+                    && self.pinned_output(path).is_none()
+            })
+            .map(|&(path, index)| (index, path))
+            .collect();
+        overflowing.sort();
+        let mut overflowing = overflowing.into_iter();
+
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = self.config.icon_size + self.label_height() + self.config.grid_spacing;
+        for (name, width, height) in &self.secondary_outputs {
+            let area = self.config.reserved_edges_for(Some(name)).usable_area(*width, *height);
+            let (columns, rows) = grid_size(area.width, area.height, cell_width, cell_height);
+            let rows = self.config.max_rows.map_or(rows, |max| rows.min(max.max(1)));
+            let regions = self.config.keep_clear_regions_for(Some(name));
+            let cell_size = (cell_width, cell_height);
+            let corner = self.config.grid_corner;
+            let blocked = keep_clear_cells(regions, area, (columns, rows), cell_size, corner);
+            let free = (columns * rows).saturating_sub(blocked.len() as u32);
+            let pinned = slots
+                .iter()
+                .filter(|(path, _)| self.pinned_output(path) == Some(name.as_str()))
+                .map(|&(path, _)| path);
+            // The range runs out first, leaving the rest for the next output
+            let icons = pinned.chain(overflowing.by_ref().map(|(_, path)| path));
+            for (n, path) in (0..free).zip(icons) {
+                let slot = free_flow_slot(n, columns, rows, self.config.grid_flow, &blocked);
+                let (x, y) = grid_cell_origin(slot, columns, cell_size, area, corner);
+                let position = crate::ipc::Position { x, y };
+                spans.insert(path.to_path_buf(), (name.clone(), position));
+            }
+        }
+        spans
+    }
+
+    /// Number of grid cells icons may fill, leaving out those in `keep_clear`
+    fn free_cells(&self, keep_clear: &HashSet<u32>) -> u32 {
+        let (columns, rows) = self.grid_dimensions();
+        (columns * rows).saturating_sub(keep_clear.len() as u32)
+    }
+
+    /// Layout index of the cell an icon was placed at, if it fits the current
+    /// grid and isn't in `keep_clear`
+    fn placed_slot(&self, icon: &DesktopIcon, keep_clear: &HashSet<u32>) -> Option<u32> {
+        if icon.pinned_corner().is_some() {
+            return None;
+        }
+        let (columns, rows) = self.grid_dimensions();
+        self.placements
+            .get(icon.path())
+            .filter(|cell| cell.col < columns && cell.row < rows)
+            .map(|cell| cell.index(columns))
+            .filter(|slot| !keep_clear.contains(slot))
+    }
+
+    /// Icons to stack in the overflow pile, bottom first, from each icon's layout index
+    ///
+    /// The pile is the icon in the grid's last cell and every icon past the
+    /// grid. It is empty unless `overflow_pile` is enabled, some icon
+    /// overflows and the pile would reach the threshold.
+    fn overflow_pile<'a>(
+        &self,
+        slots: impl Iterator<Item = (&'a Path, &'a u32)>,
+        keep_clear: &HashSet<u32>,
+    ) -> Vec<PathBuf> {
+        if !self.config.overflow_pile.enabled {
+            return Vec::new();
+        }
+        let (columns, rows) = self.grid_dimensions();
+        let capacity = columns * rows;
+        let last = self.free_cells(keep_clear).checked_sub(1).map(|n| self.nth_slot(n, keep_clear));
+        let Some(last) = last else {
+            return Vec::new();
+        };
+        let mut members: Vec<(u32, &Path)> = slots
+            .filter(|(path, _)| {
+                self.icons.get(*path).is_some_and(|icon| icon.pinned_corner().is_none())
+            })
+            .filter(|&(_, &index)| index == last || index >= capacity)
+            .map(|(path, &index)| (index, path))
+            .collect();
+        let overflowing = members.iter().any(|&(index, _)| index >= capacity);
+        if !overflowing || (members.len() as u32) < self.config.overflow_pile.threshold {
+            return Vec::new();
+        }
+        members.sort_by_key(|&(index, _)| index);
+        members.into_iter().map(|(_, path)| path.to_path_buf()).collect()
+    }
+
+    /// Where the `k`th icon of the overflow pile goes
+    ///
+    /// Collapsed, the pile is stacked in the grid's last cell, each icon
+    /// `offset` up and left of the one beneath. Fanned out, its icons take
+    /// the cells before that one, backwards in fill order.
+    fn pile_position(
+        &self,
+        k: u32,
+        area: UsableArea,
+        cell_width: u32,
+        cell_height: u32,
+        keep_clear: &HashSet<u32>,
+    ) -> crate::ipc::Position {
+        let (columns, _) = self.grid_dimensions();
+        let free = self.free_cells(keep_clear).max(1);
+        let cell_size = (cell_width, cell_height);
+        let origin = |slot| grid_cell_origin(slot, columns, cell_size, area, self.config.grid_corner);
+        if self.pile_fanned && k < free {
+            let (x, y) = origin(self.nth_slot(free - 1 - k, keep_clear));
+            return crate::ipc::Position { x, y };
+        }
+        let step = (k.min(PILE_MAX_STEPS) * self.config.overflow_pile.offset) as i32;
+        let (x, y) = origin(self.nth_slot(free - 1, keep_clear));
+        crate::ipc::Position { x: x - step, y: y - step }
+    }
+
+    /// Fan the overflow pile out on a press on it, or collapse it on a press elsewhere
+    ///
+    /// Returns whether the press was used up fanning the pile out.
+    fn pile_press(&mut self, surface_id: SurfaceId) -> bool {
+        if self.pile.is_empty() {
+            return false;
+        }
+        let on_pile = self.surface_to_path.get(&surface_id).is_some_and(|path| self.pile.contains(path));
+        if on_pile == self.pile_fanned {
+            return false;
+        }
+        debug!("{} the overflow pile", if on_pile { "Fanning out" } else { "Collapsing" });
+        self.pile_fanned = on_pile;
+        self.reposition_all_icons();
+        self.needs_render = true;
+        on_pile
+    }
+
+    /// Move an icon to grid cell (`col`, `row`) and remember it there
+    ///
+    /// An icon previously placed in that cell loses its placement and flows
+    /// into the next free cell along with the other unplaced icons.
+    pub fn place_icon(&mut self, path: &Path, col: u32, row: u32) -> Result<()> {
+        let icon = self
+            .icons
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("No icon for {}", path.display()))?;
+        if icon.pinned_corner().is_some() {
+            anyhow::bail!("{} is pinned to a corner", path.display());
+        }
+        let (columns, rows) = self.grid_dimensions();
+        if col >= columns || row >= rows {
+            anyhow::bail!("Cell ({}, {}) is outside the {}x{} grid", col, row, columns, rows);
+        }
+        let cell = Cell { col, row };
+        if self.keep_clear_slots().contains(&cell.index(columns)) {
+            anyhow::bail!("Cell ({}, {}) is in a keep-clear region", col, row);
+        }
+
+        for evicted in self.placements.at(cell) {
+            debug!("Evicting {} from cell ({}, {})", evicted.display(), col, row);
+            self.placements.set(&evicted, None);
+        }
+        self.placements.set(path, Some(cell));
+        if let Err(e) = self.placements.save() {
+            warn!("Failed to save icon placements: {:#}", e);
+        }
+
+        self.reposition_all_icons();
+        self.needs_render = true;
+        Ok(())
+    }
+
+    /// Pin the icon for `path` to the output named `output`, or unpin it with None
+    ///
+    /// While that output is disconnected the icon stays on the primary one,
+    /// and it moves back once the output reappears.
+    pub fn pin_to_output(&mut self, path: &Path, output: Option<&str>) -> Result<()> {
+        let icon = self
+            .icons
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("No icon for {}", path.display()))?;
+        if icon.pinned_corner().is_some() {
+            anyhow::bail!("{} is pinned to a corner", path.display());
+        }
+        self.placements.set_output(path, output);
+        if let Err(e) = self.placements.save() {
+            warn!("Failed to save icon placements: {:#}", e);
+        }
+
+        self.reposition_all_icons();
+        self.needs_render = true;
+        Ok(())
+    }
+
+    /// Current cell of every desktop icon, keyed by file name
+    ///
+    /// Corner-pinned icons and icons outside the desktop directory (trash,
+    /// volumes) are not part of the arrangement. Icons pinned to an output
+    /// are listed with it.
+    pub fn export_layout(&self) -> Layout {
+        let (columns, _) = self.grid_dimensions();
+        let icons = self
+            .icons
+            .iter()
+            .filter(|(_, icon)| icon.pinned_corner().is_none())
+            .filter_map(|(path, icon)| {
+                let name = path.strip_prefix(&self.desktop_dir).ok()?.to_str()?.to_string();
+                let index = icon.layout_index();
+                Some((name, Cell { col: index % columns, row: index / columns }))
+            })
+            .collect();
+        let outputs = self
+            .icons
+            .keys()
+            .filter_map(|path| {
+                let output = self.placements.output(path)?.to_string();
+                Some((path.strip_prefix(&self.desktop_dir).ok()?.to_str()?.to_string(), output))
+            })
+            .collect();
+        Layout { outputs, ..Layout::new(icons) }
+    }
+
+    /// Place every icon named in `layout`, and pin those it lists an output for
+    ///
+    /// Names without an icon on this desktop, pinned icons and cells outside
+    /// the current grid or in a keep-clear region are skipped and returned.
+    /// Icons placed in a cell the layout reuses lose their old placement.
+    pub fn import_layout(&mut self, layout: &Layout) -> Vec<String> {
+        let (columns, rows) = self.grid_dimensions();
+        let keep_clear = self.keep_clear_slots();
+        let mut skipped = Vec::new();
+
+        for (name, &cell) in &layout.icons {
+            let path = self.desktop_dir.join(name);
+            let placeable = self.icons.get(&path).is_some_and(|icon| icon.pinned_corner().is_none());
+            let outside = cell.col >= columns || cell.row >= rows;
+            if !placeable || outside || keep_clear.contains(&cell.index(columns)) {
+                debug!("Skipping {} from imported layout", name);
+                skipped.push(name.clone());
+                continue;
+            }
+            for evicted in self.placements.at(cell) {
+                self.placements.set(&evicted, None);
+            }
+            self.placements.set(&path, Some(cell));
+        }
+        for (name, output) in &layout.outputs {
+            let path = self.desktop_dir.join(name);
+            if self.icons.get(&path).is_some_and(|icon| icon.pinned_corner().is_none()) {
+                self.placements.set_output(&path, Some(output));
+            }
+        }
+
+        if let Err(e) = self.placements.save() {
+            warn!("Failed to save icon placements: {:#}", e);
+        }
+        self.reposition_all_icons();
+        self.needs_render = true;
+        skipped
+    }
+
+    /// Flash the icon for `path` to draw attention to it
+    ///
+    /// If there is no icon for `path` yet, as while a download is still being
+    /// written, the flash starts once it appears (if within
+    /// `PENDING_FLASH_TIMEOUT`). Returns whether the icon flashes right away.
+    pub fn flash_icon(&mut self, path: &Path) -> bool {
+        let duration = self.flash_duration();
+        match self.icons.get_mut(path) {
+            Some(icon) => {
+                icon.start_flash(Instant::now(), duration);
+                self.needs_render = true;
+                true
+            }
+            None => {
+                debug!("No icon for {} yet, flashing it once it appears", path.display());
+                self.pending_flashes.insert(path.to_path_buf(), Instant::now());
+                false
+            }
+        }
+    }
+
+    fn flash_duration(&self) -> Duration {
+        Duration::from_millis(self.config.flash.duration_ms)
+    }
+
+    /// Let SIGUSR1 cycle the log level while `run` is looping
+    ///
+    /// The signal must already be blocked with `signals::block`.
+    pub fn set_log_control(&mut self, control: LogControl) {
+        self.log_control = Some(control);
+    }
+
+    /// Let SIGHUP reload the configuration while `run` is looping
+    ///
+    /// `path` is the file the daemon was started with, or `None` for the
+    /// default location. Saving that file reloads it too. The signal must
+    /// already be blocked with `signals::block`.
+    pub fn enable_reload(&mut self, path: Option<PathBuf>) {
+        self.reload_on_sighup = true;
+        self.config_path = path;
+    }
+
+    /// Configuration file whose edits reload it, if reloading is enabled
+    fn config_file(&self) -> Option<PathBuf> {
+        if !self.reload_on_sighup {
+            return None;
+        }
+        self.config_path.clone().or_else(Config::default_file)
+    }
+
+    /// Let SIGINT and SIGTERM end `run` through `shutdown`
+    ///
+    /// The signals must already be blocked with `signals::block`.
+    pub fn enable_stop_signals(&mut self) {
+        self.stop_on_signals = true;
+    }
+
+    /// Stop every widget and destroy every surface
+    ///
+    /// Called when `run` returns; embedders driving `step` themselves call it
+    /// before dropping the daemon, and should not step it afterwards.
+    pub fn shutdown(&mut self) {
+        info!("Shutting down {} icons", self.icons.len());
+        for icon in self.icons.values_mut() {
+            icon.kill_lua_process();
+        }
+        self.take_drag();
+        self.close_delete_prompt();
+        self.close_context_menu();
+        self.close_spotlight();
+        self.collapse_label();
+        self.close_tooltip();
+        self.cancel_rubber_band();
+        self.drop_icon_surfaces();
+        if let Some(ref mut backend) = self.backend {
+            let overlays = self.empty_hint.take().into_iter().chain(self.debug_grid.take());
+            let overlays = overlays.chain(self.backdrop.take());
+            let fading = self.disappearing.drain(..).map(|fade| fade.surface_id);
+            for surface_id in overlays.chain(fading) {
+                backend.destroy_surface(surface_id);
+            }
+            backend.flush();
+        }
+    }
+
+    /// Re-read the configuration file and restart every widget
+    ///
+    /// Widgets are restarted even if no setting changed, so edited scripts
+    /// take effect. A file that fails to load or apply leaves the current
+    /// configuration and widgets running. Command-line overrides
+    /// (`trace_fs_events`, `debug_grid`) carry over.
+    pub fn reload(&mut self) -> Result<()> {
+        let mut config = Config::load(self.config_path.as_deref())
+            .context("Reload failed, keeping the current configuration")?;
+        config.trace_fs_events = self.config.trace_fs_events;
+        config.debug_grid = self.config.debug_grid;
+
+        let changed = reload::changed_settings(&self.config, &config);
+        self.apply_config(config)
+            .context("Reload failed to apply the new configuration")?;
+        let restarted = self.restart_widgets();
+
+        let changed = if changed.is_empty() { "none".to_string() } else { changed.join(", ") };
+        info!("Reloaded configuration (changed: {}); restarted {} widget(s)", changed, restarted);
+        Ok(())
+    }
+
+    /// Restart every icon's widget from the scripts `script_dirs` resolves to now
+    ///
+    /// Returns how many widgets are running afterwards.
+    fn restart_widgets(&mut self) -> usize {
+        // Say again if the handler is missing, even from the same directories
+        self.warned_missing_handler = false;
+        let paths: Vec<PathBuf> = self.icons.keys().cloned().collect();
+        let mut running = 0;
+        for path in paths {
+            let Some(icon_type) = self.icons.get(&path).map(|icon| icon.icon_type()) else {
+                continue;
+            };
+            let scripts = self.find_script_for_icon(icon_type);
+            let status = match scripts {
+                Some(_) => WidgetStatus::Ok,
+                None => self.missing_script_status(icon_type),
+            };
+            let Some(icon) = self.icons.get_mut(&path) else {
+                continue;
+            };
+            icon.set_script_status(status);
+            let scripts =
+                scripts.as_ref().map(|(handler, script)| (handler.as_path(), script.as_path()));
+            match icon.restart_widget(scripts, &self.config) {
+                Ok(()) if icon.has_lua_process() => running += 1,
+                Ok(()) => {}
+                Err(e) => {
+                    warn!(
+                        "Failed to restart widget for {}: {} (using fallback)",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        self.needs_render = true;
+        running
+    }
+
+    /// Switch to the next log level
+    fn cycle_log_level(&mut self) {
+        if let Some(ref mut control) = self.log_control {
+            if let Err(e) = control.cycle() {
+                warn!("{:#}", e);
+            }
+        }
+    }
+
+    /// Run a command received on the control socket
+    pub fn handle_control(&mut self, command: ControlCommand) -> ControlReply {
+        debug!("Control command: {:?}", command);
+        match command {
+            ControlCommand::Place { path, col, row } => self.place_icon(&path, col, row).into(),
+            ControlCommand::PinOutput { path, output } => self.pin_to_output(&path, output.as_deref()).into(),
+            ControlCommand::ExportLayout => match serde_json::to_value(self.export_layout()) {
+                Ok(data) => ControlReply::data(data),
+                Err(e) => ControlReply::error(e.to_string()),
+            },
+            ControlCommand::ImportLayout { data } => match Layout::from_json(data) {
+                Ok(layout) => {
+                    let skipped = self.import_layout(&layout);
+                    ControlReply::data(serde_json::json!({ "skipped": skipped }))
+                }
+                Err(e) => ControlReply::error(format!("{:#}", e)),
+            },
+            ControlCommand::Ping => self.health().reply(),
+            ControlCommand::DebugGrid { enabled } => {
+                let enabled = enabled.unwrap_or(!self.config.debug_grid);
+                self.set_debug_grid(enabled);
+                ControlReply::data(serde_json::json!({ "enabled": enabled }))
+            }
+            ControlCommand::LuaStatus => ControlReply::data(self.lua_status()),
+            ControlCommand::Pause => {
+                self.pause();
+                ControlReply::ok()
+            }
+            ControlCommand::Resume => self.resume().into(),
+            ControlCommand::Flash { path } => {
+                let flashing = self.flash_icon(&path);
+                ControlReply::data(serde_json::json!({ "queued": !flashing }))
+            }
+            ControlCommand::Refresh => self.refresh().into(),
+            ControlCommand::ReloadConfig => self.reload().into(),
+            ControlCommand::List => ControlReply::data(self.list_icons()),
+            ControlCommand::Quit => {
+                info!("Quit requested over the control socket");
+                self.quit_requested = true;
+                ControlReply::ok()
+            }
+        }
+    }
+
+    /// Rescan the desktop directory, for the `refresh` command
+    ///
+    /// Icons whose file changed are recreated, as after `resume`.
+    pub fn refresh(&mut self) -> Result<()> {
+        if self.paused || self.locked {
+            anyhow::bail!("Icons are paused or the session is locked; they catch up on their own");
+        }
+        info!("Rescanning {}", self.desktop_dir.display());
+        self.catch_up()
+    }
+
+    /// Path and grid cell of every icon, answered to `list`
+    ///
+    /// Icons pinned to a corner give the corner instead of a cell. Each icon
+    /// also gives the name of the output it is on, once known.
+    fn list_icons(&self) -> serde_json::Value {
+        let (columns, _) = self.grid_dimensions();
+        let mut icons: Vec<(&PathBuf, &DesktopIcon)> = self.icons.iter().collect();
+        icons.sort_by_key(|(path, _)| *path);
+        let icons = icons
+            .into_iter()
+            .map(|(path, icon)| {
+                let mut entry = match icon.pinned_corner() {
+                    Some(corner) => serde_json::json!({ "path": path, "corner": corner }),
+                    None => {
+                        let index = icon.layout_index();
+                        serde_json::json!({ "path": path, "col": index % columns, "row": index / columns })
+                    }
+                };
+                if let Some(output) = self.icon_outputs.get(path).or(self.output_name.as_ref()) {
+                    entry["output"] = serde_json::json!(output);
+                }
+                entry
+            })
+            .collect();
+        serde_json::Value::Array(icons)
+    }
+
+    /// Liveness snapshot answered to `ping`
+    pub fn health(&self) -> Health {
+        Health {
+            started: self.created,
+            icons: self.icons.len(),
+            wayland: self.backend.is_some(),
+            last_render: self.last_render_completed,
+            fs_coalesce_ms: self.fs_debouncer.window().as_millis() as u64,
+            paused: self.paused,
+        }
+    }
+
+    /// Freeze the desktop, e.g. during a batch file operation
+    ///
+    /// Icons stay as they are on screen: filesystem events are held or
+    /// dropped (see `paused_fs_events`), nothing is rendered and input is
+    /// ignored until `resume`.
+    pub fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+        info!("Paused");
+        self.paused = true;
+        self.pointer_press = None;
+        self.close_spotlight();
+        self.collapse_label();
+        self.close_tooltip();
+    }
+
+    /// Undo `pause`, applying held events and rescanning the desktop
+    ///
+    /// The rescan drops icons of files that are gone, recreates those whose
+    /// files changed and adds new ones, so dropped events are caught up too.
+    pub fn resume(&mut self) -> Result<()> {
+        if !self.paused {
+            return Ok(());
+        }
+        self.paused = false;
+        if self.locked {
+            info!("Resumed, catching up once the session is unlocked");
+            return Ok(());
+        }
+        info!("Resumed, rescanning {}", self.desktop_dir.display());
+        self.catch_up()
+    }
+
+    /// Apply held events and rescan the desktop after icons were frozen
+    fn catch_up(&mut self) -> Result<()> {
+        for event in self.fs_debouncer.drain_all() {
+            if let Err(e) = self.handle_fs_event(event) {
+                error!("Error handling fs event: {}", e);
+            }
+        }
+
+        let changed: Vec<PathBuf> = self
+            .icons
+            .iter()
+            .filter(|(path, icon)| path.exists() && icon.changed_on_disk())
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in changed {
+            self.remove_icon(&path);
+            self.add_icon(&path)?;
+        }
+        self.remove_missing_icons();
+        self.update_icons();
+        self.scan_desktop()?;
+        self.refresh_trash_icon();
+        self.emblems_stale = true;
+        self.needs_render = true;
+        Ok(())
+    }
+
+    /// Drop icons whose files are gone, without waiting for failed updates
+    ///
+    /// `update_error_threshold` rides out files that are briefly unreachable;
+    /// a file the catch-up rescan can't find was deleted while icons were
+    /// frozen. Files on a filesystem that went away are left to `update_icons`,
+    /// which keeps their icons with `unavailable_files = "stale"`.
+    fn remove_missing_icons(&mut self) {
+        let mount_table = (self.config.unavailable_files == UnavailableFiles::Stale).then(mounts::mount_points);
+        let missing: Vec<PathBuf> = self
+            .icons
+            .iter()
+            .filter(|(path, icon)| !icon.is_unavailable() && mounts::probe(path) == FileState::Missing)
+            .filter(|(_, icon)| !mount_table.as_ref().is_some_and(|table| mount_gone(icon, table)))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in missing {
+            debug!("{} was removed while icons were frozen", path.display());
+            self.remove_icon_animated(&path);
+        }
+    }
+
+    /// Whether the daemon is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Suspend icons while the session is locked, and catch up on unlock
+    ///
+    /// Like `pause`, except filesystem events are always held. A lock state
+    /// that can't be read leaves icons as they are.
+    fn poll_session_lock(&mut self, now: Instant) {
+        if now.duration_since(self.last_lock_poll) < LOCK_POLL_INTERVAL {
+            return;
+        }
+        self.last_lock_poll = now;
+
+        let locked = match self.session_lock {
+            Some(ref source) if self.config.suspend_when_locked => {
+                source.is_locked().unwrap_or(self.locked)
+            }
+            _ => false,
+        };
+        if locked == self.locked {
+            return;
+        }
+        self.locked = locked;
+        if locked {
+            info!("Session locked, suspending icons");
+            self.pointer_press = None;
+            self.close_spotlight();
+            self.collapse_label();
+            self.close_tooltip();
+        } else if !self.paused {
+            info!("Session unlocked, rescanning {}", self.desktop_dir.display());
+            if let Err(e) = self.catch_up() {
+                error!("Failed to catch up after unlock: {:#}", e);
+            }
+        }
+    }
+
+    /// Reposition all icon surfaces based on current screen dimensions
+    ///
+    /// Icons without a surface (e.g. after an icon size change) get a new one.
+    fn reposition_all_icons(&mut self) {
+        let surface_height = self.config.icon_size + self.label_height();
+        let cell_width = self.config.icon_size + self.config.grid_spacing;
+        let cell_height = surface_height + self.config.grid_spacing;
+        let icon_count = self.icons.values().filter(|i| i.pinned_corner().is_none()).count() as u32;
+
+        // Collect (path, surface_id) pairs to reposition, in sort order or
+        // keeping the current grid order
+        let has_backend = self.backend.is_some();
+        let to_reposition: Vec<(PathBuf, Option<SurfaceId>)> = self
+            .arrangement_order()
+            .into_iter()
+            .filter_map(|p| match self.path_to_surface.get(&p) {
+                Some(&s) => Some((p, Some(s))),
+                None if has_backend => Some((p, None)),
+                None => None,
+            })
+            .collect();
+
+        let area = self.grid_area();
+        let free_max = (
+            area.x + area.width.saturating_sub(self.config.icon_size) as i32,
+            area.y + area.height.saturating_sub(surface_height) as i32,
+        );
+
+        // Placed icons keep their cells; the rest fill the free ones in order.
+        // Icons pinned to another output take no cell here while it is connected.
+        let keep_clear = self.keep_clear_slots();
+        let placed: HashMap<PathBuf, u32> = to_reposition
+            .iter()
+            .filter(|(p, _)| self.pinned_output(p).is_none())
+            .filter_map(|(p, _)| {
+                let slot = self.placed_slot(self.icons.get(p)?, &keep_clear)?;
+                Some((p.clone(), slot))
+            })
+            .collect();
+        let placed_slots: HashSet<u32> = placed.values().copied().collect();
+
+        let mut next_free = 0;
+        let mut slots = Vec::with_capacity(to_reposition.len());
+        for (path, _) in &to_reposition {
+            let index = match placed.get(path) {
+                Some(&slot) => slot,
+                None => {
+                    while placed_slots.contains(&self.nth_slot(next_free, &keep_clear)) {
+                        next_free += 1;
+                    }
+                    self.nth_slot(next_free, &keep_clear)
+                }
+            };
+            let flows = self.icons.get(path).is_some_and(|icon| icon.pinned_corner().is_none());
+            if flows && !placed.contains_key(path) && self.pinned_output(path).is_none() {
+                next_free += 1;
+            }
+            slots.push(index);
+        }
+
+        // Pinned icons go to their outputs, and with `span_outputs` icons past
+        // the grid continue on the other outputs; only those that fit on none
+        // of them can pile up
+        let paths = to_reposition.iter().map(|(path, _)| path.as_path());
+        let indexed: Vec<(&Path, u32)> = paths.zip(slots.iter().copied()).collect();
+        let spans = self.span_positions(&indexed);
+        let unspanned = indexed.iter().filter(|(path, _)| !spans.contains_key(*path));
+        self.pile = self.overflow_pile(unspanned.map(|(path, index)| (*path, index)), &keep_clear);
+        if self.pile.is_empty() {
+            self.pile_fanned = false;
+        }
+
+        for ((path, surface_id), index) in to_reposition.into_iter().zip(slots) {
+            let Some(icon) = self.icons.get_mut(&path) else {
+                continue;
+            };
+            let span = spans.get(&path);
+            let mut position = match span {
+                Some((_, position)) => {
+                    icon.set_layout_index(index);
+                    position.clone()
+                }
+                None => {
+                    let cell = (cell_width, cell_height);
+                    let position = place_icon(icon, area, icon_count, index, cell, self.config.grid_corner);
+                    let offset = self.placements.offset(&path).filter(|_| placed.contains_key(&path));
+                    match offset.filter(|_| !self.config.snap_to_grid) {
+                        // Freely dropped icons stay on the grid area however it changed since
+                        Some((dx, dy)) => crate::ipc::Position {
+                            x: (position.x + dx).clamp(area.x, free_max.0),
+                            y: (position.y + dy).clamp(area.y, free_max.1),
+                        },
+                        None => position,
+                    }
+                }
+            };
+            if let Some(k) = self.pile.iter().position(|member| *member == path) {
+                position = self.pile_position(k as u32, area, cell_width, cell_height, &keep_clear);
+            }
+            let output = span.map(|(name, _)| name.clone());
+
+            let Some(ref mut backend) = self.backend else {
+                continue;
+            };
+            // Layer surfaces can't change outputs, so moving to another means a new one
+            let surface_id = match surface_id {
+                Some(surface_id) if self.icon_outputs.get(&path) != output.as_ref() => {
+                    backend.destroy_surface(surface_id);
+                    self.surface_to_path.remove(&surface_id);
+                    self.path_to_surface.remove(&path);
+                    self.awaiting_configure.remove(&surface_id);
+                    if let Some(icon) = self.icons.get_mut(&path) {
+                        icon.clear_presented_frame();
+                    }
+                    None
+                }
+                other => other,
+            };
+            match surface_id {
+                Some(surface_id) => {
+                    backend.set_surface_position(surface_id, position.x, position.y);
+                    debug!(
+                        "Repositioned icon {} to ({}, {})",
+                        path.display(),
+                        position.x,
+                        position.y
+                    );
+                }
+                None => match create_icon_surface(
+                    &mut **backend,
+                    output.as_deref(),
+                    (position.x, position.y),
+                    (self.config.icon_size, surface_height),
+                ) {
+                    Ok(surface_id) => {
+                        match output {
+                            Some(output) => self.icon_outputs.insert(path.clone(), output),
+                            None => self.icon_outputs.remove(&path),
+                        };
+                        // A new surface needs the icon's first frame
+                        self.dirty_icons.insert(path.clone());
+                        self.surface_to_path.insert(surface_id, path.clone());
+                        self.path_to_surface.insert(path, surface_id);
+                    }
+                    Err(e) => {
+                        warn!("Failed to create surface for {}: {}", path.display(), e);
+                    }
+                },
+            }
+        }
+
+        // The hint follows the center of the grid area
+        let (x, y) = self.empty_hint_position();
+        if let (Some(surface_id), Some(backend)) = (self.empty_hint, self.backend.as_mut()) {
+            backend.set_surface_position(surface_id, x, y);
+        }
+    }
+
+    /// Request render for all icons (called when display needs update)
+    ///
+    /// Returns a vector of (path, draw_commands) pairs
+    #[allow(dead_code)]
+    pub fn render_all_icons(
+        &mut self,
+        canvas_width: u32,
+        canvas_height: u32,
+        device_pixel_ratio: f32,
+    ) -> Vec<(PathBuf, Vec<crate::lua::DrawCommand>)> {
+        self.icons
+            .iter_mut()
+            .map(|(path, icon)| {
+                let commands = icon.request_render(canvas_width, canvas_height, device_pixel_ratio);
+                (path.clone(), commands)
+            })
+            .collect()
+    }
+
+    /// Calculate positions for all icons
+    ///
+    /// Returns a vector of (path, position) pairs
+    #[allow(dead_code)]
+    pub fn position_all_icons(
+        &mut self,
+        screen_width: u32,
+        screen_height: u32,
+        cell_width: Option<u32>,
+        cell_height: Option<u32>,
+    ) -> Vec<(PathBuf, crate::ipc::Position)> {
+        let icon_count = self.icons.len() as u32;
+
+        self.icons
+            .iter_mut()
+            .enumerate()
+            .map(|(index, (path, icon))| {
+                let position = icon.request_position(
+                    screen_width,
+                    screen_height,
+                    icon_count,
+                    index as u32,
+                    cell_width,
+                    cell_height,
+                );
+                (path.clone(), position)
+            })
+            .collect()
+    }
+
+    /// Get an icon by path
+    #[allow(dead_code)]
+    pub fn get_icon(&self, path: &Path) -> Option<&DesktopIcon> {
+        self.icons.get(path)
+    }
+
+    /// Get a mutable icon by path
+    #[allow(dead_code)]
+    pub fn get_icon_mut(&mut self, path: &Path) -> Option<&mut DesktopIcon> {
+        self.icons.get_mut(path)
+    }
+
+    /// Queue a filesystem event for the desktop directory
+    ///
+    /// Events are debounced and applied by a later `step`. Only needed when
+    /// embedding; `run` watches the directory itself.
+    pub fn queue_fs_event(&mut self, event: Event) {
+        self.push_fs_event(event, Instant::now());
+    }
+
+    /// Hand a raw watcher event to the debouncer, tracing it if configured
+    fn push_fs_event(&mut self, event: Event, now: Instant) {
+        if self.config.trace_fs_events {
+            info!("fs event: {:?} {:?}", event.kind, event.paths);
+        }
+        if self.paused && self.config.paused_fs_events == PausedFsEvents::Drop {
+            return;
+        }
+
+        // The debouncer splits events by path, so renames are paired up here
+        // (while paused or locked they are held as a removal and a creation)
+        let held = self.paused || self.locked;
+        if !held && event.kind == EventKind::Modify(ModifyKind::Name(RenameMode::Both)) {
+            if let [from, to] = &event.paths[..] {
+                if self.rename_icon(from, to) {
+                    return;
+                }
+            }
+        }
+        self.fs_debouncer.push(event, now);
+    }
+
+    /// Do one iteration of icon work at `now`
+    ///
+    /// Pulls configure and input events from the backend, applies due
+    /// filesystem events, advances animations and renders changed icons if a
+    /// frame is due. `render_tick` marks a frame timer tick under timer pacing.
+    /// Embedders call this from their own loop, and `update_icons` on a slower
+    /// cadence. While paused or locked only the backend connection is serviced.
+    pub fn step(&mut self, now: Instant, render_tick: bool) {
+        self.dispatch_backend();
+        self.poll_session_lock(now);
+        if self.paused || self.locked {
+            // Keep the connection serviced but leave icons exactly as they are
+            if let Some(ref mut backend) = self.backend {
+                backend.take_input_events();
+            }
+            return;
+        }
+        self.handle_input(now);
+        self.expire_context_menu(now);
+        self.check_long_press(now);
+        self.show_due_tooltip(now);
+        self.update_covered();
+
+        // Check for screen dimension changes from the output, and re-lay out
+        // icons at once when the output says its size or scale changed
+        let outputs_changed = self.backend.as_mut().is_some_and(|backend| backend.take_outputs_changed());
+        self.update_screen_dimensions(outputs_changed);
+        self.wait_for_output(now);
+
+        // Process file system events once their debounce window has passed
+        self.flush_fs_events(now);
+        self.collect_thumbnails();
+        self.tick_animations(now);
+        self.refresh_widgets(now);
+
+        // Render at most once per frame, and only if something changed
+        // (dirty flag is checked inside render_icons_to_surfaces)
+        if self.frame_due(render_tick) {
+            self.render_icons_to_surfaces();
+        }
+    }
+
+    /// Run the main daemon loop using calloop
+    pub fn run(&mut self) -> Result<()> {
+        info!("Icon daemon running with calloop event loop");
+
+        // Create the calloop event loop
+        let mut event_loop: EventLoop<DaemonState> = EventLoop::try_new()
+            .context("Failed to create calloop event loop")?;
+        let loop_handle = event_loop.handle();
+
+        // Create a channel for file watcher events
+        let (sender, channel): (Sender<notify::Result<Event>>, Channel<notify::Result<Event>>) =
+            calloop::channel::channel();
+
+        // Set up the file watcher with the calloop channel sender
+        self.setup_watcher(sender)?;
+
+        // Register the file watcher channel as an event source
+        loop_handle
+            .insert_source(channel, |event, _, state: &mut DaemonState| {
+                match event {
+                    calloop::channel::Event::Msg(Ok(fs_event)) => {
+                        state.pending_events.push(fs_event);
+                    }
+                    calloop::channel::Event::Msg(Err(e)) => {
+                        error!("Watcher error: {}", e);
+                    }
+                    calloop::channel::Event::Closed => {
+                        error!("Watcher channel closed");
+                        state.should_stop = true;
+                    }
+                }
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to register file watcher channel: {:?}", e))?;
+
+        // Register a timer for icon state updates, on a slower cadence than rendering
+        let update_interval = Duration::from_millis(self.config.update_interval_ms.max(1));
+        let timer = Timer::from_duration(update_interval);
+        loop_handle
+            .insert_source(timer, move |_, _, state: &mut DaemonState| {
+                state.should_update_icons = true;
+                TimeoutAction::ToDuration(update_interval)
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to register update timer: {:?}", e))?;
+
+        // Register the render timer (drives rendering with timer pacing)
+        let frame_interval = Duration::from_millis(self.config.frame_interval_ms.max(1));
+        if self.config.frame_pacing == FramePacing::Timer {
+            let timer = Timer::from_duration(frame_interval);
+            loop_handle
+                .insert_source(timer, move |_, _, state: &mut DaemonState| {
+                    state.render_tick = true;
+                    TimeoutAction::ToDuration(frame_interval)
+                })
+                .map_err(|e| anyhow::anyhow!("Failed to register render timer: {:?}", e))?;
+        }
+        info!("Frame pacing: {:?}", self.config.frame_pacing);
+
+        // Control socket for scripted commands; the daemon works fine without it
+        let socket_path = control::socket_path();
+        match control::bind(&socket_path) {
+            Ok(listener) => {
+                let clients = loop_handle.clone();
+                loop_handle
+                    .insert_source(
+                        Generic::new(listener, Interest::READ, Mode::Level),
+                        move |_, listener, _: &mut DaemonState| {
+                            loop {
+                                match listener.accept() {
+                                    Ok((stream, _)) => {
+                                        if let Err(e) = watch_control_client(&clients, stream) {
+                                            warn!("{:#}", e);
+                                        }
+                                    }
+                                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                                    Err(e) => return Err(e),
+                                }
+                            }
+                            Ok(PostAction::Continue)
+                        },
+                    )
+                    .map_err(|e| anyhow::anyhow!("Failed to register control socket: {:?}", e))?;
+                info!("Control socket: {}", socket_path.display());
+            }
+            Err(e) => warn!("{:#} (control commands disabled)", e),
+        }
+
+        // SIGUSR1 cycles the log level, SIGHUP reloads the configuration and
+        // SIGINT and SIGTERM stop the loop, so shutdown runs before the process
+        // exits; all are read from one signalfd so they are handled in the loop
+        let mut handled = Vec::new();
+        if self.log_control.is_some() {
+            handled.push(log_level::CYCLE_SIGNAL);
+        }
+        if self.reload_on_sighup {
+            handled.push(reload::RELOAD_SIGNAL);
+        }
+        if self.stop_on_signals {
+            handled.extend(shutdown::STOP_SIGNALS);
+        }
+        if !handled.is_empty() {
+            match signals::signal_fd(&handled) {
+                Ok(fd) => {
+                    loop_handle
+                        .insert_source(
+                            Generic::new(fd, Interest::READ, Mode::Level),
+                            |_, fd, state: &mut DaemonState| {
+                                // SAFETY: reading a signal neither closes nor replaces the fd
+                                let fd = unsafe { fd.get_mut() };
+                                while let Ok(Some(siginfo)) = fd.read_signal() {
+                                    match Signal::try_from(siginfo.ssi_signo as i32) {
+                                        Ok(log_level::CYCLE_SIGNAL) => state.cycle_log_level = true,
+                                        Ok(reload::RELOAD_SIGNAL) => state.reload = true,
+                                        Ok(signal) if shutdown::STOP_SIGNALS.contains(&signal) => {
+                                            info!("Received {}, stopping", signal);
+                                            state.should_stop = true;
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                                Ok(PostAction::Continue)
+                            },
+                        )
+                        .map_err(|e| anyhow::anyhow!("Failed to register signal source: {:?}", e))?;
+                }
+                Err(e) => warn!("{:#} (signal handling disabled)", e),
+            }
+        }
+
+        // Create the daemon state for the event loop
+        let mut state = DaemonState {
+            pending_events: Vec::new(),
+            control_requests: Vec::new(),
+            health: self.health(),
+            cycle_log_level: false,
+            reload: false,
+            should_update_icons: false,
+            render_tick: false,
+            should_stop: false,
+        };
+
+        // One-shot timer waking the loop when held filesystem events are due
+        let mut fs_timer: Option<(Instant, RegistrationToken)> = None;
+
+        // Wake on display events, so an idle loop can sleep instead of polling every frame
+        let display_fd = self
+            .backend
+            .as_ref()
+            .and_then(|backend| backend.event_fd())
+            .map(|fd| fd.try_clone_to_owned())
+            .transpose()
+            .context("Failed to duplicate the display fd")?;
+        let display_wakes = display_fd.is_some();
+        if let Some(fd) = display_fd {
+            loop_handle
+                .insert_source(
+                    Generic::new(fd, Interest::READ, Mode::Level),
+                    // The backend dispatches its events in `step`
+                    |_, _, _: &mut DaemonState| Ok(PostAction::Continue),
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to register display source: {:?}", e))?;
+        }
+
+        info!("Entering calloop dispatch loop");
+
+        // Do initial render of all icons
+        self.render_icons_to_surfaces();
+
+        // Main event loop
+        loop {
+            // Dispatch calloop events (blocking with timeout)
+            let timeout = self.loop_timeout(frame_interval, display_wakes);
+            event_loop
+                .dispatch(timeout, &mut state)
+                .context("Event loop dispatch failed")?;
+
+            let now = Instant::now();
+            for event in state.pending_events.drain(..) {
+                self.push_fs_event(event, now);
+            }
+            for (command, stream) in state.control_requests.drain(..) {
+                let reply = self.handle_control(command);
+                if let Err(e) = control::write_reply(&stream, &reply) {
+                    debug!("Failed to send control reply: {}", e);
+                }
+            }
+
+            if state.cycle_log_level {
+                self.cycle_log_level();
+                state.cycle_log_level = false;
+            }
+
+            if state.reload {
+                if let Err(e) = self.reload() {
+                    warn!("{:#}", e);
+                }
+                state.reload = false;
+            }
+
+            // Update icons if timer fired
+            if state.should_update_icons {
+                self.update_icons();
+                state.should_update_icons = false;
+            }
+
+            self.step(now, state.render_tick);
+            state.render_tick = false;
+            state.health = self.health();
+            if let Some(ref mut backend) = self.backend {
+                backend.flush();
+            }
+
+            let fs_due = self.fs_debouncer.next_due();
+            if fs_due != fs_timer.as_ref().map(|(due, _)| *due) {
+                if let Some((_, token)) = fs_timer.take() {
+                    loop_handle.remove(token);
+                }
+                if let Some(due) = fs_due {
+                    let token = loop_handle
+                        .insert_source(Timer::from_deadline(due), |_, _, _: &mut DaemonState| {
+                            TimeoutAction::Drop
+                        })
+                        .map_err(|e| anyhow::anyhow!("Failed to register fs timer: {:?}", e))?;
+                    fs_timer = Some((due, token));
+                }
+            }
+
+            // Check if we should stop
+            if state.should_stop || self.should_exit() {
+                info!("Daemon stopping");
+                break;
+            }
+        }
+
+        self.shutdown();
+        let _ = std::fs::remove_file(&socket_path);
+        Ok(())
+    }
+
+    /// Get the number of active icons
+    #[allow(dead_code)]
+    pub fn icon_count(&self) -> usize {
+        self.icons.len()
+    }
+
+    /// Check if a path has an icon (for testing)
+    #[cfg(test)]
+    pub fn has_icon(&self, path: &Path) -> bool {
+        self.icons.contains_key(path)
+    }
+
+    /// Get reference to icons HashMap (for testing)
+    #[cfg(test)]
+    pub fn icons(&self) -> &HashMap<PathBuf, DesktopIcon> {
+        &self.icons
+    }
+}
+
+/// Ask an icon for its grid position within the usable area
+///
+/// The icon (or its script) lays out in area-local coordinates; the result is
+/// translated back to output coordinates.
+/// Create an icon surface on the output named `output`, or the primary one
+fn create_icon_surface(
+    backend: &mut dyn SurfaceBackend,
+    output: Option<&str>,
+    (x, y): (i32, i32),
+    (width, height): (u32, u32),
+) -> IconsResult<SurfaceId> {
+    match output {
+        Some(output) => backend.create_surface_on(output, x, y, width, height),
+        None => backend.create_surface(x, y, width, height),
+    }
+}
+
+/// Whether two `(x, y, width, height)` rectangles overlap or touch
+///
+/// A band with no width or height still touches what it crosses.
+fn rects_intersect(a: (i32, i32, u32, u32), b: (i32, i32, u32, u32)) -> bool {
+    let overlaps = |a_start: i32, a_len: u32, b_start: i32, b_len: u32| {
+        i64::from(a_start) <= i64::from(b_start) + i64::from(b_len)
+            && i64::from(b_start) <= i64::from(a_start) + i64::from(a_len)
+    };
+    overlaps(a.0, a.2, b.0, b.2) && overlaps(a.1, a.3, b.1, b.3)
+}
+
+/// Top-left corner of the cell at layout index `slot` of a grid in `area`
+/// filled from `corner`, in output coordinates
+fn grid_cell_origin(
+    slot: u32,
+    columns: u32,
+    (cell_width, cell_height): (u32, u32),
+    area: UsableArea,
+    corner: Corner,
+) -> (i32, i32) {
+    let origin = cell_origin(slot, columns, cell_width, cell_height);
+    let (x, y) = mirror_to_corner(corner, origin, (cell_width, cell_height), (area.width, area.height));
+    (area.x + x, area.y + y)
+}
+
+/// Layout indices of the cells of a `columns` x `rows` grid in `area` that
+/// overlap one of `regions`
+fn keep_clear_cells(
+    regions: &[KeepClearRegion],
+    area: UsableArea,
+    (columns, rows): (u32, u32),
+    (cell_width, cell_height): (u32, u32),
+    corner: Corner,
+) -> HashSet<u32> {
+    if regions.is_empty() {
+        return HashSet::new();
+    }
+    (0..columns * rows)
+        .filter(|&slot| {
+            let (x, y) = grid_cell_origin(slot, columns, (cell_width, cell_height), area, corner);
+            regions.iter().any(|region| region.overlaps(x, y, cell_width, cell_height))
+        })
+        .collect()
+}
+
+/// Where an icon goes in `area`, with the grid filled from `grid_corner`
+fn place_icon(
+    icon: &mut DesktopIcon,
+    area: UsableArea,
+    icon_count: u32,
+    icon_index: u32,
+    (cell_width, cell_height): (u32, u32),
+    grid_corner: Corner,
+) -> crate::ipc::Position {
+    if let Some(corner) = icon.pinned_corner() {
+        return corner_position(corner, area, cell_width, cell_height);
+    }
+
+    let position = icon.request_position(
+        area.width,
+        area.height,
+        icon_count,
+        icon_index,
+        Some(cell_width),
+        Some(cell_height),
+    );
+    let cell = (cell_width, cell_height);
+    let (x, y) = mirror_to_corner(grid_corner, (position.x, position.y), cell, (area.width, area.height));
+    icon.set_layout_index(icon_index);
+    crate::ipc::Position { x: area.x + x, y: area.y + y }
+}
+
+/// Character typed by X keysym `keysym`, if it types a printable one
+///
+/// Latin-1 keysyms are their own code points; other characters have the
+/// keysym of their code point plus 0x0100_0000.
+fn keysym_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(keysym),
+        0x0100_0000..=0x0110_ffff => char::from_u32(keysym - 0x0100_0000).filter(|c| !c.is_control()),
+        _ => None,
+    }
+}
+
+/// The button the daemon acts on for a physical `button` press
+///
+/// With `swap` the primary and secondary buttons trade places; other
+/// buttons are passed through. A touch is always the primary button.
+fn logical_button(button: u32, swap: bool) -> u32 {
+    match button {
+        BTN_TOUCH => BTN_LEFT,
+        BTN_LEFT if swap => BTN_RIGHT,
+        BTN_RIGHT if swap => BTN_LEFT,
+        _ => button,
+    }
+}
+
+/// Opacity of icons `idle` after the last input under `dim`
+///
+/// Icons dim over `fade` once the delay has passed; a zero `fade` dims them
+/// at once.
+fn idle_dim_opacity(dim: &IdleDimConfig, idle: Duration, fade: Duration) -> f32 {
+    if !dim.enabled {
+        return 1.0;
+    }
+    let Some(dimming) = idle.checked_sub(Duration::from_millis(dim.delay_ms)) else {
+        return 1.0;
+    };
+    let target = dim.opacity.clamp(0.0, 1.0);
+    1.0 - (1.0 - target) * fade_progress(dimming, fade)
+}
+
+/// Eased progress from 0 to 1 of a fade lasting `fade`, `elapsed` in
+fn fade_progress(elapsed: Duration, fade: Duration) -> f32 {
+    if fade.is_zero() {
+        return 1.0;
+    }
+    ease_out(elapsed.as_secs_f32() / fade.as_secs_f32())
+}
+
+/// Position of a cell pinned to a corner of the usable area
+fn corner_position(corner: Corner, area: UsableArea, cell_width: u32, cell_height: u32) -> crate::ipc::Position {
+    let left = area.x + PINNED_MARGIN;
+    let top = area.y + PINNED_MARGIN;
+    let right = (area.x + area.width as i32 - PINNED_MARGIN - cell_width as i32).max(area.x);
+    let bottom = (area.y + area.height as i32 - PINNED_MARGIN - cell_height as i32).max(area.y);
+
+    let (x, y) = match corner {
+        Corner::TopLeft => (left, top),
+        Corner::TopRight => (right, top),
+        Corner::BottomLeft => (left, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+    crate::ipc::Position { x, y }
+}
+
+/// Whether the mount an icon's file lived on is no longer mounted
+fn mount_gone(icon: &DesktopIcon, mount_table: &[PathBuf]) -> bool {
+    icon.mount_point()
+        .is_some_and(|mount| !mount_table.iter().any(|m| m == mount))
+}
+
+/// Label of an icon named `name` at `path` while another icon has that name
+fn duplicate_label(name: &str, path: &Path, mode: DuplicateLabels) -> Option<String> {
+    let parent = path.parent()?;
+    let source = match mode {
+        DuplicateLabels::Plain => return None,
+        DuplicateLabels::ParentName => parent.file_name()?.to_string_lossy().into_owned(),
+        DuplicateLabels::ParentPath => {
+            let home = dirs::home_dir();
+            match home.as_deref().and_then(|home| parent.strip_prefix(home).ok()) {
+                Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+                Some(relative) => format!("~/{}", relative.display()),
+                None => parent.display().to_string(),
+            }
+        }
+    };
+    Some(format!("{} ({})", name, source))
+}
+
+/// Text of an icon's tooltip: its full name, wrapped when long
+///
+/// With `details` the type, size, tags and comment of its hover text follow,
+/// and when the file was last modified.
+fn tooltip_lines(icon: &DesktopIcon, details: bool) -> Vec<String> {
+    let mut lines = wrap_label(icon.name(), TOOLTIP_LINE_CHARS);
+    if details {
+        lines.extend(icon.tooltip_lines().into_iter().skip(1));
+        let modified = icon.path().metadata().and_then(|metadata| metadata.modified());
+        if let Ok(age) = modified.map(|modified| modified.elapsed().unwrap_or_default()) {
+            lines.push(format!("Modified {}", format_age(age)));
+        }
+    }
+    lines
+}
+
+/// `age` as a rough phrase, e.g. "3 hours ago"
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    let (count, unit) = match minutes {
+        0 => return "just now".to_string(),
+        1..=59 => (minutes, "minute"),
+        60..=1439 => (minutes / 60, "hour"),
+        _ => (minutes / 1440, "day"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Directory to watch for an icon whose appearance depends on directory contents
+fn icon_watch_path(icon: &DesktopIcon) -> Option<PathBuf> {
+    match icon.icon_type() {
+        // Never watch through a folder that was swapped for a symlink
+        IconType::Folder if icon.path().is_symlink() => None,
+        IconType::Folder => Some(icon.path().to_path_buf()),
+        IconType::Trash => Some(trash::files_dir(icon.path())),
+        _ => None,
+    }
+}
+
+/// What icons are sorted on, read once per sort
+struct SortFacts<'a> {
+    icon: &'a DesktopIcon,
+    name: String,
+    extension: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Icons in `order`, with folders ahead of files if `folders_first`
+///
+/// Ties fall back to the name and then the path, so the order is the same
+/// every time.
+fn sort_icons<'a>(
+    icons: impl IntoIterator<Item = &'a DesktopIcon>,
+    order: SortOrder,
+    folders_first: bool,
+) -> Vec<&'a DesktopIcon> {
+    let mut facts: Vec<SortFacts> = icons
+        .into_iter()
+        .map(|icon| {
+            let metadata = icon.path().metadata().ok();
+            let extension = icon.path().extension().map(|e| e.to_string_lossy().to_lowercase());
+            SortFacts {
+                icon,
+                name: icon.name().to_lowercase(),
+                extension: extension.unwrap_or_default(),
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata.and_then(|m| m.modified().ok()).unwrap_or(SystemTime::UNIX_EPOCH),
+            }
+        })
+        .collect();
+
+    let is_folder = |facts: &SortFacts| facts.icon.icon_type() == IconType::Folder;
+    facts.sort_by(|a, b| {
+        let folders = if folders_first { is_folder(b).cmp(&is_folder(a)) } else { Ordering::Equal };
+        let primary = match order {
+            SortOrder::Manual | SortOrder::Name | SortOrder::NameReverse => a.name.cmp(&b.name),
+            SortOrder::Type | SortOrder::TypeReverse => (a.icon.icon_name(), &a.extension)
+                .cmp(&(b.icon.icon_name(), &b.extension)),
+            SortOrder::Size | SortOrder::SizeReverse => a.size.cmp(&b.size),
+            SortOrder::Modified | SortOrder::ModifiedReverse => a.modified.cmp(&b.modified),
+        };
+        let primary = if order.is_reverse() { primary.reverse() } else { primary };
+        folders
+            .then(primary)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.icon.path().cmp(b.icon.path()))
+    });
+    facts.into_iter().map(|facts| facts.icon).collect()
+}
+
+/// Paths of the entries in a listing of `dir`, skipping entries that fail
+///
+/// Single entries can fail while the rest of the directory reads fine (e.g.
+/// a stale network mount). They are logged, since their files silently
+/// missing from the desktop would be hard to explain otherwise.
+fn listed_paths(
+    dir: &Path,
+    entries: impl Iterator<Item = std::io::Result<std::fs::DirEntry>>,
+) -> Vec<PathBuf> {
+    entries
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.path()),
+            Err(e) => {
+                debug!("Skipping unreadable entry in {}: {}", dir.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Where to read the session's lock state, if `suspend_when_locked` is on
+fn session_lock_state(config: &Config) -> Option<Box<dyn LockState>> {
+    if !config.suspend_when_locked {
+        return None;
+    }
+    let source = session_lock::detect()?;
+    info!("Suspending icons while the session is locked ({})", source.name());
+    Some(source)
+}
+
+/// Create the filesystem event debouncer for a configuration
+fn create_debouncer(config: &Config) -> EventDebouncer {
+    EventDebouncer::new(Duration::from_millis(config.fs_coalesce_ms), config.transient_files)
+}
+
+/// Clamp an icon size to what can be rendered, warning if it had to change
+///
+/// Loaded configurations are validated, but embedders and desktop settings
+/// can still ask for any size.
+fn safe_icon_size(size: u32) -> u32 {
+    let clamped = clamp_icon_size(size);
+    if clamped != size {
+        warn!("Icon size {} is out of range, using {}", size, clamped);
+    }
+    clamped
+}
+
+/// Whether an output is big enough to lay icons out on
+fn is_usable_output(width: u32, height: u32) -> bool {
+    width >= MIN_OUTPUT_SIZE && height >= MIN_OUTPUT_SIZE
+}
+
+/// Widget script for an icon type, None for types that never get a widget
+fn widget_script_name(icon_type: IconType) -> Option<&'static str> {
+    let name = match icon_type {
+        // Widgets may read their file, which blocks or never ends for these
+        IconType::Fifo | IconType::Socket | IconType::BlockDevice | IconType::CharDevice => return None,
+        IconType::Folder => "folder.lua",
+        IconType::File => "file.lua",
+        IconType::Symlink => "symlink.lua",
+        IconType::Executable => "executable.lua",
+        IconType::Image => "image.lua",
+        IconType::Document => "document.lua",
+        IconType::Archive => "archive.lua",
+        IconType::Video => "video.lua",
+        IconType::Audio => "audio.lua",
+        IconType::Trash => "trash.lua",
+        IconType::Volume => "volume.lua",
+        IconType::Unknown => "file.lua",
+    };
+    Some(name)
+}
+
+/// Directories as a comma-separated list for log messages
+fn display_dirs(dirs: &[PathBuf]) -> String {
+    dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Emblem providers enabled in `config`
+fn emblem_providers(config: &Config) -> Vec<Box<dyn EmblemProvider>> {
+    let mut providers: Vec<Box<dyn EmblemProvider>> = Vec::new();
+    if config.vcs_emblems {
+        providers.push(Box::new(GitStatusProvider::new()));
+    }
+    providers
+}
+
+/// Detect the desktop's settings if `follow_desktop_icon_size` is set, and
+/// apply its current icon size to `config`
+fn desktop_icon_size_source(config: &mut Config) -> Option<Box<dyn SettingsSource>> {
+    if !config.follow_desktop_icon_size {
+        return None;
+    }
+
+    let Some(source) = desktop_settings::detect() else {
+        info!(
+            "No supported desktop environment detected, using configured icon size {}",
+            config.icon_size
+        );
+        return None;
+    };
+
+    match source.icon_size() {
+        Some(size) if size > 0 => {
+            info!("Using icon size {} from {}", size, source.name());
+            config.icon_size = size;
+        }
+        _ => info!(
+            "No icon size set in {}, using configured icon size {}",
+            source.name(),
+            config.icon_size
+        ),
+    }
+    Some(source)
+}
+
+/// Create the icon renderer for a configuration
+fn create_renderer(config: &Config) -> IconRenderer {
+    let mut renderer = IconRenderer::new(config.icon_size, config.font_size);
+    renderer.set_icon_theme(&config.icon_theme);
+    renderer.set_type_theme(config.icon_theme_dir.as_deref().map(TypeTheme::load));
+    renderer.set_label_layout(config.label_align.into(), config.label_width);
+    renderer.set_label_lines(config.label_lines);
+    renderer.set_elevation(Some(config.icon_elevation.clone()).filter(|elevation| elevation.enabled));
+    renderer.set_state_colors(&config.colors);
+    renderer.set_label_pill(Some(config.label_pill.clone()).filter(|pill| pill.enabled));
+    if config.symbolic_icons {
+        info!("Symbolic icons on: widgets get events but their drawing is replaced by symbolic glyphs");
+        renderer.set_symbolic(Some(SymbolicStyle::new(
+            &config.icon_theme,
+            &config.colors.label_fg,
+            config.symbolic_strict,
+        )));
+    }
+    renderer
+}
+
+/// Start the thumbnail worker if `thumbnails` is on
+fn create_thumbnailer(config: &Config) -> Option<Thumbnailer> {
+    config.thumbnails.then(|| Thumbnailer::new(config.thumbnail_cache_dir.clone()))
+}
+
+/// Start the shared widget workers, unless `lua_pool_size` is 0
+fn create_lua_pool(config: &Config) -> Option<LuaPool> {
+    (config.lua_pool_size > 0)
+        .then(|| LuaPool::new(config.lua_pool_size, sandbox_options(config), &config.script_dirs))
+}
+
+/// Open the render cache if `render_cache_dir` is set
+fn create_render_cache(config: &Config) -> Option<RenderCache> {
+    let dir = config.render_cache_dir.clone()?;
+    Some(RenderCache::new(dir, config.render_cache_max_mb.saturating_mul(1024 * 1024)))
+}
+
+/// Run the commands a control client sends as they arrive, until it hangs up
+///
+/// Each client is an event source of its own, so one that is slow to send
+/// holds up neither the loop nor other clients.
+fn watch_control_client<'l>(loop_handle: &LoopHandle<'l, DaemonState>, stream: UnixStream) -> Result<()> {
+    stream.set_nonblocking(true).context("Failed to set up control client")?;
+    let mut reader = CommandReader::default();
+    loop_handle
+        .insert_source(
+            Generic::new(stream, Interest::READ, Mode::Level),
+            move |_, stream, state: &mut DaemonState| {
+                let (commands, closed) = reader.read(stream);
+                for command in commands {
+                    match command {
+                        // Answer pings here so they never wait behind other work
+                        Ok(ControlCommand::Ping) => {
+                            let _ = control::write_reply(stream, &state.health.reply());
+                        }
+                        Ok(command) => match stream.try_clone() {
+                            Ok(reply_to) => state.control_requests.push((command, reply_to)),
+                            Err(e) => debug!("Failed to keep control client for the reply: {}", e),
+                        },
+                        Err(e) => {
+                            let _ = control::write_reply(stream, &ControlReply::error(format!("{:#}", e)));
+                        }
+                    }
+                }
+                Ok(if closed { PostAction::Remove } else { PostAction::Continue })
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to watch control client: {:?}", e))?;
+    Ok(())
+}
+
+/// State passed to the calloop event loop callbacks
+struct DaemonState {
+    pending_events: Vec<Event>,
+    /// Control commands waiting to run, with the client to reply to
+    control_requests: Vec<(ControlCommand, UnixStream)>,
+    /// Snapshot answered to `ping`, refreshed every loop iteration
+    health: Health,
+    /// SIGUSR1 arrived since the last iteration
+    cycle_log_level: bool,
+    /// SIGHUP arrived since the last iteration
+    reload: bool,
+    should_update_icons: bool,
+    /// Render timer fired (timer pacing only)
+    render_tick: bool,
+    should_stop: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::HeadlessBackend;
+    use notify::{event::CreateKind, event::RemoveKind, event::ModifyKind, EventKind};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Helper to create a test config
+    fn test_config() -> Config {
+        // Keep test thumbnails out of the user's cache, the backdrop out of
+        // tests counting surfaces, and badges for the missing scripts out of frames
+        Config {
+            thumbnail_cache_dir: None,
+            rubber_band_select: false,
+            widget_status_badges: false,
+            ..Config::default()
+        }
+    }
+
+    /// Helper to create a test daemon without watchers (for unit testing)
+    fn create_test_daemon(desktop_dir: PathBuf) -> IconDaemon {
+        let config = test_config();
+        let renderer = create_renderer(&config);
+        let name_filter = config.name_filter();
+        let fs_debouncer = create_debouncer(&config);
+        IconDaemon {
+            config,
+            awaiting_desktop_dir: !desktop_dir.is_dir(),
+            desktop_dir,
+            icons: HashMap::new(),
+            watcher: None,
+            event_sender: None,
+            backend: None, // No display in tests
+            renderer,
+            surface_to_path: HashMap::new(),
+            path_to_surface: HashMap::new(),
+            screen_width: 1920,
+            screen_height: 1080,
+            output_name: None,
+            needs_render: false,
+            dirty_icons: HashSet::new(),
+            pointer_press: None,
+            last_click: None,
+            drag: None,
+            selection_anchor: None,
+            keyboard_focus: None,
+            pile: Vec::new(),
+            pile_fanned: false,
+            name_filter,
+            last_render: Instant::now(),
+            frame_group_renders: HashMap::new(),
+            last_render_completed: None,
+            awaiting_configure: HashSet::new(),
+            trash_dir: None,
+            fs_debouncer,
+            appearing: HashMap::new(),
+            disappearing: Vec::new(),
+            pending_flashes: HashMap::new(),
+            renaming: None,
+            settings_source: None,
+            last_settings_poll: Instant::now(),
+            placements: Placements::default(),
+            attach_failures: HashMap::new(),
+            volumes: HashSet::new(),
+            last_volume_refresh: Instant::now(),
+            render_cache: None,
+            thumbnailer: None,
+            lua_pool: None,
+            unrenderable_sizes: HashSet::new(),
+            dimensions_known: true,
+            created: Instant::now(),
+            empty_hint: None,
+            work_area: None,
+            secondary_outputs: Vec::new(),
+            icon_outputs: HashMap::new(),
+            log_control: None,
+            reload_on_sighup: false,
+            stop_on_signals: false,
+            scanning: false,
+            config_path: None,
+            pending_delete: None,
+            trash_undo: Vec::new(),
+            context_menu: None,
+            last_input: Instant::now(),
+            idle_wake: None,
+            rendered_idle_opacity: 1.0,
+            covered: false,
+            spotlight: None,
+            expanded_label: None,
+            tooltip: None,
+            debug_grid: None,
+            backdrop: None,
+            backdrop_drawn: None,
+            rubber_band: None,
+            cursor_shape: CursorShape::Default,
+            emblem_providers: Vec::new(),
+            emblems: HashMap::new(),
+            last_emblem_refresh: Instant::now(),
+            emblems_stale: false,
+            warned_missing_handler: false,
+            paused: false,
+            quit_requested: false,
+            session_lock: None,
+            last_lock_poll: Instant::now(),
+            locked: false,
+        }
+    }
+
+    // ========================================================================
+    // File Create Event Tests
+    // ========================================================================
+
+    #[test]
+    fn test_file_create_event_adds_icon_to_hashmap() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Create a test file
+        let test_file = desktop_path.join("test_file.txt");
+        fs::write(&test_file, "test content").unwrap();
+
+        // Simulate a Create event
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![test_file.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(event).unwrap();
+
+        assert!(daemon.has_icon(&test_file), "Icon should be added to HashMap after Create event");
+        assert_eq!(daemon.icon_count(), 1, "Should have exactly 1 icon");
+    }
+
+    #[test]
+    fn test_folder_create_event_adds_icon_to_hashmap() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Create a test folder
+        let test_folder = desktop_path.join("test_folder");
+        fs::create_dir(&test_folder).unwrap();
+
+        // Simulate a Create event
+        let event = Event {
+            kind: EventKind::Create(CreateKind::Folder),
+            paths: vec![test_folder.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(event).unwrap();
+
+        assert!(daemon.has_icon(&test_folder), "Icon should be added for folder Create event");
+    }
+
+    #[test]
+    fn test_create_event_multiple_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Create multiple test files
+        let test_file1 = desktop_path.join("file1.txt");
+        let test_file2 = desktop_path.join("file2.txt");
+        fs::write(&test_file1, "content1").unwrap();
+        fs::write(&test_file2, "content2").unwrap();
+
+        // Simulate a Create event with multiple paths
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![test_file1.clone(), test_file2.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(event).unwrap();
+
+        assert!(daemon.has_icon(&test_file1), "Icon should be added for first file");
+        assert!(daemon.has_icon(&test_file2), "Icon should be added for second file");
+        assert_eq!(daemon.icon_count(), 2, "Should have exactly 2 icons");
+    }
+
+    // ========================================================================
+    // File Delete Event Tests
+    // ========================================================================
+
+    #[test]
+    fn test_file_delete_event_removes_icon_from_hashmap() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Create a test file and add it to icons
+        let test_file = desktop_path.join("test_file.txt");
+        fs::write(&test_file, "test content").unwrap();
+
+        // First add the icon via Create event
+        let create_event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![test_file.clone()],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(create_event).unwrap();
+        assert!(daemon.has_icon(&test_file), "Icon should exist after Create");
+
+        // Now simulate a Remove event
+        let remove_event = Event {
+            kind: EventKind::Remove(RemoveKind::File),
+            paths: vec![test_file.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(remove_event).unwrap();
+
+        assert!(!daemon.has_icon(&test_file), "Icon should be removed from HashMap after Remove event");
+        assert_eq!(daemon.icon_count(), 0, "Should have no icons after removal");
+    }
+
+    #[test]
+    fn test_delete_event_for_nonexistent_icon_is_harmless() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Try to remove a file that was never added
+        let nonexistent = desktop_path.join("never_existed.txt");
+
+        let remove_event = Event {
+            kind: EventKind::Remove(RemoveKind::File),
+            paths: vec![nonexistent],
+            attrs: Default::default(),
+        };
+
+        // Should not panic or error
+        let result = daemon.handle_fs_event(remove_event);
+        assert!(result.is_ok(), "Removing nonexistent icon should not error");
+    }
+
+    // ========================================================================
+    // File Modify Event Tests
+    // ========================================================================
+
+    #[test]
+    fn test_file_modify_event_refreshes_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Create a test file and add it to icons
+        let test_file = desktop_path.join("test_file.txt");
+        fs::write(&test_file, "initial content").unwrap();
+
+        // Add the icon via Create event
+        let create_event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![test_file.clone()],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(create_event).unwrap();
+        assert_eq!(daemon.icon_count(), 1, "Should have 1 icon before modify");
+
+        // Modify the file
+        fs::write(&test_file, "modified content").unwrap();
+
+        // Simulate a Modify event
+        let modify_event = Event {
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content)),
+            paths: vec![test_file.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(modify_event).unwrap();
+
+        // Icon should still exist (was refreshed)
+        assert!(daemon.has_icon(&test_file), "Icon should still exist after Modify event");
+        assert_eq!(daemon.icon_count(), 1, "Should still have exactly 1 icon");
+    }
+
+    #[test]
+    fn test_modify_event_on_unknown_path_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Try to modify a file that was never added
+        let unknown_file = desktop_path.join("unknown.txt");
+        fs::write(&unknown_file, "content").unwrap();
+
+        let modify_event = Event {
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content)),
+            paths: vec![unknown_file.clone()],
+            attrs: Default::default(),
+        };
+
+        // Should not add the icon (only modifies existing ones)
+        daemon.handle_fs_event(modify_event).unwrap();
+
+        assert!(!daemon.has_icon(&unknown_file), "Modify event should not add new icons");
+        assert_eq!(daemon.icon_count(), 0, "Should have no icons");
+    }
+
+    /// Daemon with a selected icon for `name`, to tell whether events recreate it
+    fn daemon_with_selected_icon(desktop_path: &Path, name: &str) -> (IconDaemon, PathBuf) {
+        let mut daemon = create_test_daemon(desktop_path.to_path_buf());
+        let path = desktop_path.join(name);
+        fs::write(&path, "initial content").unwrap();
+        daemon.add_icon(&path).unwrap();
+        daemon.select_only(&path);
+        (daemon, path)
+    }
+
+    fn modify_any(path: &Path) -> Event {
+        Event {
+            kind: EventKind::Modify(ModifyKind::Any),
+            paths: vec![path.to_path_buf()],
+            attrs: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_modify_any_unchanged_file_keeps_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, path) = daemon_with_selected_icon(temp_dir.path(), "notes.txt");
+        daemon.needs_render = false;
+
+        daemon.handle_fs_event(modify_any(&path)).unwrap();
+
+        let icon = &daemon.icons[&path];
+        assert!(icon.is_selected(), "Unchanged file should keep its icon");
+        assert_eq!(icon.icon_type(), IconType::Document);
+        assert!(daemon.dirty_icons.contains(&path), "Icon should still be redrawn");
+        assert!(!daemon.needs_render, "Other icons are left alone");
+    }
+
+    #[test]
+    fn test_modify_any_changed_metadata_recreates_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, path) = daemon_with_selected_icon(temp_dir.path(), "notes.txt");
+
+        fs::write(&path, "content of another length").unwrap();
+        daemon.handle_fs_event(modify_any(&path)).unwrap();
+
+        assert!(daemon.has_icon(&path));
+        assert!(!daemon.icons[&path].is_selected(), "Changed file should get a new icon");
+    }
+
+    #[test]
+    fn test_modify_any_changed_type_recreates_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, path) = daemon_with_selected_icon(temp_dir.path(), "notes.txt");
+        assert_eq!(daemon.icons[&path].icon_type(), IconType::Document);
+
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        daemon.handle_fs_event(modify_any(&path)).unwrap();
+
+        assert_eq!(daemon.icons[&path].icon_type(), IconType::Folder);
+        assert!(!daemon.icons[&path].is_selected());
+    }
+
+    #[test]
+    fn test_modify_any_recreate_config_always_recreates() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, path) = daemon_with_selected_icon(temp_dir.path(), "notes.txt");
+        daemon.config.ambiguous_modify = AmbiguousModify::Recreate;
+
+        daemon.handle_fs_event(modify_any(&path)).unwrap();
+
+        assert!(daemon.has_icon(&path));
+        assert!(!daemon.icons[&path].is_selected(), "Icon should be recreated regardless");
+    }
+
+    // ========================================================================
+    // Hidden File Filtering Tests
+    // ========================================================================
+
+    #[test]
+    fn test_scan_desktop_ignores_hidden_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        // Create both visible and hidden files
+        let visible_file = desktop_path.join("visible.txt");
+        let hidden_file = desktop_path.join(".hidden_file");
+        let hidden_folder = desktop_path.join(".hidden_folder");
+
+        fs::write(&visible_file, "visible content").unwrap();
+        fs::write(&hidden_file, "hidden content").unwrap();
+        fs::create_dir(&hidden_folder).unwrap();
+
+        // Create daemon and scan
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        daemon.scan_desktop().unwrap();
+
+        // Only the visible file should be added
+        assert!(daemon.has_icon(&visible_file), "Visible file should have an icon");
+        assert!(!daemon.has_icon(&hidden_file), "Hidden file (.hidden_file) should be ignored");
+        assert!(!daemon.has_icon(&hidden_folder), "Hidden folder (.hidden_folder) should be ignored");
+        assert_eq!(daemon.icon_count(), 1, "Should have exactly 1 icon (visible file only)");
+    }
+
+    #[test]
+    fn test_scan_desktop_shows_hidden_files_when_enabled() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let visible_file = desktop_path.join("visible.txt");
+        let hidden_file = desktop_path.join(".hidden_file");
+        let hidden_folder = desktop_path.join(".hidden_folder");
+
+        fs::write(&visible_file, "visible content").unwrap();
+        fs::write(&hidden_file, "hidden content").unwrap();
+        fs::create_dir(&hidden_folder).unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.show_hidden = true;
+        daemon.name_filter = daemon.config.name_filter();
+
+        daemon.scan_desktop().unwrap();
+
+        assert!(daemon.has_icon(&visible_file), "Visible file should have an icon");
+        assert!(daemon.has_icon(&hidden_file), "Hidden file should be shown");
+        assert!(daemon.has_icon(&hidden_folder), "Hidden folder should be shown");
+        assert_eq!(daemon.icon_count(), 3);
+    }
+
+    #[test]
+    fn test_create_event_applies_show_hidden() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let hidden_file = desktop_path.join(".foo");
+        fs::write(&hidden_file, "foo").unwrap();
+        let create = || Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![hidden_file.clone()],
+            attrs: Default::default(),
+        };
+
+        daemon.handle_fs_event(create()).unwrap();
+        assert!(!daemon.has_icon(&hidden_file), "Created dotfiles are hidden like scanned ones");
+
+        daemon.apply_config(Config { show_hidden: true, ..test_config() }).unwrap();
+        assert!(daemon.has_icon(&hidden_file), "Enabling show_hidden picks up existing dotfiles");
+        daemon.apply_config(test_config()).unwrap();
+        assert!(!daemon.has_icon(&hidden_file));
+
+        daemon.config.show_hidden = true;
+        daemon.name_filter = daemon.config.name_filter();
+        daemon.handle_fs_event(create()).unwrap();
+        assert!(daemon.has_icon(&hidden_file));
+    }
+
+    #[test]
+    fn test_scan_desktop_ignores_files_starting_with_dot() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        // Create files with various hidden patterns
+        let dotfile = desktop_path.join(".bashrc");
+        let dot_config = desktop_path.join(".config");
+        let dot_ds_store = desktop_path.join(".DS_Store");
+        let normal_file = desktop_path.join("readme.txt");
+
+        fs::write(&dotfile, "").unwrap();
+        fs::create_dir(&dot_config).unwrap();
+        fs::write(&dot_ds_store, "").unwrap();
+        fs::write(&normal_file, "").unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path);
+
+        daemon.scan_desktop().unwrap();
+
+        assert!(!daemon.has_icon(&dotfile), ".bashrc should be ignored");
+        assert!(!daemon.has_icon(&dot_config), ".config should be ignored");
+        assert!(!daemon.has_icon(&dot_ds_store), ".DS_Store should be ignored");
+        assert!(daemon.has_icon(&normal_file), "readme.txt should have an icon");
+        assert_eq!(daemon.icon_count(), 1);
+    }
+
+    #[test]
+    fn test_scan_desktop_empty_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let mut daemon = create_test_daemon(desktop_path);
+
+        daemon.scan_desktop().unwrap();
+
+        assert_eq!(daemon.icon_count(), 0, "Empty directory should have no icons");
+    }
+
+    #[test]
+    fn test_scan_desktop_nonexistent_directory() {
+        let nonexistent_path = PathBuf::from("/nonexistent/desktop/path/12345");
+
+        let mut daemon = create_test_daemon(nonexistent_path);
+
+        // Should not error, just return Ok with no icons
+        let result = daemon.scan_desktop();
+        assert!(result.is_ok(), "Scanning nonexistent directory should not error");
+        assert_eq!(daemon.icon_count(), 0);
+    }
+
+    // ========================================================================
+    // Icon Count Tests
+    // ========================================================================
+
+    #[test]
+    fn test_icon_count_accuracy() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        assert_eq!(daemon.icon_count(), 0, "Initial count should be 0");
+
+        // Add some files
+        for i in 0..5 {
+            let file = desktop_path.join(format!("file{}.txt", i));
+            fs::write(&file, "content").unwrap();
+            let event = Event {
+                kind: EventKind::Create(CreateKind::File),
+                paths: vec![file],
+                attrs: Default::default(),
+            };
+            daemon.handle_fs_event(event).unwrap();
+        }
+
+        assert_eq!(daemon.icon_count(), 5, "Should have 5 icons after adding 5 files");
+    }
+
+    // ========================================================================
+    // Duplicate Add Prevention Tests
+    // ========================================================================
+
+    #[test]
+    fn test_add_icon_prevents_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let test_file = desktop_path.join("test_file.txt");
+        fs::write(&test_file, "content").unwrap();
+
+        // Add the same icon twice
+        daemon.add_icon(&test_file).unwrap();
+        daemon.add_icon(&test_file).unwrap();
+
+        assert_eq!(daemon.icon_count(), 1, "Should still have only 1 icon after duplicate add");
+    }
+
+    // ========================================================================
+    // Drag Tests
+    // ========================================================================
+
+    #[test]
+    fn test_cursor_shows_a_hand_over_icons_and_grabs_while_dragging() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("a.txt");
+        fs::write(&file, "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(backend.clone()));
+        daemon.scan_desktop().unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+        let button = |pressed| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+
+        backend.push_input(InputEvent::PointerEnter { surface_id, x: 5.0, y: 5.0 });
+        daemon.handle_input(Instant::now());
+        assert_eq!(backend.cursor_shape(), CursorShape::Pointer);
+
+        backend.push_input(button(true));
+        backend.push_input(InputEvent::PointerMotion { surface_id, x: 45.0, y: 35.0 });
+        daemon.handle_input(Instant::now());
+        assert!(daemon.drag.is_some());
+        assert_eq!(backend.cursor_shape(), CursorShape::Grabbing);
+
+        backend.push_input(button(false));
+        daemon.handle_input(Instant::now());
+        assert_eq!(backend.cursor_shape(), CursorShape::Pointer);
+
+        backend.push_input(InputEvent::PointerLeave { surface_id });
+        daemon.handle_input(Instant::now());
+        assert_eq!(backend.cursor_shape(), CursorShape::Default);
+    }
+
+    #[test]
+    fn test_drag_count_includes_pressed_and_selected_icons() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| desktop_path.join(name))
+            .collect();
+        for file in &files {
+            fs::write(file, "content").unwrap();
+        }
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.scan_desktop().unwrap();
+
+        // Unselected icon on its own
+        assert_eq!(daemon.drag_count(&files[0]), 1);
+
+        daemon.get_icon_mut(&files[0]).unwrap().set_selected(true);
+        daemon.get_icon_mut(&files[1]).unwrap().set_selected(true);
+        assert_eq!(daemon.selected_count(), 2);
+
+        // Dragging a selected icon carries the selection
+        assert_eq!(daemon.drag_count(&files[0]), 2);
+        // Dragging an unselected icon adds it to the selection
+        assert_eq!(daemon.drag_count(&files[2]), 3);
+    }
+
+    #[test]
+    fn test_dragging_an_icon_moves_it_to_the_drop_cell() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let (a, b) = (desktop_path.join("a.txt"), desktop_path.join("b.txt"));
+        fs::write(&a, "x").unwrap();
+        fs::write(&b, "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(backend.clone()));
+        daemon.scan_desktop().unwrap();
+
+        let surface_id = daemon.path_to_surface[&a];
+        let start = backend.surface_position(surface_id).unwrap();
+        let button = |pressed, x, y| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed,
+            x,
+            y,
+            modifiers: KeyModifiers::default(),
+        };
+        // Pointer coordinates stay relative to the surface, wherever it moved
+        let to_surface = |(x, y): (i32, i32)| {
+            let (surface_x, surface_y) = backend.surface_position(surface_id).unwrap();
+            ((x - surface_x) as f64, (y - surface_y) as f64)
+        };
+        let cell_width = daemon.config.icon_size + daemon.config.grid_spacing;
+        let cell_height = daemon.config.icon_size + daemon.label_height() + daemon.config.grid_spacing;
+        let (columns, _) = daemon.grid_dimensions();
+        let index = Cell { col: 3, row: 1 }.index(columns);
+        let (x, y) = cell_origin(index, columns, cell_width, cell_height);
+        let area = daemon.grid_area();
+        let target = (area.x + x + 10, area.y + y + 10);
+
+        backend.push_input(button(true, 5.0, 5.0));
+        daemon.handle_input(Instant::now());
+        let (x, y) = to_surface((start.0 + 5 + 30, start.1 + 5 + 20));
+        backend.push_input(InputEvent::PointerMotion { surface_id, x, y });
+        daemon.handle_input(Instant::now());
+        assert!(daemon.drag.is_some());
+        assert_eq!(backend.surface_position(surface_id), Some((start.0 + 30, start.1 + 20)));
+
+        let (x, y) = to_surface(target);
+        backend.push_input(InputEvent::PointerMotion { surface_id, x, y });
+        backend.push_input(button(false, x, y));
+        daemon.handle_input(Instant::now());
+        assert!(daemon.drag.is_none());
+        assert_eq!(daemon.placements.get(&a), Some(Cell { col: 3, row: 1 }));
+        assert_eq!(daemon.icons()[&a].layout_index(), columns + 3);
+
+        // Deleting the dragged file mid-drag cancels the drag
+        let surface_id = daemon.path_to_surface[&b];
+        let press = InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed: true,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+        backend.push_input(press);
+        backend.push_input(InputEvent::PointerMotion { surface_id, x: 50.0, y: 50.0 });
+        daemon.handle_input(Instant::now());
+        assert!(daemon.drag.is_some());
+        daemon.remove_icon(&b);
+        assert!(daemon.drag.is_none());
+        assert!(daemon.pointer_press.is_none());
+        assert_eq!(daemon.placements.get(&b), None);
+    }
+
+    #[test]
+    fn test_touch_taps_and_drags_like_the_primary_button() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("a.txt");
+        fs::write(&file, "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.config.swap_buttons = true;
+        daemon.backend = Some(Box::new(backend.clone()));
+        daemon.scan_desktop().unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+        let start = backend.surface_position(surface_id).unwrap();
+        let touch = |pressed| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_TOUCH,
+            pressed,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+
+        // A tap selects, even with the buttons swapped
+        backend.push_input(touch(true));
+        backend.push_input(touch(false));
+        daemon.handle_input(Instant::now());
+        assert!(daemon.icons()[&file].is_selected());
+        assert!(daemon.context_menu.is_none());
+
+        // A drag the compositor takes over puts the icon back
+        backend.push_input(touch(true));
+        backend.push_input(InputEvent::PointerMotion { surface_id, x: 60.0, y: 40.0 });
+        daemon.handle_input(Instant::now() + Duration::from_secs(1));
+        assert!(daemon.drag.is_some());
+        backend.push_input(InputEvent::PointerCancel { surface_id });
+        daemon.handle_input(Instant::now() + Duration::from_secs(1));
+        assert!(daemon.drag.is_none());
+        assert!(daemon.pointer_press.is_none());
+        assert_eq!(backend.surface_position(surface_id), Some(start));
+    }
+
+    #[test]
+    fn test_dropped_icons_snap_or_stay_where_dropped() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let (a, b) = (desktop_path.join("a.txt"), desktop_path.join("b.txt"));
+        fs::write(&a, "x").unwrap();
+        fs::write(&b, "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(backend.clone()));
+        daemon.scan_desktop().unwrap();
+        daemon.place_icon(&b, 3, 1).unwrap();
+
+        // Drags the icon for `path` so its surface lands at `to`
+        let drag = |daemon: &mut IconDaemon, path: &Path, to: (i32, i32)| {
+            let surface_id = daemon.path_to_surface[path];
+            let (x, y) = backend.surface_position(surface_id).unwrap();
+            let button = |pressed, x, y| InputEvent::PointerButton {
+                surface_id,
+                button: BTN_LEFT,
+                pressed,
+                x,
+                y,
+                modifiers: KeyModifiers::default(),
+            };
+            // The pointer keeps its spot on the surface, which moves with it
+            let (x, y) = (f64::from(to.0 - x + 5), f64::from(to.1 - y + 5));
+            backend.push_input(button(true, 5.0, 5.0));
+            backend.push_input(InputEvent::PointerMotion { surface_id, x, y });
+            daemon.handle_input(Instant::now());
+            backend.push_input(button(false, 5.0, 5.0));
+            daemon.handle_input(Instant::now());
+        };
+        let cell_width = daemon.config.icon_size + daemon.config.grid_spacing;
+        let cell_height = daemon.config.icon_size + daemon.label_height() + daemon.config.grid_spacing;
+        let (columns, _) = daemon.grid_dimensions();
+        let area = daemon.grid_area();
+        let cell_position = |col: u32, row: u32| {
+            let (x, y) = cell_origin(Cell { col, row }.index(columns), columns, cell_width, cell_height);
+            (area.x + x, area.y + y)
+        };
+
+        // Dropped a little off the cell `b` was placed in, `a` moves on to the next one
+        let (x, y) = cell_position(3, 1);
+        drag(&mut daemon, &a, (x + 9, y - 7));
+        assert_eq!(daemon.placements.get(&a), Some(Cell { col: 4, row: 1 }));
+        assert_eq!(daemon.placements.get(&b), Some(Cell { col: 3, row: 1 }), "Placed icons aren't evicted");
+        assert_eq!(backend.surface_position(daemon.path_to_surface[&a]), Some(cell_position(4, 1)));
+
+        // Without snapping it stays where it was dropped, and keeps a cell
+        daemon.config.snap_to_grid = false;
+        let (x, y) = cell_position(1, 2);
+        drag(&mut daemon, &a, (x + 9, y - 7));
+        assert_eq!(daemon.placements.get(&a), Some(Cell { col: 1, row: 2 }));
+        assert_eq!(backend.surface_position(daemon.path_to_surface[&a]), Some((x + 9, y - 7)));
+
+        // Turning snapping back on lines it up again
+        daemon.config.snap_to_grid = true;
+        daemon.reposition_all_icons();
+        assert_eq!(backend.surface_position(daemon.path_to_surface[&a]), Some((x, y)));
+    }
+
+    #[test]
+    fn test_end_drag_without_drag_is_harmless() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+
+        daemon.end_drag();
+        assert!(daemon.drag.is_none());
+    }
+
+    // ========================================================================
+    // Ignore/Allow Pattern Tests
+    // ========================================================================
+
+    #[test]
+    fn test_scan_desktop_applies_ignore_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let kept = desktop_path.join("notes.txt");
+        let tmp = desktop_path.join("build.tmp");
+        let thumbs = desktop_path.join("Thumbs.db");
+        for file in [&kept, &tmp, &thumbs] {
+            fs::write(file, "content").unwrap();
+        }
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.config.ignore_patterns = vec!["*.tmp".to_string(), "Thumbs.db".to_string()];
+        daemon.name_filter = daemon.config.name_filter();
+        daemon.scan_desktop().unwrap();
+
+        assert!(daemon.has_icon(&kept));
+        assert!(!daemon.has_icon(&tmp));
+        assert!(!daemon.has_icon(&thumbs));
+    }
+
+    #[test]
+    fn test_create_event_respects_ignore_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.ignore_patterns = vec!["~*".to_string()];
+        daemon.name_filter = daemon.config.name_filter();
+
+        let lock_file = desktop_path.join("~lock.odt");
+        let hidden_file = desktop_path.join(".partial");
+        fs::write(&lock_file, "lock").unwrap();
+        fs::write(&hidden_file, "partial").unwrap();
+
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![lock_file.clone(), hidden_file.clone()],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(event).unwrap();
+
+        assert_eq!(daemon.icon_count(), 0, "Ignored and hidden files should not get icons");
+    }
+
+    #[test]
+    fn test_apply_config_refilters_icons() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let txt = desktop_path.join("a.txt");
+        let log = desktop_path.join("b.log");
+        fs::write(&txt, "a").unwrap();
+        fs::write(&log, "b").unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.scan_desktop().unwrap();
+        assert_eq!(daemon.icon_count(), 2);
+
+        // Newly ignored icons are removed
+        let mut config = test_config();
+        config.ignore_patterns = vec!["*.log".to_string()];
+        daemon.apply_config(config).unwrap();
+        assert!(daemon.has_icon(&txt));
+        assert!(!daemon.has_icon(&log));
+
+        // Dropping the pattern brings them back
+        daemon.apply_config(test_config()).unwrap();
+        assert!(daemon.has_icon(&log));
+    }
+
+    #[test]
+    fn test_apply_config_that_fails_changes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        // A file where the desktop directory should be can't be listed
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::write(&desktop_path, "").unwrap();
+        let mut daemon = create_test_daemon(desktop_path);
+
+        let config = Config { show_hidden: true, grid_spacing: 31, ..test_config() };
+        assert!(daemon.apply_config(config).is_err());
+        assert!(!daemon.config.show_hidden);
+        assert_eq!(daemon.config.grid_spacing, test_config().grid_spacing);
+    }
+
+    // ========================================================================
+    // Folder Peek Tests
+    // ========================================================================
+
+    #[test]
+    fn test_event_inside_folder_does_not_add_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+
+        let folder = desktop_path.join("Photos");
+        fs::create_dir(&folder).unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.scan_desktop().unwrap();
+        assert_eq!(daemon.icon_count(), 1);
+
+        let inner = folder.join("beach.png");
+        fs::write(&inner, "png").unwrap();
+
+        let create_event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![inner.clone()],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(create_event).unwrap();
+
+        assert!(!daemon.has_icon(&inner), "Folder contents should not become desktop icons");
+        assert_eq!(daemon.icon_count(), 1);
+        assert!(daemon.dirty_icons.contains(&folder), "Folder peek change should trigger a render");
+    }
+
+    // ========================================================================
+    // Frame Pacing Tests
+    // ========================================================================
+
+    #[test]
+    fn test_frame_due_timer_pacing_waits_for_tick() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.frame_pacing = FramePacing::Timer;
+
+        assert!(!daemon.frame_due(false));
+        assert!(daemon.frame_due(true));
+    }
+
+    #[test]
+    fn test_frame_due_frame_callback_without_pending_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let daemon = create_test_daemon(temp_dir.path().to_path_buf());
+
+        // Nothing waiting on the compositor, so a frame may be drawn right away
+        assert_eq!(daemon.config.frame_pacing, FramePacing::FrameCallback);
+        assert!(daemon.frame_due(false));
+    }
+
+    #[test]
+    fn test_frame_callbacks_pace_each_output_independently() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let fast = desktop_path.join("fast.txt");
+        let also_fast = desktop_path.join("also-fast.txt");
+        let slow = desktop_path.join("slow.txt");
+        for path in [&fast, &also_fast, &slow] {
+            fs::write(path, "x").unwrap();
+        }
+
+        let backend = HeadlessBackend::new(1920, 1080).with_frame_callbacks();
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), false);
+        let fast_id = daemon.path_to_surface[&fast];
+        let also_fast_id = daemon.path_to_surface[&also_fast];
+        let slow_id = daemon.path_to_surface[&slow];
+        backend.set_frame_group(fast_id, 1);
+        backend.set_frame_group(also_fast_id, 1);
+        backend.set_frame_group(slow_id, 2);
+
+        // Output 1 presents every frame, output 2 only every fourth. With a
+        // single shared callback every icon would be drawn 3 times in 12
+        // ticks; paced per output both fast ones are drawn on every tick.
+        for tick in 0..12 {
+            for icon in daemon.icons.values_mut() {
+                icon.set_selected(!icon.is_selected());
+            }
+            daemon.needs_render = true;
+            backend.present_frames(1);
+            if tick % 4 == 0 {
+                backend.present_frames(2);
+            }
+            daemon.step(Instant::now(), false);
+        }
+
+        for id in [fast_id, also_fast_id] {
+            let fast_frames = backend.surface(id).unwrap().frames;
+            assert!(fast_frames >= 12, "An icon on the fast output drew {} frames", fast_frames);
+        }
+        let slow_frames = backend.surface(slow_id).unwrap().frames;
+        assert!(slow_frames <= 4, "The slow output drew {} frames", slow_frames);
+    }
+
+    #[test]
+    fn test_hover_redraws_only_the_hovered_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let hovered = desktop_path.join("hovered.txt");
+        let other = desktop_path.join("other.txt");
+        fs::write(&hovered, "x").unwrap();
+        fs::write(&other, "x").unwrap();
+
+        let backend = HeadlessBackend::new(1920, 1080);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let hovered_id = daemon.path_to_surface[&hovered];
+        let other_id = daemon.path_to_surface[&other];
+        let before = |id| backend.surface(id).unwrap().frames;
+        let (hovered_frames, other_frames) = (before(hovered_id), before(other_id));
+
+        backend.push_input(InputEvent::PointerEnter { surface_id: hovered_id, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        assert!(backend.surface(hovered_id).unwrap().frames > hovered_frames);
+        assert_eq!(backend.surface(other_id).unwrap().frames, other_frames);
+        assert!(daemon.dirty_icons.is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_destroys_every_surface() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        fs::write(desktop_path.join("a.txt"), "x").unwrap();
+        fs::write(desktop_path.join("b.txt"), "x").unwrap();
+
+        let backend = HeadlessBackend::new(1920, 1080);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(backend.surface_ids().len(), 2);
+
+        daemon.shutdown();
+        assert!(backend.surface_ids().is_empty());
+        assert!(daemon.path_to_surface.is_empty());
+        assert_eq!(daemon.icon_count(), 2, "Icons are kept");
+    }
+
+    // ========================================================================
+    // Late Configure Tests
+    // ========================================================================
+
+    #[test]
+    fn test_late_configure_retries_render() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+
+        // Frame 0: attach was skipped because surface 7 wasn't configured
+        daemon.awaiting_configure.insert(7);
+        daemon.needs_render = false;
+
+        // A few frames pass where other surfaces configure
+        daemon.note_configured(&[]);
+        daemon.note_configured(&[3]);
+        assert!(!daemon.needs_render, "Unrelated configures should not force a render");
+
+        // Surface 7 finally configures
+        daemon.note_configured(&[7]);
+        assert!(daemon.needs_render, "Render should be retried after configure");
+        assert!(daemon.awaiting_configure.is_empty());
+
+        // Only retried once
+        daemon.needs_render = false;
+        daemon.note_configured(&[7]);
+        assert!(!daemon.needs_render);
+    }
+
+    // ========================================================================
+    // Reserved Edge Tests
+    // ========================================================================
+
+    #[test]
+    fn test_grid_area_respects_reserved_edges() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.reserved_edges.left = 80;
+        daemon.config.reserved_edges.bottom = 60;
+
+        let area = daemon.grid_area();
+        assert_eq!(area, UsableArea { x: 80, y: 0, width: 1840, height: 1020 });
+    }
+
+    #[test]
+    fn test_grid_follows_work_area_with_reserved_edges_as_fallback() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let backend = HeadlessBackend::new(1920, 1080);
+        backend.set_work_area(Some((1920, 1040)));
+        let mut config = Config { grid_anchor: GridAnchor::WorkArea, ..test_config() };
+        config.reserved_edges.top = 80;
+        let mut daemon =
+            IconDaemon::with_backend(config, temp_dir.path().to_path_buf(), Some(Box::new(backend.clone()))).unwrap();
+
+        assert!(backend.anchors_to_work_area());
+        assert_eq!(daemon.grid_area(), UsableArea { x: 0, y: 0, width: 1920, height: 1040 });
+
+        // The panel went away, or the compositor stopped reporting the work area
+        backend.set_work_area(None);
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.grid_area(), UsableArea { x: 0, y: 80, width: 1920, height: 1000 });
+
+        backend.set_work_area(Some((1920, 1000)));
+        daemon.apply_config(test_config()).unwrap();
+        assert!(!backend.anchors_to_work_area());
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.work_area, None);
+    }
+
+    #[test]
+    fn test_place_icon_offsets_into_usable_area() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut icon = DesktopIcon::new(&file, &test_config()).unwrap();
+        let area = UsableArea { x: 100, y: 50, width: 800, height: 600 };
+
+        let first = place_icon(&mut icon, area, 1, 0, (84, 108), Corner::TopLeft);
+        assert!(first.x >= 100, "Icon must not sit in the reserved left edge");
+        assert!(first.y >= 50, "Icon must not sit in the reserved top edge");
+
+        // Filled from the far corner, the cell keeps the same distance to the far edges
+        let last = place_icon(&mut icon, area, 1, 0, (84, 108), Corner::BottomRight);
+        assert_eq!(100 + 800 - (last.x + 84), first.x - 100);
+        assert_eq!(50 + 600 - (last.y + 108), first.y - 50);
+    }
+
+    // ========================================================================
+    // Modifier Selection Tests
+    // ========================================================================
+
+    fn daemon_with_files(names: &[&str]) -> (TempDir, IconDaemon, Vec<PathBuf>) {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        // Add one by one so grid order follows the given order
+        let paths: Vec<PathBuf> = names.iter().map(|n| desktop_path.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+            daemon.add_icon(path).unwrap();
+        }
+
+        (temp_dir, daemon, paths)
+    }
+
+    fn selection(daemon: &IconDaemon, paths: &[PathBuf]) -> Vec<bool> {
+        paths.iter().map(|p| daemon.get_icon(p).unwrap().is_selected()).collect()
+    }
+
+    const NONE: KeyModifiers = KeyModifiers { ctrl: false, shift: false, alt: false };
+    const CTRL: KeyModifiers = KeyModifiers { ctrl: true, shift: false, alt: false };
+    const SHIFT: KeyModifiers = KeyModifiers { ctrl: false, shift: true, alt: false };
+    const CTRL_SHIFT: KeyModifiers = KeyModifiers { ctrl: true, shift: true, alt: false };
+
+    #[test]
+    fn test_plain_click_selects_only_clicked_icon() {
+        let (_tmp, mut daemon, paths) = daemon_with_files(&["a", "b", "c"]);
+
+        daemon.click_select(&paths[0], NONE);
+        daemon.click_select(&paths[2], NONE);
+        assert_eq!(selection(&daemon, &paths), vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_ctrl_click_toggles() {
+        let (_tmp, mut daemon, paths) = daemon_with_files(&["a", "b", "c"]);
+
+        daemon.click_select(&paths[0], CTRL);
+        daemon.click_select(&paths[2], CTRL);
+        assert_eq!(selection(&daemon, &paths), vec![true, false, true]);
+
+        daemon.click_select(&paths[0], CTRL);
+        assert_eq!(selection(&daemon, &paths), vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_shift_click_selects_grid_range() {
+        let (_tmp, mut daemon, paths) = daemon_with_files(&["a", "b", "c", "d", "e"]);
+
+        daemon.click_select(&paths[3], NONE);
+        daemon.click_select(&paths[1], SHIFT);
+        assert_eq!(selection(&daemon, &paths), vec![false, true, true, true, false]);
+
+        // The anchor stays put; another shift-click replaces the range
+        daemon.click_select(&paths[4], SHIFT);
+        assert_eq!(selection(&daemon, &paths), vec![false, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_ctrl_shift_click_extends_selection() {
+        let (_tmp, mut daemon, paths) = daemon_with_files(&["a", "b", "c", "d", "e"]);
+
+        daemon.click_select(&paths[0], NONE);
+        daemon.click_select(&paths[3], CTRL);
+        daemon.click_select(&paths[4], CTRL_SHIFT);
+        assert_eq!(selection(&daemon, &paths), vec![true, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_plain_click_on_selected_icon_defers_collapse() {
+        let (_tmp, mut daemon, paths) = daemon_with_files(&["a", "b"]);
+
+        daemon.click_select(&paths[0], CTRL);
+        daemon.click_select(&paths[1], CTRL);
+        assert!(daemon.click_select(&paths[0], NONE), "Selection should be kept for dragging");
+        assert_eq!(selection(&daemon, &paths), vec![true, true]);
+    }
+
+    // ========================================================================
+    // Trash Icon Tests
+    // ========================================================================
+
+    #[test]
+    fn test_trash_icon_tracks_trash_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut daemon = create_test_daemon(desktop_path);
+
+        daemon.add_trash_icon(&trash_dir);
+        assert!(trash::files_dir(&trash_dir).is_dir(), "Trash directory should be created");
+        assert!(!daemon.get_icon(&trash_dir).unwrap().is_trash_full());
+
+        let trashed = trash::files_dir(&trash_dir).join("old.txt");
+        fs::write(&trashed, "x").unwrap();
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![trashed],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(event).unwrap();
+
+        assert!(daemon.get_icon(&trash_dir).unwrap().is_trash_full());
+        assert_eq!(daemon.icon_count(), 1, "Trashed files must not become desktop icons");
+
+        // Emptying the trash is permanent, so it asks first
+        daemon.perform_action(&trash_dir, ContextAction::EmptyTrash).unwrap();
+        assert_eq!(daemon.delete_prompt().as_deref(), Some("Permanently empty the trash?"));
+        assert!(daemon.get_icon(&trash_dir).unwrap().is_trash_full());
+
+        daemon.answer_delete_prompt(true).unwrap();
+        assert!(!daemon.get_icon(&trash_dir).unwrap().is_trash_full());
+    }
+
+    #[test]
+    fn test_single_trash_skips_confirmation_and_delete_asks() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.add_trash_icon(&trash_dir);
+
+        let trashed = desktop_path.join("old.txt");
+        let kept = desktop_path.join("keep.txt");
+        for path in [&trashed, &kept] {
+            fs::write(path, "x").unwrap();
+            daemon.add_icon(path).unwrap();
+        }
+
+        daemon.perform_action(&trashed, ContextAction::MoveToTrash).unwrap();
+        assert!(daemon.delete_prompt().is_none());
+        assert!(!trashed.exists());
+        assert!(trash::files_dir(&trash_dir).join("old.txt").exists());
+        assert!(daemon.get_icon(&trash_dir).unwrap().is_trash_full());
+
+        daemon.perform_action(&kept, ContextAction::Delete).unwrap();
+        assert_eq!(daemon.delete_prompt().as_deref(), Some("Delete 1 item?"));
+        daemon.answer_delete_prompt(false).unwrap();
+        assert!(daemon.delete_prompt().is_none());
+        assert!(kept.exists(), "Cancelling must not delete");
+    }
+
+    #[test]
+    fn test_deleting_selection_asks_from_threshold() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.confirm_delete_threshold = 2;
+        daemon.add_trash_icon(&trash_dir);
+
+        let paths = [desktop_path.join("a.txt"), desktop_path.join("b.txt")];
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+            daemon.add_icon(path).unwrap();
+            daemon.icons.get_mut(path).unwrap().set_selected(true);
+        }
+        // The Trash icon is never deleted along with the selection
+        daemon.icons.get_mut(&trash_dir).unwrap().set_selected(true);
+
+        daemon.delete_selection(false).unwrap();
+        assert_eq!(daemon.delete_prompt().as_deref(), Some("Move 2 items to the trash?"));
+        assert!(paths.iter().all(|path| path.exists()));
+
+        daemon.answer_delete_prompt(true).unwrap();
+        assert!(paths.iter().all(|path| !path.exists()));
+        assert!(trash_dir.exists());
+    }
+
+    #[test]
+    fn test_delete_key_trashes_and_ctrl_z_restores() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.add_trash_icon(&trash_dir);
+        let ctrl = KeyModifiers { ctrl: true, ..KeyModifiers::default() };
+
+        let (file, gone) = (desktop_path.join("draft.txt"), desktop_path.join("gone.txt"));
+        for path in [&file, &gone] {
+            fs::write(path, "x").unwrap();
+            daemon.add_icon(path).unwrap();
+        }
+        daemon.place_icon(&file, 2, 1).unwrap();
+        daemon.select_only(&file);
+        daemon.handle_key(KEY_DELETE, KeyModifiers::default());
+        assert!(!file.exists());
+        assert!(!trash::is_empty(&trash_dir));
+        daemon.remove_icon(&file);
+
+        // A file deleted behind the daemon's back only logs an error
+        daemon.select_only(&gone);
+        fs::remove_file(&gone).unwrap();
+        daemon.handle_key(KEY_DELETE, KeyModifiers::default());
+
+        daemon.handle_key(KEY_Z, ctrl);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "x");
+        assert!(trash::is_empty(&trash_dir));
+        assert_eq!(daemon.placements.get(&file), Some(Cell { col: 2, row: 1 }), "Back to its cell");
+
+        // Nothing left to undo
+        daemon.handle_key(KEY_Z, ctrl);
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_delete_confirmation_popup_answers_clicks() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        daemon.perform_action(&file, ContextAction::Delete).unwrap();
+        daemon.step(Instant::now(), true);
+        let popup = daemon.pending_delete.as_ref().and_then(|pending| pending.surface).unwrap();
+        assert!(backend.surface(popup).unwrap().pixels.is_some(), "The popup should be drawn");
+
+        // A press on the message does nothing; one on Yes deletes
+        let message = (CONFIRM_POPUP_WIDTH as f64 / 2.0, 10.0);
+        let yes = (20.0, CONFIRM_POPUP_HEIGHT as f64 - 20.0);
+        for (x, y) in [message, yes] {
+            backend.push_input(InputEvent::PointerButton {
+                surface_id: popup,
+                button: BTN_LEFT,
+                pressed: true,
+                x,
+                y,
+                modifiers: KeyModifiers::default(),
+            });
+            daemon.step(Instant::now(), true);
+        }
+        assert!(!file.exists());
+        assert!(daemon.delete_prompt().is_none());
+        assert!(backend.surface(popup).is_none(), "The popup should be closed");
+    }
+
+    #[test]
+    fn test_empty_trash_action_rejects_other_icons() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let file = desktop_path.join("keep.txt");
+        fs::write(&file, "x").unwrap();
+        daemon.add_icon(&file).unwrap();
+
+        assert!(daemon.perform_action(&file, ContextAction::EmptyTrash).is_err());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_duplicate_names_get_disambiguated_labels() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir(&desktop_path).unwrap();
+        fs::create_dir(&documents).unwrap();
+        let first = desktop_path.join("notes.txt");
+        let second = documents.join("notes.txt");
+        let unique = desktop_path.join("todo.txt");
+        for path in [&first, &second, &unique] {
+            fs::write(path, "x").unwrap();
+        }
+
+        let mut daemon = create_test_daemon(desktop_path);
+        for path in [&first, &second, &unique] {
+            daemon.add_icon(path).unwrap();
+        }
+        assert_eq!(daemon.icons[&first].label(), "notes.txt (Desktop)");
+        assert_eq!(daemon.icons[&second].label(), "notes.txt (Documents)");
+        assert_eq!(daemon.icons[&unique].label(), "todo.txt");
+
+        let mut config = test_config();
+        config.duplicate_labels = DuplicateLabels::Plain;
+        daemon.apply_config(config).unwrap();
+        assert_eq!(daemon.icons[&first].label(), "notes.txt");
+
+        let mut config = test_config();
+        config.duplicate_labels = DuplicateLabels::ParentName;
+        daemon.apply_config(config).unwrap();
+        daemon.remove_icon(&second);
+        assert_eq!(daemon.icons[&first].label(), "notes.txt", "The name is unique again");
+
+        let path = Path::new("/srv/shared/notes.txt");
+        assert_eq!(
+            duplicate_label("notes.txt", path, DuplicateLabels::ParentPath).as_deref(),
+            Some("notes.txt (/srv/shared)")
+        );
+    }
+
+    #[test]
+    fn test_widget_refresh_interval_requests_renders() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let clock = desktop_path.join("clock.txt");
+        fs::write(&clock, "x").unwrap();
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.add_icon(&clock).unwrap();
+        daemon.get_icon_mut(&clock).unwrap().set_refresh_interval(Some(Duration::from_secs(1)));
+
+        let start = Instant::now();
+        daemon.dirty_icons.clear();
+        daemon.refresh_widgets(start);
+        assert!(daemon.dirty_icons.is_empty(), "Not due yet");
+
+        daemon.refresh_widgets(start + Duration::from_secs(1));
+        assert!(daemon.dirty_icons.contains(&clock));
+        daemon.dirty_icons.clear();
+        daemon.refresh_widgets(start + Duration::from_millis(1500));
+        assert!(daemon.dirty_icons.is_empty(), "Only once per interval");
+    }
+
+    #[test]
+    fn test_fifo_gets_no_widget_and_open_is_a_no_op() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let fifo = desktop_path.join("events.pipe");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let marker = temp_dir.path().join("opened");
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.config.open_commands =
+            HashMap::from([("pipe".to_string(), format!("touch {}", marker.display()))]);
+        daemon.add_icon(&fifo).unwrap();
+
+        let icon = daemon.get_icon(&fifo).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Fifo);
+        assert!(!icon.has_lua_process());
+
+        daemon.perform_action(&fifo, ContextAction::Open).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!marker.exists(), "Opening a FIFO should not run the open command");
+    }
+
+    #[test]
+    fn test_launchers_raise_their_running_application() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let launcher = desktop_path.join("foot.desktop");
+        fs::write(&launcher, "[Desktop Entry]\nType=Application\nExec=foot\nStartupWMClass=Foot\n").unwrap();
+        let marker = temp_dir.path().join("launched");
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config {
+            launcher_click: LauncherClick::FocusOrLaunch,
+            open_commands: HashMap::from([("desktop".to_string(), format!("touch {}", marker.display()))]),
+            ..test_config()
+        };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let launched = || {
+            std::thread::sleep(Duration::from_millis(100));
+            fs::remove_file(&marker).is_ok()
+        };
+
+        // Nothing running: launch
+        daemon.perform_action(&launcher, ContextAction::Open).unwrap();
+        assert!(launched());
+        assert!(backend.activated_apps().is_empty());
+
+        // Running: raise it, matching the app ID in any case
+        backend.set_running_apps(&["org.codeberg.dnkl.foot", "foot"]);
+        daemon.perform_action(&launcher, ContextAction::Open).unwrap();
+        assert!(!launched());
+        assert_eq!(backend.activated_apps(), vec!["foot"]);
+
+        // And with always_launch, the running window is left alone
+        daemon.config.launcher_click = LauncherClick::AlwaysLaunch;
+        daemon.perform_action(&launcher, ContextAction::Open).unwrap();
+        assert!(launched());
+        assert_eq!(backend.activated_apps().len(), 1);
+    }
+
+    #[test]
+    fn test_special_files_can_be_hidden() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let fifo = desktop_path.join("events");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.config.show_special_files = false;
+        daemon.add_icon(&fifo).unwrap();
+        assert!(!daemon.has_icon(&fifo));
+
+        // Toggling the option in a new config adds and removes them
+        daemon.apply_config(Config { show_special_files: true, ..test_config() }).unwrap();
+        assert!(daemon.has_icon(&fifo));
+        daemon.apply_config(Config { show_special_files: false, ..test_config() }).unwrap();
+        assert!(!daemon.has_icon(&fifo));
+    }
+
+    #[test]
+    fn test_trash_icon_survives_refilter() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.add_trash_icon(&trash_dir);
+
+        let mut config = test_config();
+        config.ignore_patterns = vec!["Trash".to_string()];
+        config.show_trash = true;
+        daemon.apply_config(config).unwrap();
+
+        assert!(daemon.has_icon(&trash_dir));
+    }
+
+    #[test]
+    fn test_pinned_icon_sits_in_corner_without_taking_a_cell() {
+        let area = UsableArea { x: 0, y: 0, width: 1920, height: 1080 };
+
+        let position = corner_position(Corner::BottomRight, area, 84, 108);
+        assert_eq!(position.x, 1920 - PINNED_MARGIN - 84);
+        assert_eq!(position.y, 1080 - PINNED_MARGIN - 108);
+
+        let position = corner_position(Corner::TopLeft, area, 84, 108);
+        assert_eq!((position.x, position.y), (PINNED_MARGIN, PINNED_MARGIN));
+
+        // Tiny areas clamp to the area origin instead of going negative
+        let tiny = UsableArea { x: 10, y: 10, width: 50, height: 50 };
+        let position = corner_position(Corner::BottomRight, tiny, 84, 108);
+        assert_eq!((position.x, position.y), (10, 10));
+    }
+
+    // ========================================================================
+    // Configured Size Tests
+    // ========================================================================
+
+    #[test]
+    fn test_icon_pixmap_matches_requested_buffer_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        daemon.add_icon(&file).unwrap();
+
+        // A compositor-chosen size that differs from icon_size + label
+        let pixmap = daemon.render_icon_pixmap(&file, 100, 120, 1).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (100, 120));
+
+        daemon.renderer.set_symbolic(Some(SymbolicStyle::new("hicolor", "#FFFFFF", false)));
+        let pixmap = daemon.render_icon_pixmap(&file, 100, 120, 1).unwrap();
+        assert_eq!(
+            (pixmap.width(), pixmap.height()),
+            (100, 120),
+            "Symbolic rendering must be fitted to the buffer too"
+        );
+    }
+
+    // ========================================================================
+    // Debounce Tests
+    // ========================================================================
+
+    #[test]
+    fn test_transient_file_never_gets_an_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let transient = desktop_path.join("download.part");
+        let start = Instant::now();
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Create(CreateKind::File)).add_path(transient.clone()),
+            start,
+        );
+        // The file still exists, so applying the create would add an icon
+        fs::write(&transient, "x").unwrap();
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(transient.clone()),
+            start,
+        );
+
+        daemon.flush_fs_events(start);
+        assert_eq!(daemon.icon_count(), 0, "Nothing is applied inside the window");
+
+        daemon.flush_fs_events(start + Duration::from_millis(daemon.config.fs_coalesce_ms));
+        assert_eq!(daemon.icon_count(), 0, "Create + remove should net to nothing");
+        assert!(!daemon.needs_render, "No icon work should have happened");
+    }
+
+    #[test]
+    fn test_zero_coalesce_window_applies_events_at_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.apply_config(Config { fs_coalesce_ms: 0, ..test_config() }).unwrap();
+        assert_eq!(daemon.health().fs_coalesce_ms, 0);
+
+        let file = desktop_path.join("report.pdf");
+        fs::write(&file, "x").unwrap();
+        let start = Instant::now();
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()),
+            start,
+        );
+        daemon.flush_fs_events(start);
+        assert!(daemon.has_icon(&file), "The create should not wait");
+
+        // A removal right after is applied on its own too, instead of cancelling the create
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(file.clone()),
+            start,
+        );
+        daemon.flush_fs_events(start);
+        assert!(!daemon.has_icon(&file));
+    }
+
+    #[test]
+    fn test_larger_coalesce_window_holds_and_folds_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.apply_config(Config { fs_coalesce_ms: 1000, ..test_config() }).unwrap();
+        assert_eq!(daemon.health().fs_coalesce_ms, 1000);
+
+        let file = desktop_path.join("report.pdf");
+        fs::write(&file, "x").unwrap();
+        let start = Instant::now();
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()),
+            start,
+        );
+        for _ in 0..3 {
+            let modify = Event::new(EventKind::Modify(ModifyKind::Any)).add_path(file.clone());
+            daemon.fs_debouncer.push(modify, start);
+        }
+
+        daemon.flush_fs_events(start + Duration::from_millis(999));
+        assert!(!daemon.has_icon(&file), "Events are held for the whole window");
+
+        daemon.flush_fs_events(start + Duration::from_millis(1000));
+        assert!(daemon.has_icon(&file));
+        assert!(daemon.fs_debouncer.is_empty());
+    }
+
+    #[test]
+    fn test_debounced_create_adds_icon_after_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let file = desktop_path.join("report.pdf");
+        fs::write(&file, "x").unwrap();
+        let start = Instant::now();
+        daemon.fs_debouncer.push(
+            Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()),
+            start,
+        );
+
+        daemon.flush_fs_events(start + Duration::from_millis(daemon.config.fs_coalesce_ms));
+        assert!(daemon.has_icon(&file));
+    }
+
+    #[test]
+    fn test_rapid_add_remove_leaves_no_animation_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let file = desktop_path.join("flicker.txt");
+        fs::write(&file, "x").unwrap();
+        for _ in 0..3 {
+            daemon
+                .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()))
+                .unwrap();
+            assert!(daemon.appearing.contains_key(&file), "New icons animate in");
+            daemon
+                .handle_fs_event(Event::new(EventKind::Remove(RemoveKind::File)).add_path(file.clone()))
+                .unwrap();
+        }
+
+        assert!(!daemon.has_icon(&file));
+        assert!(daemon.appearing.is_empty());
+        assert!(daemon.disappearing.is_empty(), "Nothing to fade without a surface");
+    }
+
+    #[test]
+    fn test_tick_animations_expires_finished_animations() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+
+        let file = desktop_path.join("new.txt");
+        fs::write(&file, "x").unwrap();
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()))
+            .unwrap();
+        daemon.disappearing.push(FadeOut {
+            surface_id: 7,
+            frame: tiny_skia::Pixmap::new(4, 4).unwrap(),
+            started: Instant::now(),
+        });
+
+        let now = Instant::now();
+        daemon.needs_render = false;
+        daemon.tick_animations(now);
+        assert!(daemon.needs_render, "Running animations keep rendering");
+        assert_eq!(daemon.appearing.len(), 1);
+        assert_eq!(daemon.disappearing.len(), 1);
+
+        let animations = &daemon.config.animations;
+        let done = now + Duration::from_millis(animations.appear_ms.max(animations.disappear_ms));
+        daemon.needs_render = false;
+        daemon.tick_animations(done);
+        assert!(daemon.needs_render, "The final frame is still drawn");
+        assert!(daemon.appearing.is_empty());
+        assert!(daemon.disappearing.is_empty());
+
+        daemon.needs_render = false;
+        daemon.tick_animations(done);
+        assert!(!daemon.needs_render, "Idle once everything has finished");
+    }
+
+    #[test]
+    fn test_disabled_animations_add_icons_statically() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.animations.enabled = false;
+
+        let file = desktop_path.join("still.txt");
+        fs::write(&file, "x").unwrap();
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()))
+            .unwrap();
+
+        assert!(daemon.has_icon(&file));
+        assert!(daemon.appearing.is_empty());
+    }
+
+    #[test]
+    fn test_reduce_motion_turns_off_animations() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.reduce_motion = true;
+        assert!(daemon.config.animations.enabled);
+
+        let file = desktop_path.join("still.txt");
+        fs::write(&file, "x").unwrap();
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()))
+            .unwrap();
+        assert!(daemon.has_icon(&file));
+        assert!(daemon.appearing.is_empty(), "Icons appear at once");
+        assert_eq!(daemon.hover_zoom_duration(), Duration::ZERO, "Hover zoom snaps");
+    }
+
+    #[test]
+    fn test_image_icons_show_their_thumbnail() {
+        let temp_dir = TempDir::new().unwrap();
+        let photo = temp_dir.path().join("photo.png");
+        image::RgbaImage::from_pixel(32, 16, image::Rgba([255, 0, 0, 255])).save(&photo).unwrap();
+        let corrupt = temp_dir.path().join("broken.png");
+        fs::write(&corrupt, "not a png").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.thumbnailer = Some(Thumbnailer::new(None));
+        daemon.add_icon(&photo).unwrap();
+        daemon.add_icon(&corrupt).unwrap();
+        assert!(daemon.thumbnailer.as_ref().unwrap().is_busy());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while daemon.thumbnailer.as_ref().unwrap().is_busy() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            daemon.collect_thumbnails();
+        }
+        assert!(daemon.icons[&photo].thumbnail().is_some());
+        // A corrupt file keeps the generic glyph
+        assert!(daemon.icons[&corrupt].thumbnail().is_none());
+
+        let size = daemon.config.icon_size;
+        let (pixels, width, _) = daemon.render_icon_bitmap(&photo).unwrap();
+        let center = ((size / 2 * width + width / 2) * 4) as usize;
+        assert_eq!(&pixels[center..center + 4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_crashed_widget_keeps_its_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        fs::create_dir(&scripts).unwrap();
+        // Every widget dies as soon as it starts
+        for name in [IPC_HANDLER_SCRIPT, "file.lua", "document.lua"] {
+            fs::write(scripts.join(name), "os.exit(1)\n").unwrap();
+        }
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, "notes").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.script_dirs = vec![scripts];
+
+        daemon.add_icon(&file).unwrap();
+        assert!(daemon.icons[&file].widget_failed());
+
+        for _ in 0..3 {
+            daemon.update_icons();
+        }
+        assert!(daemon.icons.contains_key(&file), "A crashed widget must not remove its icon");
+        assert!(daemon.icons[&file].widget_failed());
+        assert!(daemon.render_icon_bitmap(&file).is_some());
+    }
+
+    #[test]
+    fn test_render_cache_serves_first_frame() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        let cache_dir = temp_dir.path().join("cache");
+        fs::create_dir(&desktop_path).unwrap();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let mut config = Config { render_cache_dir: Some(cache_dir.clone()), ..test_config() };
+        config.animations.enabled = false;
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(config.clone(), desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1, "The first frame is cached");
+
+        // On the next start, the cached frame is shown without rendering
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+        let surface = backend.surface(surface_id).unwrap();
+        let key = daemon.render_cache_key(&daemon.icons()[&file], surface.width, surface.height).unwrap();
+        let mut cached = tiny_skia::Pixmap::new(surface.width, surface.height).unwrap();
+        cached.data_mut().fill(9);
+        daemon.render_cache.as_ref().unwrap().put(&key, &cached).unwrap();
+
+        daemon.step(Instant::now(), true);
+        let pixels = backend.surface(surface_id).unwrap().pixels.unwrap();
+        assert!(pixels.iter().all(|&b| b == 9));
+    }
+
+    #[test]
+    fn test_rename_redraws_only_the_label() {
+        use crate::backend::HeadlessBackend;
+        use notify::event::RenameMode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let old = desktop_path.join("draft.txt");
+        let new = desktop_path.join("final.txt");
+        fs::write(&old, "x").unwrap();
+
+        let mut config = Config { symbolic_icons: true, ..test_config() };
+        config.animations.enabled = false;
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&old];
+
+        // Swap in a marker glyph, which re-rendering the glyph would replace
+        let icon = daemon.get_icon_mut(&old).unwrap();
+        let mut marker = icon.glyph_layer().expect("The glyph layer is cached").clone();
+        marker.data_mut().fill(9);
+        icon.set_glyph_layer(marker);
+
+        fs::rename(&old, &new).unwrap();
+        daemon.queue_fs_event(Event {
+            kind: EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            paths: vec![old.clone(), new.clone()],
+            attrs: Default::default(),
+        });
+        daemon.step(Instant::now(), true);
+
+        assert!(!daemon.has_icon(&old));
+        assert_eq!(daemon.get_icon(&new).unwrap().name(), "final.txt");
+        assert_eq!(daemon.path_to_surface[&new], surface_id, "The icon keeps its surface");
+        let pixels = backend.surface(surface_id).unwrap().pixels.unwrap();
+        assert!(pixels[..64 * 4].iter().all(|&b| b == 9), "Only the label is drawn again");
+    }
+
+    #[test]
+    fn test_rename_to_another_type_recreates_icon() {
+        use notify::event::RenameMode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let old = desktop_path.join("photo.txt");
+        let new = desktop_path.join("photo.png");
+        fs::write(&old, "x").unwrap();
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.add_icon(&old).unwrap();
+
+        fs::rename(&old, &new).unwrap();
+        assert!(!daemon.rename_icon(&old, &new));
+        assert!(daemon.has_icon(&old), "A refused rename leaves the icon alone");
+
+        for (mode, path) in [(RenameMode::From, &old), (RenameMode::To, &new)] {
+            let event = Event {
+                kind: EventKind::Modify(ModifyKind::Name(mode)),
+                paths: vec![path.clone()],
+                attrs: Default::default(),
+            };
+            daemon.handle_fs_event(event).unwrap();
+        }
+        assert!(!daemon.has_icon(&old));
+        assert_eq!(daemon.get_icon(&new).unwrap().icon_type(), IconType::Image);
+    }
+
+    #[test]
+    fn test_render_icon_bitmap_matches_surface() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::AnimationConfig;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let config = Config {
+            animations: AnimationConfig { enabled: false, ..AnimationConfig::default() },
+            ..test_config()
+        };
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface = backend.surface(daemon.path_to_surface[&file]).unwrap();
+
+        let (pixels, width, height) = daemon.render_icon_bitmap(&file).unwrap();
+        assert_eq!((width, height), (surface.width, surface.height));
+        assert_eq!(Some(pixels), surface.pixels);
+
+        assert!(daemon.render_icon_bitmap(&desktop_path.join("missing.txt")).is_none());
+    }
+
+    #[test]
+    fn test_icons_render_at_the_surface_scale() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::AnimationConfig;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let config = Config {
+            animations: AnimationConfig { enabled: false, ..AnimationConfig::default() },
+            ..test_config()
+        };
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+        assert_eq!(backend.surface(surface_id).unwrap().buffer_scale, 1);
+
+        // Moving to a 2x output redraws the icon at twice the size
+        backend.set_surface_scale(surface_id, 2);
+        daemon.step(Instant::now(), true);
+        let surface = backend.surface(surface_id).unwrap();
+        assert_eq!(surface.buffer_scale, 2);
+        let pixels = surface.pixels.clone().unwrap();
+        assert_eq!(pixels.len(), (surface.width * 2 * surface.height * 2 * 4) as usize);
+
+        let (bitmap, width, height) = daemon.render_icon_bitmap(&file).unwrap();
+        assert_eq!((width, height), (surface.width * 2, surface.height * 2));
+        assert_eq!(Some(bitmap), surface.pixels);
+    }
+
+    #[test]
+    fn test_render_icon_bitmap_without_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.add_icon(&file).unwrap();
+
+        let (pixels, width, height) = daemon.render_icon_bitmap(&file).unwrap();
+        let icon_size = daemon.config.icon_size;
+        assert_eq!((width, height), (icon_size, icon_size + daemon.label_height()));
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        assert!(pixels.chunks_exact(4).any(|pixel| pixel[3] > 0), "The icon should draw something");
+    }
+
+    #[test]
+    fn test_icons_unmap_while_desktop_is_covered() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { hide_when_covered: true, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+
+        backend.set_desktop_covered(true);
+        daemon.step(Instant::now(), true);
+        let surface = backend.surface(surface_id).unwrap();
+        assert!(!surface.mapped && surface.pixels.is_none());
+
+        backend.set_desktop_covered(false);
+        daemon.step(Instant::now(), true);
+        daemon.step(Instant::now(), true);
+        let surface = backend.surface(surface_id).unwrap();
+        assert!(surface.mapped);
+        assert!(surface.pixels.is_some(), "The icon should be drawn again once exposed");
+
+        // Without the option a covering window changes nothing
+        daemon.config.hide_when_covered = false;
+        backend.set_desktop_covered(true);
+        daemon.step(Instant::now(), true);
+        assert!(backend.surface(surface_id).unwrap().mapped);
+    }
+
+    /// Daemon showing one icon on a headless backend, with `hover_zoom` set
+    fn hover_zoom_daemon(
+        desktop_path: &Path,
+        zoom: f32,
+        animate: bool,
+    ) -> (IconDaemon, crate::backend::HeadlessBackend, SurfaceId) {
+        use crate::backend::HeadlessBackend;
+        use crate::config::AnimationConfig;
+
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let config = Config {
+            hover_zoom: zoom,
+            animations: AnimationConfig { enabled: animate, ..AnimationConfig::default() },
+            ..test_config()
+        };
+        let backend = HeadlessBackend::new(800, 600);
+        let desktop_path = desktop_path.to_path_buf();
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+        (daemon, backend, surface_id)
+    }
+
+    #[test]
+    fn test_hover_zoom_spotlight_covers_hovered_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, backend, icon_surface) = hover_zoom_daemon(temp_dir.path(), 2.0, false);
+        let icon = backend.surface(icon_surface).unwrap();
+
+        backend.push_input(InputEvent::PointerEnter { surface_id: icon_surface, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        let spot_surface = daemon.spotlight.as_ref().unwrap().surface;
+        let spot = backend.surface(spot_surface).unwrap();
+        assert!(spot.overlay, "The zoomed icon should be raised above its neighbours");
+        assert_eq!((spot.width, spot.height), (icon.width * 2, icon.height * 2));
+        assert_eq!(spot.x + spot.width as i32 / 2, icon.x + icon.width as i32 / 2);
+        assert_eq!(spot.y + spot.height as i32 / 2, icon.y + icon.height as i32 / 2);
+        assert!(spot.pixels.is_some());
+
+        backend.push_input(InputEvent::PointerLeave { surface_id: icon_surface });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.spotlight.is_none());
+        assert!(backend.surface(spot_surface).is_none(), "Leaving should restore the icon");
+    }
+
+    #[test]
+    fn test_hover_zoom_off_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, backend, icon_surface) = hover_zoom_daemon(temp_dir.path(), 1.0, false);
+
+        backend.push_input(InputEvent::PointerEnter { surface_id: icon_surface, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.spotlight.is_none());
+        assert_eq!(backend.surface_ids().len(), 1);
+    }
+
+    #[test]
+    fn test_hover_zoom_animates_in_and_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, backend, icon_surface) = hover_zoom_daemon(temp_dir.path(), 2.0, true);
+
+        backend.push_input(InputEvent::PointerEnter { surface_id: icon_surface, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.spotlight.as_ref().unwrap().rendered_zoom < 2.0, "Zoom should start small");
+
+        daemon.spotlight.as_mut().unwrap().started -= HOVER_ZOOM_DURATION;
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.spotlight.as_ref().unwrap().rendered_zoom, 2.0);
+
+        // The spotlight shrinks back before it goes away
+        backend.push_input(InputEvent::PointerLeave { surface_id: icon_surface });
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.spotlight.as_ref().unwrap().to, 1.0);
+
+        daemon.spotlight.as_mut().unwrap().started -= HOVER_ZOOM_DURATION;
+        daemon.step(Instant::now(), true);
+        daemon.step(Instant::now(), true);
+        assert!(daemon.spotlight.is_none());
+        assert_eq!(backend.surface_ids().len(), 1);
+    }
+
+    #[test]
+    fn test_hovered_truncated_label_expands() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let long = desktop_path.join("a-rather-long-file-name.txt");
+        let short = desktop_path.join("notes.txt");
+        fs::write(&long, "x").unwrap();
+        fs::write(&short, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { expand_labels_on_hover: true, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let long_surface = daemon.path_to_surface[&long];
+        let icon = backend.surface(long_surface).unwrap();
+
+        backend.push_input(InputEvent::PointerEnter { surface_id: long_surface, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        let label = backend.surface(daemon.expanded_label.as_ref().unwrap().surface).unwrap();
+        assert!(label.overlay);
+        assert_eq!((label.x, label.y), (icon.x, icon.y + daemon.config.icon_size as i32 + 2));
+        assert!(label.height > 18, "The full name should take several lines");
+        assert!(label.pixels.is_some());
+
+        backend.push_input(InputEvent::PointerLeave { surface_id: long_surface });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.expanded_label.is_none());
+        assert_eq!(backend.surface_ids().len(), 2);
+
+        // Names that fit are never expanded
+        let short_surface = daemon.path_to_surface[&short];
+        backend.push_input(InputEvent::PointerEnter { surface_id: short_surface, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.expanded_label.is_none());
+    }
+
+    #[test]
+    fn test_expanded_label_stays_on_screen() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let long = desktop_path.join("a-rather-long-file-name.txt");
+        fs::write(&long, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { expand_labels_on_hover: true, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&long];
+
+        // An icon in the bottom-right corner gets its label shifted up and left
+        let mut backend_handle = backend.clone();
+        backend_handle.set_surface_position(surface_id, 790, 560);
+        backend.push_input(InputEvent::PointerEnter { surface_id, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        let label = backend.surface(daemon.expanded_label.as_ref().unwrap().surface).unwrap();
+        assert!(label.x + label.width as i32 <= 800);
+        assert!(label.y + label.height as i32 <= 600);
+    }
+
+    #[test]
+    fn test_tooltip_shows_after_dwell_and_goes_with_pointer() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("quarterly-report-final.txt");
+        let other = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        fs::write(&other, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { tooltip_details: true, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let now = Instant::now();
+        daemon.step(now, true);
+        let surface_id = daemon.path_to_surface[&file];
+        let icon = backend.surface(surface_id).unwrap();
+
+        backend.push_input(InputEvent::PointerEnter { surface_id, x: 1.0, y: 1.0 });
+        daemon.step(now, true);
+        assert!(daemon.tooltip.as_ref().unwrap().surface.is_none(), "Not before the dwell");
+        assert!(!daemon.is_idle(), "The dwell keeps the loop running");
+
+        let delay = Duration::from_millis(daemon.config.tooltip_delay_ms);
+        daemon.step(now + delay, true);
+        let (tooltip_surface, _, _) = daemon.tooltip.as_ref().unwrap().surface.unwrap();
+        let tooltip = backend.surface(tooltip_surface).unwrap();
+        assert!(tooltip.overlay, "The tooltip must not take the pointer from the icon");
+        assert_eq!(tooltip.x, icon.x + daemon.config.icon_size as i32 + TOOLTIP_GAP);
+        assert!(tooltip.pixels.is_some());
+
+        // Moving to another icon starts over there
+        let other_surface = daemon.path_to_surface[&other];
+        backend.push_input(InputEvent::PointerLeave { surface_id });
+        backend.push_input(InputEvent::PointerEnter { surface_id: other_surface, x: 1.0, y: 1.0 });
+        daemon.step(now + delay, true);
+        assert!(backend.surface(tooltip_surface).is_none());
+        assert_eq!(daemon.tooltip.as_ref().unwrap().path, other);
+
+        // and the tooltip goes with its file
+        daemon.step(now + delay * 2, true);
+        assert!(daemon.tooltip.as_ref().unwrap().surface.is_some());
+        daemon.remove_icon(&other);
+        assert!(daemon.tooltip.is_none());
+        assert_eq!(backend.surface_ids().len(), 1);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(30)), "just now");
+        assert_eq!(format_age(Duration::from_secs(60)), "1 minute ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3 hours ago");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2 days ago");
+    }
+
+    #[test]
+    fn test_debug_grid_toggles_overlay_matching_layout() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert!(daemon.debug_grid.is_none(), "Off by default");
+
+        let reply = daemon.handle_control(ControlCommand::DebugGrid { enabled: None });
+        assert_eq!(reply.data.unwrap()["enabled"], true);
+        daemon.step(Instant::now(), true);
+        let grid = backend.surface(daemon.debug_grid.unwrap()).unwrap();
+        assert!(grid.overlay, "The grid must not take pointer input");
+        assert_eq!((grid.x, grid.y, grid.width, grid.height), (0, 0, 800, 600));
+        assert!(grid.pixels.is_some());
+
+        // The icon sits in the first cell the grid draws
+        let (columns, rows) = daemon.grid_dimensions();
+        let cells = daemon.grid_cells();
+        assert_eq!(cells.len() as u32, columns * rows);
+        let icon = backend.surface(daemon.path_to_surface[&file]).unwrap();
+        assert_eq!((icon.x, icon.y), (cells[0].x() as i32, cells[0].y() as i32));
+
+        daemon.handle_control(ControlCommand::DebugGrid { enabled: None });
+        daemon.step(Instant::now(), true);
+        assert!(daemon.debug_grid.is_none());
+        assert_eq!(backend.surface_ids().len(), 1);
+    }
+
+    /// Positions of every icon surface on the headless backend
+    fn surface_positions(daemon: &IconDaemon, backend: &HeadlessBackend) -> HashSet<(i32, i32)> {
+        daemon.path_to_surface.values().map(|&id| backend.surface_position(id).unwrap()).collect()
+    }
+
+    /// Origins of the first `n` cells unplaced icons flow into
+    fn flowed_cells(daemon: &IconDaemon, n: u32) -> HashSet<(i32, i32)> {
+        let cells = daemon.grid_cells();
+        let keep_clear = daemon.keep_clear_slots();
+        (0..n)
+            .map(|i| &cells[daemon.nth_slot(i, &keep_clear) as usize])
+            .map(|cell| (cell.x() as i32, cell.y() as i32))
+            .collect()
+    }
+
+    #[test]
+    fn test_reload_applies_config_file_and_keeps_it_on_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = TempDir::new().unwrap();
+        fs::write(scripts.path().join("file.lua"), "").unwrap();
+        let config_file = scripts.path().join("config.toml");
+        let desktop_path = temp_dir.path().to_path_buf();
+        fs::write(desktop_path.join("notes.txt"), "x").unwrap();
+        fs::write(desktop_path.join("todo.txt"), "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.enable_reload(Some(config_file.clone()));
+        daemon.config.debug_grid = true;
+        daemon.step(Instant::now(), true);
+        let before = surface_positions(&daemon, &backend);
+
+        fs::write(
+            &config_file,
+            format!("grid_spacing = 31\nscript_dirs = [{:?}]\n", scripts.path()),
+        )
+        .unwrap();
+        daemon.reload().unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.config.grid_spacing, 31);
+        assert!(daemon.config.debug_grid, "Command-line overrides carry over");
+        // Widgets were looked up again in the new directories
+        assert!(daemon.warned_missing_handler);
+        // And the icons moved to the wider grid
+        let after = surface_positions(&daemon, &backend);
+        assert_ne!(after, before);
+        assert_eq!(after, flowed_cells(&daemon, 2));
+
+        fs::write(&config_file, "grid_spacing = [").unwrap();
+        let err = daemon.reload().unwrap_err();
+        assert!(format!("{:#}", err).contains("keeping the current configuration"));
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.config.grid_spacing, 31);
+        assert_eq!(daemon.config.script_dirs, vec![scripts.path().to_path_buf()]);
+        assert_eq!(surface_positions(&daemon, &backend), after);
+    }
+
+    #[test]
+    fn test_saving_the_config_file_reloads_it() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = TempDir::new().unwrap();
+        let config_file = config_dir.path().join("config.toml");
+        let desktop_path = temp_dir.path().to_path_buf();
+        fs::write(desktop_path.join("notes.txt"), "x").unwrap();
+        fs::write(desktop_path.join("todo.txt"), "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.enable_reload(Some(config_file.clone()));
+        daemon.step(Instant::now(), true);
+        let before = surface_positions(&daemon, &backend);
+        let saved = |kind| Event::new(kind).add_path(config_file.clone());
+
+        fs::write(&config_file, "grid_spacing = 31\n").unwrap();
+        daemon.handle_fs_event(saved(EventKind::Modify(ModifyKind::Any))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.config.grid_spacing, 31);
+        let after = surface_positions(&daemon, &backend);
+        assert_ne!(after, before, "The icons follow the new spacing");
+        assert_eq!(after, flowed_cells(&daemon, 2));
+
+        // A malformed file is ignored, and so is a removed one
+        fs::write(&config_file, "grid_spacing = [").unwrap();
+        daemon.handle_fs_event(saved(EventKind::Modify(ModifyKind::Any))).unwrap();
+        assert_eq!(daemon.config.grid_spacing, 31);
+        fs::remove_file(&config_file).unwrap();
+        daemon.handle_fs_event(saved(EventKind::Remove(RemoveKind::File))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.config.grid_spacing, 31);
+        assert_eq!(surface_positions(&daemon, &backend), after);
+
+        // Other files beside it neither become icons nor reload anything
+        let swap = config_dir.path().join(".config.toml.swp");
+        fs::write(&swap, "x").unwrap();
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(swap.clone()))
+            .unwrap();
+        assert!(!daemon.icons.contains_key(&swap));
+        assert_eq!(daemon.config.grid_spacing, 31);
+    }
+
+    #[test]
+    fn test_unreadable_entries_are_logged_and_skipped() {
+        use std::io::{Error, ErrorKind, Write};
+        use std::sync::{Arc, Mutex};
+
+        /// Log output of the test's subscriber
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        // An entry failing on its own can't be provoked portably, so add one
+        let failing = Error::new(ErrorKind::PermissionDenied, "entry went away");
+        let entries = fs::read_dir(temp_dir.path()).unwrap().chain(std::iter::once(Err(failing)));
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let paths =
+            tracing::subscriber::with_default(subscriber, || listed_paths(temp_dir.path(), entries));
+
+        assert_eq!(paths, vec![file], "The readable entries still show up");
+        let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("Skipping unreadable entry"), "Not logged: {}", log);
+        assert!(log.contains("entry went away"));
+    }
+
+    #[test]
+    fn test_missing_ipc_handler_warned_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = TempDir::new().unwrap();
+        fs::write(scripts.path().join("file.lua"), "").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.script_dirs = vec![scripts.path().to_path_buf()];
+
+        for name in ["a.txt", "b.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "x").unwrap();
+            daemon.add_icon(&file).unwrap();
+            assert!(daemon.warned_missing_handler);
+        }
+        assert!(daemon.icons.values().all(|icon| !icon.has_lua_process()));
+
+        let status = daemon.lua_status();
+        assert!(status["handler"].is_null());
+        assert_eq!(status["script_dirs"][0], scripts.path().to_str().unwrap());
+    }
+
+    #[test]
+    fn test_lua_status_reports_resolved_scripts() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = TempDir::new().unwrap();
+        let handler = scripts.path().join("ipc_handler.lua");
+        let folder_widget = scripts.path().join("widgets").join("folder.lua");
+        fs::create_dir(scripts.path().join("widgets")).unwrap();
+        fs::write(&handler, "").unwrap();
+        fs::write(&folder_widget, "").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.script_dirs = vec![PathBuf::from("/nonexistent"), scripts.path().to_path_buf()];
+
+        let reply = daemon.handle_control(ControlCommand::LuaStatus);
+        let status = reply.data.unwrap();
+        assert_eq!(status["handler"], handler.to_str().unwrap());
+        assert_eq!(status["types"]["folder"]["script"], "folder.lua");
+        assert_eq!(status["types"]["folder"]["path"], folder_widget.to_str().unwrap());
+        assert!(status["types"]["image"]["path"].is_null());
+        assert!(status["types"].get("fifo").is_none(), "Special files never get widgets");
+        assert_eq!(status["icons_with_widgets"], 0);
+    }
+
+    #[test]
+    fn test_icons_without_widget_scripts_report_why() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, "notes").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.script_dirs = vec![scripts.path().to_path_buf()];
+
+        daemon.add_icon(&file).unwrap();
+        assert_eq!(daemon.icon_statuses(), vec![(file.clone(), WidgetStatus::NoHandler)]);
+
+        // With the handler in place the widget script is still missing
+        fs::write(scripts.path().join(IPC_HANDLER_SCRIPT), "").unwrap();
+        daemon.restart_widgets();
+        assert_eq!(daemon.icons[&file].widget_status(), WidgetStatus::NoWidget);
+        let status = daemon.handle_control(ControlCommand::LuaStatus).data.unwrap();
+        assert_eq!(status["problems"][0]["path"], file.to_str().unwrap());
+        assert_eq!(status["problems"][0]["status"], "no_widget");
+
+        // The badge is drawn unless turned off
+        let (plain, _, _) = daemon.render_icon_bitmap(&file).unwrap();
+        daemon.config.widget_status_badges = true;
+        let (badged, _, _) = daemon.render_icon_bitmap(&file).unwrap();
+        assert_ne!(plain, badged);
+    }
+
+    #[test]
+    fn test_directory_file_change_refreshes_folder_icon() {
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Music");
+        fs::create_dir(&folder).unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.add_icon(&folder).unwrap();
+        assert_eq!(daemon.icons[&folder].custom_icon(), None);
+
+        let directory = folder.join(folder_icon::DIRECTORY_FILE);
+        fs::write(&directory, "[Desktop Entry]\nIcon=folder-music\n").unwrap();
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![directory.clone()],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(event).unwrap();
+        assert_eq!(
+            daemon.icons[&folder].custom_icon(),
+            Some(&FolderIcon::Named("folder-music".to_string()))
+        );
+        assert!(!daemon.icons.contains_key(&directory));
+
+        fs::remove_file(&directory).unwrap();
+        let event = Event {
+            kind: EventKind::Remove(RemoveKind::File),
+            paths: vec![directory],
+            attrs: Default::default(),
+        };
+        daemon.handle_fs_event(event).unwrap();
+        assert_eq!(daemon.icons[&folder].custom_icon(), None);
+    }
+
+    #[test]
+    fn test_create_desktop_dir_at_startup() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        let mut config = test_config();
+        config.create_desktop_dir = true;
+
+        let daemon = IconDaemon::with_backend(config, desktop_path.clone(), None).unwrap();
+        assert!(desktop_path.is_dir());
+        assert!(!daemon.awaiting_desktop_dir);
+    }
+
+    #[test]
+    fn test_desktop_dir_created_later_is_picked_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        let mut daemon = IconDaemon::with_backend(test_config(), desktop_path.clone(), None).unwrap();
+        assert!(daemon.awaiting_desktop_dir);
+        assert!(daemon.icons.is_empty());
+
+        // Files next to the desktop directory are not desktop icons
+        let sibling = temp_dir.path().join(".bashrc");
+        fs::write(&sibling, "x").unwrap();
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(sibling.clone());
+        daemon.handle_fs_event(event).unwrap();
+        assert!(daemon.icons.is_empty());
+
+        // The directory appears with a file already in it
+        fs::create_dir(&desktop_path).unwrap();
+        let early = desktop_path.join("early.txt");
+        fs::write(&early, "x").unwrap();
+        let event = Event::new(EventKind::Create(CreateKind::Folder)).add_path(desktop_path.clone());
+        daemon.handle_fs_event(event).unwrap();
+        assert!(!daemon.awaiting_desktop_dir);
+        assert!(daemon.icons.contains_key(&early));
+        assert!(!daemon.icons.contains_key(&desktop_path));
+        assert!(!daemon.icons.contains_key(&sibling));
+    }
+
+    #[test]
+    fn test_pause_holds_events_and_input_until_resume() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let kept = desktop_path.join("kept.txt");
+        fs::write(&kept, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path.clone(), Some(Box::new(backend.clone())))
+                .unwrap();
+        daemon.step(Instant::now(), true);
+
+        let reply = daemon.handle_control(ControlCommand::Pause);
+        assert!(reply.ok);
+        assert_eq!(daemon.health().reply().data.unwrap()["paused"], true);
+
+        let added = desktop_path.join("added.txt");
+        fs::write(&added, "x").unwrap();
+        daemon.queue_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(added.clone()));
+        let surface_id = daemon.path_to_surface[&kept];
+        backend.push_input(InputEvent::PointerEnter { surface_id, x: 5.0, y: 5.0 });
+        daemon.step(Instant::now() + Duration::from_secs(1), true);
+        assert!(!daemon.icons.contains_key(&added), "Events wait for resume");
+        assert!(!daemon.icons[&kept].is_hovered(), "Input is ignored while paused");
+
+        daemon.handle_control(ControlCommand::Resume);
+        assert!(!daemon.is_paused());
+        assert!(daemon.icons.contains_key(&added));
+    }
+
+    #[test]
+    fn test_refresh_list_and_quit_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let first = desktop_path.join("first.txt");
+        fs::write(&first, "x").unwrap();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.scan_desktop().unwrap();
+
+        // A file the watcher never reported turns up on refresh
+        let missed = desktop_path.join("missed.txt");
+        fs::write(&missed, "x").unwrap();
+        assert!(daemon.handle_control(ControlCommand::Refresh).ok);
+        assert!(daemon.icons.contains_key(&missed));
+
+        let listed = daemon.handle_control(ControlCommand::List).data.unwrap();
+        let entry = |path: &Path| {
+            listed.as_array().unwrap().iter().find(|entry| entry["path"] == path.to_str().unwrap()).cloned()
+        };
+        for path in [&first, &missed] {
+            let entry = entry(path).expect("Every icon is listed");
+            assert!(entry["col"].is_u64() && entry["row"].is_u64());
+        }
+
+        assert!(!daemon.should_exit());
+        assert!(daemon.handle_control(ControlCommand::Quit).ok);
+        assert!(daemon.should_exit());
+    }
+
+    #[test]
+    fn test_flash_runs_its_course_and_waits_for_missing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("report.pdf");
+        fs::write(&file, "x").unwrap();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.add_icon(&file).unwrap();
+        daemon.needs_render = false;
+
+        let reply = daemon.handle_control(ControlCommand::Flash { path: file.clone() });
+        assert_eq!(reply.data.unwrap()["queued"], false);
+        assert!(daemon.icons[&file].is_flashing());
+        assert!(!daemon.is_idle(), "Flashing keeps the frame loop running");
+
+        let later = Instant::now() + daemon.flash_duration();
+        daemon.tick_animations(later);
+        assert!(!daemon.icons[&file].is_flashing());
+        assert!(daemon.needs_render, "The icon is drawn once more without the flash");
+
+        // A download still being written flashes once it is renamed into place
+        let partial = desktop_path.join("sketch.crdownload");
+        let done = desktop_path.join("sketch");
+        fs::write(&partial, "x").unwrap();
+        daemon.add_icon(&partial).unwrap();
+        let reply = daemon.handle_control(ControlCommand::Flash { path: done.clone() });
+        assert_eq!(reply.data.unwrap()["queued"], true);
+        fs::rename(&partial, &done).unwrap();
+        assert!(daemon.rename_icon(&partial, &done));
+        assert!(daemon.icons[&done].is_flashing());
+        assert!(daemon.pending_flashes.is_empty());
+    }
+
+    #[test]
+    fn test_locked_session_suspends_icons_until_unlock() {
+        use crate::backend::HeadlessBackend;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct FakeLock(Rc<Cell<bool>>);
+
+        impl LockState for FakeLock {
+            fn name(&self) -> &'static str {
+                "fake"
+            }
+
+            fn is_locked(&self) -> Option<bool> {
+                Some(self.0.get())
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let kept = desktop_path.join("kept.txt");
+        fs::write(&kept, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path.clone(), Some(Box::new(backend.clone())))
+                .unwrap();
+        let locked = Rc::new(Cell::new(false));
+        daemon.session_lock = Some(Box::new(FakeLock(locked.clone())));
+        let mut now = Instant::now();
+        daemon.step(now, true);
+        let surface_id = daemon.path_to_surface[&kept];
+        let frames = backend.surface(surface_id).unwrap().frames;
+
+        locked.set(true);
+        now += LOCK_POLL_INTERVAL;
+        daemon.step(now, true);
+        assert!(daemon.locked);
+
+        let added = desktop_path.join("added.txt");
+        fs::write(&added, "x").unwrap();
+        daemon.queue_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(added.clone()));
+        daemon.needs_render = true;
+        now += Duration::from_secs(1);
+        daemon.step(now, true);
+        assert!(!daemon.icons.contains_key(&added), "Nothing is updated while locked");
+        assert_eq!(backend.surface(surface_id).unwrap().frames, frames, "Nothing is drawn either");
+
+        locked.set(false);
+        now += LOCK_POLL_INTERVAL;
+        daemon.step(now, true);
+        assert!(!daemon.locked);
+        assert!(daemon.icons.contains_key(&added), "Changes are caught up with on unlock");
+    }
+
+    #[test]
+    fn test_resume_rescans_after_dropped_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let gone = temp_dir.path().join("gone.txt");
+        fs::write(&gone, "x").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.config.paused_fs_events = PausedFsEvents::Drop;
+        daemon.scan_desktop().unwrap();
+
+        daemon.pause();
+        fs::remove_file(&gone).unwrap();
+        let added = temp_dir.path().join("added.txt");
+        fs::write(&added, "x").unwrap();
+        daemon.queue_fs_event(Event::new(EventKind::Remove(RemoveKind::File)).add_path(gone.clone()));
+        daemon.queue_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(added.clone()));
+        assert!(daemon.fs_debouncer.is_empty(), "Dropped, not held");
+
+        daemon.resume().unwrap();
+        assert!(!daemon.icons.contains_key(&gone));
+        assert!(daemon.icons.contains_key(&added));
+    }
+
+    /// Provider reporting a fixed emblem for one file
+    struct FixedEmblem(PathBuf, Emblem);
+
+    impl EmblemProvider for FixedEmblem {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn refresh(&mut self, _desktop_dir: &Path, _paths: &[PathBuf]) {}
+
+        fn emblem(&self, path: &Path) -> Option<Emblem> {
+            (path == self.0).then_some(self.1)
+        }
+    }
+
+    #[test]
+    fn test_emblem_provider_draws_on_icon() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        let other = desktop_path.join("other.txt");
+        fs::write(&file, "x").unwrap();
+        fs::write(&other, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let plain = backend.surface(daemon.path_to_surface[&file]).unwrap().pixels;
+
+        daemon.add_emblem_provider(Box::new(FixedEmblem(file.clone(), Emblem::Modified)));
+        daemon.update_icons();
+        assert_eq!(daemon.emblem(&file), Some(Emblem::Modified));
+        assert_eq!(daemon.emblem(&other), None, "Files without status get no emblem");
+
+        daemon.step(Instant::now(), true);
+        let badged = backend.surface(daemon.path_to_surface[&file]).unwrap().pixels;
+        assert_ne!(plain, badged);
+    }
+
+    #[test]
+    fn test_emblems_refresh_after_fs_event() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("new.txt");
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        daemon.add_emblem_provider(Box::new(FixedEmblem(file.clone(), Emblem::Untracked)));
+        daemon.update_icons();
+        assert!(!daemon.emblems_stale);
+
+        fs::write(&file, "x").unwrap();
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()))
+            .unwrap();
+        assert!(daemon.emblems_stale);
+        daemon.update_icons();
+        assert_eq!(daemon.emblem(&file), Some(Emblem::Untracked));
+    }
+
+    #[test]
+    fn test_icons_move_to_new_primary_output() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let config = Config { primary_output: Some("DP-1".to_string()), ..test_config() };
+        let backend = HeadlessBackend::new(800, 600).with_output_name("eDP-1");
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        assert_eq!(backend.primary_output().as_deref(), Some("DP-1"));
+        daemon.step(Instant::now(), true);
+        let old_surface = daemon.path_to_surface[&file];
+
+        // The named output was plugged in and the backend switched to it
+        backend.set_output_name("DP-1");
+        daemon.step(Instant::now(), true);
+        let new_surface = daemon.path_to_surface[&file];
+        assert_ne!(old_surface, new_surface, "Surfaces cannot move between outputs");
+        assert!(backend.surface(old_surface).is_none());
+        assert!(backend.surface(new_surface).unwrap().pixels.is_some());
+        assert_eq!(backend.surface_ids().len(), 1);
+    }
+
+    #[test]
+    fn test_overflowing_icons_span_other_outputs() {
+        use crate::backend::HeadlessBackend;
+        use crate::icons::GRID_MARGIN;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let paths: Vec<PathBuf> =
+            ["a.txt", "b.txt", "c.txt", "d.txt"].iter().map(|n| desktop_path.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
+
+        // One column of two cells fits on the primary output
+        let config = Config { span_outputs: true, ..test_config() };
+        let backend = HeadlessBackend::new(200, 300).with_output_name("DP-1");
+        backend.set_other_outputs(vec![("HDMI-A-1".to_string(), 800, 600)]);
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.grid_dimensions(), (1, 2));
+
+        let outputs = |daemon: &IconDaemon| -> Vec<Option<String>> {
+            let mut outputs: Vec<Option<String>> = paths
+                .iter()
+                .map(|path| backend.surface(daemon.path_to_surface[path]).unwrap().output)
+                .collect();
+            outputs.sort();
+            outputs
+        };
+        let hdmi = Some("HDMI-A-1".to_string());
+        assert_eq!(outputs(&daemon), vec![None, None, hdmi.clone(), hdmi.clone()]);
+        let mut positions: Vec<(i32, i32)> = daemon
+            .icon_outputs
+            .keys()
+            .map(|path| backend.surface_position(daemon.path_to_surface[path]).unwrap())
+            .collect();
+        positions.sort();
+        let margin = GRID_MARGIN as i32;
+        let cell_width = (daemon.config.icon_size + daemon.config.grid_spacing) as i32;
+        assert_eq!(positions, vec![(margin, margin), (margin + cell_width, margin)]);
+
+        // Unplugged: its icons come back to the primary output
+        backend.set_other_outputs(Vec::new());
+        daemon.step(Instant::now(), true);
+        assert_eq!(outputs(&daemon), vec![None; 4]);
+        assert!(daemon.icon_outputs.is_empty());
+        assert_eq!(backend.surface_ids().len(), 4);
+    }
+
+    #[test]
+    fn test_icons_pinned_to_an_output_follow_it() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let (a, b) = (desktop_path.join("a.txt"), desktop_path.join("b.txt"));
+        for path in [&a, &b] {
+            fs::write(path, "x").unwrap();
+        }
+
+        let backend = HeadlessBackend::new(800, 600).with_output_name("DP-1");
+        backend.set_other_outputs(vec![("HDMI-A-1".to_string(), 800, 600)]);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let output =
+            |daemon: &IconDaemon, path: &Path| backend.surface(daemon.path_to_surface[path]).unwrap().output;
+        assert_eq!(output(&daemon, &a), None, "Without span_outputs icons stay on the primary output");
+
+        let reply = daemon.handle_control(ControlCommand::PinOutput {
+            path: a.clone(),
+            output: Some("HDMI-A-1".to_string()),
+        });
+        assert!(reply.ok, "{:?}", reply.error);
+        let hdmi = Some("HDMI-A-1".to_string());
+        assert_eq!(output(&daemon, &a), hdmi);
+        assert_eq!(output(&daemon, &b), None);
+        assert_eq!(daemon.icons[&b].layout_index(), 0, "The pinned icon leaves its cell free");
+        let listed = daemon.handle_control(ControlCommand::List).data.unwrap();
+        assert_eq!(listed[0]["output"], "HDMI-A-1");
+        assert_eq!(listed[1]["output"], "DP-1");
+        assert_eq!(daemon.export_layout().outputs["a.txt"], "HDMI-A-1");
+
+        // Unplugged: the icon waits on the primary output
+        backend.set_other_outputs(Vec::new());
+        daemon.step(Instant::now(), true);
+        assert_eq!(output(&daemon, &a), None);
+        assert_eq!(backend.surface_ids().len(), 2);
+
+        // and goes back once it is plugged in again, without a second surface
+        backend.set_other_outputs(vec![("HDMI-A-1".to_string(), 800, 600)]);
+        daemon.step(Instant::now(), true);
+        assert_eq!(output(&daemon, &a), hdmi);
+        assert_eq!(backend.surface_ids().len(), 2);
+        assert_eq!(daemon.placements.output(&a), Some("HDMI-A-1"));
+
+        assert!(daemon.handle_control(ControlCommand::PinOutput { path: a.clone(), output: None }).ok);
+        assert_eq!(output(&daemon, &a), None);
+    }
+
+    #[test]
+    fn test_copy_path_and_text_to_clipboard() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let notes = desktop_path.join("notes.txt");
+        let todo = desktop_path.join("todo.txt");
+        fs::write(&notes, "buy milk\n").unwrap();
+        fs::write(&todo, "call home\n").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        daemon.perform_action(&notes, ContextAction::CopyAsText).unwrap();
+        assert_eq!(backend.clipboard().as_deref(), Some("buy milk\n"));
+        daemon.perform_action(&notes, ContextAction::CopyPath).unwrap();
+        assert_eq!(backend.clipboard(), Some(notes.display().to_string()));
+
+        // Ctrl+C copies every selected path, Ctrl+Shift+C needs a single file
+        daemon.get_icon_mut(&notes).unwrap().set_selected(true);
+        daemon.get_icon_mut(&todo).unwrap().set_selected(true);
+        let ctrl = KeyModifiers { ctrl: true, shift: false, alt: false };
+        backend.push_input(InputEvent::Key { keysym: KEY_C, modifiers: ctrl });
+        daemon.step(Instant::now(), true);
+        let both = format!("{}\n{}", notes.display(), todo.display());
+        assert_eq!(backend.clipboard(), Some(both.clone()));
+
+        let ctrl_shift = KeyModifiers { shift: true, ..ctrl };
+        backend.push_input(InputEvent::Key { keysym: KEY_SHIFT_C, modifiers: ctrl_shift });
+        daemon.step(Instant::now(), true);
+        assert_eq!(backend.clipboard(), Some(both), "Two files are not copied as text");
+
+        daemon.get_icon_mut(&notes).unwrap().set_selected(false);
+        backend.push_input(InputEvent::Key { keysym: KEY_SHIFT_C, modifiers: ctrl_shift });
+        daemon.step(Instant::now(), true);
+        assert_eq!(backend.clipboard().as_deref(), Some("call home\n"));
+    }
+
+    #[test]
+    fn test_surface_output_binding_change_recreates_surfaces() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::SurfaceOutputBinding;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        assert_eq!(backend.surface_output_binding(), SurfaceOutputBinding::Explicit);
+        daemon.step(Instant::now(), true);
+        let old_surface = daemon.path_to_surface[&file];
+
+        let config = Config { surface_output_binding: SurfaceOutputBinding::None, ..test_config() };
+        daemon.apply_config(config).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(backend.surface_output_binding(), SurfaceOutputBinding::None);
+        let new_surface = daemon.path_to_surface[&file];
+        assert_ne!(old_surface, new_surface);
+        assert!(backend.surface(old_surface).is_none());
+        assert!(backend.surface(new_surface).unwrap().pixels.is_some());
+    }
+
+    #[test]
+    fn test_idle_dim_opacity_fades_after_delay() {
+        let dim = IdleDimConfig { enabled: true, opacity: 0.5, delay_ms: 1000 };
+        let fade = Duration::from_millis(400);
+
+        assert_eq!(idle_dim_opacity(&dim, Duration::from_millis(999), fade), 1.0);
+        let halfway = idle_dim_opacity(&dim, Duration::from_millis(1200), fade);
+        assert!(halfway > 0.5 && halfway < 1.0);
+        assert_eq!(idle_dim_opacity(&dim, Duration::from_secs(5), fade), 0.5);
+        assert_eq!(idle_dim_opacity(&dim, Duration::from_millis(1000), Duration::ZERO), 0.5);
+
+        let off = IdleDimConfig { enabled: false, ..dim };
+        assert_eq!(idle_dim_opacity(&off, Duration::from_secs(5), fade), 1.0);
+    }
+
+    #[test]
+    fn test_idle_dim_resets_on_input() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::AnimationConfig;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let config = Config {
+            idle_dim: IdleDimConfig { enabled: true, opacity: 0.5, delay_ms: 60_000 },
+            animations: AnimationConfig { enabled: false, ..AnimationConfig::default() },
+            ..test_config()
+        };
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+        let bright = backend.surface(surface_id).unwrap().pixels;
+        assert_eq!(daemon.rendered_idle_opacity, 1.0);
+
+        daemon.last_input = Instant::now() - Duration::from_secs(120);
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.rendered_idle_opacity, 0.5);
+        let dimmed = backend.surface(surface_id).unwrap().pixels;
+        assert_ne!(dimmed, bright, "Idle icons should be redrawn dimmed");
+
+        backend.push_input(InputEvent::PointerMotion { surface_id, x: 1.0, y: 1.0 });
+        daemon.step(Instant::now(), true);
+        assert_eq!(daemon.rendered_idle_opacity, 1.0);
+        assert_eq!(backend.surface(surface_id).unwrap().pixels, bright);
+    }
+
+    #[test]
+    fn test_logical_button_swap() {
+        const BTN_MIDDLE: u32 = 274;
+
+        assert_eq!(logical_button(BTN_LEFT, false), BTN_LEFT);
+        assert_eq!(logical_button(BTN_RIGHT, false), BTN_RIGHT);
+
+        assert_eq!(logical_button(BTN_LEFT, true), BTN_RIGHT);
+        assert_eq!(logical_button(BTN_RIGHT, true), BTN_LEFT);
+        assert_eq!(logical_button(BTN_MIDDLE, true), BTN_MIDDLE);
+    }
+
+    #[test]
+    fn test_long_press_fires_unless_moved_or_released() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let marker = temp_dir.path().join("opened");
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config {
+            long_press_ms: Some(500),
+            long_press_action: ButtonAction::Open,
+            open_commands: HashMap::from([(
+                "txt".to_string(),
+                format!("touch {}", marker.display()),
+            )]),
+            ..test_config()
+        };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let start = Instant::now();
+        daemon.step(start, true);
+        let surface_id = daemon.path_to_surface[&file];
+        let button = |pressed| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+        let motion = |x| InputEvent::PointerMotion { surface_id, x, y: 5.0 };
+        let ms = |ms| start + Duration::from_millis(ms);
+
+        // Released early: an ordinary click
+        backend.push_input(button(true));
+        daemon.step(ms(0), false);
+        backend.push_input(button(false));
+        daemon.step(ms(300), false);
+        daemon.step(ms(900), false);
+        assert!(daemon.icons()[&file].is_selected());
+
+        // Moved past the drag threshold: a drag
+        backend.push_input(button(true));
+        daemon.step(ms(1000), false);
+        backend.push_input(motion(5.0 + DRAG_THRESHOLD * 2.0));
+        daemon.step(ms(1600), false);
+        assert!(daemon.drag.is_some());
+        backend.push_input(button(false));
+        daemon.step(ms(1700), false);
+
+        // Wobbling within the threshold still counts as holding still
+        backend.push_input(button(true));
+        daemon.step(ms(2000), false);
+        backend.push_input(motion(6.0));
+        daemon.step(ms(2400), false);
+        assert!(daemon.pointer_press.is_some(), "Not held long enough yet");
+        daemon.step(ms(2500), false);
+        assert!(daemon.pointer_press.is_none(), "The long press uses up the press");
+        assert!(daemon.drag.is_none());
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !marker.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(marker.exists(), "The long press should open the file");
     }
 
-    /// Get an icon by path
-    #[allow(dead_code)]
-    pub fn get_icon(&self, path: &Path) -> Option<&DesktopIcon> {
-        self.icons.get(path)
+    fn press_at(surface_id: SurfaceId, button: u32, x: f64, y: f64) -> InputEvent {
+        InputEvent::PointerButton { surface_id, button, pressed: true, x, y, modifiers: KeyModifiers::default() }
     }
 
-    /// Get a mutable icon by path
-    #[allow(dead_code)]
-    pub fn get_icon_mut(&mut self, path: &Path) -> Option<&mut DesktopIcon> {
-        self.icons.get_mut(path)
+    /// Daemon with one text file's icon and its context menu open
+    fn daemon_with_context_menu(dir: &Path) -> (IconDaemon, HeadlessBackend, PathBuf, SurfaceId) {
+        let file = dir.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), dir.to_path_buf(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        backend.push_input(press_at(daemon.path_to_surface[&file], BTN_RIGHT, 10.0, 10.0));
+        daemon.step(Instant::now(), true);
+        let menu = daemon.context_menu.as_ref().expect("A right click opens the menu").surface;
+        (daemon, backend, file, menu)
     }
 
-    /// Run the main daemon loop using calloop
-    pub fn run(&mut self) -> Result<()> {
-        info!("Icon daemon running with calloop event loop");
+    #[test]
+    fn test_context_menu_runs_the_chosen_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, backend, file, menu) = daemon_with_context_menu(temp_dir.path());
+        let surface = backend.surface(menu).unwrap();
+        assert!(surface.pixels.is_some(), "The menu is drawn");
+        assert_eq!(surface.width, CONTEXT_MENU_WIDTH);
 
-        // Create the calloop event loop
-        let mut event_loop: EventLoop<DaemonState> = EventLoop::try_new()
-            .context("Failed to create calloop event loop")?;
-        let loop_handle = event_loop.handle();
+        let entries = daemon.context_menu.as_ref().unwrap().entries.clone();
+        let copy_path = entries
+            .iter()
+            .position(|entry| matches!(entry, MenuEntry::Action { action: ContextAction::CopyPath, .. }))
+            .unwrap();
+        let y = (0..surface.height).find(|&y| context_menu_hit(&entries, 20.0, y as f64) == Some(copy_path));
+        backend.push_input(InputEvent::PointerEnter { surface_id: menu, x: 20.0, y: y.unwrap() as f64 });
+        backend.push_input(press_at(menu, BTN_LEFT, 20.0, y.unwrap() as f64));
+        daemon.step(Instant::now(), true);
+
+        assert!(daemon.context_menu.is_none());
+        assert!(backend.surface(menu).is_none());
+        assert_eq!(backend.clipboard().as_deref(), Some(file.to_str().unwrap()));
+    }
 
-        // Create a channel for file watcher events
-        let (sender, channel): (Sender<notify::Result<Event>>, Channel<notify::Result<Event>>) =
-            calloop::channel::channel();
+    #[test]
+    fn test_context_menu_closes_on_outside_press_removal_and_leave() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut daemon, backend, file, menu) = daemon_with_context_menu(temp_dir.path());
+        let icon = daemon.path_to_surface[&file];
+
+        // Only one menu at a time
+        backend.push_input(press_at(icon, BTN_RIGHT, 10.0, 10.0));
+        daemon.step(Instant::now(), true);
+        assert!(backend.surface(menu).is_none(), "A new menu replaces the open one");
+
+        backend.push_input(press_at(icon, BTN_LEFT, 10.0, 10.0));
+        daemon.step(Instant::now(), true);
+        assert!(daemon.context_menu.is_none(), "A press elsewhere closes the menu");
+        assert!(daemon.icons()[&file].is_selected(), "and is still handled");
+
+        daemon.open_context_menu(&file, 10.0, 10.0);
+        daemon.remove_icon(&file);
+        assert!(daemon.context_menu.is_none(), "The menu goes with its icon");
+
+        daemon.add_icon(&file).unwrap();
+        daemon.open_context_menu(&file, 10.0, 10.0);
+        let menu = daemon.context_menu.as_ref().unwrap().surface;
+        let now = Instant::now();
+        backend.push_input(InputEvent::PointerEnter { surface_id: menu, x: 1.0, y: 1.0 });
+        backend.push_input(InputEvent::PointerLeave { surface_id: menu });
+        daemon.step(now, true);
+        assert!(!daemon.is_idle(), "The leave timeout keeps the loop running");
+        daemon.step(now + CONTEXT_MENU_LEAVE_TIMEOUT / 2, true);
+        assert!(daemon.context_menu.is_some());
+        daemon.step(now + CONTEXT_MENU_LEAVE_TIMEOUT, true);
+        assert!(daemon.context_menu.is_none(), "The pointer stayed away too long");
+        assert!(backend.surface(menu).is_none());
+    }
 
-        // Set up the file watcher with the calloop channel sender
-        self.setup_watcher(sender)?;
+    #[test]
+    fn test_swapped_buttons_select_with_right_button() {
+        use crate::backend::HeadlessBackend;
 
-        // Register the file watcher channel as an event source
-        loop_handle
-            .insert_source(channel, |event, _, state: &mut DaemonState| {
-                match event {
-                    calloop::channel::Event::Msg(Ok(fs_event)) => {
-                        state.pending_events.push(fs_event);
-                    }
-                    calloop::channel::Event::Msg(Err(e)) => {
-                        error!("Watcher error: {}", e);
-                    }
-                    calloop::channel::Event::Closed => {
-                        error!("Watcher channel closed");
-                        state.should_stop = true;
-                    }
-                }
-            })
-            .map_err(|e| anyhow::anyhow!("Failed to register file watcher channel: {:?}", e))?;
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { swap_buttons: true, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+
+        for (button, pressed) in [(BTN_LEFT, true), (BTN_LEFT, false)] {
+            backend.push_input(InputEvent::PointerButton {
+                surface_id,
+                button,
+                pressed,
+                x: 5.0,
+                y: 5.0,
+                modifiers: KeyModifiers::default(),
+            });
+        }
+        daemon.step(Instant::now(), true);
+        assert!(!daemon.icons()[&file].is_selected(), "The left button opens the menu when swapped");
+
+        for pressed in [true, false] {
+            backend.push_input(InputEvent::PointerButton {
+                surface_id,
+                button: BTN_RIGHT,
+                pressed,
+                x: 5.0,
+                y: 5.0,
+                modifiers: KeyModifiers::default(),
+            });
+        }
+        daemon.step(Instant::now(), true);
+        assert!(daemon.icons()[&file].is_selected());
+    }
 
-        // Register a timer for periodic icon updates (16ms = ~60 FPS)
-        let timer = Timer::from_duration(Duration::from_millis(16));
-        loop_handle
-            .insert_source(timer, |_, _, state: &mut DaemonState| {
-                state.should_update_icons = true;
-                TimeoutAction::ToDuration(Duration::from_millis(16))
-            })
-            .map_err(|e| anyhow::anyhow!("Failed to register update timer: {:?}", e))?;
+    #[test]
+    fn test_side_buttons_follow_button_actions() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::ButtonAction;
 
-        // Create the daemon state for the event loop
-        let mut state = DaemonState {
-            pending_events: Vec::new(),
-            should_update_icons: false,
-            should_stop: false,
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let marker = temp_dir.path().join("opened");
+        let config = Config {
+            open_commands: HashMap::from([(
+                "txt".to_string(),
+                format!("touch {}", marker.display()),
+            )]),
+            button_actions: HashMap::from([
+                ("back".to_string(), ButtonAction::Open),
+                ("forward".to_string(), ButtonAction::Ignore),
+            ]),
+            ..test_config()
+        };
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+
+        let press = |button| InputEvent::PointerButton {
+            surface_id,
+            button,
+            pressed: true,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
         };
 
-        info!("Entering calloop dispatch loop");
+        // BTN_EXTRA is forward, which is ignored
+        backend.push_input(press(276));
+        daemon.step(Instant::now(), true);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!marker.exists());
+
+        // BTN_SIDE is back, which opens the file
+        backend.push_input(press(275));
+        daemon.step(Instant::now(), true);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !marker.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(marker.exists(), "The back button should open the file");
+        assert!(!daemon.icons()[&file].is_selected());
+    }
 
-        // Do initial render of all icons
-        self.render_icons_to_surfaces();
+    #[test]
+    fn test_embedded_backend_gets_buffers_and_feeds_input() {
+        use crate::backend::HeadlessBackend;
 
-        // Main event loop
-        loop {
-            // Dispatch Wayland events first
-            self.dispatch_wayland();
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        let surface_id = daemon.path_to_surface[&file];
+        let surface = backend.surface(surface_id).unwrap();
+        assert!(surface.pixels.is_some(), "The icon should be rendered into the backend");
+
+        for pressed in [true, false] {
+            backend.push_input(InputEvent::PointerButton {
+                surface_id,
+                button: 272,
+                pressed,
+                x: 5.0,
+                y: 5.0,
+                modifiers: KeyModifiers::default(),
+            });
+        }
+        daemon.step(Instant::now(), true);
+        assert!(daemon.icons()[&file].is_selected());
 
-            // Handle any Wayland input events
-            self.handle_wayland_input();
+        backend.request_exit();
+        assert!(daemon.should_exit());
+    }
 
-            // Check for screen dimension changes from Wayland outputs
-            self.update_screen_dimensions();
+    #[test]
+    fn test_out_of_range_icon_size_is_clamped_and_rendered() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::MAX_ICON_SIZE;
 
-            // Dispatch calloop events (blocking with timeout)
-            event_loop
-                .dispatch(Some(Duration::from_millis(16)), &mut state)
-                .context("Event loop dispatch failed")?;
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
 
-            // Process pending file system events
-            for event in state.pending_events.drain(..) {
-                if let Err(e) = self.handle_fs_event(event) {
-                    error!("Error handling fs event: {}", e);
-                }
-            }
+        let backend = HeadlessBackend::new(3840, 2160);
+        let config = Config { icon_size: 1_000_000, ..test_config() };
+        let mut daemon = IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
 
-            // Update icons if timer fired
-            if state.should_update_icons {
-                self.update_icons();
-                state.should_update_icons = false;
-            }
+        assert_eq!(daemon.config.icon_size, MAX_ICON_SIZE);
+        let surface = backend.surface(daemon.path_to_surface[&file]).unwrap();
+        assert_eq!(surface.width, MAX_ICON_SIZE);
+        assert!(surface.pixels.is_some(), "The icon is rendered at the clamped size");
 
-            // Only render if something changed (dirty flag is checked inside render_icons_to_surfaces)
-            self.render_icons_to_surfaces();
+        daemon.set_icon_size(0);
+        assert_eq!(daemon.config.icon_size, crate::config::MIN_ICON_SIZE);
+    }
 
-            // Check if we should stop
-            if state.should_stop || self.wayland_should_exit() {
-                info!("Daemon stopping");
-                break;
-            }
-        }
+    #[test]
+    fn test_theme_dir_images_replace_the_fallback_glyph() {
+        use crate::backend::HeadlessBackend;
 
-        Ok(())
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        let theme_dir = temp_dir.path().join("theme");
+        fs::create_dir_all(&desktop_path).unwrap();
+        fs::create_dir_all(&theme_dir).unwrap();
+        image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]))
+            .save(theme_dir.join("document.png"))
+            .unwrap();
+        // Text files are documents
+        let notes = desktop_path.join("notes.txt");
+        let archive = desktop_path.join("backup.zip");
+        fs::write(&notes, "x").unwrap();
+        fs::write(&archive, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { icon_theme_dir: Some(theme_dir), ..test_config() };
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        // RGBA of the pixel in the middle of an icon's glyph
+        let center = |path: &PathBuf| {
+            let surface = backend.surface(daemon.path_to_surface[path]).unwrap();
+            let offset = ((32 * surface.width + 32) * 4) as usize;
+            surface.pixels.unwrap()[offset..offset + 4].to_vec()
+        };
+        assert_eq!(center(&notes), [255, 0, 0, 255]);
+        // No archive.png or archive.svg, so the archive keeps the built-in glyph
+        assert_eq!(center(&archive), [0x75, 0x50, 0x7b, 255]);
     }
 
-    /// Get the number of active icons
-    #[allow(dead_code)]
-    pub fn icon_count(&self) -> usize {
-        self.icons.len()
+    #[test]
+    fn test_oversized_surface_renders_at_icon_size() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+        backend.resize_surface(surface_id, 100_000, 100_000);
+        daemon.step(Instant::now(), true);
+
+        assert!(daemon.unrenderable_sizes.contains(&file));
+        let pixels = backend.surface(surface_id).unwrap().pixels.unwrap();
+        assert_eq!(pixels.len(), (64 * (64 + daemon.label_height()) * 4) as usize);
     }
 
-    /// Check if a path has an icon (for testing)
-    #[cfg(test)]
-    pub fn has_icon(&self, path: &Path) -> bool {
-        self.icons.contains_key(path)
+    #[test]
+    fn test_set_icon_size_replaces_surfaces() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let old_surface = daemon.path_to_surface[&file];
+
+        daemon.set_icon_size(96);
+        daemon.step(Instant::now(), true);
+
+        assert!(backend.surface(old_surface).is_none(), "The old surface should be destroyed");
+        let surface = backend.surface(daemon.path_to_surface[&file]).unwrap();
+        assert_eq!(surface.width, 96);
+        assert_eq!(surface.height, 96 + daemon.label_height());
+        assert!(surface.pixels.is_some(), "The icon should be re-rendered at the new size");
+        assert_eq!(backend.surface_ids().len(), 1);
     }
 
-    /// Get reference to icons HashMap (for testing)
-    #[cfg(test)]
-    pub fn icons(&self) -> &HashMap<PathBuf, DesktopIcon> {
-        &self.icons
+    #[test]
+    fn test_label_lines_resize_surfaces() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let two_lines = backend.surface(daemon.path_to_surface[&file]).unwrap().height;
+        assert_eq!(two_lines, 64 + label_height(daemon.config.font_size, 2));
+
+        daemon.apply_config(Config { label_lines: 1, ..test_config() }).unwrap();
+        daemon.step(Instant::now(), true);
+
+        let surface = backend.surface(daemon.path_to_surface[&file]).unwrap();
+        assert_eq!(surface.height, 64 + daemon.label_height());
+        assert!(surface.height < two_lines);
+        assert!(surface.pixels.is_some());
     }
-}
 
-/// State passed to the calloop event loop callbacks
-struct DaemonState {
-    pending_events: Vec<Event>,
-    should_update_icons: bool,
-    should_stop: bool,
-}
+    #[test]
+    fn test_apply_config_changes_icon_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use notify::{event::CreateKind, event::RemoveKind, event::ModifyKind, EventKind};
-    use std::fs;
-    use tempfile::TempDir;
+        let config = Config { icon_size: 48, ..test_config() };
+        daemon.apply_config(config).unwrap();
+        assert_eq!(daemon.config.icon_size, 48);
+        assert!(daemon.needs_render);
+    }
 
-    /// Helper to create a test config
-    fn test_config() -> Config {
-        Config::default()
+    #[test]
+    fn test_place_icon_moves_and_evicts() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let paths: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"].iter().map(|n| desktop_path.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(HeadlessBackend::new(800, 600)));
+        daemon.scan_desktop().unwrap();
+        let (a, c) = (&paths[0], &paths[2]);
+
+        daemon.place_icon(c, 0, 0).unwrap();
+        assert_eq!(daemon.icons()[c].layout_index(), 0);
+        let mut others: Vec<u32> = paths[..2].iter().map(|p| daemon.icons()[p].layout_index()).collect();
+        others.sort();
+        assert_eq!(others, vec![1, 2], "Unplaced icons flow around the placed one");
+
+        daemon.place_icon(a, 0, 0).unwrap();
+        assert_eq!(daemon.icons()[a].layout_index(), 0);
+        assert_eq!(daemon.placements.get(c), None, "The previous occupant is evicted");
+        assert_ne!(daemon.icons()[c].layout_index(), 0);
+
+        daemon.place_icon(c, 2, 1).unwrap();
+        let (columns, _) = daemon.grid_dimensions();
+        assert_eq!(daemon.icons()[c].layout_index(), columns + 2);
     }
 
-    /// Helper to create a test daemon without watchers (for unit testing)
-    fn create_test_daemon(desktop_dir: PathBuf) -> IconDaemon {
-        let config = test_config();
-        let renderer = IconRenderer::new(config.icon_size, config.font_size);
-        IconDaemon {
-            config,
-            desktop_dir,
-            icons: HashMap::new(),
-            watcher: None,
-            event_sender: None,
-            wayland: None, // No Wayland in tests
-            renderer,
-            surface_to_path: HashMap::new(),
-            path_to_surface: HashMap::new(),
-            screen_width: 1920,
-            screen_height: 1080,
-            needs_render: false,
+    #[test]
+    fn test_scan_drops_placements_of_deleted_files() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let new_file = desktop_path.join("new.txt");
+        fs::write(&new_file, "x").unwrap();
+        let deleted = desktop_path.join("deleted.txt");
+
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(HeadlessBackend::new(800, 600)));
+        daemon.placements.set(&deleted, Some(Cell { col: 0, row: 0 }));
+        daemon.scan_desktop().unwrap();
+
+        assert_eq!(daemon.placements.get(&deleted), None);
+        assert_eq!(daemon.icons()[&new_file].layout_index(), 0, "The freed cell is filled");
+    }
+
+    #[test]
+    fn test_arrow_keys_move_focus_and_enter_opens() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.backend = Some(Box::new(HeadlessBackend::new(800, 600)));
+
+        // Nothing to focus on an empty desktop
+        daemon.handle_key(KEY_RIGHT, KeyModifiers::default());
+        daemon.handle_key(KEY_RETURN, KeyModifiers::default());
+        assert!(daemon.keyboard_focus.is_none());
+
+        let paths: Vec<PathBuf> =
+            ["a.txt", "b.txt", "c.txt", "d.txt"].iter().map(|n| desktop_path.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
         }
+        daemon.scan_desktop().unwrap();
+        // a b
+        // c d
+        for (path, (col, row)) in paths.iter().zip([(0, 0), (1, 0), (0, 1), (1, 1)]) {
+            daemon.place_icon(path, col, row).unwrap();
+        }
+        let focus = |daemon: &IconDaemon| daemon.keyboard_focus.clone().unwrap();
+        let press =
+            |daemon: &mut IconDaemon, keysym| daemon.handle_key(keysym, KeyModifiers::default());
+
+        press(&mut daemon, KEY_RIGHT);
+        assert_eq!(focus(&daemon), paths[0], "The focus starts at the top left");
+        assert!(daemon.icons()[&paths[0]].is_focused());
+        assert!(daemon.icons()[&paths[0]].is_selected());
+
+        press(&mut daemon, KEY_RIGHT);
+        press(&mut daemon, KEY_RIGHT);
+        assert_eq!(focus(&daemon), paths[2], "Right at the row's end goes to the next row");
+        assert!(!daemon.icons()[&paths[1]].is_focused());
+        assert_eq!(daemon.selected_paths(), vec![paths[2].clone()]);
+
+        press(&mut daemon, KEY_DOWN);
+        assert_eq!(focus(&daemon), paths[1], "Down at the column's end goes to the next column");
+        press(&mut daemon, KEY_UP);
+        assert_eq!(focus(&daemon), paths[2]);
+        press(&mut daemon, KEY_LEFT);
+        press(&mut daemon, KEY_LEFT);
+        assert_eq!(focus(&daemon), paths[0]);
+        press(&mut daemon, KEY_LEFT);
+        assert_eq!(focus(&daemon), paths[3], "Left from the first icon wraps to the last");
+
+        // Modified arrows are left alone
+        daemon.handle_key(KEY_LEFT, KeyModifiers { ctrl: true, ..KeyModifiers::default() });
+        assert_eq!(focus(&daemon), paths[3]);
+
+        let marker = temp_dir.path().join("opened");
+        daemon.config.open_commands =
+            HashMap::from([("txt".to_string(), format!("touch {}", marker.display()))]);
+        press(&mut daemon, KEY_RETURN);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !marker.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(marker.exists(), "Enter should open the focused icon");
+
+        daemon.remove_icon(&paths[3]);
+        assert!(daemon.keyboard_focus.is_none(), "A removed icon loses the focus");
+        press(&mut daemon, KEY_DOWN);
+        assert_eq!(focus(&daemon), paths[0]);
     }
 
-    // ========================================================================
-    // File Create Event Tests
-    // ========================================================================
+    #[test]
+    fn test_double_click_needs_the_same_surface_within_the_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        let window = Duration::from_millis(daemon.config.double_click_ms);
+        let start = Instant::now();
+
+        assert!(!daemon.is_double_click(1, start));
+        assert!(daemon.is_double_click(1, start + window));
+        assert!(!daemon.is_double_click(1, start + window), "A third press starts over");
+
+        assert!(!daemon.is_double_click(2, start + window * 2), "Another surface starts over");
+        assert!(!daemon.is_double_click(2, start + window * 4), "A long gap starts over");
+        assert!(daemon.is_double_click(2, start + window * 4 + window / 2));
+    }
 
     #[test]
-    fn test_file_create_event_adds_icon_to_hashmap() {
+    fn test_double_click_opens_the_icon() {
+        use crate::backend::HeadlessBackend;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop_path).unwrap();
+        let file = desktop_path.join("a.txt");
+        fs::write(&file, "x").unwrap();
+        let marker = temp_dir.path().join("opened");
+
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        let backend = HeadlessBackend::new(800, 600);
+        daemon.backend = Some(Box::new(backend.clone()));
+        daemon.config.open_commands =
+            HashMap::from([("txt".to_string(), format!("touch {}", marker.display()))]);
+        daemon.scan_desktop().unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+
+        let button = |pressed| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+        let start = Instant::now();
+        let gap = Duration::from_millis(daemon.config.double_click_ms) * 2;
+        for now in [start, start + gap] {
+            backend.push_input(button(true));
+            backend.push_input(button(false));
+            daemon.handle_input(now);
+        }
+        assert!(daemon.icons()[&file].is_selected(), "Slow clicks only select");
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!marker.exists());
+
+        backend.push_input(button(true));
+        backend.push_input(button(false));
+        daemon.handle_input(start + gap + gap / 4);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !marker.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(marker.exists(), "A double click should open the icon");
+    }
+
+    #[test]
+    fn test_place_icon_rejects_cells_outside_grid() {
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("a.txt");
+        fs::write(&file, "x").unwrap();
         let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.scan_desktop().unwrap();
 
-        // Create a test file
-        let test_file = desktop_path.join("test_file.txt");
-        fs::write(&test_file, "test content").unwrap();
+        let (columns, rows) = daemon.grid_dimensions();
+        assert!(daemon.place_icon(&file, columns, 0).is_err());
+        assert!(daemon.place_icon(&file, 0, rows).is_err());
+        assert!(daemon.place_icon(&desktop_path.join("missing.txt"), 0, 0).is_err());
 
-        // Simulate a Create event
-        let event = Event {
-            kind: EventKind::Create(CreateKind::File),
-            paths: vec![test_file.clone()],
-            attrs: Default::default(),
-        };
+        let reply = daemon.handle_control(ControlCommand::Place { path: file.clone(), col: columns, row: 0 });
+        assert!(!reply.ok);
+        assert!(reply.error.unwrap().contains("outside"));
+        assert!(daemon.handle_control(ControlCommand::Place { path: file, col: 0, row: 0 }).ok);
+    }
 
-        daemon.handle_fs_event(event).unwrap();
+    #[test]
+    fn test_ping_reports_last_render() {
+        use crate::backend::HeadlessBackend;
 
-        assert!(daemon.has_icon(&test_file), "Icon should be added to HashMap after Create event");
-        assert_eq!(daemon.icon_count(), 1, "Should have exactly 1 icon");
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        fs::write(desktop_path.join("notes.txt"), "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon = IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend))).unwrap();
+        let data = daemon.handle_control(ControlCommand::Ping).data.unwrap();
+        assert_eq!(data["icons"], 1);
+        assert_eq!(data["wayland"], true);
+        assert!(data["last_render_ms"].is_null(), "Nothing has been rendered yet");
+
+        daemon.step(Instant::now(), true);
+        let data = daemon.handle_control(ControlCommand::Ping).data.unwrap();
+        assert!(data["last_render_ms"].as_u64().unwrap() > 0);
     }
 
     #[test]
-    fn test_folder_create_event_adds_icon_to_hashmap() {
+    fn test_column_flow_wraps_into_next_column() {
+        use crate::config::GridFlow;
+        use crate::icons::flow_slot;
+
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
         let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.grid_flow = GridFlow::Columns;
+        let (columns, rows) = daemon.grid_dimensions();
+        assert!(columns > 2, "The test screen should fit several columns");
+
+        // One column and a bit more
+        let count = rows + 2;
+        for i in 0..count {
+            fs::write(desktop_path.join(format!("{:03}.txt", i)), "x").unwrap();
+        }
+        daemon.scan_desktop().unwrap();
 
-        // Create a test folder
-        let test_folder = desktop_path.join("test_folder");
-        fs::create_dir(&test_folder).unwrap();
+        let mut slots: Vec<u32> = daemon.icons().values().map(|icon| icon.layout_index()).collect();
+        slots.sort();
+        let mut expected: Vec<u32> = (0..count).map(|n| flow_slot(n, columns, rows, GridFlow::Columns)).collect();
+        expected.sort();
+        assert_eq!(slots, expected);
+        // The first two rows have an icon in the second column, none in the third
+        assert!(slots.contains(&1) && slots.contains(&(columns + 1)));
+        assert!(!slots.contains(&2));
+    }
 
-        // Simulate a Create event
-        let event = Event {
-            kind: EventKind::Create(CreateKind::Folder),
-            paths: vec![test_folder.clone()],
-            attrs: Default::default(),
-        };
+    #[test]
+    fn test_max_rows_caps_column_height() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::GridFlow;
 
-        daemon.handle_fs_event(event).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        for i in 0..5 {
+            fs::write(desktop_path.join(format!("{}.txt", i)), "x").unwrap();
+        }
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(HeadlessBackend::new(800, 600)));
+        daemon.config.grid_flow = GridFlow::Columns;
+        daemon.config.max_rows = Some(2);
+        daemon.scan_desktop().unwrap();
+
+        let (columns, rows) = daemon.grid_dimensions();
+        assert_eq!(rows, 2);
+        let mut slots: Vec<u32> = daemon.icons().values().map(|icon| icon.layout_index()).collect();
+        slots.sort();
+        assert_eq!(slots, vec![0, 1, 2, columns, columns + 1]);
+
+        // Repositioning keeps every icon in its cell
+        daemon.reposition_all_icons();
+        let mut after: Vec<u32> = daemon.icons().values().map(|icon| icon.layout_index()).collect();
+        after.sort();
+        assert_eq!(after, slots);
+    }
+
+    #[test]
+    fn test_icons_avoid_keep_clear_region() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::KeepClearRegion;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        for i in 0..30 {
+            fs::write(desktop_path.join(format!("{:02}.txt", i)), "x").unwrap();
+        }
+        let logo = KeepClearRegion { x: 300, y: 200, width: 200, height: 200 };
+        let mut config = test_config();
+        config.keep_clear_regions.insert("*".to_string(), vec![logo]);
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let blocked = daemon.keep_clear_slots();
+        assert!(!blocked.is_empty(), "The region covers cells in the middle");
+
+        let (width, height) = (daemon.config.icon_size, daemon.config.icon_size + daemon.label_height());
+        for (path, &surface_id) in &daemon.path_to_surface {
+            let surface = backend.surface(surface_id).unwrap();
+            assert!(
+                !logo.overlaps(surface.x, surface.y, width, height),
+                "{} landed in the keep-clear region",
+                path.display()
+            );
+            assert!(!blocked.contains(&daemon.icons[path].layout_index()));
+        }
 
-        assert!(daemon.has_icon(&test_folder), "Icon should be added for folder Create event");
+        // Icons can't be placed there either
+        let (columns, _) = daemon.grid_dimensions();
+        let slot = *blocked.iter().next().unwrap();
+        let file = desktop_path.join("00.txt");
+        let err = daemon.place_icon(&file, slot % columns, slot / columns).unwrap_err();
+        assert!(err.to_string().contains("keep-clear"));
     }
 
     #[test]
-    fn test_create_event_multiple_paths() {
+    fn test_idle_loop_sleeps_on_the_display() {
         let temp_dir = TempDir::new().unwrap();
-        let desktop_path = temp_dir.path().to_path_buf();
-        let mut daemon = create_test_daemon(desktop_path.clone());
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        let frame = Duration::from_millis(daemon.config.frame_interval_ms);
 
-        // Create multiple test files
-        let test_file1 = desktop_path.join("file1.txt");
-        let test_file2 = desktop_path.join("file2.txt");
-        fs::write(&test_file1, "content1").unwrap();
-        fs::write(&test_file2, "content2").unwrap();
+        assert_eq!(daemon.loop_timeout(frame, true), None, "Nothing to draw, so sleep");
+        assert_eq!(daemon.loop_timeout(frame, false), Some(frame), "No display fd to wake on");
 
-        // Simulate a Create event with multiple paths
-        let event = Event {
-            kind: EventKind::Create(CreateKind::File),
-            paths: vec![test_file1.clone(), test_file2.clone()],
-            attrs: Default::default(),
-        };
+        daemon.config.dispatch_timeout_ms = 500;
+        assert_eq!(daemon.loop_timeout(frame, true), Some(Duration::from_millis(500)));
 
-        daemon.handle_fs_event(event).unwrap();
+        // A busy loop wakes every frame, whatever the bound
+        daemon.needs_render = true;
+        assert_eq!(daemon.loop_timeout(frame, true), Some(frame));
+        daemon.needs_render = false;
 
-        assert!(daemon.has_icon(&test_file1), "Icon should be added for first file");
-        assert!(daemon.has_icon(&test_file2), "Icon should be added for second file");
-        assert_eq!(daemon.icon_count(), 2, "Should have exactly 2 icons");
+        daemon.config.frame_pacing = FramePacing::Timer;
+        assert_eq!(daemon.loop_timeout(frame, true), Some(frame));
     }
 
-    // ========================================================================
-    // File Delete Event Tests
-    // ========================================================================
-
     #[test]
-    fn test_file_delete_event_removes_icon_from_hashmap() {
+    fn test_idle_only_without_pending_work() {
         let temp_dir = TempDir::new().unwrap();
-        let desktop_path = temp_dir.path().to_path_buf();
-        let mut daemon = create_test_daemon(desktop_path.clone());
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let mut daemon = create_test_daemon(temp_dir.path().to_path_buf());
+        assert!(daemon.is_idle());
 
-        // Create a test file and add it to icons
-        let test_file = desktop_path.join("test_file.txt");
-        fs::write(&test_file, "test content").unwrap();
+        daemon.needs_render = true;
+        assert!(!daemon.is_idle(), "A pending render keeps the loop polling");
+        daemon.needs_render = false;
 
-        // First add the icon via Create event
-        let create_event = Event {
-            kind: EventKind::Create(CreateKind::File),
-            paths: vec![test_file.clone()],
-            attrs: Default::default(),
+        daemon.appearing.insert(file, Instant::now());
+        assert!(!daemon.is_idle(), "So does an animation");
+    }
+
+    #[test]
+    fn test_overflow_pile_stacks_and_fans_out() {
+        use crate::backend::HeadlessBackend;
+        use crate::config::OverflowPileConfig;
+
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config {
+            max_rows: Some(1),
+            overflow_pile: OverflowPileConfig { enabled: true, ..Default::default() },
+            ..test_config()
         };
-        daemon.handle_fs_event(create_event).unwrap();
-        assert!(daemon.has_icon(&test_file), "Icon should exist after Create");
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        let (columns, _) = daemon.grid_dimensions();
+        for i in 0..columns + 2 {
+            fs::write(desktop_path.join(format!("{:02}.txt", i)), "x").unwrap();
+        }
+        daemon.scan_desktop().unwrap();
+        daemon.step(Instant::now(), true);
 
-        // Now simulate a Remove event
-        let remove_event = Event {
-            kind: EventKind::Remove(RemoveKind::File),
-            paths: vec![test_file.clone()],
-            attrs: Default::default(),
+        // The last cell's icon and the two past the grid are stacked there
+        assert_eq!(daemon.pile.len(), 3);
+        let position = |daemon: &IconDaemon, path: &PathBuf| {
+            backend.surface_position(daemon.path_to_surface[path]).unwrap()
         };
+        let positions: Vec<(i32, i32)> = daemon.pile.iter().map(|path| position(&daemon, path)).collect();
+        let offset = daemon.config.overflow_pile.offset as i32;
+        assert_eq!(positions[1], (positions[0].0 - offset, positions[0].1 - offset));
+        assert_eq!(positions[2], (positions[0].0 - 2 * offset, positions[0].1 - 2 * offset));
+
+        let press = |surface_id| InputEvent::PointerButton {
+            surface_id,
+            button: BTN_LEFT,
+            pressed: true,
+            x: 5.0,
+            y: 5.0,
+            modifiers: KeyModifiers::default(),
+        };
+        let top = daemon.pile[2].clone();
+        backend.push_input(press(daemon.path_to_surface[&top]));
+        daemon.step(Instant::now(), true);
+        assert!(daemon.pile_fanned);
+        assert!(!daemon.icons()[&top].is_selected(), "Fanning out uses up the press");
+        let fanned: HashSet<(i32, i32)> = daemon.pile.iter().map(|path| position(&daemon, path)).collect();
+        assert_eq!(fanned.len(), 3, "Fanned out icons get a cell each");
+
+        // A press on an icon outside the pile collapses it
+        let outside = desktop_path.join("00.txt");
+        backend.push_input(press(daemon.path_to_surface[&outside]));
+        daemon.step(Instant::now(), true);
+        assert!(!daemon.pile_fanned);
+        assert_eq!(position(&daemon, &top), positions[2]);
+
+        // Below the threshold the pile falls apart
+        let mut config = daemon.config.clone();
+        config.overflow_pile.threshold = 4;
+        daemon.apply_config(config).unwrap();
+        assert!(daemon.pile.is_empty());
+    }
 
-        daemon.handle_fs_event(remove_event).unwrap();
+    #[test]
+    fn test_layout_export_import_round_trip() {
+        use crate::backend::HeadlessBackend;
 
-        assert!(!daemon.has_icon(&test_file), "Icon should be removed from HashMap after Remove event");
-        assert_eq!(daemon.icon_count(), 0, "Should have no icons after removal");
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let paths: Vec<PathBuf> = ["a.txt", "b.txt"].iter().map(|n| desktop_path.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
+        let mut daemon = create_test_daemon(desktop_path);
+        daemon.backend = Some(Box::new(HeadlessBackend::new(800, 600)));
+        daemon.scan_desktop().unwrap();
+        daemon.place_icon(&paths[1], 3, 2).unwrap();
+
+        let reply = daemon.handle_control(ControlCommand::ExportLayout);
+        let data = reply.data.unwrap();
+        assert_eq!(data["version"], 1);
+        assert_eq!(data["icons"]["b.txt"], serde_json::json!({ "col": 3, "row": 2 }));
+
+        let mut layout = Layout::from_json(data).unwrap();
+        layout.icons.insert("a.txt".to_string(), Cell { col: 1, row: 1 });
+        layout.icons.insert("gone.txt".to_string(), Cell { col: 0, row: 0 });
+        let reply = daemon.handle_control(ControlCommand::ImportLayout {
+            data: serde_json::to_value(&layout).unwrap(),
+        });
+        assert!(reply.ok);
+        assert_eq!(reply.data.unwrap()["skipped"], serde_json::json!(["gone.txt"]));
+
+        let (columns, _) = daemon.grid_dimensions();
+        assert_eq!(daemon.icons()[&paths[0]].layout_index(), columns + 1);
+        assert_eq!(daemon.icons()[&paths[1]].layout_index(), 2 * columns + 3);
+
+        let newer = serde_json::json!({ "version": 99, "icons": {} });
+        let reply = daemon.handle_control(ControlCommand::ImportLayout { data: newer });
+        assert!(!reply.ok);
+        assert!(reply.error.unwrap().contains("not supported"));
     }
 
     #[test]
-    fn test_delete_event_for_nonexistent_icon_is_harmless() {
+    fn test_degenerate_output_size_defers_layout() {
+        use crate::backend::HeadlessBackend;
+
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
-        let mut daemon = create_test_daemon(desktop_path.clone());
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+        let before = backend.surface(surface_id).unwrap();
+
+        for (width, height) in [(0, 0), (1, 1), (800, 0)] {
+            backend.set_output_dimensions(width, height);
+            daemon.step(Instant::now(), true);
+            assert_eq!((daemon.screen_width, daemon.screen_height), (800, 600));
+            let surface = backend.surface(surface_id).unwrap();
+            assert_eq!((surface.x, surface.y), (before.x, before.y));
+        }
 
-        // Try to remove a file that was never added
-        let nonexistent = desktop_path.join("never_existed.txt");
+        backend.set_output_dimensions(1024, 768);
+        daemon.step(Instant::now(), true);
+        assert_eq!((daemon.screen_width, daemon.screen_height), (1024, 768));
+    }
 
-        let remove_event = Event {
-            kind: EventKind::Remove(RemoveKind::File),
-            paths: vec![nonexistent],
-            attrs: Default::default(),
-        };
+    #[test]
+    fn test_output_change_repositions_icons_once() {
+        use crate::backend::HeadlessBackend;
 
-        // Should not panic or error
-        let result = daemon.handle_fs_event(remove_event);
-        assert!(result.is_ok(), "Removing nonexistent icon should not error");
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(1920, 1080);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        let surface_id = daemon.path_to_surface[&file];
+        let moves = |backend: &HeadlessBackend| backend.surface(surface_id).unwrap().moves;
+        let before = moves(&backend);
+
+        // A dock is plugged in and the output shrinks
+        backend.set_output_dimensions(1280, 720);
+        daemon.step(Instant::now(), true);
+        assert_eq!((daemon.screen_width, daemon.screen_height), (1280, 720));
+        assert_eq!(moves(&backend), before + 1, "One reposition pass for one change");
+        daemon.step(Instant::now(), true);
+        assert_eq!(moves(&backend), before + 1);
+
+        // A scale change leaves the logical size but still lays icons out again
+        backend.change_output_scale();
+        daemon.step(Instant::now(), true);
+        assert_eq!(moves(&backend), before + 2);
     }
 
-    // ========================================================================
-    // File Modify Event Tests
-    // ========================================================================
-
     #[test]
-    fn test_file_modify_event_refreshes_icon() {
+    fn test_rubber_band_selects_touched_icons() {
+        use crate::backend::HeadlessBackend;
+
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
-        let mut daemon = create_test_daemon(desktop_path.clone());
-
-        // Create a test file and add it to icons
-        let test_file = desktop_path.join("test_file.txt");
-        fs::write(&test_file, "initial content").unwrap();
+        let paths: Vec<PathBuf> =
+            ["a.txt", "b.txt", "c.txt"].iter().map(|name| desktop_path.join(name)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
 
-        // Add the icon via Create event
-        let create_event = Event {
-            kind: EventKind::Create(CreateKind::File),
-            paths: vec![test_file.clone()],
-            attrs: Default::default(),
+        let backend = HeadlessBackend::new(800, 600);
+        // Sorted by name so a and b are neighbours, rather than in listing order
+        let config = Config { rubber_band_select: true, sort_order: SortOrder::Name, ..test_config() };
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        // The backdrop is drawn, and made first so icons stack above it
+        let backdrop = daemon.backdrop.unwrap();
+        assert!(backend.surface(backdrop).unwrap().pixels.is_some());
+        assert!(daemon.surface_to_path.keys().all(|&surface_id| surface_id > backdrop));
+
+        let icon = |path: &PathBuf| backend.surface(daemon.path_to_surface[path]).unwrap();
+        let (first, second) = (icon(&paths[0]), icon(&paths[1]));
+        let button = |pressed, x, y| InputEvent::PointerButton {
+            surface_id: backdrop,
+            button: 272,
+            pressed,
+            x,
+            y,
+            modifiers: KeyModifiers::default(),
         };
-        daemon.handle_fs_event(create_event).unwrap();
-        assert_eq!(daemon.icon_count(), 1, "Should have 1 icon before modify");
-
-        // Modify the file
-        fs::write(&test_file, "modified content").unwrap();
 
-        // Simulate a Modify event
-        let modify_event = Event {
-            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content)),
-            paths: vec![test_file.clone()],
-            attrs: Default::default(),
-        };
+        // Drag from just outside the first icon to the middle of the second
+        backend.push_input(button(true, f64::from(first.x) - 2.0, f64::from(first.y) - 2.0));
+        let x = f64::from(second.x) + f64::from(second.width) / 2.0;
+        let y = f64::from(second.y) + f64::from(second.height) / 2.0;
+        backend.push_input(InputEvent::PointerMotion { surface_id: backdrop, x, y });
+        daemon.step(Instant::now(), true);
+        let band = daemon.rubber_band.as_ref().and_then(|band| band.surface).unwrap();
+        assert!(backend.surface(band).unwrap().pixels.is_some());
+
+        backend.push_input(button(false, x, y));
+        daemon.step(Instant::now(), true);
+        assert_eq!(selection(&daemon, &paths), vec![true, true, false]);
+        assert!(backend.surface(band).is_none(), "The band is gone once released");
+
+        // A click on empty desktop clears the selection
+        backend.push_input(button(true, 1.0, 599.0));
+        backend.push_input(button(false, 1.0, 599.0));
+        daemon.step(Instant::now(), true);
+        assert_eq!(selection(&daemon, &paths), vec![false, false, false]);
+    }
 
-        daemon.handle_fs_event(modify_event).unwrap();
+    #[test]
+    fn test_grid_corner_keeps_icons_pinned_on_resize() {
+        use crate::backend::HeadlessBackend;
 
-        // Icon should still exist (was refreshed)
-        assert!(daemon.has_icon(&test_file), "Icon should still exist after Modify event");
-        assert_eq!(daemon.icon_count(), 1, "Should still have exactly 1 icon");
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let first = desktop_path.join("a.txt");
+        let second = desktop_path.join("b.txt");
+        fs::write(&first, "x").unwrap();
+        fs::write(&second, "x").unwrap();
+
+        let backend = HeadlessBackend::new(1920, 1080);
+        let config = Config { grid_corner: Corner::BottomRight, ..test_config() };
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+        assert_eq!(backend.anchor_corner(), Corner::BottomRight);
+
+        // Distance of an icon's surface from the output's bottom-right corner
+        let from_corner = |daemon: &IconDaemon, path: &PathBuf| {
+            let surface = backend.surface(daemon.path_to_surface[path]).unwrap();
+            (daemon.screen_width as i32 - surface.x, daemon.screen_height as i32 - surface.y)
+        };
+        let (first_gap, second_gap) = (from_corner(&daemon, &first), from_corner(&daemon, &second));
+        let cell_width = (daemon.config.icon_size + daemon.config.grid_spacing) as i32;
+        assert_eq!((first_gap.0 - second_gap.0).abs(), cell_width, "Both icons share the bottom row");
+        assert_eq!(first_gap.1, second_gap.1);
+        assert!(first_gap.0.min(second_gap.0) <= cell_width * 2, "The row starts at the right edge");
+
+        backend.set_output_dimensions(1024, 768);
+        daemon.step(Instant::now(), true);
+        assert_eq!((daemon.screen_width, daemon.screen_height), (1024, 768));
+        assert_eq!(from_corner(&daemon, &first), first_gap);
+        assert_eq!(from_corner(&daemon, &second), second_gap);
     }
 
     #[test]
-    fn test_modify_event_on_unknown_path_is_ignored() {
+    fn test_sort_order_arranges_the_grid() {
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
-        let mut daemon = create_test_daemon(desktop_path.clone());
-
-        // Try to modify a file that was never added
-        let unknown_file = desktop_path.join("unknown.txt");
-        fs::write(&unknown_file, "content").unwrap();
-
-        let modify_event = Event {
-            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content)),
-            paths: vec![unknown_file.clone()],
-            attrs: Default::default(),
+        fs::write(desktop_path.join("b.txt"), "x").unwrap();
+        fs::write(desktop_path.join("a.txt"), "xxxxxxxxxx").unwrap();
+        fs::create_dir(desktop_path.join("zdir")).unwrap();
+
+        let backend = HeadlessBackend::new(1920, 1080);
+        let config = Config { sort_order: SortOrder::Name, sort_folders_first: true, ..test_config() };
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path.clone(), Some(Box::new(backend))).unwrap();
+        let names = |daemon: &IconDaemon| -> Vec<String> {
+            let order = daemon.grid_order();
+            order.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
         };
+        assert_eq!(names(&daemon), ["zdir", "a.txt", "b.txt"]);
+
+        // A new icon goes between the others, and a removed one leaves no gap
+        let added = desktop_path.join("aa.txt");
+        fs::write(&added, "xxxxx").unwrap();
+        daemon.handle_fs_event(Event::new(EventKind::Create(CreateKind::File)).add_path(added)).unwrap();
+        assert_eq!(names(&daemon), ["zdir", "a.txt", "aa.txt", "b.txt"]);
+        daemon.remove_icon(&desktop_path.join("a.txt"));
+        assert_eq!(names(&daemon), ["zdir", "aa.txt", "b.txt"]);
+        let indices: Vec<u32> = daemon.grid_order().iter().map(|p| daemon.icons[p].layout_index()).collect();
+        let keep_clear = daemon.keep_clear_slots();
+        let slots = [0, 1, 2].map(|n| daemon.nth_slot(n, &keep_clear));
+        assert_eq!(indices, slots);
+
+        let files = daemon.icons.values().filter(|icon| icon.icon_type() != IconType::Folder);
+        let by_size: Vec<&str> =
+            sort_icons(files, SortOrder::SizeReverse, false).iter().map(|icon| icon.name()).collect();
+        assert_eq!(by_size, ["aa.txt", "b.txt"]);
+    }
 
-        // Should not add the icon (only modifies existing ones)
-        daemon.handle_fs_event(modify_event).unwrap();
+    #[test]
+    fn test_first_render_waits_for_output_size() {
+        use crate::backend::HeadlessBackend;
 
-        assert!(!daemon.has_icon(&unknown_file), "Modify event should not add new icons");
-        assert_eq!(daemon.icon_count(), 0, "Should have no icons");
-    }
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(3840, 2160);
+        backend.clear_output_dimensions();
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+
+        daemon.step(Instant::now(), true);
+        assert!(!daemon.dimensions_known);
+        assert!(
+            backend.surface(surface_id).unwrap().pixels.is_none(),
+            "Nothing is shown while the layout is for the placeholder size"
+        );
+        assert!(daemon.needs_render);
 
-    // ========================================================================
-    // Hidden File Filtering Tests
-    // ========================================================================
+        backend.set_output_dimensions(3840, 2160);
+        daemon.step(Instant::now(), true);
+        assert_eq!((daemon.screen_width, daemon.screen_height), (3840, 2160));
+        assert!(backend.surface(surface_id).unwrap().pixels.is_some());
+    }
 
     #[test]
-    fn test_scan_desktop_ignores_hidden_files() {
+    fn test_output_wait_times_out() {
+        use crate::backend::HeadlessBackend;
+
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let backend = HeadlessBackend::new(800, 600);
+        backend.clear_output_dimensions();
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
+        let surface_id = daemon.path_to_surface[&file];
+
+        daemon.step(Instant::now() + OUTPUT_WAIT_TIMEOUT, true);
+        assert!(daemon.dimensions_known);
+        assert_eq!((daemon.screen_width, daemon.screen_height), (1920, 1080));
+        assert!(backend.surface(surface_id).unwrap().pixels.is_some());
+    }
 
-        // Create both visible and hidden files
-        let visible_file = desktop_path.join("visible.txt");
-        let hidden_file = desktop_path.join(".hidden_file");
-        let hidden_folder = desktop_path.join(".hidden_folder");
-
-        fs::write(&visible_file, "visible content").unwrap();
-        fs::write(&hidden_file, "hidden content").unwrap();
-        fs::create_dir(&hidden_folder).unwrap();
+    #[test]
+    fn test_empty_hint_follows_icon_count() {
+        use crate::backend::HeadlessBackend;
 
-        // Create daemon and scan
-        let mut daemon = create_test_daemon(desktop_path.clone());
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let backend = HeadlessBackend::new(800, 600);
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path.clone(), Some(Box::new(backend.clone()))).unwrap();
+        daemon.step(Instant::now(), true);
+
+        let hint = daemon.empty_hint.expect("An empty desktop shows the hint");
+        let surface = backend.surface(hint).unwrap();
+        assert_eq!((surface.width, surface.height), (EMPTY_HINT_WIDTH, EMPTY_HINT_HEIGHT));
+        assert_eq!((surface.x, surface.y), ((800 - 360) / 2, (600 - 48) / 2));
+        assert!(surface.pixels.is_some());
+
+        let file = desktop_path.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        daemon.add_icon(&file).unwrap();
+        assert_eq!(daemon.empty_hint, None);
+        assert!(backend.surface(hint).is_none(), "The first icon removes the hint");
+
+        daemon.remove_icon(&file);
+        let hint = daemon.empty_hint.expect("Removing the last icon brings the hint back");
+        assert!(backend.surface(hint).is_some());
+    }
 
-        daemon.scan_desktop().unwrap();
+    #[test]
+    fn test_empty_hint_can_be_turned_off() {
+        use crate::backend::HeadlessBackend;
 
-        // Only the visible file should be added
-        assert!(daemon.has_icon(&visible_file), "Visible file should have an icon");
-        assert!(!daemon.has_icon(&hidden_file), "Hidden file (.hidden_file) should be ignored");
-        assert!(!daemon.has_icon(&hidden_folder), "Hidden folder (.hidden_folder) should be ignored");
-        assert_eq!(daemon.icon_count(), 1, "Should have exactly 1 icon (visible file only)");
+        let temp_dir = TempDir::new().unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        let config = Config { show_empty_hint: false, ..test_config() };
+        let mut daemon =
+            IconDaemon::with_backend(config, temp_dir.path().to_path_buf(), Some(Box::new(backend.clone()))).unwrap();
+        assert_eq!(daemon.empty_hint, None);
+        assert!(backend.surface_ids().is_empty());
+
+        daemon.apply_config(test_config()).unwrap();
+        assert!(daemon.empty_hint.is_some());
+        daemon.apply_config(Config { show_empty_hint: false, ..test_config() }).unwrap();
+        assert_eq!(daemon.empty_hint, None);
+        assert!(backend.surface_ids().is_empty());
     }
 
     #[test]
-    fn test_scan_desktop_ignores_files_starting_with_dot() {
+    fn test_failed_attaches_are_retried_next_frame() {
+        use crate::backend::HeadlessBackend;
+
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(desktop_path.join(name), "x").unwrap();
+        }
 
-        // Create files with various hidden patterns
-        let dotfile = desktop_path.join(".bashrc");
-        let dot_config = desktop_path.join(".config");
-        let dot_ds_store = desktop_path.join(".DS_Store");
-        let normal_file = desktop_path.join("readme.txt");
+        // Room for a single icon's buffer
+        let backend = HeadlessBackend::new(800, 600);
+        let config = test_config();
+        let icon_bytes = (64 * (64 + label_height(config.font_size, config.label_lines)) * 4) as usize;
+        backend.set_pool_size(Some(icon_bytes));
+        let mut daemon =
+            IconDaemon::with_backend(config, desktop_path, Some(Box::new(backend.clone()))).unwrap();
+
+        daemon.step(Instant::now(), true);
+        let blank = |backend: &HeadlessBackend| {
+            backend.surface_ids().iter().filter(|&&id| backend.surface(id).unwrap().pixels.is_none()).count()
+        };
+        assert_eq!(blank(&backend), 2);
+        assert_eq!(daemon.dirty_icons.len(), 2, "Icons that failed to attach stay dirty");
+
+        // The pool grows; the next frame picks up the rest without any other event
+        backend.set_pool_size(None);
+        daemon.step(Instant::now(), true);
+        assert_eq!(blank(&backend), 0);
+        assert!(!daemon.needs_render && daemon.dirty_icons.is_empty());
+        assert!(daemon.attach_failures.is_empty());
+    }
 
-        fs::write(&dotfile, "").unwrap();
-        fs::create_dir(&dot_config).unwrap();
-        fs::write(&dot_ds_store, "").unwrap();
-        fs::write(&normal_file, "").unwrap();
+    #[test]
+    fn test_failed_attaches_give_up_after_max_retries() {
+        use crate::backend::HeadlessBackend;
 
-        let mut daemon = create_test_daemon(desktop_path);
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let file = desktop_path.join("a.txt");
+        fs::write(&file, "x").unwrap();
 
-        daemon.scan_desktop().unwrap();
+        let backend = HeadlessBackend::new(800, 600);
+        backend.set_pool_size(Some(0));
+        let mut daemon =
+            IconDaemon::with_backend(test_config(), desktop_path, Some(Box::new(backend.clone()))).unwrap();
 
-        assert!(!daemon.has_icon(&dotfile), ".bashrc should be ignored");
-        assert!(!daemon.has_icon(&dot_config), ".config should be ignored");
-        assert!(!daemon.has_icon(&dot_ds_store), ".DS_Store should be ignored");
-        assert!(daemon.has_icon(&normal_file), "readme.txt should have an icon");
-        assert_eq!(daemon.icon_count(), 1);
+        for _ in 0..=MAX_ATTACH_RETRIES {
+            daemon.step(Instant::now(), true);
+        }
+        assert_eq!(daemon.attach_failures[&file], MAX_ATTACH_RETRIES + 1);
+        assert!(daemon.dirty_icons.is_empty(), "Retries are bounded");
     }
 
     #[test]
-    fn test_scan_desktop_empty_directory() {
+    fn test_create_new_selects_and_renames_icon() {
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        fs::create_dir(desktop_path.join("New Folder")).unwrap();
+        daemon.scan_desktop().unwrap();
 
-        let mut daemon = create_test_daemon(desktop_path);
+        let path = daemon.create_new(&NewItem::Folder).unwrap();
+        assert_eq!(path, desktop_path.join("New Folder 2"));
+        assert!(daemon.icons()[&path].is_selected());
+        assert_eq!(daemon.renaming(), Some(path.as_path()));
 
-        daemon.scan_desktop().unwrap();
+        // The watcher's later create event must not duplicate the icon
+        daemon
+            .handle_fs_event(Event::new(EventKind::Create(CreateKind::Folder)).add_path(path.clone()))
+            .unwrap();
+        assert_eq!(daemon.icon_count(), 2);
 
-        assert_eq!(daemon.icon_count(), 0, "Empty directory should have no icons");
+        daemon.remove_icon(&path);
+        assert_eq!(daemon.renaming(), None);
     }
 
     #[test]
-    fn test_scan_desktop_nonexistent_directory() {
-        let nonexistent_path = PathBuf::from("/nonexistent/desktop/path/12345");
+    fn test_f2_renames_the_focused_icon_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let (file, taken) = (desktop_path.join("a.txt"), desktop_path.join("taken.txt"));
+        fs::write(&file, "x").unwrap();
+        fs::write(&taken, "x").unwrap();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.scan_desktop().unwrap();
+        let field_text = |daemon: &IconDaemon, path: &Path| {
+            daemon.icons[path].rename_field().map(|field| field.text().to_string())
+        };
+        let retype = |daemon: &mut IconDaemon, name: &str| {
+            daemon.handle_key(KEY_END, NONE);
+            for _ in 0..32 {
+                daemon.handle_key(KEY_BACKSPACE, NONE);
+            }
+            for c in name.chars() {
+                daemon.handle_key(c as u32, NONE);
+            }
+            daemon.handle_key(KEY_RETURN, NONE);
+        };
 
-        let mut daemon = create_test_daemon(nonexistent_path);
+        daemon.set_keyboard_focus(&file);
+        daemon.handle_key(KEY_F2, NONE);
+        assert_eq!(daemon.renaming(), Some(file.as_path()));
+        assert_eq!(field_text(&daemon, &file).as_deref(), Some("a.txt"));
 
-        // Should not error, just return Ok with no icons
-        let result = daemon.scan_desktop();
-        assert!(result.is_ok(), "Scanning nonexistent directory should not error");
-        assert_eq!(daemon.icon_count(), 0);
-    }
+        // Names with a slash and names already taken are refused
+        for refused in ["sub/b.txt", "taken.txt", ""] {
+            retype(&mut daemon, refused);
+            assert!(daemon.icons[&file].rename_field().unwrap().is_invalid(), "{:?}", refused);
+            assert!(file.exists() && taken.exists());
+        }
 
-    // ========================================================================
-    // Icon Count Tests
-    // ========================================================================
+        retype(&mut daemon, "b.txt");
+        assert!(!file.exists());
+        assert!(desktop_path.join("b.txt").exists());
+        assert_eq!(daemon.renaming(), None);
+
+        // Escape leaves the name as it was
+        daemon.set_keyboard_focus(&taken);
+        daemon.handle_key(KEY_F2, NONE);
+        daemon.handle_key('x' as u32, NONE);
+        assert_eq!(field_text(&daemon, &taken).as_deref(), Some("taken.txtx"));
+        daemon.handle_key(KEY_ESCAPE, NONE);
+        assert_eq!(field_text(&daemon, &taken), None);
+        assert!(taken.exists());
+
+        // A file removed while it is being renamed takes the field with it
+        daemon.handle_key(KEY_F2, NONE);
+        fs::remove_file(&taken).unwrap();
+        daemon.remove_icon(&taken);
+        assert_eq!(daemon.renaming(), None);
+    }
 
     #[test]
-    fn test_icon_count_accuracy() {
+    fn test_stale_mode_keeps_icons_of_unmounted_files() {
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
         let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.unavailable_files = UnavailableFiles::Stale;
 
-        assert_eq!(daemon.icon_count(), 0, "Initial count should be 0");
-
-        // Add some files
-        for i in 0..5 {
-            let file = desktop_path.join(format!("file{}.txt", i));
-            fs::write(&file, "content").unwrap();
-            let event = Event {
-                kind: EventKind::Create(CreateKind::File),
-                paths: vec![file],
-                attrs: Default::default(),
-            };
-            daemon.handle_fs_event(event).unwrap();
+        let on_usb = desktop_path.join("on-usb.txt");
+        let deleted = desktop_path.join("deleted.txt");
+        fs::write(&on_usb, "x").unwrap();
+        fs::write(&deleted, "x").unwrap();
+        daemon.scan_desktop().unwrap();
+        // Pretend this file lived on a mount that is no longer in the mount table
+        daemon
+            .get_icon_mut(&on_usb)
+            .unwrap()
+            .set_mount_point(Some(PathBuf::from("/run/media/cvh-test-unplugged")));
+
+        fs::remove_file(&on_usb).unwrap();
+        fs::remove_file(&deleted).unwrap();
+        for _ in 0..daemon.config.update_error_threshold - 1 {
+            daemon.update_icons();
         }
+        assert_eq!(daemon.icon_count(), 2, "A few failed checks are tolerated");
 
-        assert_eq!(daemon.icon_count(), 5, "Should have 5 icons after adding 5 files");
+        daemon.update_icons();
+        assert!(!daemon.has_icon(&deleted), "Plainly deleted files lose their icon");
+        assert!(daemon.get_icon(&on_usb).unwrap().is_unavailable());
+
+        // The mount comes back
+        fs::write(&on_usb, "x").unwrap();
+        daemon.update_icons();
+        assert!(!daemon.get_icon(&on_usb).unwrap().is_unavailable());
     }
 
-    // ========================================================================
-    // Duplicate Add Prevention Tests
-    // ========================================================================
+    #[test]
+    fn test_remove_mode_drops_missing_files_immediately() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop_path = temp_dir.path().to_path_buf();
+        let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.update_error_threshold = 1;
+
+        let file = desktop_path.join("gone.txt");
+        fs::write(&file, "x").unwrap();
+        daemon.scan_desktop().unwrap();
+        fs::remove_file(&file).unwrap();
+
+        daemon.update_icons();
+        assert!(!daemon.has_icon(&file));
+    }
 
     #[test]
-    fn test_add_icon_prevents_duplicates() {
+    fn test_intermittent_update_errors_keep_icon() {
         let temp_dir = TempDir::new().unwrap();
         let desktop_path = temp_dir.path().to_path_buf();
         let mut daemon = create_test_daemon(desktop_path.clone());
+        daemon.config.update_error_threshold = 3;
 
-        let test_file = desktop_path.join("test_file.txt");
-        fs::write(&test_file, "content").unwrap();
+        let file = desktop_path.join("flaky.txt");
+        fs::write(&file, "x").unwrap();
+        daemon.scan_desktop().unwrap();
 
-        // Add the same icon twice
-        daemon.add_icon(&test_file).unwrap();
-        daemon.add_icon(&test_file).unwrap();
+        // Fail twice, recover, then fail twice more: never three in a row
+        for _ in 0..2 {
+            fs::remove_file(&file).unwrap();
+            daemon.update_icons();
+            daemon.update_icons();
+            assert!(daemon.has_icon(&file), "Icon survives failures below the threshold");
+            assert_eq!(daemon.get_icon(&file).unwrap().failed_checks(), 2);
+
+            fs::write(&file, "x").unwrap();
+            daemon.update_icons();
+            assert_eq!(daemon.get_icon(&file).unwrap().failed_checks(), 0, "Success resets the count");
+        }
 
-        assert_eq!(daemon.icon_count(), 1, "Should still have only 1 icon after duplicate add");
+        fs::remove_file(&file).unwrap();
+        for _ in 0..3 {
+            daemon.update_icons();
+        }
+        assert!(!daemon.has_icon(&file), "Consecutive failures reaching the threshold remove it");
     }
 }
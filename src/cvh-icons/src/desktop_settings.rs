@@ -0,0 +1,236 @@
+//! Desktop icon size from the desktop environment's own settings
+//!
+//! With `follow_desktop_icon_size`, the icon size the user picked in GNOME
+//! (the desktop-icons extension, or Nautilus' icon view) or Plasma (the
+//! folder-view desktop containment) overrides `Config::icon_size`. Each
+//! desktop is a `SettingsSource`, compiled in behind its own feature.
+
+#[cfg(feature = "plasma-settings")]
+use std::path::PathBuf;
+#[cfg(feature = "gnome-settings")]
+use std::process::Command;
+
+/// Somewhere the desktop environment keeps its icon size setting
+pub trait SettingsSource {
+    /// Name for log messages
+    fn name(&self) -> &'static str;
+
+    /// Current icon size in pixels, or None if it is not set or unreadable
+    fn icon_size(&self) -> Option<u32>;
+}
+
+/// Pick the source for the running desktop, from `XDG_CURRENT_DESKTOP`
+pub fn detect() -> Option<Box<dyn SettingsSource>> {
+    let current = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    detect_from(&current)
+}
+
+/// Pick the source for a colon-separated `XDG_CURRENT_DESKTOP` value
+pub fn detect_from(current_desktop: &str) -> Option<Box<dyn SettingsSource>> {
+    for desktop in current_desktop.split(':').map(str::trim) {
+        match desktop.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "gnome-settings")]
+            "gnome" | "gnome-classic" | "unity" => return Some(Box::new(GnomeSettings)),
+            #[cfg(feature = "plasma-settings")]
+            "kde" | "plasma" => return Some(Box::new(PlasmaSettings::new())),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Pixel size for a GNOME zoom level name (`'standard'`, `large`, ...)
+///
+/// Covers both the desktop-icons extension's `icon-size` values and
+/// Nautilus' `default-zoom-level` values; quotes from `gsettings get` are
+/// stripped.
+pub fn gnome_zoom_to_size(level: &str) -> Option<u32> {
+    match level.trim().trim_matches('\'') {
+        "tiny" => Some(32),
+        "small" => Some(48),
+        "standard" | "small-plus" => Some(64),
+        "medium" => Some(96),
+        "large" => Some(128),
+        "larger" => Some(192),
+        "extra-large" | "largest" => Some(256),
+        _ => None,
+    }
+}
+
+/// Reads the GNOME desktop icon size through `gsettings`
+#[cfg(feature = "gnome-settings")]
+pub struct GnomeSettings;
+
+#[cfg(feature = "gnome-settings")]
+impl GnomeSettings {
+    /// Schema/key pairs in order of preference: the desktop-icons extension
+    /// draws the GNOME desktop if it is installed, Nautilus otherwise
+    const KEYS: [(&'static str, &'static str); 2] = [
+        ("org.gnome.shell.extensions.ding", "icon-size"),
+        ("org.gnome.nautilus.icon-view", "default-zoom-level"),
+    ];
+
+    fn get(schema: &str, key: &str) -> Option<String> {
+        let output = Command::new("gsettings").args(["get", schema, key]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[cfg(feature = "gnome-settings")]
+impl SettingsSource for GnomeSettings {
+    fn name(&self) -> &'static str {
+        "GNOME (gsettings)"
+    }
+
+    fn icon_size(&self) -> Option<u32> {
+        Self::KEYS
+            .iter()
+            .find_map(|(schema, key)| Self::get(schema, key).and_then(|value| gnome_zoom_to_size(&value)))
+    }
+}
+
+/// Icon sizes behind Plasma's folder-view `iconSize` index (small to enormous)
+const PLASMA_ICON_SIZES: [u32; 7] = [16, 22, 32, 48, 64, 128, 256];
+
+/// Icon size of the desktop containment in a `plasma-org.kde.plasma.desktop-appletsrc`
+///
+/// Finds containments using the desktop or folder plugin and reads `iconSize`
+/// from their `[General]` group. Plasma's default (index 3) is used when such
+/// a containment exists but never had its size changed.
+pub fn parse_plasma_icon_size(contents: &str) -> Option<u32> {
+    let mut desktop_ids = Vec::new();
+    let mut sizes = Vec::new();
+    let mut section = String::new();
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            section = line.to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        // Sections look like [Containments][1] and [Containments][1][General]
+        let Some((id, group)) = section
+            .strip_prefix("[Containments][")
+            .and_then(|rest| rest.split_once(']'))
+        else {
+            continue;
+        };
+
+        match (group, key.trim()) {
+            ("", "plugin")
+                if matches!(value.trim(), "org.kde.desktopcontainment" | "org.kde.plasma.folder") =>
+            {
+                desktop_ids.push(id.to_string());
+            }
+            ("[General]", "iconSize") => {
+                if let Ok(index) = value.trim().parse::<usize>() {
+                    sizes.push((id.to_string(), index));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let first = desktop_ids.first()?;
+    let index = sizes
+        .iter()
+        .find(|(id, _)| id == first)
+        .map_or(3, |(_, index)| *index);
+    PLASMA_ICON_SIZES.get(index).copied()
+}
+
+/// Reads the Plasma desktop icon size from the applets config file
+#[cfg(feature = "plasma-settings")]
+pub struct PlasmaSettings {
+    config_path: Option<PathBuf>,
+}
+
+#[cfg(feature = "plasma-settings")]
+impl PlasmaSettings {
+    pub fn new() -> Self {
+        Self {
+            config_path: dirs::config_dir().map(|dir| dir.join("plasma-org.kde.plasma.desktop-appletsrc")),
+        }
+    }
+}
+
+#[cfg(feature = "plasma-settings")]
+impl Default for PlasmaSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "plasma-settings")]
+impl SettingsSource for PlasmaSettings {
+    fn name(&self) -> &'static str {
+        "Plasma (desktop-appletsrc)"
+    }
+
+    fn icon_size(&self) -> Option<u32> {
+        let contents = std::fs::read_to_string(self.config_path.as_ref()?).ok()?;
+        parse_plasma_icon_size(&contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gnome_zoom_levels() {
+        assert_eq!(gnome_zoom_to_size("'standard'\n"), Some(64));
+        assert_eq!(gnome_zoom_to_size("small"), Some(48));
+        assert_eq!(gnome_zoom_to_size("'large'"), Some(128));
+        assert_eq!(gnome_zoom_to_size("'huge-ish'"), None);
+    }
+
+    #[test]
+    fn test_parse_plasma_icon_size() {
+        let contents = "\
+[Containments][2]
+plugin=org.kde.panel
+
+[Containments][2][General]
+iconSize=1
+
+[Containments][7]
+activityId=abc
+plugin=org.kde.desktopcontainment
+
+[Containments][7][General]
+iconSize=4
+";
+        assert_eq!(parse_plasma_icon_size(contents), Some(64));
+    }
+
+    #[test]
+    fn test_parse_plasma_icon_size_defaults() {
+        let unset = "[Containments][7]\nplugin=org.kde.plasma.folder\n";
+        assert_eq!(parse_plasma_icon_size(unset), Some(48));
+        assert_eq!(parse_plasma_icon_size("[Containments][2]\nplugin=org.kde.panel\n"), None);
+    }
+
+    #[test]
+    fn test_detect_from_unknown_desktop() {
+        assert!(detect_from("").is_none());
+        assert!(detect_from("sway").is_none());
+    }
+
+    #[cfg(feature = "gnome-settings")]
+    #[test]
+    fn test_detect_gnome() {
+        assert_eq!(detect_from("ubuntu:GNOME").unwrap().name(), "GNOME (gsettings)");
+    }
+
+    #[cfg(feature = "plasma-settings")]
+    #[test]
+    fn test_detect_plasma() {
+        assert_eq!(detect_from("KDE").unwrap().name(), "Plasma (desktop-appletsrc)");
+    }
+}
@@ -0,0 +1,272 @@
+//! Status emblems drawn in a corner of desktop icons
+//!
+//! An `EmblemProvider` knows the status of some files, such as their state in
+//! a version-control checkout or a cloud-sync folder. The daemon refreshes its
+//! providers on `emblem_refresh_ms` and after filesystem events, and draws the
+//! first emblem any provider reports for an icon. Files a provider knows
+//! nothing about (e.g. outside any git repository) get no emblem.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tracing::debug;
+
+use crate::ipc::{Overlay, OverlayCorner};
+
+/// Status shown as an emblem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Emblem {
+    /// Changed since the last commit
+    Modified,
+    /// Staged as a new file
+    Added,
+    /// Not tracked by version control
+    Untracked,
+    /// Unresolved merge conflict
+    Conflicted,
+    /// Uploaded and up to date
+    Synced,
+    /// Being uploaded or downloaded
+    Syncing,
+    /// Sync failed
+    SyncError,
+}
+
+impl Emblem {
+    /// How much the emblem matters when several apply to one icon (e.g. a
+    /// folder holding both modified and untracked files)
+    fn rank(self) -> u8 {
+        match self {
+            Emblem::Synced => 0,
+            Emblem::Untracked => 1,
+            Emblem::Added => 2,
+            Emblem::Syncing => 3,
+            Emblem::Modified => 4,
+            Emblem::SyncError => 5,
+            Emblem::Conflicted => 6,
+        }
+    }
+
+    /// Badge drawn for the emblem, in the bottom-left corner so it does not
+    /// cover widget badges, which default to the top right
+    pub fn overlay(self) -> Overlay {
+        let (emblem, color) = match self {
+            Emblem::Modified => ("emblem-important", "#e5a50a"),
+            Emblem::Added => ("emblem-new", "#2ec27e"),
+            Emblem::Untracked => ("dialog-question", "#77767b"),
+            Emblem::Conflicted => ("dialog-warning", "#e01b24"),
+            Emblem::Synced => ("emblem-ok", "#2ec27e"),
+            Emblem::Syncing => ("emblem-synchronizing", "#3584e4"),
+            Emblem::SyncError => ("dialog-error", "#e01b24"),
+        };
+        Overlay {
+            emblem: Some(emblem.to_string()),
+            color: Some(color.to_string()),
+            corner: OverlayCorner::BottomLeft,
+            ..Overlay::default()
+        }
+    }
+}
+
+/// Source of status emblems
+pub trait EmblemProvider {
+    /// Name for log messages
+    fn name(&self) -> &'static str;
+
+    /// Re-read the status of `paths`, the icons currently in `desktop_dir`
+    fn refresh(&mut self, desktop_dir: &Path, paths: &[PathBuf]);
+
+    /// Emblem for an icon as of the last refresh, or None if it has no status
+    fn emblem(&self, path: &Path) -> Option<Emblem>;
+}
+
+/// Record `emblem` for `path` unless it already has a more important one
+fn merge(statuses: &mut HashMap<PathBuf, Emblem>, path: PathBuf, emblem: Emblem) {
+    let entry = statuses.entry(path).or_insert(emblem);
+    if emblem.rank() > entry.rank() {
+        *entry = emblem;
+    }
+}
+
+/// Emblem for a `git status --porcelain` XY code, None for ignored files
+fn git_emblem(code: &[u8]) -> Option<Emblem> {
+    match code {
+        b"??" => Some(Emblem::Untracked),
+        b"!!" => None,
+        b"DD" | b"AU" | b"UD" | b"UA" | b"DU" | b"AA" | b"UU" => Some(Emblem::Conflicted),
+        [b'A', _] => Some(Emblem::Added),
+        _ => Some(Emblem::Modified),
+    }
+}
+
+/// Entries of `git status --porcelain=v1 -z` output, with paths relative to
+/// the repository root
+pub fn parse_porcelain(output: &[u8]) -> Vec<(PathBuf, Emblem)> {
+    let mut entries = Vec::new();
+    let mut records = output.split(|&b| b == 0);
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (code, path) = (&record[..2], &record[3..]);
+        // Renames and copies are followed by their original path
+        if matches!(code[0], b'R' | b'C') {
+            records.next();
+        }
+        if let Some(emblem) = git_emblem(code) {
+            let path = String::from_utf8_lossy(path);
+            entries.push((PathBuf::from(path.trim_end_matches('/')), emblem));
+        }
+    }
+    entries
+}
+
+/// Version-control status of files in git repositories
+///
+/// Covers both a desktop directory that is itself inside a repository, where
+/// each file gets its own status, and repositories checked out on the desktop,
+/// whose folder icon shows the most important status of anything inside.
+#[derive(Debug, Default)]
+pub struct GitStatusProvider {
+    statuses: HashMap<PathBuf, Emblem>,
+}
+
+impl GitStatusProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run git in `dir`, returning its stdout if it succeeded
+    fn git(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+        let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(output.stdout)
+    }
+
+    /// Top level of the repository `dir` is in, if any
+    fn repo_root(dir: &Path) -> Option<PathBuf> {
+        let stdout = Self::git(dir, &["rev-parse", "--show-toplevel"])?;
+        let root = String::from_utf8_lossy(&stdout).trim().to_string();
+        (!root.is_empty()).then(|| PathBuf::from(root))
+    }
+
+    /// Status of everything under `dir`, with absolute paths
+    fn status(dir: &Path) -> Option<Vec<(PathBuf, Emblem)>> {
+        let root = Self::repo_root(dir)?;
+        let args = ["status", "--porcelain=v1", "-z", "--untracked-files=normal", "."];
+        let stdout = Self::git(dir, &args)?;
+        Some(
+            parse_porcelain(&stdout)
+                .into_iter()
+                .map(|(path, emblem)| (root.join(path), emblem))
+                .collect(),
+        )
+    }
+}
+
+impl EmblemProvider for GitStatusProvider {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn refresh(&mut self, desktop_dir: &Path, paths: &[PathBuf]) {
+        self.statuses.clear();
+
+        // git reports resolved paths, so compare against the resolved desktop
+        let resolved = desktop_dir.canonicalize().unwrap_or_else(|_| desktop_dir.to_path_buf());
+        if let Some(entries) = Self::status(desktop_dir) {
+            for (path, emblem) in entries {
+                if let Ok(relative) = path.strip_prefix(&resolved) {
+                    // A change anywhere inside a folder shows on the folder
+                    if let Some(first) = relative.components().next() {
+                        merge(&mut self.statuses, desktop_dir.join(first), emblem);
+                    }
+                } else if resolved.starts_with(&path) {
+                    // The whole desktop is untracked (or otherwise changed)
+                    for icon in paths {
+                        merge(&mut self.statuses, icon.clone(), emblem);
+                    }
+                }
+            }
+        }
+
+        // Checkouts on the desktop are separate repositories
+        for path in paths.iter().filter(|path| path.join(".git").exists()) {
+            let Some(entries) = Self::status(path) else {
+                continue;
+            };
+            for (_, emblem) in entries {
+                merge(&mut self.statuses, path.clone(), emblem);
+            }
+        }
+
+        debug!("git status: {} of {} icons have an emblem", self.statuses.len(), paths.len());
+    }
+
+    fn emblem(&self, path: &Path) -> Option<Emblem> {
+        self.statuses.get(path).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = b" M notes.txt\0?? scratch/\0A  new.rs\0UU merge.rs\0R  moved.rs\0old.rs\0";
+        let entries = parse_porcelain(&[&output[..], b"!! target/\0"].concat());
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("notes.txt"), Emblem::Modified),
+                (PathBuf::from("scratch"), Emblem::Untracked),
+                (PathBuf::from("new.rs"), Emblem::Added),
+                (PathBuf::from("merge.rs"), Emblem::Conflicted),
+                (PathBuf::from("moved.rs"), Emblem::Modified),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_most_important_emblem() {
+        let mut statuses = HashMap::new();
+        let folder = PathBuf::from("/desktop/project");
+        merge(&mut statuses, folder.clone(), Emblem::Untracked);
+        merge(&mut statuses, folder.clone(), Emblem::Modified);
+        merge(&mut statuses, folder.clone(), Emblem::Added);
+        assert_eq!(statuses[&folder], Emblem::Modified);
+    }
+
+    #[test]
+    fn test_git_status_provider() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let desktop = temp_dir.path().join("Desktop");
+        let checkout = desktop.join("project");
+        std::fs::create_dir_all(&checkout).unwrap();
+        let plain = desktop.join("plain.txt");
+        std::fs::write(&plain, "x").unwrap();
+        let paths = vec![checkout.clone(), plain.clone()];
+
+        // Outside any repository there is nothing to show
+        let mut provider = GitStatusProvider::new();
+        provider.refresh(&desktop, &paths);
+        assert_eq!(provider.emblem(&plain), None);
+
+        let git_available = Command::new("git").arg("-C").arg(&checkout).arg("init").output();
+        if !git_available.is_ok_and(|output| output.status.success()) {
+            return;
+        }
+        std::fs::write(checkout.join("main.rs"), "fn main() {}").unwrap();
+        provider.refresh(&desktop, &paths);
+        assert_eq!(provider.emblem(&checkout), Some(Emblem::Untracked));
+        assert_eq!(provider.emblem(&plain), None);
+
+        Command::new("git").arg("-C").arg(&checkout).args(["add", "main.rs"]).output().unwrap();
+        provider.refresh(&desktop, &paths);
+        assert_eq!(provider.emblem(&checkout), Some(Emblem::Added));
+    }
+}
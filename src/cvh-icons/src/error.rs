@@ -0,0 +1,212 @@
+//! Error types for cvh-icons
+//!
+//! Failures that a supervisor needs to tell apart are represented by
+//! `DaemonError`, and each variant maps to a distinct process exit code:
+//!
+//! | Code | Meaning                                              |
+//! |------|------------------------------------------------------|
+//! | 0    | Clean shutdown                                       |
+//! | 1    | Any other failure                                    |
+//! | 2    | Could not connect to the Wayland display (transient) |
+//! | 3    | Compositor does not support wlr-layer-shell          |
+//! | 4    | Configuration file could not be read or parsed       |
+//! | 5    | Another instance already holds the instance lock     |
+//!
+//! The library's public API returns `IconsError`, which tells embedders what
+//! kind of thing failed. Internally errors stay `anyhow::Error`, and each
+//! `IconsError` wraps one, context chain and all.
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Exit code for a clean shutdown
+#[allow(dead_code)]
+pub const EXIT_OK: u8 = 0;
+
+/// Exit code for failures without a more specific code
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Exit code when the Wayland display cannot be reached
+pub const EXIT_WAYLAND_CONNECT: u8 = 2;
+
+/// Exit code when the compositor lacks the layer-shell protocol
+pub const EXIT_LAYER_SHELL_UNSUPPORTED: u8 = 3;
+
+/// Exit code when the configuration cannot be loaded
+pub const EXIT_CONFIG: u8 = 4;
+
+/// Exit code when another daemon instance is already running
+pub const EXIT_ALREADY_RUNNING: u8 = 5;
+
+/// Classified daemon failures
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    /// WAYLAND_DISPLAY is not set; the daemon can still run headless
+    #[error("WAYLAND_DISPLAY not set - not running under Wayland")]
+    NoWaylandDisplay,
+
+    /// Connecting to or initializing the Wayland display failed
+    #[error("failed to connect to Wayland display: {0:#}")]
+    WaylandConnect(anyhow::Error),
+
+    /// The compositor does not advertise wlr-layer-shell
+    #[error("compositor does not support wlr-layer-shell-unstable-v1: {0:#}")]
+    LayerShellUnsupported(anyhow::Error),
+
+    /// The configuration file could not be read or parsed
+    #[error("failed to load configuration: {0:#}")]
+    Config(anyhow::Error),
+
+    /// Another instance holds the instance lock
+    #[error(
+        "another cvh-icons instance is already running{} (lock: {})",
+        .pid.map(|p| format!(" (pid {})", p)).unwrap_or_default(),
+        .lock_path.display()
+    )]
+    AlreadyRunning { pid: Option<u32>, lock_path: PathBuf },
+}
+
+impl DaemonError {
+    /// Process exit code for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            DaemonError::NoWaylandDisplay | DaemonError::WaylandConnect(_) => EXIT_WAYLAND_CONNECT,
+            DaemonError::LayerShellUnsupported(_) => EXIT_LAYER_SHELL_UNSUPPORTED,
+            DaemonError::Config(_) => EXIT_CONFIG,
+            DaemonError::AlreadyRunning { .. } => EXIT_ALREADY_RUNNING,
+        }
+    }
+}
+
+/// Failures of the library API, by what failed
+///
+/// Display and `source` are those of the wrapped error, so `{:#}` still
+/// prints its whole context chain.
+#[derive(Debug, Error)]
+pub enum IconsError {
+    /// The display backend failed: the Wayland connection, a surface or a
+    /// buffer (or the same in a backend passed to the daemon)
+    #[error(transparent)]
+    Wayland(anyhow::Error),
+
+    /// An icon or overlay could not be rendered
+    #[error(transparent)]
+    Render(anyhow::Error),
+
+    /// The configuration could not be read, parsed or validated
+    #[error(transparent)]
+    Config(anyhow::Error),
+
+    /// Talking to the daemon over its control socket failed
+    #[error(transparent)]
+    Ipc(anyhow::Error),
+
+    /// The desktop directory or a file on it could not be read
+    #[error(transparent)]
+    Desktop(anyhow::Error),
+}
+
+impl IconsError {
+    /// The wrapped error
+    pub fn inner(&self) -> &anyhow::Error {
+        match self {
+            IconsError::Wayland(e)
+            | IconsError::Render(e)
+            | IconsError::Config(e)
+            | IconsError::Ipc(e)
+            | IconsError::Desktop(e) => e,
+        }
+    }
+}
+
+/// Result of the library API
+pub type IconsResult<T> = std::result::Result<T, IconsError>;
+
+/// Map an error returned from the daemon to a process exit code
+///
+/// Errors that are not a `DaemonError` (anywhere in the chain, including
+/// inside an `IconsError`) map to `EXIT_FAILURE`.
+pub fn exit_code_for(err: &anyhow::Error) -> u8 {
+    daemon_error(err).map(DaemonError::exit_code).unwrap_or(EXIT_FAILURE)
+}
+
+/// The first `DaemonError` in an error chain
+fn daemon_error(err: &anyhow::Error) -> Option<&DaemonError> {
+    err.chain().find_map(|e| match e.downcast_ref::<IconsError>() {
+        Some(icons_error) => daemon_error(icons_error.inner()),
+        None => e.downcast_ref::<DaemonError>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let codes = [
+            EXIT_OK,
+            EXIT_FAILURE,
+            EXIT_WAYLAND_CONNECT,
+            EXIT_LAYER_SHELL_UNSUPPORTED,
+            EXIT_CONFIG,
+            EXIT_ALREADY_RUNNING,
+        ];
+        for (i, a) in codes.iter().enumerate() {
+            for b in &codes[i + 1..] {
+                assert_ne!(a, b, "Exit codes must be unique");
+            }
+        }
+    }
+
+    #[test]
+    fn test_exit_code_for_daemon_error() {
+        let err: anyhow::Error = DaemonError::Config(anyhow::anyhow!("bad toml")).into();
+        assert_eq!(exit_code_for(&err), EXIT_CONFIG);
+
+        let err: anyhow::Error =
+            DaemonError::LayerShellUnsupported(anyhow::anyhow!("missing global")).into();
+        assert_eq!(exit_code_for(&err), EXIT_LAYER_SHELL_UNSUPPORTED);
+    }
+
+    #[test]
+    fn test_exit_code_for_wrapped_daemon_error() {
+        let err = anyhow::Error::from(DaemonError::WaylandConnect(anyhow::anyhow!("refused")))
+            .context("Failed to initialize daemon");
+        assert_eq!(exit_code_for(&err), EXIT_WAYLAND_CONNECT);
+    }
+
+    #[test]
+    fn test_exit_code_for_daemon_error_in_icons_error() {
+        let config_error = anyhow::Error::from(DaemonError::Config(anyhow::anyhow!("bad toml")))
+            .context("Failed to load /tmp/config.toml");
+        let err = anyhow::Error::from(IconsError::Config(config_error));
+        assert_eq!(exit_code_for(&err), EXIT_CONFIG);
+    }
+
+    #[test]
+    fn test_icons_error_keeps_chain() {
+        let cause = anyhow::anyhow!("no pixmap").context("Failed to render a.txt");
+        let err = IconsError::Render(cause);
+        assert_eq!(err.to_string(), "Failed to render a.txt");
+        assert_eq!(format!("{:#}", err), "Failed to render a.txt: no pixmap");
+        let source = std::error::Error::source(&err).expect("The cause is the source");
+        assert_eq!(source.to_string(), "no pixmap");
+    }
+
+    #[test]
+    fn test_already_running_message() {
+        let err = DaemonError::AlreadyRunning {
+            pid: Some(42),
+            lock_path: PathBuf::from("/run/user/1000/cvh-icons.lock"),
+        };
+        assert_eq!(err.exit_code(), EXIT_ALREADY_RUNNING);
+        assert!(err.to_string().contains("pid 42"));
+    }
+
+    #[test]
+    fn test_exit_code_for_other_error() {
+        let err = anyhow::anyhow!("something else");
+        assert_eq!(exit_code_for(&err), EXIT_FAILURE);
+    }
+}
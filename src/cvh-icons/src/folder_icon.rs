@@ -0,0 +1,118 @@
+//! Custom folder icons from `.directory` files
+//!
+//! Following the convention file managers use, a folder may hold a
+//! `.directory` file whose `[Desktop Entry]` group sets `Icon=` to either an
+//! icon theme name (`folder-music`) or an image path, absolute or relative
+//! to the folder (`cover.png`).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the file a folder's custom icon is set in
+pub const DIRECTORY_FILE: &str = ".directory";
+
+/// Image formats an `Icon=` value may name by extension
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "svg", "jpg", "jpeg", "ico", "xpm"];
+
+/// Icon a folder asks to be shown with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FolderIcon {
+    /// Image file to draw
+    File(PathBuf),
+    /// Name to look up in the icon theme
+    Named(String),
+}
+
+/// The custom icon set in `folder`'s `.directory` file, if any
+pub fn read(folder: &Path) -> Option<FolderIcon> {
+    let contents = fs::read_to_string(folder.join(DIRECTORY_FILE)).ok()?;
+    parse(&contents, folder)
+}
+
+/// The `Icon=` entry of a `.directory` file's `[Desktop Entry]` group
+///
+/// Relative paths are resolved against `folder`. A value without a slash or
+/// image extension is an icon name.
+pub fn parse(contents: &str, folder: &Path) -> Option<FolderIcon> {
+    let mut in_entry = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        // Localized keys (Icon[de]=) are not the plain icon
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if key.trim() != "Icon" {
+            continue;
+        }
+
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        let has_image_extension = Path::new(value)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        return Some(if value.contains('/') || has_image_extension {
+            FolderIcon::File(folder.join(value))
+        } else {
+            FolderIcon::Named(value.to_string())
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_named_icon() {
+        let contents = "[Desktop Entry]\nIcon[de]=ordner\nIcon=folder-music\n";
+        assert_eq!(
+            parse(contents, Path::new("/d/Music")),
+            Some(FolderIcon::Named("folder-music".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_icon_paths() {
+        let folder = Path::new("/d/Photos");
+        assert_eq!(
+            parse("[Desktop Entry]\nIcon=cover.PNG\n", folder),
+            Some(FolderIcon::File(PathBuf::from("/d/Photos/cover.PNG")))
+        );
+        assert_eq!(
+            parse("[Desktop Entry]\nIcon=./icons/star\n", folder),
+            Some(FolderIcon::File(PathBuf::from("/d/Photos/./icons/star")))
+        );
+        assert_eq!(
+            parse("[Desktop Entry]\nIcon=/usr/share/pixmaps/x.svg\n", folder),
+            Some(FolderIcon::File(PathBuf::from("/usr/share/pixmaps/x.svg")))
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_other_groups_and_blank_icons() {
+        let folder = Path::new("/d/x");
+        assert_eq!(parse("[Dolphin]\nIcon=folder-red\n", folder), None);
+        assert_eq!(parse("[Desktop Entry]\nIcon=\n", folder), None);
+        assert_eq!(parse("", folder), None);
+    }
+
+    #[test]
+    fn test_read_directory_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(read(temp_dir.path()), None);
+
+        let contents = "[Desktop Entry]\nIcon=folder-blue\n";
+        fs::write(temp_dir.path().join(DIRECTORY_FILE), contents).unwrap();
+        assert_eq!(read(temp_dir.path()), Some(FolderIcon::Named("folder-blue".to_string())));
+    }
+}
@@ -0,0 +1,145 @@
+//! Icon positions imported from GNOME's desktop-icons extension
+//!
+//! The desktop-icons-ng extension (DING) keeps each icon's position in GIO
+//! file metadata on the file itself: `metadata::desktop-icon-position`, or
+//! `metadata::nautilus-icon-position` in older versions, holding `x,y` in
+//! pixels. Its grid cells are sized by the `icon-size` key of the
+//! `org.gnome.shell.extensions.ding` schema. Dividing the positions by that
+//! cell size gives the cells of this daemon's grid, as a `Layout` the
+//! `import-gnome-layout` command hands to the running daemon.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use tracing::{debug, info};
+
+use crate::placements::{Cell, Layout};
+
+/// Schema of the desktop-icons extension's settings
+const DING_SCHEMA: &str = "org.gnome.shell.extensions.ding";
+
+/// Metadata attributes holding an icon's position, newest first
+const POSITION_ATTRS: [&str; 2] =
+    ["metadata::desktop-icon-position", "metadata::nautilus-icon-position"];
+
+/// Width and height of the extension's grid cells for an `icon-size` value
+///
+/// Unknown values, and a missing schema, get the `standard` size's cells.
+pub fn ding_cell_size(icon_size: &str) -> (u32, u32) {
+    match icon_size.trim().trim_matches('\'') {
+        "tiny" => (70, 70),
+        "small" => (90, 90),
+        "large" => (130, 138),
+        _ => (120, 106),
+    }
+}
+
+/// Pixel position in an `x,y` attribute value
+///
+/// Positions left of or above the desktop are treated as unset.
+pub fn parse_position(value: &str) -> Option<(u32, u32)> {
+    let (x, y) = value.trim().split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Position stored in the output of `gio info -a`, preferring the newest attribute
+pub fn position_in_gio_info(output: &str) -> Option<(u32, u32)> {
+    POSITION_ATTRS.iter().find_map(|attr| {
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(attr)?.strip_prefix(':'))
+            .and_then(parse_position)
+    })
+}
+
+/// Cell of the grid with `cell_size` cells nearest to pixel position `(x, y)`
+pub fn position_to_cell((x, y): (u32, u32), (width, height): (u32, u32)) -> Cell {
+    let (width, height) = (width.max(1), height.max(1));
+    Cell { col: (x + width / 2) / width, row: (y + height / 2) / height }
+}
+
+/// Current value of a key of the extension's schema, if it is installed
+fn ding_setting(key: &str) -> Option<String> {
+    let output = Command::new("gsettings").args(["get", DING_SCHEMA, key]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Position GNOME stored for `path`, if any
+fn stored_position(path: &Path) -> Result<Option<(u32, u32)>> {
+    let attrs = POSITION_ATTRS.join(",");
+    let output = match Command::new("gio").args(["info", "-a", &attrs]).arg(path).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!("The gio tool is needed to read GNOME's icon positions")
+        }
+        Err(e) => return Err(e).context("Failed to run gio"),
+    };
+    if !output.status.success() {
+        debug!("gio info failed for {}", path.display());
+        return Ok(None);
+    }
+    Ok(position_in_gio_info(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Layout of the icons in `desktop_dir` as GNOME last arranged them
+///
+/// Files GNOME never placed are left out. Without the extension's schema
+/// its default cell size is assumed.
+pub fn read_layout(desktop_dir: &Path) -> Result<Layout> {
+    let icon_size = ding_setting("icon-size").unwrap_or_else(|| {
+        info!("No {} settings, assuming the standard icon size", DING_SCHEMA);
+        String::new()
+    });
+    let cell_size = ding_cell_size(&icon_size);
+
+    let entries = fs::read_dir(desktop_dir)
+        .with_context(|| format!("Failed to read {}", desktop_dir.display()))?;
+    let mut icons = BTreeMap::new();
+    for entry in entries.flatten() {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if let Some(position) = stored_position(&entry.path())? {
+            icons.insert(name, position_to_cell(position, cell_size));
+        }
+    }
+    Ok(Layout::new(icons))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_in_gio_info() {
+        let output = "uri: file:///home/u/Desktop/a.txt\n\
+                      attributes:\n  \
+                      metadata::nautilus-icon-position: 10,20\n  \
+                      metadata::desktop-icon-position: 240,106\n";
+        assert_eq!(position_in_gio_info(output), Some((240, 106)));
+
+        let old = "attributes:\n  metadata::nautilus-icon-position: 10,20\n";
+        assert_eq!(position_in_gio_info(old), Some((10, 20)));
+
+        assert_eq!(position_in_gio_info("attributes:\n"), None);
+        assert_eq!(parse_position("-5,20"), None);
+        assert_eq!(parse_position("garbage"), None);
+    }
+
+    #[test]
+    fn test_positions_map_to_nearest_cell() {
+        let cell_size = ding_cell_size("'standard'");
+        assert_eq!(cell_size, (120, 106));
+        assert_eq!(position_to_cell((0, 0), cell_size), Cell { col: 0, row: 0 });
+        assert_eq!(position_to_cell((240, 106), cell_size), Cell { col: 2, row: 1 });
+        // Margins around the grid don't push icons into the next cell
+        assert_eq!(position_to_cell((250, 120), cell_size), Cell { col: 2, row: 1 });
+        assert_eq!(ding_cell_size("'large'"), (130, 138));
+    }
+}
@@ -2,22 +2,189 @@
 //!
 //! Each icon represents a file or folder on the desktop.
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, error, warn};
 
-use crate::config::Config;
+use crate::backend::MouseButton;
+use crate::clipboard;
+use crate::config::{Config, Corner, GridFlow};
+use crate::folder_icon::{self, FolderIcon};
 use crate::ipc::{
-    IconMetadata, IconType as IpcIconType, Position, PositionInput, RenderContext, Request,
-    Response,
+    IconMetadata, IconType as IpcIconType, Overlay, Position, PositionInput, RenderContext,
+    IconEvent, Request, Response, WidgetAction,
 };
-use crate::lua::{DrawCommand, LuaProcess};
+use crate::lua::respawn::{ExitVerdict, RespawnGuard};
+use crate::lua::{DrawCommand, LuaPool, LuaProcess, WidgetProcess};
+use crate::mounts::{self, FileState, VolumeUsage};
+use crate::renderer::{compute_damage, DamageRect, IconRenderer};
 use crate::sandbox::SandboxOptions;
+use crate::trash;
+use crate::xattrs::FileNotes;
 
 /// Timeout for IPC requests to Lua process
 const IPC_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// Shortest interval a widget is rendered again at; every render is a round
+/// trip to its Lua process
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Margin around the default icon grid
+pub const GRID_MARGIN: u32 = 20;
+
+/// Columns and rows of a grid of `cell_width` x `cell_height` cells
+///
+/// Zero-size or tiny areas (and zero-size cells) give a 1x1 grid instead of
+/// dividing by zero.
+pub fn grid_size(width: u32, height: u32, cell_width: u32, cell_height: u32) -> (u32, u32) {
+    let columns = width.saturating_sub(2 * GRID_MARGIN) / cell_width.max(1);
+    let rows = height.saturating_sub(2 * GRID_MARGIN) / cell_height.max(1);
+    (columns.max(1), rows.max(1))
+}
+
+/// Top-left corner of the cell at layout index `index`, relative to the grid area
+///
+/// Cells are laid out in rows of `columns`, inside `GRID_MARGIN`.
+pub fn cell_origin(index: u32, columns: u32, cell_width: u32, cell_height: u32) -> (i32, i32) {
+    let columns = columns.max(1);
+    let margin = GRID_MARGIN as i32;
+    let col = (index % columns) as i32;
+    let row = (index / columns) as i32;
+    (margin + col * cell_width as i32, margin + row * cell_height as i32)
+}
+
+/// `(x, y)` of a `width` x `height` box in an `area_width` x `area_height`
+/// area, measured from `corner` of the area instead of its top-left
+///
+/// The box is mirrored across the area for the right and bottom corners, so
+/// a grid laid out from the top-left fills from `corner` instead. Mirroring
+/// twice gives back the original position.
+pub fn mirror_to_corner(
+    corner: Corner,
+    (x, y): (i32, i32),
+    (width, height): (u32, u32),
+    (area_width, area_height): (u32, u32),
+) -> (i32, i32) {
+    let x = match corner.is_right() {
+        true => area_width as i32 - x - width as i32,
+        false => x,
+    };
+    let y = match corner.is_bottom() {
+        true => area_height as i32 - y - height as i32,
+        false => y,
+    };
+    (x, y)
+}
+
+/// Cell `(col, row)` containing point `(x, y)` relative to the grid area
+///
+/// The inverse of `cell_origin`. Points in the margin above or left of the
+/// grid have no cell; the caller checks the grid's far edges.
+pub fn cell_at(x: i32, y: i32, cell_width: u32, cell_height: u32) -> Option<(u32, u32)> {
+    let margin = GRID_MARGIN as i32;
+    let (x, y) = (u32::try_from(x - margin).ok()?, u32::try_from(y - margin).ok()?);
+    Some((x / cell_width.max(1), y / cell_height.max(1)))
+}
+
+/// Layout index of the `n`th cell filled in a `columns` x `rows` grid
+///
+/// Layout indices are row-major whatever the flow. Past the last cell the
+/// grid carries on in rows below the area, as with `GridFlow::Rows`.
+pub fn flow_slot(n: u32, columns: u32, rows: u32, flow: GridFlow) -> u32 {
+    let (columns, rows) = (columns.max(1), rows.max(1));
+    match flow {
+        GridFlow::Columns if n < columns * rows => (n % rows) * columns + n / rows,
+        _ => n,
+    }
+}
+
+/// Layout index of the `n`th cell filled, passing over the `blocked` ones
+///
+/// Like `flow_slot`, the grid carries on in rows below the area once its
+/// free cells run out.
+pub fn free_flow_slot(
+    n: u32,
+    columns: u32,
+    rows: u32,
+    flow: GridFlow,
+    blocked: &HashSet<u32>,
+) -> u32 {
+    if blocked.is_empty() {
+        return flow_slot(n, columns, rows, flow);
+    }
+    let capacity = columns.max(1) * rows.max(1);
+    let free = capacity - blocked.iter().filter(|&&slot| slot < capacity).count() as u32;
+    if n >= free {
+        return capacity + (n - free);
+    }
+    (0..capacity)
+        .map(|m| flow_slot(m, columns, rows, flow))
+        .filter(|slot| !blocked.contains(slot))
+        .nth(n as usize)
+        .unwrap_or(capacity)
+}
+
+/// Position in fill order of the cell at layout index `slot`; the inverse of `flow_slot`
+pub fn flow_rank(slot: u32, columns: u32, rows: u32, flow: GridFlow) -> u32 {
+    let (columns, rows) = (columns.max(1), rows.max(1));
+    match flow {
+        GridFlow::Columns if slot < columns * rows => (slot % columns) * rows + slot / columns,
+        _ => slot,
+    }
+}
+
+/// Whether `path`, with symlinks resolved, is inside `root`
+///
+/// Dangling links and unreadable paths count as outside.
+fn stays_within(path: &Path, root: &Path) -> bool {
+    match (path.canonicalize(), root.canonicalize()) {
+        (Ok(path), Ok(root)) => path.starts_with(root),
+        _ => false,
+    }
+}
+
+/// `bytes` as a short human-readable size, e.g. "1.5 MB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Colors of a volume icon's usage bar, turning red when nearly full
+const VOLUME_BAR_COLOR: &str = "#3584e4";
+const VOLUME_FULL_COLOR: &str = "#e01b24";
+const VOLUME_BAR_BACKGROUND: &str = "#00000066";
+const VOLUME_FULL_FRACTION: f32 = 0.9;
+
+/// Color of the file count drawn over a volume icon
+const VOLUME_TEXT_COLOR: &str = "#ffffff";
+
+/// Number of items shown in a folder's content peek
+const FOLDER_PEEK_ITEMS: usize = 3;
+
+/// Maximum directory entries read when choosing peek items
+const FOLDER_PEEK_SCAN_LIMIT: usize = 64;
+
+/// What a natively rendered glyph layer depends on, for a given renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GlyphKey {
+    icon_name: &'static str,
+    selected: bool,
+    hovered: bool,
+    focused: bool,
+}
+
 /// Represents a desktop icon
 #[allow(dead_code)]
 pub struct DesktopIcon {
@@ -27,21 +194,36 @@ pub struct DesktopIcon {
     /// Display name
     name: String,
 
-    /// Icon type
+    /// Label shown instead of the name while another icon has the same name
+    label: Option<String>,
+
+    /// Icon type; a symlink's is that of its target
     icon_type: IconType,
 
+    /// Whether the icon's path is a symlink, and if its target exists
+    link: Option<LinkState>,
+
     /// Position on desktop (grid coordinates)
     grid_x: u32,
     grid_y: u32,
 
+    /// Index of the icon in grid layout order
+    layout_index: u32,
+
     /// Whether icon is selected
     selected: bool,
 
     /// Whether icon is hovered
     hovered: bool,
 
+    /// Whether icon has the keyboard focus
+    focused: bool,
+
     /// Lua process for custom scripts (sandboxed)
-    lua_process: Option<LuaProcess>,
+    lua_process: Option<WidgetProcess>,
+
+    /// Shared workers the widget runs in, instead of a process of its own
+    lua_pool: Option<LuaPool>,
 
     /// Path to the IPC handler script
     handler_path: Option<PathBuf>,
@@ -57,13 +239,181 @@ pub struct DesktopIcon {
 
     /// Sandbox options for Lua process
     sandbox_options: SandboxOptions,
+
+    /// Crash-loop protection for respawning the Lua process
+    respawn_guard: RespawnGuard,
+
+    /// Whether the widget died and is not running again yet
+    widget_failed: bool,
+
+    /// Why no widget was started for the icon, or Ok if one was or none is needed
+    script_status: WidgetStatus,
+
+    /// RGBA pixels last presented on the icon's surface (for damage tracking)
+    presented_frame: Option<Vec<u8>>,
+
+    /// When the attention flash started and how long it lasts, while flashing
+    flash: Option<(Instant, Duration)>,
+
+    /// Cached content peek for folders (None if empty or unreadable)
+    folder_peek: Option<tiny_skia::Pixmap>,
+
+    /// Whether `folder_peek` reflects the folder's current contents
+    folder_peek_valid: bool,
+
+    /// Thumbnail of an image file, drawn instead of its glyph once decoded
+    thumbnail: Option<tiny_skia::Pixmap>,
+
+    /// Glyph layer of the last native render, and what it was rendered for
+    glyph_layer: Option<(GlyphKey, tiny_skia::Pixmap)>,
+
+    /// Corner the icon is pinned to instead of taking a grid cell
+    pinned_corner: Option<Corner>,
+
+    /// Whether the trash holds anything (Trash icons only)
+    trash_full: bool,
+
+    /// Mount point the file lives on, if not the root filesystem
+    mount_point: Option<PathBuf>,
+
+    /// Consecutive update checks that could not find the file
+    failed_checks: u32,
+
+    /// Whether the file is on a filesystem that went away (shown dimmed)
+    unavailable: bool,
+
+    /// Badge the widget asked to draw over the icon
+    overlay: Option<Overlay>,
+
+    /// Space on the volume (volume icons only; None if it could not be read)
+    volume_usage: Option<VolumeUsage>,
+
+    /// Entries at the top of the volume (volume icons only)
+    file_count: Option<usize>,
+
+    /// Context-menu entries the widget advertised when it last started
+    widget_actions: Vec<WidgetAction>,
+
+    /// How often the widget is rendered again regardless of changes
+    refresh_interval: Option<Duration>,
+
+    /// When the widget is next rendered again (with `refresh_interval`)
+    next_refresh: Option<Instant>,
+
+    /// Metadata of the file when the icon was created (None if it could not be read)
+    stamp: Option<FileStamp>,
+
+    /// Tags and comment the user attached to the file
+    notes: FileNotes,
+
+    /// Icon set in the folder's `.directory` file (folders only)
+    custom_icon: Option<FolderIcon>,
+
+    /// Name being typed over the label, while the icon is renamed in place
+    rename_field: Option<RenameField>,
+}
+
+/// Text field shown in place of an icon's label while it is renamed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameField {
+    /// Name typed so far
+    text: String,
+    /// Cursor position, in characters from the start
+    cursor: usize,
+    /// Whether the name was refused, shown until it is edited
+    invalid: bool,
+}
+
+impl RenameField {
+    /// A field holding `name`, with the cursor at its end
+    pub fn new(name: &str) -> Self {
+        Self { text: name.to_string(), cursor: name.chars().count(), invalid: false }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        self.invalid
+    }
+
+    /// Mark the name refused, until the next edit
+    pub fn set_invalid(&mut self) {
+        self.invalid = true;
+    }
+
+    /// Type `c` at the cursor
+    pub fn insert(&mut self, c: char) {
+        let at = self.byte_offset(self.cursor);
+        self.text.insert(at, c);
+        self.cursor += 1;
+        self.invalid = false;
+    }
+
+    /// Erase the character before the cursor (Backspace)
+    pub fn erase_back(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.cursor -= 1;
+        self.text.remove(self.byte_offset(self.cursor));
+        self.invalid = false;
+    }
+
+    /// Erase the character after the cursor (Delete)
+    pub fn erase_forward(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            self.text.remove(self.byte_offset(self.cursor));
+            self.invalid = false;
+        }
+    }
+
+    /// Move the cursor by `chars` characters, staying within the text
+    pub fn move_cursor(&mut self, chars: isize) {
+        let len = self.text.chars().count();
+        self.cursor = self.cursor.saturating_add_signed(chars).min(len);
+    }
+
+    /// Move the cursor to the start or the end of the text
+    pub fn move_cursor_to_end(&mut self, end: bool) {
+        self.cursor = if end { self.text.chars().count() } else { 0 };
+    }
+
+    fn byte_offset(&self, chars: usize) -> usize {
+        self.text.char_indices().nth(chars).map_or(self.text.len(), |(i, _)| i)
+    }
+}
+
+/// File metadata an icon depends on, to tell real changes from spurious events
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    permissions: std::fs::Permissions,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = path.metadata().ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            permissions: metadata.permissions(),
+        })
+    }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum IconType {
     File,
     Folder,
+    /// Symlink that leads nowhere; working links take their target's type
     Symlink,
     Executable,
     Image,
@@ -71,9 +421,52 @@ pub enum IconType {
     Archive,
     Video,
     Audio,
+    /// Built-in icon for the XDG home trash, not backed by a desktop entry
+    Trash,
+    /// Built-in icon for a mounted volume, at its mount point
+    Volume,
+    /// Named pipe
+    Fifo,
+    /// Unix domain socket
+    Socket,
+    BlockDevice,
+    CharDevice,
     Unknown,
 }
 
+/// Whether an icon's widget runs, and why not if it should
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WidgetStatus {
+    /// The widget runs, or the icon's type has none
+    Ok,
+    /// No IPC handler in `script_dirs`, so no widget can run
+    NoHandler,
+    /// No widget script for the icon's type in `script_dirs`
+    NoWidget,
+    /// The widget died and is not running again yet, or for good
+    LuaCrashed,
+}
+
+/// What an icon's symlink leads to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// An existing file, whose type the icon takes
+    Resolved,
+    /// Nothing: the target is missing or the link loops
+    Broken,
+}
+
+impl IconType {
+    /// Whether this is a FIFO, socket or device node
+    ///
+    /// Reading one can block or never end, so they get no widget and
+    /// cannot be opened.
+    pub fn is_special(self) -> bool {
+        matches!(self, IconType::Fifo | IconType::Socket | IconType::BlockDevice | IconType::CharDevice)
+    }
+}
+
 #[allow(dead_code)]
 impl DesktopIcon {
     /// Create a new desktop icon
@@ -85,44 +478,445 @@ impl DesktopIcon {
             .unwrap_or_else(|| "Unknown".to_string());
 
         let icon_type = Self::determine_type(path);
-
-        // Build sandbox options from config
-        let mut sandbox_options = SandboxOptions::default();
-        sandbox_options.allow_network = config.sandbox.allow_network;
-        for p in &config.sandbox.read_only_paths {
-            sandbox_options.read_only_paths.push(p.clone());
-        }
-        for p in &config.sandbox.read_write_paths {
-            sandbox_options.read_write_paths.push(p.clone());
-        }
+        let link = path.is_symlink().then_some(match icon_type {
+            IconType::Symlink => LinkState::Broken,
+            _ => LinkState::Resolved,
+        });
+        let sandbox_options = sandbox_options(config);
 
         Ok(Self {
             path: path.to_path_buf(),
             name,
+            label: None,
             icon_type,
+            link,
             grid_x: 0,
             grid_y: 0,
+            layout_index: 0,
             selected: false,
             hovered: false,
+            focused: false,
             lua_process: None,
+            lua_pool: None,
             handler_path: None,
             script_path: None,
             cached_draw_commands: Vec::new(),
             size: config.icon_size,
             sandbox_options,
+            respawn_guard: RespawnGuard::new(Duration::from_millis(config.lua_min_uptime_ms)),
+            widget_failed: false,
+            script_status: WidgetStatus::Ok,
+            presented_frame: None,
+            flash: None,
+            folder_peek: None,
+            folder_peek_valid: false,
+            thumbnail: None,
+            glyph_layer: None,
+            pinned_corner: None,
+            trash_full: false,
+            mount_point: None,
+            failed_checks: 0,
+            unavailable: false,
+            overlay: None,
+            volume_usage: None,
+            file_count: None,
+            widget_actions: Vec::new(),
+            refresh_interval: None,
+            next_refresh: None,
+            stamp: FileStamp::read(path),
+            notes: FileNotes::read(path),
+            // A linked folder may be anywhere, so it isn't read through the link
+            custom_icon: match icon_type == IconType::Folder && link.is_none() {
+                true => folder_icon::read(path),
+                false => None,
+            },
+            rename_field: None,
         })
     }
 
+    /// Create the built-in Trash icon for the trash at `trash_dir`
+    pub fn new_trash(trash_dir: &Path, config: &Config) -> Result<Self> {
+        let mut icon = Self::new(trash_dir, config)?;
+        icon.name = "Trash".to_string();
+        icon.icon_type = IconType::Trash;
+        icon.pinned_corner = config.trash_corner;
+        icon.trash_full = !trash::is_empty(trash_dir);
+        Ok(icon)
+    }
+
+    /// Create the icon for the volume mounted at `mount_point`
+    ///
+    /// The icon is named after the mount point, which is the volume label
+    /// for removable media.
+    pub fn new_volume(mount_point: &Path, config: &Config) -> Result<Self> {
+        let mut icon = Self::new(mount_point, config)?;
+        icon.icon_type = IconType::Volume;
+        icon.refresh_volume();
+        Ok(icon)
+    }
+
+    /// Re-read a volume's free space and file count, returning true if either changed
+    pub fn refresh_volume(&mut self) -> bool {
+        if self.icon_type != IconType::Volume {
+            return false;
+        }
+        let usage = mounts::usage(&self.path);
+        let file_count = std::fs::read_dir(&self.path).ok().map(|entries| entries.count());
+        let changed = usage != self.volume_usage || file_count != self.file_count;
+        self.volume_usage = usage;
+        self.file_count = file_count;
+        changed
+    }
+
+    /// Space on the volume, if this is a readable volume icon
+    pub fn volume_usage(&self) -> Option<VolumeUsage> {
+        self.volume_usage
+    }
+
+    /// Number of entries at the top of the volume, if readable
+    pub fn file_count(&self) -> Option<usize> {
+        self.file_count
+    }
+
+    /// Usage bar and file count drawn over a volume icon's glyph
+    ///
+    /// Whatever could not be read (e.g. permission denied) is left out, so
+    /// other icons and unreadable volumes get no commands.
+    pub fn volume_commands(&self, width: u32, glyph_size: u32) -> Vec<DrawCommand> {
+        let mut commands = Vec::new();
+        if self.icon_type != IconType::Volume {
+            return commands;
+        }
+
+        let margin = (glyph_size as f32 / 8.0).max(2.0);
+        let bar_height = (glyph_size as f32 / 12.0).max(3.0);
+        let bar_y = glyph_size as f32 - bar_height - 2.0;
+        let text_size = (glyph_size as f32 / 6.0).max(7.0);
+
+        if let Some(count) = self.file_count {
+            commands.push(DrawCommand::Text {
+                text: format!("{} {}", count, if count == 1 { "item" } else { "items" }),
+                x: width as f32 / 2.0,
+                y: bar_y - text_size - 1.0,
+                size: text_size,
+                color: VOLUME_TEXT_COLOR.to_string(),
+                align: "center".to_string(),
+            });
+        }
+
+        if let Some(usage) = self.volume_usage {
+            let fraction = usage.used_fraction();
+            let color = if fraction >= VOLUME_FULL_FRACTION { VOLUME_FULL_COLOR } else { VOLUME_BAR_COLOR };
+            commands.push(DrawCommand::Bar {
+                x: margin,
+                y: bar_y,
+                w: (width as f32 - margin * 2.0).max(1.0),
+                h: bar_height,
+                fraction,
+                color: color.to_string(),
+                background: VOLUME_BAR_BACKGROUND.to_string(),
+            });
+        }
+
+        commands
+    }
+
+    /// Regions of a newly rendered frame that differ from the presented one
+    ///
+    /// `label_top` splits the glyph area from the label area so a label-only
+    /// change damages just the label. Returns full damage if nothing has been
+    /// presented yet, and nothing if the frame is unchanged.
+    pub fn frame_damage(&self, pixels: &[u8], width: u32, height: u32, label_top: u32) -> Vec<DamageRect> {
+        match self.presented_frame {
+            Some(ref prev) => compute_damage(prev, pixels, width, height, label_top),
+            None => vec![DamageRect::full(width, height)],
+        }
+    }
+
+    /// Record the frame that was just presented on the icon's surface
+    pub fn set_presented_frame(&mut self, pixels: &[u8]) {
+        match self.presented_frame {
+            Some(ref mut frame) if frame.len() == pixels.len() => frame.copy_from_slice(pixels),
+            _ => self.presented_frame = Some(pixels.to_vec()),
+        }
+    }
+
+    /// Whether a frame has been presented on the icon's surface yet
+    pub fn has_presented_frame(&self) -> bool {
+        self.presented_frame.is_some()
+    }
+
+    /// Forget the presented frame so the next render uploads in full
+    pub fn clear_presented_frame(&mut self) {
+        self.presented_frame = None;
+    }
+
+    /// Highlight the icon for `duration` from `now` to draw attention to it
+    ///
+    /// Flashing again restarts the flash.
+    pub fn start_flash(&mut self, now: Instant, duration: Duration) {
+        self.flash = Some((now, duration));
+    }
+
+    /// Whether a flash started and has not been finished yet
+    pub fn is_flashing(&self) -> bool {
+        self.flash.is_some()
+    }
+
+    /// How far through its flash the icon is at `now`, from 0 to 1
+    pub fn flash_progress(&self, now: Instant) -> Option<f32> {
+        let (started, duration) = self.flash?;
+        let elapsed = now.saturating_duration_since(started);
+        Some((elapsed.as_secs_f32() / duration.as_secs_f32().max(f32::EPSILON)).min(1.0))
+    }
+
+    /// End the flash if it has run its course by `now`
+    ///
+    /// Returns whether it ended, so the icon is drawn once more without it.
+    pub fn finish_flash(&mut self, now: Instant) -> bool {
+        let done = self.flash_progress(now).is_some_and(|progress| progress >= 1.0);
+        if done {
+            self.flash = None;
+        }
+        done
+    }
+
+    /// Take the presented frame, e.g. to keep showing it while the icon fades out
+    pub fn take_presented_frame(&mut self) -> Option<Vec<u8>> {
+        self.presented_frame.take()
+    }
+
+    /// Content peek to composite over a folder glyph
+    ///
+    /// Rendered on first use and cached until `invalidate_folder_peek`.
+    /// Returns None for non-folders and for empty or unreadable folders.
+    pub fn folder_peek(&mut self, renderer: &IconRenderer, size: u32) -> Option<&tiny_skia::Pixmap> {
+        if self.icon_type != IconType::Folder {
+            return None;
+        }
+
+        let stale = self
+            .folder_peek
+            .as_ref()
+            .is_some_and(|p| p.width() != size);
+        if !self.folder_peek_valid || stale {
+            let items = self.folder_peek_items();
+            self.folder_peek = renderer.render_folder_peek(&items, size);
+            self.folder_peek_valid = true;
+        }
+
+        self.folder_peek.as_ref()
+    }
+
+    /// Change the icon size, dropping everything rendered at the old size
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+        self.cached_draw_commands.clear();
+        self.presented_frame = None;
+        self.folder_peek_valid = false;
+        self.thumbnail = None;
+        self.glyph_layer = None;
+    }
+
+    /// Cached glyph layer, if the glyph has not changed since it was rendered
+    pub fn glyph_layer(&self) -> Option<&tiny_skia::Pixmap> {
+        self.glyph_layer
+            .as_ref()
+            .filter(|(key, _)| *key == self.glyph_key())
+            .map(|(_, pixmap)| pixmap)
+    }
+
+    /// Cache the glyph layer rendered for the icon's current state
+    pub fn set_glyph_layer(&mut self, pixmap: tiny_skia::Pixmap) {
+        self.glyph_layer = Some((self.glyph_key(), pixmap));
+    }
+
+    fn glyph_key(&self) -> GlyphKey {
+        GlyphKey {
+            icon_name: self.icon_name(),
+            selected: self.selected,
+            hovered: self.hovered,
+            focused: self.focused,
+        }
+    }
+
+    /// Follow the file being renamed to `path`, keeping everything rendered
+    ///
+    /// Returns false without changing anything if the new name gives the
+    /// file another type (and so another glyph and widget script), or for
+    /// built-in icons, which are not named after their path.
+    pub fn rename(&mut self, path: &Path) -> bool {
+        if matches!(self.icon_type, IconType::Trash | IconType::Volume)
+            || Self::determine_type(path) != self.icon_type
+        {
+            return false;
+        }
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        self.name = name.to_string();
+        self.path = path.to_path_buf();
+        true
+    }
+
+    /// Whether the file's type or metadata differ from when the icon was created
+    ///
+    /// Always false for built-in icons, which are not drawn from their path.
+    pub fn changed_on_disk(&self) -> bool {
+        if matches!(self.icon_type, IconType::Trash | IconType::Volume) {
+            return false;
+        }
+        Self::determine_type(&self.path) != self.icon_type
+            || FileStamp::read(&self.path) != self.stamp
+    }
+
+    /// Tags and comment the user attached to the file
+    pub fn notes(&self) -> &FileNotes {
+        &self.notes
+    }
+
+    /// Read the file's tags and comment again, e.g. after its metadata changed
+    pub fn refresh_notes(&mut self) {
+        self.notes = FileNotes::read(&self.path);
+    }
+
+    /// Text of the icon's hover tooltip, one entry per line
+    ///
+    /// The name, type and size, followed by the user's tags and comment if
+    /// it has any.
+    pub fn tooltip_lines(&self) -> Vec<String> {
+        let mut lines = vec![self.name.clone()];
+        match self.get_mime_type() {
+            Some(mime) => lines.push(mime),
+            None if self.icon_type == IconType::Folder => lines.push("Folder".to_string()),
+            None => {}
+        }
+        if !matches!(self.icon_type, IconType::Folder | IconType::Trash | IconType::Volume) {
+            if let Some(size) = self.get_file_size() {
+                lines.push(format_size(size));
+            }
+        }
+        if !self.notes.tags.is_empty() {
+            lines.push(format!("Tags: {}", self.notes.tags.join(", ")));
+        }
+        if let Some(ref comment) = self.notes.comment {
+            lines.push(comment.clone());
+        }
+        lines
+    }
+
+    /// Drop the cached glyph and folder peek so the next render redraws them
+    pub fn invalidate_render(&mut self) {
+        self.glyph_layer = None;
+        self.folder_peek_valid = false;
+    }
+
+    /// Icon the folder's `.directory` file asks for, drawn instead of the
+    /// generic folder glyph
+    pub fn custom_icon(&self) -> Option<&FolderIcon> {
+        self.custom_icon.as_ref()
+    }
+
+    /// Re-read the folder's `.directory` file, returning whether its icon changed
+    pub fn refresh_custom_icon(&mut self) -> bool {
+        if self.icon_type != IconType::Folder || self.link.is_some() {
+            return false;
+        }
+        let custom_icon = folder_icon::read(&self.path);
+        if custom_icon == self.custom_icon {
+            return false;
+        }
+        self.custom_icon = custom_icon;
+        self.invalidate_render();
+        true
+    }
+
+    /// Thumbnail drawn instead of the glyph, for images that have one
+    pub fn thumbnail(&self) -> Option<&tiny_skia::Pixmap> {
+        self.thumbnail.as_ref()
+    }
+
+    /// Show `thumbnail` instead of the glyph, or go back to the glyph with None
+    pub fn set_thumbnail(&mut self, thumbnail: Option<tiny_skia::Pixmap>) {
+        self.thumbnail = thumbnail;
+        self.glyph_layer = None;
+    }
+
+    /// Mark the folder peek as outdated (folder contents changed)
+    pub fn invalidate_folder_peek(&mut self) {
+        self.folder_peek_valid = false;
+    }
+
+    /// Pick the items shown in the folder peek, images first
+    ///
+    /// Symlinks are only shown when they point inside the folder, so a link
+    /// to `/` or a large tree elsewhere is never read for thumbnails.
+    fn folder_peek_items(&self) -> Vec<PathBuf> {
+        // The folder may have been replaced by a symlink since the icon was created
+        if self.path.is_symlink() {
+            return Vec::new();
+        }
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Cannot read folder {} for peek: {}", self.path.display(), e);
+                return Vec::new();
+            }
+        };
+
+        let mut items: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .take(FOLDER_PEEK_SCAN_LIMIT)
+            .filter(|e| !e.file_type().is_ok_and(|t| t.is_symlink()) || stays_within(&e.path(), &self.path))
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| !n.starts_with('.'))
+            })
+            .collect();
+
+        items.sort_by_key(|p| (Self::determine_type(p) != IconType::Image, p.clone()));
+        items.truncate(FOLDER_PEEK_ITEMS);
+        items
+    }
+
     /// Determine the icon type based on the file
+    ///
+    /// Symlinks take the type of their fully resolved target, so a link to a
+    /// folder is a folder. Links that lead nowhere, being broken or looping,
+    /// are `Symlink`.
     fn determine_type(path: &Path) -> IconType {
         if path.is_symlink() {
-            return IconType::Symlink;
+            // The resolved target is never a link itself, so this recurses once
+            return match path.canonicalize() {
+                Ok(target) => Self::determine_type(&target),
+                Err(_) => IconType::Symlink,
+            };
         }
 
         if path.is_dir() {
             return IconType::Folder;
         }
 
+        // Special files are told apart by their file type bits, not their name
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if let Ok(metadata) = path.metadata() {
+                let file_type = metadata.file_type();
+                if file_type.is_fifo() {
+                    return IconType::Fifo;
+                } else if file_type.is_socket() {
+                    return IconType::Socket;
+                } else if file_type.is_block_device() {
+                    return IconType::BlockDevice;
+                } else if file_type.is_char_device() {
+                    return IconType::CharDevice;
+                }
+            }
+        }
+
         // Check extension
         if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
             match ext.to_lowercase().as_str() {
@@ -174,6 +968,13 @@ impl DesktopIcon {
             IconType::Archive => "package-x-generic",
             IconType::Video => "video-x-generic",
             IconType::Audio => "audio-x-generic",
+            IconType::Trash if self.trash_full => "user-trash-full",
+            IconType::Trash => "user-trash",
+            IconType::Volume => "drive-removable-media",
+            IconType::Fifo => "inode-fifo",
+            IconType::Socket => "inode-socket",
+            IconType::BlockDevice => "inode-blockdevice",
+            IconType::CharDevice => "inode-chardevice",
             IconType::Unknown => "unknown",
         }
     }
@@ -183,6 +984,27 @@ impl DesktopIcon {
         &self.name
     }
 
+    /// Text of the icon's label: its name, unless set with `set_label`
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Label the icon with `label` instead of its name (None for the name)
+    ///
+    /// Returns whether the label changed.
+    pub fn set_label(&mut self, label: Option<String>) -> bool {
+        if label == self.label {
+            return false;
+        }
+        self.label = label;
+        true
+    }
+
+    /// Whether the icon is a symlink, and if so whether it leads anywhere
+    pub fn link(&self) -> Option<LinkState> {
+        self.link
+    }
+
     /// Get the path
     pub fn path(&self) -> &Path {
         &self.path
@@ -204,6 +1026,42 @@ impl DesktopIcon {
         (self.grid_x, self.grid_y)
     }
 
+    /// Corner the icon is pinned to, if any
+    pub fn pinned_corner(&self) -> Option<Corner> {
+        self.pinned_corner
+    }
+
+    /// Pin the icon to a corner, or return it to the grid with `None`
+    pub fn set_pinned_corner(&mut self, corner: Option<Corner>) {
+        self.pinned_corner = corner;
+    }
+
+    /// Whether the trash holds anything (always false for other icons)
+    pub fn is_trash_full(&self) -> bool {
+        self.trash_full
+    }
+
+    /// Re-check the trash contents, returning true if the state changed
+    pub fn refresh_trash_state(&mut self) -> bool {
+        if self.icon_type != IconType::Trash {
+            return false;
+        }
+        let full = !trash::is_empty(&self.path);
+        let changed = full != self.trash_full;
+        self.trash_full = full;
+        changed
+    }
+
+    /// Set the index of the icon in grid layout order
+    pub fn set_layout_index(&mut self, index: u32) {
+        self.layout_index = index;
+    }
+
+    /// Get the index of the icon in grid layout order
+    pub fn layout_index(&self) -> u32 {
+        self.layout_index
+    }
+
     /// Set selection state
     pub fn set_selected(&mut self, selected: bool) {
         self.selected = selected;
@@ -216,12 +1074,92 @@ impl DesktopIcon {
 
     /// Update the icon (called each frame)
     pub fn update(&mut self) -> Result<()> {
-        // Check if file still exists
-        if !self.path.exists() {
-            return Err(anyhow::anyhow!("File no longer exists"));
+        match self.probe() {
+            FileState::Present => Ok(()),
+            FileState::Missing => Err(anyhow::anyhow!("File no longer exists")),
+            FileState::Unreachable => Err(anyhow::anyhow!("File is unreachable")),
+        }
+    }
+
+    /// Check the file, counting consecutive failures
+    pub fn probe(&mut self) -> FileState {
+        let state = mounts::probe(&self.path);
+        if state == FileState::Present {
+            self.failed_checks = 0;
+        } else {
+            self.failed_checks += 1;
+        }
+        state
+    }
+
+    /// Consecutive checks that could not find the file
+    pub fn failed_checks(&self) -> u32 {
+        self.failed_checks
+    }
+
+    /// Mount point the file lives on, if not the root filesystem
+    pub fn mount_point(&self) -> Option<&Path> {
+        self.mount_point.as_deref()
+    }
+
+    /// Record the mount point the file lives on
+    pub fn set_mount_point(&mut self, mount_point: Option<PathBuf>) {
+        self.mount_point = mount_point;
+    }
+
+    /// Badge the widget wants drawn over the icon, if any
+    pub fn overlay(&self) -> Option<&Overlay> {
+        self.overlay.as_ref()
+    }
+
+    /// Ask the widget for its current overlay badge
+    ///
+    /// Only a running Lua process whose widget said in its handshake that it
+    /// draws badges is asked; this never respawns one. Returns whether the
+    /// overlay changed and the icon needs redrawing.
+    pub fn poll_overlay(&mut self) -> bool {
+        let Some(process) = self.lua_process.as_mut() else {
+            return false;
+        };
+        if !process.has_overlay() || !process.is_running() {
+            return false;
+        }
+
+        if let Err(e) = process.send_request(&Request::Overlay) {
+            debug!("Failed to send overlay request for {}: {}", self.name, e);
+            return false;
+        }
+        let overlay = match process.receive_response_with_timeout(IPC_TIMEOUT) {
+            Ok(Response::Overlay { overlay }) => overlay,
+            Ok(Response::Error { message }) => {
+                debug!("Lua overlay error for {}: {}", self.name, message);
+                return false;
+            }
+            Ok(other) => {
+                warn!("Unexpected response from Lua: {:?}", other);
+                return false;
+            }
+            Err(e) => {
+                warn!("IPC timeout/error for {}: {}", self.name, e);
+                return false;
+            }
+        };
+
+        if overlay == self.overlay {
+            return false;
         }
+        self.overlay = overlay;
+        true
+    }
+
+    /// Whether the file's filesystem went away
+    pub fn is_unavailable(&self) -> bool {
+        self.unavailable
+    }
 
-        Ok(())
+    /// Mark the file as unavailable (or available again)
+    pub fn set_unavailable(&mut self, unavailable: bool) {
+        self.unavailable = unavailable;
     }
 
     /// Handle click event
@@ -246,9 +1184,146 @@ impl DesktopIcon {
 
     /// Handle double-click
     pub fn on_double_click(&self) -> Result<ClickAction> {
+        if self.icon_type.is_special() {
+            return Ok(ClickAction::None);
+        }
         Ok(ClickAction::Open)
     }
 
+    /// Actions offered in the icon's context menu
+    pub fn context_actions(&self) -> Vec<ContextAction> {
+        self.context_menu()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                MenuEntry::Action { action, .. } => Some(action),
+                MenuEntry::Separator => None,
+            })
+            .collect()
+    }
+
+    /// The icon's context menu: built-in entries, then the widget's own
+    /// entries after a separator
+    ///
+    /// Copy as Text is only offered for text files small enough to copy.
+    pub fn context_menu(&self) -> Vec<MenuEntry> {
+        let builtin = match self.icon_type {
+            IconType::Trash => vec![ContextAction::Open, ContextAction::EmptyTrash],
+            IconType::Volume => vec![ContextAction::Open, ContextAction::CopyPath],
+            icon_type if icon_type.is_special() => {
+                vec![ContextAction::CopyPath, ContextAction::MoveToTrash, ContextAction::Delete]
+            }
+            _ => {
+                let mut actions = vec![ContextAction::Open, ContextAction::CopyPath];
+                if clipboard::is_copyable_text(&self.path) {
+                    actions.push(ContextAction::CopyAsText);
+                }
+                actions.extend([ContextAction::MoveToTrash, ContextAction::Delete]);
+                actions
+            }
+        };
+        let mut menu: Vec<MenuEntry> = builtin
+            .into_iter()
+            .map(|action| MenuEntry::Action { label: action.builtin_label().to_string(), action })
+            .collect();
+
+        if !self.widget_actions.is_empty() {
+            menu.push(MenuEntry::Separator);
+            menu.extend(self.widget_actions.iter().map(|widget| MenuEntry::Action {
+                label: widget.label.clone(),
+                action: ContextAction::Widget(widget.id.clone()),
+            }));
+        }
+        menu
+    }
+
+    /// Run one of the widget's own context-menu entries in its Lua process
+    pub fn run_widget_action(&mut self, id: &str) -> Result<()> {
+        if !self.widget_actions.iter().any(|action| action.id == id) {
+            bail!("{} has no widget action {:?}", self.name, id);
+        }
+        if !self.ensure_process_running() {
+            bail!("Widget for {} is not running", self.name);
+        }
+        let Some(ref mut process) = self.lua_process else {
+            bail!("Widget for {} is not running", self.name);
+        };
+
+        process.send_request(&Request::Action { id: id.to_string() })?;
+        match process.receive_response_with_timeout(IPC_TIMEOUT)? {
+            Response::Event { .. } => Ok(()),
+            Response::Error { message } => bail!("Widget action {:?} failed: {}", id, message),
+            other => bail!("Unexpected response to widget action {:?}: {:?}", id, other),
+        }
+    }
+
+    /// Forward a click to the icon's widget, returning whether it handled it
+    ///
+    /// Icons without a widget ignore the click.
+    pub fn send_click(&mut self, button: MouseButton, x: f64, y: f64) -> Result<bool> {
+        if self.script_path.is_none() || !self.ensure_process_running() {
+            return Ok(false);
+        }
+        let Some(ref mut process) = self.lua_process else {
+            return Ok(false);
+        };
+
+        let event = IconEvent::Click { button: button.number(), name: button.name(), x, y };
+        process.send_request(&Request::Event { event })?;
+        match process.receive_response_with_timeout(IPC_TIMEOUT)? {
+            Response::Event { handled, .. } => Ok(handled),
+            Response::Error { message } => bail!("Widget click failed: {}", message),
+            other => bail!("Unexpected response to click: {:?}", other),
+        }
+    }
+
+    /// Forward a double click at `(x, y)` to the icon's widget, returning
+    /// whether it handled it
+    ///
+    /// Icons without a widget leave the double click to `on_double_click`.
+    pub fn send_double_click(&mut self, x: f64, y: f64) -> Result<bool> {
+        if self.script_path.is_none() || !self.ensure_process_running() {
+            return Ok(false);
+        }
+        let Some(ref mut process) = self.lua_process else {
+            return Ok(false);
+        };
+
+        process.send_request(&Request::Event { event: IconEvent::DoubleClick { x, y } })?;
+        match process.receive_response_with_timeout(IPC_TIMEOUT)? {
+            Response::Event { handled, .. } => Ok(handled),
+            Response::Error { message } => bail!("Widget double click failed: {}", message),
+            other => bail!("Unexpected response to double click: {:?}", other),
+        }
+    }
+
+    /// Forward a scroll by `(dx, dy)` pixels to the icon's widget, returning
+    /// whether it handled it
+    ///
+    /// Icons without a widget ignore the scroll.
+    pub fn on_scroll(&mut self, dx: f64, dy: f64) -> Result<bool> {
+        if self.script_path.is_none() || !self.ensure_process_running() {
+            return Ok(false);
+        }
+        let Some(ref mut process) = self.lua_process else {
+            return Ok(false);
+        };
+
+        process.send_request(&Request::Event { event: IconEvent::Scroll { dx, dy } })?;
+        match process.receive_response_with_timeout(IPC_TIMEOUT)? {
+            Response::Event { handled, .. } => Ok(handled),
+            Response::Error { message } => bail!("Widget scroll failed: {}", message),
+            other => bail!("Unexpected response to scroll: {:?}", other),
+        }
+    }
+
+    /// Path to hand to the file manager when the icon is opened
+    pub fn open_target(&self) -> PathBuf {
+        match self.icon_type {
+            IconType::Trash => trash::files_dir(&self.path),
+            _ => self.path.clone(),
+        }
+    }
+
     /// Set the hover state
     pub fn set_hovered(&mut self, hovered: bool) {
         self.hovered = hovered;
@@ -259,6 +1334,35 @@ impl DesktopIcon {
         self.hovered
     }
 
+    /// Set the keyboard focus state
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Check if focused
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Show a text field holding the name in place of the label
+    pub fn start_rename(&mut self) {
+        self.rename_field = Some(RenameField::new(&self.name));
+    }
+
+    /// The rename text field, while the icon is being renamed
+    pub fn rename_field(&self) -> Option<&RenameField> {
+        self.rename_field.as_ref()
+    }
+
+    pub fn rename_field_mut(&mut self) -> Option<&mut RenameField> {
+        self.rename_field.as_mut()
+    }
+
+    /// Go back to showing the label, returning the field
+    pub fn end_rename(&mut self) -> Option<RenameField> {
+        self.rename_field.take()
+    }
+
     /// Spawn a sandboxed Lua process for this icon
     ///
     /// # Arguments
@@ -277,21 +1381,32 @@ impl DesktopIcon {
 
         self.handler_path = Some(handler_path.to_path_buf());
         self.script_path = Some(icon_script_path.to_path_buf());
-
-        match LuaProcess::spawn(
-            handler_path.to_path_buf(),
-            icon_script_path.to_path_buf(),
-            &self.sandbox_options,
-        ) {
+        self.script_status = WidgetStatus::Ok;
+
+        let spawned = match self.lua_pool {
+            Some(ref pool) => pool.bind(handler_path, icon_script_path).map(WidgetProcess::Pooled),
+            None => LuaProcess::spawn(
+                handler_path.to_path_buf(),
+                icon_script_path.to_path_buf(),
+                &self.sandbox_options,
+            )
+            .map(WidgetProcess::Own),
+        };
+        match spawned {
             Ok(process) => {
                 debug!(
-                    "Spawned Lua process (pid {}) for icon: {} (handler: {}, script: {})",
+                    "Started Lua widget (pid {}) for icon: {} (handler: {}, script: {})",
                     process.pid(),
                     self.name,
                     handler_path.display(),
                     icon_script_path.display()
                 );
+                // A respawned widget may offer different entries
+                self.widget_actions = process.widget_actions().to_vec();
+                self.set_refresh_interval(process.refresh_interval());
                 self.lua_process = Some(process);
+                self.respawn_guard.record_spawn(Instant::now());
+                self.widget_failed = false;
                 Ok(())
             }
             Err(e) => {
@@ -300,13 +1415,93 @@ impl DesktopIcon {
                     self.path.display(),
                     e
                 );
+                // A script that dies during the handshake is a crash like any other
+                self.note_process_exit();
+                self.widget_actions.clear();
+                self.set_refresh_interval(None);
                 Err(e)
             }
         }
     }
 
+    /// How often the widget is rendered again regardless of changes
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval
+    }
+
+    /// Render the widget again every `interval`, or only on changes
+    ///
+    /// Intervals below `MIN_REFRESH_INTERVAL` are raised to it. Widgets set
+    /// this in their handshake.
+    pub fn set_refresh_interval(&mut self, interval: Option<Duration>) {
+        let interval = interval.map(|interval| {
+            if interval < MIN_REFRESH_INTERVAL {
+                warn!(
+                    "Refresh interval of {:?} for {} is too short, using {:?}",
+                    interval, self.name, MIN_REFRESH_INTERVAL
+                );
+            }
+            interval.max(MIN_REFRESH_INTERVAL)
+        });
+        self.refresh_interval = interval;
+        self.next_refresh = interval.map(|interval| Instant::now() + interval);
+    }
+
+    /// Whether the widget is due to be rendered again at `now`
+    ///
+    /// If so, the next refresh is scheduled one interval from now (refreshes
+    /// missed while the loop was busy are not caught up) and the cached
+    /// render is dropped.
+    pub fn take_refresh_due(&mut self, now: Instant) -> bool {
+        let (Some(interval), Some(next)) = (self.refresh_interval, self.next_refresh) else {
+            return false;
+        };
+        if now < next {
+            return false;
+        }
+        self.next_refresh = Some(now + interval);
+        self.invalidate_render();
+        true
+    }
+
+    /// Replace the widget with a fresh process running `scripts`
+    ///
+    /// The process is started from scratch with the sandbox and crash-loop
+    /// settings of `config`, even if respawning had been disabled. Without
+    /// scripts the icon goes back to built-in rendering.
+    pub fn restart_widget(
+        &mut self,
+        scripts: Option<(&Path, &Path)>,
+        config: &Config,
+    ) -> Result<()> {
+        self.kill_lua_process();
+        self.sandbox_options = sandbox_options(config);
+        self.respawn_guard = RespawnGuard::new(Duration::from_millis(config.lua_min_uptime_ms));
+        self.cached_draw_commands.clear();
+        self.invalidate_render();
+
+        match scripts {
+            Some((handler_path, script_path)) => self.spawn_lua_process(handler_path, script_path),
+            None => {
+                self.handler_path = None;
+                self.script_path = None;
+                self.widget_actions.clear();
+                self.set_refresh_interval(None);
+                Ok(())
+            }
+        }
+    }
+
+    /// Run the widget on `pool`'s workers, or in a process of its own if None
+    ///
+    /// Takes effect the next time the widget is started.
+    pub fn set_lua_pool(&mut self, pool: Option<LuaPool>) {
+        self.lua_pool = pool;
+    }
+
     /// Kill the Lua process if it exists
     pub fn kill_lua_process(&mut self) {
+        self.widget_failed = false;
         if let Some(mut process) = self.lua_process.take() {
             debug!("Killing Lua process for icon: {}", self.name);
             if let Err(e) = process.kill() {
@@ -316,13 +1511,26 @@ impl DesktopIcon {
     }
 
     /// Check if the Lua process is still running and restart if crashed
+    ///
+    /// Processes that die (or fail to start) within `lua_min_uptime_ms` are
+    /// respawned with exponential backoff, and not at all after repeated quick
+    /// crashes.
     fn ensure_process_running(&mut self) -> bool {
         if let Some(ref mut process) = self.lua_process {
             if process.is_running() {
                 return true;
             }
-            // Process crashed, log and attempt restart
-            error!("Lua process for {} crashed, attempting restart", self.name);
+        }
+
+        // Reap a process that exited since the last check
+        if let Some(mut process) = self.lua_process.take() {
+            error!("Lua process for {} crashed", self.name);
+            let _ = process.kill();
+            self.note_process_exit();
+        }
+
+        if !self.respawn_guard.can_respawn(Instant::now()) {
+            return false;
         }
 
         // Try to restart if we have both handler and script paths
@@ -344,6 +1552,70 @@ impl DesktopIcon {
         }
     }
 
+    /// Notice a widget that died, and restart it once its backoff is over
+    ///
+    /// Without this a crash would only be found on the icon's next render.
+    /// Returns whether the widget failed or came back, so the icon needs
+    /// drawing again with or without its broken-widget badge.
+    pub fn check_widget(&mut self) -> bool {
+        let failed = self.widget_failed;
+        self.ensure_process_running();
+        if self.widget_failed == failed {
+            return false;
+        }
+        self.invalidate_render();
+        true
+    }
+
+    /// Whether the widget died and is not running again yet
+    ///
+    /// The icon is drawn without its widget meanwhile, and for good once
+    /// respawning gave up.
+    pub fn widget_failed(&self) -> bool {
+        self.widget_failed
+    }
+
+    /// Record why no widget was started for the icon
+    pub fn set_script_status(&mut self, status: WidgetStatus) {
+        if self.script_status != status {
+            self.script_status = status;
+            self.invalidate_render();
+        }
+    }
+
+    /// Whether the icon's widget runs, and why not if it should
+    pub fn widget_status(&self) -> WidgetStatus {
+        match self.widget_failed {
+            true => WidgetStatus::LuaCrashed,
+            false => self.script_status,
+        }
+    }
+
+    /// Feed a process exit to the crash-loop guard and log its verdict
+    fn note_process_exit(&mut self) {
+        self.widget_failed = true;
+        // The dead widget's badge would never be updated again
+        self.overlay = None;
+        match self.respawn_guard.record_exit(Instant::now()) {
+            ExitVerdict::Respawn => {}
+            ExitVerdict::Backoff { uptime, delay, streak } => {
+                warn!(
+                    "Lua process for {} exited after {:?} (quick crash {} in a row), retrying in {:?}",
+                    self.name, uptime, streak, delay
+                );
+            }
+            ExitVerdict::Disabled { streak } => {
+                error!(
+                    "Crash loop detected: Lua script for {} died {} times within {:?} of starting; \
+                     disabling respawn and using built-in rendering",
+                    self.name,
+                    streak,
+                    self.respawn_guard.min_uptime()
+                );
+            }
+        }
+    }
+
     /// Convert local IconType to IPC IconType
     fn to_ipc_icon_type(&self) -> IpcIconType {
         match self.icon_type {
@@ -356,6 +1628,13 @@ impl DesktopIcon {
             IconType::Archive => IpcIconType::Custom("archive".to_string()),
             IconType::Video => IpcIconType::Custom("video".to_string()),
             IconType::Audio => IpcIconType::Custom("audio".to_string()),
+            IconType::Trash if self.trash_full => IpcIconType::Custom("trash-full".to_string()),
+            IconType::Trash => IpcIconType::Custom("trash".to_string()),
+            IconType::Volume => IpcIconType::Custom("volume".to_string()),
+            IconType::Fifo => IpcIconType::Custom("fifo".to_string()),
+            IconType::Socket => IpcIconType::Custom("socket".to_string()),
+            IconType::BlockDevice => IpcIconType::Custom("block-device".to_string()),
+            IconType::CharDevice => IpcIconType::Custom("char-device".to_string()),
             IconType::Unknown => IpcIconType::File,
         }
     }
@@ -378,8 +1657,8 @@ impl DesktopIcon {
         canvas_height: u32,
         device_pixel_ratio: f32,
     ) -> Vec<DrawCommand> {
-        // Check if we have a Lua process
-        if self.lua_process.is_none() {
+        // Without a widget script, or once respawning was given up, render natively
+        if self.script_path.is_none() || self.respawn_guard.is_disabled() {
             return self.fallback_render();
         }
 
@@ -389,13 +1668,16 @@ impl DesktopIcon {
                 "Lua process not running for {}, using fallback",
                 self.name
             );
+            if self.cached_draw_commands.is_empty() || self.respawn_guard.is_disabled() {
+                return self.fallback_render();
+            }
             return self.cached_draw_commands.clone();
         }
 
         // Build the render request
         let metadata = IconMetadata {
             path: self.path.to_string_lossy().to_string(),
-            name: self.name.clone(),
+            name: self.label().to_string(),
             mime_type: self.get_mime_type(),
             is_directory: self.icon_type == IconType::Folder,
             size: self.get_file_size(),
@@ -471,7 +1753,7 @@ impl DesktopIcon {
         cell_height: Option<u32>,
     ) -> Position {
         // If no Lua process, use default positioning
-        if self.lua_process.is_none() || !self.ensure_process_running() {
+        if self.script_path.is_none() || !self.ensure_process_running() {
             return self.default_position(screen_width, icon_index, cell_width, cell_height);
         }
 
@@ -524,18 +1806,12 @@ impl DesktopIcon {
         cell_width: Option<u32>,
         cell_height: Option<u32>,
     ) -> Position {
-        let cell_w = cell_width.unwrap_or(96) as i32;
-        let cell_h = cell_height.unwrap_or(96) as i32;
-        let margin = 20i32;
-        let cols = ((screen_width as i32 - margin * 2) / cell_w).max(1);
-
-        let col = (icon_index as i32) % cols;
-        let row = (icon_index as i32) / cols;
+        let cell_w = cell_width.unwrap_or(96);
+        let cell_h = cell_height.unwrap_or(96);
+        let (cols, _) = grid_size(screen_width, 0, cell_w, cell_h);
+        let (x, y) = cell_origin(icon_index, cols, cell_w, cell_h);
 
-        Position {
-            x: margin + col * cell_w,
-            y: margin + row * cell_h,
-        }
+        Position { x, y }
     }
 
     /// Generate fallback render commands when Lua is not available
@@ -549,9 +1825,29 @@ impl DesktopIcon {
             IconType::Archive => "#75507B",
             IconType::Video => "#C17D11",
             IconType::Audio => "#CC0000",
+            IconType::Trash => "#555753",
+            IconType::Volume => "#2E3436",
+            IconType::Fifo | IconType::Socket => "#3465A4",
+            IconType::BlockDevice | IconType::CharDevice => "#A40000",
             _ => "#888888",
         };
 
+        // Special files are drawn round so they don't pass for regular files
+        if self.icon_type.is_special() {
+            let center = self.size as f32 / 2.0;
+            return vec![
+                DrawCommand::Clear {
+                    color: "#00000000".to_string(),
+                },
+                DrawCommand::FillCircle {
+                    cx: center,
+                    cy: center,
+                    r: center - 4.0,
+                    color: color.to_string(),
+                },
+            ];
+        }
+
         vec![
             DrawCommand::Clear {
                 color: "#00000000".to_string(),
@@ -609,6 +1905,17 @@ impl DesktopIcon {
     pub fn script_path(&self) -> Option<&Path> {
         self.script_path.as_deref()
     }
+
+    /// Whether a widget drew the icon last time, rather than the fallback
+    pub fn drawn_by_widget(&self) -> bool {
+        self.script_path.is_some() && !self.respawn_guard.is_disabled() && !self.cached_draw_commands.is_empty()
+    }
+
+    /// Whether the last render came from the widget script (or no script is
+    /// used), rather than the fallback drawn while the script is unavailable
+    pub fn rendered_by_script(&self) -> bool {
+        self.script_path.is_none() || self.respawn_guard.is_disabled() || !self.cached_draw_commands.is_empty()
+    }
 }
 
 /// Action to take after a click
@@ -622,14 +1929,87 @@ pub enum ClickAction {
     ContextMenu,
 }
 
+/// Action behind an entry in an icon's context menu
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextAction {
+    Open,
+    /// Put the absolute path on the clipboard
+    CopyPath,
+    /// Put a text file's contents on the clipboard
+    CopyAsText,
+    MoveToTrash,
+    /// Delete permanently, bypassing the trash
+    Delete,
+    EmptyTrash,
+    /// Entry the icon's widget added, by its action id
+    Widget(String),
+}
+
+impl ContextAction {
+    /// Menu label of a built-in action (widget actions bring their own)
+    pub fn builtin_label(&self) -> &str {
+        match self {
+            ContextAction::Open => "Open",
+            ContextAction::CopyPath => "Copy Path",
+            ContextAction::CopyAsText => "Copy as Text",
+            ContextAction::MoveToTrash => "Move to Trash",
+            ContextAction::Delete => "Delete Permanently",
+            ContextAction::EmptyTrash => "Empty Trash",
+            ContextAction::Widget(id) => id,
+        }
+    }
+}
+
+/// Entry in an icon's context menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuEntry {
+    Action { label: String, action: ContextAction },
+    Separator,
+}
+
+/// Sandbox the widget processes run in, as set in `config`
+pub fn sandbox_options(config: &Config) -> SandboxOptions {
+    let mut options = SandboxOptions { allow_network: config.sandbox.allow_network, ..Default::default() };
+    options.read_only_paths.extend(config.sandbox.read_only_paths.iter().cloned());
+    options.read_write_paths.extend(config.sandbox.read_write_paths.iter().cloned());
+    options
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lua::respawn::MAX_QUICK_CRASHES;
 
     fn test_config() -> Config {
         Config::default()
     }
 
+    #[test]
+    fn test_rename_field_edits_at_the_cursor() {
+        let mut field = RenameField::new("naïve.txt");
+        assert_eq!(field.cursor(), 9);
+
+        field.move_cursor(-5);
+        field.erase_back();
+        field.insert('x');
+        assert_eq!(field.text(), "naïxe.txt");
+        field.move_cursor_to_end(false);
+        field.erase_forward();
+        field.move_cursor(-1);
+        field.insert('N');
+        assert_eq!(field.text(), "Naïxe.txt");
+        assert_eq!(field.cursor(), 1);
+
+        // A refused name is marked until it is edited
+        field.set_invalid();
+        field.move_cursor(100);
+        assert!(field.is_invalid());
+        field.erase_back();
+        assert!(!field.is_invalid());
+        assert_eq!(field.text(), "Naïxe.tx");
+    }
+
     #[test]
     fn test_icon_type_detection() {
         assert_eq!(
@@ -646,6 +2026,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fifo_is_special_and_cannot_be_opened() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fifo = temp_dir.path().join("events.txt");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        // The file type wins over the extension
+        let icon = DesktopIcon::new(&fifo, &test_config()).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Fifo);
+        assert!(icon.icon_type().is_special());
+        assert_eq!(icon.icon_name(), "inode-fifo");
+        assert_eq!(
+            icon.context_actions(),
+            vec![ContextAction::CopyPath, ContextAction::MoveToTrash, ContextAction::Delete]
+        );
+        assert_eq!(icon.on_double_click().unwrap(), ClickAction::None);
+        assert!(matches!(icon.fallback_render()[1], DrawCommand::FillCircle { .. }));
+    }
+
+    #[test]
+    fn test_column_flow_fills_down_then_across() {
+        // 3 columns x 2 rows: 0 2 4 / 1 3 5 in fill order
+        let slots: Vec<u32> = (0..7).map(|n| flow_slot(n, 3, 2, GridFlow::Columns)).collect();
+        assert_eq!(slots, vec![0, 3, 1, 4, 2, 5, 6]);
+        for n in 0..7 {
+            assert_eq!(flow_rank(flow_slot(n, 3, 2, GridFlow::Columns), 3, 2, GridFlow::Columns), n);
+            assert_eq!(flow_slot(n, 3, 2, GridFlow::Rows), n);
+        }
+    }
+
+    #[test]
+    fn test_free_flow_slot_skips_blocked_cells() {
+        // 3 columns x 3 rows with the middle cell kept clear
+        let blocked = HashSet::from([4]);
+        let slots: Vec<u32> =
+            (0..10).map(|n| free_flow_slot(n, 3, 3, GridFlow::Rows, &blocked)).collect();
+        assert_eq!(slots, vec![0, 1, 2, 3, 5, 6, 7, 8, 9, 10]);
+        let slots: Vec<u32> =
+            (0..9).map(|n| free_flow_slot(n, 3, 3, GridFlow::Columns, &blocked)).collect();
+        assert_eq!(slots, vec![0, 3, 6, 1, 7, 2, 5, 8, 9]);
+        assert_eq!(free_flow_slot(4, 3, 3, GridFlow::Rows, &HashSet::new()), 4);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1023), "1023 bytes");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+
+    #[test]
+    fn test_tooltip_shows_notes_only_when_set() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("report.txt");
+        std::fs::write(&path, "hello").unwrap();
+
+        let mut icon = DesktopIcon::new(&path, &test_config()).unwrap();
+        icon.notes = FileNotes::default();
+        assert_eq!(icon.tooltip_lines(), vec!["report.txt", "text/plain", "5 bytes"]);
+
+        icon.notes = FileNotes::from_values(Some(b"work,urgent".to_vec()), Some(b"Q3".to_vec()));
+        assert_eq!(
+            icon.tooltip_lines(),
+            vec!["report.txt", "text/plain", "5 bytes", "Tags: work, urgent", "Q3"]
+        );
+    }
+
     #[test]
     fn test_icon_creation_with_new_fields() {
         let config = test_config();
@@ -678,6 +2126,44 @@ mod tests {
         assert_eq!(pos.y, 20 + 1 * 96);
     }
 
+    #[test]
+    fn test_mirror_to_corner() {
+        let cell = (84, 104);
+        let area = (1000, 800);
+        let (x, y) = cell_origin(0, 5, 84, 104);
+        assert_eq!(mirror_to_corner(Corner::TopLeft, (x, y), cell, area), (x, y));
+        assert_eq!(mirror_to_corner(Corner::TopRight, (x, y), cell, area), (1000 - x - 84, y));
+        assert_eq!(
+            mirror_to_corner(Corner::BottomRight, (x, y), cell, area),
+            (1000 - x - 84, 800 - y - 104)
+        );
+        let mirrored = mirror_to_corner(Corner::BottomLeft, (x, y), cell, area);
+        assert_eq!(mirror_to_corner(Corner::BottomLeft, mirrored, cell, area), (x, y));
+    }
+
+    #[test]
+    fn test_cell_at_inverts_cell_origin() {
+        let (x, y) = cell_origin(7, 5, 84, 104);
+        assert_eq!(cell_at(x, y, 84, 104), Some((2, 1)));
+        assert_eq!(cell_at(x + 83, y + 103, 84, 104), Some((2, 1)));
+        assert_eq!(cell_at(x + 84, y, 84, 104), Some((3, 1)));
+        assert_eq!(cell_at(GRID_MARGIN as i32 - 1, 50, 84, 104), None);
+        assert_eq!(cell_at(50, -10, 84, 104), None);
+    }
+
+    #[test]
+    fn test_grid_size_degenerate_outputs() {
+        assert_eq!(grid_size(0, 0, 84, 104), (1, 1));
+        assert_eq!(grid_size(1, 1, 84, 104), (1, 1));
+        assert_eq!(grid_size(1920, 1080, 0, 0), (1880, 1040));
+        assert_eq!(grid_size(1920, 1080, 96, 96), (19, 10));
+
+        let config = test_config();
+        let icon = DesktopIcon::new(Path::new("/tmp/test.txt"), &config).unwrap();
+        let pos = icon.default_position(0, 3, Some(96), Some(96));
+        assert_eq!((pos.x, pos.y), (20, 20 + 3 * 96), "A zero-width output stacks icons in one column");
+    }
+
     #[test]
     fn test_fallback_render_returns_commands() {
         let config = test_config();
@@ -792,4 +2278,296 @@ mod tests {
         assert_eq!(pos.x, 20 + 5 * 96);
         assert_eq!(pos.y, 20);
     }
+
+    #[test]
+    fn test_folder_peek_items_bounded_and_images_first() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Stuff");
+        std::fs::create_dir(&folder).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt", "z.png", ".hidden.png"] {
+            std::fs::write(folder.join(name), "x").unwrap();
+        }
+
+        let icon = DesktopIcon::new(&folder, &test_config()).unwrap();
+        let items = icon.folder_peek_items();
+
+        assert_eq!(items.len(), FOLDER_PEEK_ITEMS);
+        assert_eq!(items[0], folder.join("z.png"), "Images should come first");
+        assert!(!items.contains(&folder.join(".hidden.png")));
+    }
+
+    #[test]
+    fn test_folder_peek_does_not_follow_symlinks_out_of_folder() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("secret.png"), "x").unwrap();
+
+        let folder = temp_dir.path().join("Desktop").join("Links");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("inner.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(&outside, folder.join("escape")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.png"), folder.join("escape.png")).unwrap();
+        std::os::unix::fs::symlink(folder.join("inner.txt"), folder.join("inner-link.txt")).unwrap();
+
+        let icon = DesktopIcon::new(&folder, &test_config()).unwrap();
+        let mut items = icon.folder_peek_items();
+        items.sort();
+        assert_eq!(items, vec![folder.join("inner-link.txt"), folder.join("inner.txt")]);
+
+        // A folder swapped for a symlink after the icon was created is not read
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::os::unix::fs::symlink(&outside, &folder).unwrap();
+        assert!(icon.folder_peek_items().is_empty());
+    }
+
+    #[test]
+    fn test_symlinks_take_their_target_type() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join(".directory"), "[Desktop Entry]\nIcon=folder-music\n").unwrap();
+        let desktop = temp_dir.path().join("Desktop");
+        std::fs::create_dir(&desktop).unwrap();
+
+        let folder_link = desktop.join("Music");
+        std::os::unix::fs::symlink(&outside, &folder_link).unwrap();
+        let icon = DesktopIcon::new(&folder_link, &test_config()).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Folder);
+        assert_eq!(icon.link(), Some(LinkState::Resolved));
+        assert!(icon.custom_icon().is_none(), "Nothing is read through the link");
+
+        let dangling = desktop.join("gone");
+        std::os::unix::fs::symlink(temp_dir.path().join("missing.png"), &dangling).unwrap();
+        let icon = DesktopIcon::new(&dangling, &test_config()).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Symlink);
+        assert_eq!(icon.link(), Some(LinkState::Broken));
+
+        // Links in a loop are broken, not followed forever
+        let (a, b) = (desktop.join("a"), desktop.join("b"));
+        std::os::unix::fs::symlink(&b, &a).unwrap();
+        std::os::unix::fs::symlink(&a, &b).unwrap();
+        assert_eq!(DesktopIcon::new(&a, &test_config()).unwrap().link(), Some(LinkState::Broken));
+
+        std::fs::write(desktop.join("plain.txt"), "x").unwrap();
+        assert_eq!(DesktopIcon::new(&desktop.join("plain.txt"), &test_config()).unwrap().link(), None);
+    }
+
+    #[test]
+    fn test_folder_peek_empty_folder_falls_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Empty");
+        std::fs::create_dir(&folder).unwrap();
+
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let mut icon = DesktopIcon::new(&folder, &test_config()).unwrap();
+        assert!(icon.folder_peek(&renderer, 32).is_none());
+    }
+
+    #[test]
+    fn test_folder_peek_refreshes_after_invalidate() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Docs");
+        std::fs::create_dir(&folder).unwrap();
+
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let mut icon = DesktopIcon::new(&folder, &test_config()).unwrap();
+        assert!(icon.folder_peek(&renderer, 32).is_none());
+
+        std::fs::write(folder.join("note.txt"), "x").unwrap();
+        // Cached until invalidated
+        assert!(icon.folder_peek(&renderer, 32).is_none());
+
+        icon.invalidate_folder_peek();
+        assert!(icon.folder_peek(&renderer, 32).is_some());
+    }
+
+    #[test]
+    fn test_folder_peek_not_rendered_for_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("file.txt");
+        std::fs::write(&file, "x").unwrap();
+
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let mut icon = DesktopIcon::new(&file, &test_config()).unwrap();
+        assert!(icon.folder_peek(&renderer, 32).is_none());
+    }
+
+    #[test]
+    fn test_trash_icon_reflects_trash_state() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        std::fs::create_dir_all(trash::files_dir(&trash_dir)).unwrap();
+
+        let mut icon = DesktopIcon::new_trash(&trash_dir, &test_config()).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Trash);
+        assert_eq!(icon.name(), "Trash");
+        assert!(!icon.is_trash_full());
+        assert_eq!(icon.icon_name(), "user-trash");
+
+        std::fs::write(trash::files_dir(&trash_dir).join("old.txt"), "x").unwrap();
+        assert!(icon.refresh_trash_state());
+        assert!(icon.is_trash_full());
+        assert_eq!(icon.icon_name(), "user-trash-full");
+        assert!(!icon.refresh_trash_state(), "Unchanged state reports no change");
+    }
+
+    #[test]
+    fn test_volume_icon_usage_and_file_count() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let volume = temp_dir.path().join("USB Stick");
+        std::fs::create_dir(&volume).unwrap();
+        std::fs::write(volume.join("a.txt"), "x").unwrap();
+
+        let mut icon = DesktopIcon::new_volume(&volume, &test_config()).unwrap();
+        assert_eq!(icon.icon_type(), IconType::Volume);
+        assert_eq!(icon.name(), "USB Stick");
+        assert_eq!(icon.file_count(), Some(1));
+        assert!(icon.volume_usage().is_some());
+
+        let commands = icon.volume_commands(64, 64);
+        assert!(matches!(&commands[0], DrawCommand::Text { text, .. } if text == "1 item"));
+        assert!(matches!(commands[1], DrawCommand::Bar { .. }));
+
+        std::fs::write(volume.join("b.txt"), "x").unwrap();
+        assert!(icon.refresh_volume());
+        assert_eq!(icon.file_count(), Some(2));
+    }
+
+    #[test]
+    fn test_unreadable_volume_has_no_bar() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let volume = temp_dir.path().join("Gone");
+        std::fs::create_dir(&volume).unwrap();
+
+        let mut icon = DesktopIcon::new_volume(&volume, &test_config()).unwrap();
+        std::fs::remove_dir(&volume).unwrap();
+        assert!(icon.refresh_volume());
+        assert_eq!(icon.volume_usage(), None);
+        assert!(icon.volume_commands(64, 64).is_empty());
+
+        let file = DesktopIcon::new(Path::new("/tmp/test.txt"), &test_config()).unwrap();
+        assert!(file.volume_commands(64, 64).is_empty());
+    }
+
+    #[test]
+    fn test_trash_icon_actions_and_open_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let mut config = test_config();
+        config.trash_corner = Some(Corner::BottomRight);
+
+        let icon = DesktopIcon::new_trash(&trash_dir, &config).unwrap();
+        assert_eq!(icon.pinned_corner(), Some(Corner::BottomRight));
+        assert!(icon.context_actions().contains(&ContextAction::EmptyTrash));
+        assert_eq!(icon.open_target(), trash_dir.join("files"));
+
+        let file = DesktopIcon::new(Path::new("/tmp/test.txt"), &config).unwrap();
+        assert!(!file.context_actions().contains(&ContextAction::EmptyTrash));
+        assert_eq!(file.pinned_corner(), None);
+    }
+
+    #[test]
+    fn test_widget_actions_follow_builtin_entries() {
+        let mut icon = DesktopIcon::new(Path::new("/tmp/photo.png"), &test_config()).unwrap();
+        assert_eq!(
+            icon.context_menu(),
+            vec![
+                MenuEntry::Action { label: "Open".to_string(), action: ContextAction::Open },
+                MenuEntry::Action {
+                    label: "Copy Path".to_string(),
+                    action: ContextAction::CopyPath,
+                },
+                MenuEntry::Action {
+                    label: "Move to Trash".to_string(),
+                    action: ContextAction::MoveToTrash,
+                },
+                MenuEntry::Action {
+                    label: "Delete Permanently".to_string(),
+                    action: ContextAction::Delete,
+                },
+            ]
+        );
+
+        icon.widget_actions = vec![
+            WidgetAction { id: "wallpaper".to_string(), label: "Set as Wallpaper".to_string() },
+            WidgetAction { id: "edit".to_string(), label: "Edit".to_string() },
+        ];
+        let menu = icon.context_menu();
+        assert_eq!(menu.len(), 7);
+        assert_eq!(menu[4], MenuEntry::Separator);
+        assert_eq!(
+            menu[5],
+            MenuEntry::Action {
+                label: "Set as Wallpaper".to_string(),
+                action: ContextAction::Widget("wallpaper".to_string()),
+            }
+        );
+        assert_eq!(
+            icon.context_actions(),
+            vec![
+                ContextAction::Open,
+                ContextAction::CopyPath,
+                ContextAction::MoveToTrash,
+                ContextAction::Delete,
+                ContextAction::Widget("wallpaper".to_string()),
+                ContextAction::Widget("edit".to_string()),
+            ]
+        );
+
+        // Small text files can also be copied as text
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let notes = temp_dir.path().join("notes.txt");
+        std::fs::write(&notes, "hello").unwrap();
+        let text_icon = DesktopIcon::new(&notes, &test_config()).unwrap();
+        assert!(text_icon.context_actions().contains(&ContextAction::CopyAsText));
+        assert!(!icon.context_actions().contains(&ContextAction::CopyAsText));
+
+        // Only advertised ids are sent to the widget
+        assert!(icon.run_widget_action("delete-everything").is_err());
+    }
+
+    #[test]
+    fn test_refresh_interval_is_clamped_and_rescheduled() {
+        let mut icon = DesktopIcon::new(Path::new("/tmp/clock.lua"), &test_config()).unwrap();
+        let start = Instant::now();
+        assert!(!icon.take_refresh_due(start + Duration::from_secs(60)), "No interval, no refresh");
+
+        icon.set_refresh_interval(Some(Duration::from_millis(1)));
+        assert_eq!(icon.refresh_interval(), Some(MIN_REFRESH_INTERVAL));
+
+        icon.set_refresh_interval(Some(Duration::from_secs(1)));
+        let now = Instant::now();
+        assert!(!icon.take_refresh_due(now));
+        let due = now + Duration::from_secs(1);
+        assert!(icon.take_refresh_due(due));
+        assert!(!icon.take_refresh_due(due), "The next refresh is an interval later");
+        assert!(icon.take_refresh_due(due + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_script_exiting_immediately_stops_respawning() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let handler = temp_dir.path().join("ipc_handler.lua");
+        let script = temp_dir.path().join("crash.lua");
+        std::fs::write(&handler, "os.exit(1)\n").unwrap();
+        std::fs::write(&script, "os.exit(1)\n").unwrap();
+
+        let mut icon = DesktopIcon::new(Path::new("/tmp/test.txt"), &test_config()).unwrap();
+
+        // The process dies before completing the handshake
+        assert!(icon.spawn_lua_process(&handler, &script).is_err());
+        assert!(!icon.ensure_process_running(), "Respawn must wait for the backoff");
+
+        for _ in 1..MAX_QUICK_CRASHES {
+            icon.respawn_guard.clear_backoff();
+            assert!(!icon.ensure_process_running());
+        }
+
+        assert!(icon.respawn_guard.is_disabled());
+        assert_eq!(
+            format!("{:?}", icon.request_render(64, 64, 1.0)),
+            format!("{:?}", icon.fallback_render())
+        );
+    }
 }
@@ -79,10 +79,24 @@ pub struct IconMetadata {
 pub enum IconEvent {
     /// Mouse click event
     Click {
+        /// Button number: 1 left, 2 middle, 3 right, 8 back, 9 forward
         button: u32,
+        /// Stable button name, see [`crate::backend::MouseButton`]
+        #[serde(default)]
+        name: String,
         x: f64,
         y: f64,
     },
+    /// Second press of a double click with the primary button
+    DoubleClick {
+        x: f64,
+        y: f64,
+    },
+    /// Scroll over the icon, in pixels; positive is right and down
+    Scroll {
+        dx: f64,
+        dy: f64,
+    },
     /// Mouse hover enter
     HoverEnter,
     /// Mouse hover exit
@@ -127,6 +141,55 @@ pub struct PositionInput {
     pub cell_height: Option<u32>,
 }
 
+/// Corner of the icon glyph an overlay badge is drawn in
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayCorner {
+    #[default]
+    TopRight,
+    TopLeft,
+    BottomRight,
+    BottomLeft,
+}
+
+/// Badge a widget draws over its icon, independent of the main render
+///
+/// Normally one of `count`, `emblem` and `dot` is set; if several are, `count`
+/// wins over `emblem`, which wins over `dot`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Overlay {
+    /// Number shown in a pill (e.g. unread mail), "99+" above 99
+    #[serde(default)]
+    pub count: Option<u32>,
+    /// Icon theme emblem name (e.g. "emblem-synchronizing")
+    #[serde(default)]
+    pub emblem: Option<String>,
+    /// Color of a plain status dot
+    #[serde(default)]
+    pub dot: Option<String>,
+    /// Background color of count and emblem badges
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub corner: OverlayCorner,
+    /// Clockwise rotation of the badge in degrees (e.g. a spinning sync emblem)
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// Context-menu entry a widget offers for its icon
+///
+/// Widgets list these in their handshake reply. The daemon shows them after
+/// the built-in entries and sends `Request::Action` with the `id` when one is
+/// chosen.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WidgetAction {
+    /// Identifier passed back to the widget
+    pub id: String,
+    /// Menu label
+    pub label: String,
+}
+
 /// Request messages sent from Rust to Lua process
 ///
 /// Uses internally tagged JSON serialization to produce `{"type":"Handshake", ...}`
@@ -154,10 +217,44 @@ pub enum Request {
         /// Inputs for position calculation
         input: PositionInput,
     },
+    /// Ask for the icon's current overlay badge (polled between renders)
+    Overlay,
+    /// Run one of the widget's own context-menu entries
+    Action {
+        id: String,
+    },
+    /// Load a widget script into the slot a routed request names
+    ///
+    /// Only sent to pooled workers; answered like `Handshake`.
+    Bind {
+        script_path: String,
+    },
+    /// Unload the widget in the slot a routed request names
+    Release,
     /// Request to shutdown the Lua process
     Shutdown,
 }
 
+/// A request for one of the widgets a pooled worker runs
+///
+/// Serializes as the request itself with an `icon` field naming the slot
+/// the worker should dispatch it to.
+#[derive(Debug, Serialize)]
+pub struct Routed<'a> {
+    pub icon: u32,
+    #[serde(flatten)]
+    pub request: &'a Request,
+}
+
+/// The slot a pooled worker's response came from
+///
+/// Workers echo the `icon` field of routed requests in their responses.
+#[derive(Debug, Deserialize)]
+pub struct RoutedReply {
+    #[serde(default)]
+    pub icon: Option<u32>,
+}
+
 /// Action to perform in response to an event
 #[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -180,6 +277,16 @@ pub enum Response {
     HandshakeAck {
         version: u32,
         success: bool,
+        /// Context-menu entries the widget adds (Lua omits an empty list)
+        #[serde(default)]
+        actions: Vec<WidgetAction>,
+        /// How often the widget wants to be rendered again, in milliseconds,
+        /// for clocks and the like (None to render only on changes)
+        #[serde(default)]
+        refresh_ms: Option<u64>,
+        /// Whether the widget defines overlay(); others are never asked for a badge
+        #[serde(default)]
+        overlay: bool,
     },
     /// Render result with draw commands
     Render {
@@ -197,10 +304,17 @@ pub enum Response {
         /// Computed position for the icon
         position: Position,
     },
+    /// Overlay badge result (None to show no badge)
+    Overlay {
+        #[serde(default)]
+        overlay: Option<Overlay>,
+    },
     /// Error response
     Error {
         message: String,
     },
+    /// The widget in a pooled worker's slot was unloaded
+    Released,
     /// Shutdown acknowledgement
     ShutdownAck,
 }
@@ -365,7 +479,8 @@ mod tests {
         // Note: Request uses internally tagged JSON for Lua IPC compatibility
         let request = Request::Event {
             event: IconEvent::Click {
-                button: 1,
+                button: 8,
+                name: "back".to_string(),
                 x: 32.0,
                 y: 32.0,
             },
@@ -374,8 +489,9 @@ mod tests {
         let decoded = Request::deserialize(&encoded, IpcEncoding::Json).unwrap();
         match decoded {
             Request::Event { event } => match event {
-                IconEvent::Click { button, x, y } => {
-                    assert_eq!(button, 1);
+                IconEvent::Click { button, name, x, y } => {
+                    assert_eq!(button, 8);
+                    assert_eq!(name, "back");
                     assert!((x - 32.0).abs() < 0.001);
                     assert!((y - 32.0).abs() < 0.001);
                 }
@@ -385,6 +501,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_scroll_event_serialization() {
+        let request = Request::Event { event: IconEvent::Scroll { dx: 0.0, dy: -15.0 } };
+        let encoded = request.serialize(IpcEncoding::Json).unwrap();
+        assert!(String::from_utf8_lossy(&encoded).contains(r#"{"Scroll":{"dx":0.0,"dy":-15.0}}"#));
+        match Request::deserialize(&encoded, IpcEncoding::Json).unwrap() {
+            Request::Event { event: IconEvent::Scroll { dx, dy } } => {
+                assert_eq!((dx, dy), (0.0, -15.0));
+            }
+            other => panic!("Expected Scroll event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_double_click_event_serialization() {
+        let request = Request::Event { event: IconEvent::DoubleClick { x: 12.0, y: 30.5 } };
+        let encoded = request.serialize(IpcEncoding::Json).unwrap();
+        let json = String::from_utf8_lossy(&encoded);
+        assert!(json.contains(r#"{"DoubleClick":{"x":12.0,"y":30.5}}"#));
+        match Request::deserialize(&encoded, IpcEncoding::Json).unwrap() {
+            Request::Event { event: IconEvent::DoubleClick { x, y } } => {
+                assert_eq!((x, y), (12.0, 30.5));
+            }
+            other => panic!("Expected DoubleClick event, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_response_render_serialization() {
         // Note: Response uses internally tagged JSON for Lua IPC compatibility
@@ -593,11 +736,14 @@ mod tests {
         let response = Response::HandshakeAck {
             version: PROTOCOL_VERSION,
             success: true,
+            actions: Vec::new(),
+            refresh_ms: None,
+            overlay: false,
         };
         let json_data = response.serialize(IpcEncoding::Json).unwrap();
         let decoded = Response::deserialize(&json_data, IpcEncoding::Json).unwrap();
         match decoded {
-            Response::HandshakeAck { version, success } => {
+            Response::HandshakeAck { version, success, .. } => {
                 assert_eq!(version, PROTOCOL_VERSION);
                 assert!(success);
             }
@@ -706,6 +852,7 @@ mod tests {
         let request = Request::Event {
             event: IconEvent::Click {
                 button: 1,
+                name: "left".to_string(),
                 x: 32.5,
                 y: 48.0,
             },
@@ -714,7 +861,7 @@ mod tests {
         let decoded = Request::deserialize(&json_data, IpcEncoding::Json).unwrap();
         match decoded {
             Request::Event { event } => match event {
-                IconEvent::Click { button, x, y } => {
+                IconEvent::Click { button, x, y, .. } => {
                     assert_eq!(button, 1);
                     assert!((x - 32.5).abs() < 0.01);
                     assert!((y - 48.0).abs() < 0.01);
@@ -773,6 +920,9 @@ mod tests {
         let response = Response::HandshakeAck {
             version: PROTOCOL_VERSION,
             success: true,
+            actions: Vec::new(),
+            refresh_ms: None,
+            overlay: false,
         };
         let json_data = response.serialize(IpcEncoding::Json).unwrap();
         let json_str = String::from_utf8(json_data).unwrap();
@@ -941,7 +1091,7 @@ mod tests {
                 },
             },
             Request::Event {
-                event: IconEvent::Click { button: 1, x: 0.0, y: 0.0 },
+                event: IconEvent::Click { button: 1, name: "left".to_string(), x: 0.0, y: 0.0 },
             },
             Request::Position {
                 input: PositionInput {
@@ -953,6 +1103,10 @@ mod tests {
                     cell_height: None,
                 },
             },
+            Request::Overlay,
+            Request::Action { id: "edit".to_string() },
+            Request::Bind { script_path: "/scripts/file.lua".to_string() },
+            Request::Release,
             Request::Shutdown,
         ];
 
@@ -969,11 +1123,20 @@ mod tests {
 
         // Test that all response variants can be serialized and deserialized with JSON
         let responses = vec![
-            Response::HandshakeAck { version: 1, success: true },
+            Response::HandshakeAck {
+                version: 1,
+                success: true,
+                actions: Vec::new(),
+                refresh_ms: Some(1000),
+                overlay: true,
+            },
             Response::Render { commands: vec![DrawCommand::Clear { color: "#000".to_string() }] },
             Response::Event { handled: true, action: None },
             Response::Position { position: Position { x: 0, y: 0 } },
+            Response::Overlay { overlay: Some(Overlay { count: Some(3), ..Overlay::default() }) },
+            Response::Overlay { overlay: None },
             Response::Error { message: "test".to_string() },
+            Response::Released,
             Response::ShutdownAck,
         ];
 
@@ -983,4 +1146,60 @@ mod tests {
             assert!(decoded.is_ok(), "Failed to deserialize response: {:?}", response);
         }
     }
+
+    #[test]
+    fn test_routed_request_carries_the_icon_slot() {
+        let request = Request::Action { id: "edit".to_string() };
+        let json = serde_json::to_value(Routed { icon: 7, request: &request }).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "Action", "icon": 7, "id": "edit"}));
+    }
+
+    #[test]
+    fn test_handshake_ack_with_widget_actions_from_lua_json() {
+        let plain = Response::deserialize(br#"{"type":"HandshakeAck","version":1,"success":true}"#, IpcEncoding::Json)
+            .unwrap();
+        assert!(matches!(plain, Response::HandshakeAck { ref actions, .. } if actions.is_empty()));
+
+        let json = br#"{"type":"HandshakeAck","version":1,"success":true,"actions":[{"id":"wallpaper","label":"Set as Wallpaper"}]}"#;
+        match Response::deserialize(json, IpcEncoding::Json).unwrap() {
+            Response::HandshakeAck { actions, .. } => {
+                assert_eq!(
+                    actions,
+                    vec![WidgetAction { id: "wallpaper".to_string(), label: "Set as Wallpaper".to_string() }]
+                );
+            }
+            other => panic!("Expected HandshakeAck response, got {:?}", other),
+        }
+
+        let json = br#"{"type":"HandshakeAck","version":1,"success":true,"refresh_ms":1000}"#;
+        let clock = Response::deserialize(json, IpcEncoding::Json).unwrap();
+        assert!(matches!(clock, Response::HandshakeAck { refresh_ms: Some(1000), .. }));
+        assert!(matches!(plain, Response::HandshakeAck { overlay: false, .. }));
+
+        let json = br#"{"type":"HandshakeAck","version":1,"success":true,"overlay":true}"#;
+        let badged = Response::deserialize(json, IpcEncoding::Json).unwrap();
+        assert!(matches!(badged, Response::HandshakeAck { overlay: true, .. }));
+
+        let request = Request::Action { id: "wallpaper".to_string() };
+        let json = String::from_utf8(request.serialize(IpcEncoding::Json).unwrap()).unwrap();
+        assert_eq!(json, r#"{"type":"Action","id":"wallpaper"}"#);
+    }
+
+    #[test]
+    fn test_overlay_response_from_lua_json() {
+        // Lua drops nil fields, so everything but the type may be missing
+        let empty = Response::deserialize(br#"{"type":"Overlay"}"#, IpcEncoding::Json).unwrap();
+        assert!(matches!(empty, Response::Overlay { overlay: None }));
+
+        let json = br#"{"type":"Overlay","overlay":{"emblem":"emblem-synchronizing","corner":"bottom_left","rotation":90}}"#;
+        match Response::deserialize(json, IpcEncoding::Json).unwrap() {
+            Response::Overlay { overlay: Some(overlay) } => {
+                assert_eq!(overlay.emblem.as_deref(), Some("emblem-synchronizing"));
+                assert_eq!(overlay.corner, OverlayCorner::BottomLeft);
+                assert_eq!(overlay.count, None);
+                assert!((overlay.rotation - 90.0).abs() < 0.001);
+            }
+            other => panic!("Expected Overlay response, got {:?}", other),
+        }
+    }
 }
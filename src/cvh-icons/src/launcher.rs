@@ -0,0 +1,109 @@
+//! Application launchers (`.desktop` files) on the desktop
+//!
+//! With `launcher_click = "focus_or_launch"`, opening a launcher raises a
+//! running window of its application instead of starting another instance.
+//! Windows are matched by app ID: the launcher's `StartupWMClass` if it sets
+//! one, and its file name without `.desktop`, which is the app ID by
+//! convention (`org.gnome.Nautilus.desktop`).
+
+use std::fs;
+use std::path::Path;
+
+/// App IDs a window of the application `path` launches may have
+///
+/// Empty unless `path` is a `.desktop` file of type `Application`.
+pub fn app_ids(path: &Path) -> Vec<String> {
+    let Some(stem) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".desktop"))
+    else {
+        return Vec::new();
+    };
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents, stem),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// App IDs from a launcher's `[Desktop Entry]` group, `StartupWMClass` first
+pub fn parse(contents: &str, stem: &str) -> Vec<String> {
+    let mut in_entry = false;
+    let mut application = false;
+    let mut wm_class = None;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_entry) else {
+            continue;
+        };
+        match key.trim() {
+            "Type" => application = value.trim() == "Application",
+            "StartupWMClass" if !value.trim().is_empty() => wm_class = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if !application {
+        return Vec::new();
+    }
+
+    let mut ids: Vec<String> = wm_class.into_iter().collect();
+    if !stem.is_empty() && !ids.iter().any(|id| id.eq_ignore_ascii_case(stem)) {
+        ids.push(stem.to_string());
+    }
+    ids
+}
+
+/// Whether a window's app ID is one of a launcher's, ignoring ASCII case
+///
+/// `StartupWMClass` values often differ from the app ID only in case
+/// (`Firefox` and `firefox`).
+pub fn matches(app_ids: &[String], app_id: &str) -> bool {
+    app_ids.iter().any(|id| id.eq_ignore_ascii_case(app_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_ids_from_wm_class_and_file_name() {
+        let contents = "[Desktop Entry]\nType=Application\nExec=firefox %u\nStartupWMClass=Firefox\n";
+        assert_eq!(parse(contents, "firefox"), vec!["Firefox"]);
+        assert_eq!(parse(contents, "org.mozilla.firefox"), vec!["Firefox", "org.mozilla.firefox"]);
+
+        let contents = "[Desktop Entry]\nType=Application\nExec=nautilus\n";
+        assert_eq!(parse(contents, "org.gnome.Nautilus"), vec!["org.gnome.Nautilus"]);
+    }
+
+    #[test]
+    fn test_only_application_entries_have_app_ids() {
+        assert!(parse("[Desktop Entry]\nType=Link\nURL=https://example.org\n", "site").is_empty());
+        assert!(parse("[Desktop Entry]\nExec=true\n", "untyped").is_empty());
+        // Keys of other groups (desktop actions) don't count
+        let contents = "[Desktop Entry]\nType=Application\n[Desktop Action new]\nStartupWMClass=Other\n";
+        assert_eq!(parse(contents, "app"), vec!["app"]);
+    }
+
+    #[test]
+    fn test_app_ids_only_for_desktop_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let launcher = temp_dir.path().join("foot.desktop");
+        let text = temp_dir.path().join("foot.txt");
+        fs::write(&launcher, "[Desktop Entry]\nType=Application\nExec=foot\n").unwrap();
+        fs::write(&text, "[Desktop Entry]\nType=Application\nExec=foot\n").unwrap();
+
+        assert_eq!(app_ids(&launcher), vec!["foot"]);
+        assert!(app_ids(&text).is_empty());
+        assert!(app_ids(&temp_dir.path().join("gone.desktop")).is_empty());
+    }
+
+    #[test]
+    fn test_app_ids_match_ignoring_case() {
+        let ids = vec!["Firefox".to_string()];
+        assert!(matches(&ids, "firefox"));
+        assert!(!matches(&ids, "firefox-esr"));
+    }
+}
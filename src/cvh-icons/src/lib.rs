@@ -0,0 +1,48 @@
+//! CVH Icons library
+//!
+//! The `cvh-icons` binary is a thin command-line wrapper around these
+//! modules. They are also exposed so a Rust compositor or shell can embed
+//! desktop-icon management directly: create an [`IconDaemon`] with
+//! [`IconDaemon::with_backend`], passing your own [`SurfaceBackend`] (or a
+//! [`HeadlessBackend`] to read rendered buffers back), then feed it
+//! filesystem events with [`IconDaemon::queue_fs_event`] and drive it with
+//! [`IconDaemon::step`] from your own loop.
+//!
+//! Fallible API calls return an [`IconsError`] saying what kind of thing
+//! failed, so embedders can match on it.
+
+pub mod backend;
+pub mod clipboard;
+pub mod config;
+pub mod control;
+pub mod daemon;
+pub mod desktop_settings;
+pub mod emblems;
+pub mod error;
+pub mod folder_icon;
+pub mod gnome_layout;
+pub mod icons;
+pub mod ipc;
+pub mod launcher;
+pub mod lock;
+pub mod log_level;
+pub mod lua;
+pub mod mounts;
+pub mod placements;
+pub mod reload;
+pub mod render_cache;
+pub mod renderer;
+pub mod sandbox;
+pub mod session_lock;
+pub mod shutdown;
+pub mod signals;
+pub mod templates;
+pub mod thumbnails;
+pub mod trash;
+pub mod wayland;
+pub mod xattrs;
+
+pub use backend::{HeadlessBackend, HeadlessSurface, InputEvent, KeyModifiers, MouseButton, SurfaceBackend, SurfaceId};
+pub use config::Config;
+pub use daemon::IconDaemon;
+pub use error::{IconsError, IconsResult};
@@ -0,0 +1,154 @@
+//! Single-instance lock
+//!
+//! The daemon holds an exclusive `flock` on `$XDG_RUNTIME_DIR/cvh-icons.lock`
+//! for its whole lifetime. The kernel drops the lock when the process exits,
+//! so a lock file left behind by a crashed instance is simply re-acquired.
+
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::error::DaemonError;
+
+/// Lock file name inside the runtime directory
+const LOCK_FILE_NAME: &str = "cvh-icons.lock";
+
+/// Exclusive lock held while the daemon runs; released on drop
+pub struct InstanceLock {
+    _lock: Flock<File>,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Default lock file location
+    ///
+    /// Uses `$XDG_RUNTIME_DIR`, falling back to a per-user file in the temp directory.
+    pub fn default_path() -> PathBuf {
+        match dirs::runtime_dir() {
+            Some(dir) => dir.join(LOCK_FILE_NAME),
+            None => std::env::temp_dir().join(format!(
+                "cvh-icons-{}.lock",
+                nix::unistd::getuid()
+            )),
+        }
+    }
+
+    /// Acquire the lock at `path`
+    ///
+    /// Fails with `DaemonError::AlreadyRunning` if another instance holds it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        let mut lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((mut file, Errno::EWOULDBLOCK)) => {
+                return Err(DaemonError::AlreadyRunning {
+                    pid: read_pid(&mut file),
+                    lock_path: path.to_path_buf(),
+                }
+                .into());
+            }
+            Err((_, errno)) => {
+                return Err(anyhow::Error::from(errno))
+                    .with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        };
+
+        // Record our PID for the "already running" message of later instances
+        lock.set_len(0).context("Failed to truncate lock file")?;
+        lock.seek(SeekFrom::Start(0)).context("Failed to seek lock file")?;
+        writeln!(lock, "{}", std::process::id()).context("Failed to write lock file")?;
+        lock.flush().context("Failed to write lock file")?;
+
+        debug!("Acquired instance lock {}", path.display());
+
+        Ok(Self {
+            _lock: lock,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Path of the held lock file
+    #[allow(dead_code)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        debug!("Releasing instance lock {}", self.path.display());
+    }
+}
+
+/// Read the PID recorded by the lock holder, if any
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_acquire_writes_pid() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(LOCK_FILE_NAME);
+
+        let _lock = InstanceLock::acquire(&path).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.trim(), std::process::id().to_string());
+    }
+
+    #[test]
+    fn test_second_acquire_fails_while_held() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(LOCK_FILE_NAME);
+
+        let _lock = InstanceLock::acquire(&path).unwrap();
+        let err = InstanceLock::acquire(&path).err().expect("second acquire should fail");
+
+        match err.downcast_ref::<DaemonError>() {
+            Some(DaemonError::AlreadyRunning { pid, .. }) => {
+                assert_eq!(*pid, Some(std::process::id()));
+            }
+            other => panic!("Expected AlreadyRunning, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lock_released_on_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(LOCK_FILE_NAME);
+
+        drop(InstanceLock::acquire(&path).unwrap());
+        assert!(InstanceLock::acquire(&path).is_ok());
+    }
+
+    #[test]
+    fn test_stale_lock_file_is_reused() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(LOCK_FILE_NAME);
+
+        // Left behind by a crashed instance: present but not locked
+        std::fs::write(&path, "999999\n").unwrap();
+
+        let _lock = InstanceLock::acquire(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.trim(), std::process::id().to_string());
+    }
+}
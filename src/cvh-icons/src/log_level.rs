@@ -0,0 +1,120 @@
+//! Switching the log level of a running daemon
+//!
+//! `SIGUSR1` cycles the level info → debug → trace → info, so debug logs of
+//! an intermittent issue can be captured without a restart. The signal is
+//! read in the event loop (see `signals`).
+
+use anyhow::{Context, Result};
+use nix::sys::signal::Signal;
+use std::fmt;
+use tracing::info;
+
+/// Signal that moves to the next log level
+pub const CYCLE_SIGNAL: Signal = Signal::SIGUSR1;
+
+/// Log level the daemon's own messages are shown at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// The level `CYCLE_SIGNAL` switches to from this one
+    pub fn next(self) -> Self {
+        match self {
+            LogLevel::Info => LogLevel::Debug,
+            LogLevel::Debug => LogLevel::Trace,
+            LogLevel::Trace => LogLevel::Info,
+        }
+    }
+
+    /// `EnvFilter` directives for this level; other crates stay at warn
+    pub fn filter(self) -> &'static str {
+        match self {
+            LogLevel::Info => "cvh_icons=info,warn",
+            LogLevel::Debug => "cvh_icons=debug,warn",
+            LogLevel::Trace => "cvh_icons=trace,warn",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The current log level and how to change it
+///
+/// The binary passes a closure that reloads its `tracing` filter; embedders
+/// that manage logging themselves simply don't set one.
+pub struct LogControl {
+    level: LogLevel,
+    apply: Box<dyn FnMut(LogLevel) -> Result<()>>,
+}
+
+impl LogControl {
+    /// Start at `level`, switching levels with `apply`
+    pub fn new(level: LogLevel, apply: impl FnMut(LogLevel) -> Result<()> + 'static) -> Self {
+        Self { level, apply: Box::new(apply) }
+    }
+
+    /// Current level
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Switch to the next level, logging the change
+    pub fn cycle(&mut self) -> Result<LogLevel> {
+        let next = self.level.next();
+        (self.apply)(next).with_context(|| format!("Failed to switch log level to {}", next))?;
+        self.level = next;
+        info!("Log level is now {}", next);
+        Ok(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_levels_cycle_back_to_info() {
+        assert_eq!(LogLevel::Info.next(), LogLevel::Debug);
+        assert_eq!(LogLevel::Debug.next(), LogLevel::Trace);
+        assert_eq!(LogLevel::Trace.next(), LogLevel::Info);
+        assert_eq!(LogLevel::Trace.filter(), "cvh_icons=trace,warn");
+    }
+
+    #[test]
+    fn test_log_control_applies_each_level() {
+        let applied = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&applied);
+        let mut control = LogControl::new(LogLevel::Info, move |level| {
+            sink.borrow_mut().push(level);
+            Ok(())
+        });
+
+        for _ in 0..3 {
+            control.cycle().unwrap();
+        }
+        assert_eq!(*applied.borrow(), vec![LogLevel::Debug, LogLevel::Trace, LogLevel::Info]);
+        assert_eq!(control.level(), LogLevel::Info);
+    }
+
+    #[test]
+    fn test_failed_switch_keeps_level() {
+        let mut control = LogControl::new(LogLevel::Debug, |_| anyhow::bail!("no subscriber"));
+        assert!(control.cycle().is_err());
+        assert_eq!(control.level(), LogLevel::Debug);
+    }
+}
@@ -26,6 +26,45 @@ pub enum DrawCommand {
     Text { text: String, x: f32, y: f32, size: f32, color: String, align: String },
     Image { path: String, x: f32, y: f32, w: f32, h: f32 },
     Clear { color: String },
+    /// Horizontal gauge: `background` across the whole rect, `color` over `fraction` of it
+    Bar { x: f32, y: f32, w: f32, h: f32, fraction: f32, color: String, background: String },
+}
+
+impl DrawCommand {
+    /// The command with its coordinates, sizes and line widths multiplied by `factor`
+    ///
+    /// Widgets draw in surface coordinates; this maps them onto a buffer
+    /// `factor` times the surface size.
+    pub fn scaled(self, factor: f32) -> Self {
+        let s = |v: f32| v * factor;
+        match self {
+            Self::FillRect { x, y, w, h, color } => {
+                Self::FillRect { x: s(x), y: s(y), w: s(w), h: s(h), color }
+            }
+            Self::StrokeRect { x, y, w, h, color, width } => {
+                Self::StrokeRect { x: s(x), y: s(y), w: s(w), h: s(h), color, width: s(width) }
+            }
+            Self::FillCircle { cx, cy, r, color } => {
+                Self::FillCircle { cx: s(cx), cy: s(cy), r: s(r), color }
+            }
+            Self::StrokeCircle { cx, cy, r, color, width } => {
+                Self::StrokeCircle { cx: s(cx), cy: s(cy), r: s(r), color, width: s(width) }
+            }
+            Self::Line { x1, y1, x2, y2, color, width } => {
+                Self::Line { x1: s(x1), y1: s(y1), x2: s(x2), y2: s(y2), color, width: s(width) }
+            }
+            Self::Text { text, x, y, size, color, align } => {
+                Self::Text { text, x: s(x), y: s(y), size: s(size), color, align }
+            }
+            Self::Image { path, x, y, w, h } => {
+                Self::Image { path, x: s(x), y: s(y), w: s(w), h: s(h) }
+            }
+            Self::Clear { color } => Self::Clear { color },
+            Self::Bar { x, y, w, h, fraction, color, background } => {
+                Self::Bar { x: s(x), y: s(y), w: s(w), h: s(h), fraction, color, background }
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -88,6 +127,14 @@ impl UserData for Canvas {
             Ok(())
         });
 
+        methods.add_method_mut(
+            "bar",
+            |_, this, (x, y, w, h, fraction, color, background): (f32, f32, f32, f32, f32, String, String)| {
+                this.commands.push(DrawCommand::Bar { x, y, w, h, fraction, color, background });
+                Ok(())
+            },
+        );
+
         methods.add_method("width", |_, this, ()| Ok(this.width));
         methods.add_method("height", |_, this, ()| Ok(this.height));
     }
@@ -7,10 +7,13 @@ use mlua::{Error as LuaError, Function, Lua, Table, Value};
 use std::path::Path;
 
 pub mod api;
+pub mod pool;
 pub mod process;
+pub mod respawn;
 mod stdlib;
 
 pub use api::DrawCommand;
+pub use pool::{LuaPool, WidgetProcess};
 #[allow(unused_imports)]
 pub use process::LuaProcess;
 
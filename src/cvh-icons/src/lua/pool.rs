@@ -0,0 +1,349 @@
+//! Widget processes shared between icons
+//!
+//! With `lua_pool_size` set, icons don't get a sandboxed process each.
+//! Instead a few worker processes run the widgets of all icons: an icon
+//! binds its widget script to a slot on the least busy worker, and every
+//! request it sends is routed to that slot by id over the worker's usual
+//! stdin/stdout channel. A worker that crashes only takes the icons bound
+//! to it down; they bind again, to a fresh worker, through the usual
+//! respawn path.
+//!
+//! Widgets sharing a worker share its Lua globals, so only pool-safe
+//! scripts should run pooled; `Config::lua_pool_size` says which are.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tracing::debug;
+
+use super::process::{sanitize_widget_actions, LuaProcess};
+use crate::ipc::{Request, Response, WidgetAction};
+use crate::sandbox::SandboxOptions;
+
+/// How long a worker may take to load or unload a widget
+const BIND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A worker process and the number of icons bound to it
+struct Worker {
+    process: LuaProcess,
+    bound: usize,
+}
+
+type SharedWorker = Arc<Mutex<Worker>>;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Default)]
+struct Workers {
+    running: Vec<SharedWorker>,
+    /// Id of the next slot handed out, unique across all workers
+    next_slot: u32,
+}
+
+/// Up to `size` worker processes running the widgets of every icon
+///
+/// Cloning gives another handle to the same workers.
+#[derive(Clone)]
+pub struct LuaPool {
+    size: usize,
+    sandbox_options: SandboxOptions,
+    workers: Arc<Mutex<Workers>>,
+}
+
+impl LuaPool {
+    /// A pool of `size` workers, sandboxed with `sandbox_options`
+    ///
+    /// Every worker can read `script_dirs`, as it may run any of the widgets.
+    pub fn new(size: usize, mut sandbox_options: SandboxOptions, script_dirs: &[PathBuf]) -> Self {
+        sandbox_options.read_only_paths.extend(script_dirs.iter().cloned());
+        Self {
+            size: size.max(1),
+            sandbox_options,
+            workers: Arc::new(Mutex::new(Workers::default())),
+        }
+    }
+
+    /// Load `script_path` into a slot on the least busy worker running `handler_path`
+    ///
+    /// Workers are started as they are needed, up to the pool size.
+    pub fn bind(&self, handler_path: &Path, script_path: &Path) -> Result<PooledWidget> {
+        let (worker, slot) = {
+            let mut workers = lock(&self.workers);
+            let slot = workers.next_slot;
+            workers.next_slot = workers.next_slot.wrapping_add(1);
+            (self.worker_for(&mut workers, handler_path, script_path)?, slot)
+        };
+
+        let mut guard = lock(&worker);
+        let request = Request::Bind { script_path: script_path.to_string_lossy().into_owned() };
+        guard.process.send_routed(slot, &request)?;
+        match guard.process.receive_routed(slot, BIND_TIMEOUT)? {
+            Response::HandshakeAck { actions, refresh_ms, overlay, .. } => {
+                guard.bound += 1;
+                drop(guard);
+                Ok(PooledWidget {
+                    worker,
+                    slot,
+                    widget_actions: sanitize_widget_actions(actions),
+                    refresh_interval: refresh_ms.filter(|&ms| ms > 0).map(Duration::from_millis),
+                    has_overlay: overlay,
+                    released: false,
+                })
+            }
+            Response::Error { message } => bail!("Failed to load {}: {}", script_path.display(), message),
+            other => bail!("Unexpected response to bind: {:?}", other),
+        }
+    }
+
+    /// Number of worker processes currently running
+    pub fn worker_count(&self) -> usize {
+        lock(&self.workers).running.len()
+    }
+
+    /// The worker a new widget should go to, started if need be
+    fn worker_for(
+        &self,
+        workers: &mut Workers,
+        handler_path: &Path,
+        script_path: &Path,
+    ) -> Result<SharedWorker> {
+        // Crashed workers are forgotten, as are idle ones left over from
+        // another handler
+        workers.running.retain(|worker| {
+            let mut worker = lock(worker);
+            worker.process.is_running()
+                && (worker.bound > 0 || worker.process.handler_path() == handler_path)
+        });
+
+        let least_busy = workers
+            .running
+            .iter()
+            .filter(|worker| lock(worker).process.handler_path() == handler_path)
+            .min_by_key(|worker| lock(worker).bound)
+            .cloned();
+        if let Some(worker) = least_busy {
+            if lock(&worker).bound == 0 || workers.running.len() >= self.size {
+                return Ok(worker);
+            }
+        }
+
+        // Until workers of a previous handler are released the pool may
+        // briefly run more workers than its size
+        let process = LuaProcess::spawn(
+            handler_path.to_path_buf(),
+            script_path.to_path_buf(),
+            &self.sandbox_options,
+        )?;
+        debug!("Started Lua worker (pid {}) for {}", process.pid(), handler_path.display());
+        let worker = Arc::new(Mutex::new(Worker { process, bound: 0 }));
+        workers.running.push(worker.clone());
+        Ok(worker)
+    }
+}
+
+/// An icon's widget, running in a slot of a pooled worker
+pub struct PooledWidget {
+    worker: SharedWorker,
+    slot: u32,
+    widget_actions: Vec<WidgetAction>,
+    refresh_interval: Option<Duration>,
+    has_overlay: bool,
+    released: bool,
+}
+
+impl PooledWidget {
+    /// Send a request to the widget
+    pub fn send_request(&mut self, request: &Request) -> Result<()> {
+        lock(&self.worker).process.send_routed(self.slot, request)
+    }
+
+    /// Receive the widget's response with a custom timeout
+    ///
+    /// Late replies meant for other slots on the worker are skipped.
+    pub fn receive_response_with_timeout(&mut self, timeout: Duration) -> Result<Response> {
+        lock(&self.worker).process.receive_routed(self.slot, timeout)
+    }
+
+    /// Whether the widget is loaded and its worker still running
+    pub fn is_running(&mut self) -> bool {
+        !self.released && lock(&self.worker).process.is_running()
+    }
+
+    /// Unload the widget, leaving the worker running for other icons
+    pub fn release(&mut self) -> Result<()> {
+        if std::mem::replace(&mut self.released, true) {
+            return Ok(());
+        }
+        let mut worker = lock(&self.worker);
+        worker.bound = worker.bound.saturating_sub(1);
+        if !worker.process.is_running() {
+            return Ok(());
+        }
+        worker.process.send_routed(self.slot, &Request::Release)?;
+        match worker.process.receive_routed(self.slot, BIND_TIMEOUT)? {
+            Response::Released => Ok(()),
+            other => bail!("Unexpected response to release: {:?}", other),
+        }
+    }
+
+    /// Process ID of the worker
+    pub fn pid(&self) -> u32 {
+        lock(&self.worker).process.pid()
+    }
+
+    /// Context-menu entries the widget advertised
+    pub fn widget_actions(&self) -> &[WidgetAction] {
+        &self.widget_actions
+    }
+
+    /// How often the widget asked to be rendered again, if at all
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval
+    }
+
+    /// Whether the widget draws overlay badges
+    pub fn has_overlay(&self) -> bool {
+        self.has_overlay
+    }
+}
+
+impl Drop for PooledWidget {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+/// The process an icon's widget runs in
+pub enum WidgetProcess {
+    /// A process of the icon's own
+    Own(LuaProcess),
+    /// A slot on a worker shared with other icons
+    Pooled(PooledWidget),
+}
+
+impl WidgetProcess {
+    /// Send a request to the widget
+    pub fn send_request(&mut self, request: &Request) -> Result<()> {
+        match self {
+            Self::Own(process) => process.send_request(request),
+            Self::Pooled(widget) => widget.send_request(request),
+        }
+    }
+
+    /// Receive the widget's response with a custom timeout
+    pub fn receive_response_with_timeout(&mut self, timeout: Duration) -> Result<Response> {
+        match self {
+            Self::Own(process) => process.receive_response_with_timeout(timeout),
+            Self::Pooled(widget) => widget.receive_response_with_timeout(timeout),
+        }
+    }
+
+    /// Whether the widget can still be talked to
+    pub fn is_running(&mut self) -> bool {
+        match self {
+            Self::Own(process) => process.is_running(),
+            Self::Pooled(widget) => widget.is_running(),
+        }
+    }
+
+    /// Stop the widget: its own process is killed, a pooled one released
+    pub fn kill(&mut self) -> Result<()> {
+        match self {
+            Self::Own(process) => process.kill(),
+            Self::Pooled(widget) => widget.release(),
+        }
+    }
+
+    /// Process ID the widget runs in
+    pub fn pid(&self) -> u32 {
+        match self {
+            Self::Own(process) => process.pid(),
+            Self::Pooled(widget) => widget.pid(),
+        }
+    }
+
+    /// Context-menu entries the widget advertised
+    pub fn widget_actions(&self) -> &[WidgetAction] {
+        match self {
+            Self::Own(process) => process.widget_actions(),
+            Self::Pooled(widget) => widget.widget_actions(),
+        }
+    }
+
+    /// How often the widget asked to be rendered again, if at all
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        match self {
+            Self::Own(process) => process.refresh_interval(),
+            Self::Pooled(widget) => widget.refresh_interval(),
+        }
+    }
+
+    /// Whether the widget draws overlay badges
+    pub fn has_overlay(&self) -> bool {
+        match self {
+            Self::Own(process) => process.has_overlay(),
+            Self::Pooled(widget) => widget.has_overlay(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_dying_in_handshake_is_not_kept() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let handler = temp_dir.path().join("ipc_handler.lua");
+        let script = temp_dir.path().join("crash.lua");
+        std::fs::write(&handler, "os.exit(1)\n").unwrap();
+        std::fs::write(&script, "os.exit(1)\n").unwrap();
+
+        let pool = LuaPool::new(2, SandboxOptions::default(), &[temp_dir.path().to_path_buf()]);
+        assert!(pool.bind(&handler, &script).is_err());
+        assert!(pool.bind(&handler, &script).is_err());
+        assert_eq!(pool.worker_count(), 0);
+    }
+
+    #[test]
+    fn test_late_reply_is_not_taken_by_the_next_icon() {
+        // A worker whose widget in slot 1 answers only after 300ms, followed
+        // by the answer for slot 2
+        let script = r#"
+            reply() { printf "\\x$(printf %02x ${#1})\\x00\\x00\\x00%s" "$1"; }
+            sleep 0.3
+            reply '{"type":"Error","message":"slow","icon":1}'
+            reply '{"type":"Overlay","overlay":null,"icon":2}'
+            sleep 5
+        "#;
+        let mut cmd = std::process::Command::new("bash");
+        cmd.args(["-c", script]);
+        let process = LuaProcess::spawn_unsandboxed(cmd, PathBuf::from("ipc_handler.lua")).unwrap();
+        let worker = Arc::new(Mutex::new(Worker { process, bound: 2 }));
+        let widget = |slot| PooledWidget {
+            worker: worker.clone(),
+            slot,
+            widget_actions: Vec::new(),
+            refresh_interval: None,
+            has_overlay: false,
+            released: true,
+        };
+        let (mut slow, mut next) = (widget(1), widget(2));
+
+        slow.send_request(&Request::Overlay).unwrap();
+        assert!(slow.receive_response_with_timeout(Duration::from_millis(50)).is_err());
+
+        next.send_request(&Request::Overlay).unwrap();
+        let response = next.receive_response_with_timeout(Duration::from_secs(2)).unwrap();
+        assert!(
+            matches!(response, Response::Overlay { overlay: None }),
+            "The slow widget's reply went to the next icon: {:?}",
+            response
+        );
+        lock(&worker).process.kill().unwrap();
+    }
+}
@@ -7,12 +7,13 @@ use std::io::{Read, Write};
 use std::os::fd::{AsFd, BorrowedFd};
 use std::path::PathBuf;
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context, Result};
 use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use tracing::{debug, warn};
 
-use crate::ipc::{IpcEncoding, Request, Response, PROTOCOL_VERSION};
+use crate::ipc::{IpcEncoding, Request, Response, Routed, RoutedReply, WidgetAction, PROTOCOL_VERSION};
 use crate::sandbox::SandboxOptions;
 
 /// Default timeout for receiving responses (1 second)
@@ -38,6 +39,12 @@ pub struct LuaProcess {
     icon_script_path: PathBuf,
     /// Whether the handshake has been completed
     handshake_complete: bool,
+    /// Context-menu entries the widget advertised in its handshake
+    widget_actions: Vec<WidgetAction>,
+    /// How often the widget asked to be rendered again
+    refresh_interval: Option<Duration>,
+    /// Whether the widget said it draws overlay badges
+    has_overlay: bool,
 }
 
 #[allow(dead_code)]
@@ -83,6 +90,9 @@ impl LuaProcess {
             handler_path,
             icon_script_path,
             handshake_complete: false,
+            widget_actions: Vec::new(),
+            refresh_interval: None,
+            has_overlay: false,
         };
 
         // Perform protocol handshake
@@ -91,6 +101,25 @@ impl LuaProcess {
         Ok(process)
     }
 
+    /// Run `cmd` as a stand-in worker, without sandbox or handshake
+    #[cfg(test)]
+    pub(crate) fn spawn_unsandboxed(mut cmd: Command, handler_path: PathBuf) -> Result<Self> {
+        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().context("Failed to get child stdin")?;
+        let stdout = child.stdout.take().context("Failed to get child stdout")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            handler_path,
+            icon_script_path: PathBuf::new(),
+            handshake_complete: true,
+            widget_actions: Vec::new(),
+            refresh_interval: None,
+            has_overlay: false,
+        })
+    }
+
     /// Build the bubblewrap command with appropriate arguments
     ///
     /// # Arguments
@@ -225,7 +254,7 @@ impl LuaProcess {
         self.send_request(&request)?;
 
         match self.receive_response()? {
-            Response::HandshakeAck { version, success } => {
+            Response::HandshakeAck { version, success, actions, refresh_ms, overlay } => {
                 if !success {
                     bail!("Handshake failed: version mismatch (local: {}, remote: {})",
                           PROTOCOL_VERSION, version);
@@ -235,6 +264,9 @@ impl LuaProcess {
                           PROTOCOL_VERSION, version);
                 }
                 self.handshake_complete = true;
+                self.widget_actions = sanitize_widget_actions(actions);
+                self.refresh_interval = refresh_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+                self.has_overlay = overlay;
                 Ok(())
             }
             Response::Error { message } => {
@@ -250,7 +282,18 @@ impl LuaProcess {
     pub fn send_request(&mut self, request: &Request) -> Result<()> {
         let data = request.serialize(IpcEncoding::Json)
             .map_err(|e| anyhow::anyhow!("Failed to serialize request: {}", e))?;
+        self.send_message(&data)
+    }
+
+    /// Send a request for the widget in slot `icon` of a pooled worker
+    pub fn send_routed(&mut self, icon: u32, request: &Request) -> Result<()> {
+        let data = serde_json::to_vec(&Routed { icon, request })
+            .context("Failed to serialize request")?;
+        self.send_message(&data)
+    }
 
+    /// Write one length-prefixed message to stdin
+    fn send_message(&mut self, data: &[u8]) -> Result<()> {
         if data.len() > MAX_MESSAGE_SIZE {
             bail!("Request too large: {} bytes (max: {})", data.len(), MAX_MESSAGE_SIZE);
         }
@@ -263,7 +306,7 @@ impl LuaProcess {
 
         // Write the actual data
         self.stdin
-            .write_all(&data)
+            .write_all(data)
             .context("Failed to write message data")?;
 
         self.stdin.flush().context("Failed to flush stdin")?;
@@ -281,6 +324,38 @@ impl LuaProcess {
     /// Uses poll() to wait for data with a timeout, preventing indefinite blocking
     /// on dead or unresponsive child processes.
     pub fn receive_response_with_timeout(&mut self, timeout: Duration) -> Result<Response> {
+        let data = self.receive_message(timeout)?;
+
+        // Deserialize the response using JSON
+        let response = Response::deserialize(&data, IpcEncoding::Json)
+            .map_err(|e| anyhow::anyhow!("Failed to deserialize response: {}", e))?;
+
+        Ok(response)
+    }
+
+    /// Receive the response of the widget in slot `icon` of a pooled worker
+    ///
+    /// Workers echo the slot in every response. Replies for other slots
+    /// arrive when a widget answers after its icon gave up waiting; they are
+    /// dropped so they can't be taken for the answer to a later request.
+    pub fn receive_routed(&mut self, icon: u32, timeout: Duration) -> Result<Response> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let data = self.receive_message(remaining)?;
+            let reply: RoutedReply = serde_json::from_slice(&data)
+                .context("Failed to deserialize response")?;
+            if reply.icon != Some(icon) {
+                debug!("Dropping late reply for slot {:?} while waiting on slot {}", reply.icon, icon);
+                continue;
+            }
+            return Response::deserialize(&data, IpcEncoding::Json)
+                .map_err(|e| anyhow::anyhow!("Failed to deserialize response: {}", e));
+        }
+    }
+
+    /// Read one length-prefixed message from stdout
+    fn receive_message(&mut self, timeout: Duration) -> Result<Vec<u8>> {
         // Read length prefix (4 bytes, little-endian) with timeout
         let mut len_bytes = [0u8; 4];
         self.read_exact_with_timeout(&mut len_bytes, timeout)
@@ -297,11 +372,7 @@ impl LuaProcess {
         self.read_exact_with_timeout(&mut data, timeout)
             .context("Failed to read message data")?;
 
-        // Deserialize the response using JSON
-        let response = Response::deserialize(&data, IpcEncoding::Json)
-            .map_err(|e| anyhow::anyhow!("Failed to deserialize response: {}", e))?;
-
-        Ok(response)
+        Ok(data)
     }
 
     /// Read exactly `buf.len()` bytes from stdout with a timeout
@@ -416,6 +487,38 @@ impl LuaProcess {
     pub fn pid(&self) -> u32 {
         self.child.id()
     }
+
+    /// Context-menu entries the widget advertised
+    pub fn widget_actions(&self) -> &[WidgetAction] {
+        &self.widget_actions
+    }
+
+    /// How often the widget asked to be rendered again, if at all
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval
+    }
+
+    /// Whether the widget draws overlay badges
+    pub fn has_overlay(&self) -> bool {
+        self.has_overlay
+    }
+}
+
+/// Drop widget actions without an id or label, and repeated ids
+pub(super) fn sanitize_widget_actions(actions: Vec<WidgetAction>) -> Vec<WidgetAction> {
+    let mut kept: Vec<WidgetAction> = Vec::with_capacity(actions.len());
+    for action in actions {
+        if action.id.is_empty() || action.label.trim().is_empty() {
+            warn!("Ignoring widget action without an id or label: {:?}", action);
+            continue;
+        }
+        if kept.iter().any(|k| k.id == action.id) {
+            warn!("Ignoring repeated widget action {:?}", action.id);
+            continue;
+        }
+        kept.push(action);
+    }
+    kept
 }
 
 impl Drop for LuaProcess {
@@ -439,6 +542,19 @@ mod tests {
         assert_eq!(MAX_MESSAGE_SIZE, 1024 * 1024);
     }
 
+    #[test]
+    fn test_sanitize_widget_actions() {
+        let action = |id: &str, label: &str| WidgetAction { id: id.to_string(), label: label.to_string() };
+        let actions = sanitize_widget_actions(vec![
+            action("edit", "Edit"),
+            action("", "No id"),
+            action("blank", "  "),
+            action("edit", "Edit again"),
+            action("wallpaper", "Set as Wallpaper"),
+        ]);
+        assert_eq!(actions, vec![action("edit", "Edit"), action("wallpaper", "Set as Wallpaper")]);
+    }
+
     #[test]
     fn test_build_bwrap_command_basic() {
         let options = SandboxOptions::default();
@@ -786,6 +902,9 @@ mod tests {
             let response = Response::HandshakeAck {
                 version: PROTOCOL_VERSION,
                 success: true,
+                actions: Vec::new(),
+                refresh_ms: None,
+                overlay: false,
             };
             let data = response.serialize(IpcEncoding::Json).unwrap();
 
@@ -807,7 +926,7 @@ mod tests {
 
         let response = Response::deserialize(&data, IpcEncoding::Json).unwrap();
         match response {
-            Response::HandshakeAck { version, success } => {
+            Response::HandshakeAck { version, success, .. } => {
                 assert_eq!(version, PROTOCOL_VERSION);
                 assert!(success);
             }
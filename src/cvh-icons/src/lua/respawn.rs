@@ -0,0 +1,182 @@
+//! Crash-loop protection for icon Lua processes
+//!
+//! A process that exits sooner than the configured minimum uptime counts as a
+//! quick crash. Each consecutive quick crash doubles the delay before the next
+//! respawn, and after `MAX_QUICK_CRASHES` respawning is disabled for good so a
+//! broken script cannot pin a CPU. A process that stays up long enough resets
+//! the streak.
+
+use std::time::{Duration, Instant};
+
+/// Consecutive quick crashes tolerated before respawning is disabled
+pub const MAX_QUICK_CRASHES: u32 = 5;
+
+/// Delay before the first respawn after a quick crash
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+
+/// Upper bound for the respawn delay
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// What the guard decided after a process exit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitVerdict {
+    /// The process ran long enough; respawn right away
+    Respawn,
+    /// Quick crash; respawn no earlier than after the delay
+    Backoff { uptime: Duration, delay: Duration, streak: u32 },
+    /// Too many quick crashes in a row; do not respawn again
+    Disabled { streak: u32 },
+}
+
+/// Tracks process lifetimes to detect crash loops
+#[derive(Debug, Clone)]
+pub struct RespawnGuard {
+    min_uptime: Duration,
+    spawned_at: Option<Instant>,
+    quick_crashes: u32,
+    retry_at: Option<Instant>,
+    disabled: bool,
+}
+
+impl RespawnGuard {
+    /// Create a guard treating exits within `min_uptime` of spawning as crashes
+    pub fn new(min_uptime: Duration) -> Self {
+        Self {
+            min_uptime,
+            spawned_at: None,
+            quick_crashes: 0,
+            retry_at: None,
+            disabled: false,
+        }
+    }
+
+    /// Record that a process was (re)spawned at `now`
+    pub fn record_spawn(&mut self, now: Instant) {
+        self.spawned_at = Some(now);
+        self.retry_at = None;
+    }
+
+    /// Record that the process exited (or failed to start) at `now`
+    pub fn record_exit(&mut self, now: Instant) -> ExitVerdict {
+        let uptime = self
+            .spawned_at
+            .take()
+            .map(|at| now.saturating_duration_since(at))
+            .unwrap_or_default();
+
+        if uptime >= self.min_uptime {
+            self.quick_crashes = 0;
+            self.retry_at = None;
+            return ExitVerdict::Respawn;
+        }
+
+        self.quick_crashes += 1;
+        if self.quick_crashes >= MAX_QUICK_CRASHES {
+            self.disabled = true;
+            return ExitVerdict::Disabled { streak: self.quick_crashes };
+        }
+
+        let delay = BACKOFF_BASE
+            .saturating_mul(1 << (self.quick_crashes - 1))
+            .min(BACKOFF_MAX);
+        self.retry_at = Some(now + delay);
+        ExitVerdict::Backoff { uptime, delay, streak: self.quick_crashes }
+    }
+
+    /// Whether a respawn may be attempted at `now`
+    pub fn can_respawn(&self, now: Instant) -> bool {
+        !self.disabled && self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// Minimum uptime below which an exit counts as a crash
+    pub fn min_uptime(&self) -> Duration {
+        self.min_uptime
+    }
+
+    /// Whether respawning has been given up on
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Allow the next respawn right away (for tests that cannot wait out delays)
+    #[cfg(test)]
+    pub fn clear_backoff(&mut self) {
+        self.retry_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_UPTIME: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn test_long_lived_exit_respawns_immediately() {
+        let mut guard = RespawnGuard::new(MIN_UPTIME);
+        let start = Instant::now();
+        guard.record_spawn(start);
+
+        assert_eq!(guard.record_exit(start + Duration::from_secs(60)), ExitVerdict::Respawn);
+        assert!(guard.can_respawn(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_quick_crashes_back_off_exponentially() {
+        let mut guard = RespawnGuard::new(MIN_UPTIME);
+        let mut now = Instant::now();
+        let mut delays = Vec::new();
+
+        for _ in 0..3 {
+            guard.record_spawn(now);
+            match guard.record_exit(now + Duration::from_millis(10)) {
+                ExitVerdict::Backoff { delay, .. } => {
+                    now += Duration::from_millis(10);
+                    assert!(!guard.can_respawn(now), "Must wait out the backoff");
+                    now += delay;
+                    assert!(guard.can_respawn(now));
+                    delays.push(delay);
+                }
+                other => panic!("Expected backoff, got {:?}", other),
+            }
+        }
+
+        assert_eq!(delays, vec![BACKOFF_BASE, BACKOFF_BASE * 2, BACKOFF_BASE * 4]);
+    }
+
+    #[test]
+    fn test_crash_loop_disables_respawn() {
+        let mut guard = RespawnGuard::new(MIN_UPTIME);
+        let now = Instant::now();
+
+        let mut verdict = ExitVerdict::Respawn;
+        for _ in 0..MAX_QUICK_CRASHES {
+            guard.record_spawn(now);
+            verdict = guard.record_exit(now);
+        }
+
+        assert_eq!(verdict, ExitVerdict::Disabled { streak: MAX_QUICK_CRASHES });
+        assert!(guard.is_disabled());
+        assert!(!guard.can_respawn(now + BACKOFF_MAX * 2));
+    }
+
+    #[test]
+    fn test_stable_run_resets_streak() {
+        let mut guard = RespawnGuard::new(MIN_UPTIME);
+        let now = Instant::now();
+
+        for _ in 0..MAX_QUICK_CRASHES - 1 {
+            guard.record_spawn(now);
+            guard.record_exit(now);
+        }
+        guard.record_spawn(now);
+        assert_eq!(guard.record_exit(now + MIN_UPTIME), ExitVerdict::Respawn);
+
+        // The streak starts over rather than tipping into Disabled
+        guard.record_spawn(now);
+        assert!(matches!(
+            guard.record_exit(now),
+            ExitVerdict::Backoff { streak: 1, .. }
+        ));
+    }
+}
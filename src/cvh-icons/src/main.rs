@@ -3,19 +3,23 @@
 //! Displays desktop icons for files and folders with customizable
 //! Lua scripts for rendering and behavior.
 
-use anyhow::Result;
-use clap::Parser;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-mod config;
-mod daemon;
-mod icons;
-mod ipc;
-mod lua;
-mod renderer;
-mod sandbox;
-mod wayland;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, info};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+
+use cvh_icons::control::{self, ControlCommand};
+use cvh_icons::log_level::{LogControl, LogLevel};
+use cvh_icons::{config, daemon, error, gnome_layout, lock, signals};
+
+/// Environment variable overriding the desktop directory
+const DESKTOP_DIR_ENV: &str = "CVH_DESKTOP_DIR";
+
+/// Set to "1" to create the `CVH_DESKTOP_DIR` directory if it is missing
+const CREATE_DESKTOP_DIR_ENV: &str = "CVH_CREATE_DESKTOP_DIR";
 
 /// CVH Icons - Desktop icon manager
 #[derive(Parser, Debug)]
@@ -23,12 +27,19 @@ mod wayland;
 #[command(author = "CVH Linux Team")]
 #[command(version = "0.1.0")]
 #[command(about = "Sandboxed Lua-scriptable desktop icons")]
+#[command(after_help = "Exit codes:\n  \
+    0  clean shutdown\n  \
+    1  unspecified failure\n  \
+    2  could not connect to the Wayland display\n  \
+    3  compositor does not support wlr-layer-shell\n  \
+    4  configuration could not be read or parsed\n  \
+    5  another instance is already running")]
 struct Args {
     /// Configuration file path
     #[arg(short, long)]
     config: Option<std::path::PathBuf>,
 
-    /// Desktop directory to display icons for
+    /// Desktop directory to display icons for (takes precedence over CVH_DESKTOP_DIR)
     #[arg(short, long)]
     desktop: Option<std::path::PathBuf>,
 
@@ -43,27 +54,114 @@ struct Args {
     /// List available icon scripts
     #[arg(long)]
     list_scripts: bool,
+
+    /// Log every filesystem event the watcher delivers (for debugging icons not updating)
+    #[arg(long)]
+    trace_fs_events: bool,
+
+    /// Draw the icon grid's cells and layout indices (for tuning spacing)
+    #[arg(long)]
+    debug_grid: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands sent to the running daemon over its control socket
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the icon arrangement as JSON
+    ExportLayout,
+
+    /// Apply an arrangement printed by export-layout ("-" reads stdin)
+    ImportLayout {
+        file: PathBuf,
+    },
+
+    /// Place icons where GNOME's desktop-icons extension last had them
+    ImportGnomeLayout,
+
+    /// Check that the daemon is responsive and print its health as JSON
+    Ping,
+
+    /// Toggle the layout debugging grid of the running daemon
+    DebugGrid {
+        /// Show ("on") or hide ("off") it instead of toggling
+        #[arg(value_parser = ["on", "off"])]
+        state: Option<String>,
+    },
+
+    /// Print which Lua handler and widget scripts the daemon resolved, as JSON
+    LuaStatus,
+
+    /// Freeze the desktop icons (e.g. during a large file operation)
+    Pause,
+
+    /// Unfreeze the desktop icons and catch up with changes made meanwhile
+    Resume,
+
+    /// Make a file's icon flash for a moment, e.g. when a download finishes
+    Flash {
+        path: PathBuf,
+    },
+
+    /// Rescan the desktop directory of the running daemon
+    Refresh,
+
+    /// Make the running daemon re-read its configuration file
+    ReloadConfig,
+
+    /// Print the path and grid cell of every icon as JSON
+    List,
+
+    /// Keep a file's icon on the output with this connector name (e.g. DP-1)
+    PinOutput {
+        path: PathBuf,
+        /// Connector name; leave out to unpin the icon
+        output: Option<String>,
+    },
+
+    /// Stop the running daemon
+    Quit,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = Args::parse();
 
-    // Initialize logging
-    let filter = if args.verbose {
-        "cvh_icons=debug,warn"
-    } else {
-        "cvh_icons=info,warn"
-    };
+    // Initialize logging, with a filter SIGUSR1 can swap at runtime
+    let level = if args.verbose { LogLevel::Debug } else { LogLevel::Info };
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(level.filter()));
 
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(tracing_subscriber::EnvFilter::new(filter))
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     info!("CVH Icons v{}", env!("CARGO_PKG_VERSION"));
 
+    let log_control = LogControl::new(level, move |level| {
+        filter_handle.reload(EnvFilter::new(level.filter()))?;
+        Ok(())
+    });
+
+    match run(args, log_control) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{:#}", e);
+            ExitCode::from(error::exit_code_for(&e))
+        }
+    }
+}
+
+fn run(args: Args, log_control: LogControl) -> Result<()> {
+    if let Some(command) = args.command {
+        return run_command(command, &control::socket_path(), args.desktop);
+    }
+
     // Load configuration
-    let config = config::Config::load(args.config.as_deref())?;
+    let mut config = config::Config::load(args.config.as_deref())?;
+    config.trace_fs_events |= args.trace_fs_events;
+    config.debug_grid |= args.debug_grid;
 
     if args.list_scripts {
         // List available Lua scripts
@@ -72,18 +170,26 @@ fn main() -> Result<()> {
     }
 
     // Determine desktop directory
-    let desktop_dir = args.desktop
-        .or_else(|| dirs::desktop_dir())
-        .unwrap_or_else(|| {
-            dirs::home_dir()
-                .map(|h| h.join("Desktop"))
-                .unwrap_or_else(|| std::path::PathBuf::from("."))
-        });
+    let desktop_dir = resolve_desktop_dir(
+        args.desktop,
+        std::env::var_os(DESKTOP_DIR_ENV).map(PathBuf::from),
+        std::env::var(CREATE_DESKTOP_DIR_ENV).is_ok_and(|v| v == "1"),
+    )?;
 
     info!("Desktop directory: {}", desktop_dir.display());
 
+    // Make sure only one daemon manages the desktop; held until run() returns
+    let _instance_lock = lock::InstanceLock::acquire(&lock::InstanceLock::default_path())?;
+
+    // Before the daemon spawns any threads, so they all leave SIGUSR1, SIGHUP,
+    // SIGINT and SIGTERM to the loop
+    signals::block(&signals::HANDLED_SIGNALS)?;
+
     // Initialize the daemon
     let mut daemon = daemon::IconDaemon::new(config, desktop_dir)?;
+    daemon.set_log_control(log_control);
+    daemon.enable_reload(args.config);
+    daemon.enable_stop_signals();
 
     // Run the main loop (uses calloop event loop)
     daemon.run()?;
@@ -91,6 +197,139 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Pick the desktop directory
+///
+/// Precedence: `--desktop`, then `CVH_DESKTOP_DIR`, then XDG, then `~/Desktop`.
+/// A directory given through the environment must exist unless `create` is set,
+/// in which case it is created.
+fn resolve_desktop_dir(cli: Option<PathBuf>, env: Option<PathBuf>, create: bool) -> Result<PathBuf> {
+    if let Some(dir) = cli {
+        return Ok(dir);
+    }
+
+    if let Some(dir) = env.filter(|d| !d.as_os_str().is_empty()) {
+        if !dir.is_dir() {
+            if !create {
+                anyhow::bail!(
+                    "{} points to a missing directory: {} (set {}=1 to create it)",
+                    DESKTOP_DIR_ENV,
+                    dir.display(),
+                    CREATE_DESKTOP_DIR_ENV
+                );
+            }
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create desktop directory {}", dir.display()))?;
+            info!("Created desktop directory {}", dir.display());
+        }
+        return Ok(dir);
+    }
+
+    Ok(dirs::desktop_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .map(|h| h.join("Desktop"))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }))
+}
+
+/// Send a subcommand to the running daemon and print the result
+///
+/// `desktop` is the `--desktop` option, for commands that read the desktop
+/// directory themselves.
+fn run_command(command: Command, socket: &Path, desktop: Option<PathBuf>) -> Result<()> {
+    match command {
+        Command::ExportLayout => {
+            let reply = control::send(socket, &ControlCommand::ExportLayout)?;
+            let layout = reply.data.context("Daemon returned no layout")?;
+            println!("{}", serde_json::to_string_pretty(&layout)?);
+        }
+        Command::ImportLayout { file } => {
+            let mut contents = String::new();
+            if file == Path::new("-") {
+                std::io::stdin().read_to_string(&mut contents)?;
+            } else {
+                contents = std::fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+            }
+            let data = serde_json::from_str(&contents).context("Layout is not valid JSON")?;
+
+            let reply = control::send(socket, &ControlCommand::ImportLayout { data })?;
+            let skipped = reply.data.as_ref().and_then(|data| data["skipped"].as_array());
+            for name in skipped.into_iter().flatten().filter_map(|name| name.as_str()) {
+                println!("Skipped {} (not on this desktop or outside the grid)", name);
+            }
+        }
+        Command::ImportGnomeLayout => {
+            let env = std::env::var_os(DESKTOP_DIR_ENV).map(PathBuf::from);
+            let desktop_dir = resolve_desktop_dir(desktop, env, false)?;
+            let layout = gnome_layout::read_layout(&desktop_dir)?;
+            if layout.icons.is_empty() {
+                println!("No GNOME icon positions found in {}", desktop_dir.display());
+                return Ok(());
+            }
+
+            let data = serde_json::to_value(&layout)?;
+            let reply = control::send(socket, &ControlCommand::ImportLayout { data })?;
+            let skipped = reply.data.as_ref().and_then(|data| data["skipped"].as_array());
+            let mut skipped_count = 0;
+            for name in skipped.into_iter().flatten().filter_map(|name| name.as_str()) {
+                println!("Skipped {} (not on this desktop or outside the grid)", name);
+                skipped_count += 1;
+            }
+            let total = layout.icons.len();
+            println!("Imported {} of {} icon positions", total - skipped_count, total);
+        }
+        Command::Ping => {
+            let reply = control::send(socket, &ControlCommand::Ping)?;
+            let health = reply.data.context("Daemon returned no health data")?;
+            println!("{}", health);
+        }
+        Command::DebugGrid { state } => {
+            let enabled = state.map(|state| state == "on");
+            let reply = control::send(socket, &ControlCommand::DebugGrid { enabled })?;
+            let shown = reply.data.as_ref().and_then(|data| data["enabled"].as_bool());
+            println!("Debug grid {}", if shown == Some(true) { "shown" } else { "hidden" });
+        }
+        Command::LuaStatus => {
+            let reply = control::send(socket, &ControlCommand::LuaStatus)?;
+            let status = reply.data.context("Daemon returned no Lua status")?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        }
+        Command::Pause => {
+            control::send(socket, &ControlCommand::Pause)?;
+        }
+        Command::Resume => {
+            control::send(socket, &ControlCommand::Resume)?;
+        }
+        Command::Flash { path } => {
+            // The daemon knows icons by absolute path; the file may not exist yet
+            let path = std::path::absolute(&path).unwrap_or(path);
+            let reply = control::send(socket, &ControlCommand::Flash { path: path.clone() })?;
+            if reply.data.as_ref().is_some_and(|data| data["queued"] == true) {
+                println!("No icon for {} yet, it will flash once it appears", path.display());
+            }
+        }
+        Command::Refresh => {
+            control::send(socket, &ControlCommand::Refresh)?;
+        }
+        Command::ReloadConfig => {
+            control::send(socket, &ControlCommand::ReloadConfig)?;
+        }
+        Command::List => {
+            let reply = control::send(socket, &ControlCommand::List)?;
+            let icons = reply.data.context("Daemon returned no icons")?;
+            println!("{}", serde_json::to_string_pretty(&icons)?);
+        }
+        Command::PinOutput { path, output } => {
+            let path = std::path::absolute(&path).unwrap_or(path);
+            control::send(socket, &ControlCommand::PinOutput { path, output })?;
+        }
+        Command::Quit => {
+            control::send(socket, &ControlCommand::Quit)?;
+        }
+    }
+    Ok(())
+}
+
 fn list_scripts(config: &config::Config) -> Result<()> {
     println!("Available icon scripts:");
     println!();
@@ -116,3 +355,44 @@ fn list_scripts(config: &config::Config) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cli_desktop_dir_wins() {
+        let dir = resolve_desktop_dir(
+            Some(PathBuf::from("/from/cli")),
+            Some(PathBuf::from("/from/env")),
+            false,
+        )
+        .unwrap();
+        assert_eq!(dir, PathBuf::from("/from/cli"));
+    }
+
+    #[test]
+    fn test_env_desktop_dir_overrides_xdg() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = resolve_desktop_dir(None, Some(temp_dir.path().to_path_buf()), false).unwrap();
+        assert_eq!(dir, temp_dir.path());
+    }
+
+    #[test]
+    fn test_missing_env_desktop_dir_is_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing");
+        assert!(resolve_desktop_dir(None, Some(missing.clone()), false).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_missing_env_desktop_dir_is_created_on_request() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("a/b");
+        let dir = resolve_desktop_dir(None, Some(missing.clone()), true).unwrap();
+        assert_eq!(dir, missing);
+        assert!(missing.is_dir());
+    }
+}
@@ -0,0 +1,197 @@
+//! Mount table lookups
+//!
+//! Used to tell a file that was deleted from one whose filesystem went away
+//! (e.g. a USB stick holding the desktop was unplugged), and to find the
+//! volumes shown as desktop icons.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Current mount points, from `/proc/self/mounts`
+pub fn mount_points() -> Vec<PathBuf> {
+    fs::read_to_string("/proc/self/mounts")
+        .map(|contents| parse_mounts(&contents))
+        .unwrap_or_default()
+}
+
+/// Mount points listed in a `/proc/self/mounts`-style table
+pub fn parse_mounts(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|field| PathBuf::from(unescape(field)))
+        .collect()
+}
+
+/// Undo the octal escaping (`\040` for space etc.) used in mount tables
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape.and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()) {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Directories removable media and user mounts live under
+const VOLUME_ROOTS: [&str; 3] = ["/run/media", "/media", "/mnt"];
+
+/// Mount points of user volumes (USB sticks, network shares, ...)
+///
+/// Mounts nested inside another volume are part of that volume.
+pub fn volumes(mounts: &[PathBuf]) -> Vec<PathBuf> {
+    let candidates: Vec<&PathBuf> = mounts
+        .iter()
+        .filter(|mount| {
+            VOLUME_ROOTS
+                .iter()
+                .any(|root| mount.starts_with(root) && mount.as_path() != Path::new(root))
+        })
+        .collect();
+
+    let mut volumes: Vec<PathBuf> = candidates
+        .iter()
+        .filter(|mount| !candidates.iter().any(|other| other != *mount && mount.starts_with(other)))
+        .map(|mount| (*mount).clone())
+        .collect();
+    volumes.sort();
+    volumes.dedup();
+    volumes
+}
+
+/// Space on a mounted filesystem, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeUsage {
+    pub total: u64,
+    /// Space available to unprivileged users
+    pub free: u64,
+}
+
+impl VolumeUsage {
+    /// Fraction of the volume in use, from 0.0 to 1.0
+    pub fn used_fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.total - self.free.min(self.total)) as f32 / self.total as f32
+    }
+}
+
+/// Space on the filesystem holding `path`, or None if it cannot be queried
+pub fn usage(path: &Path) -> Option<VolumeUsage> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    let fragment = stat.fragment_size() as u64;
+    Some(VolumeUsage {
+        total: stat.blocks() as u64 * fragment,
+        free: stat.blocks_available() as u64 * fragment,
+    })
+}
+
+/// Innermost mount point containing `path`, ignoring the root filesystem
+pub fn mount_point_of(path: &Path, mounts: &[PathBuf]) -> Option<PathBuf> {
+    mounts
+        .iter()
+        .filter(|mount| mount.as_path() != Path::new("/") && path.starts_with(mount))
+        .max_by_key(|mount| mount.components().count())
+        .cloned()
+}
+
+/// What a metadata probe says about a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Present,
+    /// The file is not there (ENOENT)
+    Missing,
+    /// The filesystem did not answer (EIO, ENOTCONN, ESTALE, ...)
+    Unreachable,
+}
+
+/// Probe `path`, following symlinks like `Path::exists`
+pub fn probe(path: &Path) -> FileState {
+    match fs::metadata(path) {
+        Ok(_) => FileState::Present,
+        Err(e) if e.kind() == ErrorKind::NotFound => FileState::Missing,
+        Err(_) => FileState::Unreachable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MOUNTS: &str = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+proc /proc proc rw,nosuid 0 0
+/dev/sdb1 /run/media/user/USB\\040Stick vfat rw,nosuid 0 0
+/dev/sdc1 /run/media/user/USB\\040Stick/inner ext4 rw 0 0
+";
+
+    #[test]
+    fn test_parse_mounts_unescapes_spaces() {
+        let mounts = parse_mounts(MOUNTS);
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[2], PathBuf::from("/run/media/user/USB Stick"));
+    }
+
+    #[test]
+    fn test_mount_point_of_picks_innermost() {
+        let mounts = parse_mounts(MOUNTS);
+
+        assert_eq!(
+            mount_point_of(Path::new("/run/media/user/USB Stick/Desktop/a.txt"), &mounts),
+            Some(PathBuf::from("/run/media/user/USB Stick"))
+        );
+        assert_eq!(
+            mount_point_of(Path::new("/run/media/user/USB Stick/inner/b"), &mounts),
+            Some(PathBuf::from("/run/media/user/USB Stick/inner"))
+        );
+        assert_eq!(mount_point_of(Path::new("/home/user/Desktop/a.txt"), &mounts), None);
+    }
+
+    #[test]
+    fn test_volumes_skip_system_and_nested_mounts() {
+        let mut mounts = parse_mounts(MOUNTS);
+        mounts.push(PathBuf::from("/mnt"));
+        mounts.push(PathBuf::from("/mnt/backup"));
+
+        assert_eq!(
+            volumes(&mounts),
+            vec![PathBuf::from("/mnt/backup"), PathBuf::from("/run/media/user/USB Stick")]
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let stats = usage(temp_dir.path()).unwrap();
+        assert!(stats.free <= stats.total);
+        assert!((0.0..=1.0).contains(&stats.used_fraction()));
+
+        assert_eq!(usage(&temp_dir.path().join("missing")), None);
+        assert_eq!(VolumeUsage { total: 0, free: 0 }.used_fraction(), 0.0);
+        assert_eq!(VolumeUsage { total: 100, free: 25 }.used_fraction(), 0.75);
+    }
+
+    #[test]
+    fn test_probe() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        assert_eq!(probe(&file), FileState::Missing);
+
+        fs::write(&file, "x").unwrap();
+        assert_eq!(probe(&file), FileState::Present);
+    }
+}
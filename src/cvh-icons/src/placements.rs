@@ -0,0 +1,409 @@
+//! Icons placed at specific grid cells
+//!
+//! Cells chosen with the `place` control command are remembered per file in
+//! `$XDG_STATE_HOME/cvh-icons/placements.json`, so they survive restarts.
+//! Icons without a placement flow into the free cells in between. Placements
+//! of desktop files deleted while the daemon wasn't watching are dropped on
+//! the next scan.
+//!
+//! With `snap_to_grid` off, an icon dropped between cells keeps its cell and
+//! remembers how far from it it was dropped, so it can be drawn there.
+//!
+//! An icon can also be pinned to an output by its connector name (`DP-1`),
+//! so it stays on that monitor whichever order outputs are announced in.
+//!
+//! A whole arrangement can be exported as a `Layout` and imported on another
+//! machine, where it becomes a set of placements.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// A cell of the icon grid, counted from the top-left of the usable area
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cell {
+    pub col: u32,
+    pub row: u32,
+}
+
+impl Cell {
+    /// Layout index of the cell in a row-major grid `columns` wide
+    pub fn index(self, columns: u32) -> u32 {
+        self.row * columns + self.col
+    }
+}
+
+/// Placed icons by path, optionally backed by a file
+#[derive(Debug, Default)]
+pub struct Placements {
+    /// Where placements are saved; None keeps them in memory only
+    file: Option<PathBuf>,
+    cells: BTreeMap<PathBuf, Cell>,
+    /// Pixels from its cell each freely dropped icon sits at
+    offsets: BTreeMap<PathBuf, (i32, i32)>,
+    /// Output each pinned icon lives on, by connector name
+    outputs: BTreeMap<PathBuf, String>,
+}
+
+/// Contents of the placements file
+///
+/// Files without offsets or outputs are just the map of cells, as before
+/// those existed.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Offset {
+        cells: BTreeMap<PathBuf, Cell>,
+        offsets: BTreeMap<PathBuf, (i32, i32)>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        outputs: BTreeMap<PathBuf, String>,
+    },
+    Cells(BTreeMap<PathBuf, Cell>),
+}
+
+impl Placements {
+    /// Default placements file location
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::state_dir().map(|dir| dir.join("cvh-icons").join("placements.json"))
+    }
+
+    /// Load the placements at the default location, starting empty on failure
+    pub fn load_default() -> Self {
+        let Some(file) = Self::default_path() else {
+            return Self::default();
+        };
+        Self::load(&file).unwrap_or_else(|e| {
+            warn!("{:#} (starting without icon placements)", e);
+            Self { file: Some(file), ..Self::default() }
+        })
+    }
+
+    /// Load placements from `file`; a missing file means no placements yet
+    pub fn load(file: &Path) -> Result<Self> {
+        let stored = match fs::read_to_string(file) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid placements file {}", file.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Stored::Cells(BTreeMap::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", file.display()));
+            }
+        };
+        let (cells, offsets, outputs) = match stored {
+            Stored::Offset { cells, offsets, outputs } => (cells, offsets, outputs),
+            Stored::Cells(cells) => (cells, BTreeMap::new(), BTreeMap::new()),
+        };
+        Ok(Self { file: Some(file.to_path_buf()), cells, offsets, outputs })
+    }
+
+    /// Write placements back to their file (no-op for in-memory placements)
+    pub fn save(&self) -> Result<()> {
+        let Some(ref file) = self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        // Write-then-rename so a crash never leaves a truncated file
+        let tmp = file.with_extension("json.tmp");
+        let contents = match self.offsets.is_empty() && self.outputs.is_empty() {
+            true => serde_json::to_vec_pretty(&self.cells)?,
+            false => serde_json::to_vec_pretty(&Stored::Offset {
+                cells: self.cells.clone(),
+                offsets: self.offsets.clone(),
+                outputs: self.outputs.clone(),
+            })?,
+        };
+        fs::write(&tmp, contents)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, file).with_context(|| format!("Failed to replace {}", file.display()))?;
+        Ok(())
+    }
+
+    /// Cell the icon for `path` was placed at, if any
+    pub fn get(&self, path: &Path) -> Option<Cell> {
+        self.cells.get(path).copied()
+    }
+
+    /// Place `path` at `cell`, or forget its placement with None
+    ///
+    /// Any offset from its previous cell is forgotten.
+    pub fn set(&mut self, path: &Path, cell: Option<Cell>) {
+        self.offsets.remove(path);
+        match cell {
+            Some(cell) => {
+                self.cells.insert(path.to_path_buf(), cell);
+            }
+            None => {
+                self.cells.remove(path);
+            }
+        }
+    }
+
+    /// Pixels from its cell the icon for `path` was dropped at, if placed freely
+    pub fn offset(&self, path: &Path) -> Option<(i32, i32)> {
+        self.offsets.get(path).copied()
+    }
+
+    /// Remember that `path` sits `offset` pixels from its cell
+    ///
+    /// Only placed icons have an offset; others are left as they are.
+    pub fn set_offset(&mut self, path: &Path, offset: (i32, i32)) {
+        if self.cells.contains_key(path) {
+            self.offsets.insert(path.to_path_buf(), offset);
+        }
+    }
+
+    /// Output the icon for `path` is pinned to, if any
+    pub fn output(&self, path: &Path) -> Option<&str> {
+        self.outputs.get(path).map(String::as_str)
+    }
+
+    /// Pin the icon for `path` to the output named `output`, or unpin it with None
+    pub fn set_output(&mut self, path: &Path, output: Option<&str>) {
+        match output {
+            Some(output) => {
+                self.outputs.insert(path.to_path_buf(), output.to_string());
+            }
+            None => {
+                self.outputs.remove(path);
+            }
+        }
+    }
+
+    /// Forget the placements of files directly in `dir` that no longer exist
+    ///
+    /// Files elsewhere (the trash, mounted volumes) are kept, as they can come
+    /// back, and so are dangling symlinks, which still have an icon. Returns
+    /// the paths dropped.
+    pub fn prune_missing(&mut self, dir: &Path) -> Vec<PathBuf> {
+        let mut missing: Vec<PathBuf> = self
+            .cells
+            .keys()
+            .chain(self.outputs.keys())
+            .filter(|path| path.parent() == Some(dir) && path.symlink_metadata().is_err())
+            .cloned()
+            .collect();
+        missing.sort();
+        missing.dedup();
+        for path in &missing {
+            self.set(path, None);
+            self.outputs.remove(path);
+        }
+        missing
+    }
+
+    /// Paths placed at `cell`
+    pub fn at(&self, cell: Cell) -> Vec<PathBuf> {
+        self.cells
+            .iter()
+            .filter(|(_, c)| **c == cell)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Current version of the exported layout format
+pub const LAYOUT_VERSION: u32 = 1;
+
+/// Exported arrangement: the cell of every desktop icon, by file name
+///
+/// File names rather than full paths, so a layout still applies when the
+/// desktop directory lives somewhere else. The map keeps the JSON sorted, so
+/// two exports of the same arrangement are identical. Icons pinned to an
+/// output are listed in `outputs` too, by connector name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    pub version: u32,
+    pub icons: BTreeMap<String, Cell>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+}
+
+impl Layout {
+    pub fn new(icons: BTreeMap<String, Cell>) -> Self {
+        Self { version: LAYOUT_VERSION, icons, outputs: BTreeMap::new() }
+    }
+
+    /// Read an exported layout, checking its version first
+    ///
+    /// A layout without a version is taken to be version 1. Layouts from a
+    /// newer format are rejected with a message saying so, rather than a
+    /// confusing parse error about whatever field changed.
+    pub fn from_json(value: serde_json::Value) -> Result<Self> {
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .with_context(|| format!("Invalid layout version {}", version))?,
+        };
+        if version == 0 || version > LAYOUT_VERSION {
+            bail!(
+                "Layout format version {} is not supported (this version reads 1 to {})",
+                version,
+                LAYOUT_VERSION
+            );
+        }
+
+        let icons = value.get("icons").cloned().context("Layout has no \"icons\" map")?;
+        let icons = serde_json::from_value(icons).context("Invalid layout")?;
+        let outputs = match value.get("outputs") {
+            Some(outputs) => {
+                serde_json::from_value(outputs.clone()).context("Invalid layout outputs")?
+            }
+            None => BTreeMap::new(),
+        };
+        Ok(Self { outputs, ..Self::new(icons) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cell_index_is_row_major() {
+        assert_eq!(Cell { col: 0, row: 0 }.index(5), 0);
+        assert_eq!(Cell { col: 2, row: 1 }.index(5), 7);
+    }
+
+    #[test]
+    fn test_placements_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("state").join("placements.json");
+
+        let mut placements = Placements::load(&file).unwrap();
+        assert_eq!(placements.get(Path::new("/d/a.txt")), None);
+
+        placements.set(Path::new("/d/a.txt"), Some(Cell { col: 2, row: 1 }));
+        placements.set(Path::new("/d/b.txt"), Some(Cell { col: 0, row: 0 }));
+        placements.set(Path::new("/d/b.txt"), None);
+        placements.save().unwrap();
+
+        let loaded = Placements::load(&file).unwrap();
+        assert_eq!(loaded.get(Path::new("/d/a.txt")), Some(Cell { col: 2, row: 1 }));
+        assert_eq!(loaded.get(Path::new("/d/b.txt")), None);
+        assert_eq!(loaded.at(Cell { col: 2, row: 1 }), vec![PathBuf::from("/d/a.txt")]);
+    }
+
+    #[test]
+    fn test_offsets_round_trip_and_go_with_the_cell() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("placements.json");
+        let (a, b) = (Path::new("/d/a.txt"), Path::new("/d/b.txt"));
+
+        let mut placements = Placements::load(&file).unwrap();
+        placements.set_offset(a, (1, 1));
+        assert_eq!(placements.offset(a), None, "Unplaced icons have no offset");
+        placements.set(a, Some(Cell { col: 1, row: 0 }));
+        placements.set_offset(a, (12, -7));
+        placements.set(b, Some(Cell { col: 2, row: 0 }));
+        placements.save().unwrap();
+
+        let mut loaded = Placements::load(&file).unwrap();
+        assert_eq!(loaded.offset(a), Some((12, -7)));
+        assert_eq!(loaded.get(b), Some(Cell { col: 2, row: 0 }));
+
+        loaded.set(a, Some(Cell { col: 3, row: 0 }));
+        assert_eq!(loaded.offset(a), None);
+        loaded.save().unwrap();
+        let plain: BTreeMap<PathBuf, Cell> = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+        assert_eq!(plain.len(), 2, "Without offsets the file keeps its old format");
+    }
+
+    #[test]
+    fn test_outputs_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("placements.json");
+        let (a, b) = (Path::new("/d/a.txt"), Path::new("/d/b.txt"));
+
+        let mut placements = Placements::load(&file).unwrap();
+        placements.set(a, Some(Cell { col: 0, row: 0 }));
+        placements.set_output(a, Some("HDMI-A-1"));
+        placements.set_output(b, Some("DP-1"));
+        placements.set_output(b, None);
+        placements.save().unwrap();
+
+        let loaded = Placements::load(&file).unwrap();
+        assert_eq!(loaded.output(a), Some("HDMI-A-1"));
+        assert_eq!(loaded.output(b), None);
+        assert_eq!(loaded.get(a), Some(Cell { col: 0, row: 0 }));
+    }
+
+    #[test]
+    fn test_prune_missing_keeps_files_outside_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop).unwrap();
+        let kept = desktop.join("kept.txt");
+        fs::write(&kept, "x").unwrap();
+        let deleted = desktop.join("deleted.txt");
+        let volume = temp_dir.path().join("media").join("usb");
+
+        let mut placements = Placements::default();
+        for (i, path) in [&kept, &deleted, &volume].into_iter().enumerate() {
+            placements.set(path, Some(Cell { col: i as u32, row: 0 }));
+        }
+        assert_eq!(placements.prune_missing(&desktop), vec![deleted.clone()]);
+        assert!(placements.get(&kept).is_some());
+        assert!(placements.get(&deleted).is_none());
+        assert!(placements.get(&volume).is_some(), "Unmounted volumes keep their cell");
+        assert!(placements.prune_missing(&desktop).is_empty());
+    }
+
+    #[test]
+    fn test_prune_missing_keeps_dangling_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let link = temp_dir.path().join("report.pdf");
+        std::os::unix::fs::symlink(temp_dir.path().join("gone.pdf"), &link).unwrap();
+
+        let mut placements = Placements::default();
+        placements.set(&link, Some(Cell { col: 2, row: 1 }));
+        assert!(placements.prune_missing(temp_dir.path()).is_empty());
+        assert_eq!(placements.get(&link), Some(Cell { col: 2, row: 1 }));
+    }
+
+    #[test]
+    fn test_invalid_placements_file_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("placements.json");
+        fs::write(&file, "not json").unwrap();
+
+        assert!(Placements::load(&file).is_err());
+    }
+
+    #[test]
+    fn test_layout_json_is_sorted() {
+        let mut icons = BTreeMap::new();
+        icons.insert("b.txt".to_string(), Cell { col: 1, row: 0 });
+        icons.insert("a.txt".to_string(), Cell { col: 0, row: 2 });
+
+        let json = serde_json::to_string(&Layout::new(icons)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"icons":{"a.txt":{"col":0,"row":2},"b.txt":{"col":1,"row":0}}}"#
+        );
+    }
+
+    #[test]
+    fn test_layout_versions() {
+        let unversioned = serde_json::json!({ "icons": { "a.txt": { "col": 1, "row": 1 } } });
+        let layout = Layout::from_json(unversioned).unwrap();
+        assert_eq!(layout.icons["a.txt"], Cell { col: 1, row: 1 });
+
+        let newer = serde_json::json!({ "version": 2, "icons": {}, "groups": [] });
+        let error = format!("{:#}", Layout::from_json(newer).unwrap_err());
+        assert!(error.contains("version 2 is not supported"), "{}", error);
+
+        assert!(Layout::from_json(serde_json::json!({ "version": "one", "icons": {} })).is_err());
+        assert!(Layout::from_json(serde_json::json!({ "version": 1 })).is_err());
+
+        let pinned = serde_json::json!({ "icons": {}, "outputs": { "a.txt": "DP-1" } });
+        assert_eq!(Layout::from_json(pinned).unwrap().outputs["a.txt"], "DP-1");
+    }
+}
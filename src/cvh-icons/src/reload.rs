@@ -0,0 +1,45 @@
+//! Reloading a running daemon's configuration
+//!
+//! `SIGHUP` makes the daemon re-read its configuration file and restart
+//! every widget from the scripts `script_dirs` now resolves to, keeping the
+//! display connection and all surfaces. The signal is read in the event
+//! loop (see `signals`).
+
+use nix::sys::signal::Signal;
+
+use crate::config::Config;
+
+/// Signal that reloads the configuration
+pub const RELOAD_SIGNAL: Signal = Signal::SIGHUP;
+
+/// Names of the top-level settings that differ between `old` and `new`
+pub fn changed_settings(old: &Config, new: &Config) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    let mut changed: Vec<String> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    changed.sort();
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_settings() {
+        let old = Config::default();
+        assert!(changed_settings(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.show_trash = !old.show_trash;
+        new.colors.label_fg = "#123456".to_string();
+        assert_eq!(changed_settings(&old, &new), vec!["colors", "show_trash"]);
+    }
+}
@@ -0,0 +1,192 @@
+//! On-disk cache of rendered icons for fast startup
+//!
+//! With `render_cache_dir` set, the first frame of each icon is saved as raw
+//! pixels, keyed by everything that affects it (path, mtime, size, theme,
+//! widget script). On the next start an unchanged icon is shown from the
+//! cache straight away instead of waiting for its Lua script or SVG render.
+//! A changed file gets a new key, so stale entries are never read; they age
+//! out through LRU eviction once the cache is over its size limit.
+
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tiny_skia::Pixmap;
+use tracing::debug;
+
+/// Marks a cache entry file, followed by the format version
+const MAGIC: &[u8; 4] = b"CVR1";
+
+/// Extension of cache entry files
+const ENTRY_EXTENSION: &str = "rgba";
+
+/// Rendered icons stored in a directory, limited to `max_bytes`
+#[derive(Debug)]
+pub struct RenderCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl RenderCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+
+    /// File holding the entry for `key`
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", fnv1a(key.as_bytes()), ENTRY_EXTENSION))
+    }
+
+    /// Cached pixmap for `key`, marking the entry as recently used
+    pub fn get(&self, key: &str) -> Option<Pixmap> {
+        let path = self.entry_path(key);
+        let contents = fs::read(&path).ok()?;
+        let pixmap = decode(&contents, key)?;
+
+        // The entry's mtime is its last use, for eviction
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(pixmap)
+    }
+
+    /// Store `pixmap` under `key`, then evict old entries if over the limit
+    pub fn put(&self, key: &str, pixmap: &Pixmap) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create render cache {}", self.dir.display()))?;
+
+        // Write-then-rename so a reader never sees a partial entry
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, encode(key, pixmap)).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to replace {}", path.display()))?;
+
+        self.evict()
+    }
+
+    /// Remove the least recently used entries until the cache fits `max_bytes`
+    fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != ENTRY_EXTENSION) {
+                continue;
+            }
+            let Ok(metadata) = path.metadata() else {
+                continue;
+            };
+            total += metadata.len();
+            entries.push((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len(), path));
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => debug!("Evicted {} from the render cache", path.display()),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to evict {}", path.display())),
+            }
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Entry layout: magic, key length and key, width, height, premultiplied RGBA
+fn encode(key: &str, pixmap: &Pixmap) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16 + key.len() + pixmap.data().len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    out.extend_from_slice(key.as_bytes());
+    out.extend_from_slice(&pixmap.width().to_le_bytes());
+    out.extend_from_slice(&pixmap.height().to_le_bytes());
+    out.extend_from_slice(pixmap.data());
+    out
+}
+
+/// Read an entry, rejecting other formats and entries for a different key
+fn decode(contents: &[u8], key: &str) -> Option<Pixmap> {
+    let rest = contents.strip_prefix(MAGIC)?;
+    let (key_len, rest) = split_u32(rest)?;
+    let (stored_key, rest) = rest.split_at_checked(key_len as usize)?;
+    if stored_key != key.as_bytes() {
+        // Hash collision, or a leftover from another key
+        return None;
+    }
+    let (width, rest) = split_u32(rest)?;
+    let (height, data) = split_u32(rest)?;
+    Pixmap::from_vec(data.to_vec(), tiny_skia::IntSize::from_wh(width, height)?)
+}
+
+fn split_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let (head, rest) = bytes.split_first_chunk::<4>()?;
+    Some((u32::from_le_bytes(*head), rest))
+}
+
+/// 64-bit FNV-1a, stable across builds unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn solid(width: u32, height: u32, value: u8) -> Pixmap {
+        let mut pixmap = Pixmap::new(width, height).unwrap();
+        pixmap.data_mut().fill(value);
+        pixmap
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = RenderCache::new(temp_dir.path().join("cache"), 1 << 20);
+        assert!(cache.get("a").is_none());
+
+        cache.put("a", &solid(4, 3, 7)).unwrap();
+        let pixmap = cache.get("a").unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (4, 3));
+        assert!(pixmap.data().iter().all(|&b| b == 7));
+        assert!(cache.get("b").is_none());
+    }
+
+    #[test]
+    fn test_rejects_other_keys_and_corrupt_entries() {
+        let pixmap = solid(2, 2, 1);
+        let entry = encode("a", &pixmap);
+        assert!(decode(&entry, "a").is_some());
+        assert!(decode(&entry, "b").is_none());
+        assert!(decode(&entry[..entry.len() - 1], "a").is_none());
+        assert!(decode(b"nope", "a").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let temp_dir = TempDir::new().unwrap();
+        let entry_size = encode("a", &solid(8, 8, 0)).len() as u64;
+        let cache = RenderCache::new(temp_dir.path().to_path_buf(), entry_size * 2);
+
+        cache.put("a", &solid(8, 8, 0)).unwrap();
+        cache.put("b", &solid(8, 8, 0)).unwrap();
+        // Make "b" the older entry, then use "a"
+        let old = SystemTime::now() - Duration::from_secs(60);
+        File::options().write(true).open(cache.entry_path("b")).unwrap().set_modified(old).unwrap();
+        File::options().write(true).open(cache.entry_path("a")).unwrap().set_modified(old).unwrap();
+        assert!(cache.get("a").is_some());
+
+        cache.put("c", &solid(8, 8, 0)).unwrap();
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none(), "The least recently used entry is evicted");
+        assert!(cache.get("c").is_some());
+    }
+}
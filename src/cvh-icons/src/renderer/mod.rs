@@ -3,18 +3,28 @@
 //! Uses tiny-skia for software rendering to Wayland surfaces.
 
 use anyhow::Result;
-use fontdue::{Font, FontSettings};
 use image::imageops::FilterType;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tiny_skia::{
-    Color, FillRule, LineCap, LineJoin, Paint, Pixmap, PixmapPaint, PathBuilder, Rect, Stroke,
-    Transform,
+    BlendMode, Color, FillRule, LineCap, LineJoin, Paint, Pixmap, PixmapPaint, PathBuilder, Rect,
+    Stroke, Transform,
 };
 use tracing::warn;
 
-use crate::icons::DesktopIcon;
+use crate::config::{Colors, ElevationConfig, LabelAlign, LabelPillConfig, PillLines};
+use crate::error::{IconsError, IconsResult};
+use crate::icons::{DesktopIcon, IconType, MenuEntry, RenameField, WidgetStatus};
+use crate::emblems::Emblem;
+use crate::folder_icon::FolderIcon;
+use crate::ipc::{Overlay, OverlayCorner};
 use crate::lua::DrawCommand;
 
+mod shaping;
+mod type_theme;
+
+pub use shaping::{visual_runs, LabelFont, PositionedGlyph};
+pub use type_theme::TypeTheme;
+
 /// Text alignment options
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TextAlign {
@@ -34,6 +44,16 @@ impl TextAlign {
     }
 }
 
+impl From<LabelAlign> for TextAlign {
+    fn from(align: LabelAlign) -> Self {
+        match align {
+            LabelAlign::Left => TextAlign::Left,
+            LabelAlign::Center => TextAlign::Center,
+            LabelAlign::Right => TextAlign::Right,
+        }
+    }
+}
+
 /// Common system font paths to search for DejaVu Sans
 const FONT_SEARCH_PATHS: &[&str] = &[
     "/usr/share/fonts/TTF/DejaVuSans.ttf",
@@ -48,10 +68,10 @@ const FONT_SEARCH_PATHS: &[&str] = &[
 ];
 
 /// Try to load a default font from common system paths
-fn load_default_font() -> Option<Font> {
+fn load_default_font() -> Option<LabelFont> {
     for path in FONT_SEARCH_PATHS {
         if let Ok(font_data) = std::fs::read(path) {
-            match Font::from_bytes(font_data, FontSettings::default()) {
+            match LabelFont::from_bytes(font_data) {
                 Ok(font) => {
                     tracing::debug!("Loaded font from: {}", path);
                     return Some(font);
@@ -66,6 +86,218 @@ fn load_default_font() -> Option<Font> {
     None
 }
 
+/// Damaged area at or above this fraction of the buffer is reported as full damage
+const FULL_DAMAGE_RATIO: f32 = 0.5;
+
+/// A rectangular region of a buffer, in buffer pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamageRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl DamageRect {
+    /// Damage covering a whole buffer
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: width as i32,
+            height: height as i32,
+        }
+    }
+
+    fn area(&self) -> i64 {
+        self.width as i64 * self.height as i64
+    }
+}
+
+/// Compute the regions that changed between two RGBA frames
+///
+/// The frame is split into two bands at `split_y` (the icon glyph above and
+/// the label below) and each band contributes at most one bounding rectangle.
+/// Mismatched frame sizes, or damage covering most of the buffer (e.g. a hover
+/// highlight), yield a single full-buffer rectangle.
+pub fn compute_damage(prev: &[u8], next: &[u8], width: u32, height: u32, split_y: u32) -> Vec<DamageRect> {
+    let full = DamageRect::full(width, height);
+    if prev.len() != next.len() || next.len() != (width * height * 4) as usize {
+        return vec![full];
+    }
+
+    let split_y = split_y.min(height);
+    let rects: Vec<DamageRect> = [(0, split_y), (split_y, height)]
+        .into_iter()
+        .filter_map(|(top, bottom)| band_damage(prev, next, width, top, bottom))
+        .collect();
+
+    let damaged: i64 = rects.iter().map(DamageRect::area).sum();
+    if damaged as f32 >= full.area() as f32 * FULL_DAMAGE_RATIO {
+        return vec![full];
+    }
+
+    rects
+}
+
+/// Bounding box of changed pixels within rows `top..bottom`
+fn band_damage(prev: &[u8], next: &[u8], width: u32, top: u32, bottom: u32) -> Option<DamageRect> {
+    let stride = width as usize * 4;
+    let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+    let (mut max_x, mut max_y) = (0u32, 0u32);
+
+    for y in top..bottom {
+        let row = y as usize * stride..(y as usize + 1) * stride;
+        let (prev_row, next_row) = (&prev[row.clone()], &next[row]);
+        if prev_row == next_row {
+            continue;
+        }
+
+        for x in 0..width {
+            let px = x as usize * 4..x as usize * 4 + 4;
+            if prev_row[px.clone()] != next_row[px] {
+                min_x = min_x.min(x);
+                max_x = max_x.max(x);
+            }
+        }
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+    }
+
+    if min_y == u32::MAX {
+        return None;
+    }
+
+    Some(DamageRect {
+        x: min_x as i32,
+        y: min_y as i32,
+        width: (max_x - min_x + 1) as i32,
+        height: (max_y - min_y + 1) as i32,
+    })
+}
+
+/// Area of the confirmation popup's Yes (`true`) or No button
+fn confirm_button_rect(yes: bool) -> Rect {
+    let width = (CONFIRM_POPUP_WIDTH as f32 - 3.0 * CONFIRM_POPUP_PADDING) / 2.0;
+    let x = if yes { CONFIRM_POPUP_PADDING } else { 2.0 * CONFIRM_POPUP_PADDING + width };
+    let y = CONFIRM_POPUP_HEIGHT as f32 - CONFIRM_POPUP_PADDING - CONFIRM_BUTTON_HEIGHT;
+    Rect::from_xywh(x, y, width, CONFIRM_BUTTON_HEIGHT).expect("confirmation button has a positive size")
+}
+
+/// Which confirmation popup button is at surface coordinates `(x, y)`
+///
+/// `Some(true)` is Yes, `Some(false)` is No, and `None` misses both.
+pub fn confirm_popup_hit(x: f64, y: f64) -> Option<bool> {
+    let (x, y) = (x as f32, y as f32);
+    [true, false].into_iter().find(|&yes| {
+        let rect = confirm_button_rect(yes);
+        (rect.left()..rect.right()).contains(&x) && (rect.top()..rect.bottom()).contains(&y)
+    })
+}
+
+/// Width and height of the selection count badge
+pub const SELECTION_BADGE_SIZE: u32 = 28;
+
+/// Width and height of the delete confirmation popup
+pub const CONFIRM_POPUP_WIDTH: u32 = 280;
+pub const CONFIRM_POPUP_HEIGHT: u32 = 96;
+
+/// Margin around the confirmation popup's message and buttons
+const CONFIRM_POPUP_PADDING: f32 = 12.0;
+
+/// Height of the confirmation popup's Yes/No buttons
+const CONFIRM_BUTTON_HEIGHT: f32 = 30.0;
+
+/// Width of an icon's context menu
+pub const CONTEXT_MENU_WIDTH: u32 = 200;
+
+/// Height of a context menu entry, and of a separator between entries
+const MENU_ITEM_HEIGHT: u32 = 28;
+const MENU_SEPARATOR_HEIGHT: u32 = 9;
+
+/// Space above the first and below the last context menu row
+const MENU_PADDING: u32 = 4;
+
+/// Indent of context menu labels
+const MENU_TEXT_INSET: f32 = 12.0;
+
+fn menu_row_height(entry: &MenuEntry) -> u32 {
+    match entry {
+        MenuEntry::Action { .. } => MENU_ITEM_HEIGHT,
+        MenuEntry::Separator => MENU_SEPARATOR_HEIGHT,
+    }
+}
+
+/// Height of a context menu listing `entries`
+pub fn context_menu_height(entries: &[MenuEntry]) -> u32 {
+    2 * MENU_PADDING + entries.iter().map(menu_row_height).sum::<u32>()
+}
+
+/// Index of the context menu entry at surface coordinates `(x, y)`
+///
+/// Separators and the padding around the entries are not entries.
+pub fn context_menu_hit(entries: &[MenuEntry], x: f64, y: f64) -> Option<usize> {
+    if !(0.0..CONTEXT_MENU_WIDTH as f64).contains(&x) {
+        return None;
+    }
+    let mut top = MENU_PADDING as f64;
+    for (i, entry) in entries.iter().enumerate() {
+        let bottom = top + menu_row_height(entry) as f64;
+        if (top..bottom).contains(&y) {
+            return matches!(entry, MenuEntry::Action { .. }).then_some(i);
+        }
+        top = bottom;
+    }
+    None
+}
+
+/// Smallest overlay badge, so counts stay legible on small icons
+const MIN_OVERLAY_SIZE: u32 = 12;
+
+/// Default background of count and emblem overlay badges
+const OVERLAY_COLOR: &str = "#e01b24";
+
+/// Background of the badge on icons whose widget crashed
+const BROKEN_WIDGET_COLOR: &str = "#e66100";
+
+/// Background of the badge on icons whose widget scripts were not found
+const MISSING_SCRIPT_COLOR: &str = "#1c71d8";
+
+/// Background of the arrow badge on symlinks
+const LINK_COLOR: &str = "#5e5c64";
+
+/// Background of the badge on symlinks whose target is missing
+const BROKEN_LINK_COLOR: &str = "#c01c28";
+
+/// Gap between left/right-aligned label text and the edge of the label band
+const LABEL_PADDING: f32 = 2.0;
+
+/// Default label length in characters, including the "…" of a truncated name
+const DEFAULT_LABEL_CHARS: usize = 12;
+
+/// Lines a label wraps over unless `set_label_lines` says otherwise
+const DEFAULT_LABEL_LINES: u32 = 2;
+
+/// Space in the label band besides its lines: the gap below the glyph and
+/// the band's own padding
+const LABEL_BAND_MARGIN: u32 = 8;
+
+/// Icon theme used until `set_icon_theme` is called
+const DEFAULT_ICON_THEME: &str = "Adwaita";
+
+/// Height of one line of an expanded label
+const EXPANDED_LINE_HEIGHT: u32 = 16;
+
+/// Space around the text of a tooltip
+const TOOLTIP_PADDING: u32 = 6;
+
+/// Width of the focus ring drawn around the focused icon
+const FOCUS_RING_WIDTH: f32 = 2.0;
+
+/// Background of the inline rename field, and its outline once a name is refused
+const RENAME_FIELD_BACKGROUND: &str = "#1e1e1ee6";
+const RENAME_INVALID_COLOR: &str = "#e01b24";
+
 /// Icon renderer
 #[allow(dead_code)]
 pub struct IconRenderer {
@@ -76,12 +308,60 @@ pub struct IconRenderer {
     font_size: f32,
 
     /// Loaded font for text rendering (None if loading failed)
-    font: Option<Font>,
+    font: Option<LabelFont>,
 
     /// Colors
     label_fg: Color,
     label_bg: Color,
     selection_color: Color,
+    hover_color: Color,
+    focus_color: Color,
+
+    /// Symbolic (monochrome) glyph style, if enabled
+    symbolic: Option<SymbolicStyle>,
+
+    /// Alignment of label text within the label band
+    label_align: TextAlign,
+
+    /// Longest label line in characters before the name wraps
+    label_chars: usize,
+
+    /// Most lines a label wraps over before the name is truncated
+    label_lines: u32,
+
+    /// Drop shadow drawn beneath icons, if enabled
+    elevation: Option<ElevationConfig>,
+
+    /// Pill drawn behind the label text instead of the label background, if enabled
+    label_pill: Option<LabelPillConfig>,
+
+    /// Icon theme that named custom folder icons are looked up in
+    icon_theme: String,
+
+    /// Per-type images drawn instead of the built-in glyphs, if set
+    type_theme: Option<TypeTheme>,
+}
+
+/// Settings for rendering monochrome symbolic glyphs
+#[derive(Debug, Clone)]
+pub struct SymbolicStyle {
+    /// Icon theme to look up `<name>-symbolic.svg` in
+    pub theme: String,
+    /// Color glyphs are recolored to
+    pub tint: Color,
+    /// Also recolor image thumbnails
+    pub strict: bool,
+}
+
+impl SymbolicStyle {
+    /// Create a style from a theme name and a hex tint (white if invalid)
+    pub fn new(theme: &str, tint: &str, strict: bool) -> Self {
+        Self {
+            theme: theme.to_string(),
+            tint: parse_color(tint).unwrap_or(Color::WHITE),
+            strict,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -94,11 +374,21 @@ impl IconRenderer {
             label_fg: Color::WHITE,
             label_bg: Color::from_rgba8(0, 0, 0, 128),
             selection_color: Color::from_rgba8(136, 192, 208, 64),
+            hover_color: Color::from_rgba8(255, 255, 255, 38),
+            focus_color: Color::from_rgba8(136, 192, 208, 255),
+            symbolic: None,
+            label_align: TextAlign::Center,
+            label_chars: DEFAULT_LABEL_CHARS,
+            label_lines: DEFAULT_LABEL_LINES,
+            elevation: None,
+            label_pill: None,
+            icon_theme: DEFAULT_ICON_THEME.to_string(),
+            type_theme: None,
         }
     }
 
     /// Create a renderer with a specific font (useful for testing)
-    pub fn with_font(size: u32, font_size: f32, font: Option<Font>) -> Self {
+    pub fn with_font(size: u32, font_size: f32, font: Option<LabelFont>) -> Self {
         Self {
             size,
             font_size,
@@ -106,11 +396,169 @@ impl IconRenderer {
             label_fg: Color::WHITE,
             label_bg: Color::from_rgba8(0, 0, 0, 128),
             selection_color: Color::from_rgba8(136, 192, 208, 64),
+            hover_color: Color::from_rgba8(255, 255, 255, 38),
+            focus_color: Color::from_rgba8(136, 192, 208, 255),
+            symbolic: None,
+            label_align: TextAlign::Center,
+            label_chars: DEFAULT_LABEL_CHARS,
+            label_lines: DEFAULT_LABEL_LINES,
+            elevation: None,
+            label_pill: None,
+            icon_theme: DEFAULT_ICON_THEME.to_string(),
+            type_theme: None,
+        }
+    }
+
+    /// Set the selection fill, hover highlight and focus ring colors
+    ///
+    /// Colors that don't parse keep their current value.
+    pub fn set_state_colors(&mut self, colors: &Colors) {
+        let states = [
+            ("selection", &colors.selection, &mut self.selection_color),
+            ("hover", &colors.hover, &mut self.hover_color),
+            ("focus_ring", &colors.focus_ring, &mut self.focus_color),
+        ];
+        for (name, value, color) in states {
+            match parse_color(value) {
+                Some(parsed) => *color = parsed,
+                None => warn!("Invalid {} color {:?}, keeping the default", name, value),
+            }
+        }
+    }
+
+    /// Set the icon theme that named custom folder icons are looked up in
+    pub fn set_icon_theme(&mut self, theme: &str) {
+        self.icon_theme = theme.to_string();
+    }
+
+    /// Draw the images of a theme directory for the types it has images for
+    pub fn set_type_theme(&mut self, theme: Option<TypeTheme>) {
+        self.type_theme = theme;
+    }
+
+    /// Whether a theme image stands in for the glyph of `icon_type`
+    pub fn has_type_image(&self, icon_type: IconType) -> bool {
+        self.type_theme.as_ref().is_some_and(|theme| theme.has(icon_type))
+    }
+
+    /// Draw the theme image of `icon_type` centered in the glyph area of a
+    /// pixmap drawn at `scale`
+    ///
+    /// The image is rasterized at the buffer size, so SVGs stay sharp.
+    /// Returns false, drawing nothing, if the theme has no image for the type.
+    pub fn draw_type_image(&self, pixmap: &mut Pixmap, icon_type: IconType, scale: u32) -> bool {
+        let size = self.size * scale.max(1);
+        let Some(mut image) = self.type_theme.as_ref().and_then(|theme| theme.image(icon_type, size)) else {
+            return false;
+        };
+        if let Some(ref style) = self.symbolic {
+            recolor(&mut image, style.tint);
+        }
+        let x = (size as i32 - image.width() as i32) / 2;
+        let y = (size as i32 - image.height() as i32) / 2;
+        pixmap.draw_pixmap(x, y, image.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+        true
+    }
+
+    /// Enable or disable symbolic glyph rendering
+    pub fn set_symbolic(&mut self, style: Option<SymbolicStyle>) {
+        self.symbolic = style;
+    }
+
+    /// Whether symbolic glyph rendering is enabled
+    pub fn is_symbolic(&self) -> bool {
+        self.symbolic.is_some()
+    }
+
+    /// Enable or disable the drop shadow beneath icons
+    pub fn set_elevation(&mut self, elevation: Option<ElevationConfig>) {
+        self.elevation = elevation;
+    }
+
+    /// Draw labels on a pill behind their text, or on the full label background with None
+    pub fn set_label_pill(&mut self, pill: Option<LabelPillConfig>) {
+        let invalid = pill.as_ref().map(|pill| &pill.color).filter(|c| parse_color(c).is_none());
+        if let Some(color) = invalid {
+            warn!("Invalid label pill color {:?}, using the label background", color);
+        }
+        self.label_pill = pill;
+    }
+
+    /// Width of `text` drawn by `render_text` at `size`; 0 without a font
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        self.font
+            .as_ref()
+            .map_or(0.0, |font| font.layout(text, size).iter().map(|glyph| glyph.advance).sum())
+    }
+
+    /// Fill the label pill around text `width` wide anchored at `anchor_x`
+    ///
+    /// The pill spans `top` to `top + height` and reaches `padding` past
+    /// the text at either end.
+    fn fill_label_pill(
+        &self,
+        pixmap: &mut Pixmap,
+        pill: &LabelPillConfig,
+        anchor_x: f32,
+        width: f32,
+        top: f32,
+        height: f32,
+    ) {
+        if width <= 0.0 {
+            return;
+        }
+        let text_left = match self.label_align {
+            TextAlign::Left => anchor_x,
+            TextAlign::Center => anchor_x - width / 2.0,
+            TextAlign::Right => anchor_x - width,
+        };
+        let left = text_left - pill.padding;
+        let Some(rect) = Rect::from_xywh(left, top, width + 2.0 * pill.padding, height) else {
+            return;
+        };
+        let Some(path) = rounded_rect(rect, pill.radius) else {
+            return;
+        };
+        let mut paint = Paint::default();
+        paint.set_color(parse_color(&pill.color).unwrap_or(self.label_bg));
+        paint.anti_alias = true;
+        pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+    }
+
+    /// Put the drop shadow, if enabled, beneath a rendered icon
+    ///
+    /// `scale` is the device pixel ratio the icon was rendered at.
+    pub fn apply_elevation(&self, pixmap: &mut Pixmap, scale: f32) {
+        let Some(ref elevation) = self.elevation else {
+            return;
+        };
+        if let Some(mut shadowed) = drop_shadow(pixmap, elevation, scale) {
+            shadowed.draw_pixmap(0, 0, pixmap.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+            *pixmap = shadowed;
         }
     }
 
+    /// Set how labels are aligned and how many characters they may show
+    pub fn set_label_layout(&mut self, align: TextAlign, chars: usize) {
+        self.label_align = align;
+        self.label_chars = chars;
+    }
+
+    /// Set how many lines a label may wrap over
+    pub fn set_label_lines(&mut self, lines: u32) {
+        self.label_lines = lines.max(1);
+    }
+
+    /// Height of the label band below the icon, as `label_height` works out
+    pub fn label_height(&self) -> u32 {
+        label_height(self.font_size, self.label_lines)
+    }
+
     /// Render text to a pixmap
     ///
+    /// Right-to-left and mixed-direction text is reordered and shaped (see
+    /// `LabelFont::layout`), so alignment applies to the text as displayed.
+    ///
     /// # Arguments
     /// * `pixmap` - Target pixmap to draw on
     /// * `text` - Text string to render
@@ -139,14 +587,8 @@ impl IconRenderer {
         }
 
         // Calculate total text width for alignment
-        let mut total_width = 0.0f32;
-        let mut glyph_data: Vec<(fontdue::Metrics, Vec<u8>)> = Vec::new();
-
-        for ch in text.chars() {
-            let (metrics, bitmap) = font.rasterize(ch, size);
-            total_width += metrics.advance_width;
-            glyph_data.push((metrics, bitmap));
-        }
+        let glyphs = font.layout(text, size);
+        let total_width: f32 = glyphs.iter().map(|glyph| glyph.advance).sum();
 
         // Calculate starting x position based on alignment
         let start_x = match align {
@@ -163,16 +605,17 @@ impl IconRenderer {
 
         let mut cursor_x = start_x;
 
-        for (metrics, bitmap) in glyph_data {
+        for glyph in glyphs {
+            let PositionedGlyph { metrics, bitmap, .. } = &glyph;
             if bitmap.is_empty() {
-                cursor_x += metrics.advance_width;
+                cursor_x += glyph.advance;
                 continue;
             }
 
             // Calculate glyph position
             // y is baseline, ymin is typically negative for glyphs above baseline
-            let glyph_x = cursor_x + metrics.xmin as f32;
-            let glyph_y = y + metrics.ymin as f32;
+            let glyph_x = cursor_x + glyph.x_offset + metrics.xmin as f32;
+            let glyph_y = y - glyph.y_offset + metrics.ymin as f32;
 
             // Create a small pixmap for the glyph
             if metrics.width > 0 && metrics.height > 0 {
@@ -207,7 +650,7 @@ impl IconRenderer {
                 }
             }
 
-            cursor_x += metrics.advance_width;
+            cursor_x += glyph.advance;
         }
     }
 
@@ -253,11 +696,8 @@ impl IconRenderer {
         // Scale the image to the requested dimensions using bilinear filter
         let scaled = img.resize_exact(target_width, target_height, FilterType::Triangle);
 
-        // Convert to RGBA8
-        let rgba = scaled.to_rgba8();
-
         // Create a pixmap for the image
-        let img_pixmap = match Pixmap::new(target_width, target_height) {
+        let mut img_pixmap = match pixmap_from_rgba(&scaled.to_rgba8()) {
             Some(p) => p,
             None => {
                 warn!("Failed to create pixmap for image '{}'", path);
@@ -265,27 +705,10 @@ impl IconRenderer {
             }
         };
 
-        // We need a mutable pixmap to fill
-        let mut img_pixmap = img_pixmap;
-        let pixels = img_pixmap.pixels_mut();
-
-        // Copy image data to pixmap with premultiplied alpha
-        for (i, pixel) in rgba.pixels().enumerate() {
-            let [r, g, b, a] = pixel.0;
-
-            if a == 0 {
-                // Fully transparent, skip (pixmap is already zero-initialized)
-                continue;
-            }
-
-            // Premultiply alpha for tiny-skia
-            let alpha = a as f32 / 255.0;
-            let pm_r = (r as f32 * alpha) as u8;
-            let pm_g = (g as f32 * alpha) as u8;
-            let pm_b = (b as f32 * alpha) as u8;
-
-            if let Some(color) = tiny_skia::PremultipliedColorU8::from_rgba(pm_r, pm_g, pm_b, a) {
-                pixels[i] = color;
+        // Strict symbolic mode recolors thumbnails like every other glyph
+        if let Some(ref style) = self.symbolic {
+            if style.strict {
+                recolor(&mut img_pixmap, style.tint);
             }
         }
 
@@ -304,34 +727,266 @@ impl IconRenderer {
     }
 
     /// Render an icon to a pixmap
-    pub fn render(&self, icon: &DesktopIcon) -> Result<Pixmap> {
-        let total_height = self.size + 24; // Icon + label space
+    ///
+    /// The same as drawing the label layer over the glyph layer.
+    pub fn render(&self, icon: &DesktopIcon) -> IconsResult<Pixmap> {
+        self.render_glyph_layer(icon)
+            .and_then(|glyph| self.render_label_layer(&glyph, icon.label()))
+            .map_err(IconsError::Render)
+    }
+
+    /// Render everything but the label: state highlights and glyph
+    ///
+    /// The selection fill goes beneath the hover highlight and both beneath
+    /// the glyph; the focus ring goes on top so it shows on any glyph. Each
+    /// state can be combined with the others. The label band only gets the
+    /// highlights, so a renamed icon can reuse this layer and draw just its
+    /// new label over a copy.
+    pub fn render_glyph_layer(&self, icon: &DesktopIcon) -> Result<Pixmap> {
+        let total_height = self.size + self.label_height();
         let mut pixmap = Pixmap::new(self.size, total_height)
             .ok_or_else(|| anyhow::anyhow!("Failed to create pixmap"))?;
 
         // Clear with transparent
         pixmap.fill(Color::TRANSPARENT);
+        self.draw_state_fills(&mut pixmap, icon);
+
+        // A folder's own icon, an image's thumbnail or the theme directory's
+        // image for the type replaces the built-in glyph
+        if let Some(glyph) = icon.custom_icon().and_then(|custom| self.render_custom_icon(custom)) {
+            pixmap.draw_pixmap(0, 0, glyph.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+        } else if let Some(thumbnail) = icon.thumbnail() {
+            self.draw_thumbnail(&mut pixmap, thumbnail);
+        } else if !self.draw_type_image(&mut pixmap, icon.icon_type(), 1) {
+            // Types the theme directory has no image for keep the built-in glyph
+            match self.symbolic {
+                Some(ref style) => self.draw_symbolic_glyph(&mut pixmap, icon, style)?,
+                // Draw icon placeholder (would load actual icon in production)
+                None => self.draw_icon_placeholder(&mut pixmap, icon)?,
+            }
+        }
 
-        // Draw selection background if selected
-        if icon.is_selected() {
-            let mut paint = Paint::default();
-            paint.set_color(self.selection_color);
+        if icon.is_focused() {
+            self.draw_focus_ring(&mut pixmap);
+        }
 
-            let rect = Rect::from_xywh(0.0, 0.0, self.size as f32, total_height as f32)
-                .ok_or_else(|| anyhow::anyhow!("Invalid rect"))?;
+        Ok(pixmap)
+    }
 
+    /// Fill the whole of `pixmap` with `icon`'s selection and hover highlights
+    ///
+    /// The fills go beneath what `pixmap` already holds, the selection below
+    /// the hover highlight, so they can be added under a finished glyph.
+    pub fn draw_state_fills(&self, pixmap: &mut Pixmap, icon: &DesktopIcon) {
+        let Some(rect) = Rect::from_xywh(0.0, 0.0, pixmap.width() as f32, pixmap.height() as f32) else {
+            return;
+        };
+        let fills = [(icon.is_hovered(), self.hover_color), (icon.is_selected(), self.selection_color)];
+        for (_, color) in fills.into_iter().filter(|&(on, _)| on) {
+            let mut paint = Paint::default();
+            paint.set_color(color);
+            paint.blend_mode = BlendMode::DestinationOver;
             pixmap.fill_rect(rect, &paint, Transform::identity(), None);
         }
+    }
 
-        // Draw icon placeholder (would load actual icon in production)
-        self.draw_icon_placeholder(&mut pixmap, icon)?;
+    /// Draw an image's thumbnail centered in the glyph area
+    fn draw_thumbnail(&self, pixmap: &mut Pixmap, thumbnail: &Pixmap) {
+        let mut thumbnail = thumbnail.clone();
+        // Strict symbolic mode recolors thumbnails like every other glyph
+        if let Some(ref style) = self.symbolic {
+            if style.strict {
+                recolor(&mut thumbnail, style.tint);
+            }
+        }
+        let x = (self.size as i32 - thumbnail.width() as i32) / 2;
+        let y = (self.size as i32 - thumbnail.height() as i32) / 2;
+        pixmap.draw_pixmap(x, y, thumbnail.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    }
 
-        // Draw label
-        self.draw_label(&mut pixmap, icon.name())?;
+    /// Outline the whole of `pixmap` to show it has the keyboard focus
+    pub fn draw_focus_ring(&self, pixmap: &mut Pixmap) {
+        let half = FOCUS_RING_WIDTH / 2.0;
+        let Some(outline) = Rect::from_xywh(
+            half,
+            half,
+            pixmap.width() as f32 - FOCUS_RING_WIDTH,
+            pixmap.height() as f32 - FOCUS_RING_WIDTH,
+        ) else {
+            return;
+        };
+        let mut paint = Paint::default();
+        paint.set_color(self.focus_color);
+        let stroke = Stroke { width: FOCUS_RING_WIDTH, ..Default::default() };
+        let path = PathBuilder::from_rect(outline);
+        pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+    }
 
+    /// Draw an inline rename `field` over the label band of an icon frame
+    ///
+    /// `pixmap` is the frame at `scale` times the icon's size. The field is
+    /// one line tall; text that doesn't fit scrolls to keep the cursor in
+    /// view. It is outlined in the focus color, or in red once a name was
+    /// refused.
+    pub fn draw_rename_field(&self, pixmap: &mut Pixmap, field: &RenameField, scale: f32) {
+        let width = pixmap.width() as f32 / scale;
+        let (field_width, field_height) = (width - 2.0, (label_line_height(self.font_size) + 4) as f32);
+        let Some(mut text_area) = Pixmap::new(
+            (field_width * scale).ceil().max(1.0) as u32,
+            (field_height * scale).ceil().max(1.0) as u32,
+        ) else {
+            return;
+        };
+        text_area.fill(parse_color(RENAME_FIELD_BACKGROUND).unwrap_or(Color::BLACK));
+
+        // Scroll the text left just far enough to show the cursor
+        let before_cursor: String = field.text().chars().take(field.cursor()).collect();
+        let cursor_x = self.text_width(&before_cursor, self.font_size);
+        let visible = field_width - 2.0 * LABEL_PADDING;
+        let text_x = LABEL_PADDING - (cursor_x - visible).max(0.0);
+        let baseline = field_height - 4.0;
+        self.render_text(
+            &mut text_area,
+            field.text(),
+            text_x * scale,
+            baseline * scale,
+            self.font_size * scale,
+            self.label_fg,
+            TextAlign::Left,
+        );
+
+        let mut paint = Paint::default();
+        paint.set_color(self.label_fg);
+        // Whole pixels, at least one wide: tiny-skia trips over anti-aliased
+        // rects narrower than the pixel they straddle
+        let caret_x = ((text_x + cursor_x) * scale).round();
+        let caret_width = scale.round().max(1.0);
+        let caret_height = ((field_height - 4.0) * scale).round().max(1.0);
+        let caret = Rect::from_xywh(caret_x, (2.0 * scale).round(), caret_width, caret_height)
+            .filter(|caret| caret.left() >= 0.0 && caret.right() <= text_area.width() as f32);
+        if let Some(caret) = caret {
+            text_area.fill_rect(caret, &paint, Transform::identity(), None);
+        }
+
+        let outline_color = match field.is_invalid() {
+            true => parse_color(RENAME_INVALID_COLOR).unwrap_or(self.focus_color),
+            false => self.focus_color,
+        };
+        paint.set_color(outline_color);
+        let half = scale / 2.0;
+        if let Some(outline) = Rect::from_xywh(
+            half,
+            half,
+            text_area.width() as f32 - scale,
+            text_area.height() as f32 - scale,
+        ) {
+            let stroke = Stroke { width: scale, ..Default::default() };
+            let path = PathBuilder::from_rect(outline);
+            text_area.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+
+        pixmap.draw_pixmap(
+            scale.round() as i32,
+            ((self.size as f32 + 2.0) * scale).round() as i32,
+            text_area.as_ref(),
+            &PixmapPaint::default(),
+            Transform::identity(),
+            None,
+        );
+    }
+
+    /// Draw the label for `name` over a copy of a glyph layer
+    pub fn render_label_layer(&self, glyph: &Pixmap, name: &str) -> Result<Pixmap> {
+        let mut pixmap = glyph.clone();
+        self.draw_label(&mut pixmap, name)?;
         Ok(pixmap)
     }
 
+    /// Draw the icon's type glyph as a single-color symbolic icon
+    ///
+    /// Uses the theme's `<name>-symbolic.svg` when available, otherwise the
+    /// placeholder shape, recolored to the style's tint either way.
+    fn draw_symbolic_glyph(&self, pixmap: &mut Pixmap, icon: &DesktopIcon, style: &SymbolicStyle) -> Result<()> {
+        let margin = 8;
+        let glyph_size = self.size.saturating_sub(margin * 2).max(1);
+
+        let mut glyph = match find_symbolic_icon(&style.theme, icon.icon_name())
+            .and_then(|path| render_svg(&path, glyph_size, glyph_size))
+        {
+            Some(svg) => {
+                let mut glyph = Pixmap::new(self.size, self.size)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to create glyph pixmap"))?;
+                glyph.draw_pixmap(
+                    margin as i32,
+                    margin as i32,
+                    svg.as_ref(),
+                    &PixmapPaint::default(),
+                    Transform::identity(),
+                    None,
+                );
+                glyph
+            }
+            None => {
+                let mut glyph = Pixmap::new(self.size, self.size)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to create glyph pixmap"))?;
+                self.draw_icon_placeholder(&mut glyph, icon)?;
+                glyph
+            }
+        };
+
+        recolor(&mut glyph, style.tint);
+        pixmap.draw_pixmap(
+            0,
+            0,
+            glyph.as_ref(),
+            &PixmapPaint::default(),
+            Transform::identity(),
+            None,
+        );
+
+        Ok(())
+    }
+
+    /// Render a folder's custom icon as a glyph, or None if it cannot be found
+    /// or loaded
+    ///
+    /// Named icons are looked up in the icon theme (as a symbolic icon first
+    /// in symbolic mode, where the result is recolored like every glyph).
+    fn render_custom_icon(&self, custom: &FolderIcon) -> Option<Pixmap> {
+        let margin = 8;
+        let glyph_size = self.size.saturating_sub(margin * 2).max(1);
+        let path = match custom {
+            FolderIcon::File(path) => Some(path.clone()),
+            FolderIcon::Named(name) => self
+                .symbolic
+                .as_ref()
+                .and_then(|style| find_symbolic_icon(&style.theme, name))
+                .or_else(|| find_theme_icon(&self.icon_theme, name)),
+        }
+        .filter(|path| path.is_file())?;
+
+        let mut glyph = Pixmap::new(self.size, self.size)?;
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg")) {
+            let svg = render_svg(&path, glyph_size, glyph_size)?;
+            glyph.draw_pixmap(
+                margin as i32,
+                margin as i32,
+                svg.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+        } else {
+            let size = glyph_size as f32;
+            self.render_image(&mut glyph, path.to_str()?, margin as f32, margin as f32, size, size);
+        }
+
+        if let Some(ref style) = self.symbolic {
+            recolor(&mut glyph, style.tint);
+        }
+        Some(glyph)
+    }
+
     /// Draw a placeholder icon shape
     fn draw_icon_placeholder(&self, pixmap: &mut Pixmap, icon: &DesktopIcon) -> Result<()> {
         let mut paint = Paint::default();
@@ -346,6 +1001,14 @@ impl IconRenderer {
             crate::icons::IconType::Archive => Color::from_rgba8(224, 108, 117, 255),
             crate::icons::IconType::Video => Color::from_rgba8(209, 154, 102, 255),
             crate::icons::IconType::Audio => Color::from_rgba8(86, 182, 194, 255),
+            crate::icons::IconType::Trash => Color::from_rgba8(130, 137, 151, 255),
+            crate::icons::IconType::Volume => Color::from_rgba8(94, 92, 100, 255),
+            crate::icons::IconType::Fifo | crate::icons::IconType::Socket => {
+                Color::from_rgba8(52, 101, 164, 255)
+            }
+            crate::icons::IconType::BlockDevice | crate::icons::IconType::CharDevice => {
+                Color::from_rgba8(164, 0, 0, 255)
+            }
             _ => Color::from_rgba8(171, 178, 191, 255),
         };
 
@@ -355,6 +1018,11 @@ impl IconRenderer {
         let icon_size = self.size as f32 - margin * 2.0;
 
         match icon.icon_type() {
+            icon_type if icon_type.is_special() => {
+                // Round, so pipes, sockets and devices don't pass for regular files
+                let center = margin + icon_size / 2.0;
+                fill_circle(pixmap, center, center, icon_size / 2.0, color);
+            }
             crate::icons::IconType::Folder => {
                 // Draw folder shape
                 let mut pb = PathBuilder::new();
@@ -370,6 +1038,44 @@ impl IconRenderer {
                     pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
                 }
             }
+            crate::icons::IconType::Trash => {
+                // Paper sticking out of the bin when the trash is full
+                if icon.is_trash_full() {
+                    let mut paper = Paint::default();
+                    paper.set_color(Color::from_rgba8(236, 239, 244, 255));
+                    if let Some(rect) = Rect::from_xywh(
+                        margin + icon_size * 0.3,
+                        margin,
+                        icon_size * 0.4,
+                        icon_size * 0.25,
+                    ) {
+                        pixmap.fill_rect(rect, &paper, Transform::identity(), None);
+                    }
+                }
+
+                // Lid
+                if let Some(rect) = Rect::from_xywh(
+                    margin,
+                    margin + icon_size * 0.15,
+                    icon_size,
+                    icon_size * 0.1,
+                ) {
+                    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+                }
+
+                // Tapered bin body
+                let top = margin + icon_size * 0.3;
+                let mut pb = PathBuilder::new();
+                pb.move_to(margin + icon_size * 0.1, top);
+                pb.line_to(margin + icon_size * 0.9, top);
+                pb.line_to(margin + icon_size * 0.8, margin + icon_size);
+                pb.line_to(margin + icon_size * 0.2, margin + icon_size);
+                pb.close();
+
+                if let Some(path) = pb.finish() {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+                }
+            }
             _ => {
                 // Draw file shape
                 let mut pb = PathBuilder::new();
@@ -404,41 +1110,533 @@ impl IconRenderer {
         Ok(())
     }
 
-    /// Draw the label below the icon
+    /// Draw the label below the icon, over up to `label_lines` lines
     fn draw_label(&self, pixmap: &mut Pixmap, name: &str) -> Result<()> {
-        // Truncate name if too long
-        let max_chars = 12;
-        let display_name = if name.len() > max_chars {
-            format!("{}...", &name[..max_chars - 3])
-        } else {
-            name.to_string()
-        };
+        let lines = self.label_lines(name);
+        let line_height = label_line_height(self.font_size) as f32;
 
-        // Label background
+        // Label background, as tall as the lines drawn
         let label_y = self.size as f32 + 2.0;
-        let label_height = 18.0;
+        let label_height = lines.len() as f32 * line_height + 2.0;
+
+        let text_x = label_anchor_x(self.label_align, self.size as f32);
+        match self.label_pill {
+            Some(ref pill) if pill.lines == PillLines::PerLine => {
+                for (i, line) in lines.iter().enumerate() {
+                    let width = self.text_width(line, self.font_size);
+                    let top = label_y + i as f32 * line_height;
+                    self.fill_label_pill(pixmap, pill, text_x, width, top, line_height + 2.0);
+                }
+            }
+            Some(ref pill) => {
+                let width = lines
+                    .iter()
+                    .map(|line| self.text_width(line, self.font_size))
+                    .fold(0.0, f32::max);
+                self.fill_label_pill(pixmap, pill, text_x, width, label_y, label_height);
+            }
+            None => {
+                let mut bg_paint = Paint::default();
+                bg_paint.set_color(self.label_bg);
+                if let Some(rect) = Rect::from_xywh(0.0, label_y, self.size as f32, label_height) {
+                    pixmap.fill_rect(rect, &bg_paint, Transform::identity(), None);
+                }
+            }
+        }
+
+        // Render text at the configured alignment, with baselines near the bottom of each line
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = label_y + (i + 1) as f32 * line_height - 2.0;
+            self.render_text(
+                pixmap,
+                line,
+                text_x,
+                baseline,
+                self.font_size,
+                self.label_fg,
+                self.label_align,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Lines of the label for `name` as drawn under the icon
+    ///
+    /// The name wraps over up to `label_lines` lines of `label_chars`
+    /// characters; a line still too wide for the icon, measured in the
+    /// label font, is cut short with "…", as is the last line of a name
+    /// that needs more.
+    pub fn label_lines(&self, name: &str) -> Vec<String> {
+        let width = self.size as f32 - 2.0 * LABEL_PADDING;
+        fit_label(name, self.label_chars, self.label_lines)
+            .into_iter()
+            .map(|line| self.ellipsize_to_width(line, width))
+            .collect()
+    }
+
+    /// `line` cut short with "…" until it is at most `width` pixels wide
+    ///
+    /// Without a font to measure with, lines are left as they are.
+    fn ellipsize_to_width(&self, line: String, width: f32) -> String {
+        if self.text_width(&line, self.font_size) <= width {
+            return line;
+        }
+        let mut kept: Vec<char> = line.trim_end_matches('…').chars().collect();
+        while kept.pop().is_some() {
+            let candidate = format!("{}…", kept.iter().collect::<String>());
+            if self.text_width(&candidate, self.font_size) <= width {
+                return candidate;
+            }
+        }
+        "…".to_string()
+    }
+
+    /// Whether `name` is cut short on the label
+    pub fn label_truncated(&self, name: &str) -> bool {
+        self.label_lines(name) != wrap_label(name, self.label_chars)
+    }
+
+    /// Height of the full, wrapped label for `name`
+    pub fn expanded_label_height(&self, name: &str) -> u32 {
+        wrap_label(name, self.label_chars).len() as u32 * EXPANDED_LINE_HEIGHT + 4
+    }
+
+    /// Render the full name over as many lines as it needs, on the label background
+    ///
+    /// The pixmap is as wide as an icon and `expanded_label_height` tall.
+    /// With a label pill, the lines get pills instead, one each or one
+    /// around them all.
+    pub fn render_expanded_label(&self, name: &str) -> Result<Pixmap> {
+        let lines = wrap_label(name, self.label_chars);
+        let mut pixmap = Pixmap::new(self.size, self.expanded_label_height(name))
+            .ok_or_else(|| anyhow::anyhow!("Failed to create expanded label pixmap"))?;
+
+        let text_x = label_anchor_x(self.label_align, self.size as f32);
+        let line_height = EXPANDED_LINE_HEIGHT as f32;
+        let widths: Vec<f32> =
+            lines.iter().map(|line| self.text_width(line, self.font_size)).collect();
+        match self.label_pill {
+            Some(ref pill) if pill.lines == PillLines::PerLine => {
+                for (i, &width) in widths.iter().enumerate() {
+                    let top = 2.0 + i as f32 * line_height;
+                    self.fill_label_pill(&mut pixmap, pill, text_x, width, top, line_height);
+                }
+            }
+            Some(ref pill) => {
+                let width = widths.iter().copied().fold(0.0, f32::max);
+                let height = lines.len() as f32 * line_height;
+                self.fill_label_pill(&mut pixmap, pill, text_x, width, 2.0, height);
+            }
+            None => pixmap.fill(self.label_bg),
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = ((i as u32 + 1) * EXPANDED_LINE_HEIGHT) as f32 - 2.0;
+            self.render_text(
+                &mut pixmap,
+                line,
+                text_x,
+                baseline,
+                self.font_size,
+                self.label_fg,
+                self.label_align,
+            );
+        }
+
+        Ok(pixmap)
+    }
+
+    /// Size of a tooltip showing `lines`
+    pub fn tooltip_size(&self, lines: &[String]) -> (u32, u32) {
+        let text_width = lines
+            .iter()
+            .map(|line| self.text_width(line, self.font_size))
+            .fold(0.0, f32::max);
+        let width = text_width.ceil() as u32 + 2 * TOOLTIP_PADDING;
+        let height = lines.len() as u32 * EXPANDED_LINE_HEIGHT + 2 * TOOLTIP_PADDING;
+        (width, height)
+    }
+
+    /// Render a tooltip: the first line as the label is, the rest dimmer
+    ///
+    /// The pixmap is `tooltip_size` big.
+    pub fn render_tooltip(&self, lines: &[String]) -> Result<Pixmap> {
+        let (width, height) = self.tooltip_size(lines);
+        let mut pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create tooltip pixmap"))?;
+        pixmap.fill(Color::from_rgba8(32, 32, 32, 240));
+
+        let mut detail_color = self.label_fg;
+        detail_color.set_alpha(0.7);
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = (TOOLTIP_PADDING + (i as u32 + 1) * EXPANDED_LINE_HEIGHT) as f32 - 2.0;
+            let color = if i == 0 { self.label_fg } else { detail_color };
+            self.render_text(
+                &mut pixmap,
+                line,
+                TOOLTIP_PADDING as f32,
+                baseline,
+                self.font_size,
+                color,
+                TextAlign::Left,
+            );
+        }
+
+        Ok(pixmap)
+    }
+
+    /// Render the layout debugging overlay: cell outlines numbered by layout index
+    ///
+    /// `cells` are the cells' rectangles in the `width` x `height` overlay, in
+    /// layout index order.
+    pub fn render_debug_grid(&self, width: u32, height: u32, cells: &[Rect]) -> Result<Pixmap> {
+        let mut pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create debug grid pixmap"))?;
+
+        let color = Color::from_rgba8(255, 0, 255, 200);
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        let stroke = Stroke {
+            width: 1.0,
+            ..Default::default()
+        };
+        for (index, cell) in cells.iter().enumerate() {
+            // Inset by half a pixel so 1px lines land on whole pixels
+            let Some(outline) = cell.inset(0.5, 0.5) else {
+                continue;
+            };
+            let path = PathBuilder::from_rect(outline);
+            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+            self.render_text(
+                &mut pixmap,
+                &index.to_string(),
+                cell.x() + 3.0,
+                cell.y() + self.font_size,
+                self.font_size,
+                color,
+                TextAlign::Left,
+            );
+        }
 
-        let mut bg_paint = Paint::default();
-        bg_paint.set_color(self.label_bg);
+        Ok(pixmap)
+    }
 
-        if let Some(rect) = Rect::from_xywh(0.0, label_y, self.size as f32, label_height) {
-            pixmap.fill_rect(rect, &bg_paint, Transform::identity(), None);
+    /// Render the rubber band selecting icons: `band` filled and outlined in
+    /// the selection color, on a clear `width` x `height` overlay
+    pub fn render_rubber_band(&self, width: u32, height: u32, band: Rect) -> Result<Pixmap> {
+        let mut pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create rubber band pixmap"))?;
+
+        let mut paint = Paint::default();
+        paint.set_color(self.selection_color);
+        pixmap.fill_rect(band, &paint, Transform::identity(), None);
+
+        let mut outline = self.selection_color;
+        outline.set_alpha(1.0);
+        paint.set_color(outline);
+        let stroke = Stroke { width: 1.0, ..Default::default() };
+        // Inset by half a pixel so the 1px outline lands on whole pixels
+        if let Some(edge) = band.inset(0.5, 0.5) {
+            pixmap.stroke_path(&PathBuilder::from_rect(edge), &paint, &stroke, Transform::identity(), None);
         }
 
-        // Render text centered horizontally, with baseline near bottom of label area
-        let text_x = self.size as f32 / 2.0;
-        let text_y = label_y + label_height - 4.0; // Position baseline
+        Ok(pixmap)
+    }
+
+    /// Render the selection count badge shown next to the cursor while dragging
+    ///
+    /// Counts above 99 are shown as "99+".
+    pub fn render_selection_badge(&self, count: usize) -> Result<Pixmap> {
+        let mut pixmap = Pixmap::new(SELECTION_BADGE_SIZE, SELECTION_BADGE_SIZE)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create badge pixmap"))?;
+
+        let radius = SELECTION_BADGE_SIZE as f32 / 2.0;
+        if let Some(circle) = PathBuilder::from_circle(radius, radius, radius - 1.0) {
+            // The selection tint is translucent; draw the badge fully opaque
+            let mut color = self.selection_color;
+            color.set_alpha(1.0);
+
+            let mut paint = Paint::default();
+            paint.set_color(color);
+            paint.anti_alias = true;
+            pixmap.fill_path(&circle, &paint, FillRule::Winding, Transform::identity(), None);
+        }
+
+        let text = if count > 99 {
+            "99+".to_string()
+        } else {
+            count.to_string()
+        };
+        let text_size = self.font_size.min(radius);
         self.render_text(
-            pixmap,
-            &display_name,
-            text_x,
-            text_y,
+            &mut pixmap,
+            &text,
+            radius,
+            radius + text_size / 3.0,
+            text_size,
+            self.label_fg,
+            TextAlign::Center,
+        );
+
+        Ok(pixmap)
+    }
+
+    /// Render the confirmation popup asking `message`, with Yes/No buttons
+    pub fn render_confirm_popup(&self, message: &str) -> Result<Pixmap> {
+        let mut pixmap = Pixmap::new(CONFIRM_POPUP_WIDTH, CONFIRM_POPUP_HEIGHT)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create confirmation popup pixmap"))?;
+        pixmap.fill(Color::from_rgba8(32, 32, 32, 240));
+
+        self.render_text(
+            &mut pixmap,
+            message,
+            CONFIRM_POPUP_WIDTH as f32 / 2.0,
+            CONFIRM_POPUP_PADDING + self.font_size,
             self.font_size,
             self.label_fg,
             TextAlign::Center,
         );
 
-        Ok(())
+        let mut yes_color = self.selection_color;
+        yes_color.set_alpha(1.0);
+        let buttons = [
+            ("Yes", confirm_button_rect(true), yes_color),
+            ("No", confirm_button_rect(false), self.label_bg),
+        ];
+        for (label, rect, color) in buttons {
+            let mut paint = Paint::default();
+            paint.set_color(color);
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+            self.render_text(
+                &mut pixmap,
+                label,
+                rect.x() + rect.width() / 2.0,
+                rect.y() + (rect.height() + self.font_size * 0.7) / 2.0,
+                self.font_size,
+                self.label_fg,
+                TextAlign::Center,
+            );
+        }
+
+        Ok(pixmap)
+    }
+
+    /// Render a context menu listing `entries`, with the `highlighted` one marked
+    ///
+    /// The pixmap is `CONTEXT_MENU_WIDTH` wide and `context_menu_height` tall.
+    pub fn render_context_menu(&self, entries: &[MenuEntry], highlighted: Option<usize>) -> Result<Pixmap> {
+        let mut pixmap = Pixmap::new(CONTEXT_MENU_WIDTH, context_menu_height(entries))
+            .ok_or_else(|| anyhow::anyhow!("Failed to create context menu pixmap"))?;
+        pixmap.fill(Color::from_rgba8(32, 32, 32, 240));
+
+        let mut highlight = self.selection_color;
+        highlight.set_alpha(1.0);
+        let mut separator = self.label_fg;
+        separator.set_alpha(0.3);
+
+        let width = CONTEXT_MENU_WIDTH as f32;
+        let mut top = MENU_PADDING as f32;
+        for (i, entry) in entries.iter().enumerate() {
+            let height = menu_row_height(entry) as f32;
+            match entry {
+                MenuEntry::Action { label, .. } => {
+                    if highlighted == Some(i) {
+                        if let Some(rect) = Rect::from_xywh(0.0, top, width, height) {
+                            let mut paint = Paint::default();
+                            paint.set_color(highlight);
+                            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+                        }
+                    }
+                    self.render_text(
+                        &mut pixmap,
+                        label,
+                        MENU_TEXT_INSET,
+                        top + (height + self.font_size * 0.7) / 2.0,
+                        self.font_size,
+                        self.label_fg,
+                        TextAlign::Left,
+                    );
+                }
+                MenuEntry::Separator => {
+                    let inset = MENU_TEXT_INSET;
+                    if let Some(line) = Rect::from_xywh(inset, top + height / 2.0, width - 2.0 * inset, 1.0) {
+                        let mut paint = Paint::default();
+                        paint.set_color(separator);
+                        pixmap.fill_rect(line, &paint, Transform::identity(), None);
+                    }
+                }
+            }
+            top += height;
+        }
+
+        Ok(pixmap)
+    }
+
+    /// Draw a widget's overlay badge into a corner of the icon glyph
+    ///
+    /// The badge is 3/8 of the glyph size (at least `MIN_OVERLAY_SIZE`) and is
+    /// kept entirely within the pixmap, which holds the glyph centered at its top.
+    pub fn composite_overlay(&self, pixmap: &mut Pixmap, overlay: &Overlay, glyph_size: u32) {
+        let badge_size = overlay_size(glyph_size, pixmap.width(), pixmap.height());
+        let Some(badge) = self.render_overlay_badge(overlay, badge_size) else {
+            return;
+        };
+
+        let (x, y) = overlay_origin(overlay.corner, pixmap.width(), pixmap.height(), glyph_size, badge_size);
+        let center = badge_size as f32 / 2.0;
+        let transform = Transform::from_rotate_at(overlay.rotation, x as f32 + center, y as f32 + center)
+            .pre_translate(x as f32, y as f32);
+        let paint = PixmapPaint {
+            quality: tiny_skia::FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        pixmap.draw_pixmap(0, 0, badge.as_ref(), &paint, transform, None);
+    }
+
+    /// Draw a status emblem into its corner of the icon glyph
+    pub fn composite_emblem(&self, pixmap: &mut Pixmap, emblem: Emblem, glyph_size: u32) {
+        self.composite_overlay(pixmap, &emblem.overlay(), glyph_size);
+    }
+
+    /// Mark an icon whose widget isn't running, in the top-left corner of its glyph
+    ///
+    /// A crashed widget and missing scripts get different badges; icons
+    /// whose widget runs are left alone.
+    pub fn composite_widget_status(&self, pixmap: &mut Pixmap, status: WidgetStatus, glyph_size: u32) {
+        let (emblem, color) = match status {
+            WidgetStatus::Ok => return,
+            WidgetStatus::LuaCrashed => ("action-unavailable", BROKEN_WIDGET_COLOR),
+            WidgetStatus::NoHandler | WidgetStatus::NoWidget => ("dialog-warning", MISSING_SCRIPT_COLOR),
+        };
+        let overlay = Overlay {
+            emblem: Some(emblem.to_string()),
+            color: Some(color.to_string()),
+            corner: OverlayCorner::TopLeft,
+            ..Overlay::default()
+        };
+        self.composite_overlay(pixmap, &overlay, glyph_size);
+    }
+
+    /// Mark a symlink's icon, in the bottom-right corner of its glyph
+    ///
+    /// Links to a missing target get a broken-link badge instead of the arrow.
+    pub fn composite_link(&self, pixmap: &mut Pixmap, broken: bool, glyph_size: u32) {
+        let (emblem, color) = match broken {
+            true => ("emblem-unreadable", BROKEN_LINK_COLOR),
+            false => ("emblem-symbolic-link", LINK_COLOR),
+        };
+        let overlay = Overlay {
+            emblem: Some(emblem.to_string()),
+            color: Some(color.to_string()),
+            corner: OverlayCorner::BottomRight,
+            ..Overlay::default()
+        };
+        self.composite_overlay(pixmap, &overlay, glyph_size);
+    }
+
+    /// Render an overlay badge into a new `size` x `size` pixmap
+    pub fn render_overlay_badge(&self, overlay: &Overlay, size: u32) -> Option<Pixmap> {
+        let mut pixmap = Pixmap::new(size, size)?;
+        let radius = size as f32 / 2.0;
+        let background = overlay
+            .color
+            .as_deref()
+            .and_then(parse_color)
+            .or_else(|| parse_color(OVERLAY_COLOR))
+            .unwrap_or(Color::BLACK);
+
+        if let Some(count) = overlay.count {
+            fill_circle(&mut pixmap, radius, radius, radius - 0.5, background);
+            let text = if count > 99 { "99+".to_string() } else { count.to_string() };
+            let text_size = (size as f32 * 0.6).min(self.font_size.max(radius));
+            self.render_text(
+                &mut pixmap,
+                &text,
+                radius,
+                radius + text_size / 3.0,
+                text_size,
+                Color::WHITE,
+                TextAlign::Center,
+            );
+        } else if let Some(ref emblem) = overlay.emblem {
+            fill_circle(&mut pixmap, radius, radius, radius - 0.5, background);
+            let theme = self.symbolic.as_ref().map_or("Adwaita", |style| style.theme.as_str());
+            let inner = size * 5 / 8;
+            if let Some(mut glyph) = find_symbolic_icon(theme, emblem).and_then(|path| render_svg(&path, inner, inner)) {
+                recolor(&mut glyph, Color::WHITE);
+                let offset = ((size - inner) / 2) as i32;
+                pixmap.draw_pixmap(
+                    offset,
+                    offset,
+                    glyph.as_ref(),
+                    &PixmapPaint::default(),
+                    Transform::identity(),
+                    None,
+                );
+            }
+        } else if let Some(color) = overlay.dot.as_deref().and_then(parse_color) {
+            // A dot is half the badge, ringed so it shows on any glyph color
+            fill_circle(&mut pixmap, radius, radius, radius / 2.0 + 1.5, Color::WHITE);
+            fill_circle(&mut pixmap, radius, radius, radius / 2.0, color);
+        } else {
+            return None;
+        }
+
+        Some(pixmap)
+    }
+
+    /// Render a stacked preview of folder items, to composite over a folder glyph
+    ///
+    /// Each item is drawn as a small card, offset so the stack fans out; image
+    /// items show a thumbnail. Returns None if there are no items.
+    pub fn render_folder_peek(&self, items: &[PathBuf], size: u32) -> Option<Pixmap> {
+        if items.is_empty() || size == 0 {
+            return None;
+        }
+
+        let mut pixmap = Pixmap::new(size, size)?;
+        let count = items.len() as f32;
+        let step = size as f32 * 0.15;
+        let card = size as f32 - step * (count - 1.0);
+        let inset = 2.0;
+
+        let mut card_paint = Paint::default();
+        card_paint.set_color(Color::from_rgba8(250, 250, 250, 255));
+        let mut border_paint = Paint::default();
+        border_paint.set_color(Color::from_rgba8(0, 0, 0, 80));
+        let border = Stroke {
+            width: 1.0,
+            ..Default::default()
+        };
+
+        // Draw back to front so the first item ends up on top
+        for (i, item) in items.iter().enumerate().rev() {
+            let offset = step * i as f32;
+            let rect = match Rect::from_xywh(offset, step * (count - 1.0) - offset, card, card) {
+                Some(r) => r,
+                None => continue,
+            };
+
+            pixmap.fill_rect(rect, &card_paint, Transform::identity(), None);
+            let outline = PathBuilder::from_rect(rect);
+            pixmap.stroke_path(&outline, &border_paint, &border, Transform::identity(), None);
+
+            if is_image_path(item) {
+                if let Some(path) = item.to_str() {
+                    self.render_image(
+                        &mut pixmap,
+                        path,
+                        rect.x() + inset,
+                        rect.y() + inset,
+                        rect.width() - inset * 2.0,
+                        rect.height() - inset * 2.0,
+                    );
+                }
+            }
+        }
+
+        Some(pixmap)
     }
 
     /// Execute Lua draw commands
@@ -527,6 +1725,19 @@ impl IconRenderer {
                 DrawCommand::Image { path, x, y, w, h } => {
                     self.render_image(pixmap, path, *x, *y, *w, *h);
                 }
+                DrawCommand::Bar { x, y, w, h, fraction, color, background } => {
+                    let filled = w * fraction.clamp(0.0, 1.0);
+                    for (width, color) in [(*w, background), (filled, color)] {
+                        if let (Some(rect), Some(color)) = (
+                            Rect::from_xywh(*x, *y, width, *h),
+                            parse_color(color),
+                        ) {
+                            let mut paint = Paint::default();
+                            paint.set_color(color);
+                            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+                        }
+                    }
+                }
                 DrawCommand::StrokeCircle { cx, cy, r, color, width } => {
                     if let Some(color) = parse_color(color) {
                         let mut paint = Paint::default();
@@ -539,19 +1750,483 @@ impl IconRenderer {
                             ..Default::default()
                         };
 
-                        let mut pb = PathBuilder::new();
-                        pb.push_circle(*cx, *cy, *r);
+                        let mut pb = PathBuilder::new();
+                        pb.push_circle(*cx, *cy, *r);
+
+                        if let Some(path) = pb.finish() {
+                            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Base directories searched for icon themes
+fn icon_theme_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(data_dir) = dirs::data_dir() {
+        dirs.push(data_dir.join("icons"));
+    }
+    dirs.push(PathBuf::from("/usr/share/icons"));
+    dirs
+}
+
+/// Theme subdirectories that may contain symbolic icons
+const SYMBOLIC_SUBDIRS: &[&str] = &["symbolic", "scalable"];
+
+/// Icon contexts searched within each subdirectory
+const SYMBOLIC_CONTEXTS: &[&str] = &["places", "mimetypes", "emblems", "status", "legacy", "apps"];
+
+/// Find `<name>-symbolic.svg` in an icon theme
+pub fn find_symbolic_icon(theme: &str, name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}-symbolic.svg", name);
+
+    icon_theme_dirs()
+        .into_iter()
+        .flat_map(|base| {
+            SYMBOLIC_SUBDIRS.iter().flat_map(move |sub| {
+                let base = base.clone();
+                SYMBOLIC_CONTEXTS
+                    .iter()
+                    .map(move |ctx| base.join(theme).join(sub).join(ctx))
+            })
+        })
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// Theme subdirectories searched for full-color icons, largest first
+const THEME_SIZE_SUBDIRS: &[&str] = &["scalable", "256x256", "128x128", "96x96", "64x64", "48x48"];
+
+/// Icon contexts searched for full-color icons
+const THEME_CONTEXTS: &[&str] = &["places", "apps", "devices", "mimetypes", "emblems", "status"];
+
+/// Find a full-color icon named `name` in an icon theme
+///
+/// Falls back to the hicolor theme and `/usr/share/pixmaps`, where
+/// applications install their icons.
+pub fn find_theme_icon(theme: &str, name: &str) -> Option<PathBuf> {
+    let bases = icon_theme_dirs();
+    let themed = [theme, "hicolor"].into_iter().flat_map(|theme| {
+        bases.iter().flat_map(move |base| {
+            THEME_SIZE_SUBDIRS.iter().flat_map(move |sub| {
+                THEME_CONTEXTS.iter().map(move |ctx| base.join(theme).join(sub).join(ctx))
+            })
+        })
+    });
+
+    themed
+        .chain(std::iter::once(PathBuf::from("/usr/share/pixmaps")))
+        .flat_map(|dir| ["svg", "png"].map(|ext| dir.join(format!("{}.{}", name, ext))))
+        .find(|path| path.is_file())
+}
+
+/// Rasterize an SVG file to a pixmap of the given size
+pub fn render_svg(path: &Path, width: u32, height: u32) -> Option<Pixmap> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read SVG '{}': {}", path.display(), e);
+            return None;
+        }
+    };
+
+    let tree = match resvg::usvg::Tree::from_data(&data, &resvg::usvg::Options::default()) {
+        Ok(tree) => tree,
+        Err(e) => {
+            warn!("Failed to parse SVG '{}': {}", path.display(), e);
+            return None;
+        }
+    };
+    render_tree(&tree, width, height)
+}
+
+/// Rasterize a parsed SVG to a pixmap of the given size
+fn render_tree(tree: &resvg::usvg::Tree, width: u32, height: u32) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(width, height)?;
+    let size = tree.size();
+    let transform = Transform::from_scale(
+        width as f32 / size.width(),
+        height as f32 / size.height(),
+    );
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    Some(pixmap)
+}
+
+/// Recolor every pixel to `tint`, keeping its coverage (alpha)
+///
+/// This is how symbolic icons are themed: the shape comes from the alpha
+/// channel and the color from the tint.
+pub fn recolor(pixmap: &mut Pixmap, tint: Color) {
+    let tint = tint.to_color_u8();
+    for pixel in pixmap.pixels_mut() {
+        let coverage = pixel.alpha() as u32;
+        if coverage == 0 {
+            continue;
+        }
+
+        let a = coverage * tint.alpha() as u32 / 255;
+        let premultiply = |c: u8| (c as u32 * a / 255) as u8;
+        if let Some(color) = tiny_skia::PremultipliedColorU8::from_rgba(
+            premultiply(tint.red()),
+            premultiply(tint.green()),
+            premultiply(tint.blue()),
+            a as u8,
+        ) {
+            *pixel = color;
+        }
+    }
+}
+
+/// Convert tiny-skia's premultiplied RGBA pixels to little-endian ARGB8888 (BGRA bytes)
+///
+/// Converts as many whole pixels as fit in both slices.
+pub fn rgba_to_bgra(src: &[u8], dst: &mut [u8]) {
+    for (from, to) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        to[0] = from[2]; // B
+        to[1] = from[1]; // G
+        to[2] = from[0]; // R
+        to[3] = from[3]; // A
+    }
+}
+
+/// Multiply the opacity of premultiplied pixels by `opacity`
+///
+/// Color channels are premultiplied by alpha, so all four are scaled alike;
+/// scaling only the alpha would brighten the pixels instead. Works on RGBA
+/// and BGRA bytes alike.
+pub fn scale_opacity(pixels: &mut [u8], opacity: f32) {
+    let factor = (opacity.clamp(0.0, 1.0) * 255.0).round() as u32;
+    if factor == 255 {
+        return;
+    }
+    for channel in pixels.iter_mut() {
+        *channel = ((*channel as u32 * factor + 127) / 255) as u8;
+    }
+}
+
+/// Ease-out curve for animation progress in `0.0..=1.0`
+pub fn ease_out(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+/// Strength from 0 to 1 of a flash highlight `progress` of the way through
+///
+/// The highlight swells and fades `pulses` times, starting and ending at 0.
+pub fn flash_level(progress: f32, pulses: u32) -> f32 {
+    let phase = progress.clamp(0.0, 1.0) * pulses.max(1) as f32;
+    0.5 - 0.5 * (phase * std::f32::consts::TAU).cos()
+}
+
+/// Draw `pixmap` over a rounded glow of `color` behind its glyph
+///
+/// The glow's opacity is scaled by `level`; it fills the `glyph_size` square
+/// centered at the top, where the glyph is drawn.
+pub fn glow_frame(pixmap: &Pixmap, glyph_size: u32, color: &str, level: f32) -> Option<Pixmap> {
+    let mut frame = Pixmap::new(pixmap.width(), pixmap.height())?;
+    let mut color = parse_color(color).unwrap_or(Color::WHITE);
+    color.apply_opacity(level.clamp(0.0, 1.0));
+
+    let size = glyph_size.min(pixmap.width()) as f32;
+    let left = (pixmap.width() as f32 - size) / 2.0;
+    let rect = Rect::from_xywh(left, 0.0, size, size);
+    if let Some(glow) = rect.and_then(|rect| rounded_rect(rect, size / 5.0)) {
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        paint.anti_alias = true;
+        frame.fill_path(&glow, &paint, FillRule::Winding, Transform::identity(), None);
+    }
+    let paint = PixmapPaint::default();
+    frame.draw_pixmap(0, 0, pixmap.as_ref(), &paint, Transform::identity(), None);
+    Some(frame)
+}
+
+/// Draw a frame of an appear/disappear animation
+///
+/// The pixmap is scaled about its center and its opacity multiplied by
+/// `opacity`; the result has the same size as the input.
+pub fn animate_frame(pixmap: &Pixmap, opacity: f32, scale: f32) -> Option<Pixmap> {
+    let mut frame = Pixmap::new(pixmap.width(), pixmap.height())?;
+    let cx = pixmap.width() as f32 / 2.0;
+    let cy = pixmap.height() as f32 / 2.0;
+    let transform = Transform::from_translate(cx, cy)
+        .pre_scale(scale, scale)
+        .pre_translate(-cx, -cy);
+    let paint = PixmapPaint {
+        opacity: opacity.clamp(0.0, 1.0),
+        quality: tiny_skia::FilterQuality::Bilinear,
+        ..PixmapPaint::default()
+    };
+    frame.draw_pixmap(0, 0, pixmap.as_ref(), &paint, transform, None);
+    Some(frame)
+}
+
+/// Draw `pixmap` magnified by `zoom`, centered in a `width` x `height` pixmap
+///
+/// Whatever does not fit is cut off.
+pub fn zoom_frame(pixmap: &Pixmap, width: u32, height: u32, zoom: f32) -> Option<Pixmap> {
+    let mut frame = Pixmap::new(width, height)?;
+    let transform = Transform::from_translate(width as f32 / 2.0, height as f32 / 2.0)
+        .pre_scale(zoom, zoom)
+        .pre_translate(-(pixmap.width() as f32) / 2.0, -(pixmap.height() as f32) / 2.0);
+    let paint = PixmapPaint {
+        quality: tiny_skia::FilterQuality::Bicubic,
+        ..PixmapPaint::default()
+    };
+    frame.draw_pixmap(0, 0, pixmap.as_ref(), &paint, transform, None);
+    Some(frame)
+}
+
+/// A black shadow of `pixmap`'s shape, offset and blurred, at the same size
+pub fn drop_shadow(pixmap: &Pixmap, elevation: &ElevationConfig, scale: f32) -> Option<Pixmap> {
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    let dx = (elevation.offset_x * scale).round() as i32;
+    let dy = (elevation.offset_y * scale).round() as i32;
+    let radius = (elevation.blur * scale).round().max(0.0) as usize;
+    let opacity = elevation.opacity.clamp(0.0, 1.0);
+
+    // Coverage of the offset icon
+    let mut alpha = vec![0.0f32; (width * height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let (sx, sy) = (x - dx, y - dy);
+            if sx >= 0 && sy >= 0 && sx < width && sy < height {
+                let pixel = pixmap.pixels()[(sy * width + sx) as usize];
+                alpha[(y * width + x) as usize] = pixel.alpha() as f32 / 255.0;
+            }
+        }
+    }
+    box_blur(&mut alpha, width as usize, height as usize, radius);
+
+    let mut shadow = Pixmap::new(pixmap.width(), pixmap.height())?;
+    for (pixel, coverage) in shadow.pixels_mut().iter_mut().zip(alpha) {
+        let a = (coverage * opacity * 255.0).round() as u8;
+        *pixel = tiny_skia::PremultipliedColorU8::from_rgba(0, 0, 0, a).expect("black is valid at any alpha");
+    }
+    Some(shadow)
+}
+
+/// Blur a coverage map with a box `2 * radius + 1` wide, horizontally then vertically
+fn box_blur(values: &mut [f32], width: usize, height: usize, radius: usize) {
+    if radius == 0 {
+        return;
+    }
+    let window = (2 * radius + 1) as f32;
+    let mut line = Vec::new();
+    for y in 0..height {
+        line.clear();
+        line.extend_from_slice(&values[y * width..(y + 1) * width]);
+        for x in 0..width {
+            let (start, end) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            values[y * width + x] = line[start..end].iter().sum::<f32>() / window;
+        }
+    }
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| values[y * width + x]));
+        for y in 0..height {
+            let (start, end) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+            values[y * width + x] = line[start..end].iter().sum::<f32>() / window;
+        }
+    }
+}
+
+/// Side length of overlay badges for a glyph, limited to the surface
+pub fn overlay_size(glyph_size: u32, width: u32, height: u32) -> u32 {
+    (glyph_size * 3 / 8).max(MIN_OVERLAY_SIZE).min(width).min(height)
+}
+
+/// Top-left corner of an overlay badge, clamped so it stays within the surface
+///
+/// The glyph is a `glyph_size` square centered horizontally at the top of a
+/// `width` x `height` surface; badges sit flush in its corners.
+pub fn overlay_origin(corner: OverlayCorner, width: u32, height: u32, glyph_size: u32, badge_size: u32) -> (i32, i32) {
+    let glyph_size = glyph_size.min(width).min(height) as i32;
+    let badge = badge_size as i32;
+    let left = (width as i32 - glyph_size) / 2;
+    let right = left + glyph_size - badge;
+    let bottom = glyph_size - badge;
+
+    let (x, y) = match corner {
+        OverlayCorner::TopLeft => (left, 0),
+        OverlayCorner::TopRight => (right, 0),
+        OverlayCorner::BottomLeft => (left, bottom),
+        OverlayCorner::BottomRight => (right, bottom),
+    };
+    (
+        x.clamp(0, (width as i32 - badge).max(0)),
+        y.clamp(0, (height as i32 - badge).max(0)),
+    )
+}
+
+/// `name` shortened to `max_chars` characters, ending in "…" if cut
+fn truncate_label(name: &str, max_chars: usize) -> String {
+    if name.chars().count() <= max_chars {
+        return name.to_string();
+    }
+    let kept: String = name.chars().take(max_chars.saturating_sub(1)).collect();
+    format!("{}…", kept)
+}
+
+/// `name` wrapped as by `wrap_label`, into at most `max_lines` lines
+///
+/// The last line of a name needing more takes as much of the rest as fits,
+/// ending in "…".
+fn fit_label(name: &str, max_chars: usize, max_lines: u32) -> Vec<String> {
+    let max_lines = max_lines.max(1) as usize;
+    let mut lines = wrap_label(name, max_chars);
+    if lines.len() <= max_lines {
+        return lines;
+    }
+    lines.truncate(max_lines - 1);
+    // Each line starts the rest of the name, less the spaces it broke at
+    let mut rest = name;
+    for line in &lines {
+        rest = rest[line.len()..].trim_start();
+    }
+    lines.push(truncate_label(rest, max_chars.max(1)));
+    lines
+}
+
+/// Height of one label line at `font_size`
+fn label_line_height(font_size: f32) -> u32 {
+    (font_size * 4.0 / 3.0).ceil().max(1.0) as u32
+}
+
+/// Height of the band below an icon for labels of up to `lines` lines at `font_size`
+///
+/// Icon surfaces are this much taller than the icon.
+pub fn label_height(font_size: f32, lines: u32) -> u32 {
+    label_line_height(font_size) * lines.max(1) + LABEL_BAND_MARGIN
+}
+
+/// `name` split into lines of at most `max_chars` characters
+///
+/// Lines break after a space, `-`, `_` or `.` where one falls on the line,
+/// and mid-word otherwise.
+pub fn wrap_label(name: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    let mut rest: Vec<char> = name.chars().collect();
+    while rest.len() > max_chars {
+        let cut = rest[..max_chars]
+            .iter()
+            .rposition(|c| matches!(c, ' ' | '-' | '_' | '.'))
+            .map(|i| i + 1)
+            .unwrap_or(max_chars);
+        let line: String = rest.drain(..cut).collect();
+        lines.push(line.trim_end().to_string());
+    }
+    if !rest.is_empty() || lines.is_empty() {
+        lines.push(rest.into_iter().collect());
+    }
+    lines
+}
+
+/// The x `render_text` anchors a label at in a band `width` wide
+///
+/// Left and right labels keep `LABEL_PADDING` from the band's edge; centered
+/// labels are anchored at its middle.
+pub fn label_anchor_x(align: TextAlign, width: f32) -> f32 {
+    match align {
+        TextAlign::Left => LABEL_PADDING,
+        TextAlign::Center => width / 2.0,
+        TextAlign::Right => width - LABEL_PADDING,
+    }
+}
+
+/// Path of `rect` with its corners rounded to `radius`, at most half its shorter side
+fn rounded_rect(rect: Rect, radius: f32) -> Option<tiny_skia::Path> {
+    let r = radius.clamp(0.0, rect.width().min(rect.height()) / 2.0);
+    if r <= 0.0 {
+        return Some(PathBuilder::from_rect(rect));
+    }
+    // Control point distance for a cubic approximating a quarter circle
+    let k = r * 0.552_284_8;
+    let (left, top, right, bottom) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+    let mut pb = PathBuilder::new();
+    pb.move_to(left + r, top);
+    pb.line_to(right - r, top);
+    pb.cubic_to(right - r + k, top, right, top + r - k, right, top + r);
+    pb.line_to(right, bottom - r);
+    pb.cubic_to(right, bottom - r + k, right - r + k, bottom, right - r, bottom);
+    pb.line_to(left + r, bottom);
+    pb.cubic_to(left + r - k, bottom, left, bottom - r + k, left, bottom - r);
+    pb.line_to(left, top + r);
+    pb.cubic_to(left, top + r - k, left + r - k, top, left + r, top);
+    pb.close();
+    pb.finish()
+}
+
+/// Fill an anti-aliased circle
+fn fill_circle(pixmap: &mut Pixmap, cx: f32, cy: f32, radius: f32, color: Color) {
+    if let Some(circle) = PathBuilder::from_circle(cx, cy, radius) {
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        paint.anti_alias = true;
+        pixmap.fill_path(&circle, &paint, FillRule::Winding, Transform::identity(), None);
+    }
+}
+
+/// Fit a rendered icon into a buffer of the given size
+///
+/// The icon is centered horizontally and kept at the top so the glyph and
+/// label stay aligned; larger buffers are padded with transparency and smaller
+/// ones crop the edges. Returns the pixmap unchanged if it already matches.
+pub fn fit_pixmap(pixmap: Pixmap, width: u32, height: u32) -> Option<Pixmap> {
+    if pixmap.width() == width && pixmap.height() == height {
+        return Some(pixmap);
+    }
+
+    let mut fitted = Pixmap::new(width, height)?;
+    let x = (width as i32 - pixmap.width() as i32) / 2;
+    fitted.draw_pixmap(
+        x,
+        0,
+        pixmap.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+    Some(fitted)
+}
 
-                        if let Some(path) = pb.finish() {
-                            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
-                        }
-                    }
-                }
-            }
+/// Copy an RGBA image into a pixmap, premultiplying its alpha for tiny-skia
+pub fn pixmap_from_rgba(rgba: &image::RgbaImage) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(rgba.width(), rgba.height())?;
+    let pixels = pixmap.pixels_mut();
+    for (i, pixel) in rgba.pixels().enumerate() {
+        let [r, g, b, a] = pixel.0;
+
+        if a == 0 {
+            // Fully transparent, skip (pixmap is already zero-initialized)
+            continue;
         }
 
-        Ok(())
+        let alpha = a as f32 / 255.0;
+        let pm_r = (r as f32 * alpha) as u8;
+        let pm_g = (g as f32 * alpha) as u8;
+        let pm_b = (b as f32 * alpha) as u8;
+
+        if let Some(color) = tiny_skia::PremultipliedColorU8::from_rgba(pm_r, pm_g, pm_b, a) {
+            pixels[i] = color;
+        }
     }
+    Some(pixmap)
+}
+
+/// Check whether a path looks like an image we can thumbnail
+fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| matches!(e.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "ico"))
+        .unwrap_or(false)
 }
 
 /// Parse a color string (hex format)
@@ -1008,6 +2683,29 @@ mod tests {
         assert!(near_line.blue() > 0, "Pixel near wide line should also be blue");
     }
 
+    #[test]
+    fn test_bar_fills_fraction_over_background() {
+        let renderer = IconRenderer::new(64, 12.0);
+        let mut pixmap = Pixmap::new(64, 64).unwrap();
+
+        let commands = vec![DrawCommand::Bar {
+            x: 0.0,
+            y: 30.0,
+            w: 64.0,
+            h: 4.0,
+            fraction: 0.25,
+            color: "#ff0000".to_string(),
+            background: "#0000ff".to_string(),
+        }];
+        renderer.execute_commands(&mut pixmap, &commands).unwrap();
+
+        let used = pixmap.pixel(8, 31).unwrap();
+        assert_eq!((used.red(), used.blue()), (255, 0), "Used part should be the bar color");
+        let free = pixmap.pixel(40, 31).unwrap();
+        assert_eq!((free.red(), free.blue()), (0, 255), "Free part should be the background");
+        assert_eq!(pixmap.pixel(8, 20).unwrap().alpha(), 0, "Nothing outside the bar");
+    }
+
     // ========================================================================
     // Multiple Commands Tests
     // ========================================================================
@@ -1173,6 +2871,225 @@ mod tests {
         assert_eq!(TextAlign::from_str("justify"), TextAlign::Left);
     }
 
+    fn test_elevation() -> ElevationConfig {
+        ElevationConfig { enabled: true, blur: 0.0, offset_x: 0.0, offset_y: 4.0, opacity: 0.5 }
+    }
+
+    /// A transparent 32x40 icon with an opaque white square glyph at 8..24
+    fn square_icon() -> Pixmap {
+        let mut pixmap = Pixmap::new(32, 40).unwrap();
+        let mut paint = Paint::default();
+        paint.set_color(Color::WHITE);
+        pixmap.fill_rect(Rect::from_xywh(8.0, 8.0, 16.0, 16.0).unwrap(), &paint, Transform::identity(), None);
+        pixmap
+    }
+
+    #[test]
+    fn test_elevation_draws_shadow_below_glyph() {
+        let mut renderer = IconRenderer::with_font(32, 12.0, None);
+        let mut pixmap = square_icon();
+        renderer.apply_elevation(&mut pixmap, 1.0);
+        assert_eq!(pixmap.pixel(16, 26).unwrap().alpha(), 0, "No shadow while disabled");
+
+        renderer.set_elevation(Some(test_elevation()));
+        renderer.apply_elevation(&mut pixmap, 1.0);
+
+        let shadow = pixmap.pixel(16, 26).unwrap();
+        assert_eq!((shadow.red(), shadow.alpha()), (0, 128));
+        // The glyph stays on top, and nothing appears above it
+        assert_eq!(pixmap.pixel(16, 16).unwrap().red(), 255);
+        assert_eq!(pixmap.pixel(16, 6).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_elevation_scales_and_blurs() {
+        let elevation = ElevationConfig { blur: 2.0, ..test_elevation() };
+        let icon = square_icon();
+
+        // At 2x the offset is 8px, so the shadow reaches row 31
+        let shadow = drop_shadow(&icon, &elevation, 2.0).unwrap();
+        assert!(shadow.pixel(16, 30).unwrap().alpha() > 0);
+        assert_eq!(drop_shadow(&icon, &elevation, 1.0).unwrap().pixel(16, 30).unwrap().alpha(), 0);
+
+        // The blurred edge fades out rather than stopping hard
+        let edge = shadow.pixel(16, 34).unwrap().alpha();
+        assert!(edge > 0 && edge < 128, "edge alpha {}", edge);
+    }
+
+    #[test]
+    fn test_label_anchor_x_for_each_alignment() {
+        assert_eq!(label_anchor_x(TextAlign::Left, 64.0), 2.0);
+        assert_eq!(label_anchor_x(TextAlign::Center, 64.0), 32.0);
+        assert_eq!(label_anchor_x(TextAlign::Right, 64.0), 62.0);
+        assert_eq!(TextAlign::from(LabelAlign::Right), TextAlign::Right);
+    }
+
+    #[test]
+    fn test_truncate_label_counts_characters() {
+        assert_eq!(truncate_label("notes.txt", 12), "notes.txt");
+        assert_eq!(truncate_label("a-very-long-name.txt", 12), "a-very-long…");
+        // Multi-byte names are cut on character boundaries
+        assert_eq!(truncate_label("ééééééééééééé", 6), "ééééé…");
+    }
+
+    #[test]
+    fn test_fit_label_wraps_then_ellipsizes() {
+        // A single word too long for two lines is cut mid-word
+        assert_eq!(fit_label("abcdefghijklmnopqrstuvwxyz", 10, 2), vec!["abcdefghij", "klmnopqrs…"]);
+        // Words wrap at their spaces, and the rest of the name fills the last line
+        assert_eq!(
+            fit_label("quarterly report final draft.pdf", 12, 2),
+            vec!["quarterly", "report fina…"]
+        );
+        assert_eq!(fit_label("notes.txt", 12, 2), vec!["notes.txt"]);
+        assert_eq!(fit_label("", 12, 2), vec![""]);
+        assert_eq!(fit_label("quarterly report", 12, 1), vec!["quarterly r…"]);
+    }
+
+    #[test]
+    fn test_label_band_grows_with_lines() {
+        assert_eq!(label_height(12.0, 1), 24);
+        assert_eq!(label_height(12.0, 2), 40);
+        assert!(label_height(20.0, 2) > label_height(12.0, 2));
+
+        let mut renderer = IconRenderer::new(64, 12.0);
+        renderer.set_label_lines(2);
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("n.txt");
+        std::fs::write(&file, "x").unwrap();
+        let icon = DesktopIcon::new(&file, &crate::config::Config::default()).unwrap();
+        assert_eq!(renderer.render(&icon).unwrap().height(), 64 + 40);
+        // An empty name draws an empty band without failing
+        let glyph = renderer.render_glyph_layer(&icon).unwrap();
+        assert!(renderer.render_label_layer(&glyph, "").is_ok());
+        assert_eq!(renderer.label_lines(""), vec![""]);
+    }
+
+    #[test]
+    fn test_rename_field_covers_the_label_and_shows_refusals() {
+        let renderer = IconRenderer::new(64, 12.0);
+        let mut field = RenameField::new("notes.txt");
+        for scale in [1.0, 2.0] {
+            let size = (64.0 * scale) as u32;
+            let mut pixmap = Pixmap::new(size, size + (40.0 * scale) as u32).unwrap();
+            renderer.draw_rename_field(&mut pixmap, &field, scale);
+            // Outlined in the focus color just below the glyph, which is left alone
+            let top = pixmap.pixel(size / 2, (66.0 * scale) as u32).unwrap();
+            assert_eq!((top.red(), top.green(), top.blue()), (136, 192, 208));
+            assert_eq!(pixmap.pixel(size / 2, size / 2).unwrap().alpha(), 0);
+        }
+
+        field.set_invalid();
+        let mut pixmap = Pixmap::new(64, 104).unwrap();
+        renderer.draw_rename_field(&mut pixmap, &field, 1.0);
+        let top = pixmap.pixel(32, 66).unwrap();
+        assert_eq!((top.red(), top.green(), top.blue()), (224, 27, 36));
+    }
+
+    #[test]
+    fn test_debug_grid_outlines_cells() {
+        let renderer = IconRenderer::new(64, 12.0);
+        let cells = [
+            Rect::from_xywh(10.0, 10.0, 40.0, 40.0).unwrap(),
+            Rect::from_xywh(50.0, 10.0, 40.0, 40.0).unwrap(),
+        ];
+        let pixmap = renderer.render_debug_grid(100, 60, &cells).unwrap();
+
+        assert!(pixmap.pixel(10, 30).unwrap().alpha() > 0, "Left edge of the first cell");
+        assert!(pixmap.pixel(89, 30).unwrap().alpha() > 0, "Right edge of the second cell");
+        assert_eq!(pixmap.pixel(30, 30).unwrap().alpha(), 0, "Cells are not filled");
+        assert_eq!(pixmap.pixel(95, 55).unwrap().alpha(), 0, "Nothing outside the cells");
+    }
+
+    #[test]
+    fn test_wrap_label_breaks_at_separators() {
+        assert_eq!(wrap_label("notes.txt", 12), vec!["notes.txt"]);
+        assert_eq!(
+            wrap_label("quarterly report final.pdf", 12),
+            vec!["quarterly", "report", "final.pdf"]
+        );
+        assert_eq!(wrap_label("abcdefghijklmnop", 6), vec!["abcdef", "ghijkl", "mnop"]);
+        assert_eq!(wrap_label("", 12), vec![""]);
+    }
+
+    #[test]
+    fn test_expanded_label_fits_every_line() {
+        let renderer = IconRenderer::new(64, 12.0);
+        let name = "a-rather-long-file-name.txt";
+        assert!(renderer.label_truncated(name));
+        assert!(!renderer.label_truncated("notes.txt"));
+
+        let pixmap = renderer.render_expanded_label(name).unwrap();
+        let lines = wrap_label(name, DEFAULT_LABEL_CHARS).len() as u32;
+        assert!(lines > 1);
+        assert_eq!((pixmap.width(), pixmap.height()), (64, lines * EXPANDED_LINE_HEIGHT + 4));
+    }
+
+    #[test]
+    fn test_label_alignment_moves_text() {
+        let Some(font) = load_default_font() else {
+            return; // No system font to measure text with
+        };
+        let mut renderer = IconRenderer::with_font(64, 12.0, Some(font));
+        let glyph = Pixmap::new(64, 88).unwrap();
+
+        let mut first_column = |align| {
+            renderer.set_label_layout(align, 12);
+            let pixmap = renderer.render_label_layer(&glyph, "ab").unwrap();
+            let label_top = 64 + 2;
+            let background = pixmap.pixel(0, label_top).unwrap();
+            (0..64)
+                .find(|&x| (label_top..label_top + 18).any(|y| pixmap.pixel(x, y).unwrap() != background))
+                .unwrap()
+        };
+        let left = first_column(TextAlign::Left);
+        let center = first_column(TextAlign::Center);
+        let right = first_column(TextAlign::Right);
+        assert!(left < center && center < right, "{} {} {}", left, center, right);
+    }
+
+    #[test]
+    fn test_label_pill_fits_text() {
+        let Some(font) = load_default_font() else {
+            return; // No system font to measure text with
+        };
+        let mut renderer = IconRenderer::with_font(64, 12.0, Some(font));
+        let pill =
+            LabelPillConfig { enabled: true, color: "#ff0000".to_string(), ..Default::default() };
+        renderer.set_label_pill(Some(pill.clone()));
+        let glyph = Pixmap::new(64, 88).unwrap();
+
+        // Red pixels along the middle of the label band
+        let pill_span = |pixmap: &Pixmap, y: u32| {
+            let red: Vec<u32> = (0..pixmap.width())
+                .filter(|&x| {
+                    let pixel = pixmap.pixel(x, y).unwrap();
+                    pixel.red() > 200 && pixel.green() < 50
+                })
+                .collect();
+            red.first().zip(red.last()).map(|(&first, &last)| last - first + 1)
+        };
+        let short = renderer.render_label_layer(&glyph, "a").unwrap();
+        let long = renderer.render_label_layer(&glyph, "abcdef").unwrap();
+        let short_width = pill_span(&short, 75).unwrap();
+        let long_width = pill_span(&long, 75).unwrap();
+        assert!(short_width < long_width, "{} {}", short_width, long_width);
+        assert_eq!(short.pixel(0, 75).unwrap().alpha(), 0, "No background across the band");
+
+        // Per-line pills leave a gap beside a short last line; an enclosing one fills it
+        let name = "abcdefghij k";
+        let lines = wrap_label(name, 10);
+        assert_eq!(lines.len(), 2);
+        renderer.set_label_layout(TextAlign::Center, 10);
+        let last_line_y = 2 + EXPANDED_LINE_HEIGHT + EXPANDED_LINE_HEIGHT / 2;
+        let per_line = renderer.render_expanded_label(name).unwrap();
+        renderer.set_label_pill(Some(LabelPillConfig { lines: PillLines::Enclosing, ..pill }));
+        let enclosing = renderer.render_expanded_label(name).unwrap();
+        let per_line_width = pill_span(&per_line, last_line_y).unwrap();
+        let enclosing_width = pill_span(&enclosing, last_line_y).unwrap();
+        assert!(per_line_width < enclosing_width, "{} {}", per_line_width, enclosing_width);
+    }
+
     // ========================================================================
     // Text Rendering Tests
     // ========================================================================
@@ -1702,4 +3619,521 @@ mod tests {
         let pixel = pixmap.pixel(32, 32).unwrap();
         assert_eq!(pixel.red(), 128, "Pixmap should be unchanged with invalid color");
     }
+
+    // ========================================================================
+    // State Highlight Tests
+    // ========================================================================
+
+    #[test]
+    fn test_focus_hover_and_selection_combine() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "x").unwrap();
+        let mut icon = DesktopIcon::new(&file, &crate::config::Config::default()).unwrap();
+
+        let mut renderer = IconRenderer::with_font(64, 12.0, None);
+        renderer.set_state_colors(&Colors {
+            selection: "#0000ff80".to_string(),
+            hover: "#ff000040".to_string(),
+            focus_ring: "#00ff00".to_string(),
+            ..Colors::default()
+        });
+
+        // Both points are in the label band, away from the glyph: one on the
+        // ring, one inside it
+        let (ring, inside) = ((1, 76), (10, 76));
+        for bits in 0..8 {
+            let (selected, hovered, focused) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+            icon.set_selected(selected);
+            icon.set_hovered(hovered);
+            icon.set_focused(focused);
+            let pixmap = renderer.render_glyph_layer(&icon).unwrap();
+            let state = format!("selected {}, hovered {}, focused {}", selected, hovered, focused);
+
+            let fill = pixmap.pixel(inside.0, inside.1).unwrap();
+            assert_eq!(fill.blue() > 0, selected, "Selection fill ({})", state);
+            assert_eq!(fill.red() > 0, hovered, "Hover highlight ({})", state);
+            assert_eq!(fill.green(), 0, "No ring inside ({})", state);
+
+            let edge = pixmap.pixel(ring.0, ring.1).unwrap();
+            if focused {
+                assert_eq!((edge.red(), edge.green(), edge.alpha()), (0, 255, 255), "Ring ({})", state);
+            } else {
+                assert_eq!(edge, fill, "No ring ({})", state);
+            }
+        }
+    }
+
+    // ========================================================================
+    // Selection Badge Tests
+    // ========================================================================
+
+    #[test]
+    fn test_selection_badge_size_and_fill() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let pixmap = renderer.render_selection_badge(3).unwrap();
+
+        assert_eq!(pixmap.width(), SELECTION_BADGE_SIZE);
+        assert_eq!(pixmap.height(), SELECTION_BADGE_SIZE);
+
+        let center = SELECTION_BADGE_SIZE / 2;
+        let pixel = pixmap.pixel(center, center).unwrap();
+        assert_eq!(pixel.alpha(), 255, "Badge center should be opaque");
+
+        let corner = pixmap.pixel(0, 0).unwrap();
+        assert_eq!(corner.alpha(), 0, "Badge corners should be transparent");
+    }
+
+    #[test]
+    fn test_context_menu_rows() {
+        use crate::icons::ContextAction;
+
+        let action = |action: ContextAction| MenuEntry::Action { label: action.builtin_label().to_string(), action };
+        let entries = vec![action(ContextAction::Open), MenuEntry::Separator, action(ContextAction::Delete)];
+        let height = context_menu_height(&entries);
+        assert_eq!(height, 2 * MENU_PADDING + 2 * MENU_ITEM_HEIGHT + MENU_SEPARATOR_HEIGHT);
+
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let pixmap = renderer.render_context_menu(&entries, Some(2)).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (CONTEXT_MENU_WIDTH, height));
+
+        let row_center = |top: u32, row_height: u32| (top + row_height / 2) as f64;
+        assert_eq!(context_menu_hit(&entries, 20.0, row_center(MENU_PADDING, MENU_ITEM_HEIGHT)), Some(0));
+        let separator_top = MENU_PADDING + MENU_ITEM_HEIGHT;
+        assert_eq!(context_menu_hit(&entries, 20.0, row_center(separator_top, MENU_SEPARATOR_HEIGHT)), None);
+        let last_top = separator_top + MENU_SEPARATOR_HEIGHT;
+        assert_eq!(context_menu_hit(&entries, 20.0, row_center(last_top, MENU_ITEM_HEIGHT)), Some(2));
+        assert_eq!(context_menu_hit(&entries, 20.0, 1.0), None, "Padding is not an entry");
+        assert_eq!(context_menu_hit(&entries, -1.0, row_center(MENU_PADDING, MENU_ITEM_HEIGHT)), None);
+    }
+
+    #[test]
+    fn test_confirm_popup_buttons() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let pixmap = renderer.render_confirm_popup("Delete 3 items?").unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (CONFIRM_POPUP_WIDTH, CONFIRM_POPUP_HEIGHT));
+
+        let (yes, no) = (confirm_button_rect(true), confirm_button_rect(false));
+        assert!(yes.right() <= no.left());
+        let hit_center = |rect: Rect| {
+            confirm_popup_hit((rect.x() + rect.width() / 2.0) as f64, (rect.y() + rect.height() / 2.0) as f64)
+        };
+        assert_eq!(hit_center(yes), Some(true));
+        assert_eq!(hit_center(no), Some(false));
+        assert_eq!(confirm_popup_hit(5.0, 5.0), None, "The message area is not a button");
+    }
+
+    #[test]
+    fn test_selection_badge_large_count() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        assert!(renderer.render_selection_badge(1000).is_ok());
+    }
+
+    // ========================================================================
+    // Damage Tracking Tests
+    // ========================================================================
+
+    fn frame(width: u32, height: u32) -> Vec<u8> {
+        vec![0u8; (width * height * 4) as usize]
+    }
+
+    fn set_pixel(frame: &mut [u8], width: u32, x: u32, y: u32) {
+        let offset = ((y * width + x) * 4) as usize;
+        frame[offset..offset + 4].copy_from_slice(&[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_compute_damage_unchanged_frame() {
+        let prev = frame(64, 88);
+        assert!(compute_damage(&prev, &prev.clone(), 64, 88, 64).is_empty());
+    }
+
+    #[test]
+    fn test_compute_damage_label_only() {
+        let prev = frame(64, 88);
+        let mut next = prev.clone();
+        set_pixel(&mut next, 64, 10, 70);
+        set_pixel(&mut next, 64, 20, 80);
+
+        let damage = compute_damage(&prev, &next, 64, 88, 64);
+        assert_eq!(
+            damage,
+            vec![DamageRect { x: 10, y: 70, width: 11, height: 11 }]
+        );
+    }
+
+    #[test]
+    fn test_compute_damage_glyph_and_label_bands() {
+        let prev = frame(64, 88);
+        let mut next = prev.clone();
+        set_pixel(&mut next, 64, 5, 5);
+        set_pixel(&mut next, 64, 30, 75);
+
+        let damage = compute_damage(&prev, &next, 64, 88, 64);
+        assert_eq!(damage.len(), 2, "Each band should produce its own rectangle");
+        assert_eq!(damage[0], DamageRect { x: 5, y: 5, width: 1, height: 1 });
+        assert_eq!(damage[1], DamageRect { x: 30, y: 75, width: 1, height: 1 });
+    }
+
+    #[test]
+    fn test_compute_damage_large_change_is_full() {
+        let prev = frame(64, 88);
+        let next = vec![255u8; prev.len()];
+
+        let damage = compute_damage(&prev, &next, 64, 88, 64);
+        assert_eq!(damage, vec![DamageRect::full(64, 88)]);
+    }
+
+    #[test]
+    fn test_compute_damage_size_mismatch_is_full() {
+        let prev = frame(32, 32);
+        let next = frame(64, 88);
+
+        let damage = compute_damage(&prev, &next, 64, 88, 64);
+        assert_eq!(damage, vec![DamageRect::full(64, 88)]);
+    }
+
+    // ========================================================================
+    // Folder Peek Tests
+    // ========================================================================
+
+    #[test]
+    fn test_folder_peek_empty_returns_none() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        assert!(renderer.render_folder_peek(&[], 32).is_none());
+    }
+
+    #[test]
+    fn test_folder_peek_draws_cards() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let items = vec![PathBuf::from("/nonexistent/a.txt"), PathBuf::from("/nonexistent/b.txt")];
+
+        let pixmap = renderer.render_folder_peek(&items, 32).unwrap();
+        assert_eq!(pixmap.width(), 32);
+
+        let center = pixmap.pixel(16, 16).unwrap();
+        assert_eq!(center.alpha(), 255, "Stack should cover the middle of the peek");
+    }
+
+    #[test]
+    fn test_is_image_path() {
+        assert!(is_image_path(Path::new("photo.JPG")));
+        assert!(is_image_path(Path::new("a.png")));
+        assert!(!is_image_path(Path::new("notes.txt")));
+        assert!(!is_image_path(Path::new("noext")));
+    }
+
+    // ========================================================================
+    // Symbolic Icon Tests
+    // ========================================================================
+
+    #[test]
+    fn test_recolor_matches_tint() {
+        let mut pixmap = Pixmap::new(4, 4).unwrap();
+        pixmap.fill(Color::from_rgba8(255, 0, 0, 255));
+
+        recolor(&mut pixmap, Color::from_rgba8(0, 128, 255, 255));
+
+        let pixel = pixmap.pixel(1, 1).unwrap();
+        assert_eq!(pixel.red(), 0);
+        assert_eq!(pixel.green(), 128);
+        assert_eq!(pixel.blue(), 255);
+        assert_eq!(pixel.alpha(), 255);
+    }
+
+    #[test]
+    fn test_recolor_keeps_coverage() {
+        let mut pixmap = Pixmap::new(2, 2).unwrap();
+        if let Some(rect) = Rect::from_xywh(0.0, 0.0, 1.0, 2.0) {
+            let mut paint = Paint::default();
+            paint.set_color(Color::from_rgba8(10, 20, 30, 128));
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+
+        recolor(&mut pixmap, Color::WHITE);
+
+        let covered = pixmap.pixel(0, 0).unwrap();
+        assert_eq!(covered.alpha(), 128, "Coverage should be preserved");
+        assert_eq!(covered.red(), 128, "White premultiplied by half coverage");
+
+        let empty = pixmap.pixel(1, 0).unwrap();
+        assert_eq!(empty.alpha(), 0, "Transparent pixels stay transparent");
+    }
+
+    #[test]
+    fn test_symbolic_render_uses_tint() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "x").unwrap();
+        let icon = DesktopIcon::new(&file, &crate::config::Config::default()).unwrap();
+
+        let mut renderer = IconRenderer::with_font(64, 12.0, None);
+        // Theme that doesn't exist, so the placeholder shape gets recolored
+        renderer.set_symbolic(Some(SymbolicStyle::new("no-such-theme", "#00ff00", false)));
+
+        let pixmap = renderer.render(&icon).unwrap();
+        let pixel = pixmap.pixel(32, 32).unwrap();
+        assert_eq!(pixel.alpha(), 255);
+        assert_eq!(pixel.red(), 0);
+        assert_eq!(pixel.green(), 255);
+        assert_eq!(pixel.blue(), 0);
+    }
+
+    #[test]
+    fn test_symbolic_thumbnails_recolored_only_when_strict() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("photo.png");
+        std::fs::write(&file, "x").unwrap();
+        let mut icon = DesktopIcon::new(&file, &crate::config::Config::default()).unwrap();
+        let mut thumbnail = Pixmap::new(48, 48).unwrap();
+        thumbnail.fill(Color::from_rgba8(255, 0, 0, 255));
+        icon.set_thumbnail(Some(thumbnail));
+
+        let mut renderer = IconRenderer::with_font(64, 12.0, None);
+        renderer.set_symbolic(Some(SymbolicStyle::new("no-such-theme", "#00ff00", false)));
+        let pixel = renderer.render_glyph_layer(&icon).unwrap().pixel(32, 32).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0), "Thumbnails keep their colors");
+
+        renderer.set_symbolic(Some(SymbolicStyle::new("no-such-theme", "#00ff00", true)));
+        let pixel = renderer.render_glyph_layer(&icon).unwrap().pixel(32, 32).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (0, 255, 0), "Strict mode tints them too");
+    }
+
+    #[test]
+    fn test_render_svg_recolored() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let svg = temp_dir.path().join("square-symbolic.svg");
+        std::fs::write(
+            &svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16"><rect width="16" height="16" fill="#bebebe"/></svg>"##,
+        )
+        .unwrap();
+
+        let mut pixmap = render_svg(&svg, 32, 32).unwrap();
+        recolor(&mut pixmap, Color::from_rgba8(255, 0, 0, 255));
+
+        let pixel = pixmap.pixel(16, 16).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_folder_custom_icon_replaces_glyph() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Projects");
+        std::fs::create_dir(&folder).unwrap();
+        std::fs::write(
+            folder.join("star.svg"),
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16"><rect width="16" height="16" fill="#ff0000"/></svg>"##,
+        )
+        .unwrap();
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+
+        let mut icon = DesktopIcon::new(&folder, &crate::config::Config::default()).unwrap();
+        let generic = renderer.render_glyph_layer(&icon).unwrap();
+        assert_ne!(generic.pixel(32, 32).unwrap().red(), 255);
+
+        std::fs::write(folder.join(".directory"), "[Desktop Entry]\nIcon=star.svg\n").unwrap();
+        assert!(icon.refresh_custom_icon());
+        let custom = renderer.render_glyph_layer(&icon).unwrap();
+        let pixel = custom.pixel(32, 32).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0));
+
+        // A missing image falls back to the generic glyph
+        std::fs::write(folder.join(".directory"), "[Desktop Entry]\nIcon=gone.png\n").unwrap();
+        assert!(icon.refresh_custom_icon());
+        assert_eq!(renderer.render_glyph_layer(&icon).unwrap(), generic);
+    }
+
+    #[test]
+    fn test_symbolic_style_invalid_tint_is_white() {
+        let style = SymbolicStyle::new("Adwaita", "nope", false);
+        assert_eq!(style.tint, Color::WHITE);
+    }
+
+    #[test]
+    fn test_fit_pixmap_same_size_is_unchanged() {
+        let mut pixmap = Pixmap::new(8, 8).unwrap();
+        pixmap.fill(Color::WHITE);
+
+        let fitted = fit_pixmap(pixmap.clone(), 8, 8).unwrap();
+        assert_eq!(fitted.data(), pixmap.data());
+    }
+
+    #[test]
+    fn test_fit_pixmap_pads_and_centers() {
+        let mut pixmap = Pixmap::new(4, 4).unwrap();
+        pixmap.fill(Color::WHITE);
+
+        let fitted = fit_pixmap(pixmap, 8, 6).unwrap();
+        assert_eq!((fitted.width(), fitted.height()), (8, 6));
+        assert_eq!(fitted.pixel(1, 0).unwrap().alpha(), 0, "Left padding is transparent");
+        assert_eq!(fitted.pixel(2, 0).unwrap().alpha(), 255);
+        assert_eq!(fitted.pixel(5, 3).unwrap().alpha(), 255);
+        assert_eq!(fitted.pixel(2, 4).unwrap().alpha(), 0, "Bottom padding is transparent");
+    }
+
+    #[test]
+    fn test_fit_pixmap_crops_to_smaller_buffer() {
+        let mut pixmap = Pixmap::new(10, 10).unwrap();
+        pixmap.fill(Color::WHITE);
+
+        let fitted = fit_pixmap(pixmap, 6, 4).unwrap();
+        assert_eq!((fitted.width(), fitted.height()), (6, 4));
+        assert!(fitted.pixels().iter().all(|p| p.alpha() == 255));
+    }
+
+    #[test]
+    fn test_rgba_to_bgra_swaps_red_and_blue() {
+        let src = [1, 2, 3, 4, 10, 20, 30, 40];
+        let mut dst = [0u8; 8];
+        rgba_to_bgra(&src, &mut dst);
+        assert_eq!(dst, [3, 2, 1, 4, 30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn test_scale_opacity_keeps_pixels_premultiplied() {
+        // Opaque orange and a half-transparent premultiplied white
+        let mut pixels = [255, 128, 0, 255, 128, 128, 128, 128];
+        scale_opacity(&mut pixels, 0.5);
+        assert_eq!(pixels, [128, 64, 0, 128, 64, 64, 64, 64]);
+        assert!(pixels.chunks_exact(4).all(|p| p[..3].iter().all(|&c| c <= p[3])));
+
+        let mut opaque = [10, 20, 30, 255];
+        scale_opacity(&mut opaque, 1.0);
+        assert_eq!(opaque, [10, 20, 30, 255]);
+        scale_opacity(&mut opaque, 0.0);
+        assert_eq!(opaque, [0; 4]);
+    }
+
+    #[test]
+    fn test_flash_glow_pulses_behind_glyph() {
+        assert_eq!(flash_level(0.0, 3), 0.0);
+        assert!((flash_level(1.0 / 6.0, 3) - 1.0).abs() < 1e-4, "Peaks mid-pulse");
+        assert!(flash_level(1.0, 3) < 1e-4, "Ends back at rest");
+
+        let icon = Pixmap::new(64, 80).unwrap();
+        let glow = glow_frame(&icon, 48, "#ff0000", 1.0).unwrap();
+        // Red behind the glyph, nothing around the label
+        assert_eq!(glow.pixel(32, 24).unwrap().red(), 255);
+        assert_eq!(glow.pixel(32, 70).unwrap().alpha(), 0);
+        let faint = glow_frame(&icon, 48, "#ff0000", 0.0).unwrap();
+        assert_eq!(faint.pixel(32, 24).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_ease_out_bounds() {
+        assert_eq!(ease_out(0.0), 0.0);
+        assert_eq!(ease_out(1.0), 1.0);
+        assert_eq!(ease_out(2.0), 1.0);
+        assert!(ease_out(0.5) > 0.5, "Ease-out moves fast first");
+    }
+
+    #[test]
+    fn test_animate_frame_fades_and_scales() {
+        let mut pixmap = Pixmap::new(20, 20).unwrap();
+        pixmap.fill(Color::WHITE);
+
+        let frame = animate_frame(&pixmap, 0.5, 1.0).unwrap();
+        let alpha = frame.pixel(10, 10).unwrap().alpha();
+        assert!((120..=135).contains(&alpha), "Half opacity, got {}", alpha);
+
+        let frame = animate_frame(&pixmap, 1.0, 0.5).unwrap();
+        assert_eq!((frame.width(), frame.height()), (20, 20));
+        assert_eq!(frame.pixel(10, 10).unwrap().alpha(), 255);
+        assert_eq!(frame.pixel(1, 1).unwrap().alpha(), 0, "Corners are empty when scaled down");
+    }
+
+    #[test]
+    fn test_zoom_frame_magnifies_about_center() {
+        let mut pixmap = Pixmap::new(20, 20).unwrap();
+        pixmap.fill(Color::WHITE);
+
+        let frame = zoom_frame(&pixmap, 40, 40, 2.0).unwrap();
+        assert_eq!((frame.width(), frame.height()), (40, 40));
+        assert_eq!(frame.pixel(1, 1).unwrap().alpha(), 255, "Full zoom fills the frame");
+
+        let frame = zoom_frame(&pixmap, 40, 40, 1.0).unwrap();
+        assert_eq!(frame.pixel(20, 20).unwrap().alpha(), 255);
+        assert_eq!(frame.pixel(1, 1).unwrap().alpha(), 0, "Unzoomed icon sits in the middle");
+    }
+
+    #[test]
+    fn test_overlay_origin_corners() {
+        // 64px glyph centered in an 80px wide surface
+        assert_eq!(overlay_origin(OverlayCorner::TopLeft, 80, 88, 64, 24), (8, 0));
+        assert_eq!(overlay_origin(OverlayCorner::TopRight, 80, 88, 64, 24), (48, 0));
+        assert_eq!(overlay_origin(OverlayCorner::BottomRight, 80, 88, 64, 24), (48, 40));
+    }
+
+    #[test]
+    fn test_overlay_stays_within_surface() {
+        let size = overlay_size(64, 16, 10);
+        assert_eq!(size, 10, "Badge shrinks to fit a tiny surface");
+        for corner in [
+            OverlayCorner::TopLeft,
+            OverlayCorner::TopRight,
+            OverlayCorner::BottomLeft,
+            OverlayCorner::BottomRight,
+        ] {
+            let (x, y) = overlay_origin(corner, 16, 10, 64, size);
+            assert!(x >= 0 && x + size as i32 <= 16, "{:?} x out of bounds", corner);
+            assert!(y >= 0 && y + size as i32 <= 10, "{:?} y out of bounds", corner);
+        }
+    }
+
+    #[test]
+    fn test_composite_count_overlay_in_top_right() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let mut pixmap = Pixmap::new(64, 88).unwrap();
+        let overlay = Overlay { count: Some(3), ..Overlay::default() };
+
+        renderer.composite_overlay(&mut pixmap, &overlay, 64);
+
+        let badge = overlay_size(64, 64, 88);
+        let center = 64 - badge / 2;
+        assert_eq!(pixmap.pixel(center, badge / 2).unwrap().alpha(), 255);
+        assert_eq!(pixmap.pixel(2, 2).unwrap().alpha(), 0, "Other corners untouched");
+    }
+
+    #[test]
+    fn test_widget_status_badges() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let badge = overlay_size(64, 64, 88);
+        let (x, y) = overlay_origin(OverlayCorner::TopLeft, 64, 88, 64, badge);
+        // Inside the badge's circle but clear of its emblem
+        let (px, py) = (x as u32 + badge / 8, y as u32 + badge / 2);
+
+        let mut pixmap = Pixmap::new(64, 88).unwrap();
+        renderer.composite_widget_status(&mut pixmap, WidgetStatus::Ok, 64);
+        assert!(pixmap.pixels().iter().all(|pixel| pixel.alpha() == 0), "Running widgets get no badge");
+
+        renderer.composite_widget_status(&mut pixmap, WidgetStatus::NoWidget, 64);
+        let missing = pixmap.pixel(px, py).unwrap();
+        assert_eq!((missing.alpha(), missing.blue()), (255, 0xd8));
+
+        let mut pixmap = Pixmap::new(64, 88).unwrap();
+        renderer.composite_widget_status(&mut pixmap, WidgetStatus::LuaCrashed, 64);
+        let crashed = pixmap.pixel(px, py).unwrap();
+        assert_eq!((crashed.alpha(), crashed.red()), (255, 0xe6));
+    }
+
+    #[test]
+    fn test_composite_emblem_in_bottom_left() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        let mut pixmap = Pixmap::new(64, 88).unwrap();
+
+        renderer.composite_emblem(&mut pixmap, Emblem::Modified, 64);
+
+        let badge = overlay_size(64, 64, 88);
+        let (x, y) = overlay_origin(OverlayCorner::BottomLeft, 64, 88, 64, badge);
+        let center = (x as u32 + badge / 2, y as u32 + badge / 2);
+        assert_eq!(pixmap.pixel(center.0, center.1).unwrap().alpha(), 255);
+        assert_eq!(pixmap.pixel(62, 2).unwrap().alpha(), 0, "Widget badge corner untouched");
+    }
+
+    #[test]
+    fn test_empty_overlay_draws_nothing() {
+        let renderer = IconRenderer::with_font(64, 12.0, None);
+        assert!(renderer.render_overlay_badge(&Overlay::default(), 24).is_none());
+    }
 }
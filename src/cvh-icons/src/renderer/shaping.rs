@@ -0,0 +1,174 @@
+//! Bidirectional text layout for labels
+//!
+//! Text is split into directional runs by the Unicode bidi algorithm, so
+//! Arabic and Hebrew names read right to left and mixed names put each
+//! segment in its place. Each run is shaped with rustybuzz, which picks
+//! joined Arabic letter forms and mirrors brackets, and the shaped glyphs are
+//! rasterized with fontdue.
+
+use std::ops::Range;
+
+use fontdue::{Font, FontSettings, Metrics};
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+
+/// A font loaded for labels, kept with its data for shaping
+pub struct LabelFont {
+    font: Font,
+    data: Vec<u8>,
+}
+
+/// A rasterized glyph ready to be drawn, in display order
+pub struct PositionedGlyph {
+    /// Index of the glyph in the font
+    pub glyph_id: u16,
+    pub metrics: Metrics,
+    pub bitmap: Vec<u8>,
+    /// Distance to the next glyph's origin, in pixels
+    pub advance: f32,
+    /// Offset from the pen position, in pixels (y up)
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+impl LabelFont {
+    /// Parse a TrueType or OpenType font
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, &'static str> {
+        let font = Font::from_bytes(data.as_slice(), FontSettings::default())?;
+        Ok(Self { font, data })
+    }
+
+    /// The font used for rasterizing
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Glyphs of `text` at `size` pixels, left to right as they are drawn
+    ///
+    /// Falls back to one glyph per character, with right-to-left runs
+    /// reversed, if rustybuzz cannot read the font.
+    pub fn layout(&self, text: &str, size: f32) -> Vec<PositionedGlyph> {
+        let face = rustybuzz::Face::from_slice(&self.data, 0);
+        let mut glyphs = Vec::new();
+        for (range, rtl) in visual_runs(text) {
+            let run = &text[range];
+            match &face {
+                Some(face) => self.shape_run(face, run, rtl, size, &mut glyphs),
+                None => self.layout_run_unshaped(run, rtl, size, &mut glyphs),
+            }
+        }
+        glyphs
+    }
+
+    fn shape_run(
+        &self,
+        face: &rustybuzz::Face,
+        run: &str,
+        rtl: bool,
+        size: f32,
+        glyphs: &mut Vec<PositionedGlyph>,
+    ) {
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(run);
+        buffer.guess_segment_properties();
+        buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+
+        // Shaped right-to-left runs already come out in display order
+        let output = rustybuzz::shape(face, &[], buffer);
+        let scale = size / face.units_per_em() as f32;
+        for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            let glyph_id = info.glyph_id as u16;
+            let (metrics, bitmap) = self.font.rasterize_indexed(glyph_id, size);
+            glyphs.push(PositionedGlyph {
+                glyph_id,
+                metrics,
+                bitmap,
+                advance: position.x_advance as f32 * scale,
+                x_offset: position.x_offset as f32 * scale,
+                y_offset: position.y_offset as f32 * scale,
+            });
+        }
+    }
+
+    fn layout_run_unshaped(
+        &self,
+        run: &str,
+        rtl: bool,
+        size: f32,
+        glyphs: &mut Vec<PositionedGlyph>,
+    ) {
+        let mut chars: Vec<char> = run.chars().collect();
+        if rtl {
+            chars.reverse();
+        }
+        for ch in chars {
+            let glyph_id = self.font.lookup_glyph_index(ch);
+            let (metrics, bitmap) = self.font.rasterize_indexed(glyph_id, size);
+            glyphs.push(PositionedGlyph {
+                glyph_id,
+                advance: metrics.advance_width,
+                metrics,
+                bitmap,
+                x_offset: 0.0,
+                y_offset: 0.0,
+            });
+        }
+    }
+}
+
+/// Byte ranges of `text`'s directional runs in display order, left to right,
+/// each with whether it reads right to left
+///
+/// The base direction comes from the first strong character, so a name
+/// starting in Hebrew is laid out right to left as a whole.
+pub fn visual_runs(text: &str) -> Vec<(Range<usize>, bool)> {
+    let info = BidiInfo::new(text, None);
+    let mut runs = Vec::new();
+    for paragraph in &info.paragraphs {
+        let (levels, line_runs) = info.visual_runs(paragraph, paragraph.range.clone());
+        runs.extend(line_runs.into_iter().map(|run| {
+            let rtl = levels[run.start].is_rtl();
+            (run, rtl)
+        }));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_texts(text: &str) -> Vec<(&str, bool)> {
+        visual_runs(text).into_iter().map(|(range, rtl)| (&text[range], rtl)).collect()
+    }
+
+    #[test]
+    fn test_visual_runs_mixed_directions() {
+        assert_eq!(run_texts("report.txt"), vec![("report.txt", false)]);
+        assert_eq!(
+            run_texts("notes שלום final"),
+            vec![("notes ", false), ("שלום", true), (" final", false)]
+        );
+        // A name starting in Hebrew reads right to left, so the Latin part is
+        // drawn first, on the left
+        assert_eq!(run_texts("שלום abc"), vec![("abc", false), ("שלום ", true)]);
+    }
+
+    #[test]
+    fn test_layout_draws_rtl_text_right_to_left() {
+        let Some(font) = crate::renderer::load_default_font() else {
+            return; // No system font to shape with
+        };
+        let text = "שלום";
+        let expected: Vec<u16> =
+            text.chars().rev().map(|ch| font.font().lookup_glyph_index(ch)).collect();
+        if expected.contains(&0) {
+            return; // The font has no Hebrew glyphs
+        }
+
+        let glyphs = font.layout(text, 12.0);
+        let ids: Vec<u16> = glyphs.iter().map(|glyph| glyph.glyph_id).collect();
+        assert_eq!(ids, expected, "The last letter is drawn leftmost");
+        assert!(glyphs.iter().all(|glyph| glyph.advance > 0.0));
+    }
+}
@@ -0,0 +1,163 @@
+//! Static per-type icon images from a theme directory
+//!
+//! With `icon_theme_dir` set, a file named for an icon type (`folder.png`,
+//! `archive.svg`, ...) is drawn for every icon of that type no widget draws.
+//! The images are read once, when the renderer is made. SVGs are kept parsed
+//! and rasterized at the size asked for, so they stay sharp at any buffer
+//! scale; PNGs are scaled from their decoded pixels. Types without a file
+//! keep the built-in glyph.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+use resvg::usvg;
+use tiny_skia::Pixmap;
+use tracing::{debug, warn};
+
+use super::{pixmap_from_rgba, render_tree};
+use crate::icons::IconType;
+
+/// File name, without extension, of each type's image
+const TYPE_FILES: &[(IconType, &str)] = &[
+    (IconType::File, "file"),
+    (IconType::Folder, "folder"),
+    (IconType::Symlink, "symlink"),
+    (IconType::Executable, "executable"),
+    (IconType::Image, "image"),
+    (IconType::Document, "document"),
+    (IconType::Archive, "archive"),
+    (IconType::Video, "video"),
+    (IconType::Audio, "audio"),
+    (IconType::Trash, "trash"),
+    (IconType::Volume, "volume"),
+    (IconType::Fifo, "fifo"),
+    (IconType::Socket, "socket"),
+    (IconType::BlockDevice, "block-device"),
+    (IconType::CharDevice, "char-device"),
+    (IconType::Unknown, "unknown"),
+];
+
+/// A theme image as read from disk
+enum ThemeImage {
+    Svg(Box<usvg::Tree>),
+    Raster(RgbaImage),
+}
+
+/// The images of a theme directory, by icon type
+#[derive(Default)]
+pub struct TypeTheme {
+    images: HashMap<IconType, ThemeImage>,
+}
+
+impl TypeTheme {
+    /// Read the image of every type that has one in `dir`
+    ///
+    /// An SVG wins over a PNG of the same name. Files that can't be read
+    /// are skipped with a warning, leaving their type the built-in glyph.
+    pub fn load(dir: &Path) -> Self {
+        let mut images = HashMap::new();
+        for &(icon_type, name) in TYPE_FILES {
+            let svg = dir.join(format!("{}.svg", name));
+            let png = dir.join(format!("{}.png", name));
+            let image = if svg.is_file() {
+                load_svg(&svg)
+            } else if png.is_file() {
+                load_png(&png)
+            } else {
+                continue;
+            };
+            if let Some(image) = image {
+                images.insert(icon_type, image);
+            }
+        }
+        debug!("Loaded {} icon type images from {}", images.len(), dir.display());
+        Self { images }
+    }
+
+    /// Whether the theme has an image for `icon_type`
+    pub fn has(&self, icon_type: IconType) -> bool {
+        self.images.contains_key(&icon_type)
+    }
+
+    /// The image for `icon_type` fitted into a `size` pixel square, keeping its aspect ratio
+    pub fn image(&self, icon_type: IconType, size: u32) -> Option<Pixmap> {
+        let size = size.max(1);
+        match self.images.get(&icon_type)? {
+            ThemeImage::Svg(tree) => {
+                let (width, height) = fit(tree.size().width(), tree.size().height(), size);
+                render_tree(tree, width, height)
+            }
+            ThemeImage::Raster(image) => {
+                let (width, height) = fit(image.width() as f32, image.height() as f32, size);
+                pixmap_from_rgba(&image::imageops::resize(image, width, height, FilterType::Triangle))
+            }
+        }
+    }
+}
+
+/// Size of a `width` x `height` image scaled to fit a `size` pixel square
+fn fit(width: f32, height: f32, size: u32) -> (u32, u32) {
+    let scale = size as f32 / width.max(height).max(1.0);
+    let side = |length: f32| ((length * scale).round() as u32).clamp(1, size);
+    (side(width), side(height))
+}
+
+fn load_svg(path: &Path) -> Option<ThemeImage> {
+    let parsed = std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(tree) => Some(ThemeImage::Svg(Box::new(tree))),
+        Err(e) => {
+            warn!("Failed to load theme image '{}': {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn load_png(path: &Path) -> Option<ThemeImage> {
+    match image::open(path) {
+        Ok(image) => Some(ThemeImage::Raster(image.to_rgba8())),
+        Err(e) => {
+            warn!("Failed to load theme image '{}': {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_type_images_load_by_name_and_scale() {
+        let dir = TempDir::new().unwrap();
+        RgbaImage::from_pixel(40, 20, image::Rgba([255, 0, 0, 255]))
+            .save(dir.path().join("folder.png"))
+            .unwrap();
+        std::fs::write(
+            dir.path().join("archive.svg"),
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16">
+                <rect width="16" height="16" fill="#00ff00"/></svg>"##,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("video.svg"), "not an svg").unwrap();
+
+        let theme = TypeTheme::load(dir.path());
+        let folder = theme.image(IconType::Folder, 64).unwrap();
+        assert_eq!((folder.width(), folder.height()), (64, 32));
+
+        // SVGs are rasterized at the size asked for, not their own
+        let archive = theme.image(IconType::Archive, 128).unwrap();
+        assert_eq!((archive.width(), archive.height()), (128, 128));
+        assert_eq!(archive.pixel(64, 64).unwrap().green(), 255);
+
+        // Missing and unreadable files leave the type without an image
+        assert!(!theme.has(IconType::Document));
+        assert!(!theme.has(IconType::Video));
+        assert!(theme.image(IconType::Document, 64).is_none());
+    }
+}
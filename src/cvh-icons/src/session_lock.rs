@@ -0,0 +1,83 @@
+//! Whether the user's session is locked
+//!
+//! Icons are hidden under the lock screen, so with `suspend_when_locked`
+//! the daemon stops rendering and updating them while the session is
+//! locked. Layer-shell clients are not told about the lock, so the state
+//! comes from logind's `LockedHint`, which lock screens and desktop
+//! environments set when they lock. Without logind nothing is suspended.
+
+use anyhow::{Context, Result};
+use tracing::debug;
+use zbus::blocking::Connection;
+use zbus::zvariant::OwnedValue;
+
+/// Somewhere the session's lock state can be read
+pub trait LockState {
+    /// Name for log messages
+    fn name(&self) -> &'static str;
+
+    /// Whether the session is locked, or None if that can't be read right now
+    fn is_locked(&self) -> Option<bool>;
+}
+
+/// Reads `LockedHint` of the caller's logind session over the system bus
+pub struct LogindLock {
+    connection: Connection,
+}
+
+impl LogindLock {
+    const DESTINATION: &'static str = "org.freedesktop.login1";
+    /// logind resolves this to the session of the calling process
+    const SESSION_PATH: &'static str = "/org/freedesktop/login1/session/auto";
+    const SESSION_INTERFACE: &'static str = "org.freedesktop.login1.Session";
+
+    /// Connect to the system bus and check the hint can be read
+    pub fn connect() -> Result<Self> {
+        let connection = Connection::system().context("Failed to connect to the system bus")?;
+        let lock = Self { connection };
+        lock.locked_hint()?;
+        Ok(lock)
+    }
+
+    fn locked_hint(&self) -> Result<bool> {
+        let reply = self
+            .connection
+            .call_method(
+                Some(Self::DESTINATION),
+                Self::SESSION_PATH,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(Self::SESSION_INTERFACE, "LockedHint"),
+            )
+            .context("Failed to read the session's LockedHint")?;
+        let value: OwnedValue = reply.body().deserialize()?;
+        bool::try_from(value).context("LockedHint is not a boolean")
+    }
+}
+
+impl LockState for LogindLock {
+    fn name(&self) -> &'static str {
+        "logind"
+    }
+
+    fn is_locked(&self) -> Option<bool> {
+        match self.locked_hint() {
+            Ok(locked) => Some(locked),
+            Err(e) => {
+                debug!("{:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Source of the lock state on this system, if there is one
+pub fn detect() -> Option<Box<dyn LockState>> {
+    match LogindLock::connect() {
+        Ok(lock) => Some(Box::new(lock)),
+        Err(e) => {
+            debug!("Session lock state unavailable: {:#}", e);
+            None
+        }
+    }
+}
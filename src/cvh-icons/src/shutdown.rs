@@ -0,0 +1,12 @@
+//! Stopping a running daemon cleanly
+//!
+//! `SIGINT` and `SIGTERM` would otherwise end the process on the spot,
+//! leaving widget processes running and surfaces to the compositor. They
+//! are read in the event loop instead (see `signals`), so the daemon
+//! finishes its pass and shuts down through `IconDaemon::shutdown` like on
+//! any other exit.
+
+use nix::sys::signal::Signal;
+
+/// Signals that stop the daemon
+pub const STOP_SIGNALS: [Signal; 2] = [Signal::SIGINT, Signal::SIGTERM];
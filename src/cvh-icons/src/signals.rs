@@ -0,0 +1,45 @@
+//! Signals the daemon handles in its event loop
+//!
+//! `SIGUSR1` cycles the log level, `SIGHUP` reloads the configuration and
+//! `SIGINT`/`SIGTERM` stop the daemon. Rather than being handled
+//! asynchronously, they are blocked and read from a single signalfd, so
+//! each is acted on between passes of the loop.
+
+use anyhow::{Context, Result};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+
+use crate::log_level::CYCLE_SIGNAL;
+use crate::reload::RELOAD_SIGNAL;
+use crate::shutdown::STOP_SIGNALS;
+
+/// Every signal the event loop can read
+pub const HANDLED_SIGNALS: [Signal; 4] = [CYCLE_SIGNAL, RELOAD_SIGNAL, STOP_SIGNALS[0], STOP_SIGNALS[1]];
+
+/// Block `signals` so they are only received through `signal_fd`
+///
+/// Call this before any threads are spawned: they inherit the mask, and a
+/// thread without it would take the signal's default action and exit.
+pub fn block(signals: &[Signal]) -> Result<()> {
+    mask(signals)
+        .thread_block()
+        .with_context(|| format!("Failed to block {}", names(signals)))
+}
+
+/// Non-blocking signalfd that becomes readable when one of `signals` arrives
+pub fn signal_fd(signals: &[Signal]) -> Result<SignalFd> {
+    SignalFd::with_flags(&mask(signals), SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+        .with_context(|| format!("Failed to create signalfd for {}", names(signals)))
+}
+
+fn mask(signals: &[Signal]) -> SigSet {
+    let mut mask = SigSet::empty();
+    for &signal in signals {
+        mask.add(signal);
+    }
+    mask
+}
+
+fn names(signals: &[Signal]) -> String {
+    signals.iter().map(|signal| signal.as_str()).collect::<Vec<_>>().join(", ")
+}
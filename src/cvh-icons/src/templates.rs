@@ -0,0 +1,188 @@
+//! "New" items for the desktop context menu
+//!
+//! New folders, new empty files, and copies of the files in the user's
+//! templates directory (`XDG_TEMPLATES_DIR`, usually `~/Templates`). Names
+//! that are already taken on the desktop get a number appended.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name given to new folders before the user renames them
+const NEW_FOLDER_NAME: &str = "New Folder";
+
+/// Name given to new empty files before the user renames them
+const NEW_FILE_NAME: &str = "New File";
+
+/// Something the "New" submenu can create on the desktop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NewItem {
+    Folder,
+    EmptyFile,
+    /// Copy of a template file
+    Template(PathBuf),
+}
+
+impl NewItem {
+    /// Menu label
+    pub fn label(&self) -> String {
+        match self {
+            NewItem::Folder => "New Folder".to_string(),
+            NewItem::EmptyFile => "New Empty File".to_string(),
+            NewItem::Template(path) => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Name the created entry starts out with (before collision handling)
+    fn default_name(&self) -> String {
+        match self {
+            NewItem::Folder => NEW_FOLDER_NAME.to_string(),
+            NewItem::EmptyFile => NEW_FILE_NAME.to_string(),
+            NewItem::Template(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| NEW_FILE_NAME.to_string()),
+        }
+    }
+}
+
+/// The user's templates directory, if configured
+pub fn templates_dir() -> Option<PathBuf> {
+    dirs::template_dir()
+}
+
+/// Items for the "New" submenu: folder, empty file, then templates by name
+///
+/// A missing or empty templates directory just yields the first two.
+pub fn menu_items(templates_dir: Option<&Path>) -> Vec<NewItem> {
+    let mut templates: Vec<PathBuf> = templates_dir
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            // Subdirectories would be submenus; only plain files are offered
+            !hidden && path.is_file()
+        })
+        .collect();
+    templates.sort();
+
+    let mut items = vec![NewItem::Folder, NewItem::EmptyFile];
+    items.extend(templates.into_iter().map(NewItem::Template));
+    items
+}
+
+/// First free path for `name` in `dir`: `name`, then `name 2`, `name 3`, ...
+///
+/// The number goes before the extension, so `Report.odt` becomes `Report 2.odt`.
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    // symlink_metadata so dangling symlinks count as taken
+    if candidate.symlink_metadata().is_err() {
+        return candidate;
+    }
+
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+        _ => (name, ""),
+    };
+    (2..)
+        .map(|n| dir.join(format!("{} {}{}", stem, n, ext)))
+        .find(|path| path.symlink_metadata().is_err())
+        .expect("unbounded range always yields a free name")
+}
+
+/// Create `item` in `dir` and return the path of the new entry
+pub fn create(dir: &Path, item: &NewItem) -> Result<PathBuf> {
+    let path = unique_path(dir, &item.default_name());
+
+    match item {
+        NewItem::Folder => {
+            fs::create_dir(&path)
+                .with_context(|| format!("Failed to create folder {}", path.display()))?;
+        }
+        NewItem::EmptyFile => {
+            fs::File::create_new(&path)
+                .with_context(|| format!("Failed to create file {}", path.display()))?;
+        }
+        NewItem::Template(template) => {
+            fs::copy(template, &path).with_context(|| {
+                format!("Failed to copy template {} to {}", template.display(), path.display())
+            })?;
+        }
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_menu_without_templates() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("Templates");
+
+        assert_eq!(menu_items(None), vec![NewItem::Folder, NewItem::EmptyFile]);
+        assert_eq!(menu_items(Some(&missing)), vec![NewItem::Folder, NewItem::EmptyFile]);
+        assert_eq!(menu_items(Some(temp_dir.path())).len(), 2);
+    }
+
+    #[test]
+    fn test_menu_lists_template_files_sorted() {
+        let temp_dir = TempDir::new().unwrap();
+        let templates = temp_dir.path();
+        fs::write(templates.join("Spreadsheet.ods"), "").unwrap();
+        fs::write(templates.join("Document.odt"), "").unwrap();
+        fs::write(templates.join(".hidden"), "").unwrap();
+        fs::create_dir(templates.join("Nested")).unwrap();
+
+        let items = menu_items(Some(templates));
+        let labels: Vec<String> = items.iter().map(NewItem::label).collect();
+        assert_eq!(labels, ["New Folder", "New Empty File", "Document", "Spreadsheet"]);
+    }
+
+    #[test]
+    fn test_unique_path_numbers_before_extension() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        assert_eq!(unique_path(dir, "Report.odt"), dir.join("Report.odt"));
+
+        fs::write(dir.join("Report.odt"), "").unwrap();
+        fs::write(dir.join("Report 2.odt"), "").unwrap();
+        assert_eq!(unique_path(dir, "Report.odt"), dir.join("Report 3.odt"));
+
+        fs::create_dir(dir.join("New Folder")).unwrap();
+        assert_eq!(unique_path(dir, "New Folder"), dir.join("New Folder 2"));
+    }
+
+    #[test]
+    fn test_create_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let desktop = temp_dir.path().join("Desktop");
+        fs::create_dir(&desktop).unwrap();
+        let template = temp_dir.path().join("Letter.txt");
+        fs::write(&template, "Dear ...").unwrap();
+
+        let folder = create(&desktop, &NewItem::Folder).unwrap();
+        assert!(folder.is_dir());
+        let second = create(&desktop, &NewItem::Folder).unwrap();
+        assert_eq!(second, desktop.join("New Folder 2"));
+
+        let file = create(&desktop, &NewItem::EmptyFile).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"");
+
+        let letter = create(&desktop, &NewItem::Template(template)).unwrap();
+        assert_eq!(letter, desktop.join("Letter.txt"));
+        assert_eq!(fs::read_to_string(&letter).unwrap(), "Dear ...");
+    }
+}
@@ -0,0 +1,200 @@
+//! Thumbnails of image files, drawn instead of the generic image glyph
+//!
+//! Decoding a large photo can take longer than a frame, so thumbnails are
+//! made on a worker thread and picked up by the daemon once ready. Finished
+//! thumbnails are kept in a `RenderCache` keyed by the file's path, mtime
+//! and the thumbnail size, so unchanged files are not decoded again on the
+//! next start. Files that cannot be decoded get no thumbnail and keep the
+//! generic glyph.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::UNIX_EPOCH;
+
+use image::imageops::FilterType;
+use tiny_skia::Pixmap;
+use tracing::{debug, warn};
+
+use crate::render_cache::RenderCache;
+use crate::renderer::pixmap_from_rgba;
+
+/// Size limit of the on-disk thumbnail cache
+const CACHE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A thumbnail the worker is asked to make
+struct Request {
+    path: PathBuf,
+    key: String,
+    size: u32,
+}
+
+/// A thumbnail the worker has finished, or None if the file can't be decoded
+pub struct Finished {
+    pub path: PathBuf,
+    pub thumbnail: Option<Pixmap>,
+}
+
+/// Makes thumbnails on a worker thread
+pub struct Thumbnailer {
+    requests: Sender<Request>,
+    results: Receiver<(String, Finished)>,
+    /// Key of the thumbnail being made for each path
+    pending: HashMap<PathBuf, String>,
+    /// Key each path's latest thumbnail was made for, so it isn't made twice
+    done: HashMap<PathBuf, String>,
+}
+
+impl Thumbnailer {
+    /// Start the worker, caching thumbnails in `cache_dir` if set
+    pub fn new(cache_dir: Option<PathBuf>) -> Self {
+        let (requests, request_rx) = mpsc::channel::<Request>();
+        let (result_tx, results) = mpsc::channel();
+        let cache = cache_dir.map(|dir| RenderCache::new(dir, CACHE_MAX_BYTES));
+
+        // Ends when the thumbnailer is dropped and the request channel closes
+        std::thread::spawn(move || {
+            for request in request_rx {
+                let thumbnail = thumbnail_cached(cache.as_ref(), &request);
+                let finished = Finished { path: request.path, thumbnail };
+                if result_tx.send((request.key, finished)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { requests, results, pending: HashMap::new(), done: HashMap::new() }
+    }
+
+    /// Ask for a `size` pixel thumbnail of the image at `path`
+    ///
+    /// Does nothing if the same one is already being made, or was made for
+    /// the file as it is now.
+    pub fn request(&mut self, path: &Path, size: u32) {
+        let Some(key) = thumbnail_key(path, size) else {
+            return;
+        };
+        if self.pending.get(path) == Some(&key) || self.done.get(path) == Some(&key) {
+            return;
+        }
+        let request = Request { path: path.to_path_buf(), key: key.clone(), size };
+        if self.requests.send(request).is_ok() {
+            self.pending.insert(path.to_path_buf(), key);
+        }
+    }
+
+    /// Thumbnails finished since the last call
+    ///
+    /// Thumbnails superseded by a later request for the same path are dropped.
+    pub fn finished(&mut self) -> Vec<Finished> {
+        let mut finished = Vec::new();
+        while let Ok((key, result)) = self.results.try_recv() {
+            if self.pending.get(&result.path) != Some(&key) {
+                continue;
+            }
+            self.pending.remove(&result.path);
+            self.done.insert(result.path.clone(), key);
+            finished.push(result);
+        }
+        finished
+    }
+
+    /// Whether any thumbnail is still being made
+    pub fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Forget the thumbnail made for `path`, so asking again makes a new one
+    pub fn forget(&mut self, path: &Path) {
+        self.done.remove(path);
+    }
+}
+
+/// Cache key of a `size` pixel thumbnail of `path`; None if its mtime is unknown
+pub fn thumbnail_key(path: &Path, size: u32) -> Option<String> {
+    let mtime = path.metadata().ok()?.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("thumbnail|{}|{}|{}", path.display(), mtime.as_nanos(), size))
+}
+
+/// Decode the image at `path` and scale it to fit a `size` pixel square
+///
+/// The aspect ratio is kept. Returns None for files that aren't images the
+/// decoder supports, or are corrupt.
+pub fn render_thumbnail(path: &Path, size: u32) -> Option<Pixmap> {
+    let image = match image::open(path) {
+        Ok(image) => image,
+        Err(e) => {
+            debug!("No thumbnail for {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let scaled = image.resize(size, size, FilterType::Triangle);
+    pixmap_from_rgba(&scaled.to_rgba8())
+}
+
+/// A request's thumbnail from the cache, or freshly made and then cached
+fn thumbnail_cached(cache: Option<&RenderCache>, request: &Request) -> Option<Pixmap> {
+    if let Some(pixmap) = cache.and_then(|cache| cache.get(&request.key)) {
+        return Some(pixmap);
+    }
+    let thumbnail = render_thumbnail(&request.path, request.size)?;
+    if let Some(cache) = cache {
+        if let Err(e) = cache.put(&request.key, &thumbnail) {
+            warn!("Failed to cache the thumbnail of {}: {:#}", request.path.display(), e);
+        }
+    }
+    Some(thumbnail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn wait_for(thumbnailer: &mut Thumbnailer) -> Vec<Finished> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut finished = Vec::new();
+        while thumbnailer.is_busy() && Instant::now() < deadline {
+            finished.extend(thumbnailer.finished());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        finished
+    }
+
+    #[test]
+    fn test_thumbnails_keep_aspect_and_reject_corrupt_files() {
+        let dir = TempDir::new().unwrap();
+        let photo = dir.path().join("wide.png");
+        image::RgbaImage::from_pixel(40, 20, image::Rgba([255, 0, 0, 255])).save(&photo).unwrap();
+        let thumbnail = render_thumbnail(&photo, 10).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (10, 5));
+
+        let corrupt = dir.path().join("broken.png");
+        fs::write(&corrupt, "not a png").unwrap();
+        assert!(render_thumbnail(&corrupt, 10).is_none());
+    }
+
+    #[test]
+    fn test_thumbnails_are_made_once_and_cached_on_disk() {
+        let dir = TempDir::new().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let photo = dir.path().join("photo.png");
+        image::RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 255, 255])).save(&photo).unwrap();
+
+        let mut thumbnailer = Thumbnailer::new(Some(cache_dir.clone()));
+        thumbnailer.request(&photo, 4);
+        let finished = wait_for(&mut thumbnailer);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].thumbnail.as_ref().map(Pixmap::width), Some(4));
+
+        // The unchanged file isn't thumbnailed again
+        thumbnailer.request(&photo, 4);
+        assert!(!thumbnailer.is_busy());
+
+        // and kept on disk for the next start
+        let key = thumbnail_key(&photo, 4).unwrap();
+        assert!(RenderCache::new(cache_dir, CACHE_MAX_BYTES).get(&key).is_some());
+    }
+}
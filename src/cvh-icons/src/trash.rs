@@ -0,0 +1,277 @@
+//! XDG home trash
+//!
+//! Just enough of the FreeDesktop.org Trash specification for the built-in
+//! Trash icon: locating `$XDG_DATA_HOME/Trash`, telling whether it holds
+//! anything, moving files into it and back out, and emptying it. Trashed
+//! files live in `files/` and their metadata in `info/`.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Location of the current user's home trash
+pub fn home_trash_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("Trash"))
+}
+
+/// Directory holding the trashed files themselves
+pub fn files_dir(trash_dir: &Path) -> PathBuf {
+    trash_dir.join("files")
+}
+
+/// Whether the trash holds no files (a missing trash counts as empty)
+pub fn is_empty(trash_dir: &Path) -> bool {
+    fs::read_dir(files_dir(trash_dir))
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(true)
+}
+
+/// Permanently delete everything in the trash
+pub fn empty(trash_dir: &Path) -> Result<()> {
+    for sub in ["files", "info"] {
+        let dir = trash_dir.join(sub);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", dir.display()))
+            }
+        };
+
+        for entry in entries {
+            delete(&entry?.path())?;
+        }
+    }
+
+    // Cached sizes refer to entries that no longer exist
+    let _ = fs::remove_file(trash_dir.join("directorysizes"));
+
+    debug!("Emptied trash at {}", trash_dir.display());
+    Ok(())
+}
+
+/// Permanently delete a file, or a directory with everything in it
+pub fn delete(path: &Path) -> Result<()> {
+    // symlink_metadata so symlinks are removed, not followed
+    let result = if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.with_context(|| format!("Failed to delete {}", path.display()))
+}
+
+/// Move `path` into the trash at `trash_dir`, returning its new location
+///
+/// The file is renamed, so it must be on the same filesystem as the trash;
+/// the per-volume `.Trash-$uid` directories are not supported. Name clashes
+/// get a numeric suffix, reserved by creating the `.trashinfo` file first.
+pub fn trash(trash_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("Cannot trash {}", path.display()))?
+        .to_string_lossy()
+        .into_owned();
+    let original = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?.join(&name),
+        _ => std::env::current_dir()?.join(&name),
+    };
+
+    let info_dir = trash_dir.join("info");
+    for dir in [info_dir.clone(), files_dir(trash_dir)] {
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    for n in 1.. {
+        let trashed_name = if n == 1 { name.clone() } else { format!("{}.{}", name, n) };
+        let info_path = info_dir.join(format!("{}.trashinfo", trashed_name));
+        let mut info = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", info_path.display()))
+            }
+        };
+        write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(&original),
+            deletion_date(SystemTime::now())
+        )?;
+
+        let target = files_dir(trash_dir).join(&trashed_name);
+        if let Err(e) = fs::rename(path, &target) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e).with_context(|| format!("Failed to move {} to the trash", path.display()));
+        }
+        debug!("Trashed {} as {}", path.display(), target.display());
+        return Ok(target);
+    }
+    unreachable!("trash names are unbounded")
+}
+
+/// Move the file trashed as `trashed` back to `original`, dropping its `.trashinfo`
+///
+/// Fails rather than overwrite a file that took the original's place.
+pub fn restore(trash_dir: &Path, trashed: &Path, original: &Path) -> Result<()> {
+    if original.symlink_metadata().is_ok() {
+        bail!("Cannot restore {}: it exists again", original.display());
+    }
+    fs::rename(trashed, original)
+        .with_context(|| format!("Failed to restore {} from the trash", original.display()))?;
+
+    let name = trashed.file_name().unwrap_or_default().to_string_lossy();
+    let info_path = trash_dir.join("info").join(format!("{}.trashinfo", name));
+    if let Err(e) = fs::remove_file(&info_path) {
+        debug!("Failed to remove {}: {}", info_path.display(), e);
+    }
+    debug!("Restored {} from {}", original.display(), trashed.display());
+    Ok(())
+}
+
+/// Percent-encode a path for the `Path=` key of a `.trashinfo` file
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// `DeletionDate=` value for `time`, as `YYYY-MM-DDThh:mm:ss`
+///
+/// The spec asks for local time, but there is no time zone database here,
+/// so this is UTC.
+fn deletion_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_trash_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(is_empty(&temp_dir.path().join("Trash")));
+    }
+
+    #[test]
+    fn test_trash_with_files_is_not_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("Trash");
+        fs::create_dir_all(files_dir(&trash)).unwrap();
+        assert!(is_empty(&trash));
+
+        fs::write(files_dir(&trash).join("old.txt"), "x").unwrap();
+        assert!(!is_empty(&trash));
+    }
+
+    #[test]
+    fn test_empty_removes_files_and_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("Trash");
+        fs::create_dir_all(files_dir(&trash).join("folder/nested")).unwrap();
+        fs::write(files_dir(&trash).join("old.txt"), "x").unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        fs::write(trash.join("info/old.txt.trashinfo"), "[Trash Info]").unwrap();
+
+        empty(&trash).unwrap();
+
+        assert!(is_empty(&trash));
+        assert_eq!(fs::read_dir(trash.join("info")).unwrap().count(), 0);
+        assert!(files_dir(&trash).exists(), "The trash directories themselves are kept");
+    }
+
+    #[test]
+    fn test_empty_missing_trash_is_ok() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(empty(&temp_dir.path().join("Trash")).is_ok());
+    }
+
+    #[test]
+    fn test_trash_moves_file_and_writes_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let file = temp_dir.path().join("my notes.txt");
+        fs::write(&file, "x").unwrap();
+
+        let trashed = trash(&trash_dir, &file).unwrap();
+        assert!(!file.exists());
+        assert_eq!(trashed, files_dir(&trash_dir).join("my notes.txt"));
+
+        let info = fs::read_to_string(trash_dir.join("info/my notes.txt.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains("my%20notes.txt\n"));
+        assert!(info.contains("DeletionDate="));
+
+        // A second file of the same name gets a suffix
+        fs::write(&file, "y").unwrap();
+        assert_eq!(trash(&trash_dir, &file).unwrap(), files_dir(&trash_dir).join("my notes.txt.2"));
+    }
+
+    #[test]
+    fn test_restore_puts_file_back_and_drops_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash_dir = temp_dir.path().join("Trash");
+        let file = temp_dir.path().join("draft.txt");
+        fs::write(&file, "x").unwrap();
+
+        let trashed = trash(&trash_dir, &file).unwrap();
+        fs::write(&file, "new").unwrap();
+        assert!(restore(&trash_dir, &trashed, &file).is_err(), "Never overwrites");
+        assert!(trashed.exists());
+
+        fs::remove_file(&file).unwrap();
+        restore(&trash_dir, &trashed, &file).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "x");
+        assert!(is_empty(&trash_dir));
+        assert!(!trash_dir.join("info/draft.txt.trashinfo").exists());
+    }
+
+    #[test]
+    fn test_deletion_date_format() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(951_827_696);
+        assert_eq!(deletion_date(time), "2000-02-29T12:34:56");
+    }
+
+    #[test]
+    fn test_delete_removes_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("folder");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        delete(&dir).unwrap();
+        assert!(!dir.exists());
+    }
+}
@@ -2,15 +2,54 @@
 //!
 //! Uses layer-shell protocol for desktop-level surfaces.
 //! Icons are rendered as layer-shell surfaces on the background layer.
+//!
+//! layer-shell has no request for an output's work area. When anchoring to
+//! it, an unmapped probe surface is stretched to all four edges with an
+//! exclusive zone of 0, so the compositor sizes it to the output minus the
+//! panels' exclusive zones. Icon surfaces then switch to exclusive zone 0
+//! too, which makes the compositor offset their margins by the same panels.
+//!
+//! Where the compositor offers `zwlr-foreign-toplevel-management-v1`, windows
+//! are tracked so the daemon can tell when one covers the desktop, and so a
+//! launcher can raise its application's window. The
+//! `ext-foreign-toplevel-list-v1` protocol neither reports window states nor
+//! activates windows, so it cannot be used for either.
+//!
+//! Copied paths and text are offered on the clipboard through
+//! `wl_data_device`, selected with the serial of the last key or button press
+//! (compositors ignore selections not tied to recent input).
+//!
+//! The cursor is set through `wp_cursor_shape_v1` where the compositor offers
+//! it, and otherwise drawn from the cursor theme into a surface of our own.
+//! Either way it needs the serial of the pointer's last enter, so the shape
+//! the daemon last asked for is set again on every enter.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::collections::HashMap;
+use std::os::fd::{AsFd, BorrowedFd};
 use tracing::{debug, info};
 
+use crate::clipboard;
+use crate::config::{Corner, SurfaceOutputBinding};
+use crate::error::{DaemonError, IconsError, IconsResult};
+use crate::launcher;
+use crate::renderer::{rgba_to_bgra, scale_opacity, DamageRect};
+
+pub use crate::backend::{FrameGroup, InputEvent, KeyModifiers, SurfaceId};
+use crate::backend::{buffer_scale, CursorShape, InputQueue, SurfaceBackend, BTN_TOUCH};
+use crate::icons::mirror_to_corner;
+
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
-    delegate_seat, delegate_shm,
+    compositor::{CompositorHandler, CompositorState, Region},
+    data_device_manager::{
+        data_device::{DataDevice, DataDeviceHandler},
+        data_offer::{DataOfferHandler, DragOffer},
+        data_source::{CopyPasteSource, DataSourceHandler},
+        DataDeviceManagerState, WritePipe,
+    },
+    delegate_compositor, delegate_data_device, delegate_keyboard, delegate_layer,
+    delegate_output, delegate_pointer, delegate_registry, delegate_seat, delegate_shm,
+    delegate_touch,
     output::{OutputHandler, OutputState},
     reexports::{
         calloop::{EventLoop, LoopHandle},
@@ -18,19 +57,26 @@ use smithay_client_toolkit::{
         client::{
             globals::registry_queue_init,
             protocol::{
+                wl_data_device::WlDataDevice,
+                wl_data_device_manager::DndAction,
+                wl_data_source::WlDataSource,
+                wl_keyboard::WlKeyboard,
                 wl_output::WlOutput,
                 wl_pointer::WlPointer,
                 wl_seat::WlSeat,
                 wl_shm,
                 wl_surface::WlSurface,
+                wl_touch::WlTouch,
             },
-            Connection, QueueHandle,
+            event_created_child, Connection, Dispatch, Proxy, QueueHandle,
         },
     },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::{
-        pointer::{PointerEvent, PointerEventKind, PointerHandler},
+        keyboard::{KeyEvent, KeyboardHandler, Keysym, Modifiers, RawModifiers},
+        pointer::{CursorIcon, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec, ThemedPointer},
+        touch::TouchHandler,
         Capability, SeatHandler, SeatState,
     },
     shell::{
@@ -45,35 +91,20 @@ use smithay_client_toolkit::{
         Shm, ShmHandler,
     },
 };
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
 
-/// Unique identifier for icon surfaces
-pub type SurfaceId = u64;
+/// Buffers a surface draws into in turn, so one the compositor still
+/// reads from is never written
+const SURFACE_BUFFERS: usize = 2;
 
-/// Input event from Wayland
-#[derive(Debug, Clone)]
-pub enum InputEvent {
-    /// Pointer entered a surface
-    PointerEnter {
-        surface_id: SurfaceId,
-        x: f64,
-        y: f64,
-    },
-    /// Pointer left a surface
-    PointerLeave { surface_id: SurfaceId },
-    /// Pointer moved on a surface
-    PointerMotion {
-        surface_id: SurfaceId,
-        x: f64,
-        y: f64,
-    },
-    /// Pointer button pressed/released
-    PointerButton {
-        surface_id: SurfaceId,
-        button: u32,
-        pressed: bool,
-        x: f64,
-        y: f64,
-    },
+/// A shared-memory buffer and its size in pixels
+struct ShmBuffer {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
 }
 
 /// Icon surface data
@@ -82,11 +113,84 @@ struct IconSurfaceData {
     width: u32,
     height: u32,
     configured: bool,
-    buffer: Option<Buffer>,
-    #[allow(dead_code)]
+    /// Buffers of the surface's current size, the last attached last
+    buffers: Vec<ShmBuffer>,
     position_x: i32,
-    #[allow(dead_code)]
     position_y: i32,
+    /// Top-left corner of the output the surface is bound to, relative to the
+    /// primary output's; margins are the position minus this
+    output_origin: (i32, i32),
+    /// Output the surface is shown on: the one it was bound to, then the
+    /// last one the compositor said it entered
+    output: Option<WlOutput>,
+    /// A frame callback was requested and has not fired yet
+    frame_pending: bool,
+    /// Whether the surface is mapped (see `set_surface_mapped`)
+    mapped: bool,
+    /// Integer scale of the output the surface is on
+    scale: u32,
+}
+
+/// Logical size and integer scale of an output, as far as it has announced them
+type OutputGeometry = (Option<(i32, i32)>, i32);
+
+/// States of a foreign toplevel that matter for covering the desktop
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ToplevelState {
+    maximized: bool,
+    minimized: bool,
+    activated: bool,
+    fullscreen: bool,
+}
+
+impl ToplevelState {
+    /// Parse the `state` event's array of native-endian u32 values
+    fn from_wire(states: &[u8]) -> Self {
+        let mut state = Self::default();
+        for value in states.chunks_exact(4) {
+            match u32::from_ne_bytes([value[0], value[1], value[2], value[3]]) {
+                0 => state.maximized = true,
+                1 => state.minimized = true,
+                2 => state.activated = true,
+                3 => state.fullscreen = true,
+                _ => {}
+            }
+        }
+        state
+    }
+
+    /// Whether a window in this state surely covers the desktop on its output
+    ///
+    /// Only the focused window counts: windows on hidden workspaces are
+    /// reported too, often maximized, and cover nothing.
+    fn covers_desktop(self) -> bool {
+        self.activated && !self.minimized && (self.maximized || self.fullscreen)
+    }
+}
+
+/// The touch point followed as if it were the pointer
+///
+/// Touches made while one is down are ignored, so a second finger can't
+/// start another click or drag.
+#[derive(Debug, Clone, Copy)]
+struct TouchPoint {
+    id: i32,
+    surface_id: SurfaceId,
+    x: f64,
+    y: f64,
+}
+
+/// A window reported by the foreign toplevel manager
+#[derive(Debug, Default)]
+struct Toplevel {
+    /// Application the window belongs to (empty until announced)
+    app_id: String,
+    /// Outputs the window is on
+    outputs: Vec<WlOutput>,
+    /// State as of the last `done` event
+    state: ToplevelState,
+    /// State received since the last `done` event
+    pending_state: Option<ToplevelState>,
 }
 
 /// Wayland application state
@@ -115,15 +219,56 @@ pub struct WaylandState {
     next_surface_id: SurfaceId,
     /// Available outputs
     outputs: Vec<WlOutput>,
-    /// Current pointer
-    pointer: Option<WlPointer>,
+    /// Name of the output to place icons on, if set (e.g. "DP-1")
+    primary_output_name: Option<String>,
+    /// Output the work area probe was created on
+    probe_output: Option<WlOutput>,
+    /// Which output new surfaces are bound to
+    surface_output_binding: SurfaceOutputBinding,
+    /// Corner of the output surfaces are anchored to
+    anchor_corner: Corner,
+    /// Current pointer, with the cursor it shows over our surfaces
+    pointer: Option<ThemedPointer>,
+    /// Cursor the daemon last asked for
+    cursor_shape: CursorShape,
+    /// Current touchscreen
+    touch: Option<WlTouch>,
+    /// Touch point standing in for the pointer while it is down
+    touch_point: Option<TouchPoint>,
+    /// Current keyboard (only used for modifier state)
+    keyboard: Option<WlKeyboard>,
+    /// Currently held keyboard modifiers
+    modifiers: KeyModifiers,
+    /// Serial of the last key or button press, needed to set the clipboard
+    last_input_serial: u32,
+    /// Clipboard support, if the compositor offers it
+    data_device_manager: Option<DataDeviceManagerState>,
+    data_device: Option<DataDevice>,
+    /// Our current clipboard offer and the text it holds
+    clipboard: Option<(CopyPasteSource, String)>,
     /// Pointer position
     pointer_x: f64,
     pointer_y: f64,
     /// Surface under pointer
     pointer_surface: Option<SurfaceId>,
     /// Pending input events
-    input_events: Vec<InputEvent>,
+    input_events: InputQueue,
+    /// Opacity every attached buffer is shown at
+    surface_opacity: f32,
+    /// Surfaces that received their first configure since the last drain
+    newly_configured: Vec<SurfaceId>,
+    /// Configured surfaces whose size the compositor changed since the last drain
+    resized: Vec<SurfaceId>,
+    /// Logical size and scale of each output as last announced
+    output_geometry: HashMap<WlOutput, OutputGeometry>,
+    /// Whether an output icons are on changed its geometry since the last drain
+    outputs_changed: bool,
+    /// Surface the compositor sizes to the work area, while anchoring to it
+    work_area_probe: Option<LayerSurface>,
+    /// Size of the work area as last configured on the probe
+    work_area: Option<(u32, u32)>,
+    /// Windows reported by the foreign toplevel manager, if bound
+    toplevels: HashMap<ZwlrForeignToplevelHandleV1, Toplevel>,
     /// Whether to exit
     exit: bool,
 }
@@ -131,30 +276,163 @@ pub struct WaylandState {
 impl WaylandState {
     /// Create a new surface for an icon
     pub fn create_surface(&mut self, x: i32, y: i32, width: u32, height: u32) -> Result<SurfaceId> {
+        // On-demand keyboard focus lets us see modifier state when an icon is clicked
+        self.create_layer_surface(
+            x,
+            y,
+            width,
+            height,
+            Layer::Background,
+            KeyboardInteractivity::OnDemand,
+            "cvh-icon",
+        )
+    }
+
+    /// Create a surface for an icon on the output named `output`, at `(x, y)`
+    /// from that output's top-left corner
+    pub fn create_surface_on(
+        &mut self,
+        output: &str,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> Result<SurfaceId> {
+        let Some(wl_output) = self.output_named(output).cloned() else {
+            bail!("No output named {}", output);
+        };
+        self.create_layer_surface_on(
+            (Some(wl_output), (0, 0)),
+            x,
+            y,
+            width,
+            height,
+            Layer::Background,
+            KeyboardInteractivity::OnDemand,
+            "cvh-icon",
+        )
+    }
+
+    /// Create a transient overlay surface (e.g. the selection badge)
+    ///
+    /// Overlay surfaces sit above regular windows and have an empty input
+    /// region, so pointer events pass through to whatever is underneath.
+    pub fn create_overlay_surface(&mut self, x: i32, y: i32, width: u32, height: u32) -> Result<SurfaceId> {
+        let surface_id = self.create_layer_surface(
+            x,
+            y,
+            width,
+            height,
+            Layer::Overlay,
+            KeyboardInteractivity::None,
+            "cvh-icon-overlay",
+        )?;
+
+        if let Some(surface_data) = self.surfaces.get(&surface_id) {
+            let region = Region::new(&self.compositor_state)
+                .context("Failed to create input region")?;
+            let wl_surface = surface_data.layer_surface.wl_surface();
+            wl_surface.set_input_region(Some(region.wl_region()));
+            wl_surface.commit();
+        }
+
+        Ok(surface_id)
+    }
+
+    /// Create a transparent surface filling the primary output beneath the icons
+    ///
+    /// Anchored to every edge with no size, so the compositor sizes it to the
+    /// output (or the work area) and resizes it as that changes. It takes
+    /// pointer input, catching presses on the desktop between icons.
+    pub fn create_backdrop_surface(&mut self) -> Result<SurfaceId> {
+        let output = self.primary_output().cloned();
+        let surface_id = self.create_layer_surface_on(
+            (output, (0, 0)),
+            0,
+            0,
+            0,
+            0,
+            Layer::Background,
+            KeyboardInteractivity::OnDemand,
+            "cvh-icons-backdrop",
+        )?;
+        if let Some(surface_data) = self.surfaces.get(&surface_id) {
+            let layer_surface = &surface_data.layer_surface;
+            layer_surface.set_anchor(Anchor::all());
+            layer_surface.set_margin(0, 0, 0, 0);
+            layer_surface.set_size(0, 0);
+            layer_surface.commit();
+        }
+        Ok(surface_id)
+    }
+
+    /// Create a layer surface anchored to the top-left of its output
+    ///
+    /// The output is chosen by `surface_output_binding`.
+    #[allow(clippy::too_many_arguments)]
+    fn create_layer_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        layer: Layer,
+        keyboard_interactivity: KeyboardInteractivity,
+        namespace: &str,
+    ) -> Result<SurfaceId> {
+        // Without any output yet the compositor picks one
+        let target = self.output_for_surface(x, y);
+        self.create_layer_surface_on(
+            target,
+            x,
+            y,
+            width,
+            height,
+            layer,
+            keyboard_interactivity,
+            namespace,
+        )
+    }
+
+    /// Create a layer surface on `output`, whose top-left corner is at
+    /// `output_origin` relative to the primary output's
+    #[allow(clippy::too_many_arguments)]
+    fn create_layer_surface_on(
+        &mut self,
+        (output, output_origin): (Option<WlOutput>, (i32, i32)),
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        layer: Layer,
+        keyboard_interactivity: KeyboardInteractivity,
+        namespace: &str,
+    ) -> Result<SurfaceId> {
         let surface_id = self.next_surface_id;
         self.next_surface_id += 1;
 
-        // Get the first output (or create surface without specific output)
-        let output = self.outputs.first().cloned();
-
         // Create the wl_surface
         let wl_surface = self.compositor_state.create_surface(&self.queue_handle);
 
-        // Create layer surface on background layer
+        // Create layer surface on the requested layer
         let layer_surface = self.layer_shell.create_layer_surface(
             &self.queue_handle,
             wl_surface.clone(),
-            Layer::Background,
-            Some("cvh-icon"),
+            layer,
+            Some(namespace),
             output.as_ref(),
         );
 
         // Configure layer surface
-        layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT);
-        layer_surface.set_exclusive_zone(-1); // Don't reserve space
+        let local = (x - output_origin.0, y - output_origin.1);
+        let reference = self.anchor_reference(output.as_ref());
+        let (anchor, (top, right, bottom, left)) =
+            corner_anchor(self.anchor_corner, local, (width, height), reference);
+        layer_surface.set_anchor(anchor);
+        layer_surface.set_exclusive_zone(self.exclusive_zone()); // Don't reserve space
         layer_surface.set_size(width, height);
-        layer_surface.set_margin(y, 0, 0, x); // top, right, bottom, left margins for positioning
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer_surface.set_margin(top, right, bottom, left);
+        layer_surface.set_keyboard_interactivity(keyboard_interactivity);
 
         // Commit initial state
         layer_surface.commit();
@@ -165,9 +443,14 @@ impl WaylandState {
             width,
             height,
             configured: false,
-            buffer: None,
+            buffers: Vec::new(),
             position_x: x,
             position_y: y,
+            output_origin,
+            output,
+            frame_pending: false,
+            mapped: true,
+            scale: 1,
         };
 
         self.surfaces.insert(surface_id, surface_data);
@@ -178,6 +461,85 @@ impl WaylandState {
         Ok(surface_id)
     }
 
+    /// Exclusive zone of icon surfaces
+    ///
+    /// -1 ignores other surfaces' exclusive zones, so margins count from the
+    /// output's corner; 0 makes the compositor move us out of panels' way.
+    fn exclusive_zone(&self) -> i32 {
+        if self.work_area.is_some() {
+            0
+        } else {
+            -1
+        }
+    }
+
+    /// Start or stop placing surfaces relative to the work area
+    pub fn set_work_area_anchoring(&mut self, enabled: bool) {
+        if enabled == self.work_area_probe.is_some() {
+            return;
+        }
+        if !enabled {
+            // Dropping the last handle destroys the probe
+            self.work_area_probe = None;
+            self.probe_output = None;
+            self.set_work_area(None);
+            return;
+        }
+
+        let output = self.primary_output().cloned();
+        let wl_surface = self.compositor_state.create_surface(&self.queue_handle);
+        let probe = self.layer_shell.create_layer_surface(
+            &self.queue_handle,
+            wl_surface,
+            Layer::Background,
+            Some("cvh-icons-work-area"),
+            output.as_ref(),
+        );
+        // Size 0 on both axes is filled in from the anchors by the compositor
+        probe.set_anchor(Anchor::all());
+        probe.set_exclusive_zone(0);
+        probe.set_size(0, 0);
+        probe.set_keyboard_interactivity(KeyboardInteractivity::None);
+        // Never attaching a buffer keeps the probe unmapped and invisible
+        probe.commit();
+        self.work_area_probe = Some(probe);
+        self.probe_output = output;
+        debug!("Probing the work area");
+    }
+
+    /// Record the work area, switching every surface's exclusive zone to match
+    fn set_work_area(&mut self, area: Option<(u32, u32)>) {
+        if area == self.work_area {
+            return;
+        }
+        let was_known = self.work_area.is_some();
+        self.work_area = area;
+        match area {
+            Some((width, height)) => info!("Work area is {}x{}", width, height),
+            None => info!("Placing icons relative to the full output"),
+        }
+
+        if was_known != area.is_some() {
+            let zone = self.exclusive_zone();
+            for surface_data in self.surfaces.values() {
+                surface_data.layer_surface.set_exclusive_zone(zone);
+                surface_data.layer_surface.commit();
+            }
+        }
+    }
+
+    /// Size of the work area, if anchoring to it and the compositor sized the probe
+    pub fn get_work_area(&self) -> Option<(u32, u32)> {
+        self.work_area
+    }
+
+    /// Whether `layer` is the work area probe
+    fn is_work_area_probe(&self, layer: &LayerSurface) -> bool {
+        self.work_area_probe
+            .as_ref()
+            .is_some_and(|probe| probe.wl_surface() == layer.wl_surface())
+    }
+
     /// Destroy a surface
     pub fn destroy_surface(&mut self, surface_id: SurfaceId) {
         if let Some(surface_data) = self.surfaces.remove(&surface_id) {
@@ -191,27 +553,120 @@ impl WaylandState {
 
     /// Set surface position (via margins)
     pub fn set_surface_position(&mut self, surface_id: SurfaceId, x: i32, y: i32) {
+        let Some(output) = self.surfaces.get(&surface_id).map(|data| data.output.clone()) else {
+            return;
+        };
+        let reference = self.anchor_reference(output.as_ref());
         if let Some(surface_data) = self.surfaces.get_mut(&surface_id) {
             surface_data.position_x = x;
             surface_data.position_y = y;
             // Layer-shell uses margins for positioning relative to anchor
-            surface_data.layer_surface.set_margin(y, 0, 0, x);
+            let (origin_x, origin_y) = surface_data.output_origin;
+            let size = (surface_data.width, surface_data.height);
+            let (anchor, (top, right, bottom, left)) =
+                corner_anchor(self.anchor_corner, (x - origin_x, y - origin_y), size, reference);
+            surface_data.layer_surface.set_anchor(anchor);
+            surface_data.layer_surface.set_margin(top, right, bottom, left);
             surface_data.layer_surface.commit();
         }
     }
 
-    /// Attach a pixmap buffer to a surface
-    pub fn attach_buffer(&mut self, surface_id: SurfaceId, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+    /// Unmap a surface by attaching no buffer, or map it again
+    ///
+    /// A remapped layer surface starts over as if just created: it commits
+    /// without a buffer and waits for a configure before buffers attach.
+    pub fn set_surface_mapped(&mut self, surface_id: SurfaceId, mapped: bool) {
+        let Some(surface_data) = self.surfaces.get_mut(&surface_id) else {
+            return;
+        };
+        if surface_data.mapped == mapped {
+            return;
+        }
+        surface_data.mapped = mapped;
+        surface_data.configured = false;
+
+        let wl_surface = surface_data.layer_surface.wl_surface();
+        if !mapped {
+            wl_surface.attach(None, 0, 0);
+            surface_data.buffers.clear();
+            surface_data.frame_pending = false;
+        }
+        wl_surface.commit();
+        debug!("Surface {} {}", surface_id, if mapped { "remapped" } else { "unmapped" });
+    }
+
+    /// Whether a focused maximized or fullscreen window is on the icons' output
+    ///
+    /// Windows on other outputs leave the icons visible.
+    pub fn desktop_covered(&self) -> bool {
+        let Some(output) = self.primary_output() else {
+            return false;
+        };
+        self.toplevels
+            .values()
+            .any(|toplevel| toplevel.state.covers_desktop() && toplevel.outputs.contains(output))
+    }
+
+    /// Raise a window whose app ID is one of `app_ids`
+    ///
+    /// A window on the icons' output is preferred. Returns whether one was
+    /// found; without a seat to activate it with, none is.
+    pub fn activate_app(&self, app_ids: &[String]) -> bool {
+        let Some(seat) = self.seat_state.seats().next() else {
+            return false;
+        };
+        let output = self.primary_output();
+        let mut matching: Vec<(&ZwlrForeignToplevelHandleV1, &Toplevel)> = self
+            .toplevels
+            .iter()
+            .filter(|(_, toplevel)| launcher::matches(app_ids, &toplevel.app_id))
+            .collect();
+        matching.sort_by_key(|(_, toplevel)| !output.is_some_and(|o| toplevel.outputs.contains(o)));
+        let Some((handle, toplevel)) = matching.first() else {
+            return false;
+        };
+        debug!("Activating running {} window", toplevel.app_id);
+        handle.activate(&seat);
+        true
+    }
+
+    /// Get a surface's position (layer-shell margins from the top-left corner)
+    pub fn surface_position(&self, surface_id: SurfaceId) -> Option<(i32, i32)> {
+        self.surfaces
+            .get(&surface_id)
+            .map(|s| (s.position_x, s.position_y))
+    }
+
+    /// Attach a pixmap buffer to a surface, damaging the whole surface
+    ///
+    /// Returns `false` if the surface is not configured yet and nothing was attached.
+    pub fn attach_buffer(&mut self, surface_id: SurfaceId, pixels: &[u8], width: u32, height: u32) -> Result<bool> {
+        self.attach_buffer_damaged(surface_id, pixels, width, height, &[DamageRect::full(width, height)])
+    }
+
+    /// Attach a pixmap buffer to a surface, damaging only the given regions
+    ///
+    /// `width` and `height` are the buffer's size in pixels; a buffer at the
+    /// surface's scale times its size is shown at that scale. Returns `false`
+    /// if the surface is not configured yet and nothing was attached.
+    pub fn attach_buffer_damaged(
+        &mut self,
+        surface_id: SurfaceId,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        damage: &[DamageRect],
+    ) -> Result<bool> {
         let surface_data = self.surfaces.get_mut(&surface_id)
             .ok_or_else(|| anyhow::anyhow!("Surface {} not found", surface_id))?;
 
         if !surface_data.configured {
             // Wait for configure event before attaching buffer
             debug!("Surface {} not yet configured, skipping buffer attach", surface_id);
-            return Ok(());
+            return Ok(false);
         }
 
-        // Ensure buffer size matches
+        // Ensure buffer size matches (in buffer pixels, so scaled for HiDPI)
         let expected_size = (width * height * 4) as usize;
         if pixels.len() != expected_size {
             return Err(anyhow::anyhow!(
@@ -220,45 +675,102 @@ impl WaylandState {
             ));
         }
 
-        // Create or reuse buffer
-        let (buffer, canvas) = self.pool
-            .create_buffer(
-                width as i32,
-                height as i32,
-                width as i32 * 4,
-                wl_shm::Format::Argb8888,
-            )
-            .context("Failed to create buffer")?;
+        // Draw into a buffer of this size the compositor has released, and
+        // only allocate one when the size changed or all are still in use
+        surface_data.buffers.retain(|shm| (shm.width, shm.height) == (width, height));
+        let released = surface_data
+            .buffers
+            .iter()
+            .position(|shm| shm.buffer.canvas(&mut self.pool).is_some());
+        let shm = match released {
+            Some(index) => surface_data.buffers.remove(index),
+            None => {
+                // A compositor holding on to more than that gets the oldest
+                // destroyed once it lets go of it
+                if surface_data.buffers.len() >= SURFACE_BUFFERS {
+                    surface_data.buffers.remove(0);
+                }
+                let buffer = create_shm_buffer(&mut self.pool, width, height)?;
+                ShmBuffer { buffer, width, height }
+            }
+        };
+        let canvas = shm.buffer.canvas(&mut self.pool).context("Buffer is still in use")?;
 
-        // Copy pixels (converting from RGBA to ARGB if needed)
         // tiny-skia uses RGBA premultiplied, Wayland expects ARGB
-        for (i, chunk) in pixels.chunks(4).enumerate() {
-            let r = chunk[0];
-            let g = chunk[1];
-            let b = chunk[2];
-            let a = chunk[3];
-            let offset = i * 4;
-            canvas[offset] = b;     // B
-            canvas[offset + 1] = g; // G
-            canvas[offset + 2] = r; // R
-            canvas[offset + 3] = a; // A
-        }
+        rgba_to_bgra(pixels, canvas);
+        scale_opacity(canvas, self.surface_opacity);
 
         // Attach and commit
+        let scale = buffer_scale(
+            (surface_data.width, surface_data.height),
+            (width, height),
+            surface_data.scale,
+        );
         let wl_surface = surface_data.layer_surface.wl_surface();
-        buffer.attach_to(wl_surface).context("Failed to attach buffer")?;
-        wl_surface.damage_buffer(0, 0, width as i32, height as i32);
+        wl_surface.set_buffer_scale(scale as i32);
+        shm.buffer.attach_to(wl_surface).context("Failed to attach buffer")?;
+        for rect in damage {
+            wl_surface.damage_buffer(rect.x, rect.y, rect.width, rect.height);
+        }
+        // Ask to be told when the compositor is ready for the next frame
+        wl_surface.frame(&self.queue_handle, wl_surface.clone());
+        surface_data.frame_pending = true;
         wl_surface.commit();
 
-        // Store buffer reference to keep it alive
-        surface_data.buffer = Some(buffer);
+        // Kept as the newest, to be drawn into again once released
+        surface_data.buffers.push(shm);
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Check whether any surface is still waiting for a frame callback
+    pub fn frame_callback_pending(&self) -> bool {
+        self.surfaces.values().any(|s| s.frame_pending)
+    }
+
+    /// Frame group of a surface: the protocol id of its output, or 0 while
+    /// the output is not known
+    pub fn frame_group(&self, surface_id: SurfaceId) -> FrameGroup {
+        self.surfaces.get(&surface_id).map_or(0, surface_frame_group)
+    }
+
+    /// Whether a surface on the group's output is waiting for a frame callback
+    pub fn frame_group_pending(&self, group: FrameGroup) -> bool {
+        self.surfaces
+            .values()
+            .any(|s| s.frame_pending && surface_frame_group(s) == group)
+    }
+
+    /// Get surfaces that were configured for the first time (drains the list)
+    pub fn take_newly_configured(&mut self) -> Vec<SurfaceId> {
+        std::mem::take(&mut self.newly_configured)
+    }
+
+    /// Get configured surfaces that the compositor resized (drains the list)
+    pub fn take_resized(&mut self) -> Vec<SurfaceId> {
+        std::mem::take(&mut self.resized)
+    }
+
+    /// Whether an output icons are on changed size or scale (clears the flag)
+    pub fn take_outputs_changed(&mut self) -> bool {
+        std::mem::take(&mut self.outputs_changed)
+    }
+
+    /// Current size of a surface as last configured by the compositor
+    pub fn surface_size(&self, surface_id: SurfaceId) -> Option<(u32, u32)> {
+        self.surfaces
+            .get(&surface_id)
+            .map(|surface_data| (surface_data.width, surface_data.height))
+    }
+
+    /// Integer scale of the output a surface is on
+    pub fn surface_scale(&self, surface_id: SurfaceId) -> u32 {
+        self.surfaces.get(&surface_id).map_or(1, |surface_data| surface_data.scale)
     }
 
     /// Get pending input events (drains the queue)
     pub fn take_input_events(&mut self) -> Vec<InputEvent> {
-        std::mem::take(&mut self.input_events)
+        self.input_events.take()
     }
 
     /// Check if should exit
@@ -285,10 +797,22 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
-        _new_factor: i32,
+        surface: &WlSurface,
+        new_factor: i32,
     ) {
-        // Handle scale factor changes if needed
+        // Icons are drawn again at the new scale, as after a resize
+        let Some(&surface_id) = self.surface_ids.get(surface) else {
+            return;
+        };
+        let Some(surface_data) = self.surfaces.get_mut(&surface_id) else {
+            return;
+        };
+        let scale = new_factor.max(1) as u32;
+        if surface_data.scale != scale {
+            debug!("Surface {} scale changed to {}", surface_id, scale);
+            surface_data.scale = scale;
+            self.resized.push(surface_id);
+        }
     }
 
     fn transform_changed(
@@ -305,19 +829,30 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
+        surface: &WlSurface,
         _time: u32,
     ) {
-        // Handle frame callbacks
+        // The compositor presented our last commit; the surface may draw again
+        if let Some(surface_id) = self.surface_ids.get(surface).copied() {
+            if let Some(surface_data) = self.surfaces.get_mut(&surface_id) {
+                surface_data.frame_pending = false;
+            }
+        }
     }
 
     fn surface_enter(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
-        _output: &WlOutput,
+        surface: &WlSurface,
+        output: &WlOutput,
     ) {
+        // Surfaces created without an output learn theirs here, for pacing
+        if let Some(surface_id) = self.surface_ids.get(surface).copied() {
+            if let Some(surface_data) = self.surfaces.get_mut(&surface_id) {
+                surface_data.output = Some(output.clone());
+            }
+        }
     }
 
     fn surface_leave(
@@ -342,17 +877,29 @@ impl OutputHandler for WaylandState {
         output: WlOutput,
     ) {
         info!("New output detected");
+        if let Some(geometry) = self.output_geometry(&output) {
+            self.output_geometry.insert(output.clone(), geometry);
+        }
         self.outputs.push(output);
+        self.primary_output_changed();
     }
 
     fn update_output(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: WlOutput,
+        output: WlOutput,
     ) {
-        // Handle output updates (dimensions may have changed)
+        // Dimensions, scale or name may have changed
         debug!("Output updated");
+        if let Some(geometry) = self.output_geometry(&output) {
+            let previous = self.output_geometry.insert(output.clone(), geometry);
+            if previous != Some(geometry) && self.output_has_icons(&output) {
+                info!("Output with icons changed to {:?} at scale {}", geometry.0, geometry.1);
+                self.outputs_changed = true;
+            }
+        }
+        self.primary_output_changed();
     }
 
     fn output_destroyed(
@@ -362,32 +909,245 @@ impl OutputHandler for WaylandState {
         output: WlOutput,
     ) {
         info!("Output destroyed");
+        self.output_geometry.remove(&output);
         self.outputs.retain(|o| o != &output);
+        self.primary_output_changed();
     }
 }
 
 impl WaylandState {
+    /// Output icons are placed on
+    ///
+    /// The output named by `primary_output_name` while it is connected, the
+    /// first announced output otherwise.
+    fn primary_output(&self) -> Option<&WlOutput> {
+        let names: Vec<Option<String>> = self
+            .outputs
+            .iter()
+            .map(|output| self.output_state.info(output).and_then(|info| info.name))
+            .collect();
+        let index = primary_output_index(&names, self.primary_output_name.as_deref())?;
+        self.outputs.get(index)
+    }
+
+    /// Prefer the output with this name for icons, or the first output if None
+    pub fn set_primary_output(&mut self, name: Option<String>) {
+        if name == self.primary_output_name {
+            return;
+        }
+        self.primary_output_name = name;
+        self.primary_output_changed();
+    }
+
+    /// Choose which output surfaces created from now on are bound to
+    pub fn set_surface_output_binding(&mut self, binding: SurfaceOutputBinding) {
+        self.surface_output_binding = binding;
+    }
+
+    /// Anchor surfaces created or moved from now on to `corner`
+    pub fn set_anchor_corner(&mut self, corner: Corner) {
+        self.anchor_corner = corner;
+    }
+
+    /// Size of the area surface margins on `output` count from
+    ///
+    /// The work area while anchoring to it, else the output (or the primary
+    /// output for surfaces the compositor placed).
+    fn anchor_reference(&self, output: Option<&WlOutput>) -> Option<(u32, u32)> {
+        self.work_area.or_else(|| self.output_size(output.or(self.primary_output())?))
+    }
+
+    /// Output to create a surface at `(x, y)` on, and that output's top-left
+    /// corner relative to the primary output's
+    fn output_for_surface(&self, x: i32, y: i32) -> (Option<WlOutput>, (i32, i32)) {
+        let primary = (self.primary_output().cloned(), (0, 0));
+        match self.surface_output_binding {
+            SurfaceOutputBinding::Explicit => primary,
+            SurfaceOutputBinding::None => (None, (0, 0)),
+            SurfaceOutputBinding::PerPosition => self.output_at(x, y).unwrap_or(primary),
+        }
+    }
+
+    /// Output containing `(x, y)`, counted from the primary output's top-left
+    /// corner in the global layout, with that output's corner
+    ///
+    /// None if the compositor has not reported output positions.
+    fn output_at(&self, x: i32, y: i32) -> Option<(Option<WlOutput>, (i32, i32))> {
+        let primary = self.output_state.info(self.primary_output()?)?;
+        let (primary_x, primary_y) = primary.logical_position?;
+        let rects: Vec<Option<(i32, i32, i32, i32)>> = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let info = self.output_state.info(output)?;
+                let (output_x, output_y) = info.logical_position?;
+                let (width, height) = info.logical_size?;
+                Some((output_x - primary_x, output_y - primary_y, width, height))
+            })
+            .collect();
+        let index = output_index_at(&rects, x, y)?;
+        let (origin_x, origin_y, _, _) = rects[index]?;
+        Some((self.outputs.get(index).cloned(), (origin_x, origin_y)))
+    }
+
+    /// Logical size and scale `output` has announced, if any
+    fn output_geometry(&self, output: &WlOutput) -> Option<OutputGeometry> {
+        let info = self.output_state.info(output)?;
+        Some((info.logical_size, info.scale_factor))
+    }
+
+    /// Abandon the touch standing in for the pointer, without a release
+    fn cancel_touch(&mut self) {
+        if let Some(point) = self.touch_point.take() {
+            self.input_events.push(InputEvent::PointerCancel { surface_id: point.surface_id });
+            self.input_events.push(InputEvent::PointerLeave { surface_id: point.surface_id });
+        }
+    }
+
+    /// Whether icons are placed on `output` or have a surface shown on it
+    fn output_has_icons(&self, output: &WlOutput) -> bool {
+        self.primary_output() == Some(output)
+            || self.surfaces.values().any(|surface| surface.output.as_ref() == Some(output))
+    }
+
+    /// Follow the primary output after outputs or the preference changed
+    ///
+    /// The daemon notices the new output name and recreates icon surfaces on
+    /// it; the work area probe is recreated here since it belongs to us.
+    fn primary_output_changed(&mut self) {
+        let output = self.primary_output().cloned();
+        if self.work_area_probe.is_none() || output == self.probe_output {
+            return;
+        }
+        info!("Primary output changed, probing its work area");
+        self.set_work_area_anchoring(false);
+        self.set_work_area_anchoring(true);
+    }
+
+    /// Get the name of the primary output (e.g. "DP-1")
+    pub fn get_output_name(&self) -> Option<String> {
+        let output = self.primary_output()?;
+        self.output_state.info(output)?.name
+    }
+
     /// Get the dimensions of the primary output
     pub fn get_output_dimensions(&self) -> Option<(u32, u32)> {
-        // Get the first output's info
-        if let Some(output) = self.outputs.first() {
-            if let Some(info) = self.output_state.info(output) {
-                // Get the logical size (respects scaling)
-                if let Some(logical_size) = info.logical_size {
-                    return Some((logical_size.0 as u32, logical_size.1 as u32));
-                }
-                // Fall back to physical mode size if logical not available
-                if let Some(mode) = info.modes.iter().find(|m| m.current) {
-                    return Some((mode.dimensions.0 as u32, mode.dimensions.1 as u32));
-                }
-            }
+        self.output_size(self.primary_output()?)
+    }
+
+    /// Name and size of every output that reported both
+    pub fn output_dimensions_all(&self) -> Vec<(String, u32, u32)> {
+        self.outputs
+            .iter()
+            .filter_map(|output| {
+                let name = self.output_state.info(output)?.name?;
+                let (width, height) = self.output_size(output)?;
+                Some((name, width, height))
+            })
+            .collect()
+    }
+
+    /// Connected output with this name
+    fn output_named(&self, name: &str) -> Option<&WlOutput> {
+        self.outputs.iter().find(|output| {
+            self.output_state.info(output).and_then(|info| info.name).as_deref() == Some(name)
+        })
+    }
+
+    /// Logical size of `output`, or its current mode's size if unknown
+    fn output_size(&self, output: &WlOutput) -> Option<(u32, u32)> {
+        let info = self.output_state.info(output)?;
+        // Get the logical size (respects scaling)
+        if let Some(logical_size) = info.logical_size {
+            return Some((logical_size.0 as u32, logical_size.1 as u32));
+        }
+        // Fall back to physical mode size if logical not available
+        let mode = info.modes.iter().find(|m| m.current)?;
+        Some((mode.dimensions.0 as u32, mode.dimensions.1 as u32))
+    }
+}
+
+/// Index of the output to place icons on, given each output's name
+///
+/// The output named `preferred` if there is one, else the first output.
+pub fn primary_output_index(names: &[Option<String>], preferred: Option<&str>) -> Option<usize> {
+    preferred
+        .and_then(|preferred| names.iter().position(|name| name.as_deref() == Some(preferred)))
+        .or_else(|| (!names.is_empty()).then_some(0))
+}
+
+/// Frame group of a surface's output
+fn surface_frame_group(surface: &IconSurfaceData) -> FrameGroup {
+    surface.output.as_ref().map_or(0, |output| output.id().protocol_id())
+}
+
+/// Anchor and `(top, right, bottom, left)` margins placing a `width` x
+/// `height` surface at `(x, y)` of an area of size `reference`, counted from
+/// `corner` of the area
+///
+/// Without a known reference size surfaces are anchored to the top-left.
+pub fn corner_anchor(
+    corner: Corner,
+    (x, y): (i32, i32),
+    size: (u32, u32),
+    reference: Option<(u32, u32)>,
+) -> (Anchor, (i32, i32, i32, i32)) {
+    let Some(reference) = reference else {
+        return (Anchor::TOP | Anchor::LEFT, (y, 0, 0, x));
+    };
+    let (from_edge_x, from_edge_y) = mirror_to_corner(corner, (x, y), size, reference);
+    let (horizontal, right, left) = match corner.is_right() {
+        true => (Anchor::RIGHT, from_edge_x, 0),
+        false => (Anchor::LEFT, 0, from_edge_x),
+    };
+    let (vertical, top, bottom) = match corner.is_bottom() {
+        true => (Anchor::BOTTOM, 0, from_edge_y),
+        false => (Anchor::TOP, from_edge_y, 0),
+    };
+    (vertical | horizontal, (top, right, bottom, left))
+}
+
+/// A new `width` x `height` ARGB buffer from `pool`
+///
+/// The pool grows to fit new buffers by itself; should that fail, it is
+/// grown to twice its size and the buffer tried once more.
+fn create_shm_buffer(pool: &mut SlotPool, width: u32, height: u32) -> Result<Buffer> {
+    let (width, height) = (width as i32, height as i32);
+    let format = wl_shm::Format::Argb8888;
+    match pool.create_buffer(width, height, width * 4, format) {
+        Ok((buffer, _)) => Ok(buffer),
+        Err(e) => {
+            debug!("Growing the buffer pool after: {}", e);
+            let needed = width as usize * height as usize * 4;
+            pool.resize(pool.len() * 2 + needed).context("Failed to grow the buffer pool")?;
+            let (buffer, _) = pool
+                .create_buffer(width, height, width * 4, format)
+                .context("Failed to create buffer")?;
+            Ok(buffer)
         }
-        None
     }
 }
 
+/// Index of the output whose rectangle (x, y, width, height) contains the
+/// point, skipping outputs without a known rectangle
+pub fn output_index_at(rects: &[Option<(i32, i32, i32, i32)>], x: i32, y: i32) -> Option<usize> {
+    rects.iter().position(|rect| {
+        rect.is_some_and(|(left, top, width, height)| {
+            (left..left + width).contains(&x) && (top..top + height).contains(&y)
+        })
+    })
+}
+
 impl LayerShellHandler for WaylandState {
     fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        if self.is_work_area_probe(layer) {
+            // e.g. its output went away; fall back to the full output
+            debug!("Work area probe closed");
+            self.work_area_probe = None;
+            self.set_work_area(None);
+            return;
+        }
+
         // Find the surface that was closed
         let wl_surface = layer.wl_surface();
         if let Some(&surface_id) = self.surface_ids.get(wl_surface) {
@@ -405,16 +1165,29 @@ impl LayerShellHandler for WaylandState {
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
+        if self.is_work_area_probe(layer) {
+            let (width, height) = configure.new_size;
+            self.set_work_area(Some((width, height)).filter(|&(w, h)| w > 0 && h > 0));
+            layer.wl_surface().commit();
+            return;
+        }
+
         let wl_surface = layer.wl_surface();
         if let Some(&surface_id) = self.surface_ids.get(wl_surface) {
             if let Some(surface_data) = self.surfaces.get_mut(&surface_id) {
                 // Update size if the compositor requested a different size
+                let old_size = (surface_data.width, surface_data.height);
                 if configure.new_size.0 > 0 {
                     surface_data.width = configure.new_size.0;
                 }
                 if configure.new_size.1 > 0 {
                     surface_data.height = configure.new_size.1;
                 }
+                if !surface_data.configured {
+                    self.newly_configured.push(surface_id);
+                } else if old_size != (surface_data.width, surface_data.height) {
+                    self.resized.push(surface_id);
+                }
                 surface_data.configured = true;
                 debug!(
                     "Surface {} configured with size {}x{}",
@@ -444,9 +1217,27 @@ impl SeatHandler for WaylandState {
         seat: WlSeat,
         capability: Capability,
     ) {
+        if let (None, Some(manager)) = (&self.data_device, &self.data_device_manager) {
+            self.data_device = Some(manager.get_data_device(qh, &seat));
+        }
         if capability == Capability::Pointer && self.pointer.is_none() {
             debug!("Creating pointer for seat");
-            self.pointer = self.seat_state.get_pointer(qh, &seat).ok();
+            let cursor_surface = self.compositor_state.create_surface(qh);
+            self.pointer = self
+                .seat_state
+                .get_pointer_with_theme(qh, &seat, self.shm.wl_shm(), cursor_surface, ThemeSpec::default())
+                .ok();
+        }
+        if capability == Capability::Touch && self.touch.is_none() {
+            debug!("Creating touch for seat");
+            self.touch = self.seat_state.get_touch(qh, &seat).ok();
+        }
+        if capability == Capability::Keyboard && self.keyboard.is_none() {
+            debug!("Creating keyboard for seat");
+            self.keyboard = self
+                .seat_state
+                .get_keyboard::<Self, Self>(qh, &seat, None)
+                .ok();
         }
     }
 
@@ -460,6 +1251,18 @@ impl SeatHandler for WaylandState {
         if capability == Capability::Pointer {
             self.pointer = None;
         }
+        if capability == Capability::Touch {
+            if let Some(touch) = self.touch.take() {
+                touch.release();
+            }
+            self.cancel_touch();
+        }
+        if capability == Capability::Keyboard {
+            if let Some(keyboard) = self.keyboard.take() {
+                keyboard.release();
+            }
+            self.modifiers = KeyModifiers::default();
+        }
     }
 
     fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {
@@ -470,7 +1273,7 @@ impl SeatHandler for WaylandState {
 impl PointerHandler for WaylandState {
     fn pointer_frame(
         &mut self,
-        _conn: &Connection,
+        conn: &Connection,
         _qh: &QueueHandle<Self>,
         _pointer: &WlPointer,
         events: &[PointerEvent],
@@ -485,6 +1288,7 @@ impl PointerHandler for WaylandState {
                     self.pointer_y = y;
                     if let Some(&surface_id) = self.surface_ids.get(surface) {
                         self.pointer_surface = Some(surface_id);
+                        self.show_cursor(conn);
                         self.input_events.push(InputEvent::PointerEnter {
                             surface_id,
                             x,
@@ -509,7 +1313,8 @@ impl PointerHandler for WaylandState {
                         });
                     }
                 }
-                PointerEventKind::Press { button, .. } => {
+                PointerEventKind::Press { button, serial, .. } => {
+                    self.last_input_serial = *serial;
                     if let Some(surface_id) = self.pointer_surface {
                         self.input_events.push(InputEvent::PointerButton {
                             surface_id,
@@ -517,6 +1322,7 @@ impl PointerHandler for WaylandState {
                             pressed: true,
                             x: self.pointer_x,
                             y: self.pointer_y,
+                            modifiers: self.modifiers,
                         });
                     }
                 }
@@ -528,44 +1334,471 @@ impl PointerHandler for WaylandState {
                             pressed: false,
                             x: self.pointer_x,
                             y: self.pointer_y,
+                            modifiers: self.modifiers,
                         });
                     }
                 }
-                PointerEventKind::Axis { .. } => {
-                    // Scroll events - not handling for now
+                PointerEventKind::Axis { horizontal, vertical, .. } => {
+                    // Scrolls after the pointer left have no surface to go to
+                    if let Some(surface_id) = self.pointer_surface {
+                        self.input_events.push(InputEvent::PointerScroll {
+                            surface_id,
+                            horizontal: horizontal.absolute,
+                            vertical: vertical.absolute,
+                            discrete: (horizontal.discrete, vertical.discrete),
+                        });
+                    }
                 }
             }
         }
     }
 }
 
-impl ShmHandler for WaylandState {
-    fn shm_state(&mut self) -> &mut Shm {
-        &mut self.shm
+/// Touches drive the pointer's interactions: a touch on a surface enters it
+/// and presses `BTN_TOUCH`, moving drags, and lifting releases and leaves it.
+/// A quick second tap makes a double click the same way two clicks do.
+impl TouchHandler for WaylandState {
+    fn down(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        serial: u32,
+        _time: u32,
+        surface: WlSurface,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        if self.touch_point.is_some() {
+            return;
+        }
+        let Some(&surface_id) = self.surface_ids.get(&surface) else {
+            return;
+        };
+        let (x, y) = position;
+        self.last_input_serial = serial;
+        self.touch_point = Some(TouchPoint { id, surface_id, x, y });
+        self.input_events.push(InputEvent::PointerEnter { surface_id, x, y });
+        self.input_events.push(InputEvent::PointerButton {
+            surface_id,
+            button: BTN_TOUCH,
+            pressed: true,
+            x,
+            y,
+            modifiers: self.modifiers,
+        });
     }
-}
 
-impl ProvidesRegistryState for WaylandState {
-    fn registry(&mut self) -> &mut RegistryState {
-        &mut self.registry_state
+    fn up(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _serial: u32,
+        _time: u32,
+        id: i32,
+    ) {
+        let Some(point) = self.touch_point.filter(|point| point.id == id) else {
+            return;
+        };
+        self.touch_point = None;
+        self.input_events.push(InputEvent::PointerButton {
+            surface_id: point.surface_id,
+            button: BTN_TOUCH,
+            pressed: false,
+            x: point.x,
+            y: point.y,
+            modifiers: self.modifiers,
+        });
+        self.input_events.push(InputEvent::PointerLeave { surface_id: point.surface_id });
     }
-    registry_handlers![OutputState, SeatState];
-}
-
-delegate_compositor!(WaylandState);
-delegate_output!(WaylandState);
-delegate_layer!(WaylandState);
-delegate_seat!(WaylandState);
-delegate_pointer!(WaylandState);
-delegate_shm!(WaylandState);
-delegate_registry!(WaylandState);
 
-/// Wayland manager - high level interface for the daemon
-pub struct WaylandManager {
-    /// The event loop
-    event_loop: EventLoop<'static, WaylandState>,
-    /// Wayland state (shared with event loop)
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _time: u32,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        let Some(point) = self.touch_point.as_mut().filter(|point| point.id == id) else {
+            return;
+        };
+        (point.x, point.y) = position;
+        let (surface_id, x, y) = (point.surface_id, point.x, point.y);
+        self.input_events.push(InputEvent::PointerMotion { surface_id, x, y });
+    }
+
+    fn shape(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _major: f64,
+        _minor: f64,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _orientation: f64,
+    ) {
+    }
+
+    fn cancel(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _touch: &WlTouch) {
+        self.cancel_touch();
+    }
+}
+
+impl KeyboardHandler for WaylandState {
+    fn enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        _surface: &WlSurface,
+        _serial: u32,
+        _raw: &[u32],
+        _keysyms: &[Keysym],
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        _surface: &WlSurface,
+        _serial: u32,
+    ) {
+        // Modifier state is only reported while we have focus
+        self.modifiers = KeyModifiers::default();
+    }
+
+    fn press_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        serial: u32,
+        event: KeyEvent,
+    ) {
+        self.last_input_serial = serial;
+        self.input_events.push(InputEvent::Key {
+            keysym: event.keysym.raw(),
+            modifiers: self.modifiers,
+        });
+    }
+
+    fn repeat_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        _serial: u32,
+        _event: KeyEvent,
+    ) {
+    }
+
+    fn release_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        _serial: u32,
+        _event: KeyEvent,
+    ) {
+    }
+
+    fn update_modifiers(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &WlKeyboard,
+        _serial: u32,
+        modifiers: Modifiers,
+        _raw_modifiers: RawModifiers,
+        _layout: u32,
+    ) {
+        self.modifiers = KeyModifiers {
+            ctrl: modifiers.ctrl,
+            shift: modifiers.shift,
+            alt: modifiers.alt,
+        };
+    }
+}
+
+impl ShmHandler for WaylandState {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.toplevels.insert(toplevel, Toplevel::default());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                debug!("Foreign toplevel manager finished");
+                state.toplevels.clear();
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = state.toplevels.get_mut(handle) else {
+            return;
+        };
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                toplevel.pending_state = Some(ToplevelState::from_wire(&states));
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevel.app_id = app_id;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output }
+                if !toplevel.outputs.contains(&output) =>
+            {
+                toplevel.outputs.push(output);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                toplevel.outputs.retain(|o| o != &output);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                if let Some(pending) = toplevel.pending_state.take() {
+                    toplevel.state = pending;
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.toplevels.remove(handle);
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ProvidesRegistryState for WaylandState {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState, SeatState];
+}
+
+impl WaylandState {
+    /// Offer `text` on the clipboard as plain text
+    pub fn set_clipboard_text(&mut self, text: &str) -> Result<()> {
+        let (Some(manager), Some(device)) = (&self.data_device_manager, &self.data_device) else {
+            anyhow::bail!("The compositor offers no clipboard");
+        };
+        let source =
+            manager.create_copy_paste_source(&self.queue_handle, clipboard::TEXT_MIME_TYPES);
+        source.set_selection(device, self.last_input_serial);
+        // Replacing the offer drops (and destroys) the previous source
+        self.clipboard = Some((source, text.to_string()));
+        Ok(())
+    }
+
+    /// Show `shape` while the pointer is over our surfaces
+    pub fn set_cursor_shape(&mut self, conn: &Connection, shape: CursorShape) {
+        self.cursor_shape = shape;
+        self.show_cursor(conn);
+    }
+
+    /// Set the cursor the daemon last asked for, if the pointer is over one of our surfaces
+    ///
+    /// Failures, such as a theme without the cursor, leave whatever cursor
+    /// is shown.
+    fn show_cursor(&self, conn: &Connection) {
+        let (Some(pointer), Some(_)) = (&self.pointer, self.pointer_surface) else {
+            return;
+        };
+        let icon = match self.cursor_shape {
+            CursorShape::Default => CursorIcon::Default,
+            CursorShape::Pointer => CursorIcon::Pointer,
+            CursorShape::Grabbing => CursorIcon::Grabbing,
+        };
+        if let Err(e) = pointer.set_cursor(conn, icon) {
+            debug!("Failed to set the {:?} cursor: {}", icon, e);
+        }
+    }
+}
+
+impl DataDeviceHandler for WaylandState {
+    fn enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+        _x: f64,
+        _y: f64,
+        _wl_surface: &WlSurface,
+    ) {
+    }
+
+    fn leave(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _data_device: &WlDataDevice) {}
+
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+        _x: f64,
+        _y: f64,
+    ) {
+    }
+
+    fn selection(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+    ) {
+        // We only offer, never paste
+    }
+
+    fn drop_performed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+    ) {
+    }
+}
+
+impl DataOfferHandler for WaylandState {
+    fn source_actions(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _offer: &mut DragOffer,
+        _actions: DndAction,
+    ) {
+    }
+
+    fn selected_action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _offer: &mut DragOffer,
+        _actions: DndAction,
+    ) {
+    }
+}
+
+impl DataSourceHandler for WaylandState {
+    fn accept_mime(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _mime: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        source: &WlDataSource,
+        mime: String,
+        mut fd: WritePipe,
+    ) {
+        let ours = self.clipboard.as_ref().filter(|(offer, _)| offer.inner() == source);
+        let Some((_, text)) = ours else {
+            return;
+        };
+        // Write from a thread so a slow reader cannot stall the event loop
+        let text = text.clone();
+        std::thread::spawn(move || {
+            use std::io::Write;
+            if let Err(e) = fd.write_all(text.as_bytes()) {
+                debug!("Failed to send clipboard text as {}: {}", mime, e);
+            }
+        });
+    }
+
+    fn cancelled(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, source: &WlDataSource) {
+        // Something else was copied
+        if self.clipboard.as_ref().is_some_and(|(offer, _)| offer.inner() == source) {
+            self.clipboard = None;
+        }
+    }
+
+    fn dnd_dropped(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+    ) {
+    }
+
+    fn dnd_finished(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+    ) {
+    }
+
+    fn action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _action: DndAction,
+    ) {
+    }
+}
+
+delegate_compositor!(WaylandState);
+delegate_data_device!(WaylandState);
+delegate_output!(WaylandState);
+delegate_layer!(WaylandState);
+delegate_seat!(WaylandState);
+delegate_keyboard!(WaylandState);
+delegate_pointer!(WaylandState);
+delegate_touch!(WaylandState);
+delegate_shm!(WaylandState);
+delegate_registry!(WaylandState);
+
+/// Wayland manager - high level interface for the daemon
+pub struct WaylandManager {
+    /// The event loop
+    event_loop: EventLoop<'static, WaylandState>,
+    /// Wayland state (shared with event loop)
     state: WaylandState,
+    /// Display connection, to flush requests before the daemon sleeps
+    connection: Connection,
 }
 
 impl WaylandManager {
@@ -573,18 +1806,22 @@ impl WaylandManager {
     pub fn new() -> Result<Self> {
         // Check for Wayland display
         if std::env::var("WAYLAND_DISPLAY").is_err() {
-            return Err(anyhow::anyhow!("WAYLAND_DISPLAY not set - not running under Wayland"));
+            return Err(DaemonError::NoWaylandDisplay.into());
         }
 
         // Connect to the Wayland display
         let conn = Connection::connect_to_env()
-            .context("Failed to connect to Wayland display")?;
+            .map_err(|e| DaemonError::WaylandConnect(e.into()))?;
 
         info!("Connected to Wayland display");
 
         // Initialize the registry
         let (globals, event_queue) = registry_queue_init(&conn)
-            .context("Failed to initialize registry")?;
+            .map_err(|e| {
+                DaemonError::WaylandConnect(
+                    anyhow::Error::new(e).context("Failed to initialize registry"),
+                )
+            })?;
 
         let qh = event_queue.handle();
 
@@ -597,7 +1834,7 @@ impl WaylandManager {
 
         // Get layer shell
         let layer_shell = LayerShell::bind(&globals, &qh)
-            .context("Failed to bind layer shell - is wlr-layer-shell-unstable-v1 supported?")?;
+            .map_err(|e| DaemonError::LayerShellUnsupported(e.into()))?;
 
         // Get shared memory
         let shm = Shm::bind(&globals, &qh)
@@ -606,7 +1843,17 @@ impl WaylandManager {
         // Get seat state
         let seat_state = SeatState::new(&globals, &qh);
 
-        // Create buffer pool (initial size 1MB, will grow as needed)
+        // Clipboard for copied paths and text (optional)
+        let data_device_manager = DataDeviceManagerState::bind(&globals, &qh)
+            .map_err(|e| debug!("No data device manager, copying is unavailable: {}", e))
+            .ok();
+
+        // Track windows, to tell when one covers the desktop (optional)
+        if let Err(e) = globals.bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ()) {
+            debug!("No foreign toplevel manager, icons are never hidden: {}", e);
+        }
+
+        // Create buffer pool (initial size 1MB, grown as buffers need)
         let pool = SlotPool::new(1024 * 1024, &shm)
             .context("Failed to create buffer pool")?;
 
@@ -628,11 +1875,32 @@ impl WaylandManager {
             surface_ids: HashMap::new(),
             next_surface_id: 1,
             outputs: Vec::new(),
+            primary_output_name: None,
+            probe_output: None,
+            surface_output_binding: SurfaceOutputBinding::default(),
+            anchor_corner: Corner::default(),
             pointer: None,
+            cursor_shape: CursorShape::Default,
+            touch: None,
+            touch_point: None,
             pointer_x: 0.0,
             pointer_y: 0.0,
             pointer_surface: None,
-            input_events: Vec::new(),
+            keyboard: None,
+            modifiers: KeyModifiers::default(),
+            last_input_serial: 0,
+            data_device_manager,
+            data_device: None,
+            clipboard: None,
+            input_events: InputQueue::default(),
+            surface_opacity: 1.0,
+            newly_configured: Vec::new(),
+            resized: Vec::new(),
+            output_geometry: HashMap::new(),
+            outputs_changed: false,
+            work_area_probe: None,
+            work_area: None,
+            toplevels: HashMap::new(),
             exit: false,
         };
 
@@ -647,63 +1915,205 @@ impl WaylandManager {
 
         info!("Wayland manager initialized");
 
-        Ok(Self { event_loop, state })
+        Ok(Self { event_loop, state, connection: conn })
     }
 
-    /// Create a new surface for an icon
-    pub fn create_surface(&mut self, x: i32, y: i32, width: u32, height: u32) -> Result<SurfaceId> {
-        self.state.create_surface(x, y, width, height)
+    /// Get the calloop handle for integrating with external event sources
+    #[allow(dead_code)]
+    pub fn loop_handle(&self) -> LoopHandle<'static, WaylandState> {
+        self.event_loop.handle()
     }
 
-    /// Destroy a surface
-    pub fn destroy_surface(&mut self, surface_id: SurfaceId) {
+    /// Get all surface IDs
+    #[allow(dead_code)]
+    pub fn surface_ids(&self) -> Vec<SurfaceId> {
+        self.state.surface_ids()
+    }
+}
+
+impl SurfaceBackend for WaylandManager {
+    fn create_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        self.state.create_surface(x, y, width, height).map_err(IconsError::Wayland)
+    }
+
+    fn create_surface_on(
+        &mut self,
+        output: &str,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        self.state.create_surface_on(output, x, y, width, height).map_err(IconsError::Wayland)
+    }
+
+    fn create_overlay_surface(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> IconsResult<SurfaceId> {
+        self.state.create_overlay_surface(x, y, width, height).map_err(IconsError::Wayland)
+    }
+
+    fn create_backdrop_surface(&mut self) -> IconsResult<SurfaceId> {
+        self.state.create_backdrop_surface().map_err(IconsError::Wayland)
+    }
+
+    fn destroy_surface(&mut self, surface_id: SurfaceId) {
         self.state.destroy_surface(surface_id)
     }
 
-    /// Set surface position
-    pub fn set_surface_position(&mut self, surface_id: SurfaceId, x: i32, y: i32) {
+    fn set_surface_position(&mut self, surface_id: SurfaceId, x: i32, y: i32) {
         self.state.set_surface_position(surface_id, x, y)
     }
 
-    /// Attach a buffer to a surface (pixels in RGBA format)
-    pub fn attach_buffer(&mut self, surface_id: SurfaceId, pixels: &[u8], width: u32, height: u32) -> Result<()> {
-        self.state.attach_buffer(surface_id, pixels, width, height)
+    fn set_surface_mapped(&mut self, surface_id: SurfaceId, mapped: bool) {
+        self.state.set_surface_mapped(surface_id, mapped)
     }
 
-    /// Dispatch Wayland events (non-blocking)
-    pub fn dispatch_events(&mut self) -> Result<()> {
+    fn attach_buffer_damaged(
+        &mut self,
+        surface_id: SurfaceId,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        damage: &[DamageRect],
+    ) -> IconsResult<bool> {
+        self.state
+            .attach_buffer_damaged(surface_id, pixels, width, height, damage)
+            .map_err(IconsError::Wayland)
+    }
+
+    fn surface_size(&self, surface_id: SurfaceId) -> Option<(u32, u32)> {
+        self.state.surface_size(surface_id)
+    }
+
+    fn surface_scale(&self, surface_id: SurfaceId) -> u32 {
+        self.state.surface_scale(surface_id)
+    }
+
+    fn surface_position(&self, surface_id: SurfaceId) -> Option<(i32, i32)> {
+        self.state.surface_position(surface_id)
+    }
+
+    fn dispatch_events(&mut self) -> IconsResult<()> {
+        // Never blocks: the daemon's loop already sleeps on the display fd
         self.event_loop
             .dispatch(Some(std::time::Duration::ZERO), &mut self.state)
-            .context("Failed to dispatch Wayland events")?;
-        Ok(())
+            .context("Failed to dispatch Wayland events")
+            .map_err(IconsError::Wayland)
     }
 
-    /// Get pending input events
-    pub fn take_input_events(&mut self) -> Vec<InputEvent> {
+    fn take_newly_configured(&mut self) -> Vec<SurfaceId> {
+        self.state.take_newly_configured()
+    }
+
+    fn take_resized(&mut self) -> Vec<SurfaceId> {
+        self.state.take_resized()
+    }
+
+    fn take_outputs_changed(&mut self) -> bool {
+        self.state.take_outputs_changed()
+    }
+
+    fn take_input_events(&mut self) -> Vec<InputEvent> {
         self.state.take_input_events()
     }
 
-    /// Get the calloop handle for integrating with external event sources
-    #[allow(dead_code)]
-    pub fn loop_handle(&self) -> LoopHandle<'static, WaylandState> {
-        self.event_loop.handle()
+    fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        // Readable whenever one of the loop's sources, the display socket among them, is
+        Some(self.event_loop.as_fd())
     }
 
-    /// Check if should exit
-    pub fn should_exit(&self) -> bool {
+    fn flush(&mut self) {
+        if let Err(e) = self.connection.flush() {
+            debug!("Failed to flush the Wayland connection: {}", e);
+        }
+    }
+
+    fn frame_callback_pending(&self) -> bool {
+        self.state.frame_callback_pending()
+    }
+
+    fn frame_group(&self, surface_id: SurfaceId) -> FrameGroup {
+        self.state.frame_group(surface_id)
+    }
+
+    fn frame_group_pending(&self, group: FrameGroup) -> bool {
+        self.state.frame_group_pending(group)
+    }
+
+    fn should_exit(&self) -> bool {
         self.state.should_exit()
     }
 
-    /// Get all surface IDs
-    #[allow(dead_code)]
-    pub fn surface_ids(&self) -> Vec<SurfaceId> {
-        self.state.surface_ids()
+    fn desktop_covered(&self) -> bool {
+        self.state.desktop_covered()
     }
 
-    /// Get the dimensions of the primary output
-    pub fn get_output_dimensions(&self) -> Option<(u32, u32)> {
+    fn activate_app(&mut self, app_ids: &[String]) -> bool {
+        let activated = self.state.activate_app(app_ids);
+        if activated {
+            self.flush();
+        }
+        activated
+    }
+
+    fn get_output_dimensions(&self) -> Option<(u32, u32)> {
         self.state.get_output_dimensions()
     }
+
+    fn get_output_name(&self) -> Option<String> {
+        self.state.get_output_name()
+    }
+
+    fn output_dimensions_all(&self) -> Vec<(String, u32, u32)> {
+        self.state.output_dimensions_all()
+    }
+
+    fn set_input_queue_limit(&mut self, limit: usize) {
+        self.state.input_events.set_limit(limit)
+    }
+
+    fn set_surface_opacity(&mut self, opacity: f32) {
+        self.state.surface_opacity = opacity;
+    }
+
+    fn set_work_area_anchoring(&mut self, enabled: bool) {
+        self.state.set_work_area_anchoring(enabled)
+    }
+
+    fn set_primary_output(&mut self, name: Option<String>) {
+        self.state.set_primary_output(name)
+    }
+
+    fn set_surface_output_binding(&mut self, binding: SurfaceOutputBinding) {
+        self.state.set_surface_output_binding(binding)
+    }
+
+    fn set_anchor_corner(&mut self, corner: Corner) {
+        self.state.set_anchor_corner(corner)
+    }
+
+    fn set_clipboard_text(&mut self, text: &str) -> IconsResult<()> {
+        self.state.set_clipboard_text(text).map_err(IconsError::Wayland)
+    }
+
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.state.set_cursor_shape(&self.connection, shape)
+    }
+
+    fn get_work_area(&self) -> Option<(u32, u32)> {
+        self.state.get_work_area()
+    }
 }
 
 #[cfg(test)]
@@ -730,6 +2140,7 @@ mod tests {
             pressed: true,
             x: 10.0,
             y: 20.0,
+            modifiers: KeyModifiers::default(),
         };
         let cloned = event.clone();
         match cloned {
@@ -742,6 +2153,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_primary_output_index() {
+        let names = vec![Some("eDP-1".to_string()), None, Some("DP-1".to_string())];
+        assert_eq!(primary_output_index(&names, Some("DP-1")), Some(2));
+        assert_eq!(primary_output_index(&names, None), Some(0));
+        assert_eq!(primary_output_index(&names, Some("HDMI-A-1")), Some(0), "Unplugged falls back to first");
+        assert_eq!(primary_output_index(&[], Some("DP-1")), None);
+    }
+
+    #[test]
+    fn test_output_index_at() {
+        // A 1920x1080 primary with a 1280x1024 output to its right
+        let rects = vec![Some((0, 0, 1920, 1080)), None, Some((1920, 0, 1280, 1024))];
+        assert_eq!(output_index_at(&rects, 100, 100), Some(0));
+        assert_eq!(output_index_at(&rects, 1920, 0), Some(2));
+        assert_eq!(output_index_at(&rects, 2000, 1050), None, "Below the smaller output");
+        assert_eq!(output_index_at(&rects, -1, 0), None);
+    }
+
+    #[test]
+    fn test_corner_anchor() {
+        let reference = Some((1920, 1080));
+        let (anchor, margins) = corner_anchor(Corner::TopLeft, (20, 30), (64, 84), reference);
+        assert_eq!((anchor, margins), (Anchor::TOP | Anchor::LEFT, (30, 0, 0, 20)));
+
+        let (anchor, margins) = corner_anchor(Corner::BottomRight, (20, 30), (64, 84), reference);
+        assert_eq!(anchor, Anchor::BOTTOM | Anchor::RIGHT);
+        assert_eq!(margins, (0, 1920 - 20 - 64, 1080 - 30 - 84, 0));
+
+        let (anchor, margins) = corner_anchor(Corner::TopRight, (20, 30), (64, 84), None);
+        assert_eq!((anchor, margins), (Anchor::TOP | Anchor::LEFT, (30, 0, 0, 20)));
+    }
+
+    #[test]
+    fn test_toplevel_state_from_wire() {
+        let wire: Vec<u8> = [0u32, 2].iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let state = ToplevelState::from_wire(&wire);
+        assert!(state.maximized && state.activated);
+        assert!(!state.minimized && !state.fullscreen);
+        assert!(state.covers_desktop());
+    }
+
+    #[test]
+    fn test_only_focused_unminimized_windows_cover_desktop() {
+        let maximized = ToplevelState { maximized: true, ..Default::default() };
+        assert!(!maximized.covers_desktop(), "It may be on a hidden workspace");
+
+        let fullscreen = ToplevelState { fullscreen: true, activated: true, ..Default::default() };
+        assert!(fullscreen.covers_desktop());
+        assert!(!ToplevelState { minimized: true, ..fullscreen }.covers_desktop());
+        assert!(!ToplevelState { activated: true, ..Default::default() }.covers_desktop());
+    }
+
     // Note: WaylandManager tests require a running Wayland display
     // and are better suited for integration testing
 }
@@ -0,0 +1,113 @@
+//! User-set tags and comments stored in extended attributes
+//!
+//! Follows the freedesktop convention: `user.xdg.tags` holds a
+//! comma-separated list of tags and `user.xdg.comment` free text. Reading
+//! them needs the `xattr` feature; without it (or on filesystems without
+//! xattr support) every file simply has none.
+
+use std::path::Path;
+
+/// Attribute holding a file's comma-separated tags
+pub const TAGS_ATTR: &str = "user.xdg.tags";
+
+/// Attribute holding a file's comment
+pub const COMMENT_ATTR: &str = "user.xdg.comment";
+
+/// Tags and comment a user attached to a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileNotes {
+    pub tags: Vec<String>,
+    pub comment: Option<String>,
+}
+
+impl FileNotes {
+    /// Read the notes attached to `path` (following symlinks)
+    pub fn read(path: &Path) -> Self {
+        Self::from_values(get(path, TAGS_ATTR), get(path, COMMENT_ATTR))
+    }
+
+    /// Notes from raw attribute values, dropping empty tags and blank comments
+    pub fn from_values(tags: Option<Vec<u8>>, comment: Option<Vec<u8>>) -> Self {
+        let tags = tags
+            .map(|value| {
+                String::from_utf8_lossy(&value)
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let comment = comment
+            .map(|value| String::from_utf8_lossy(&value).trim().to_string())
+            .filter(|comment| !comment.is_empty());
+        Self { tags, comment }
+    }
+
+    /// Whether there is nothing to show
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.comment.is_none()
+    }
+}
+
+/// Value of the extended attribute `name` on `path`, if set
+#[cfg(all(feature = "xattr", target_os = "linux"))]
+fn get(path: &Path, name: &str) -> Option<Vec<u8>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let c_name = CString::new(name).ok()?;
+
+    // Ask for the size first; the value may change in between, so a failed
+    // second call just means no value this time
+    // SAFETY: both strings are NUL-terminated and a null buffer of size 0 is allowed
+    let size = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0) };
+    if size <= 0 {
+        return None;
+    }
+    let mut value = vec![0u8; size as usize];
+    // SAFETY: `value` is valid for writes of `value.len()` bytes
+    let len = unsafe {
+        libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), value.as_mut_ptr().cast(), value.len())
+    };
+    if len < 0 {
+        return None;
+    }
+    value.truncate(len as usize);
+    Some(value)
+}
+
+#[cfg(not(all(feature = "xattr", target_os = "linux")))]
+fn get(_path: &Path, _name: &str) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes_from_values() {
+        let notes =
+            FileNotes::from_values(Some(b"work, urgent,,".to_vec()), Some(b" Q3 report ".to_vec()));
+        assert_eq!(notes.tags, vec!["work", "urgent"]);
+        assert_eq!(notes.comment.as_deref(), Some("Q3 report"));
+        assert!(!notes.is_empty());
+    }
+
+    #[test]
+    fn test_blank_notes_are_empty() {
+        assert!(FileNotes::from_values(None, None).is_empty());
+        assert!(FileNotes::from_values(Some(b" , ".to_vec()), Some(b"  ".to_vec())).is_empty());
+    }
+
+    #[test]
+    fn test_file_without_xattrs_has_no_notes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("plain.txt");
+        std::fs::write(&path, "x").unwrap();
+        assert!(FileNotes::read(&path).is_empty());
+        assert!(FileNotes::read(&temp_dir.path().join("missing")).is_empty());
+    }
+}